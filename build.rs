@@ -0,0 +1,12 @@
+fn main() {
+    #[cfg(feature = "grpc")]
+    {
+        println!("cargo:rerun-if-changed=proto/store.proto");
+        println!("cargo:rerun-if-changed=proto/proof.proto");
+        let descriptors =
+            protox::compile(["store.proto", "proof.proto"], ["proto"]).expect("invalid proto file");
+        tonic_build::configure()
+            .compile_fds(descriptors)
+            .expect("failed to generate gRPC code");
+    }
+}
@@ -21,7 +21,7 @@ fn main() -> Result<()> {
     tree.insert(key1, value1.clone(), sum1)?;
     println!("Inserted key1 with value1 and sum1");
     let merkle_root = tree.root()?;
-    println!("Merkle root: {:?}", merkle_root.node_hash());
+    println!("Merkle root: {}", merkle_root.node_hash());
 
     let key2 = to_array(&Sha256::digest(b"key2"));
     let value2 = b"value2".to_vec();
@@ -31,7 +31,7 @@ fn main() -> Result<()> {
     println!("Inserted key2 with value2 and sum2");
 
     let merkle_root = tree.root()?;
-    println!("Merkle root: {:?}", merkle_root.node_hash());
+    println!("Merkle root: {}", merkle_root.node_hash());
 
     let key3 = to_array(&Sha256::digest(b"key3"));
     let value3 = b"value3".to_vec();
@@ -41,7 +41,7 @@ fn main() -> Result<()> {
     println!("Inserted key3 with value3 and sum3");
 
     let merkle_root = tree.root()?;
-    println!("Merkle root: {:?}", merkle_root.node_hash());
+    println!("Merkle root: {}", merkle_root.node_hash());
 
     // Step 3: Fetch values and log the results
     println!("\nFetching values from the tree...");
@@ -0,0 +1,259 @@
+//! Batches of proofs against one root, packed into a single file.
+//!
+//! An issuer committing to many holders at once hands each of them a proof against the same root.
+//! `ProofArchiveWriter` packs the root commitment and the compressed proof of every key into one
+//! versioned file, and `ProofArchive` reads it back. The file carries an index of its keys, so a holder
+//! decodes only the proofs it looks up.
+//!
+//! An archive is not trusted for being well formed: every proof it returns is still verified against
+//! the commitment, with `Proof::verify_against`, by the holder.
+
+use crate::commitment::RootCommitment;
+use crate::error::{MssmtError, Result};
+use crate::format::{header, Format, HEADER_SIZE};
+use crate::key::Key;
+use crate::proof::{CompressedProof, Proof};
+use crate::store::{read_checked, Cursor, HashingWriter, TreeStoreReader};
+use crate::tree::FullTree;
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+use std::ops::Range;
+
+/// The magic bytes at the start of every proof archive, followed by the format version.
+pub(crate) const ARCHIVE_MAGIC: &[u8; 8] = b"MSSMTPRA";
+
+/// The header of proof archives written by this release.
+const ARCHIVE_HEADER: [u8; HEADER_SIZE] =
+    header(ARCHIVE_MAGIC, Format::ProofArchive.current_version());
+
+/// The size of an index entry: the key, and the offset and length of its proof.
+const INDEX_ENTRY_SIZE: usize = 32 + 8 + 4;
+
+/// Collects the proofs of an archive and writes it, see `ProofArchive`.
+///
+/// The binary encoding written by `write_to` is the header `MSSMTPRA` followed by the format version
+/// byte, the encoded `RootCommitment`, the number of entries as a big-endian `u64`, the index of the
+/// entries sorted by key, each as the key, the big-endian `u64` offset and `u32` length of its proof,
+/// then the encoded compressed proofs, and finally the SHA-256 digest of everything before it.
+pub struct ProofArchiveWriter {
+    commitment: RootCommitment,
+    proofs: BTreeMap<[u8; 32], Vec<u8>>,
+}
+
+impl ProofArchiveWriter {
+    /// Creates an empty archive of proofs against `commitment`.
+    pub fn new(commitment: RootCommitment) -> Self {
+        Self {
+            commitment,
+            proofs: BTreeMap::new(),
+        }
+    }
+
+    /// Adds the proof of `key`, replacing any earlier proof of the same key.
+    pub fn add(&mut self, key: impl Into<Key>, proof: &Proof) {
+        self.proofs.insert(key.into().0, proof.compress().encode());
+    }
+
+    /// Returns the number of proofs in the archive.
+    pub fn len(&self) -> usize {
+        self.proofs.len()
+    }
+
+    /// Returns `true` if the archive holds no proof.
+    pub fn is_empty(&self) -> bool {
+        self.proofs.is_empty()
+    }
+
+    /// Writes the archive to `writer`.
+    pub fn write_to<W: Write>(&self, writer: W) -> Result<()> {
+        let mut writer = HashingWriter::new(writer);
+        writer.write_all(&ARCHIVE_HEADER)?;
+        writer.write_all(&self.commitment.encode())?;
+        writer.write_all(&(self.proofs.len() as u64).to_be_bytes())?;
+        let mut offset = 0u64;
+        for (key, proof) in &self.proofs {
+            writer.write_all(key)?;
+            writer.write_all(&offset.to_be_bytes())?;
+            writer.write_all(&(proof.len() as u32).to_be_bytes())?;
+            offset += proof.len() as u64;
+        }
+        for proof in self.proofs.values() {
+            writer.write_all(proof)?;
+        }
+        writer.finish()
+    }
+}
+
+impl<S: TreeStoreReader> FullTree<S> {
+    /// Returns an archive of the proofs of `keys` against the commitment of the tree.
+    ///
+    /// Proofs are generated under the hash scheme of the tree, see `FullTree::scheme_proof`, and absent
+    /// keys get non-inclusion proofs.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use mssmt::archive::ProofArchive;
+    /// use mssmt::{DefaultStore, FullTree, LeafNode};
+    ///
+    /// let mut tree = FullTree::new(DefaultStore::new());
+    /// tree.insert([1u8; 32], b"alice".to_vec(), 10).unwrap();
+    /// tree.insert([2u8; 32], b"bob".to_vec(), 20).unwrap();
+    ///
+    /// let mut bytes = Vec::new();
+    /// tree.proof_archive([[1u8; 32], [2u8; 32]]).unwrap().write_to(&mut bytes).unwrap();
+    ///
+    /// let archive = ProofArchive::read_from(bytes.as_slice()).unwrap();
+    /// let proof = archive.get([1u8; 32]).unwrap().unwrap();
+    /// let leaf = LeafNode::new([1u8; 32], b"alice".to_vec(), 10);
+    /// assert!(proof.verify_against([1u8; 32], &leaf, &archive.commitment()));
+    /// ```
+    pub fn proof_archive(
+        &self,
+        keys: impl IntoIterator<Item = impl Into<Key>>,
+    ) -> Result<ProofArchiveWriter> {
+        let mut archive = ProofArchiveWriter::new(self.commitment()?);
+        for key in keys {
+            let key = key.into();
+            archive.add(key, &self.scheme_proof(key)?);
+        }
+        Ok(archive)
+    }
+}
+
+/// A proof archive read from a file, see `ProofArchiveWriter` for the format.
+pub struct ProofArchive {
+    commitment: RootCommitment,
+    index: Vec<([u8; 32], Range<usize>)>,
+    proofs: Vec<u8>,
+}
+
+impl ProofArchive {
+    /// Reads an archive written by `ProofArchiveWriter::write_to`.
+    ///
+    /// The index is checked against the size of the proofs, but the proofs themselves are only decoded
+    /// by `ProofArchive::get`.
+    ///
+    /// # Returns
+    ///
+    /// - The archive.
+    /// - `MssmtError::InvalidEncoding` if the input is not a proof archive, has an unsupported version,
+    ///   is truncated, fails its checksum, or has an unsorted or out-of-bounds index.
+    pub fn read_from<R: Read>(reader: R) -> Result<Self> {
+        let bytes = read_checked(reader, Format::ProofArchive, ARCHIVE_MAGIC)?;
+        let mut body = Cursor::new(&bytes, Format::ProofArchive);
+        let commitment = RootCommitment::decode(body.take(RootCommitment::ENCODED_SIZE)?)?;
+        let count = u64::from_be_bytes(body.take_array()?) as usize;
+        let index_bytes = body.take(count.saturating_mul(INDEX_ENTRY_SIZE))?;
+        let proofs = body.remaining().to_vec();
+
+        let mut index = Vec::with_capacity(count);
+        let mut entries = Cursor::new(index_bytes, Format::ProofArchive);
+        for _ in 0..count {
+            let key: [u8; 32] = entries.take_array()?;
+            let offset = u64::from_be_bytes(entries.take_array()?) as usize;
+            let len = u32::from_be_bytes(entries.take_array()?) as usize;
+            if let Some((previous, _)) = index.last() {
+                if *previous >= key {
+                    return Err(invalid("proof archive index is not sorted by key"));
+                }
+            }
+            let end = offset.checked_add(len).filter(|end| *end <= proofs.len());
+            let Some(end) = end else {
+                return Err(invalid("proof archive entry is out of bounds"));
+            };
+            index.push((key, offset..end));
+        }
+
+        Ok(Self {
+            commitment,
+            index,
+            proofs,
+        })
+    }
+
+    /// Returns the commitment the proofs of the archive verify against.
+    pub fn commitment(&self) -> RootCommitment {
+        self.commitment
+    }
+
+    /// Returns the number of proofs in the archive.
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    /// Returns `true` if the archive holds no proof.
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    /// Returns the keys of the archive in ascending order.
+    pub fn keys(&self) -> impl Iterator<Item = Key> + '_ {
+        self.index.iter().map(|(key, _)| Key(*key))
+    }
+
+    /// Returns the proof of `key`, or `Ok(None)` if the archive has no proof of the key.
+    ///
+    /// # Returns
+    ///
+    /// - `MssmtError::InvalidEncoding` if the stored proof cannot be decoded.
+    pub fn get(&self, key: impl Into<Key>) -> Result<Option<Proof>> {
+        let key = key.into().0;
+        let Ok(position) = self.index.binary_search_by(|(entry, _)| entry.cmp(&key)) else {
+            return Ok(None);
+        };
+        let range = self.index[position].1.clone();
+        CompressedProof::decode(&self.proofs[range])?
+            .decompress()
+            .map(Some)
+    }
+}
+
+fn invalid(message: &str) -> MssmtError {
+    MssmtError::InvalidEncoding(message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::{LeafNode, Sum, EMPTY_LEAF_NODE};
+    use crate::store::DefaultStore;
+    use crate::tagged::HashScheme;
+
+    #[test]
+    fn test_proof_archive_round_trip() -> Result<()> {
+        let mut tree = FullTree::new(DefaultStore::new());
+        tree.set_hash_scheme(HashScheme::V1);
+        for i in 1..=32u8 {
+            tree.insert([i; 32], vec![i], i as Sum)?;
+        }
+        let keys: Vec<[u8; 32]> = (0..=32u8).rev().map(|i| [i; 32]).collect();
+        let mut bytes = Vec::new();
+        tree.proof_archive(keys)?.write_to(&mut bytes)?;
+
+        let archive = ProofArchive::read_from(bytes.as_slice())?;
+        assert_eq!(archive.len(), 33);
+        assert_eq!(archive.commitment(), tree.commitment()?);
+        assert_eq!(archive.keys().next(), Some(Key([0u8; 32])));
+        for i in 1..=32u8 {
+            let proof = archive.get([i; 32])?.unwrap();
+            let leaf = LeafNode::new([i; 32], vec![i], i as Sum);
+            assert!(proof.verify_against([i; 32], &leaf, &archive.commitment()));
+        }
+        let proof = archive.get([0u8; 32])?.unwrap();
+        assert!(proof.verify_against([0u8; 32], &EMPTY_LEAF_NODE, &archive.commitment()));
+        assert!(archive.get([99u8; 32])?.is_none());
+
+        assert_eq!(crate::format::detect(&bytes)?, (Format::ProofArchive, 1));
+        let mut corrupted = bytes.clone();
+        corrupted[HEADER_SIZE + 2] ^= 1;
+        for input in [&corrupted[..], &bytes[..bytes.len() - 1]] {
+            assert!(matches!(
+                ProofArchive::read_from(input),
+                Err(MssmtError::InvalidEncoding(_))
+            ));
+        }
+
+        Ok(())
+    }
+}
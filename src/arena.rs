@@ -0,0 +1,195 @@
+//! Batch inserts reusing their buffers from one batch to the next.
+//!
+//! Inserting entries one at a time rebuilds the whole 256-level path of every entry, allocating a
+//! branch per level even where the paths of the batch overlap. `FullTree::insert_batch_in` merges a
+//! sorted batch into the tree in a single descent instead, so every rebuilt branch is allocated once per
+//! batch, and it stages the batch in a `BatchArena` whose buffers keep their capacity across batches. A
+//! service applying a steady stream of batches therefore stops allocating staging buffers once the
+//! arena has grown to its batch size.
+//!
+//! This module requires the `arena` feature.
+
+use crate::error::Result;
+use crate::key::Key;
+use crate::node::{LeafNode, NodeHash, Sum};
+use crate::store::TreeStore;
+use crate::tree::{merge_leaves, FullTree, SubtreeUpdate};
+
+/// Buffers reused by `FullTree::insert_batch_in`.
+///
+/// An arena holds no tree data between batches, only allocated capacity, so one arena can serve any
+/// number of trees in turn.
+#[derive(Default)]
+pub struct BatchArena {
+    // The sorted, unique leaves of the batch being inserted
+    leaves: Vec<LeafNode>,
+    // The nodes rebuilt by the batch, before they are written
+    update: SubtreeUpdate,
+}
+
+impl BatchArena {
+    /// Creates an empty arena.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates an arena sized for batches of `entries` entries.
+    ///
+    /// A batch of `n` entries rebuilds at most `256 * n` branches, far fewer when their keys share
+    /// prefixes; the branch buffer grows on demand beyond `entries` branches.
+    pub fn with_capacity(entries: usize) -> Self {
+        Self {
+            leaves: Vec::with_capacity(entries),
+            update: SubtreeUpdate {
+                leaves: Vec::with_capacity(entries),
+                branches: Vec::with_capacity(entries),
+            },
+        }
+    }
+
+    /// Returns the number of leaves and branches the arena can stage without reallocating.
+    pub fn capacity(&self) -> (usize, usize) {
+        (self.leaves.capacity(), self.update.branches.capacity())
+    }
+
+    fn clear(&mut self) {
+        self.leaves.clear();
+        self.update.leaves.clear();
+        self.update.branches.clear();
+    }
+}
+
+impl<S: TreeStore> FullTree<S> {
+    /// Inserts a batch of key-value-sum entries, staging it in the buffers of `arena`.
+    ///
+    /// The resulting tree is the same as inserting the entries one by one: a later entry for a key
+    /// overwrites an earlier one, and observers are notified of every inserted leaf and of the root
+    /// change. Nothing is written to the store unless the whole batch applies, so a batch overflowing
+    /// the root sum or holding a leaf rejected by the configuration of the tree leaves the tree unchanged.
+    ///
+    /// # Returns
+    ///
+    /// - The hash of the new root.
+    /// - `MssmtError::SumOverflow` if the batch would overflow the root sum.
+    /// - `MssmtError::InvalidLeaf` if a leaf is rejected by the configuration of the tree.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use mssmt::arena::BatchArena;
+    /// use mssmt::{DefaultStore, FullTree};
+    ///
+    /// let mut tree = FullTree::new(DefaultStore::new());
+    /// let mut arena = BatchArena::with_capacity(100);
+    /// for batch in 0..3u8 {
+    ///     let entries = (0..100u8).map(|i| ([i; 32], vec![batch, i], i.into()));
+    ///     tree.insert_batch_in(&mut arena, entries).unwrap();
+    /// }
+    ///
+    /// assert_eq!(tree.get([42u8; 32]).unwrap(), Some((vec![2, 42], 42)));
+    /// assert_eq!(tree.total_sum().unwrap(), 4950);
+    /// ```
+    pub fn insert_batch_in<T: Into<Key>>(
+        &mut self,
+        arena: &mut BatchArena,
+        entries: impl IntoIterator<Item = (T, Vec<u8>, Sum)>,
+    ) -> Result<NodeHash> {
+        arena.clear();
+        arena.leaves.extend(
+            entries
+                .into_iter()
+                .map(|(key, value, sum)| LeafNode::new(key.into().0, value, sum)),
+        );
+        // The sort is stable, so the last entry for a key is the last of its run
+        arena.leaves.sort_by_key(|leaf| leaf.key);
+        arena.leaves.reverse();
+        arena.leaves.dedup_by_key(|leaf| leaf.key);
+        arena.leaves.reverse();
+        for leaf in &arena.leaves {
+            self.config().validate(leaf)?;
+        }
+
+        let root = self.root()?;
+        let (old_root_hash, old_root_sum) = (root.node_hash(), root.node_sum());
+        if arena.leaves.is_empty() {
+            return Ok(old_root_hash);
+        }
+        let overflow = self.config().overflow_policy();
+        let new_root = merge_leaves(
+            self.store(),
+            root,
+            0,
+            &arena.leaves,
+            overflow,
+            &mut arena.update,
+        )?;
+        let changes = arena.update.leaves.iter().map(|(leaf, previous)| {
+            (
+                leaf.sum,
+                previous.as_ref().map_or(0, |previous| previous.sum),
+            )
+        });
+        self.config()
+            .check_root_sum(old_root_sum, changes, new_root.node_sum())?;
+        let root_hash = new_root.node_hash();
+
+        let store = self.store_mut();
+        for (leaf, _) in &arena.update.leaves {
+            store.insert_leaf(leaf.clone())?;
+        }
+        for (height, branch) in arena.update.branches.drain(..) {
+            store.insert_branch_at(height, branch)?;
+        }
+        store.update_root(new_root.clone())?;
+
+        for (leaf, previous) in &arena.update.leaves {
+            self.notify_insert(leaf, previous.as_ref());
+        }
+        self.notify_root_change(old_root_hash, new_root.as_ref());
+        self.stamp_leaf_metadata()?;
+        arena.clear();
+        Ok(root_hash)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::MssmtError;
+    use crate::store::DefaultStore;
+
+    #[test]
+    fn test_insert_batch_in_matches_sequential_inserts() -> Result<()> {
+        let mut sequential = FullTree::new(DefaultStore::new());
+        let mut batched = FullTree::new(DefaultStore::new());
+        let mut arena = BatchArena::new();
+        for batch in 0..4u8 {
+            let entries: Vec<_> = (0..50u8)
+                .map(|i| (Key::hash([i, batch % 2]), vec![batch, i], i as Sum))
+                .chain([(Key::hash([0, batch % 2]), b"last".to_vec(), 7)])
+                .collect();
+            for (key, value, sum) in &entries {
+                sequential.insert(*key, value.clone(), *sum)?;
+            }
+            let root_hash = batched.insert_batch_in(&mut arena, entries)?;
+            assert_eq!(root_hash, sequential.root()?.node_hash());
+        }
+        assert_eq!(batched.get(Key::hash([0, 1]))?, Some((b"last".to_vec(), 7)));
+
+        // The buffers are kept for the next batch
+        let (leaves, branches) = arena.capacity();
+        assert!(leaves >= 50 && branches >= 50);
+
+        // An overflowing batch writes nothing
+        let root_hash = batched.root()?.node_hash();
+        let stored = batched.store().leaves.len();
+        assert!(matches!(
+            batched.insert_batch_in(&mut arena, [([9u8; 32], vec![], Sum::MAX)]),
+            Err(MssmtError::SumOverflow)
+        ));
+        assert_eq!(batched.root()?.node_hash(), root_hash);
+        assert_eq!(batched.store().leaves.len(), stored);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,285 @@
+//! Auditor exports proving the total sum of a tree.
+//!
+//! Proof-of-reserves style audits need every leaf of a commitment and the assurance that the leaves
+//! account for the whole root sum, with nothing hidden in a subtree the auditor never saw.
+//! `FullTree::audit_export` streams every leaf in key order. Leaves in key order are their own sibling
+//! data: every branch of the tree is built from the leaves below it and from empty subtrees, which are
+//! constants. An `AuditVerifier` rebuilds the root from the stream with memory bounded by the height of
+//! the tree, and checks that it matches the committed root hash and that the committed root sum equals
+//! the sum of the emitted leaves.
+
+use crate::commitment::RootCommitment;
+use crate::error::{MssmtError, Result};
+use crate::node::{
+    bit_index, branch_hash, tree_levels, EmptyTreeOf, LeafNode, LeafValue, Node, NodeHash, Sum,
+    HASH_SIZE,
+};
+use crate::store::TreeStoreReader;
+use crate::tree::FullTree;
+use crate::walk::WalkControl;
+
+/// The outcome of an audit export or of its verification.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AuditSummary {
+    /// The root hash and sum the leaves add up to.
+    pub commitment: RootCommitment,
+    /// The number of leaves.
+    pub leaves: u64,
+}
+
+impl<S: TreeStoreReader<K, V>, const K: usize, V: LeafValue> FullTree<S, K, V> {
+    /// Passes every leaf of the tree to `emit`, in key order, and returns the commitment they add up to.
+    ///
+    /// The leaves are read one subtree at a time, so exporting does not hold the tree in memory. Feeding
+    /// them to an `AuditVerifier` in the same order reproduces the commitment.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use mssmt::audit::AuditVerifier;
+    /// use mssmt::{DefaultStore, FullTree};
+    ///
+    /// let mut tree = FullTree::new(DefaultStore::new());
+    /// tree.insert([1u8; 32], b"alice".to_vec(), 10).unwrap();
+    /// tree.insert([2u8; 32], b"bob".to_vec(), 5).unwrap();
+    ///
+    /// let mut verifier = AuditVerifier::new();
+    /// let summary = tree.audit_export(|leaf| verifier.push(leaf)).unwrap();
+    /// assert_eq!(verifier.finish(&summary.commitment).unwrap(), summary);
+    /// assert_eq!((summary.commitment.sum, summary.leaves), (15, 2));
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error of `emit`, or an error if a node cannot be read. The export stops at the
+    /// first error.
+    pub fn audit_export(
+        &self,
+        mut emit: impl FnMut(&LeafNode<K, V>) -> Result<()>,
+    ) -> Result<AuditSummary> {
+        let mut leaves = 0;
+        let mut failure = None;
+        self.walk(|_, node| match node.as_leaf::<K, V>() {
+            Some(leaf) => match emit(leaf) {
+                Ok(()) => {
+                    leaves += 1;
+                    WalkControl::Continue
+                }
+                Err(err) => {
+                    failure = Some(err);
+                    WalkControl::Stop
+                }
+            },
+            None => WalkControl::Continue,
+        })?;
+        if let Some(err) = failure {
+            return Err(err);
+        }
+        Ok(AuditSummary {
+            commitment: RootCommitment::of(self.root()?.as_ref()),
+            leaves,
+        })
+    }
+}
+
+/// Rebuilds the root of a tree from its leaves, streamed in key order.
+///
+/// The verifier keeps one pending subtree per level at most, the left siblings still waiting for their
+/// right half, so it runs in memory bounded by the height of the tree however many leaves it is fed.
+/// Sums are added with overflow checks, as in trees with the default `OverflowPolicy::Checked`.
+/// `K` is the key size in bytes of the tree, 32 by default.
+pub struct AuditVerifier<const K: usize = HASH_SIZE> {
+    // Subtrees not yet combined with their right sibling, as (height, key of a leaf, hash, sum)
+    pending: Vec<(usize, [u8; K], NodeHash, Sum)>,
+    total: Sum,
+    leaves: u64,
+}
+
+impl<const K: usize> Default for AuditVerifier<K> {
+    fn default() -> Self {
+        Self {
+            pending: Vec::new(),
+            total: 0,
+            leaves: 0,
+        }
+    }
+}
+
+impl<const K: usize> AuditVerifier<K> {
+    /// Creates a verifier expecting the first leaf.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the next leaf of the export.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(())` once the leaf is folded into the pending subtrees.
+    /// - `MssmtError::InvalidEncoding` if the leaf does not come strictly after the previous one in
+    ///   key order.
+    /// - `MssmtError::SumOverflow` if the sum of the leaves overflows.
+    pub fn push(&mut self, leaf: &LeafNode<K, impl LeafValue>) -> Result<()> {
+        let levels = tree_levels(K);
+        if let Some(&(_, previous, _, _)) = self.pending.last() {
+            if leaf.key <= previous {
+                return Err(MssmtError::InvalidEncoding(
+                    "audit leaves are not in strictly increasing key order".to_string(),
+                ));
+            }
+            // The paths split below the first differing bit, so the subtree of the previous leaf under
+            // that split is complete
+            let divergence = (0..levels)
+                .find(|&height| bit_index(height, &leaf.key) != bit_index(height, &previous))
+                .expect("distinct keys differ in a bit");
+            self.collapse(divergence + 1)?;
+        }
+
+        self.total = self
+            .total
+            .checked_add(leaf.sum)
+            .ok_or(MssmtError::SumOverflow)?;
+        self.leaves += 1;
+        self.pending
+            .push((levels, leaf.key, leaf.node_hash(), leaf.sum));
+        Ok(())
+    }
+
+    /// Completes the rebuild and checks the root against `commitment`.
+    ///
+    /// # Returns
+    ///
+    /// - The summary of the verified export.
+    /// - `MssmtError::RootHashMismatch` if the leaves do not rebuild the committed root.
+    /// - `MssmtError::InvalidLeaf` if the committed root sum differs from the sum of the leaves.
+    pub fn finish(mut self, commitment: &RootCommitment) -> Result<AuditSummary> {
+        self.collapse(0)?;
+        let (hash, sum) = match self.pending.pop() {
+            Some((_, _, hash, sum)) => (hash, sum),
+            None => (EmptyTreeOf::<K>::hash_at(0), 0),
+        };
+        if !hash.constant_time_eq(&commitment.hash) {
+            return Err(MssmtError::RootHashMismatch {
+                expected: commitment.hash,
+                actual: hash,
+            });
+        }
+        if sum != self.total || commitment.sum != self.total {
+            return Err(MssmtError::InvalidLeaf(format!(
+                "root sum {} differs from the sum {} of the leaves",
+                commitment.sum, self.total
+            )));
+        }
+        Ok(AuditSummary {
+            commitment: *commitment,
+            leaves: self.leaves,
+        })
+    }
+
+    /// Lifts the last pending subtree up to `height`, combining it with the pending left siblings it
+    /// meets and with empty subtrees elsewhere.
+    fn collapse(&mut self, height: usize) -> Result<()> {
+        while let Some(&(top_height, key, hash, sum)) = self.pending.last() {
+            if top_height <= height {
+                break;
+            }
+            self.pending.pop();
+            let parent = top_height - 1;
+            let (left, right) = match self.pending.last() {
+                Some(&(sibling_height, _, sibling_hash, sibling_sum))
+                    if sibling_height == top_height =>
+                {
+                    self.pending.pop();
+                    ((sibling_hash, sibling_sum), (hash, sum))
+                }
+                _ => {
+                    let empty = (EmptyTreeOf::<K>::hash_at(top_height), 0);
+                    if bit_index(parent, &key) == 0 {
+                        ((hash, sum), empty)
+                    } else {
+                        (empty, (hash, sum))
+                    }
+                }
+            };
+            let sum = left.1.checked_add(right.1).ok_or(MssmtError::SumOverflow)?;
+            self.pending
+                .push((parent, key, branch_hash(&left.0, &right.0, sum), sum));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::DefaultStore;
+
+    #[test]
+    fn test_audit_export_round_trip() -> Result<()> {
+        let mut tree = FullTree::new(DefaultStore::new());
+        let empty =
+            AuditVerifier::<32>::new().finish(&RootCommitment::of(tree.root()?.as_ref()))?;
+        assert_eq!(empty.leaves, 0);
+
+        for i in 0..64u8 {
+            tree.insert([i.wrapping_mul(37); 32], vec![i], i as Sum)?;
+        }
+        tree.insert([0xff; 32], b"last".to_vec(), 1000)?;
+        let mut exported = Vec::new();
+        let summary = tree.audit_export(|leaf| {
+            exported.push(leaf.clone());
+            Ok(())
+        })?;
+        assert_eq!(summary.leaves, 65);
+        assert_eq!(summary.commitment.sum, tree.total_sum()?);
+
+        let verify = |leaves: &[LeafNode]| {
+            let mut verifier = AuditVerifier::new();
+            for leaf in leaves {
+                verifier.push(leaf)?;
+            }
+            verifier.finish(&summary.commitment)
+        };
+        assert_eq!(verify(&exported)?, summary);
+
+        // A hidden, altered or reordered leaf is caught
+        assert!(matches!(
+            verify(&exported[1..]),
+            Err(MssmtError::RootHashMismatch { .. })
+        ));
+        let mut altered = exported.clone();
+        altered[3] = LeafNode::new(altered[3].key, altered[3].value.clone(), 0);
+        assert!(verify(&altered).is_err());
+        let mut reordered = exported.clone();
+        reordered.swap(0, 1);
+        assert!(matches!(
+            verify(&reordered),
+            Err(MssmtError::InvalidEncoding(_))
+        ));
+
+        // An inflated root sum is caught even with a matching hash
+        let mut verifier = AuditVerifier::new();
+        for leaf in &exported {
+            verifier.push(leaf)?;
+        }
+        let inflated = RootCommitment::new(summary.commitment.hash, summary.commitment.sum + 1);
+        assert!(matches!(
+            verifier.finish(&inflated),
+            Err(MssmtError::InvalidLeaf(_))
+        ));
+
+        // Errors of the sink stop the export
+        let mut emitted = 0;
+        let result = tree.audit_export(|_| {
+            emitted += 1;
+            if emitted == 3 {
+                return Err(MssmtError::Cancelled);
+            }
+            Ok(())
+        });
+        assert!(matches!(result, Err(MssmtError::Cancelled)));
+        assert_eq!(emitted, 3);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,283 @@
+//! Incremental backups layered over a base snapshot.
+//!
+//! A `StoreSnapshot` of a large tree holds every node of the store, which makes frequent full backups
+//! expensive. `FullTree::backup_since` captures only the nodes the current version added since the root
+//! of a prior backup: the leaves and branches on the paths that changed. Restoring writes the base
+//! snapshot into a store, then each incremental in the order it was taken.
+//!
+//! Every incremental records the root it was taken against, and `IncrementalBackup::restore` refuses to
+//! layer it over a store at another root, so a missing or reordered incremental is detected rather than
+//! producing a tree with dangling references.
+
+use crate::error::{MssmtError, Result};
+use crate::format::{header, Format, HEADER_SIZE};
+use crate::node::{BranchNode, EmptyTree, LeafNode, Node, NodeHash, EMPTY_TREE};
+use crate::store::{
+    read_checked, read_nodes, write_nodes, Cursor, DefaultStore, HashingWriter, StoreSnapshot,
+    TreeStore, TreeStoreReader,
+};
+use crate::sync::{walk_changes, SyncNode};
+use crate::tree::FullTree;
+use std::fs::File;
+use std::io::{BufReader, Read, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+/// The magic bytes at the start of every incremental backup, followed by the format version.
+pub(crate) const BACKUP_MAGIC: &[u8; 8] = b"MSSMTINC";
+
+/// The header of incremental backups written by this release.
+const BACKUP_HEADER: [u8; HEADER_SIZE] =
+    header(BACKUP_MAGIC, Format::IncrementalBackup.current_version());
+
+/// The nodes a tree added between two roots, see `FullTree::backup_since`.
+///
+/// The binary encoding written by `write_to` is the header `MSSMTINC` followed by the format version
+/// byte, the base root hash, the root hash, then the branches, leaves and checksum laid out as in a
+/// `StoreSnapshot`.
+#[derive(Clone)]
+pub struct IncrementalBackup {
+    /// The root of the prior backup this one is layered over.
+    pub base: NodeHash,
+    /// The root of the tree when the backup was taken.
+    pub root: NodeHash,
+    /// The branches added since `base`, with their children as hash references, children first and
+    /// left before right.
+    pub branches: Vec<Arc<BranchNode>>,
+    /// The leaves added since `base`, in lexicographic key order.
+    pub leaves: Vec<Arc<LeafNode>>,
+}
+
+impl<S: TreeStoreReader> FullTree<S> {
+    /// Captures the nodes of the current version that the version with root `last_root` lacks.
+    ///
+    /// `last_root` is the root of the base snapshot or of the previous incremental backup. The
+    /// incremental grows with the number of changed leaves, not with the size of the tree.
+    ///
+    /// # Returns
+    ///
+    /// - The incremental backup from `last_root` to the current root.
+    /// - `MssmtError::NodeNotFound` if `last_root` is no longer in the store, for example after
+    ///   compaction. A full `StoreSnapshot` is then needed as the new base.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use mssmt::backup::IncrementalBackup;
+    /// use mssmt::store::StoreSnapshot;
+    /// use mssmt::{DefaultStore, FullTree, Node};
+    ///
+    /// let mut tree = FullTree::new(DefaultStore::new());
+    /// tree.insert([1u8; 32], b"one".to_vec(), 1).unwrap();
+    /// let base = StoreSnapshot::capture(tree.store()).unwrap();
+    ///
+    /// tree.insert([2u8; 32], b"two".to_vec(), 2).unwrap();
+    /// let incremental = tree.backup_since(base.root).unwrap();
+    /// assert_eq!(incremental.leaves.len(), 1);
+    ///
+    /// let mut store = DefaultStore::new();
+    /// base.restore(&mut store).unwrap();
+    /// incremental.restore(&mut store).unwrap();
+    /// let restored = FullTree::new(store);
+    /// assert_eq!(restored.root().unwrap().node_hash(), tree.root().unwrap().node_hash());
+    /// ```
+    pub fn backup_since(&self, last_root: NodeHash) -> Result<IncrementalBackup> {
+        let mut branches = Vec::new();
+        let mut leaves = Vec::new();
+        let root = self.sync_delta(last_root, |node| {
+            match node {
+                SyncNode::Branch { branch, .. } => branches.push(branch),
+                SyncNode::Leaf(leaf) => leaves.push(leaf),
+            }
+            Ok(())
+        })?;
+        Ok(IncrementalBackup {
+            base: last_root,
+            root: root.hash,
+            branches,
+            leaves,
+        })
+    }
+}
+
+impl IncrementalBackup {
+    /// Writes the nodes of the backup to `store`, then makes its root the root of the store.
+    ///
+    /// The leaves the new root no longer references are deleted, like `FullTree::apply_sync_delta` does,
+    /// and the superseded branches are left to compaction.
+    ///
+    /// # Returns
+    ///
+    /// - `MssmtError::RootHashMismatch` if the root of `store` is not the base of the backup, in which
+    ///   case the store is left untouched.
+    /// - `MssmtError::InvalidEncoding` if the root is not among the branches of the backup.
+    pub fn restore<S: TreeStore + ?Sized>(&self, store: &mut S) -> Result<()> {
+        let actual = store.root_node()?.node_hash();
+        if actual != self.base {
+            return Err(MssmtError::RootHashMismatch {
+                expected: self.base,
+                actual,
+            });
+        }
+
+        let root: Arc<dyn Node> = if let Some(branch) = self
+            .branches
+            .iter()
+            .find(|branch| branch.node_hash() == self.root)
+        {
+            branch.clone()
+        } else if self.root == self.base {
+            return Ok(());
+        } else if self.root == EmptyTree::hash_at(0) {
+            EMPTY_TREE[0].clone()
+        } else {
+            return Err(MssmtError::InvalidEncoding(format!(
+                "backup root {} is missing from its branches",
+                self.root
+            )));
+        };
+
+        store.insert_nodes(self.branches.clone(), self.leaves.clone())?;
+
+        let old_root = store.root_node()?;
+        let mut stale = Vec::new();
+        walk_changes(
+            store,
+            Some(old_root),
+            Some(root.clone()),
+            0,
+            &mut |_, _, _| Ok(()),
+            &mut |leaf: &LeafNode| {
+                stale.push(leaf.node_hash());
+                Ok(())
+            },
+        )?;
+        for hash in &stale {
+            store.delete_leaf(hash)?;
+        }
+        store.update_root(root)
+    }
+
+    /// Writes the binary encoding of the backup to `writer`.
+    pub fn write_to<W: Write>(&self, writer: W) -> Result<()> {
+        let mut writer = HashingWriter::new(writer);
+        writer.write_all(&BACKUP_HEADER)?;
+        writer.write_all(self.base.as_bytes())?;
+        writer.write_all(self.root.as_bytes())?;
+        write_nodes(&mut writer, &self.branches, &self.leaves)?;
+        writer.finish()
+    }
+
+    /// Reads a backup written by `write_to`.
+    ///
+    /// # Returns
+    ///
+    /// - The decoded backup.
+    /// - `MssmtError::InvalidEncoding` if the input is not an incremental backup, has an unsupported
+    ///   version, is truncated or fails its checksum.
+    pub fn read_from<R: Read>(reader: R) -> Result<Self> {
+        let bytes = read_checked(reader, Format::IncrementalBackup, BACKUP_MAGIC)?;
+        let mut body = Cursor::new(&bytes, Format::IncrementalBackup);
+        let base = NodeHash::new(body.take_array()?);
+        let root = NodeHash::new(body.take_array()?);
+        let (branches, leaves) = read_nodes(&mut body)?;
+        body.finish()?;
+
+        Ok(Self {
+            base,
+            root,
+            branches,
+            leaves,
+        })
+    }
+}
+
+impl DefaultStore {
+    /// Loads a store from a snapshot file written by `save_to`, then layers the incremental backups in
+    /// `incrementals` over it, oldest first.
+    ///
+    /// # Returns
+    ///
+    /// - The restored store.
+    /// - `MssmtError::RootHashMismatch` if an incremental was not taken against the root left by the
+    ///   files before it.
+    pub fn load_layered<P: AsRef<Path>>(
+        base: impl AsRef<Path>,
+        incrementals: impl IntoIterator<Item = P>,
+    ) -> Result<Self> {
+        let mut store = Self::new();
+        StoreSnapshot::read_from(BufReader::new(File::open(base)?))?.restore(&mut store)?;
+        for path in incrementals {
+            IncrementalBackup::read_from(BufReader::new(File::open(path)?))?.restore(&mut store)?;
+        }
+        Ok(store)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::Sum;
+
+    #[test]
+    fn test_incremental_backups_layer_over_base() -> Result<()> {
+        let dir = std::env::temp_dir();
+        let pid = std::process::id();
+        let mut tree = FullTree::new(DefaultStore::new());
+        for i in 0..16u8 {
+            tree.insert([i; 32], vec![i], i as Sum)?;
+        }
+        let base = dir.join(format!("mssmt-backup-{pid}.snapshot"));
+        tree.store().save_to(&base)?;
+        let mut last_root = tree.root()?.node_hash();
+
+        let mut incrementals = Vec::new();
+        for round in 0..3u8 {
+            tree.insert([100 + round; 32], vec![round], 1)?;
+            tree.delete([round; 32])?;
+            let backup = tree.backup_since(last_root)?;
+            // Two changed paths at most, never the whole tree
+            assert!(backup.leaves.len() == 1 && backup.branches.len() <= 512);
+
+            let path = dir.join(format!("mssmt-backup-{pid}-{round}.inc"));
+            let mut bytes = Vec::new();
+            backup.write_to(&mut bytes)?;
+            std::fs::write(&path, &bytes)?;
+            last_root = backup.root;
+            incrementals.push(path);
+        }
+
+        let restored = FullTree::new(DefaultStore::load_layered(&base, &incrementals)?);
+        assert_eq!(restored.root()?.node_hash(), tree.root()?.node_hash());
+        assert_eq!(restored.get([101u8; 32])?, Some((vec![1], 1)));
+        assert!(!restored.contains_key([2u8; 32])?);
+
+        // A skipped incremental is rejected
+        assert!(matches!(
+            DefaultStore::load_layered(&base, &incrementals[1..]),
+            Err(MssmtError::RootHashMismatch { .. })
+        ));
+
+        // An unchanged tree yields an empty incremental that restores as a no-op
+        let empty = tree.backup_since(last_root)?;
+        assert!(empty.branches.is_empty() && empty.leaves.is_empty());
+        let mut store = DefaultStore::load_layered(&base, &incrementals)?;
+        empty.restore(&mut store)?;
+
+        let bytes = std::fs::read(&incrementals[0])?;
+        assert_eq!(
+            crate::format::detect(&bytes)?,
+            (Format::IncrementalBackup, 1)
+        );
+        assert!(matches!(
+            IncrementalBackup::read_from(&bytes[..bytes.len() - 1]),
+            Err(MssmtError::InvalidEncoding(_))
+        ));
+
+        std::fs::remove_file(&base)?;
+        for path in &incrementals {
+            std::fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+}
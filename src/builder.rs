@@ -0,0 +1,218 @@
+//! A single construction path for configured trees.
+//!
+//! A tree picks up its validation policy, hash scheme, observers, metrics, executor and optional
+//! indexes through setters called after `FullTree::new`. `FullTree::builder` gathers them before the
+//! tree exists, so a configured tree is created in one expression and never observed half-configured.
+//!
+//! Caches live outside the tree: node caches wrap the store, see `CachedStore`, and a `ProofCache` is
+//! registered with `TreeBuilder::observer` so it is cleared on every root change.
+
+use crate::config::TreeConfig;
+use crate::error::Result;
+use crate::executor::Executor;
+use crate::metrics::Metrics;
+use crate::node::{LeafValue, HASH_SIZE};
+use crate::observer::TreeObserver;
+use crate::store::TreeStoreReader;
+use crate::tagged::HashScheme;
+use crate::tree::FullTree;
+use std::sync::Arc;
+
+/// Builds a `FullTree` over a store, see `FullTree::builder`.
+///
+/// `K` is the key size in bytes of the tree, 32 by default, and `V` the type of its values, `Vec<u8>`
+/// by default.
+pub struct TreeBuilder<S, const K: usize = HASH_SIZE, V = Vec<u8>> {
+    store: S,
+    config: TreeConfig<K, V>,
+    hash_scheme: HashScheme,
+    observers: Vec<Box<dyn TreeObserver<K, V>>>,
+    metrics: Option<Arc<dyn Metrics>>,
+    executor: Option<Arc<dyn Executor>>,
+    sum_index: bool,
+    leaf_metadata: bool,
+    root_history: bool,
+}
+
+impl<S: TreeStoreReader<K, V>, const K: usize, V: LeafValue> FullTree<S, K, V> {
+    /// Returns a builder for a tree over `store`, with the defaults of `FullTree::new`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use mssmt::config::{OverflowPolicy, TreeConfig};
+    /// use mssmt::proof_cache::ProofCache;
+    /// use mssmt::{DefaultStore, FullTree, HashScheme};
+    /// use std::sync::Arc;
+    ///
+    /// let cache = Arc::new(ProofCache::new(1024));
+    /// let mut tree = FullTree::builder(DefaultStore::new())
+    ///     .config(
+    ///         TreeConfig::default()
+    ///             .with_max_value_size(64)
+    ///             .with_overflow_policy(OverflowPolicy::Saturating),
+    ///     )
+    ///     .hash_scheme(HashScheme::V1)
+    ///     .observer(cache.clone())
+    ///     .root_history()
+    ///     .build()
+    ///     .unwrap();
+    ///
+    /// assert!(tree.insert([1u8; 32], vec![0; 65], 1).is_err());
+    /// tree.insert([1u8; 32], b"value".to_vec(), 1).unwrap();
+    /// assert_eq!(tree.root_history().len(), 2);
+    /// ```
+    pub fn builder(store: S) -> TreeBuilder<S, K, V> {
+        TreeBuilder {
+            store,
+            config: TreeConfig::default(),
+            hash_scheme: HashScheme::V0,
+            observers: Vec::new(),
+            metrics: None,
+            executor: None,
+            sum_index: false,
+            leaf_metadata: false,
+            root_history: false,
+        }
+    }
+}
+
+impl<S: TreeStoreReader<K, V>, const K: usize, V: LeafValue> TreeBuilder<S, K, V> {
+    /// Sets the validation and overflow policies, see `FullTree::set_config`.
+    pub fn config(mut self, config: TreeConfig<K, V>) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Sets the hash scheme the tree commits under, see `FullTree::set_hash_scheme`.
+    pub fn hash_scheme(mut self, hash_scheme: HashScheme) -> Self {
+        self.hash_scheme = hash_scheme;
+        self
+    }
+
+    /// Registers an observer, see `FullTree::add_observer`. Observers are called in the order they are
+    /// registered.
+    pub fn observer(mut self, observer: impl TreeObserver<K, V> + 'static) -> Self {
+        self.observers.push(Box::new(observer));
+        self
+    }
+
+    /// Reports operation latencies and proof generations to `metrics`, see `FullTree::set_metrics`.
+    pub fn metrics(mut self, metrics: Arc<dyn Metrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Runs the parallel work of batch operations on `executor`, see `FullTree::set_executor`.
+    pub fn executor(mut self, executor: Arc<dyn Executor>) -> Self {
+        self.executor = Some(executor);
+        self
+    }
+
+    /// Enables the sum index, see `FullTree::enable_sum_index`.
+    pub fn sum_index(mut self) -> Self {
+        self.sum_index = true;
+        self
+    }
+
+    /// Enables leaf metadata, see `FullTree::enable_leaf_metadata`.
+    pub fn leaf_metadata(mut self) -> Self {
+        self.leaf_metadata = true;
+        self
+    }
+
+    /// Enables the root history, see `FullTree::enable_root_history`.
+    pub fn root_history(mut self) -> Self {
+        self.root_history = true;
+        self
+    }
+
+    /// Creates the tree.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the root history is enabled and the current root cannot be read.
+    pub fn build(self) -> Result<FullTree<S, K, V>> {
+        let mut tree = FullTree::new(self.store);
+        tree.set_config(self.config);
+        tree.set_hash_scheme(self.hash_scheme);
+        for observer in self.observers {
+            tree.add_boxed_observer(observer);
+        }
+        if let Some(metrics) = self.metrics {
+            tree.set_metrics(metrics);
+        }
+        if let Some(executor) = self.executor {
+            tree.set_executor(executor);
+        }
+        if self.sum_index {
+            tree.enable_sum_index();
+        }
+        if self.leaf_metadata {
+            tree.enable_leaf_metadata();
+        }
+        if self.root_history {
+            tree.enable_root_history()?;
+        }
+        Ok(tree)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::OverflowPolicy;
+    use crate::error::MssmtError;
+    use crate::executor::SingleThreaded;
+    use crate::key::Key;
+    use crate::node::{LeafNode, Sum};
+    use crate::store::DefaultStore;
+    use parking_lot::Mutex;
+
+    struct Inserts(Mutex<usize>);
+
+    impl TreeObserver for Inserts {
+        fn on_insert(&self, _key: &Key, _leaf: &LeafNode, _previous: Option<&LeafNode>) {
+            *self.0.lock() += 1;
+        }
+    }
+
+    #[test]
+    fn test_builder_applies_every_setting() -> Result<()> {
+        let inserts = Arc::new(Inserts(Mutex::new(0)));
+        let scheme = HashScheme::V1;
+        let mut tree = FullTree::builder(DefaultStore::new())
+            .config(TreeConfig::default().with_overflow_policy(OverflowPolicy::Saturating))
+            .hash_scheme(scheme)
+            .observer(inserts.clone())
+            .executor(Arc::new(SingleThreaded))
+            .sum_index()
+            .leaf_metadata()
+            .root_history()
+            .build()?;
+
+        tree.insert([1u8; 32], b"one".to_vec(), Sum::MAX)?;
+        tree.insert([2u8; 32], b"two".to_vec(), 1)?;
+        assert_eq!(tree.root()?.node_sum(), Sum::MAX);
+        assert_eq!(tree.config().overflow_policy(), OverflowPolicy::Saturating);
+        assert_eq!(tree.hash_scheme(), scheme);
+        assert_eq!(*inserts.0.lock(), 2);
+        assert_eq!(tree.executor().parallelism(), 1);
+        assert_eq!(tree.root_history().len(), 3);
+        assert_eq!(tree.max_sum_leaf()?.map(|leaf| leaf.key), Some([1u8; 32]));
+        let metadata = tree.leaf_metadata([2u8; 32])?;
+        assert_eq!(metadata.map(|metadata| metadata.inserted_version), Some(2));
+
+        // The defaults match `FullTree::new`
+        let mut plain = FullTree::builder(DefaultStore::new()).build()?;
+        assert!(matches!(
+            plain
+                .insert([1u8; 32], b"one".to_vec(), Sum::MAX)
+                .and_then(|_| plain.insert([2u8; 32], b"two".to_vec(), 1)),
+            Err(MssmtError::SumOverflow)
+        ));
+        assert_eq!(plain.hash_scheme(), HashScheme::V0);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,224 @@
+//! Cancellation and resumption of long-running bulk operations.
+//!
+//! Bulk inserts, builds from leaves, compactions and integrity audits can run for minutes on large
+//! trees. The cancellable variants of these operations take a `CancellationToken`, check it between
+//! store writes, and return `MssmtError::Cancelled` once it is cancelled, leaving the store consistent:
+//!
+//! - `FullTree::insert_batch` and `FullTree::from_leaves_cancellable` record their progress in a
+//!   `Checkpoint`, and pick up where they stopped when called again with the same input and checkpoint.
+//! - `FullTree::compact_cancellable` only deletes unreachable nodes, so running it again resumes the
+//!   compaction from the nodes left in the store.
+//! - `FullTree::verify_integrity_cancellable` does not write to the store, and restarts from the root.
+
+use crate::error::{MssmtError, Result};
+use crate::key::Key;
+use crate::node::{LeafValue, NodeHash, Sum};
+use crate::progress::{exact_len, insert_writes, Progress, ProgressTracker};
+use crate::store::TreeStore;
+use crate::tree::FullTree;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// A flag shared between a bulk operation and the code that may abort it.
+///
+/// Clones share the flag, so one clone can be handed to the operation and another kept by a signal
+/// handler or a supervising task.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    /// Creates a token that is not cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancels the operations using this token, or any of its clones.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns `true` once the token has been cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Returns `MssmtError::Cancelled` if the token has been cancelled.
+    pub(crate) fn check(&self) -> Result<()> {
+        match self.is_cancelled() {
+            true => Err(MssmtError::Cancelled),
+            false => Ok(()),
+        }
+    }
+}
+
+/// The progress of a resumable bulk operation.
+///
+/// A checkpoint only makes sense for the input it was recorded with: resuming with different input
+/// skips the wrong items.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Checkpoint {
+    /// The number of items already applied to the store.
+    pub completed: usize,
+}
+
+impl<S: TreeStore<K, V>, const K: usize, V: LeafValue> FullTree<S, K, V> {
+    /// Inserts a batch of key-value-sum entries, one at a time, until done or cancelled.
+    ///
+    /// Entries are inserted in order, and the first `checkpoint.completed` entries are skipped, so a
+    /// cancelled batch is resumed by calling this again with the same entries and checkpoint. Every
+    /// entry is applied like `FullTree::insert`, so the tree is consistent whenever the batch stops.
+    ///
+    /// # Arguments
+    ///
+    /// - `entries`: The keys, values and sums to insert.
+    /// - `token`: Checked before each entry.
+    /// - `checkpoint`: The number of entries already inserted, updated after each entry.
+    /// - `progress`: Called after each entry. The total is known if the iterator reports its exact length.
+    ///
+    /// # Returns
+    ///
+    /// - The hash of the new root, once every entry is inserted.
+    /// - `MssmtError::Cancelled` if the token was cancelled before the last entry.
+    /// - `MssmtError::InvalidLeaf` if a leaf is rejected by the configuration of the tree. The checkpoint
+    ///   points at the rejected entry.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use mssmt::cancel::{CancellationToken, Checkpoint};
+    /// use mssmt::{DefaultStore, FullTree, MssmtError};
+    ///
+    /// let mut tree = FullTree::new(DefaultStore::new());
+    /// let entries: Vec<_> = (0..10u8).map(|i| ([i; 32], vec![i], i.into())).collect();
+    /// let token = CancellationToken::new();
+    /// let mut checkpoint = Checkpoint::default();
+    ///
+    /// token.cancel();
+    /// let result = tree.insert_batch(entries.clone(), &token, &mut checkpoint, |_| {});
+    /// assert!(matches!(result, Err(MssmtError::Cancelled)));
+    ///
+    /// let mut remaining = None;
+    /// tree.insert_batch(entries, &CancellationToken::new(), &mut checkpoint, |progress| {
+    ///     remaining = progress.eta();
+    /// })
+    /// .unwrap();
+    /// assert_eq!(checkpoint.completed, 10);
+    /// assert!(remaining.is_some());
+    /// assert_eq!(tree.total_sum().unwrap(), 45);
+    /// ```
+    pub fn insert_batch<T: Into<Key<K>>>(
+        &mut self,
+        entries: impl IntoIterator<Item = (T, V, Sum)>,
+        token: &CancellationToken,
+        checkpoint: &mut Checkpoint,
+        progress: impl FnMut(Progress),
+    ) -> Result<NodeHash> {
+        let entries = entries.into_iter();
+        let total = exact_len(&entries);
+        let mut progress = ProgressTracker::new(progress, total, checkpoint.completed as u64);
+        for (key, value, sum) in entries.skip(checkpoint.completed) {
+            token.check()?;
+            self.insert(key, value, sum)?;
+            checkpoint.completed += 1;
+            progress.advance(1, insert_writes(K));
+        }
+        Ok(self.root()?.node_hash())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::LeafNode;
+    use crate::store::DefaultStore;
+    use crate::tree::FullTree;
+
+    #[test]
+    fn test_cancelled_operations_resume() -> Result<()> {
+        let entries: Vec<_> = (0..16u8).map(|i| ([i; 32], vec![i], i as Sum)).collect();
+        let leaves: Vec<_> = entries
+            .iter()
+            .map(|(key, value, sum)| LeafNode::new(*key, value.clone(), *sum))
+            .collect();
+        let expected = FullTree::from_leaves(DefaultStore::new(), leaves.clone())?
+            .root()?
+            .node_hash();
+
+        // A batch cancelled halfway resumes from its checkpoint
+        let token = CancellationToken::new();
+        let mut checkpoint = Checkpoint::default();
+        let mut tree = FullTree::new(DefaultStore::new());
+        let mut inserted = 0;
+        let result = tree.insert_batch(
+            entries.iter().cloned().inspect(|_| {
+                inserted += 1;
+                if inserted == 8 {
+                    token.cancel();
+                }
+            }),
+            &token,
+            &mut checkpoint,
+            |_| {},
+        );
+        assert!(matches!(result, Err(MssmtError::Cancelled)));
+        assert_eq!(checkpoint.completed, 7);
+        assert_eq!(tree.get([6u8; 32])?, Some((vec![6], 6)));
+        assert_eq!(tree.get([7u8; 32])?, None);
+        let mut reports = Vec::new();
+        let root_hash = tree.insert_batch(
+            entries,
+            &CancellationToken::new(),
+            &mut checkpoint,
+            |progress| reports.push(progress),
+        )?;
+        assert_eq!((root_hash, checkpoint.completed), (expected, 16));
+        let last = reports.last().unwrap();
+        assert_eq!(reports.len(), 9);
+        assert_eq!(
+            (last.items_processed, last.items_total, last.nodes_written),
+            (16, Some(16), 9 * 257)
+        );
+
+        // A cancelled build leaves the store root untouched until it is resumed
+        let mut checkpoint = Checkpoint::default();
+        let token = CancellationToken::new();
+        token.cancel();
+        let mut store = DefaultStore::new();
+        let result = FullTree::from_leaves_cancellable(
+            &mut store,
+            leaves.clone(),
+            &token,
+            &mut checkpoint,
+            |_| {},
+        );
+        assert!(matches!(result, Err(MssmtError::Cancelled)));
+        assert_eq!(checkpoint.completed, 0);
+        let tree = FullTree::from_leaves_cancellable(
+            &mut store,
+            leaves,
+            &CancellationToken::new(),
+            &mut checkpoint,
+            |_| {},
+        )?;
+        assert_eq!(tree.root()?.node_hash(), expected);
+
+        // Compactions and audits stop when cancelled, and complete when run again
+        let mut tree = FullTree::new(store);
+        tree.insert([1u8; 32], b"updated".to_vec(), 10)?;
+        assert!(matches!(
+            tree.compact_cancellable(&token),
+            Err(MssmtError::Cancelled)
+        ));
+        assert!(matches!(
+            tree.verify_integrity_cancellable(&token, |_| {}),
+            Err(MssmtError::Cancelled)
+        ));
+        let token = CancellationToken::new();
+        assert_eq!(tree.compact_cancellable(&token)?.leaves_removed, 1);
+        assert!(tree.verify_integrity_cancellable(&token, |_| {})?.is_ok());
+
+        Ok(())
+    }
+}
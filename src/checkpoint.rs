@@ -0,0 +1,275 @@
+//! Periodic checkpoints of the roots of a tree.
+//!
+//! Services committing to a tree usually need a timeline of their commitments: which root was current
+//! at a given time, or at a given version. A `Checkpointer` consumes the `RootUpdate`s of a tree,
+//! see `FullTree::subscribe`, and records a `RootCheckpoint` into a `CheckpointSink` whenever its
+//! `Cadence` is due. `MemoryCheckpoints` keeps them in memory and answers queries over them; sinks
+//! anchoring checkpoints elsewhere, such as a database or a public ledger, implement the trait.
+//!
+//! Checkpoints are taken from the updates a tree emits, so a root is checkpointed only after it was
+//! written to the store.
+
+use crate::commitment::RootCommitment;
+use crate::error::Result;
+use crate::node::{NodeHash, Sum};
+use crate::observer::RootUpdate;
+use parking_lot::Mutex;
+use std::sync::mpsc::Receiver;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// A root of a tree, recorded at a point in time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct RootCheckpoint {
+    /// The time of the checkpoint, in seconds since the Unix epoch.
+    pub timestamp: u64,
+    /// The version of the tree, see `FullTree::root_version`.
+    pub version: u64,
+    /// The hash of the root.
+    pub root_hash: NodeHash,
+    /// The sum of the root.
+    pub root_sum: Sum,
+}
+
+impl RootCheckpoint {
+    /// Returns the hash and sum of the root as a commitment.
+    pub fn commitment(&self) -> RootCommitment {
+        RootCommitment::new(self.root_hash, self.root_sum)
+    }
+}
+
+/// How often a `Checkpointer` records a checkpoint.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Cadence {
+    /// Every `n`-th version of the tree, counting from the first update seen. 0 is treated as 1.
+    Versions(u64),
+    /// The first update at least the given duration after the previous checkpoint.
+    Interval(Duration),
+}
+
+/// A destination for checkpoints.
+pub trait CheckpointSink: Send + Sync {
+    /// Records `checkpoint`. Checkpoints are recorded in increasing version order.
+    fn record(&self, checkpoint: &RootCheckpoint) -> Result<()>;
+}
+
+impl<T: CheckpointSink + ?Sized> CheckpointSink for Arc<T> {
+    fn record(&self, checkpoint: &RootCheckpoint) -> Result<()> {
+        (**self).record(checkpoint)
+    }
+}
+
+/// Records checkpoints of a tree into a sink on a cadence.
+///
+/// # Examples
+///
+/// ```rust
+/// use mssmt::checkpoint::{Cadence, Checkpointer, MemoryCheckpoints};
+/// use mssmt::{DefaultStore, FullTree};
+/// use std::sync::Arc;
+///
+/// let checkpoints = Arc::new(MemoryCheckpoints::new());
+/// let mut checkpointer = Checkpointer::new(checkpoints.clone(), Cadence::Versions(2));
+/// let mut tree = FullTree::new(DefaultStore::new());
+/// let updates = tree.subscribe();
+///
+/// for i in 0..5u8 {
+///     tree.insert([i; 32], vec![i], 1).unwrap();
+/// }
+/// checkpointer.poll(&updates).unwrap();
+///
+/// let versions: Vec<u64> = checkpoints.all().iter().map(|c| c.version).collect();
+/// assert_eq!(versions, [1, 3, 5]);
+/// assert_eq!(checkpoints.at_version(4).unwrap().root_sum, 3);
+/// ```
+pub struct Checkpointer<S> {
+    sink: S,
+    cadence: Cadence,
+    last: Option<RootCheckpoint>,
+}
+
+impl<S: CheckpointSink> Checkpointer<S> {
+    /// Creates a checkpointer recording into `sink` on `cadence`. The first update it sees is always
+    /// checkpointed.
+    pub fn new(sink: S, cadence: Cadence) -> Self {
+        Self {
+            sink,
+            cadence,
+            last: None,
+        }
+    }
+
+    /// Returns the last recorded checkpoint, if any.
+    pub fn last(&self) -> Option<RootCheckpoint> {
+        self.last
+    }
+
+    /// Records a checkpoint of `update` if the cadence is due, timestamped now.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(true)` if a checkpoint was recorded.
+    /// - The error of the sink, in which case the next update is checkpointed instead.
+    pub fn observe(&mut self, update: RootUpdate) -> Result<bool> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        self.observe_at(update, timestamp)
+    }
+
+    /// Records a checkpoint of `update` at `timestamp`, in seconds since the Unix epoch, if the cadence
+    /// is due.
+    pub fn observe_at(&mut self, update: RootUpdate, timestamp: u64) -> Result<bool> {
+        let due = match (self.last, self.cadence) {
+            (None, _) => true,
+            (Some(last), Cadence::Versions(n)) => update.version >= last.version + n.max(1),
+            (Some(last), Cadence::Interval(interval)) => {
+                timestamp.saturating_sub(last.timestamp) >= interval.as_secs()
+            }
+        };
+        if !due {
+            return Ok(false);
+        }
+
+        let checkpoint = RootCheckpoint {
+            timestamp,
+            version: update.version,
+            root_hash: update.root_hash,
+            root_sum: update.root_sum,
+        };
+        self.sink.record(&checkpoint)?;
+        self.last = Some(checkpoint);
+        Ok(true)
+    }
+
+    /// Observes every update pending on `updates`, without waiting for new ones.
+    ///
+    /// # Returns
+    ///
+    /// - The number of checkpoints recorded.
+    /// - The first error of the sink, leaving the remaining updates pending.
+    pub fn poll(&mut self, updates: &Receiver<RootUpdate>) -> Result<usize> {
+        let mut recorded = 0;
+        for update in updates.try_iter() {
+            recorded += usize::from(self.observe(update)?);
+        }
+        Ok(recorded)
+    }
+
+    /// Observes the updates of `updates` until the tree is dropped, for running on a dedicated thread.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(())` once the tree is dropped.
+    /// - The first error of the sink.
+    pub fn run(&mut self, updates: Receiver<RootUpdate>) -> Result<()> {
+        for update in updates {
+            self.observe(update)?;
+        }
+        Ok(())
+    }
+}
+
+/// A sink keeping checkpoints in memory, with queries over the timeline.
+#[derive(Default)]
+pub struct MemoryCheckpoints {
+    checkpoints: Mutex<Vec<RootCheckpoint>>,
+}
+
+impl MemoryCheckpoints {
+    /// Creates an empty timeline.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns every checkpoint, oldest first.
+    pub fn all(&self) -> Vec<RootCheckpoint> {
+        self.checkpoints.lock().clone()
+    }
+
+    /// Returns the most recent checkpoint, if any.
+    pub fn latest(&self) -> Option<RootCheckpoint> {
+        self.checkpoints.lock().last().copied()
+    }
+
+    /// Returns the last checkpoint at or before `version`, the root that was checkpointed when the
+    /// tree was at that version.
+    pub fn at_version(&self, version: u64) -> Option<RootCheckpoint> {
+        let checkpoints = self.checkpoints.lock();
+        let end = checkpoints.partition_point(|checkpoint| checkpoint.version <= version);
+        end.checked_sub(1).map(|index| checkpoints[index])
+    }
+
+    /// Returns the last checkpoint at or before `timestamp`, in seconds since the Unix epoch.
+    pub fn at_time(&self, timestamp: u64) -> Option<RootCheckpoint> {
+        let checkpoints = self.checkpoints.lock();
+        let end = checkpoints.partition_point(|checkpoint| checkpoint.timestamp <= timestamp);
+        end.checked_sub(1).map(|index| checkpoints[index])
+    }
+
+    /// Returns the checkpoint of the root `root_hash`, if it was checkpointed.
+    pub fn find(&self, root_hash: &NodeHash) -> Option<RootCheckpoint> {
+        let checkpoints = self.checkpoints.lock();
+        checkpoints
+            .iter()
+            .rev()
+            .find(|checkpoint| checkpoint.root_hash == *root_hash)
+            .copied()
+    }
+}
+
+impl CheckpointSink for MemoryCheckpoints {
+    fn record(&self, checkpoint: &RootCheckpoint) -> Result<()> {
+        self.checkpoints.lock().push(*checkpoint);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::DefaultStore;
+    use crate::tree::FullTree;
+
+    #[test]
+    fn test_interval_cadence_and_queries() -> Result<()> {
+        let checkpoints = Arc::new(MemoryCheckpoints::new());
+        let mut checkpointer = Checkpointer::new(
+            checkpoints.clone(),
+            Cadence::Interval(Duration::from_secs(60)),
+        );
+        let mut tree = FullTree::new(DefaultStore::new());
+        let updates = tree.subscribe();
+
+        // One update every 25 seconds, checkpointed every 60 seconds at most
+        for i in 0..10u8 {
+            tree.insert([i; 32], vec![i], 1)?;
+            let update = updates.try_recv().unwrap();
+            checkpointer.observe_at(update, 1_000 + 25 * i as u64)?;
+        }
+        let timeline: Vec<(u64, u64)> = checkpoints
+            .all()
+            .iter()
+            .map(|checkpoint| (checkpoint.timestamp, checkpoint.version))
+            .collect();
+        assert_eq!(timeline, [(1_000, 1), (1_075, 4), (1_150, 7), (1_225, 10)]);
+        assert_eq!(checkpointer.last(), checkpoints.latest());
+
+        assert_eq!(checkpoints.at_time(999), None);
+        assert_eq!(checkpoints.at_time(1_100).map(|c| c.version), Some(4));
+        assert_eq!(checkpoints.at_version(9).map(|c| c.root_sum), Some(7));
+        let latest = checkpoints.latest().unwrap();
+        assert_eq!(latest.commitment(), tree.commitment()?);
+        assert_eq!(checkpoints.find(&latest.root_hash), Some(latest));
+
+        // `run` drains the subscription until the tree is dropped
+        tree.insert([99u8; 32], vec![], 1)?;
+        drop(tree);
+        let mut checkpointer = Checkpointer::new(checkpoints.clone(), Cadence::Versions(1));
+        checkpointer.run(updates)?;
+        assert_eq!(checkpoints.latest().map(|c| c.version), Some(11));
+
+        Ok(())
+    }
+}
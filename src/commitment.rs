@@ -0,0 +1,211 @@
+//! The root hash and sum of a tree as one value.
+//!
+//! A tree commits to its leaves through the hash of its root, and to their total through the sum of
+//! its root. Verifiers almost always need both, so `RootCommitment` carries them together and
+//! `Proof::verify_against` checks a proof against the pair. A commitment encodes to
+//! `HASH_SIZE + SUM_SIZE` bytes: the hash followed by the big-endian sum.
+
+use crate::error::{MssmtError, Result};
+use crate::key::Key;
+use crate::node::{decode_sum, LeafNode, LeafValue, Node, NodeHash, Sum, HASH_SIZE, SUM_SIZE};
+use crate::proof::Proof;
+use std::fmt;
+use std::str::FromStr;
+
+/// The hash and sum of the root of a tree.
+///
+/// # Examples
+///
+/// ```rust
+/// use mssmt::{DefaultStore, FullTree, LeafNode, RootCommitment};
+///
+/// let mut tree = FullTree::new(DefaultStore::new());
+/// tree.insert([1u8; 32], b"one".to_vec(), 1).unwrap();
+///
+/// let commitment = tree.commitment().unwrap();
+/// assert_eq!(commitment.sum, 1);
+/// assert_eq!(commitment.to_hex().parse::<RootCommitment>().unwrap(), commitment);
+///
+/// let proof = tree.merkle_proof([1u8; 32]).unwrap();
+/// let leaf = LeafNode::new([1u8; 32], b"one".to_vec(), 1);
+/// assert!(proof.verify_against([1u8; 32], &leaf, &commitment));
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "json",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(into = "JsonCommitment", try_from = "JsonCommitment")
+)]
+pub struct RootCommitment {
+    /// The hash of the root.
+    pub hash: NodeHash,
+    /// The sum of the root.
+    pub sum: Sum,
+}
+
+impl RootCommitment {
+    /// The size of the byte encoding of a commitment.
+    pub const ENCODED_SIZE: usize = HASH_SIZE + SUM_SIZE;
+
+    /// Creates a commitment to `hash` and `sum`.
+    pub fn new(hash: NodeHash, sum: Sum) -> Self {
+        Self { hash, sum }
+    }
+
+    /// Returns the commitment of `node`.
+    pub fn of(node: &dyn Node) -> Self {
+        Self::new(node.node_hash(), node.node_sum())
+    }
+
+    /// Encodes the commitment as the root hash followed by the big-endian sum.
+    pub fn encode(&self) -> [u8; Self::ENCODED_SIZE] {
+        let mut bytes = [0u8; Self::ENCODED_SIZE];
+        bytes[..HASH_SIZE].copy_from_slice(self.hash.as_bytes());
+        bytes[HASH_SIZE..].copy_from_slice(&self.sum.to_be_bytes());
+        bytes
+    }
+
+    /// Decodes a commitment produced by `encode`.
+    ///
+    /// # Returns
+    ///
+    /// - The decoded commitment.
+    /// - `MssmtError::InvalidEncoding` if `bytes` is not `ENCODED_SIZE` bytes long.
+    pub fn decode(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() != Self::ENCODED_SIZE {
+            return Err(MssmtError::InvalidEncoding(format!(
+                "expected {} bytes, got {}",
+                Self::ENCODED_SIZE,
+                bytes.len()
+            )));
+        }
+        let mut hash = [0u8; HASH_SIZE];
+        hash.copy_from_slice(&bytes[..HASH_SIZE]);
+        Ok(Self::new(NodeHash(hash), decode_sum(&bytes[HASH_SIZE..])))
+    }
+
+    /// Encodes the commitment as lowercase hex.
+    pub fn to_hex(&self) -> String {
+        hex::encode(self.encode())
+    }
+
+    /// Decodes a commitment produced by `to_hex`.
+    pub fn from_hex(hex_str: &str) -> Result<Self> {
+        let bytes =
+            hex::decode(hex_str).map_err(|err| MssmtError::InvalidEncoding(err.to_string()))?;
+        Self::decode(&bytes)
+    }
+}
+
+impl From<(NodeHash, Sum)> for RootCommitment {
+    fn from((hash, sum): (NodeHash, Sum)) -> Self {
+        Self::new(hash, sum)
+    }
+}
+
+impl From<RootCommitment> for (NodeHash, Sum) {
+    fn from(commitment: RootCommitment) -> Self {
+        (commitment.hash, commitment.sum)
+    }
+}
+
+/// Formats the commitment as `to_hex`.
+impl fmt::Display for RootCommitment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_hex())
+    }
+}
+
+impl FromStr for RootCommitment {
+    type Err = MssmtError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Self::from_hex(s)
+    }
+}
+
+/// The serialized form of a commitment, with the hash in hex.
+#[cfg(feature = "json")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct JsonCommitment {
+    hash: String,
+    sum: Sum,
+}
+
+#[cfg(feature = "json")]
+impl From<RootCommitment> for JsonCommitment {
+    fn from(commitment: RootCommitment) -> Self {
+        Self {
+            hash: commitment.hash.to_string(),
+            sum: commitment.sum,
+        }
+    }
+}
+
+#[cfg(feature = "json")]
+impl TryFrom<JsonCommitment> for RootCommitment {
+    type Error = MssmtError;
+
+    fn try_from(json: JsonCommitment) -> Result<Self> {
+        Ok(Self::new(json.hash.parse()?, json.sum))
+    }
+}
+
+impl<const K: usize> Proof<K> {
+    /// Verifies the proof of `leaf` at `key` against both the hash and the sum of `commitment`.
+    ///
+    /// Equivalent to `verify_with_sum` with the hash and sum of the commitment.
+    pub fn verify_against(
+        &self,
+        key: impl Into<Key<K>>,
+        leaf: &LeafNode<K, impl LeafValue>,
+        commitment: &RootCommitment,
+    ) -> bool {
+        self.verify_with_sum(key, leaf, commitment.hash, Some(commitment.sum))
+            .is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::DefaultStore;
+    use crate::tree::FullTree;
+
+    #[test]
+    fn test_commitment_encodings_and_verification() -> Result<()> {
+        let mut tree = FullTree::new(DefaultStore::new());
+        for i in 1..=4u8 {
+            tree.insert([i; 32], vec![i], i as Sum)?;
+        }
+        let commitment = tree.commitment()?;
+        assert_eq!(commitment, RootCommitment::of(tree.root()?.as_ref()));
+
+        let encoded = commitment.encode();
+        assert_eq!(RootCommitment::decode(&encoded)?, commitment);
+        assert!(RootCommitment::decode(&encoded[1..]).is_err());
+        assert_eq!(
+            commitment.to_string().parse::<RootCommitment>()?,
+            commitment
+        );
+
+        #[cfg(feature = "json")]
+        {
+            let json = serde_json::to_string(&commitment).unwrap();
+            assert!(json.contains(&commitment.hash.to_string()));
+            assert_eq!(
+                serde_json::from_str::<RootCommitment>(&json).unwrap(),
+                commitment
+            );
+        }
+
+        // The sum is checked along with the hash
+        let leaf = LeafNode::new([2u8; 32], vec![2], 2);
+        let proof = tree.merkle_proof([2u8; 32])?;
+        assert!(proof.verify_against([2u8; 32], &leaf, &commitment));
+        let wrong_sum = RootCommitment::new(commitment.hash, commitment.sum + 1);
+        assert!(!proof.verify_against([2u8; 32], &leaf, &wrong_sum));
+
+        Ok(())
+    }
+}
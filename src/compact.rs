@@ -0,0 +1,303 @@
+//! Store compaction for the Merkle-Sum Sparse Merkle Tree.
+//!
+//! Tree updates are copy-on-write: every insert or delete writes a fresh path of branches and leaves the
+//! superseded nodes behind in the store. `FullTree::compact` reclaims that garbage by deleting every node
+//! that is not reachable from the current root. Trees deleting in `DeleteMode::Tombstone` also have
+//! their tombstones purged first.
+
+use crate::cancel::CancellationToken;
+use crate::config::DeleteMode;
+use crate::error::Result;
+use crate::node::{EmptyTree, Node, NodeHash, MAX_TREE_LEVELS};
+use crate::store::{resolve_node, TreeStore, TreeStoreReader};
+use crate::tree::FullTree;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+/// The number of nodes removed by a compaction.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CompactionReport {
+    /// The number of unreachable branch nodes deleted from the store.
+    pub branches_removed: usize,
+    /// The number of unreachable leaf nodes deleted from the store.
+    pub leaves_removed: usize,
+    /// The number of tombstones replaced with the empty leaf, see `DeleteMode::Tombstone`.
+    pub tombstones_purged: usize,
+}
+
+impl<S: TreeStore> FullTree<S> {
+    /// Deletes every branch and leaf in the store that is not reachable from the current root.
+    ///
+    /// The store must be able to list its nodes (see `TreeStoreReader::branch_hashes`). Nodes belonging
+    /// to the precomputed empty tree are never needed in the store and are removed as well.
+    ///
+    /// In `DeleteMode::Tombstone`, every tombstone is first deleted from the tree as if deletions emptied
+    /// the position of their key, which changes the root, and observers are notified of the new root.
+    ///
+    /// Compaction discards previous versions of the tree, so it must not be run while other handles still
+    /// read older roots from the same store.
+    /// See `VersionedStore::compact` to keep live versions.
+    ///
+    /// # Returns
+    ///
+    /// - A `CompactionReport` with the number of deleted nodes.
+    /// - `MssmtError::Unsupported` if the store cannot list its nodes.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use mssmt::{DefaultStore, FullTree};
+    ///
+    /// let mut tree = FullTree::new(DefaultStore::new());
+    /// tree.insert([1u8; 32], b"one".to_vec(), 1).unwrap();
+    /// tree.insert([1u8; 32], b"uno".to_vec(), 1).unwrap();
+    ///
+    /// let report = tree.compact().unwrap();
+    /// assert_eq!(report.leaves_removed, 1);
+    /// assert_eq!(tree.store().leaves.len(), 1);
+    /// ```
+    pub fn compact(&mut self) -> Result<CompactionReport> {
+        self.compact_cancellable(&CancellationToken::new())
+    }
+
+    /// Deletes the nodes unreachable from the current root, until done or cancelled.
+    ///
+    /// Works like `FullTree::compact`, checking `token` before marking the reachable nodes and between
+    /// deletions. Tombstones are purged one at a time and only unreachable nodes are ever deleted, so the
+    /// tree stays intact when the compaction is cancelled, and running it again resumes from the
+    /// tombstones and nodes left in the store.
+    ///
+    /// # Returns
+    ///
+    /// - A `CompactionReport` with the number of deleted nodes.
+    /// - `MssmtError::Unsupported` if the store cannot list its nodes.
+    /// - `MssmtError::Cancelled` if the token was cancelled before the compaction completed.
+    pub fn compact_cancellable(&mut self, token: &CancellationToken) -> Result<CompactionReport> {
+        token.check()?;
+        let mut report = CompactionReport::default();
+        if self.config().delete_mode() == DeleteMode::Tombstone {
+            let mut tombstones = Vec::new();
+            collect_tombstones(self.store(), &self.root()?, 0, &mut tombstones)?;
+            for key in tombstones {
+                token.check()?;
+                self.delete_leaf_node(key, None, &mut Vec::new())?;
+                report.tombstones_purged += 1;
+            }
+        }
+
+        let mut reachable = HashSet::new();
+        mark_reachable(self.store(), &self.root()?, 0, &mut reachable)?;
+
+        let store = self.store_mut();
+        for hash in store.branch_hashes()? {
+            if !reachable.contains(&hash) {
+                token.check()?;
+                store.delete_branch(&hash)?;
+                report.branches_removed += 1;
+            }
+        }
+        for hash in store.leaf_hashes()? {
+            if !reachable.contains(&hash) {
+                token.check()?;
+                store.delete_leaf(&hash)?;
+                report.leaves_removed += 1;
+            }
+        }
+
+        Ok(report)
+    }
+}
+
+/// Collects the keys of the tombstones in the subtree rooted at `node`, in key order.
+fn collect_tombstones<S: TreeStoreReader>(
+    store: &S,
+    node: &Arc<dyn Node>,
+    height: usize,
+    keys: &mut Vec<[u8; 32]>,
+) -> Result<()> {
+    if EmptyTree::is_empty_at(height, &node.node_hash()) {
+        return Ok(());
+    }
+
+    let node = resolve_node(store, node, height)?;
+    if let Some(branch) = node.as_branch() {
+        collect_tombstones(store, &branch.left, height + 1, keys)?;
+        collect_tombstones(store, &branch.right, height + 1, keys)?;
+    } else if let Some(leaf) = node.as_leaf::<32, Vec<u8>>() {
+        if leaf.is_empty() {
+            keys.push(leaf.key);
+        }
+    }
+    Ok(())
+}
+
+/// Collects the hashes of all non-empty nodes reachable from `node`.
+pub(crate) fn mark_reachable<S: TreeStoreReader>(
+    store: &S,
+    node: &Arc<dyn Node>,
+    height: usize,
+    reachable: &mut HashSet<NodeHash>,
+) -> Result<()> {
+    let hash = node.node_hash();
+    if EmptyTree::is_empty_at(height, &hash) {
+        return Ok(());
+    }
+
+    // Shared subtrees only need to be visited once
+    if !reachable.insert(hash) {
+        return Ok(());
+    }
+
+    // Leaves have no children, so hash-referenced leaves are never loaded
+    if height == MAX_TREE_LEVELS {
+        return Ok(());
+    }
+    let node = resolve_node(store, node, height)?;
+    if let Some(branch) = node.as_branch() {
+        mark_reachable(store, &branch.left, height + 1, reachable)?;
+        mark_reachable(store, &branch.right, height + 1, reachable)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::TreeConfig;
+    use crate::error::MssmtError;
+    use crate::key::Key;
+    use crate::node::{LeafNode, Sum};
+    use crate::store::DefaultStore;
+
+    #[test]
+    fn test_compact_keeps_current_version() -> Result<()> {
+        let mut tree = FullTree::new(DefaultStore::new());
+        for i in 0..8u8 {
+            tree.insert([i; 32], vec![i], i as Sum)?;
+        }
+        tree.delete([3u8; 32])?;
+        tree.insert([4u8; 32], b"updated".to_vec(), 40)?;
+        let root_hash = tree.root()?.node_hash();
+
+        let report = tree.compact()?;
+        assert!(report.branches_removed > 0);
+        // Deleted leaves are already gone, only the overwritten one is garbage
+        assert_eq!(report.leaves_removed, 1);
+
+        // The current version is untouched and fully backed by the store
+        assert_eq!(tree.root()?.node_hash(), root_hash);
+        assert_eq!(tree.get([4u8; 32])?, Some((b"updated".to_vec(), 40)));
+        assert!(tree.verify_integrity()?.is_ok());
+
+        // A second pass finds nothing left to remove
+        assert_eq!(tree.compact()?, CompactionReport::default());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_compact_purges_tombstones() -> Result<()> {
+        let mut tree = FullTree::new(DefaultStore::new());
+        tree.set_config(
+            TreeConfig::default()
+                .with_delete_mode(DeleteMode::Tombstone)
+                .with_strict_sums(true),
+        );
+        let mut expected = FullTree::new(DefaultStore::new());
+        for i in 1..=8u8 {
+            tree.insert([i; 32], vec![i], i as Sum)?;
+            expected.insert([i; 32], vec![i], i as Sum)?;
+        }
+        for i in [2u8, 5] {
+            assert_eq!(tree.delete([i; 32])?.map(|leaf| leaf.sum), Some(i as Sum));
+            expected.delete([i; 32])?;
+        }
+
+        // Tombstones read as absent but stay in the commitment
+        let root_hash = tree.root()?.node_hash();
+        assert_ne!(root_hash, expected.root()?.node_hash());
+        assert_eq!(tree.total_sum()?, expected.total_sum()?);
+        assert_eq!(tree.get([2u8; 32])?, None);
+        assert!(!tree.contains_key([5u8; 32])?);
+        let tombstone = LeafNode::tombstone([2u8; 32]);
+        assert!(tree
+            .merkle_proof([2u8; 32])?
+            .verify([2u8; 32], &tombstone, root_hash));
+
+        // Deleting a tombstone or an absent key changes nothing
+        assert!(tree.delete([2u8; 32])?.is_none());
+        assert!(tree.delete([9u8; 32])?.is_none());
+        assert_eq!(tree.root()?.node_hash(), root_hash);
+
+        let report = tree.compact()?;
+        assert_eq!(report.tombstones_purged, 2);
+        assert!(tree.equals(&expected)?);
+        assert_eq!(tree.store().leaves.len(), 6);
+        assert!(tree.verify_integrity()?.is_ok());
+        assert_eq!(tree.compact()?, CompactionReport::default());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_readers_skip_tombstones() -> Result<()> {
+        let mut tree = FullTree::new(DefaultStore::new());
+        tree.set_config(TreeConfig::default().with_delete_mode(DeleteMode::Tombstone));
+        tree.insert([1u8; 32], b"one".to_vec(), 1)?;
+        tree.insert([2u8; 32], b"two".to_vec(), 2)?;
+        tree.delete([1u8; 32])?;
+
+        // Every reader agrees that the tombstoned key is absent
+        assert_eq!(tree.get([1u8; 32])?, None);
+        assert!(!tree.contains_key([1u8; 32])?);
+        assert!(tree.get_with_proof([1u8; 32])?.is_none());
+        assert_eq!(
+            tree.get_many([[1u8; 32], [2u8; 32]])?,
+            [None, Some((b"two".to_vec(), 2))]
+        );
+        let keys: Vec<Key> = tree.keys().collect::<Result<_>>()?;
+        assert_eq!(keys, [Key::new([2u8; 32])]);
+        let values: Vec<(Vec<u8>, Sum)> = tree.values().collect::<Result<_>>()?;
+        assert_eq!(values, [(b"two".to_vec(), 2)]);
+        assert_eq!(tree.keys_page(None, 10)?, keys);
+        assert_eq!(tree.range([0u8; 32], 0)?.len(), 1);
+        assert!(!tree.is_empty()?);
+
+        // A tree holding only tombstones is empty, before and after compaction
+        tree.delete([2u8; 32])?;
+        assert!(tree.keys().next().is_none());
+        assert!(tree.is_empty()?);
+        tree.compact()?;
+        assert!(tree.is_empty()?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_compact_keeps_caller_empty_leaves() -> Result<()> {
+        // Without tombstones, a caller-inserted empty leaf is data that compaction keeps
+        let mut tree = FullTree::new(DefaultStore::new());
+        tree.insert([1u8; 32], Vec::new(), 0)?;
+        tree.insert([2u8; 32], b"two".to_vec(), 2)?;
+        let root_hash = tree.root()?.node_hash();
+        assert_eq!(tree.compact()?.tombstones_purged, 0);
+        assert_eq!(tree.root()?.node_hash(), root_hash);
+        assert_eq!(tree.get([1u8; 32])?, Some((Vec::new(), 0)));
+        assert_eq!(tree.keys().count(), 2);
+
+        // In tombstone mode, the same leaf cannot be told apart from a tombstone and is rejected
+        let mut tree = FullTree::new(DefaultStore::new());
+        tree.set_config(TreeConfig::default().with_delete_mode(DeleteMode::Tombstone));
+        tree.insert([2u8; 32], b"two".to_vec(), 2)?;
+        let root_hash = tree.root()?.node_hash();
+        assert!(matches!(
+            tree.insert([1u8; 32], Vec::new(), 0),
+            Err(MssmtError::InvalidLeaf(_))
+        ));
+        assert_eq!(tree.compact()?.tombstones_purged, 0);
+        assert_eq!(tree.root()?.node_hash(), root_hash);
+        assert_eq!(tree.get([2u8; 32])?, Some((b"two".to_vec(), 2)));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,163 @@
+//! Structural comparison of two trees.
+//!
+//! Replicas of a tree that disagree on their root hash disagree on at least one leaf, and usually on
+//! very few. `FullTree::first_divergence` finds the leftmost of them by descending both trees along the
+//! children whose hashes differ, loading one path per tree instead of every leaf. The trees may live in
+//! different stores, such as a local replica and a `RemoteStore`.
+
+use crate::error::Result;
+use crate::node::{tree_levels, EmptyTreeOf, LeafValue, Node, HASH_SIZE};
+use crate::store::{node_children, resolve_node, TreeStoreReader};
+use crate::tree::FullTree;
+use std::sync::Arc;
+
+/// The first subtree on which two trees differ, see `FullTree::first_divergence`.
+///
+/// `K` is the key size in bytes of the trees, 32 by default.
+#[derive(Clone)]
+pub struct Divergence<const K: usize = HASH_SIZE> {
+    /// The height of the subtree, from 0 at the root to the leaf level.
+    pub height: usize,
+    /// The path to the subtree: its first `height` bits, most significant first, and zero bits after.
+    pub prefix: [u8; K],
+    /// The subtree in the tree being compared, a `LeafNode` at the leaf level.
+    pub ours: Arc<dyn Node>,
+    /// The subtree in the other tree, a `LeafNode` at the leaf level.
+    pub theirs: Arc<dyn Node>,
+}
+
+impl<const K: usize> Divergence<K> {
+    /// Returns `true` if the subtree is empty in the tree being compared, so that the other tree holds
+    /// leaves there that this one lacks.
+    pub fn is_missing_ours(&self) -> bool {
+        EmptyTreeOf::<K>::is_empty_at(self.height, &self.ours.node_hash())
+    }
+
+    /// Returns `true` if the subtree is empty in the other tree.
+    pub fn is_missing_theirs(&self) -> bool {
+        EmptyTreeOf::<K>::is_empty_at(self.height, &self.theirs.node_hash())
+    }
+}
+
+impl<S: TreeStoreReader<K, V>, const K: usize, V: LeafValue> FullTree<S, K, V> {
+    /// Returns `true` if `other` has the same root hash, and thus the same leaves and sums.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if either root cannot be read.
+    pub fn equals<T: TreeStoreReader<K, V>>(&self, other: &FullTree<T, K, V>) -> Result<bool> {
+        Ok(self.root()?.node_hash() == other.root()?.node_hash())
+    }
+
+    /// Descends this tree and `other` along their differing children, and returns the leftmost subtree on
+    /// which they differ.
+    ///
+    /// The descent stops at the leaf level, where both sides are leaves or empty, or above it when one
+    /// side is an empty subtree.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(None)` if both trees have the same root hash.
+    /// - `Ok(Some(divergence))` otherwise.
+    /// - `MssmtError::NodeNotFound` if a node on the path is missing from either store.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use mssmt::{DefaultStore, FullTree, LeafNode};
+    ///
+    /// let mut ours = FullTree::new(DefaultStore::new());
+    /// let mut theirs = FullTree::new(DefaultStore::new());
+    /// for tree in [&mut ours, &mut theirs] {
+    ///     tree.insert([1u8; 32], b"one".to_vec(), 1).unwrap();
+    ///     tree.insert([2u8; 32], b"two".to_vec(), 2).unwrap();
+    /// }
+    /// theirs.insert([2u8; 32], b"TWO".to_vec(), 2).unwrap();
+    ///
+    /// let divergence = ours.first_divergence(&theirs).unwrap().unwrap();
+    /// assert_eq!(divergence.prefix, [2u8; 32]);
+    /// let leaf = divergence.theirs.as_leaf::<32, Vec<u8>>().unwrap();
+    /// assert_eq!(leaf.value, b"TWO");
+    /// ```
+    pub fn first_divergence<T: TreeStoreReader<K, V>>(
+        &self,
+        other: &FullTree<T, K, V>,
+    ) -> Result<Option<Divergence<K>>> {
+        let mut ours = self.root()?;
+        let mut theirs = other.root()?;
+        if ours.node_hash() == theirs.node_hash() {
+            return Ok(None);
+        }
+
+        let levels = tree_levels(K);
+        let mut prefix = [0u8; K];
+        let mut height = 0;
+        let is_empty =
+            |height, node: &Arc<dyn Node>| EmptyTreeOf::<K>::is_empty_at(height, &node.node_hash());
+        while height < levels && !is_empty(height, &ours) && !is_empty(height, &theirs) {
+            let (ours_left, ours_right) = node_children(self.store(), &ours, height)?;
+            let (theirs_left, theirs_right) = node_children(other.store(), &theirs, height)?;
+            (ours, theirs) = if ours_left.node_hash() != theirs_left.node_hash() {
+                (ours_left, theirs_left)
+            } else if ours_right.node_hash() != theirs_right.node_hash() {
+                prefix[height / 8] |= 0x80 >> (height % 8);
+                (ours_right, theirs_right)
+            } else {
+                // Equal children under differing branches: the trees combine sums differently
+                break;
+            };
+            height += 1;
+        }
+
+        Ok(Some(Divergence {
+            height,
+            prefix,
+            ours: resolve_node(self.store(), &ours, height)?,
+            theirs: resolve_node(other.store(), &theirs, height)?,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::Sum;
+    use crate::store::{ConcurrentStore, DefaultStore};
+
+    #[test]
+    fn test_first_divergence_finds_leftmost_difference() -> Result<()> {
+        let mut ours = FullTree::new(DefaultStore::new());
+        let mut theirs = FullTree::new(ConcurrentStore::new());
+        assert!(ours.equals(&theirs)?);
+        for i in 0..32u8 {
+            ours.insert([i * 8; 32], vec![i], i as Sum)?;
+            theirs.insert([i * 8; 32], vec![i], i as Sum)?;
+        }
+        assert!(ours.equals(&theirs)?);
+        assert!(ours.first_divergence(&theirs)?.is_none());
+
+        // Differing values are found at the leaf level, leftmost first
+        theirs.insert([200u8; 32], b"changed".to_vec(), 25)?;
+        theirs.insert([40u8; 32], b"changed".to_vec(), 5)?;
+        assert!(!ours.equals(&theirs)?);
+        let divergence = ours.first_divergence(&theirs)?.unwrap();
+        assert_eq!((divergence.height, divergence.prefix), (256, [40u8; 32]));
+        let leaf = divergence.theirs.as_leaf::<HASH_SIZE, Vec<u8>>();
+        assert_eq!(
+            leaf.map(|leaf| leaf.value.clone()),
+            Some(b"changed".to_vec())
+        );
+
+        // A key missing from one side ends the descent at the empty subtree
+        theirs.insert([40u8; 32], vec![5], 5)?;
+        ours.delete([200u8; 32])?;
+        let divergence = ours.first_divergence(&theirs)?.unwrap();
+        assert!(divergence.is_missing_ours() && !divergence.is_missing_theirs());
+        // 200 is 0b11001000, and no other key starts with 0b11001
+        assert_eq!(divergence.height, 5);
+        assert_eq!(divergence.prefix[..2], [200, 0]);
+        assert_eq!(divergence.theirs.node_sum(), 25);
+
+        Ok(())
+    }
+}
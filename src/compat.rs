@@ -0,0 +1,447 @@
+//! Cross-implementation test vectors.
+//!
+//! Test vectors describe trees by the leaves inserted into, deleted from and replaced in an empty tree,
+//! together with the root hash and sum each case must produce and compressed proofs that must verify
+//! against it. The JSON layout follows the `mssmt` test vectors published by lightninglabs' Go
+//! package, so vectors can be exchanged between implementations:
+//!
+//! ```json
+//! {
+//!   "all_tree_leaves": [
+//!     { "key": "<hex key>", "node": { "value": "<hex value>", "sum": "10" } }
+//!   ],
+//!   "valid_test_cases": [
+//!     {
+//!       "root_hash": "<hex hash>",
+//!       "root_sum": "10",
+//!       "inserted_leaves": ["<hex key>"],
+//!       "deleted_leaves": [],
+//!       "replaced_leaves": [],
+//!       "inclusion_proofs": [{ "proof_key": "<hex key>", "compressed_proof": "<hex proof>" }],
+//!       "exclusion_proofs": [],
+//!       "comment": "single leaf"
+//!     }
+//!   ]
+//! }
+//! ```
+//!
+//! Sums are decimal strings, since large integers do not survive every JSON parser. Inserted and deleted
+//! leaves refer to `all_tree_leaves` by key, and replaced leaves carry their new value and sum. Proofs
+//! use the encoding of `CompressedProof::encode`.
+//!
+//! Roots are checked under the hashing of this crate by default, which commits to the key of each leaf.
+//! Vectors published by the Go package check out under `HashScheme::Lnd`, see `TestVectors::check_with_scheme`.
+//! Proofs in vectors carry no scheme marker: they are decoded under the scheme the vectors are checked
+//! under.
+//!
+//! This module requires the `json` feature.
+
+use crate::error::{MssmtError, Result};
+use crate::json::decode_hash;
+use crate::node::{collect_leaves, LeafNode, NodeHash, Sum, EMPTY_LEAF_NODE};
+use crate::proof::{CompressedProof, Proof};
+use crate::store::DefaultStore;
+use crate::tagged::HashScheme;
+use crate::tree::FullTree;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{Read, Write};
+
+/// A leaf of a test vector, as a key and the node stored under it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TestLeaf {
+    /// The hex key of the leaf.
+    pub key: String,
+    /// The value and sum of the leaf.
+    pub node: TestNode,
+}
+
+/// The value and sum of a test vector leaf.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TestNode {
+    /// The hex value of the leaf.
+    pub value: String,
+    /// The sum of the leaf, as a decimal string.
+    pub sum: String,
+}
+
+/// A proof a test case must verify, for the key it was generated for.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TestProof {
+    /// The hex key the proof is for.
+    pub proof_key: String,
+    /// The hex encoding of the compressed proof.
+    pub compressed_proof: String,
+}
+
+/// A tree built from an empty tree and the root it must commit to.
+///
+/// The leaves listed in `inserted_leaves` are inserted first, then those in `deleted_leaves` are
+/// deleted, and finally `replaced_leaves` are inserted over the existing ones.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ValidTestCase {
+    /// The expected hex root hash.
+    pub root_hash: String,
+    /// The expected root sum, as a decimal string.
+    pub root_sum: String,
+    /// The keys of the leaves of `all_tree_leaves` to insert.
+    pub inserted_leaves: Vec<String>,
+    /// The keys of the leaves to delete.
+    #[serde(default)]
+    pub deleted_leaves: Vec<String>,
+    /// Leaves replacing existing ones with a new value and sum.
+    #[serde(default)]
+    pub replaced_leaves: Vec<TestLeaf>,
+    /// Proofs that the tree holds its leaf for `proof_key`.
+    #[serde(default)]
+    pub inclusion_proofs: Vec<TestProof>,
+    /// Proofs that the tree holds no leaf for `proof_key`.
+    #[serde(default)]
+    pub exclusion_proofs: Vec<TestProof>,
+    /// A description of the case.
+    #[serde(default)]
+    pub comment: String,
+}
+
+/// A set of test vectors, see the module documentation for the format.
+///
+/// # Examples
+///
+/// ```rust
+/// use mssmt::compat::TestVectors;
+/// use mssmt::LeafNode;
+///
+/// let leaves = (1..=4u8).map(|i| LeafNode::new([i; 32], vec![i], i.into()));
+/// let vectors = TestVectors::generate(leaves).unwrap();
+///
+/// let mut json = Vec::new();
+/// vectors.to_json(&mut json).unwrap();
+///
+/// let loaded = TestVectors::from_json(json.as_slice()).unwrap();
+/// loaded.check().unwrap();
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TestVectors {
+    /// Every leaf the test cases insert.
+    pub all_tree_leaves: Vec<TestLeaf>,
+    /// The test cases.
+    pub valid_test_cases: Vec<ValidTestCase>,
+}
+
+impl TestVectors {
+    /// Reads test vectors from a JSON document.
+    ///
+    /// # Returns
+    ///
+    /// - The test vectors.
+    /// - `MssmtError::InvalidEncoding` if the document does not follow the format.
+    pub fn from_json<R: Read>(reader: R) -> Result<Self> {
+        serde_json::from_reader(reader).map_err(|err| MssmtError::InvalidEncoding(err.to_string()))
+    }
+
+    /// Writes the test vectors as a JSON document.
+    pub fn to_json<W: Write>(&self, writer: W) -> Result<()> {
+        serde_json::to_writer_pretty(writer, self)
+            .map_err(|err| MssmtError::InvalidEncoding(err.to_string()))
+    }
+
+    /// Runs every test case, checking its root and proofs.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(())` if every case produces its root and every proof verifies.
+    /// - `MssmtError::VectorMismatch` for the first case that does not.
+    /// - `MssmtError::InvalidEncoding` if a key, value, sum or proof cannot be decoded.
+    pub fn check(&self) -> Result<()> {
+        self.check_with_scheme(HashScheme::V0)
+    }
+
+    /// Runs every test case under `scheme`, checking its root and proofs.
+    ///
+    /// Works like `TestVectors::check`, with roots computed by `FullTree::commitment` and proofs decoded
+    /// and verified under `scheme`. Vectors of the Go package check out under `HashScheme::Lnd`.
+    pub fn check_with_scheme(&self, scheme: HashScheme) -> Result<()> {
+        let leaves = self.leaves_by_key()?;
+        for (index, case) in self.valid_test_cases.iter().enumerate() {
+            let mismatch = |reason: String| MssmtError::VectorMismatch {
+                case: index,
+                reason,
+            };
+            let tree = case.build(&leaves, scheme)?;
+            let root = tree.commitment()?;
+
+            let root_hash: NodeHash = case.root_hash.parse()?;
+            if root.hash != root_hash {
+                return Err(mismatch(format!(
+                    "root hash {} instead of {}",
+                    root.hash, root_hash
+                )));
+            }
+            if root.sum != decode_sum(&case.root_sum)? {
+                return Err(mismatch(format!(
+                    "root sum {} instead of {}",
+                    root.sum, case.root_sum
+                )));
+            }
+
+            for test_proof in &case.inclusion_proofs {
+                let key = decode_hash(&test_proof.proof_key)?;
+                let proof = decode_proof(&test_proof.compressed_proof, scheme)?;
+                let leaf = match tree.get(key)? {
+                    Some((value, sum)) => LeafNode::new(key, value, sum),
+                    None => {
+                        return Err(mismatch(format!(
+                            "no leaf for inclusion proof key {}",
+                            test_proof.proof_key
+                        )))
+                    }
+                };
+                if !proof.verify(key, &leaf, root_hash) {
+                    return Err(mismatch(format!(
+                        "inclusion proof for {} does not verify",
+                        test_proof.proof_key
+                    )));
+                }
+            }
+            for test_proof in &case.exclusion_proofs {
+                let key = decode_hash(&test_proof.proof_key)?;
+                let proof = decode_proof(&test_proof.compressed_proof, scheme)?;
+                if !proof.verify(key, &EMPTY_LEAF_NODE, root_hash) {
+                    return Err(mismatch(format!(
+                        "exclusion proof for {} does not verify",
+                        test_proof.proof_key
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Generates test vectors from this crate for other implementations to check against.
+    ///
+    /// Three cases are generated over the given leaves: all leaves inserted, all inserted and every
+    /// other one deleted, and all inserted and every other one replaced. Each case carries an inclusion
+    /// proof per remaining leaf and an exclusion proof per deleted leaf.
+    ///
+    /// # Returns
+    ///
+    /// - The generated vectors.
+    /// - `MssmtError::SumOverflow` if the sums of the leaves overflow.
+    pub fn generate(leaves: impl IntoIterator<Item = LeafNode>) -> Result<Self> {
+        Self::generate_with_scheme(leaves, HashScheme::V0)
+    }
+
+    /// Generates test vectors under `scheme`, see `TestVectors::generate`.
+    ///
+    /// Under `HashScheme::Lnd` the vectors can be checked by the Go package.
+    pub fn generate_with_scheme(
+        leaves: impl IntoIterator<Item = LeafNode>,
+        scheme: HashScheme,
+    ) -> Result<Self> {
+        let leaves: Vec<LeafNode> = leaves.into_iter().filter(|leaf| !leaf.is_empty()).collect();
+        let all_tree_leaves: Vec<TestLeaf> = leaves.iter().map(TestLeaf::from).collect();
+        let keys: Vec<String> = all_tree_leaves
+            .iter()
+            .map(|leaf| leaf.key.clone())
+            .collect();
+        let every_other: Vec<String> = keys.iter().step_by(2).cloned().collect();
+
+        let inserted = ValidTestCase {
+            inserted_leaves: keys.clone(),
+            comment: "all leaves inserted".to_string(),
+            ..Default::default()
+        };
+        let deleted = ValidTestCase {
+            inserted_leaves: keys.clone(),
+            deleted_leaves: every_other.clone(),
+            comment: "every other leaf deleted".to_string(),
+            ..Default::default()
+        };
+        let replaced = ValidTestCase {
+            inserted_leaves: keys,
+            replaced_leaves: leaves
+                .iter()
+                .step_by(2)
+                .map(|leaf| {
+                    let mut value = leaf.value.clone();
+                    value.reverse();
+                    value.push(0xff);
+                    TestLeaf::from(&LeafNode::new(leaf.key, value, leaf.sum / 2))
+                })
+                .collect(),
+            comment: "every other leaf replaced".to_string(),
+            ..Default::default()
+        };
+
+        let mut vectors = TestVectors {
+            all_tree_leaves,
+            valid_test_cases: Vec::new(),
+        };
+        let by_key = vectors.leaves_by_key()?;
+        for case in [inserted, deleted, replaced] {
+            let case = case.complete(&by_key, scheme)?;
+            vectors.valid_test_cases.push(case);
+        }
+        Ok(vectors)
+    }
+
+    /// Decodes `all_tree_leaves`, indexed by hex key.
+    fn leaves_by_key(&self) -> Result<HashMap<String, LeafNode>> {
+        self.all_tree_leaves
+            .iter()
+            .map(|leaf| Ok((leaf.key.to_lowercase(), leaf.decode()?)))
+            .collect()
+    }
+}
+
+impl ValidTestCase {
+    /// Builds the tree of the case over an in-memory store, committing under `scheme`.
+    fn build(
+        &self,
+        leaves: &HashMap<String, LeafNode>,
+        scheme: HashScheme,
+    ) -> Result<FullTree<DefaultStore>> {
+        let mut tree = FullTree::new(DefaultStore::new());
+        tree.set_hash_scheme(scheme);
+        for key in &self.inserted_leaves {
+            let leaf = leaves.get(&key.to_lowercase()).ok_or_else(|| {
+                MssmtError::InvalidEncoding(format!("unknown inserted leaf {}", key))
+            })?;
+            tree.insert(leaf.key, leaf.value.clone(), leaf.sum)?;
+        }
+        for key in &self.deleted_leaves {
+            tree.delete(decode_hash(key)?)?;
+        }
+        for leaf in &self.replaced_leaves {
+            let leaf = leaf.decode()?;
+            tree.insert(leaf.key, leaf.value, leaf.sum)?;
+        }
+        Ok(tree)
+    }
+
+    /// Fills in the root and the proofs of the case from the tree it builds.
+    fn complete(mut self, leaves: &HashMap<String, LeafNode>, scheme: HashScheme) -> Result<Self> {
+        let tree = self.build(leaves, scheme)?;
+        let root = tree.commitment()?;
+        self.root_hash = root.hash.to_string();
+        self.root_sum = root.sum.to_string();
+
+        let mut present = Vec::new();
+        collect_leaves(tree.store(), &tree.root()?, 0, &mut present)?;
+        for leaf in &present {
+            self.inclusion_proofs.push(TestProof {
+                proof_key: hex::encode(leaf.key),
+                compressed_proof: encode_proof(tree.scheme_proof(leaf.key)?),
+            });
+        }
+        for key in &self.deleted_leaves {
+            self.exclusion_proofs.push(TestProof {
+                proof_key: key.clone(),
+                compressed_proof: encode_proof(tree.scheme_proof(decode_hash(key)?)?),
+            });
+        }
+        Ok(self)
+    }
+}
+
+/// Encodes a proof as hex, without the scheme marker.
+fn encode_proof(proof: Proof) -> String {
+    let mut compressed = proof.compress();
+    compressed.scheme = HashScheme::V0;
+    compressed.to_hex()
+}
+
+/// Decodes a proof produced by `encode_proof` under `scheme`.
+fn decode_proof(hex_str: &str, scheme: HashScheme) -> Result<Proof> {
+    let mut compressed = CompressedProof::from_hex(hex_str)?;
+    compressed.scheme = scheme;
+    compressed.decompress()
+}
+
+impl TestLeaf {
+    /// Decodes the leaf.
+    fn decode(&self) -> Result<LeafNode> {
+        let value = hex::decode(&self.node.value)
+            .map_err(|err| MssmtError::InvalidEncoding(err.to_string()))?;
+        Ok(LeafNode::new(
+            decode_hash(&self.key)?,
+            value,
+            decode_sum(&self.node.sum)?,
+        ))
+    }
+}
+
+impl From<&LeafNode> for TestLeaf {
+    fn from(leaf: &LeafNode) -> Self {
+        TestLeaf {
+            key: hex::encode(leaf.key),
+            node: TestNode {
+                value: hex::encode(&leaf.value),
+                sum: leaf.sum.to_string(),
+            },
+        }
+    }
+}
+
+/// Decodes a decimal sum.
+fn decode_sum(sum: &str) -> Result<Sum> {
+    sum.parse()
+        .map_err(|_| MssmtError::InvalidEncoding(format!("invalid sum {:?}", sum)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generated_vectors_check_and_detect_tampering() -> Result<()> {
+        let leaves =
+            (1..=6u8).map(|i| LeafNode::new([i * 0x21; 32], vec![i; i as usize], i as Sum));
+        let vectors = TestVectors::generate(leaves)?;
+        assert_eq!(vectors.valid_test_cases.len(), 3);
+        assert_eq!(vectors.valid_test_cases[0].root_sum, "21");
+        assert_eq!(vectors.valid_test_cases[1].exclusion_proofs.len(), 3);
+
+        let mut json = Vec::new();
+        vectors.to_json(&mut json)?;
+        let loaded = TestVectors::from_json(json.as_slice())?;
+        assert_eq!(loaded, vectors);
+        loaded.check()?;
+
+        // A wrong root sum is reported for its case
+        let mut tampered = vectors.clone();
+        tampered.valid_test_cases[2].root_sum = "0".to_string();
+        assert!(matches!(
+            tampered.check(),
+            Err(MssmtError::VectorMismatch { case: 2, .. })
+        ));
+
+        // An inclusion proof moved to another case no longer verifies
+        let mut tampered = vectors.clone();
+        let proof = tampered.valid_test_cases[0].inclusion_proofs[0].clone();
+        tampered.valid_test_cases[2].inclusion_proofs[0] = proof;
+        assert!(matches!(
+            tampered.check(),
+            Err(MssmtError::VectorMismatch { case: 2, .. })
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_vectors_under_lnd_scheme() -> Result<()> {
+        let leaves = (1..=5u8).map(|i| LeafNode::new([i * 0x13; 32], vec![i], i as Sum));
+        let vectors = TestVectors::generate_with_scheme(leaves, HashScheme::Lnd)?;
+        vectors.check_with_scheme(HashScheme::Lnd)?;
+
+        // Proofs carry no scheme marker, and the roots differ from those of this crate
+        let proof = &vectors.valid_test_cases[0].inclusion_proofs[0].compressed_proof;
+        assert_eq!(CompressedProof::from_hex(proof)?.scheme, HashScheme::V0);
+        assert!(matches!(
+            vectors.check(),
+            Err(MssmtError::VectorMismatch { case: 0, .. })
+        ));
+
+        Ok(())
+    }
+}
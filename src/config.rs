@@ -0,0 +1,677 @@
+//! Leaf validation and sum overflow policies for the Merkle-Sum Sparse Merkle Tree.
+//!
+//! A `TreeConfig` set on a `FullTree` is checked against every leaf before it is inserted, so that
+//! services can enforce their domain invariants where leaves are committed instead of in every caller.
+//! A rejected insert fails with `MssmtError::InvalidLeaf` and leaves the tree unchanged. An
+//! `EmptyLeafPolicy` can also reject leaves that would read like the empty leaf of unoccupied positions.
+//!
+//! The configuration also selects the `OverflowPolicy` combining the sums of sibling subtrees. Trees
+//! reject overflowing sums by default, while accounting applications may prefer to saturate or wrap.
+//! Its `NodeRetention` decides whether updates leave the nodes they supersede in the store, and its
+//! strict sums mode cross-checks the sum of every new root against the sums the update changed. Its
+//! `DeleteMode` decides whether deletions empty the position of the key or leave a tombstone leaf there.
+
+use crate::error::{MssmtError, Result};
+use crate::node::{LeafNode, LeafValue, Sum, HASH_SIZE};
+use std::sync::Arc;
+
+/// How the sums of two sibling subtrees combine into the sum of their parent branch.
+///
+/// The policy changes the sums committed to by branches whose subtree sum exceeds `Sum::MAX`, so a proof
+/// records the policy of the tree it was generated from and verifies under it.
+///
+/// Branches decoded by `BranchNode::decode` combine their children under `OverflowPolicy::Checked`. A
+/// tree whose sums actually saturate or wrap must be kept in a store holding decoded nodes, such as
+/// `DefaultStore`, or decoding its branches with `BranchNode::decode_with_policy`.
+///
+/// # Examples
+///
+/// ```rust
+/// use mssmt::config::{OverflowPolicy, TreeConfig};
+/// use mssmt::{DefaultStore, FullTree, LeafNode, Node, Sum};
+///
+/// let mut tree = FullTree::new(DefaultStore::new());
+/// tree.set_config(TreeConfig::default().with_overflow_policy(OverflowPolicy::Saturating));
+/// tree.insert([1u8; 32], b"a".to_vec(), Sum::MAX).unwrap();
+/// tree.insert([2u8; 32], b"b".to_vec(), 1).unwrap();
+/// assert_eq!(tree.total_sum().unwrap(), Sum::MAX);
+///
+/// let proof = tree.merkle_proof([2u8; 32]).unwrap();
+/// assert_eq!(proof.overflow, OverflowPolicy::Saturating);
+/// let leaf = LeafNode::new([2u8; 32], b"b".to_vec(), 1);
+/// assert!(proof.verify([2u8; 32], &leaf, tree.root().unwrap().node_hash()));
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum OverflowPolicy {
+    /// Sums that overflow are rejected with `MssmtError::SumOverflow`.
+    #[default]
+    Checked,
+    /// Sums that overflow are clamped to `Sum::MAX`.
+    Saturating,
+    /// Sums that overflow wrap around, modulo `Sum::MAX + 1`.
+    Wrapping,
+}
+
+impl OverflowPolicy {
+    /// Combines two sums, returning `None` if they overflow under `OverflowPolicy::Checked`.
+    pub fn combine(self, a: Sum, b: Sum) -> Option<Sum> {
+        match self {
+            OverflowPolicy::Checked => a.checked_add(b),
+            OverflowPolicy::Saturating => Some(a.saturating_add(b)),
+            OverflowPolicy::Wrapping => Some(a.wrapping_add(b)),
+        }
+    }
+
+    /// Returns the identifier of the policy in encoded proofs.
+    pub fn id(self) -> u8 {
+        match self {
+            OverflowPolicy::Checked => 0,
+            OverflowPolicy::Saturating => 1,
+            OverflowPolicy::Wrapping => 2,
+        }
+    }
+
+    /// Returns the policy with the given identifier.
+    ///
+    /// # Returns
+    ///
+    /// - The policy.
+    /// - `MssmtError::InvalidEncoding` if no policy has this identifier.
+    pub fn from_id(id: u8) -> Result<Self> {
+        match id {
+            0 => Ok(OverflowPolicy::Checked),
+            1 => Ok(OverflowPolicy::Saturating),
+            2 => Ok(OverflowPolicy::Wrapping),
+            _ => Err(MssmtError::InvalidEncoding(format!(
+                "unknown overflow policy {id}"
+            ))),
+        }
+    }
+}
+
+/// How a tree treats inserted leaves that look like the empty leaf.
+///
+/// Every unoccupied position of a tree holds the empty leaf, with the all-zero key, an empty value and a
+/// zero sum. Under the default `Allow`, an inserted leaf with an empty value and a zero sum is read back
+/// by `FullTree::get`, `FullTree::get_many` and the `FullTree::keys` and `FullTree::values` iterators
+/// like any other leaf, but paged listings, ranges and exports skip it, and at the all-zero key it
+/// hashes like the empty leaf itself, so that neither its insertion nor its deletion changes the root
+/// and no proof can tell it apart from an unoccupied position. Under `Reject`, such leaves cannot be
+/// inserted, and every empty leaf reads as absent.
+///
+/// # Examples
+///
+/// ```rust
+/// use mssmt::config::{EmptyLeafPolicy, TreeConfig};
+/// use mssmt::{DefaultStore, FullTree, MssmtError};
+///
+/// let mut tree = FullTree::new(DefaultStore::new());
+/// tree.set_config(TreeConfig::default().with_empty_leaf_policy(EmptyLeafPolicy::Reject));
+/// assert!(matches!(
+///     tree.insert([0u8; 32], Vec::new(), 0),
+///     Err(MssmtError::InvalidLeaf(_))
+/// ));
+/// tree.insert([0u8; 32], Vec::new(), 1).unwrap();
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum EmptyLeafPolicy {
+    /// Such leaves are inserted, and read as absent. Trees committing to roots computed before the
+    /// policy existed keep this default.
+    #[default]
+    Allow,
+    /// Such leaves are rejected with `MssmtError::InvalidLeaf`, so that user data cannot forge the empty
+    /// leaf.
+    Reject,
+}
+
+/// What updates do with the nodes they supersede.
+///
+/// Updates are copy-on-write: an insert or delete writes a new path from the leaf to the root, and the
+/// branches and leaf of the old path only belong to the previous versions of the tree. Those versions
+/// serve proofs against past roots, `FullTree::sync_delta` and `FullTree::backup_since`, so they are
+/// kept by default and reclaimed by compaction.
+///
+/// # Examples
+///
+/// ```rust
+/// use mssmt::config::{NodeRetention, TreeConfig};
+/// use mssmt::{DefaultStore, FullTree};
+///
+/// let mut tree = FullTree::new(DefaultStore::new());
+/// tree.set_config(TreeConfig::default().with_node_retention(NodeRetention::Prune));
+/// tree.insert([1u8; 32], b"one".to_vec(), 1).unwrap();
+/// tree.insert([2u8; 32], b"two".to_vec(), 2).unwrap();
+/// tree.delete([1u8; 32]).unwrap();
+///
+/// // Only the nodes of the current version remain
+/// assert_eq!(tree.store().leaves.len(), 1);
+/// assert_eq!(tree.store().branches.len(), 256);
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum NodeRetention {
+    /// Superseded nodes stay in the store until compaction, so previous roots remain readable.
+    #[default]
+    Retain,
+    /// Inserts, deletes and single-key updates delete the nodes they supersede, unless the new version
+    /// still references them. Trees with their root history enabled keep them, as the archived roots
+    /// reference them. Batch updates and bulk loads leave theirs to compaction.
+    ///
+    /// Other versions of the tree than the archived ones are unknown to it, so this policy must not be
+    /// used on stores shared with readers of previous roots. `VersionedStore` defers the deletions of
+    /// its versions to its own compaction.
+    Prune,
+}
+
+/// What deletions leave at the position of the deleted key.
+///
+/// By default a deletion swaps in the shared empty leaf, so the tree reads as if the key had never been
+/// inserted. In tombstone mode it instead writes a tombstone: a leaf bound to the key, with an empty
+/// value and a zero sum. Tombstones read as absent and do not count towards the sums, but they are part
+/// of the commitment, so replication protocols exchanging leaves or proofs propagate deletions as data.
+/// Compaction purges them, replacing each with the empty leaf, which changes the root.
+///
+/// Tombstones are recognized by their empty value and zero sum, so in tombstone mode inserting such a
+/// leaf fails with `MssmtError::InvalidLeaf`, whatever the `EmptyLeafPolicy`. Such leaves inserted
+/// before the mode was enabled are taken for tombstones, and purged by the next compaction.
+///
+/// # Examples
+///
+/// ```rust
+/// use mssmt::config::{DeleteMode, TreeConfig};
+/// use mssmt::{DefaultStore, FullTree, LeafNode, Node};
+///
+/// let mut tree = FullTree::new(DefaultStore::new());
+/// tree.set_config(TreeConfig::default().with_delete_mode(DeleteMode::Tombstone));
+/// tree.insert([1u8; 32], b"one".to_vec(), 1).unwrap();
+/// tree.delete([1u8; 32]).unwrap();
+///
+/// assert_eq!(tree.get([1u8; 32]).unwrap(), None);
+/// let tombstone = LeafNode::tombstone([1u8; 32]);
+/// let proof = tree.merkle_proof([1u8; 32]).unwrap();
+/// assert!(proof.verify([1u8; 32], &tombstone, tree.root().unwrap().node_hash()));
+///
+/// let report = tree.compact().unwrap();
+/// assert_eq!(report.tombstones_purged, 1);
+/// assert!(tree.store().leaves.is_empty());
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum DeleteMode {
+    /// Deletions replace the leaf with the shared empty leaf.
+    #[default]
+    Remove,
+    /// Deletions replace the leaf with a tombstone leaf, until compaction purges it. Deleting an absent
+    /// key or a tombstone leaves the tree unchanged.
+    Tombstone,
+}
+
+/// A custom leaf validator, returning the reason a leaf is rejected.
+type Validator<const K: usize, V> =
+    Arc<dyn Fn(&LeafNode<K, V>) -> std::result::Result<(), String> + Send + Sync>;
+
+/// Limits and hooks applied to every leaf inserted into a tree, and the overflow policy of its sums.
+///
+/// The default configuration accepts every leaf and rejects overflowing sums. `K` and `V` are the key size and value type of the
+/// tree the configuration is set on.
+///
+/// # Examples
+///
+/// ```rust
+/// use mssmt::config::TreeConfig;
+/// use mssmt::{DefaultStore, FullTree, MssmtError};
+///
+/// let mut tree = FullTree::new(DefaultStore::new());
+/// tree.set_config(
+///     TreeConfig::default()
+///         .with_max_value_size(4)
+///         .with_reject_zero_sum(true)
+///         .with_validator(|leaf| match leaf.key[0] {
+///             0xff => Err("reserved key".to_string()),
+///             _ => Ok(()),
+///         }),
+/// );
+///
+/// tree.insert([1u8; 32], b"ok".to_vec(), 1).unwrap();
+/// for (key, value, sum) in [
+///     ([2u8; 32], b"too long".to_vec(), 1),
+///     ([3u8; 32], b"ok".to_vec(), 0),
+///     ([0xffu8; 32], b"ok".to_vec(), 1),
+/// ] {
+///     assert!(matches!(
+///         tree.insert(key, value, sum),
+///         Err(MssmtError::InvalidLeaf(_))
+///     ));
+/// }
+/// ```
+pub struct TreeConfig<const K: usize = HASH_SIZE, V = Vec<u8>> {
+    max_value_size: Option<usize>,
+    reject_zero_sum: bool,
+    validator: Option<Validator<K, V>>,
+    overflow_policy: OverflowPolicy,
+    empty_leaf_policy: EmptyLeafPolicy,
+    node_retention: NodeRetention,
+    strict_sums: bool,
+    delete_mode: DeleteMode,
+    empty_value: Option<fn() -> V>,
+}
+
+impl<const K: usize, V> Default for TreeConfig<K, V> {
+    fn default() -> Self {
+        Self {
+            max_value_size: None,
+            reject_zero_sum: false,
+            validator: None,
+            overflow_policy: OverflowPolicy::Checked,
+            empty_leaf_policy: EmptyLeafPolicy::Allow,
+            node_retention: NodeRetention::Retain,
+            strict_sums: false,
+            delete_mode: DeleteMode::Remove,
+            empty_value: None,
+        }
+    }
+}
+
+impl<const K: usize, V> Clone for TreeConfig<K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            max_value_size: self.max_value_size,
+            reject_zero_sum: self.reject_zero_sum,
+            validator: self.validator.clone(),
+            overflow_policy: self.overflow_policy,
+            empty_leaf_policy: self.empty_leaf_policy,
+            node_retention: self.node_retention,
+            strict_sums: self.strict_sums,
+            delete_mode: self.delete_mode,
+            empty_value: self.empty_value,
+        }
+    }
+}
+
+impl<const K: usize, V: LeafValue> TreeConfig<K, V> {
+    /// Rejects leaves whose value is longer than `max_value_size` bytes.
+    pub fn with_max_value_size(mut self, max_value_size: usize) -> Self {
+        self.max_value_size = Some(max_value_size);
+        self
+    }
+
+    /// Rejects leaves with a zero sum if `reject_zero_sum` is `true`.
+    pub fn with_reject_zero_sum(mut self, reject_zero_sum: bool) -> Self {
+        self.reject_zero_sum = reject_zero_sum;
+        self
+    }
+
+    /// Rejects leaves for which `validator` returns an error, with its message.
+    ///
+    /// The validator runs after the built-in limits, so it only sees leaves that passed them.
+    pub fn with_validator(
+        mut self,
+        validator: impl Fn(&LeafNode<K, V>) -> std::result::Result<(), String> + Send + Sync + 'static,
+    ) -> Self {
+        self.validator = Some(Arc::new(validator));
+        self
+    }
+
+    /// Combines the sums of sibling subtrees under `overflow_policy`.
+    ///
+    /// The policy applies to the branches the tree builds from then on. Changing it on a tree whose sums
+    /// already overflowed leaves the existing branches as they are.
+    pub fn with_overflow_policy(mut self, overflow_policy: OverflowPolicy) -> Self {
+        self.overflow_policy = overflow_policy;
+        self
+    }
+
+    /// Treats leaves with an empty value and a zero sum, which read like the empty leaf, under
+    /// `empty_leaf_policy`.
+    pub fn with_empty_leaf_policy(mut self, empty_leaf_policy: EmptyLeafPolicy) -> Self {
+        self.empty_leaf_policy = empty_leaf_policy;
+        self
+    }
+
+    /// Keeps or deletes the nodes superseded by updates under `node_retention`.
+    pub fn with_node_retention(mut self, node_retention: NodeRetention) -> Self {
+        self.node_retention = node_retention;
+        self
+    }
+
+    /// Cross-checks the root sum of every update if `strict_sums` is `true`.
+    ///
+    /// The new root of an insert, delete or batch update must then sum to the old root sum plus the
+    /// sums of the new leaves minus those of the leaves they replaced or removed. A store returning
+    /// siblings with wrong sums otherwise goes unnoticed until the next integrity audit: the update
+    /// instead fails with `MssmtError::SumInvariant` before the new root is written. Roots saturated
+    /// under `OverflowPolicy::Saturating` no longer carry the exact total and are not checked.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use mssmt::config::TreeConfig;
+    /// use mssmt::{DefaultStore, FullTree};
+    ///
+    /// let mut tree = FullTree::new(DefaultStore::new());
+    /// tree.set_config(TreeConfig::default().with_strict_sums(true));
+    /// tree.insert([1u8; 32], b"one".to_vec(), 1).unwrap();
+    /// tree.insert([1u8; 32], b"uno".to_vec(), 5).unwrap();
+    /// assert_eq!(tree.total_sum().unwrap(), 5);
+    /// ```
+    pub fn with_strict_sums(mut self, strict_sums: bool) -> Self {
+        self.strict_sums = strict_sums;
+        self
+    }
+
+    /// Deletes keys under `delete_mode`.
+    ///
+    /// Tombstones hold the default value of the value type, which must be empty.
+    pub fn with_delete_mode(mut self, delete_mode: DeleteMode) -> Self
+    where
+        V: Default,
+    {
+        self.delete_mode = delete_mode;
+        self.empty_value = Some(V::default);
+        self
+    }
+
+    /// Returns the maximum value size in bytes, if any.
+    pub fn max_value_size(&self) -> Option<usize> {
+        self.max_value_size
+    }
+
+    /// Returns `true` if leaves with a zero sum are rejected.
+    pub fn rejects_zero_sum(&self) -> bool {
+        self.reject_zero_sum
+    }
+
+    /// Returns the policy combining the sums of sibling subtrees.
+    pub fn overflow_policy(&self) -> OverflowPolicy {
+        self.overflow_policy
+    }
+
+    /// Returns the policy applied to leaves that read like the empty leaf.
+    pub fn empty_leaf_policy(&self) -> EmptyLeafPolicy {
+        self.empty_leaf_policy
+    }
+
+    /// Returns what updates do with the nodes they supersede.
+    pub fn node_retention(&self) -> NodeRetention {
+        self.node_retention
+    }
+
+    /// Returns `true` if the root sum of every update is cross-checked.
+    pub fn strict_sums(&self) -> bool {
+        self.strict_sums
+    }
+
+    /// Returns what deletions leave at the position of the deleted key.
+    pub fn delete_mode(&self) -> DeleteMode {
+        self.delete_mode
+    }
+
+    /// Returns the tombstone a deletion of `key` writes, or `None` if deletions empty the position.
+    pub(crate) fn tombstone(&self, key: [u8; K]) -> Option<LeafNode<K, V>> {
+        match (self.delete_mode, self.empty_value) {
+            (DeleteMode::Tombstone, Some(empty_value)) => {
+                Some(LeafNode::new(key, empty_value(), 0))
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns `true` if reads of `leaf` report its key as absent.
+    ///
+    /// That is the case of the empty leaf of unoccupied positions, which carries the all-zero key. Other
+    /// leaves with an empty value and a zero sum are caller data under `EmptyLeafPolicy::Allow`, but
+    /// cannot be inserted under `EmptyLeafPolicy::Reject` and are tombstones under
+    /// `DeleteMode::Tombstone`.
+    pub(crate) fn reads_as_absent(&self, leaf: &LeafNode<K, V>) -> bool {
+        leaf.is_empty()
+            && (self.empty_leaf_policy == EmptyLeafPolicy::Reject
+                || self.delete_mode == DeleteMode::Tombstone
+                || leaf.key == [0u8; K])
+    }
+
+    /// Checks, in strict sums mode, that an update from a root summing to `old_sum` reached a root
+    /// summing to `new_sum`.
+    ///
+    /// `changes` holds the sum of each leaf the update wrote, or 0 for a removal, with the sum of the
+    /// leaf it replaced, or 0 if there was none.
+    ///
+    /// # Returns
+    ///
+    /// - `MssmtError::SumInvariant` if the new root does not account for the changes.
+    pub(crate) fn check_root_sum(
+        &self,
+        old_sum: Sum,
+        changes: impl IntoIterator<Item = (Sum, Sum)>,
+        new_sum: Sum,
+    ) -> Result<()> {
+        if !self.strict_sums {
+            return Ok(());
+        }
+        // Totals that fit are exact, and wrapped totals are exact modulo `Sum::MAX + 1`
+        let expected = changes.into_iter().fold(old_sum, |sum, (added, removed)| {
+            sum.wrapping_add(added).wrapping_sub(removed)
+        });
+        let saturated = self.overflow_policy == OverflowPolicy::Saturating
+            && (old_sum == Sum::MAX || new_sum == Sum::MAX);
+        if expected != new_sum && !saturated {
+            return Err(MssmtError::SumInvariant {
+                expected,
+                actual: new_sum,
+            });
+        }
+        Ok(())
+    }
+
+    /// Checks `leaf` against the configured limits and validator.
+    ///
+    /// # Returns
+    ///
+    /// - `MssmtError::InvalidLeaf` with the reason if the leaf is rejected.
+    pub fn validate(&self, leaf: &LeafNode<K, V>) -> Result<()> {
+        let size = leaf.value_bytes().len();
+        if let Some(max_value_size) = self.max_value_size {
+            if size > max_value_size {
+                return Err(MssmtError::InvalidLeaf(format!(
+                    "value of {size} bytes exceeds the limit of {max_value_size} bytes"
+                )));
+            }
+        }
+        if self.empty_leaf_policy == EmptyLeafPolicy::Reject && leaf.is_empty() {
+            return Err(MssmtError::InvalidLeaf(
+                "leaf is indistinguishable from the empty leaf".to_string(),
+            ));
+        }
+        if self.delete_mode == DeleteMode::Tombstone && leaf.is_empty() {
+            return Err(MssmtError::InvalidLeaf(
+                "leaf is indistinguishable from a tombstone".to_string(),
+            ));
+        }
+        if self.reject_zero_sum && leaf.sum == 0 {
+            return Err(MssmtError::InvalidLeaf("zero sum".to_string()));
+        }
+        if let Some(validator) = &self.validator {
+            validator(leaf).map_err(MssmtError::InvalidLeaf)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::ComputedNode;
+    use crate::proof::Proof;
+    use crate::store::{DefaultStore, TreeStoreWriter};
+    use crate::tree::FullTree;
+
+    #[test]
+    fn test_rejected_leaves_leave_the_tree_unchanged() -> Result<()> {
+        let mut tree = FullTree::new(DefaultStore::new());
+        tree.insert([1u8; 32], b"one".to_vec(), 1)?;
+        tree.set_config(
+            TreeConfig::default()
+                .with_max_value_size(8)
+                .with_reject_zero_sum(true)
+                .with_validator(|leaf| {
+                    if leaf.value_bytes().is_ascii() {
+                        Ok(())
+                    } else {
+                        Err("value is not ascii".to_string())
+                    }
+                }),
+        );
+        let root_hash = tree.root()?.node_hash();
+        let leaves = tree.store().leaves.len();
+
+        for (value, sum, reason) in [
+            (
+                vec![b'a'; 9],
+                1,
+                "value of 9 bytes exceeds the limit of 8 bytes",
+            ),
+            (b"zero".to_vec(), 0, "zero sum"),
+            (vec![0xff], 1, "value is not ascii"),
+        ] {
+            match tree.insert([2u8; 32], value, sum) {
+                Err(MssmtError::InvalidLeaf(message)) => assert_eq!(message, reason),
+                other => panic!("unexpected result: {other:?}"),
+            }
+        }
+        assert!(matches!(
+            tree.insert_with_proof([2u8; 32], Vec::new(), 0),
+            Err(MssmtError::InvalidLeaf(_))
+        ));
+        assert_eq!(tree.root()?.node_hash(), root_hash);
+        assert_eq!(tree.store().leaves.len(), leaves);
+
+        // Replacing an existing leaf is validated too
+        assert!(tree.insert([1u8; 32], vec![b'a'; 9], 1).is_err());
+        tree.insert([1u8; 32], vec![b'a'; 8], 2)?;
+        assert_eq!(tree.get([1u8; 32])?, Some((vec![b'a'; 8], 2)));
+        assert_eq!(tree.config().max_value_size(), Some(8));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_empty_leaf_policy() -> Result<()> {
+        // By default, a leaf forging the empty leaf leaves no trace in the root
+        let mut tree = FullTree::new(DefaultStore::new());
+        tree.insert([0u8; 32], Vec::new(), 0)?;
+        assert!(tree.is_empty()?);
+
+        // At any other key, it is caller data that reads back like any other leaf
+        tree.insert([1u8; 32], Vec::new(), 0)?;
+        assert_eq!(tree.get([1u8; 32])?, Some((Vec::new(), 0)));
+        assert!(tree.contains_key([1u8; 32])?);
+        let (value, sum, proof) = tree.get_with_proof([1u8; 32])?.unwrap();
+        let leaf = LeafNode::new([1u8; 32], value, sum);
+        assert!(proof.verify([1u8; 32], &leaf, tree.root()?.node_hash()));
+        assert_eq!(
+            tree.insert([1u8; 32], b"one".to_vec(), 1)?,
+            Some((Vec::new(), 0))
+        );
+        tree.insert([1u8; 32], Vec::new(), 0)?;
+        assert!(tree.delete([1u8; 32])?.is_some());
+        assert_eq!(tree.get([1u8; 32])?, None);
+
+        let mut tree = FullTree::new(DefaultStore::new());
+        tree.set_config(TreeConfig::default().with_empty_leaf_policy(EmptyLeafPolicy::Reject));
+        for key in [[0u8; 32], [1u8; 32]] {
+            match tree.insert(key, Vec::new(), 0) {
+                Err(MssmtError::InvalidLeaf(message)) => {
+                    assert_eq!(message, "leaf is indistinguishable from the empty leaf")
+                }
+                other => panic!("unexpected result: {other:?}"),
+            }
+        }
+        assert!(tree.store().leaves.is_empty());
+
+        // Either an empty value or a zero sum alone is still accepted
+        tree.insert([0u8; 32], Vec::new(), 1)?;
+        tree.insert([1u8; 32], vec![0], 0)?;
+        assert_eq!(tree.get([0u8; 32])?, Some((Vec::new(), 1)));
+        assert_eq!(tree.get([1u8; 32])?, Some((vec![0], 0)));
+        assert_eq!(tree.config().empty_leaf_policy(), EmptyLeafPolicy::Reject);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_overflow_policies() -> Result<()> {
+        for (policy, total) in [
+            (OverflowPolicy::Saturating, Sum::MAX),
+            (OverflowPolicy::Wrapping, 4),
+        ] {
+            let mut tree = FullTree::new(DefaultStore::new());
+            tree.set_config(
+                TreeConfig::default()
+                    .with_overflow_policy(policy)
+                    .with_strict_sums(true),
+            );
+            tree.insert([1u8; 32], b"a".to_vec(), Sum::MAX)?;
+            tree.insert([2u8; 32], b"b".to_vec(), 5)?;
+            assert_eq!(tree.total_sum()?, total);
+            assert!(tree.verify_integrity()?.is_ok());
+
+            // Proofs carry the policy through their encoding
+            let leaf = LeafNode::new([2u8; 32], b"b".to_vec(), 5);
+            let root_hash = tree.root()?.node_hash();
+            let proof = Proof::from_hex(&tree.merkle_proof([2u8; 32])?.to_hex())?;
+            assert_eq!(proof.overflow, policy);
+            assert_eq!(
+                proof.verify_with_sum([2u8; 32], &leaf, root_hash, None),
+                Some(total)
+            );
+            let checked = proof.clone().with_overflow(OverflowPolicy::Checked);
+            assert!(!checked.verify([2u8; 32], &leaf, root_hash));
+
+            tree.delete([2u8; 32])?;
+            assert_eq!(tree.total_sum()?, Sum::MAX);
+        }
+
+        // The default policy rejects the overflow
+        let mut tree = FullTree::new(DefaultStore::new());
+        tree.insert([1u8; 32], b"a".to_vec(), Sum::MAX)?;
+        assert!(matches!(
+            tree.insert([2u8; 32], b"b".to_vec(), 5),
+            Err(MssmtError::SumOverflow)
+        ));
+        assert_eq!(OverflowPolicy::from_id(2)?, OverflowPolicy::Wrapping);
+        assert!(OverflowPolicy::from_id(3).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_strict_sums_catch_inconsistent_roots() -> Result<()> {
+        let mut tree = FullTree::new(DefaultStore::new());
+        tree.set_config(TreeConfig::default().with_strict_sums(true));
+        for i in 1..=4u8 {
+            tree.insert([i; 32], vec![i], i as Sum)?;
+        }
+        tree.insert([2u8; 32], b"two".to_vec(), 20)?;
+        tree.delete([3u8; 32])?;
+        tree.update_sum([4u8; 32], 40)?;
+        assert_eq!(tree.total_sum()?, 61);
+
+        // A store whose root record carries a stale sum
+        let root = tree.root()?;
+        let stale = ComputedNode::new(root.node_hash(), root.node_sum() + 1);
+        tree.store_mut().update_root(Arc::new(stale))?;
+        assert!(matches!(
+            tree.insert([5u8; 32], b"five".to_vec(), 5),
+            Err(MssmtError::SumInvariant {
+                expected: 67,
+                actual: 66
+            })
+        ));
+        assert_eq!(tree.root()?.node_hash(), root.node_hash());
+
+        // Without strict sums the update goes through
+        tree.set_config(TreeConfig::default());
+        tree.insert([5u8; 32], b"five".to_vec(), 5)?;
+        assert_eq!(tree.total_sum()?, 66);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,187 @@
+//! Copying a tree into another store.
+//!
+//! `FullTree::copy_to` writes the current version of a tree into another backend, such as when migrating
+//! from a `DefaultStore` to a persistent store. Only the nodes reachable from the current root are
+//! copied, so superseded versions are left behind, and the copy is read back from the destination before
+//! the copy is reported as complete.
+
+use crate::error::{MssmtError, Result};
+use crate::node::{tree_levels, ComputedNode, EmptyTreeOf, LeafValue, Node, NodeHash};
+use crate::store::{resolve_node, TreeStore, TreeStoreReader};
+use crate::tree::FullTree;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+impl<S: TreeStoreReader<K, V>, const K: usize, V: LeafValue> FullTree<S, K, V> {
+    /// Copies every node reachable from the current root into `dest`, and makes it the root of `dest`.
+    ///
+    /// Nodes already in `dest` are overwritten with identical ones, so an interrupted copy can be
+    /// restarted. Once written, the tree is walked again from its root hash through `dest` alone.
+    ///
+    /// # Returns
+    ///
+    /// - The root hash of the copied tree.
+    /// - `MssmtError::NodeNotFound` if a node is missing from the source store, or was not persisted by
+    ///   `dest`.
+    /// - `MssmtError::RootHashMismatch` if the root of `dest` differs from the copied root.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use mssmt::store::ConcurrentStore;
+    /// use mssmt::{DefaultStore, FullTree, Node};
+    ///
+    /// let mut tree = FullTree::new(DefaultStore::new());
+    /// tree.insert([1u8; 32], b"one".to_vec(), 1).unwrap();
+    ///
+    /// let mut dest = ConcurrentStore::new();
+    /// let root_hash = tree.copy_to(&mut dest).unwrap();
+    ///
+    /// let copy = FullTree::new(dest);
+    /// assert_eq!(copy.root().unwrap().node_hash(), root_hash);
+    /// assert_eq!(copy.get([1u8; 32]).unwrap(), Some((b"one".to_vec(), 1)));
+    /// ```
+    pub fn copy_to<D: TreeStore<K, V>>(&self, dest: &mut D) -> Result<NodeHash> {
+        let root = self.root()?;
+        let root_hash = root.node_hash();
+        copy_node(self.store(), dest, &root, 0, &mut HashSet::new())?;
+        dest.update_root(root.clone())?;
+
+        let actual = dest.root_node()?.node_hash();
+        if actual != root_hash {
+            return Err(MssmtError::RootHashMismatch {
+                expected: root_hash,
+                actual,
+            });
+        }
+        let root_ref: Arc<dyn Node> = Arc::new(ComputedNode::new(root_hash, root.node_sum()));
+        check_reachable(dest, &root_ref, 0, &mut HashSet::new())?;
+
+        Ok(root_hash)
+    }
+}
+
+/// Copies the non-empty nodes of the subtree rooted at `node` from `src` into `dest`, children first.
+fn copy_node<S, D, const K: usize, V: LeafValue>(
+    src: &S,
+    dest: &mut D,
+    node: &Arc<dyn Node>,
+    height: usize,
+    copied: &mut HashSet<NodeHash>,
+) -> Result<()>
+where
+    S: TreeStoreReader<K, V> + ?Sized,
+    D: TreeStore<K, V> + ?Sized,
+{
+    let hash = node.node_hash();
+    if EmptyTreeOf::<K>::is_empty_at(height, &hash) || !copied.insert(hash) {
+        return Ok(());
+    }
+
+    let node = resolve_node(src, node, height)?;
+    if height == tree_levels(K) {
+        if let Some(leaf) = node.as_leaf::<K, V>() {
+            dest.insert_leaf(Arc::new(leaf.clone()))?;
+        }
+        return Ok(());
+    }
+    if let Some(branch) = node.as_branch() {
+        copy_node(src, dest, &branch.left, height + 1, copied)?;
+        copy_node(src, dest, &branch.right, height + 1, copied)?;
+        dest.insert_branch(Arc::new(branch.clone()))?;
+    }
+    Ok(())
+}
+
+/// Loads every non-empty node of the subtree rooted at `node` from `store`.
+fn check_reachable<S: TreeStoreReader<K, V> + ?Sized, const K: usize, V: LeafValue>(
+    store: &S,
+    node: &Arc<dyn Node>,
+    height: usize,
+    checked: &mut HashSet<NodeHash>,
+) -> Result<()> {
+    let hash = node.node_hash();
+    if EmptyTreeOf::<K>::is_empty_at(height, &hash) || !checked.insert(hash) {
+        return Ok(());
+    }
+
+    if height == tree_levels(K) {
+        return match store.get_leaf(&hash)? {
+            Some(_) => Ok(()),
+            None => Err(MssmtError::NodeNotFound(hash)),
+        };
+    }
+    let branch = store
+        .get_branch(&hash)?
+        .ok_or(MssmtError::NodeNotFound(hash))?;
+    check_reachable(store, &branch.left, height + 1, checked)?;
+    check_reachable(store, &branch.right, height + 1, checked)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::{BranchNode, LeafNode, Sum};
+    use crate::store::{DefaultStore, TreeStoreWriter};
+
+    #[test]
+    fn test_copy_to_skips_stale_nodes() -> Result<()> {
+        let mut tree = FullTree::new(DefaultStore::new());
+        for i in 1..=8u8 {
+            tree.insert([i; 32], vec![i], i as Sum)?;
+        }
+        tree.delete([3u8; 32])?;
+        tree.insert([4u8; 32], b"updated".to_vec(), 40)?;
+
+        let mut dest = DefaultStore::new();
+        let root_hash = tree.copy_to(&mut dest)?;
+        assert_eq!(root_hash, tree.root()?.node_hash());
+        assert_eq!(dest.leaves.len(), 7);
+        assert!(dest.branches.len() < tree.store().branches.len());
+
+        let copy = FullTree::new(dest);
+        assert_eq!(copy.get([4u8; 32])?, Some((b"updated".to_vec(), 40)));
+        assert_eq!(copy.get([3u8; 32])?, None);
+        assert!(copy.verify_integrity()?.is_ok());
+
+        // Copying again is idempotent, and a destination dropping writes is detected
+        let mut dest = copy.into_store();
+        assert_eq!(tree.copy_to(&mut dest)?, root_hash);
+
+        struct DroppingStore(DefaultStore);
+        impl TreeStoreReader for DroppingStore {
+            fn root_node(&self) -> Result<Arc<dyn Node>> {
+                self.0.root_node()
+            }
+            fn get_branch(&self, key: &NodeHash) -> Result<Option<Arc<BranchNode>>> {
+                self.0.get_branch(key)
+            }
+            fn get_leaf(&self, key: &NodeHash) -> Result<Option<Arc<LeafNode>>> {
+                self.0.get_leaf(key)
+            }
+        }
+        impl TreeStoreWriter for DroppingStore {
+            fn insert_branch(&mut self, branch: Arc<BranchNode>) -> Result<()> {
+                self.0.insert_branch(branch)
+            }
+            fn insert_leaf(&mut self, _leaf: Arc<LeafNode>) -> Result<()> {
+                Ok(())
+            }
+            fn delete_branch(&mut self, key: &NodeHash) -> Result<()> {
+                self.0.delete_branch(key)
+            }
+            fn delete_leaf(&mut self, key: &NodeHash) -> Result<()> {
+                self.0.delete_leaf(key)
+            }
+            fn update_root(&mut self, root: Arc<dyn Node>) -> Result<()> {
+                self.0.update_root(root)
+            }
+        }
+        assert!(matches!(
+            tree.copy_to(&mut DroppingStore(DefaultStore::new())),
+            Err(MssmtError::NodeNotFound(_))
+        ));
+
+        Ok(())
+    }
+}
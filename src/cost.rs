@@ -0,0 +1,270 @@
+//! Per-operation costs of tree operations.
+//!
+//! A tree update reads the path to its leaf, hashes a new path and writes it, so a single changed leaf
+//! costs hundreds of node writes. Backend implementers quantify this write amplification, and the effect
+//! of branch caching and compaction strategies on it, by wrapping their store in a `CountingStore` and
+//! running operations through `FullTree::measure`, which returns the `OperationCost` of each.
+//!
+//! Stack the `CountingStore` directly above the backend to count the traffic reaching it, or above a
+//! `CachedStore` to count the traffic generated by the tree.
+
+use crate::error::Result;
+use crate::metadata::LeafMetadata;
+use crate::node::{hashes_computed, BranchNode, LeafNode, LeafValue, Node, NodeHash};
+use crate::store::{TreeStoreReader, TreeStoreWriter};
+use crate::tree::FullTree;
+use std::ops::AddAssign;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// The node traffic of an operation, see `FullTree::measure`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct OperationCost {
+    /// Node lookups reaching the store, including misses.
+    pub nodes_read: u64,
+    /// Nodes written to the store.
+    pub nodes_written: u64,
+    /// Nodes deleted from the store.
+    pub nodes_deleted: u64,
+    /// Leaf and branch hashes computed on the calling thread.
+    pub nodes_hashed: u64,
+}
+
+impl AddAssign for OperationCost {
+    fn add_assign(&mut self, other: Self) {
+        self.nodes_read += other.nodes_read;
+        self.nodes_written += other.nodes_written;
+        self.nodes_deleted += other.nodes_deleted;
+        self.nodes_hashed += other.nodes_hashed;
+    }
+}
+
+/// A `TreeStore` decorator counting the node reads, writes and deletes reaching the inner store.
+///
+/// Unlike `MeteredStore`, the counts are kept in the store, so they are attributed to operations by
+/// `FullTree::measure` without a `Metrics` sink.
+#[derive(Debug, Default)]
+pub struct CountingStore<S> {
+    inner: S,
+    reads: AtomicU64,
+    writes: AtomicU64,
+    deletes: AtomicU64,
+}
+
+impl<S> CountingStore<S> {
+    /// Creates a new `CountingStore` wrapping `inner`, with all counts at zero.
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            reads: AtomicU64::new(0),
+            writes: AtomicU64::new(0),
+            deletes: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns a reference to the wrapped store.
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// Consumes the `CountingStore`, returning the wrapped store.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    /// Returns the node traffic since the store was created. `nodes_hashed` is always zero, as hashes
+    /// are not computed by stores.
+    pub fn totals(&self) -> OperationCost {
+        OperationCost {
+            nodes_read: self.reads.load(Ordering::Relaxed),
+            nodes_written: self.writes.load(Ordering::Relaxed),
+            nodes_deleted: self.deletes.load(Ordering::Relaxed),
+            nodes_hashed: 0,
+        }
+    }
+
+    fn count(counter: &AtomicU64, nodes: usize) {
+        counter.fetch_add(nodes as u64, Ordering::Relaxed);
+    }
+}
+
+impl<S: TreeStoreReader<K, V>, const K: usize, V> TreeStoreReader<K, V> for CountingStore<S> {
+    fn root_node(&self) -> Result<Arc<dyn Node>> {
+        self.inner.root_node()
+    }
+
+    fn get_branch(&self, key: &NodeHash) -> Result<Option<Arc<BranchNode>>> {
+        Self::count(&self.reads, 1);
+        self.inner.get_branch(key)
+    }
+
+    fn get_leaf(&self, key: &NodeHash) -> Result<Option<Arc<LeafNode<K, V>>>> {
+        Self::count(&self.reads, 1);
+        self.inner.get_leaf(key)
+    }
+
+    fn get_leaf_by_key(&self, key: &[u8; K]) -> Result<Option<Arc<LeafNode<K, V>>>> {
+        Self::count(&self.reads, 1);
+        self.inner.get_leaf_by_key(key)
+    }
+
+    fn may_contain_key(&self, key: &[u8; K]) -> Result<bool> {
+        self.inner.may_contain_key(key)
+    }
+
+    fn get_children(
+        &self,
+        height: usize,
+        hash: &NodeHash,
+    ) -> Result<(Arc<dyn Node>, Arc<dyn Node>)> {
+        Self::count(&self.reads, 1);
+        self.inner.get_children(height, hash)
+    }
+
+    fn branch_hashes(&self) -> Result<Vec<NodeHash>> {
+        self.inner.branch_hashes()
+    }
+
+    fn leaf_hashes(&self) -> Result<Vec<NodeHash>> {
+        self.inner.leaf_hashes()
+    }
+
+    fn get_leaf_metadata(&self, key: &[u8; K]) -> Result<Option<LeafMetadata>> {
+        self.inner.get_leaf_metadata(key)
+    }
+}
+
+impl<S: TreeStoreWriter<K, V>, const K: usize, V> TreeStoreWriter<K, V> for CountingStore<S> {
+    fn insert_branch(&mut self, branch: Arc<BranchNode>) -> Result<()> {
+        Self::count(&self.writes, 1);
+        self.inner.insert_branch(branch)
+    }
+
+    fn insert_leaf(&mut self, leaf: Arc<LeafNode<K, V>>) -> Result<()> {
+        Self::count(&self.writes, 1);
+        self.inner.insert_leaf(leaf)
+    }
+
+    fn insert_nodes(
+        &mut self,
+        branches: Vec<Arc<BranchNode>>,
+        leaves: Vec<Arc<LeafNode<K, V>>>,
+    ) -> Result<()> {
+        Self::count(&self.writes, branches.len() + leaves.len());
+        self.inner.insert_nodes(branches, leaves)
+    }
+
+    fn delete_branch(&mut self, key: &NodeHash) -> Result<()> {
+        Self::count(&self.deletes, 1);
+        self.inner.delete_branch(key)
+    }
+
+    fn delete_leaf(&mut self, key: &NodeHash) -> Result<()> {
+        Self::count(&self.deletes, 1);
+        self.inner.delete_leaf(key)
+    }
+
+    fn set_leaf_metadata(&mut self, key: &[u8; K], metadata: Option<LeafMetadata>) -> Result<()> {
+        self.inner.set_leaf_metadata(key, metadata)
+    }
+
+    fn update_root(&mut self, root: Arc<dyn Node>) -> Result<()> {
+        self.inner.update_root(root)
+    }
+
+    fn compare_and_update_root(&mut self, expected: NodeHash, root: Arc<dyn Node>) -> Result<bool> {
+        self.inner.compare_and_update_root(expected, root)
+    }
+}
+
+impl<S: TreeStoreReader<K, V>, const K: usize, V: LeafValue> FullTree<CountingStore<S>, K, V> {
+    /// Runs `operation` on the tree and returns its result with the node traffic it caused.
+    ///
+    /// Hashes are counted on the calling thread only, so the hashes computed by the worker threads of
+    /// parallel operations are not included. The first operation of a process also counts the hashes of
+    /// the empty subtrees, which are computed once.
+    ///
+    /// # Returns
+    ///
+    /// - The result of the operation and its cost.
+    /// - The error of the operation.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use mssmt::cost::CountingStore;
+    /// use mssmt::{DefaultStore, FullTree};
+    ///
+    /// let mut tree = FullTree::new(CountingStore::new(DefaultStore::new()));
+    /// tree.insert([1u8; 32], b"one".to_vec(), 1).unwrap();
+    /// let (_, cost) = tree
+    ///     .measure(|tree| tree.insert([2u8; 32], b"two".to_vec(), 2))
+    ///     .unwrap();
+    ///
+    /// // The leaf and a branch per level of the tree
+    /// assert_eq!(cost.nodes_written, 257);
+    /// assert_eq!(cost.nodes_hashed, 257);
+    /// ```
+    pub fn measure<T>(
+        &mut self,
+        operation: impl FnOnce(&mut Self) -> Result<T>,
+    ) -> Result<(T, OperationCost)> {
+        let store_before = self.store().totals();
+        let hashes_before = hashes_computed();
+        let output = operation(self)?;
+        let store_after = self.store().totals();
+        let cost = OperationCost {
+            nodes_read: store_after.nodes_read - store_before.nodes_read,
+            nodes_written: store_after.nodes_written - store_before.nodes_written,
+            nodes_deleted: store_after.nodes_deleted - store_before.nodes_deleted,
+            nodes_hashed: hashes_computed() - hashes_before,
+        };
+        debug_event!(
+            reads = cost.nodes_read,
+            writes = cost.nodes_written,
+            deletes = cost.nodes_deleted,
+            hashes = cost.nodes_hashed,
+            "operation measured"
+        );
+        Ok((output, cost))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::Sum;
+    use crate::store::{CachedStore, DefaultStore};
+
+    #[test]
+    fn test_measure_reports_node_traffic() -> Result<()> {
+        let mut tree = FullTree::new(CountingStore::new(DefaultStore::new()));
+        let mut total = OperationCost::default();
+        for i in 0..8u8 {
+            let (_, cost) = tree.measure(|tree| tree.insert([i; 32], vec![i], i as Sum))?;
+            assert_eq!(cost.nodes_written, 257);
+            total += cost;
+        }
+        assert_eq!(total.nodes_written, tree.store().totals().nodes_written);
+
+        // Reads write nothing, deletes drop the leaf and rewrite the path above the emptied subtree
+        let (value, cost) = tree.measure(|tree| tree.get([3u8; 32]))?;
+        assert_eq!(value, Some((vec![3], 3)));
+        assert_eq!(cost.nodes_written + cost.nodes_hashed, 0);
+        let (_, cost) = tree.measure(|tree| tree.delete([3u8; 32]))?;
+        assert_eq!((cost.nodes_written, cost.nodes_deleted), (8, 1));
+
+        // Below a cache, repeated reads of the same path stop reaching the store
+        let mut cached = FullTree::new(CachedStore::new(
+            CountingStore::new(DefaultStore::new()),
+            1024,
+        ));
+        cached.insert([1u8; 32], vec![1], 1)?;
+        cached.merkle_proof([1u8; 32])?;
+        let reads = cached.store().inner().totals().nodes_read;
+        cached.merkle_proof([1u8; 32])?;
+        assert_eq!(cached.store().inner().totals().nodes_read, reads);
+
+        Ok(())
+    }
+}
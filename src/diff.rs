@@ -0,0 +1,183 @@
+//! Change sets between two versions of a Merkle-Sum Sparse Merkle Tree.
+//!
+//! Since tree updates are copy-on-write, the nodes of previous roots remain in the store until they are
+//! pruned. `FullTree::diff` walks the current root and a previous root side by side, skipping every subtree
+//! the two versions share, and reports the leaves that were inserted, deleted, or modified.
+
+use crate::error::Result;
+use crate::node::{collect_leaves, LeafNode, Node, NodeHash, MAX_TREE_LEVELS};
+use crate::store::{resolve_node, resolve_root, TreeStoreReader};
+use crate::tree::FullTree;
+use std::sync::Arc;
+
+/// A single leaf-level change between two tree versions.
+#[derive(Clone)]
+pub enum DiffEntry {
+    /// A leaf present in the new version only.
+    Inserted(LeafNode),
+    /// A leaf present in the old version only.
+    Deleted(LeafNode),
+    /// A key present in both versions with a different value or sum.
+    Modified { old: LeafNode, new: LeafNode },
+}
+
+impl DiffEntry {
+    /// Returns the key of the changed leaf.
+    pub fn key(&self) -> &[u8; 32] {
+        match self {
+            DiffEntry::Inserted(leaf) | DiffEntry::Deleted(leaf) => &leaf.key,
+            DiffEntry::Modified { new, .. } => &new.key,
+        }
+    }
+}
+
+impl<S: TreeStoreReader> FullTree<S> {
+    /// Computes the changes between a previous version of the tree and the current one.
+    ///
+    /// Both versions must share this tree's store. Subtrees with identical hashes are skipped, so the
+    /// cost is proportional to the size of the change set rather than the size of the tree.
+    ///
+    /// # Arguments
+    ///
+    /// - `other_root`: The root hash of the previous version.
+    ///
+    /// # Returns
+    ///
+    /// - The entries turning the tree at `other_root` into the current tree, in key order.
+    /// - `MssmtError::NodeNotFound` if `other_root` is not in the store.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use mssmt::diff::DiffEntry;
+    /// use mssmt::{DefaultStore, FullTree, Node};
+    ///
+    /// let mut tree = FullTree::new(DefaultStore::new());
+    /// tree.insert([1u8; 32], b"one".to_vec(), 1).unwrap();
+    /// let snapshot = tree.root().unwrap().node_hash();
+    ///
+    /// tree.insert([2u8; 32], b"two".to_vec(), 2).unwrap();
+    ///
+    /// let diff = tree.diff(snapshot).unwrap();
+    /// assert_eq!(diff.len(), 1);
+    /// assert!(matches!(&diff[0], DiffEntry::Inserted(leaf) if leaf.key == [2u8; 32]));
+    /// ```
+    pub fn diff(&self, other_root: NodeHash) -> Result<Vec<DiffEntry>> {
+        let old_root = resolve_root(self.store(), &other_root)?;
+        let new_root = self.root()?;
+
+        let mut entries = Vec::new();
+        diff_nodes(self.store(), &old_root, &new_root, 0, &mut entries)?;
+        Ok(entries)
+    }
+}
+
+fn diff_nodes<S: TreeStoreReader>(
+    store: &S,
+    old: &Arc<dyn Node>,
+    new: &Arc<dyn Node>,
+    height: usize,
+    entries: &mut Vec<DiffEntry>,
+) -> Result<()> {
+    if old.node_hash() == new.node_hash() {
+        return Ok(());
+    }
+
+    let old = resolve_node(store, old, height)?;
+    let new = resolve_node(store, new, height)?;
+    if height < MAX_TREE_LEVELS {
+        let old_branch = old.as_branch();
+        let new_branch = new.as_branch();
+        if let (Some(old_branch), Some(new_branch)) = (old_branch, new_branch) {
+            diff_nodes(
+                store,
+                &old_branch.left,
+                &new_branch.left,
+                height + 1,
+                entries,
+            )?;
+            diff_nodes(
+                store,
+                &old_branch.right,
+                &new_branch.right,
+                height + 1,
+                entries,
+            )?;
+            return Ok(());
+        }
+    }
+
+    // At least one side is not a branch, so compare the leaves of both subtrees directly
+    let mut old_leaves = Vec::new();
+    let mut new_leaves = Vec::new();
+    collect_leaves(store, &old, height, &mut old_leaves)?;
+    collect_leaves(store, &new, height, &mut new_leaves)?;
+
+    let mut old_leaves = old_leaves.into_iter().peekable();
+    let mut new_leaves = new_leaves.into_iter().peekable();
+    loop {
+        let entry = match (old_leaves.peek(), new_leaves.peek()) {
+            (None, None) => break,
+            (Some(_), None) => DiffEntry::Deleted(old_leaves.next().unwrap()),
+            (None, Some(_)) => DiffEntry::Inserted(new_leaves.next().unwrap()),
+            (Some(old_leaf), Some(new_leaf)) => {
+                if old_leaf.key < new_leaf.key {
+                    DiffEntry::Deleted(old_leaves.next().unwrap())
+                } else if old_leaf.key > new_leaf.key {
+                    DiffEntry::Inserted(new_leaves.next().unwrap())
+                } else {
+                    let old_leaf = old_leaves.next().unwrap();
+                    let new_leaf = new_leaves.next().unwrap();
+                    if old_leaf.node_hash() == new_leaf.node_hash() {
+                        continue;
+                    }
+                    DiffEntry::Modified {
+                        old: old_leaf,
+                        new: new_leaf,
+                    }
+                }
+            }
+        };
+        entries.push(entry);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::MssmtError;
+    use crate::store::DefaultStore;
+
+    #[test]
+    fn test_diff_reports_all_change_kinds() -> Result<()> {
+        let mut tree = FullTree::new(DefaultStore::new());
+        tree.insert([1u8; 32], b"one".to_vec(), 1)?;
+        tree.insert([2u8; 32], b"two".to_vec(), 2)?;
+        let snapshot = tree.root()?.node_hash();
+
+        tree.insert([2u8; 32], b"two!".to_vec(), 20)?;
+        tree.insert([3u8; 32], b"three".to_vec(), 3)?;
+        tree.delete([1u8; 32])?;
+
+        let diff = tree.diff(snapshot)?;
+        let keys: Vec<_> = diff.iter().map(|entry| *entry.key()).collect();
+        assert_eq!(keys, vec![[1u8; 32], [2u8; 32], [3u8; 32]]);
+        assert!(matches!(&diff[0], DiffEntry::Deleted(_)));
+        assert!(
+            matches!(&diff[1], DiffEntry::Modified { old, new } if old.sum == 2 && new.sum == 20)
+        );
+        assert!(matches!(&diff[2], DiffEntry::Inserted(_)));
+
+        // Diffing against the current root yields no changes
+        assert!(tree.diff(tree.root()?.node_hash())?.is_empty());
+
+        let unknown = NodeHash::new([7u8; 32]);
+        assert!(matches!(
+            tree.diff(unknown),
+            Err(MssmtError::NodeNotFound(_))
+        ));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,156 @@
+//! Error types for the Merkle-Sum Sparse Merkle Tree.
+//!
+//! This module defines the `MssmtError` enum returned by all fallible tree, store, and proof operations,
+//! together with a crate-level `Result` alias.
+
+use crate::key::Key;
+use crate::node::{NodeHash, Sum};
+use thiserror::Error;
+
+/// The error type for MS-SMT operations.
+///
+/// Downstream code can match on the variants to handle specific failure kinds.
+///
+/// # Examples
+///
+/// ```rust
+/// use mssmt::{DefaultStore, FullTree, MssmtError, Sum};
+///
+/// let mut tree = FullTree::new(DefaultStore::new());
+/// tree.insert([1u8; 32], b"a".to_vec(), Sum::MAX).unwrap();
+///
+/// match tree.insert([2u8; 32], b"b".to_vec(), 1) {
+///     Err(MssmtError::SumOverflow) => println!("tree sum would overflow"),
+///     other => panic!("unexpected result: {:?}", other),
+/// }
+/// ```
+#[derive(Debug, Error)]
+pub enum MssmtError {
+    /// A storage backend operation failed.
+    #[error("store error: {0}")]
+    Store(String),
+
+    /// The storage backend does not support the requested operation.
+    #[error("operation not supported by the store: {0}")]
+    Unsupported(&'static str),
+
+    /// Combining the sums of two nodes overflowed a `Sum`.
+    #[error("sum overflow")]
+    SumOverflow,
+
+    /// A proof does not contain the expected number of nodes.
+    #[error("invalid proof length: expected {expected} nodes, got {actual}")]
+    InvalidProofLength { expected: usize, actual: usize },
+
+    /// A leaf is not stored under the key it is used with.
+    #[error("leaf key does not match the proof key")]
+    KeyMismatch,
+
+    /// A tree height or key prefix length is out of range.
+    #[error("invalid height: {0}")]
+    InvalidHeight(usize),
+
+    /// A node referenced by its hash could not be found in the store.
+    #[error("node not found: {0}")]
+    NodeNotFound(NodeHash),
+
+    /// The store returned a node graph that is not a tree of the expected shape, such as a leaf above
+    /// the leaf level or a node that does not match the hash it was loaded by.
+    #[error("corrupted tree at height {height}, node {hash}: {reason}")]
+    CorruptedTree {
+        height: usize,
+        hash: NodeHash,
+        reason: &'static str,
+    },
+
+    /// The root sum of an update does not account for the leaves it changed, see
+    /// `TreeConfig::with_strict_sums`.
+    #[error("root sum invariant violated: expected {expected}, computed {actual}")]
+    SumInvariant { expected: Sum, actual: Sum },
+
+    /// Encoded data could not be decoded.
+    #[error("invalid encoding: {0}")]
+    InvalidEncoding(String),
+
+    /// Encoded data exceeds a bound of the `DecodeLimits` it was decoded under, see
+    /// `CompressedProof::decode_with_limits`.
+    #[error("{what} exceeds the decode limit: {actual} > {limit}")]
+    DecodeLimit {
+        what: &'static str,
+        limit: usize,
+        actual: usize,
+    },
+
+    /// Reading or writing external data failed.
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    /// A tree rebuilt from exported data does not have the expected root hash.
+    #[error("root hash mismatch: expected {expected}, computed {actual}")]
+    RootHashMismatch {
+        expected: NodeHash,
+        actual: NodeHash,
+    },
+
+    /// Two distinct keys share the prefix locating their leaf in a truncated tree.
+    #[error("key collision: {key} has the same prefix as {existing}")]
+    KeyCollision { key: Key, existing: Key },
+
+    /// A leaf was rejected by the validation policy of the tree, see `TreeConfig`.
+    #[error("invalid leaf: {0}")]
+    InvalidLeaf(String),
+
+    /// No root of the requested version is archived, see `FullTree::enable_root_history`.
+    #[error("version {0} is not archived")]
+    VersionNotArchived(u64),
+
+    /// An update was made through a version of a `VersionedStore` that is no longer the latest one.
+    #[error(
+        "stale version: the update started from {expected}, but the latest version is {actual}"
+    )]
+    StaleVersion {
+        expected: NodeHash,
+        actual: NodeHash,
+    },
+
+    /// A bulk operation was aborted through its `CancellationToken`.
+    #[error("operation cancelled")]
+    Cancelled,
+
+    /// A test vector case did not reproduce its expected root or proofs, see `TestVectors::check`.
+    #[error("test vector case {case} failed: {reason}")]
+    VectorMismatch { case: usize, reason: String },
+
+    /// A proof failed verification.
+    #[error(transparent)]
+    Proof(#[from] ProofError),
+}
+
+/// The reason a Merkle proof failed verification.
+///
+/// Returned by [`Proof::verify_detailed`](crate::proof::Proof::verify_detailed) so failed verifications
+/// can be diagnosed.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ProofError {
+    /// The proof does not contain one node per tree level.
+    #[error("invalid proof length: expected {expected} nodes, got {actual}")]
+    InvalidLength { expected: usize, actual: usize },
+
+    /// The leaf is not stored under the key the proof is verified for.
+    #[error("leaf key does not match the proof key")]
+    KeyMismatch,
+
+    /// Summing the leaf and sibling sums overflowed while rebuilding the branch at `height`.
+    #[error("sum overflow while reconstructing height {height}")]
+    SumOverflow { height: usize },
+
+    /// The reconstructed root hash does not match the expected root hash.
+    #[error("root hash mismatch: expected {expected}, computed {actual}")]
+    RootHashMismatch {
+        expected: NodeHash,
+        actual: NodeHash,
+    },
+}
+
+/// A specialized `Result` type for MS-SMT operations.
+pub type Result<T> = std::result::Result<T, MssmtError>;
@@ -0,0 +1,201 @@
+//! Executors running the parallel work of batch operations.
+//!
+//! Bulk builds, integrity audits, parallel batch inserts and batch proof verification split their work
+//! into independent tasks and hand them to an `Executor`. `RayonExecutor` runs them on a rayon thread
+//! pool (requires the `rayon` feature) and `SingleThreaded` runs them one after the other on the calling
+//! thread, so the batch APIs also work where threads are not available, such as in WASM or on an async
+//! runtime that must not be blocked by a thread pool of its own.
+//!
+//! A tree runs its batch operations on the executor set with `FullTree::set_executor`, by default
+//! `default_executor`.
+
+use std::sync::Arc;
+
+/// A unit of work handed to an executor, which may borrow from the caller.
+pub type Task<'a> = Box<dyn FnOnce() + Send + 'a>;
+
+/// Runs the independent tasks of a batch operation.
+///
+/// # Examples
+///
+/// ```rust
+/// use mssmt::executor::{self, Executor, Task};
+/// use std::sync::Arc;
+///
+/// // Runs the tasks in reverse order, to show that callers do not depend on the order
+/// struct Reversed;
+///
+/// impl Executor for Reversed {
+///     fn run<'a>(&self, tasks: Vec<Task<'a>>) {
+///         tasks.into_iter().rev().for_each(|task| task());
+///     }
+///
+///     fn parallelism(&self) -> usize {
+///         4
+///     }
+/// }
+///
+/// let squares = executor::map(&Reversed, &[1u64, 2, 3, 4, 5], |x| x * x);
+/// assert_eq!(squares, [1, 4, 9, 16, 25]);
+/// ```
+pub trait Executor: Send + Sync {
+    /// Runs `tasks`, returning once all of them have completed.
+    ///
+    /// The tasks do not depend on each other and may run in any order, or concurrently.
+    fn run<'a>(&self, tasks: Vec<Task<'a>>);
+
+    /// Returns the number of tasks the executor runs at once, which batch operations split their work
+    /// by. The default implementation returns 1.
+    fn parallelism(&self) -> usize {
+        1
+    }
+}
+
+/// Runs tasks one after the other on the calling thread.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SingleThreaded;
+
+impl Executor for SingleThreaded {
+    fn run<'a>(&self, tasks: Vec<Task<'a>>) {
+        for task in tasks {
+            task();
+        }
+    }
+}
+
+/// Runs tasks on a rayon thread pool, the global one unless created with `RayonExecutor::with_pool`.
+#[cfg(feature = "rayon")]
+#[derive(Clone, Debug, Default)]
+pub struct RayonExecutor {
+    pool: Option<Arc<rayon::ThreadPool>>,
+}
+
+#[cfg(feature = "rayon")]
+impl RayonExecutor {
+    /// Creates an executor running tasks on the global rayon thread pool.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates an executor running tasks on `pool`.
+    pub fn with_pool(pool: Arc<rayon::ThreadPool>) -> Self {
+        Self { pool: Some(pool) }
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl Executor for RayonExecutor {
+    fn run<'a>(&self, tasks: Vec<Task<'a>>) {
+        let spawn_all = |scope: &rayon::Scope<'a>| {
+            for task in tasks {
+                scope.spawn(move |_| task());
+            }
+        };
+        match &self.pool {
+            Some(pool) => pool.scope(spawn_all),
+            None => rayon::scope(spawn_all),
+        }
+    }
+
+    fn parallelism(&self) -> usize {
+        match &self.pool {
+            Some(pool) => pool.current_num_threads(),
+            None => rayon::current_num_threads(),
+        }
+    }
+}
+
+/// Returns the executor trees use unless configured otherwise: `RayonExecutor` on the global thread
+/// pool when the `rayon` feature is enabled, `SingleThreaded` otherwise.
+pub fn default_executor() -> Arc<dyn Executor> {
+    #[cfg(feature = "rayon")]
+    {
+        Arc::new(RayonExecutor::new())
+    }
+    #[cfg(not(feature = "rayon"))]
+    {
+        Arc::new(SingleThreaded)
+    }
+}
+
+/// The number of tasks per unit of parallelism `map` splits its items into, so that uneven items
+/// still keep every thread busy.
+const TASKS_PER_THREAD: usize = 4;
+
+/// Applies `f` to each of `items` on `executor`, returning the results in the order of the items.
+pub fn map<T: Sync, R: Send>(
+    executor: &dyn Executor,
+    items: &[T],
+    f: impl Fn(&T) -> R + Sync,
+) -> Vec<R> {
+    let tasks = (executor.parallelism() * TASKS_PER_THREAD).clamp(1, items.len().max(1));
+    let chunk_size = items.len().div_ceil(tasks).max(1);
+    let mut outputs: Vec<Vec<R>> = Vec::new();
+    outputs.resize_with(items.len().div_ceil(chunk_size), Vec::new);
+
+    let f = &f;
+    executor.run(
+        items
+            .chunks(chunk_size)
+            .zip(outputs.iter_mut())
+            .map(|(chunk, output)| {
+                Box::new(move || *output = chunk.iter().map(f).collect()) as Task<'_>
+            })
+            .collect(),
+    );
+    outputs.into_iter().flatten().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::key::Key;
+    use crate::node::{LeafNode, Sum};
+    use crate::proof::Proof;
+    use crate::store::DefaultStore;
+    use crate::tree::FullTree;
+
+    #[test]
+    fn test_executors_build_the_same_tree() -> crate::error::Result<()> {
+        let leaves: Vec<LeafNode> = (0..200u8)
+            .map(|i| LeafNode::new([i; 32], vec![i], i as Sum))
+            .collect();
+        let expected = FullTree::from_leaves(DefaultStore::new(), leaves.clone())?;
+
+        let executors: Vec<Arc<dyn Executor>> = vec![
+            Arc::new(SingleThreaded),
+            #[cfg(feature = "rayon")]
+            Arc::new(RayonExecutor::with_pool(Arc::new(
+                rayon::ThreadPoolBuilder::new()
+                    .num_threads(3)
+                    .build()
+                    .unwrap(),
+            ))),
+        ];
+        let root_hash = expected.root()?.node_hash();
+        let mut items = Vec::new();
+        for leaf in &leaves[..20] {
+            items.push((
+                Key(leaf.key),
+                leaf.clone(),
+                expected.merkle_proof(leaf.key)?,
+            ));
+        }
+        items[7].1 = LeafNode::new(items[7].0 .0, vec![0], 1);
+
+        for executor in executors {
+            let mut tree = FullTree::from_leaves_on(DefaultStore::new(), leaves.clone(), executor)?;
+            assert_eq!(tree.root()?.node_hash(), root_hash);
+            assert!(tree.verify_integrity()?.is_ok());
+            let verified = Proof::verify_batch(tree.executor().as_ref(), &items, root_hash);
+            assert_eq!(verified.iter().filter(|ok| **ok).count(), 19);
+            assert!(!verified[7]);
+
+            tree.par_insert_batch((0..50u8).map(|i| ([i; 32], vec![i], 1)))?;
+            assert_eq!(tree.total_sum()?, (50..200).sum::<Sum>() + 50);
+        }
+        assert!(map(&SingleThreaded, &[] as &[u8], |x| *x).is_empty());
+
+        Ok(())
+    }
+}
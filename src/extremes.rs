@@ -0,0 +1,192 @@
+//! Leaves with the smallest and largest sums.
+//!
+//! `FullTree::max_sum_leaf` and `FullTree::min_sum_leaf` answer "largest holder" style queries. Without
+//! an index they scan every leaf. `FullTree::enable_sum_index` keeps the smallest and largest leaf sums
+//! under each branch, so the queries descend greedily from the root in one pass over the path.
+//!
+//! Branches are addressed by their hash, which commits to every leaf below them, so the bounds of a
+//! branch never change. The index is filled on demand: after an update, only the new branches on the
+//! updated path are missing, and their bounds are combined from those of their children.
+
+use crate::error::{MssmtError, Result};
+use crate::node::{tree_levels, EmptyTreeOf, LeafNode, LeafValue, Node, NodeHash, Sum};
+use crate::store::{resolve_node, TreeStoreReader};
+use crate::tree::FullTree;
+use crate::walk::WalkControl;
+use parking_lot::RwLock;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// The smallest and largest leaf sums under each indexed branch, keyed by branch hash.
+#[derive(Default)]
+pub(crate) struct SumIndex {
+    bounds: RwLock<HashMap<NodeHash, (Sum, Sum)>>,
+}
+
+/// Which end of the sums a query looks for.
+#[derive(Clone, Copy)]
+enum Extreme {
+    Min,
+    Max,
+}
+
+impl Extreme {
+    /// Picks the bound of this extreme.
+    fn of(self, (min, max): (Sum, Sum)) -> Sum {
+        match self {
+            Extreme::Min => min,
+            Extreme::Max => max,
+        }
+    }
+}
+
+impl<S: TreeStoreReader<K, V>, const K: usize, V: LeafValue> FullTree<S, K, V> {
+    /// Returns the leaf with the largest sum, or `None` if the tree is empty.
+    ///
+    /// Among leaves with the same sum, the one with the smallest key is returned. See
+    /// `FullTree::enable_sum_index` to answer without scanning every leaf.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use mssmt::{DefaultStore, FullTree};
+    ///
+    /// let mut tree = FullTree::new(DefaultStore::new());
+    /// tree.enable_sum_index();
+    /// tree.insert([1u8; 32], b"alice".to_vec(), 30).unwrap();
+    /// tree.insert([2u8; 32], b"bob".to_vec(), 50).unwrap();
+    /// tree.insert([3u8; 32], b"carol".to_vec(), 10).unwrap();
+    ///
+    /// assert_eq!(tree.max_sum_leaf().unwrap().unwrap().value, b"bob".to_vec());
+    /// assert_eq!(tree.min_sum_leaf().unwrap().unwrap().value, b"carol".to_vec());
+    /// ```
+    pub fn max_sum_leaf(&self) -> Result<Option<LeafNode<K, V>>> {
+        self.extreme_leaf(Extreme::Max)
+    }
+
+    /// Returns the leaf with the smallest sum, or `None` if the tree is empty.
+    ///
+    /// Among leaves with the same sum, the one with the smallest key is returned.
+    pub fn min_sum_leaf(&self) -> Result<Option<LeafNode<K, V>>> {
+        self.extreme_leaf(Extreme::Min)
+    }
+
+    fn extreme_leaf(&self, extreme: Extreme) -> Result<Option<LeafNode<K, V>>> {
+        let Some(index) = self.sum_index() else {
+            return self.scan_extreme_leaf(extreme);
+        };
+
+        let mut node = self.root()?;
+        let Some(bounds) = self.sum_bounds(index, &node, 0)? else {
+            return Ok(None);
+        };
+        let target = extreme.of(bounds);
+        for height in 0..tree_levels(K) {
+            node = resolve_node(self.store(), &node, height)?;
+            let Some(branch) = node.as_branch() else {
+                return Err(MssmtError::NodeNotFound(node.node_hash()));
+            };
+            let left = self.sum_bounds(index, &branch.left, height + 1)?;
+            node = match left {
+                Some(bounds) if extreme.of(bounds) == target => branch.left.clone(),
+                _ => branch.right.clone(),
+            };
+        }
+
+        let node = resolve_node(self.store(), &node, tree_levels(K))?;
+        Ok(node.as_leaf::<K, V>().cloned())
+    }
+
+    /// Returns the smallest and largest leaf sums below `node`, at `height`, or `None` if the subtree is
+    /// empty, indexing the bounds of every branch it computes.
+    fn sum_bounds(
+        &self,
+        index: &SumIndex,
+        node: &Arc<dyn Node>,
+        height: usize,
+    ) -> Result<Option<(Sum, Sum)>> {
+        let hash = node.node_hash();
+        if EmptyTreeOf::<K>::is_empty_at(height, &hash) {
+            return Ok(None);
+        }
+        if height == tree_levels(K) {
+            return Ok(Some((node.node_sum(), node.node_sum())));
+        }
+        if let Some(bounds) = index.bounds.read().get(&hash) {
+            return Ok(Some(*bounds));
+        }
+
+        let node = resolve_node(self.store(), node, height)?;
+        let Some(branch) = node.as_branch() else {
+            return Err(MssmtError::NodeNotFound(hash));
+        };
+        let left = self.sum_bounds(index, &branch.left, height + 1)?;
+        let right = self.sum_bounds(index, &branch.right, height + 1)?;
+        let bounds = match (left, right) {
+            (Some(left), Some(right)) => (left.0.min(right.0), left.1.max(right.1)),
+            (Some(bounds), None) | (None, Some(bounds)) => bounds,
+            (None, None) => return Ok(None),
+        };
+        index.bounds.write().insert(hash, bounds);
+        Ok(Some(bounds))
+    }
+
+    /// Finds the extreme leaf by visiting every leaf, in key order.
+    fn scan_extreme_leaf(&self, extreme: Extreme) -> Result<Option<LeafNode<K, V>>> {
+        let mut best: Option<LeafNode<K, V>> = None;
+        self.walk(|_, node| {
+            if let Some(leaf) = node.as_leaf::<K, V>() {
+                let better = match (&best, extreme) {
+                    (None, _) => true,
+                    (Some(best), Extreme::Max) => leaf.sum > best.sum,
+                    (Some(best), Extreme::Min) => leaf.sum < best.sum,
+                };
+                if better && !leaf.is_empty() {
+                    best = Some(leaf.clone());
+                }
+            }
+            WalkControl::Continue
+        })?;
+        Ok(best)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::DefaultStore;
+
+    #[test]
+    fn test_indexed_queries_match_scans() -> Result<()> {
+        let mut indexed = FullTree::new(DefaultStore::new());
+        indexed.enable_sum_index();
+        let mut scanned = FullTree::new(DefaultStore::new());
+        assert!(indexed.max_sum_leaf()?.is_none());
+
+        let sums = [40, 7, 93, 7, 15, 93, 61, 2];
+        for (i, sum) in sums.iter().enumerate() {
+            let key = [(i as u8 + 1) * 29; 32];
+            indexed.insert(key, vec![i as u8], *sum)?;
+            scanned.insert(key, vec![i as u8], *sum)?;
+
+            for (a, b) in [
+                (indexed.max_sum_leaf()?, scanned.max_sum_leaf()?),
+                (indexed.min_sum_leaf()?, scanned.min_sum_leaf()?),
+            ] {
+                let (a, b) = (a.unwrap(), b.unwrap());
+                assert_eq!((a.key, a.sum), (b.key, b.sum));
+            }
+        }
+        // Ties resolve to the smallest key
+        assert_eq!(indexed.max_sum_leaf()?.unwrap().key, [3 * 29; 32]);
+
+        // Updates only add the bounds of the new path
+        indexed.delete([3 * 29; 32])?;
+        indexed.update_sum([8 * 29; 32], 100)?;
+        assert_eq!(indexed.max_sum_leaf()?.unwrap().key, [8 * 29; 32]);
+        assert_eq!(indexed.min_sum_leaf()?.unwrap().sum, 7);
+        assert!(!indexed.sum_index().unwrap().bounds.read().is_empty());
+
+        Ok(())
+    }
+}
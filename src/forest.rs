@@ -0,0 +1,354 @@
+//! Many Merkle-Sum Sparse Merkle Trees over a single store.
+//!
+//! A `Forest` keeps one independent tree per namespace, such as one tree per asset, in a single storage
+//! backend. The roots of the namespace trees are themselves committed in a registry tree, which is the
+//! root of the underlying store: the leaf at a namespace key holds the namespace root hash as its value
+//! and the namespace root sum as its sum. Updating a namespace and the registry therefore lands in the
+//! store with a single root update.
+//!
+//! Nodes are addressed by their hash, so identical nodes of different namespaces are stored once. Since
+//! a node may be shared, namespace trees never delete nodes themselves; `Forest::compact` removes the
+//! nodes no longer reachable from any namespace.
+//!
+//! The registry commits namespace roots like a parent tree commits child trees (see the [`nested`]
+//! module), so leaves of a namespace can be proven against the forest root.
+//!
+//! [`nested`]: crate::nested
+
+use crate::compact::{mark_reachable, CompactionReport};
+use crate::error::{MssmtError, Result};
+use crate::hash_utils::to_array;
+use crate::key::Key;
+use crate::node::{
+    collect_leaves, BranchNode, EmptyTree, LeafNode, Node, NodeHash, EMPTY_TREE, HASH_SIZE,
+};
+use crate::store::{TreeStore, TreeStoreReader, TreeStoreWriter};
+use crate::tree::FullTree;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+/// A collection of trees keyed by namespace, sharing one store.
+///
+/// Namespace trees are regular `FullTree`s over a `NamespaceStore` view of the forest's store, so the
+/// whole tree API (proofs, batches, observers, ...) is available for each namespace. A namespace exists
+/// as long as its tree is not empty.
+///
+/// The sums of all namespace trees are added up in the registry root, so their total must fit in a `Sum`.
+///
+/// # Examples
+///
+/// ```rust
+/// use mssmt::forest::Forest;
+/// use mssmt::{DefaultStore, Node};
+///
+/// let mut forest = Forest::new(DefaultStore::new());
+/// forest.tree_mut([1u8; 32]).unwrap().insert([7u8; 32], b"a".to_vec(), 10).unwrap();
+/// forest.tree_mut([2u8; 32]).unwrap().insert([7u8; 32], b"b".to_vec(), 20).unwrap();
+///
+/// let tree = forest.tree([1u8; 32]).unwrap();
+/// assert_eq!(tree.get([7u8; 32]).unwrap(), Some((b"a".to_vec(), 10)));
+/// assert_eq!(forest.namespaces().unwrap().len(), 2);
+/// assert_eq!(forest.root().unwrap().node_sum(), 30);
+/// ```
+pub struct Forest<S> {
+    store: S,
+}
+
+impl<S> Forest<S> {
+    /// Creates a forest over `store`, whose root is the registry of the namespace roots.
+    ///
+    /// An empty store holds an empty forest.
+    pub fn new(store: S) -> Self {
+        Self { store }
+    }
+
+    /// Returns a reference to the underlying store.
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// Consumes the forest, returning the underlying store.
+    pub fn into_store(self) -> S {
+        self.store
+    }
+}
+
+impl<S: TreeStoreReader> Forest<S> {
+    /// Returns the root of the registry tree, which commits to the roots of all namespaces.
+    pub fn root(&self) -> Result<Arc<dyn Node>> {
+        self.store.root_node()
+    }
+
+    /// Returns the root of the tree of `namespace`, which is the empty tree root for unknown namespaces.
+    pub fn tree_root(&self, namespace: impl Into<Key>) -> Result<Arc<dyn Node>> {
+        let registry = FullTree::new(Registry(&self.store));
+        match registry.get(namespace)? {
+            Some((value, _)) => load_root(&self.store, &decode_root_hash(&value)?),
+            None => Ok(EMPTY_TREE[0].clone()),
+        }
+    }
+
+    /// Returns the namespaces holding a non-empty tree, in key order.
+    pub fn namespaces(&self) -> Result<Vec<Key>> {
+        Ok(self
+            .registry_leaves()?
+            .into_iter()
+            .map(|leaf| Key(leaf.key))
+            .collect())
+    }
+
+    /// Returns a read-only view of the tree of `namespace`.
+    pub fn tree(&self, namespace: impl Into<Key>) -> Result<FullTree<NamespaceStore<&S>>> {
+        let namespace = namespace.into();
+        let root = self.tree_root(namespace)?;
+        Ok(FullTree::new(NamespaceStore {
+            store: &self.store,
+            namespace,
+            root,
+        }))
+    }
+
+    fn registry_leaves(&self) -> Result<Vec<LeafNode>> {
+        let mut leaves = Vec::new();
+        collect_leaves(&self.store, &self.store.root_node()?, 0, &mut leaves)?;
+        Ok(leaves)
+    }
+}
+
+impl<S: TreeStore> Forest<S> {
+    /// Returns the tree of `namespace` for updating.
+    ///
+    /// Every root update of the returned tree is committed to the registry right away.
+    pub fn tree_mut(
+        &mut self,
+        namespace: impl Into<Key>,
+    ) -> Result<FullTree<NamespaceStore<&mut S>>> {
+        let namespace = namespace.into();
+        let root = self.tree_root(namespace)?;
+        Ok(FullTree::new(NamespaceStore {
+            store: &mut self.store,
+            namespace,
+            root,
+        }))
+    }
+
+    /// Removes the tree of `namespace` from the registry.
+    ///
+    /// The nodes of the removed tree stay in the store until the next `compact`.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(true)` if the namespace held a tree.
+    /// - `Ok(false)` if the namespace was empty.
+    pub fn remove(&mut self, namespace: impl Into<Key>) -> Result<bool> {
+        let mut registry = FullTree::new(Registry(&mut self.store));
+        Ok(registry.delete(namespace)?.is_some())
+    }
+
+    /// Deletes every node that is reachable neither from the registry nor from a namespace tree.
+    ///
+    /// The store must be able to list its nodes. Like `FullTree::compact`, this discards previous
+    /// versions of all trees.
+    pub fn compact(&mut self) -> Result<CompactionReport> {
+        let mut reachable = HashSet::new();
+        mark_reachable(&self.store, &self.store.root_node()?, 0, &mut reachable)?;
+        for leaf in self.registry_leaves()? {
+            let root = load_root(&self.store, &decode_root_hash(&leaf.value)?)?;
+            mark_reachable(&self.store, &root, 0, &mut reachable)?;
+        }
+
+        let mut report = CompactionReport::default();
+        for hash in self.store.branch_hashes()? {
+            if !reachable.contains(&hash) {
+                self.store.delete_branch(&hash)?;
+                report.branches_removed += 1;
+            }
+        }
+        for hash in self.store.leaf_hashes()? {
+            if !reachable.contains(&hash) {
+                self.store.delete_leaf(&hash)?;
+                report.leaves_removed += 1;
+            }
+        }
+        Ok(report)
+    }
+}
+
+/// The view of a `Forest` store backing the tree of one namespace.
+///
+/// Node reads and writes go to the shared store, node deletions are deferred to `Forest::compact`, and
+/// root updates are committed to the registry of the forest.
+pub struct NamespaceStore<S> {
+    store: S,
+    namespace: Key,
+    root: Arc<dyn Node>,
+}
+
+impl<S> NamespaceStore<S> {
+    /// Returns the namespace of the tree.
+    pub fn namespace(&self) -> Key {
+        self.namespace
+    }
+}
+
+impl<S: TreeStoreReader> TreeStoreReader for NamespaceStore<S> {
+    fn root_node(&self) -> Result<Arc<dyn Node>> {
+        Ok(self.root.clone())
+    }
+
+    fn get_branch(&self, key: &NodeHash) -> Result<Option<Arc<BranchNode>>> {
+        self.store.get_branch(key)
+    }
+
+    fn get_leaf(&self, key: &NodeHash) -> Result<Option<Arc<LeafNode>>> {
+        self.store.get_leaf(key)
+    }
+}
+
+impl<S: TreeStore> TreeStoreWriter for NamespaceStore<S> {
+    fn insert_branch(&mut self, branch: Arc<BranchNode>) -> Result<()> {
+        self.store.insert_branch(branch)
+    }
+
+    fn insert_leaf(&mut self, leaf: Arc<LeafNode>) -> Result<()> {
+        self.store.insert_leaf(leaf)
+    }
+
+    fn insert_nodes(
+        &mut self,
+        branches: Vec<Arc<BranchNode>>,
+        leaves: Vec<Arc<LeafNode>>,
+    ) -> Result<()> {
+        self.store.insert_nodes(branches, leaves)
+    }
+
+    fn delete_branch(&mut self, _key: &NodeHash) -> Result<()> {
+        Ok(())
+    }
+
+    fn delete_leaf(&mut self, _key: &NodeHash) -> Result<()> {
+        Ok(())
+    }
+
+    fn update_root(&mut self, root: Arc<dyn Node>) -> Result<()> {
+        let mut registry = FullTree::new(Registry(&mut self.store));
+        if EmptyTree::is_empty_at(0, &root.node_hash()) {
+            registry.delete(self.namespace)?;
+        } else {
+            registry.commit_child(self.namespace, root.as_ref())?;
+        }
+        self.root = root;
+        Ok(())
+    }
+}
+
+/// The view of a `Forest` store backing the registry tree.
+///
+/// The store's key index also covers the leaves of namespace trees, so it is not used, and deletions
+/// are deferred because registry nodes may be shared with namespace trees.
+struct Registry<S>(S);
+
+impl<S: TreeStoreReader> TreeStoreReader for Registry<S> {
+    fn root_node(&self) -> Result<Arc<dyn Node>> {
+        self.0.root_node()
+    }
+
+    fn get_branch(&self, key: &NodeHash) -> Result<Option<Arc<BranchNode>>> {
+        self.0.get_branch(key)
+    }
+
+    fn get_leaf(&self, key: &NodeHash) -> Result<Option<Arc<LeafNode>>> {
+        self.0.get_leaf(key)
+    }
+}
+
+impl<S: TreeStoreWriter> TreeStoreWriter for Registry<S> {
+    fn insert_branch(&mut self, branch: Arc<BranchNode>) -> Result<()> {
+        self.0.insert_branch(branch)
+    }
+
+    fn insert_leaf(&mut self, leaf: Arc<LeafNode>) -> Result<()> {
+        self.0.insert_leaf(leaf)
+    }
+
+    fn insert_nodes(
+        &mut self,
+        branches: Vec<Arc<BranchNode>>,
+        leaves: Vec<Arc<LeafNode>>,
+    ) -> Result<()> {
+        self.0.insert_nodes(branches, leaves)
+    }
+
+    fn delete_branch(&mut self, _key: &NodeHash) -> Result<()> {
+        Ok(())
+    }
+
+    fn delete_leaf(&mut self, _key: &NodeHash) -> Result<()> {
+        Ok(())
+    }
+
+    fn update_root(&mut self, root: Arc<dyn Node>) -> Result<()> {
+        self.0.update_root(root)
+    }
+}
+
+fn decode_root_hash(value: &[u8]) -> Result<NodeHash> {
+    if value.len() != HASH_SIZE {
+        return Err(MssmtError::InvalidEncoding(format!(
+            "expected a {}-byte namespace root, got {} bytes",
+            HASH_SIZE,
+            value.len()
+        )));
+    }
+    Ok(NodeHash::new(to_array(value)))
+}
+
+fn load_root<S: TreeStoreReader>(store: &S, hash: &NodeHash) -> Result<Arc<dyn Node>> {
+    match store.get_branch(hash)? {
+        Some(root) => Ok(root),
+        None => Err(MssmtError::NodeNotFound(*hash)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::DefaultStore;
+
+    #[test]
+    fn test_namespaces_are_independent() -> Result<()> {
+        let mut forest = Forest::new(DefaultStore::new());
+        let mut standalone = FullTree::new(DefaultStore::new());
+        for i in 0..4u8 {
+            forest.tree_mut([1u8; 32])?.insert([i; 32], vec![i], 1)?;
+            standalone.insert([i; 32], vec![i], 1)?;
+        }
+        // The same leaf in another namespace shares its node, and deleting it there keeps it here
+        forest.tree_mut([2u8; 32])?.insert([0u8; 32], vec![0], 1)?;
+        forest.tree_mut([2u8; 32])?.insert([9u8; 32], vec![9], 9)?;
+        forest.tree_mut([2u8; 32])?.delete([0u8; 32])?;
+
+        let root = standalone.root()?.node_hash();
+        assert_eq!(forest.tree_root([1u8; 32])?.node_hash(), root);
+        assert_eq!(forest.tree([1u8; 32])?.get([0u8; 32])?, Some((vec![0], 1)));
+        assert_eq!(forest.tree([2u8; 32])?.get([0u8; 32])?, None);
+        assert_eq!(forest.root()?.node_sum(), 4 + 9);
+
+        // The registry survives a reopen of the store
+        let mut forest = Forest::new(forest.into_store());
+        assert_eq!(forest.namespaces()?, vec![Key([1u8; 32]), Key([2u8; 32])]);
+
+        assert!(forest.remove([2u8; 32])?);
+        assert!(!forest.remove([3u8; 32])?);
+        let report = forest.compact()?;
+        assert!(report.leaves_removed > 0);
+        assert_eq!(
+            forest.tree([2u8; 32])?.root()?.node_hash(),
+            EmptyTree::hash_at(0)
+        );
+        assert!(forest.tree([1u8; 32])?.verify_integrity()?.is_ok());
+        assert_eq!(forest.tree([1u8; 32])?.root()?.node_hash(), root);
+
+        Ok(())
+    }
+}
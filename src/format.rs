@@ -0,0 +1,345 @@
+//! Versions of the persistent formats and migrations between them.
+//!
+//! Every format this crate persists records the version it was written in: binary store snapshots,
+//! incremental backups, proof archives, operation logs and log stores start with 8 magic bytes followed by a version byte, JSON snapshots carry a `version`
+//! field and Redis stores keep their version under the `<namespace>:version` key. Readers reject
+//! versions newer than the ones they support, and versions older than `Format::oldest_readable_version`,
+//! whose layout has since changed.
+//!
+//! `migrate` upgrades a file in place to the current version of its format, one version at a time, and
+//! `RedisStore::migrate` does the same for a Redis namespace. Data written before formats were versioned
+//! has version 0.
+
+use crate::error::{MssmtError, Result};
+use std::fmt;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// The size of the header of binary formats: 8 magic bytes and the version byte.
+pub(crate) const HEADER_SIZE: usize = 9;
+
+/// A persistent format of this crate.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Format {
+    /// Binary store snapshots, see `StoreSnapshot`.
+    StoreSnapshot,
+    /// Binary incremental backups, see `IncrementalBackup`.
+    IncrementalBackup,
+    /// Binary batches of proofs, see `ProofArchive`.
+    ProofArchive,
+    /// Binary operation sequences, see `replay::encode_ops`.
+    OpLog,
+    /// Append-only log files, see `LogStore`.
+    LogStore,
+    /// JSON snapshots of a tree, see `FullTree::export_json`.
+    JsonSnapshot,
+    /// Namespaces of a Redis server, see `RedisStore`.
+    RedisStore,
+}
+
+impl Format {
+    /// Returns the version this release writes.
+    pub const fn current_version(self) -> u8 {
+        match self {
+            Format::StoreSnapshot
+            | Format::IncrementalBackup
+            | Format::ProofArchive
+            | Format::OpLog
+            | Format::JsonSnapshot => 1,
+            // Version 2 added compressed leaves
+            Format::RedisStore => 2,
+            // Version 2 added compressed leaves, version 3 deduplicated values
+            Format::LogStore => 3,
+        }
+    }
+
+    /// Returns the oldest version this release reads without migrating it first.
+    pub const fn oldest_readable_version(self) -> u8 {
+        match self {
+            Format::StoreSnapshot
+            | Format::IncrementalBackup
+            | Format::ProofArchive
+            | Format::OpLog
+            | Format::LogStore => 1,
+            // Versioning added the version field and key, the layout is unchanged
+            Format::JsonSnapshot | Format::RedisStore => 0,
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Format::StoreSnapshot => "store snapshot",
+            Format::IncrementalBackup => "incremental backup",
+            Format::ProofArchive => "proof archive",
+            Format::OpLog => "operation log",
+            Format::LogStore => "log store",
+            Format::JsonSnapshot => "JSON snapshot",
+            Format::RedisStore => "Redis store",
+        })
+    }
+}
+
+/// The outcome of a migration.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Migration {
+    /// The format of the migrated data.
+    pub format: Format,
+    /// The version of the data before the migration.
+    pub from_version: u8,
+    /// The version of the data after the migration.
+    pub to_version: u8,
+}
+
+impl Migration {
+    /// Returns `true` if the data was already at the current version and was left untouched.
+    pub fn is_noop(&self) -> bool {
+        self.from_version == self.to_version
+    }
+}
+
+/// Builds the header of a binary format.
+pub(crate) const fn header(magic: &[u8; 8], version: u8) -> [u8; HEADER_SIZE] {
+    let mut header = [version; HEADER_SIZE];
+    let mut i = 0;
+    while i < magic.len() {
+        header[i] = magic[i];
+        i += 1;
+    }
+    header
+}
+
+/// Checks that data in `format` at `version` can be read by this release.
+///
+/// # Returns
+///
+/// - `MssmtError::InvalidEncoding` if the version is newer than the current one, or older than the
+///   oldest readable one and must be migrated first.
+pub(crate) fn check_version(format: Format, version: u8) -> Result<()> {
+    if version > format.current_version() {
+        return Err(MssmtError::InvalidEncoding(format!(
+            "{} version {} is newer than the supported version {}",
+            format,
+            version,
+            format.current_version()
+        )));
+    }
+    if version < format.oldest_readable_version() {
+        return Err(MssmtError::InvalidEncoding(format!(
+            "{} version {} must be migrated to version {} first",
+            format,
+            version,
+            format.current_version()
+        )));
+    }
+    Ok(())
+}
+
+/// Detects the format and version of persisted data from its contents.
+///
+/// # Returns
+///
+/// - The format and version of the data.
+/// - `MssmtError::InvalidEncoding` if the data is not in a format of this crate.
+/// - `MssmtError::Unsupported` for JSON snapshots without the `json` feature.
+pub fn detect(bytes: &[u8]) -> Result<(Format, u8)> {
+    let version = bytes.get(HEADER_SIZE - 1).copied();
+    if bytes.starts_with(crate::store::SNAPSHOT_MAGIC) {
+        return Ok((Format::StoreSnapshot, version.unwrap_or(0)));
+    }
+    if bytes.starts_with(crate::backup::BACKUP_MAGIC) {
+        return Ok((Format::IncrementalBackup, version.unwrap_or(0)));
+    }
+    if bytes.starts_with(crate::archive::ARCHIVE_MAGIC) {
+        return Ok((Format::ProofArchive, version.unwrap_or(0)));
+    }
+    if bytes.starts_with(crate::replay::OPS_MAGIC) {
+        return Ok((Format::OpLog, version.unwrap_or(0)));
+    }
+    if bytes.starts_with(crate::store::LOG_MAGIC) {
+        return Ok((Format::LogStore, version.unwrap_or(0)));
+    }
+    if bytes.trim_ascii_start().starts_with(b"{") {
+        #[cfg(feature = "json")]
+        return Ok((Format::JsonSnapshot, crate::json::snapshot_version(bytes)?));
+        #[cfg(not(feature = "json"))]
+        return Err(MssmtError::Unsupported(
+            "JSON snapshots require the `json` feature",
+        ));
+    }
+    Err(MssmtError::InvalidEncoding(
+        "not a format of this crate".to_string(),
+    ))
+}
+
+/// Upgrades the file at `path` to the current version of its format.
+///
+/// The format is detected from the contents of the file. Each version is upgraded to the next until the
+/// current one is reached, and the result replaces the file atomically. A file already at the current
+/// version is left untouched.
+///
+/// # Returns
+///
+/// - The performed `Migration`.
+/// - `MssmtError::InvalidEncoding` if the file is not in a format of this crate or is newer than this
+///   release supports.
+/// - `MssmtError::Io` if the file cannot be read or replaced.
+///
+/// # Examples
+///
+/// ```rust
+/// use mssmt::format::{self, Format};
+/// use mssmt::{DefaultStore, FullTree};
+///
+/// let path = std::env::temp_dir().join(format!("mssmt-doc-{}.migrate", std::process::id()));
+///
+/// let mut tree = FullTree::new(DefaultStore::new());
+/// tree.insert([1u8; 32], b"value".to_vec(), 10).unwrap();
+/// tree.store().save_to(&path).unwrap();
+///
+/// let migration = format::migrate(&path).unwrap();
+/// assert_eq!(migration.format, Format::StoreSnapshot);
+/// assert!(migration.is_noop());
+/// # std::fs::remove_file(&path).unwrap();
+/// ```
+pub fn migrate(path: impl AsRef<Path>) -> Result<Migration> {
+    let path = path.as_ref();
+    let mut bytes = std::fs::read(path)?;
+    let (format, from_version) = detect(&bytes)?;
+    let to_version = format.current_version();
+    if from_version > to_version {
+        check_version(format, from_version)?;
+    }
+
+    for version in from_version..to_version {
+        bytes = migrate_step(format, version, &bytes)?;
+    }
+    if from_version < to_version {
+        replace_file(path, &bytes)?;
+    }
+    debug_event!(
+        path = %path.display(),
+        format = %format,
+        from_version,
+        to_version,
+        "format migrated"
+    );
+    Ok(Migration {
+        format,
+        from_version,
+        to_version,
+    })
+}
+
+/// Upgrades data in `format` from `version` to the next version.
+fn migrate_step(format: Format, version: u8, bytes: &[u8]) -> Result<Vec<u8>> {
+    match (format, version) {
+        #[cfg(feature = "json")]
+        (Format::JsonSnapshot, 0) => crate::json::upgrade_snapshot(bytes),
+        // Later log versions only add record types
+        (Format::LogStore, 1 | 2) => {
+            let mut bytes = bytes.to_vec();
+            bytes[HEADER_SIZE - 1] = version + 1;
+            Ok(bytes)
+        }
+        _ => Err(MssmtError::InvalidEncoding(format!(
+            "no migration from {} version {}",
+            format, version
+        ))),
+    }
+}
+
+/// Replaces the file at `path` by `bytes`, through a temporary file renamed over it.
+fn replace_file(path: &Path, bytes: &[u8]) -> Result<()> {
+    let mut temp = PathBuf::from(path);
+    temp.as_mut_os_string().push(".migrating");
+    let mut file = File::create(&temp)?;
+    file.write_all(bytes)?;
+    file.sync_all()?;
+    std::fs::rename(&temp, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::{DefaultStore, LogStore};
+    use crate::tree::FullTree;
+
+    #[test]
+    fn test_detect_and_migrate() -> Result<()> {
+        let dir = std::env::temp_dir();
+        let pid = std::process::id();
+        let mut tree = FullTree::new(DefaultStore::new());
+        tree.insert([1u8; 32], b"one".to_vec(), 1)?;
+
+        let snapshot = dir.join(format!("mssmt-format-{pid}.snapshot"));
+        tree.store().save_to(&snapshot)?;
+        let log = dir.join(format!("mssmt-format-{pid}.log"));
+        let _ = std::fs::remove_file(&log);
+        drop(LogStore::open(&log)?);
+        for (path, format) in [(&snapshot, Format::StoreSnapshot), (&log, Format::LogStore)] {
+            assert_eq!(
+                detect(&std::fs::read(path)?)?,
+                (format, format.current_version())
+            );
+            assert!(migrate(path)?.is_noop());
+        }
+
+        // Older logs are upgraded by rewriting their header
+        let mut bytes = std::fs::read(&log)?;
+        bytes[HEADER_SIZE - 1] = 1;
+        std::fs::write(&log, &bytes)?;
+        let migration = migrate(&log)?;
+        assert_eq!((migration.from_version, migration.to_version), (1, 3));
+        assert_eq!(detect(&std::fs::read(&log)?)?, (Format::LogStore, 3));
+
+        // Files from a later release are rejected, by readers and migrations alike
+        let mut bytes = std::fs::read(&snapshot)?;
+        bytes[HEADER_SIZE - 1] = 2;
+        std::fs::write(&snapshot, &bytes)?;
+        assert!(matches!(
+            migrate(&snapshot),
+            Err(MssmtError::InvalidEncoding(_))
+        ));
+        assert!(DefaultStore::load_from(&snapshot).is_err());
+        std::fs::remove_file(&snapshot)?;
+
+        let mut bytes = std::fs::read(&log)?;
+        bytes[HEADER_SIZE - 1] = 4;
+        std::fs::write(&log, &bytes)?;
+        assert!(matches!(
+            LogStore::open(&log),
+            Err(MssmtError::InvalidEncoding(_))
+        ));
+        std::fs::remove_file(&log)?;
+
+        assert!(detect(b"not a tree").is_err());
+
+        // Unversioned JSON snapshots are upgraded in place
+        #[cfg(feature = "json")]
+        {
+            let mut json = Vec::new();
+            tree.export_json(&mut json)?;
+            assert_eq!(detect(&json)?, (Format::JsonSnapshot, 1));
+            let legacy = String::from_utf8(json)
+                .unwrap()
+                .replace("\"version\": 1,", "");
+            assert_eq!(detect(legacy.as_bytes())?, (Format::JsonSnapshot, 0));
+
+            let path = dir.join(format!("mssmt-format-{pid}.json"));
+            std::fs::write(&path, &legacy)?;
+            let migration = migrate(&path)?;
+            assert_eq!((migration.from_version, migration.to_version), (0, 1));
+            let migrated = std::fs::read(&path)?;
+            assert_eq!(detect(&migrated)?, (Format::JsonSnapshot, 1));
+            let imported = FullTree::import_json(migrated.as_slice(), DefaultStore::new())?;
+            assert_eq!(imported.root()?.node_hash(), tree.root()?.node_hash());
+            std::fs::remove_file(&path)?;
+        }
+
+        Ok(())
+    }
+}
@@ -21,7 +21,6 @@
 /// let bytes = [1u8; 16];
 /// let _array = to_array(&bytes);
 /// ```
-
 pub fn to_array(bytes: &[u8]) -> [u8; 32] {
     let mut array = [0u8; 32];
     array.copy_from_slice(&bytes[..32]);
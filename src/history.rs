@@ -0,0 +1,226 @@
+//! Archived roots of a tree and proofs against past versions.
+//!
+//! A tree with its root history enabled archives the hash, sum and version of every root it commits
+//! to, see `FullTree::enable_root_history`. Updates are copy-on-write, so the branches of the archived
+//! versions stay in the store until it is compacted, and `FullTree::prove_at_version` proves a key
+//! against any of them. The resulting `HistoricalProof` is bound to the archived root: auditors holding
+//! the `(root_hash, root_sum, version)` of a past checkpoint can check what it contained without
+//! trusting the current state of the tree.
+
+use crate::commitment::RootCommitment;
+use crate::error::{MssmtError, Result};
+use crate::key::Key;
+use crate::node::{LeafNode, LeafValue, NodeHash, Sum, HASH_SIZE};
+use crate::observer::RootUpdate;
+use crate::proof::Proof;
+use crate::store::{resolve_root, TreeStoreReader};
+use crate::tree::FullTree;
+
+/// A root committed to by a tree, with the version it was committed at.
+///
+/// Versions count the root changes made through the tree, as reported by `FullTree::root_version`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ArchivedRoot {
+    /// The hash of the root.
+    pub root_hash: NodeHash,
+    /// The sum of the root.
+    pub root_sum: Sum,
+    /// The version of the tree the root was committed at.
+    pub version: u64,
+}
+
+impl ArchivedRoot {
+    /// Returns the hash and sum of the root as a commitment.
+    pub fn commitment(&self) -> RootCommitment {
+        RootCommitment::new(self.root_hash, self.root_sum)
+    }
+}
+
+impl From<RootUpdate> for ArchivedRoot {
+    fn from(update: RootUpdate) -> Self {
+        Self {
+            root_hash: update.root_hash,
+            root_sum: update.root_sum,
+            version: update.version,
+        }
+    }
+}
+
+/// A proof of a key against an archived root, see `FullTree::prove_at_version`.
+///
+/// `K` is the key size in bytes of the tree, 32 by default.
+#[derive(Clone)]
+pub struct HistoricalProof<const K: usize = HASH_SIZE> {
+    /// The archived root the proof verifies against.
+    pub root: ArchivedRoot,
+    /// The proof of the key in the archived version.
+    pub proof: Proof<K>,
+}
+
+impl<const K: usize> HistoricalProof<K> {
+    /// Verifies that `leaf` was at `key` in the archived version, against both the hash and the sum of
+    /// its root.
+    ///
+    /// Exclusion proofs verify with the empty leaf, `EMPTY_LEAF_NODE` for 32-byte keys.
+    pub fn verify(&self, key: impl Into<Key<K>>, leaf: &LeafNode<K, impl LeafValue>) -> bool {
+        self.proof
+            .verify_against(key, leaf, &self.root.commitment())
+    }
+
+    /// Verifies the proof like `verify`, and that it is bound to `archived`, a root the verifier
+    /// obtained independently of the proof, such as from a published checkpoint.
+    pub fn verify_archived(
+        &self,
+        archived: &ArchivedRoot,
+        key: impl Into<Key<K>>,
+        leaf: &LeafNode<K, impl LeafValue>,
+    ) -> bool {
+        self.root == *archived && self.verify(key, leaf)
+    }
+}
+
+impl<S: TreeStoreReader<K, V>, const K: usize, V: LeafValue> FullTree<S, K, V> {
+    /// Archives the current root and every later root of the tree, so that the tree can prove keys
+    /// against them with `FullTree::prove_at_version`.
+    ///
+    /// The history is held in memory and grows by one entry per root change. Enabling it again has no
+    /// effect.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use mssmt::{DefaultStore, FullTree, LeafNode};
+    ///
+    /// let mut tree = FullTree::new(DefaultStore::new());
+    /// tree.enable_root_history().unwrap();
+    /// tree.insert([1u8; 32], b"old".to_vec(), 1).unwrap();
+    /// let checkpoint = *tree.root_history().last().unwrap();
+    /// tree.insert([1u8; 32], b"new".to_vec(), 2).unwrap();
+    ///
+    /// // The proof shows what the checkpoint contained, not what the tree holds now
+    /// let proof = tree.prove_at_version(checkpoint.version, [1u8; 32]).unwrap();
+    /// let leaf = LeafNode::new([1u8; 32], b"old".to_vec(), 1);
+    /// assert!(proof.verify_archived(&checkpoint, [1u8; 32], &leaf));
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the current root cannot be read.
+    pub fn enable_root_history(&mut self) -> Result<()> {
+        if self.root_history_mut().is_none() {
+            let root = self.root()?;
+            let archived = ArchivedRoot {
+                root_hash: root.node_hash(),
+                root_sum: root.node_sum(),
+                version: self.root_version(),
+            };
+            *self.root_history_mut() = Some(vec![archived]);
+        }
+        Ok(())
+    }
+
+    /// Returns the archived root of `version`, if any.
+    pub fn archived_root(&self, version: u64) -> Option<ArchivedRoot> {
+        let history = self.root_history();
+        history
+            .binary_search_by_key(&version, |archived| archived.version)
+            .ok()
+            .map(|index| history[index])
+    }
+
+    /// Proves `key` against the archived root of `version`.
+    ///
+    /// # Returns
+    ///
+    /// - A `HistoricalProof` of the key, or of its absence, in that version.
+    /// - `MssmtError::VersionNotArchived` if no root of that version is archived.
+    /// - `MssmtError::NodeNotFound` if the branches of that version were removed from the store, for
+    ///   example by compaction.
+    pub fn prove_at_version(
+        &self,
+        version: u64,
+        key: impl Into<Key<K>>,
+    ) -> Result<HistoricalProof<K>> {
+        let root = self
+            .archived_root(version)
+            .ok_or(MssmtError::VersionNotArchived(version))?;
+        let node = resolve_root(self.store(), &root.root_hash)?;
+        let proof = self.merkle_proof_at(node, &key.into().0)?;
+        Ok(HistoricalProof { root, proof })
+    }
+
+    /// Retrieves the value and sum of `key` in the archived version `version`.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(Some((value, sum)))` if the key was present in that version, `Ok(None)` otherwise.
+    /// - `MssmtError::VersionNotArchived` if no root of that version is archived.
+    /// - `MssmtError::NodeNotFound` if the nodes on the path of the key in that version were removed
+    ///   from the store. Trees delete the leaves they replace, so stores that resolve leaves by hash
+    ///   rather than holding them in their branches only keep the leaves still current.
+    pub fn get_at_version(&self, version: u64, key: impl Into<Key<K>>) -> Result<Option<(V, Sum)>> {
+        let root = self
+            .archived_root(version)
+            .ok_or(MssmtError::VersionNotArchived(version))?;
+        let node = resolve_root(self.store(), &root.root_hash)?;
+        self.get_at_node(node, 0, &key.into().0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::EMPTY_LEAF_NODE;
+    use crate::store::DefaultStore;
+
+    #[test]
+    fn test_proofs_at_archived_versions() -> Result<()> {
+        let mut tree = FullTree::new(DefaultStore::new());
+        tree.insert([9u8; 32], b"before".to_vec(), 9)?;
+        tree.enable_root_history()?;
+        tree.insert([1u8; 32], b"one".to_vec(), 1)?;
+        tree.insert([2u8; 32], b"two".to_vec(), 2)?;
+        tree.insert([1u8; 32], b"changed".to_vec(), 5)?;
+        tree.delete([2u8; 32])?;
+
+        let versions: Vec<u64> = tree
+            .root_history()
+            .iter()
+            .map(|root| root.version)
+            .collect();
+        assert_eq!(versions, vec![1, 2, 3, 4, 5]);
+        assert_eq!(tree.root_history().last().unwrap().root_sum, 14);
+
+        // Version 3 held both keys with their original values
+        let checkpoint = tree.archived_root(3).unwrap();
+        assert_eq!(checkpoint.root_sum, 12);
+        let old = LeafNode::new([1u8; 32], b"one".to_vec(), 1);
+        let proof = tree.prove_at_version(3, [1u8; 32])?;
+        assert!(proof.verify_archived(&checkpoint, [1u8; 32], &old));
+        assert!(!proof.verify([1u8; 32], &LeafNode::new([1u8; 32], b"changed".to_vec(), 5)));
+        assert!(!proof.verify_archived(&tree.archived_root(4).unwrap(), [1u8; 32], &old));
+        assert_eq!(
+            tree.get_at_version(3, [2u8; 32])?,
+            Some((b"two".to_vec(), 2))
+        );
+
+        // Keys absent from a version are proven absent
+        let proof = tree.prove_at_version(2, [2u8; 32])?;
+        assert!(proof.verify([2u8; 32], &EMPTY_LEAF_NODE));
+        assert_eq!(tree.get_at_version(5, [2u8; 32])?, None);
+
+        assert!(matches!(
+            tree.prove_at_version(0, [1u8; 32]),
+            Err(MssmtError::VersionNotArchived(0))
+        ));
+
+        // Compaction removes the branches of the archived versions
+        tree.compact()?;
+        assert!(matches!(
+            tree.prove_at_version(3, [1u8; 32]),
+            Err(MssmtError::NodeNotFound(_))
+        ));
+
+        Ok(())
+    }
+}
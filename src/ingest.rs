@@ -0,0 +1,173 @@
+//! Streaming ingestion of leaf records into a Merkle-Sum Sparse Merkle Tree.
+//!
+//! Records are read one line at a time, so exported ledgers of any size can be loaded without
+//! holding them in memory. Two formats are supported: newline-delimited JSON objects with hex
+//! `key` and `value` fields and a numeric `sum`, and CSV lines of the form `key,value,sum`.
+//!
+//! This module requires the `json` feature.
+
+use crate::error::{MssmtError, Result};
+use crate::json::{decode_hash, JsonLeaf};
+use crate::node::{NodeHash, Sum, HASH_SIZE};
+use crate::progress::{insert_writes, Progress, ProgressTracker};
+use crate::store::TreeStore;
+use crate::tree::FullTree;
+use std::io::BufRead;
+
+/// The format of the records being ingested.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IngestFormat {
+    /// One JSON object per line: `{"key": "<hex>", "value": "<hex>", "sum": <n>}`.
+    Ndjson,
+    /// One `key,value,sum` record per line, with key and value in hex. A leading
+    /// `key,value,sum` header line is skipped.
+    Csv,
+}
+
+/// The outcome of an ingestion run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IngestReport {
+    /// The number of records inserted.
+    pub records: u64,
+    /// The root hash of the tree after the last record.
+    pub root: NodeHash,
+    /// The root sum of the tree after the last record.
+    pub sum: Sum,
+}
+
+impl<S: TreeStore> FullTree<S> {
+    /// Inserts every record read from `reader` into the tree.
+    ///
+    /// Blank lines are ignored. Records are inserted in order, so a later record for the same key
+    /// overwrites an earlier one. On a malformed record the error names its line number and the
+    /// records before it remain inserted.
+    ///
+    /// # Arguments
+    ///
+    /// - `reader`: The source of the records.
+    /// - `format`: The record format.
+    /// - `progress`: Called with the progress of the ingestion after each record. The number of records
+    ///   is not known in advance.
+    ///
+    /// # Returns
+    ///
+    /// - An `IngestReport` with the record count and the final root.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use mssmt::ingest::IngestFormat;
+    /// use mssmt::{DefaultStore, FullTree};
+    ///
+    /// let csv = format!("key,value,sum\n{},6f6e65,1\n", "01".repeat(32));
+    ///
+    /// let mut tree = FullTree::new(DefaultStore::new());
+    /// let report = tree.ingest(csv.as_bytes(), IngestFormat::Csv, |_| {}).unwrap();
+    /// assert_eq!(report.records, 1);
+    /// assert_eq!(tree.get([1u8; 32]).unwrap(), Some((b"one".to_vec(), 1)));
+    /// ```
+    pub fn ingest<R: BufRead>(
+        &mut self,
+        reader: R,
+        format: IngestFormat,
+        progress: impl FnMut(Progress),
+    ) -> Result<IngestReport> {
+        let mut progress = ProgressTracker::new(progress, None, 0);
+        let mut records = 0;
+        for (index, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || (index == 0 && format == IngestFormat::Csv && is_header(line)) {
+                continue;
+            }
+
+            let (key, value, sum) = parse_record(line, format).map_err(|err| {
+                MssmtError::InvalidEncoding(format!("line {}: {}", index + 1, err))
+            })?;
+            self.insert(key, value, sum)?;
+
+            records += 1;
+            progress.advance(1, insert_writes(HASH_SIZE));
+        }
+
+        let root = self.root()?;
+        Ok(IngestReport {
+            records,
+            root: root.node_hash(),
+            sum: root.node_sum(),
+        })
+    }
+}
+
+fn is_header(line: &str) -> bool {
+    line.eq_ignore_ascii_case("key,value,sum")
+}
+
+/// Parses a single record, returning a description of the problem on failure.
+fn parse_record(
+    line: &str,
+    format: IngestFormat,
+) -> std::result::Result<([u8; 32], Vec<u8>, Sum), String> {
+    let (key, value, sum) = match format {
+        IngestFormat::Ndjson => {
+            let leaf: JsonLeaf = serde_json::from_str(line).map_err(|err| err.to_string())?;
+            (leaf.key, leaf.value, leaf.sum)
+        }
+        IngestFormat::Csv => {
+            let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+            let [key, value, sum] = fields[..] else {
+                return Err(format!("expected 3 fields, got {}", fields.len()));
+            };
+            let sum = sum.parse().map_err(|err| format!("invalid sum: {}", err))?;
+            (key.to_string(), value.to_string(), sum)
+        }
+    };
+
+    let key = decode_hash(&key).map_err(|err| err.to_string())?;
+    let value = hex::decode(value).map_err(|err| format!("invalid value: {}", err))?;
+    Ok((key, value, sum))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::DefaultStore;
+
+    #[test]
+    fn test_ingest_formats_agree() -> Result<()> {
+        let ndjson = format!(
+            "{{\"key\": \"{}\", \"value\": \"6f6e65\", \"sum\": 1}}\n\n{{\"key\": \"{}\", \"value\": \"74776f\", \"sum\": 2}}\n",
+            "01".repeat(32),
+            "02".repeat(32)
+        );
+        let csv = format!(
+            "{},6f6e65,1\n{},74776f,2\n",
+            "01".repeat(32),
+            "02".repeat(32)
+        );
+
+        let mut ndjson_tree = FullTree::new(DefaultStore::new());
+        let mut last = None;
+        let ndjson_report =
+            ndjson_tree.ingest(ndjson.as_bytes(), IngestFormat::Ndjson, |p| last = Some(p))?;
+        assert_eq!(ndjson_report.records, 2);
+        let last = last.unwrap();
+        assert_eq!((last.items_processed, last.nodes_written), (2, 514));
+        assert_eq!(last.items_total, None);
+
+        let mut csv_tree = FullTree::new(DefaultStore::new());
+        let csv_report = csv_tree.ingest(csv.as_bytes(), IngestFormat::Csv, |_| {})?;
+        assert_eq!(csv_report, ndjson_report);
+        assert_eq!(csv_report.sum, 3);
+
+        // Errors point at the offending line
+        let bad = format!("{},6f6e65,1\n{},zz,2\n", "01".repeat(32), "02".repeat(32));
+        let mut tree = FullTree::new(DefaultStore::new());
+        let result = tree.ingest(bad.as_bytes(), IngestFormat::Csv, |_| {});
+        assert!(
+            matches!(result, Err(MssmtError::InvalidEncoding(msg)) if msg.starts_with("line 2"))
+        );
+
+        Ok(())
+    }
+}
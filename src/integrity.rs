@@ -0,0 +1,394 @@
+//! Integrity audits of a Merkle-Sum Sparse Merkle Tree.
+//!
+//! Nodes cache their hash and sum once computed, and persistent backends may return corrupted records.
+//! `FullTree::verify_integrity` re-walks the tree from the root, recomputes every hash and sum from the
+//! node contents, and reports every node whose cached values disagree or that is missing from the store.
+//! Nodes are read from the store in traversal order and their hashes recomputed in batches, on the
+//! executor of the tree (see `FullTree::set_executor`). `FullTree::health_check` is the cheap
+//! counterpart for liveness probes, which only reads the top of the tree back from the store.
+
+use crate::cancel::CancellationToken;
+use crate::error::{MssmtError, Result};
+use crate::executor::{self, Executor};
+use crate::node::{
+    branch_hash, EmptyTree, LeafNode, LeafValue, Node, NodeHash, NodeView, Sum, EMPTY_TREE,
+    HASH_SIZE, MAX_TREE_LEVELS,
+};
+use crate::progress::{Progress, ProgressTracker};
+use crate::store::{check_node_shape, load_node, TreeStoreReader};
+use crate::tree::FullTree;
+use std::sync::Arc;
+
+/// The kind of inconsistency found by an integrity audit.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum IntegrityIssueKind {
+    /// The hash reported by the node differs from the hash recomputed from its contents.
+    HashMismatch { computed: NodeHash },
+    /// The sum reported by the node differs from the sum recomputed from its children.
+    SumMismatch { stored: Sum, computed: Option<Sum> },
+    /// The node is reachable from the root but cannot be found in the store.
+    MissingFromStore,
+    /// The node cannot stand at its height, such as a leaf above the leaf level.
+    Misplaced { reason: &'static str },
+}
+
+/// An inconsistent node found by an integrity audit.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IntegrityIssue {
+    /// The height of the node in the tree.
+    pub height: usize,
+    /// The hash reported by the node.
+    pub hash: NodeHash,
+    /// What is wrong with the node.
+    pub kind: IntegrityIssueKind,
+}
+
+/// The result of an integrity audit.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct IntegrityReport {
+    /// The number of non-empty nodes that were checked.
+    pub nodes_checked: usize,
+    /// The inconsistencies found, in traversal order.
+    pub issues: Vec<IntegrityIssue>,
+}
+
+impl IntegrityReport {
+    /// Returns `true` if no inconsistency was found.
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+impl<S: TreeStoreReader> FullTree<S> {
+    /// Re-walks the tree from the root and checks every node against its contents.
+    ///
+    /// Each branch hash and sum is recomputed from its children, each leaf hash from its key, value and sum,
+    /// and every node not equivalent to an empty subtree must be present in the store. Subtrees shared with the precomputed empty
+    /// tree are skipped.
+    ///
+    /// # Returns
+    ///
+    /// - An `IntegrityReport` listing every inconsistent node.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use mssmt::{DefaultStore, FullTree};
+    ///
+    /// let mut tree = FullTree::new(DefaultStore::new());
+    /// tree.insert([1u8; 32], b"value".to_vec(), 10).unwrap();
+    ///
+    /// let report = tree.verify_integrity().unwrap();
+    /// assert!(report.is_ok());
+    /// assert_eq!(report.nodes_checked, 257);
+    /// ```
+    pub fn verify_integrity(&self) -> Result<IntegrityReport> {
+        self.verify_integrity_cancellable(&CancellationToken::new(), |_| {})
+    }
+
+    /// Checks every node against its contents, until done or cancelled.
+    ///
+    /// Works like `FullTree::verify_integrity`, checking `token` whenever a batch of nodes is audited.
+    /// The audit does not write to the store, so a cancelled audit is simply run again from the root.
+    ///
+    /// `progress` is called whenever a batch of nodes is audited, counting the nodes checked as items.
+    /// The number of nodes is not known in advance.
+    ///
+    /// # Returns
+    ///
+    /// - An `IntegrityReport` listing every inconsistent node.
+    /// - `MssmtError::Cancelled` if the token was cancelled before the audit completed.
+    pub fn verify_integrity_cancellable(
+        &self,
+        token: &CancellationToken,
+        progress: impl FnMut(Progress),
+    ) -> Result<IntegrityReport> {
+        token.check()?;
+        let mut control = AuditControl {
+            token,
+            executor: self.executor().as_ref(),
+            progress: ProgressTracker::new(progress, None, 0),
+        };
+        let mut report = IntegrityReport::default();
+        let mut pending = Vec::with_capacity(AUDIT_BATCH_SIZE);
+        let root = self.root()?;
+        self.collect_node(&root, 0, &mut control, &mut pending, &mut report)?;
+        control.audit(&mut pending, &mut report);
+        Ok(report)
+    }
+
+    /// Walks the subtree at `node`, queueing every node with the outcome of its store lookup.
+    ///
+    /// Store reads happen here, in traversal order; recomputing hashes is left to `audit_batch`, which
+    /// runs whenever `AUDIT_BATCH_SIZE` nodes are queued.
+    fn collect_node(
+        &self,
+        node: &Arc<dyn Node>,
+        height: usize,
+        control: &mut AuditControl<'_, impl FnMut(Progress)>,
+        pending: &mut Vec<AuditedNode>,
+        report: &mut IntegrityReport,
+    ) -> Result<()> {
+        if height <= MAX_TREE_LEVELS && Arc::ptr_eq(node, &EMPTY_TREE[height]) {
+            return Ok(());
+        }
+
+        let hash = node.node_hash();
+        // Nodes equivalent to the empty tree are never required to be in the store
+        let must_be_stored = !EmptyTree::is_empty_at(height, &hash);
+
+        // Hash-referenced children are audited in their stored form
+        let node = match load_node(self.store(), node, height) {
+            Ok(node) => node,
+            Err(MssmtError::NodeNotFound(_)) => {
+                pending.push(AuditedNode {
+                    height,
+                    hash,
+                    node: None,
+                    stored: false,
+                });
+                return Ok(());
+            }
+            Err(err) => return Err(err),
+        };
+
+        let children = if let Some(branch) = node.as_branch() {
+            let stored = !must_be_stored || self.store().get_branch(&hash)?.is_some();
+            let children = [branch.left.clone(), branch.right.clone()];
+            pending.push(AuditedNode {
+                height,
+                hash,
+                node: Some(node),
+                stored,
+            });
+            Some(children)
+        } else if let Some(leaf) = node.as_leaf::<HASH_SIZE, Vec<u8>>() {
+            if leaf.is_empty() {
+                return Ok(());
+            }
+            let stored = self.store().get_leaf(&hash)?.is_some();
+            pending.push(AuditedNode {
+                height,
+                hash,
+                node: Some(node),
+                stored,
+            });
+            None
+        } else {
+            None
+        };
+
+        if pending.len() >= AUDIT_BATCH_SIZE {
+            control.token.check()?;
+            control.audit(pending, report);
+        }
+        for child in children.iter().flatten() {
+            self.collect_node(child, height + 1, control, pending, report)?;
+        }
+        Ok(())
+    }
+}
+
+impl<S: TreeStoreReader<K, V>, const K: usize, V: LeafValue> FullTree<S, K, V> {
+    /// Checks that the store can still serve the tree, see `TreeStoreReader::health_check`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use mssmt::{DefaultStore, FullTree};
+    ///
+    /// let mut tree = FullTree::new(DefaultStore::new());
+    /// tree.insert([1u8; 32], b"value".to_vec(), 10).unwrap();
+    /// assert!(tree.health_check().is_ok());
+    ///
+    /// let stats = tree.stats().unwrap();
+    /// assert_eq!((stats.store_leaves, stats.store_bytes), (Some(1), None));
+    /// ```
+    pub fn health_check(&self) -> Result<()> {
+        self.store().health_check()
+    }
+}
+
+/// The cancellation token and progress callback of an audit.
+struct AuditControl<'a, F> {
+    token: &'a CancellationToken,
+    executor: &'a dyn Executor,
+    progress: ProgressTracker<F>,
+}
+
+impl<F: FnMut(Progress)> AuditControl<'_, F> {
+    /// Audits the queued nodes, see `audit_batch`, and reports the nodes checked.
+    fn audit(&mut self, pending: &mut Vec<AuditedNode>, report: &mut IntegrityReport) {
+        let checked = report.nodes_checked;
+        audit_batch(self.executor, pending, report);
+        self.progress
+            .advance((report.nodes_checked - checked) as u64, 0);
+    }
+}
+
+/// The number of nodes queued before their hashes are recomputed together.
+const AUDIT_BATCH_SIZE: usize = 4096;
+
+/// A node reached by an integrity audit, with the outcome of its store lookup.
+struct AuditedNode {
+    height: usize,
+    /// The hash reported by the node, or by the reference to it.
+    hash: NodeHash,
+    /// The node, or `None` if it could not be loaded from the store.
+    node: Option<Arc<dyn Node>>,
+    /// Whether the node is in the store, or does not need to be.
+    stored: bool,
+}
+
+impl AuditedNode {
+    /// Recomputes the hash and sum of the node, returning what is wrong with it.
+    fn issues(&self) -> Vec<IntegrityIssueKind> {
+        let mut kinds = Vec::new();
+        let view = self.node.as_deref().map(|node| node.as_view());
+        if let Some(NodeView::Branch(branch)) = view {
+            let computed_sum = branch
+                .overflow_policy()
+                .combine(branch.left.node_sum(), branch.right.node_sum());
+            if computed_sum != Some(branch.node_sum()) {
+                kinds.push(IntegrityIssueKind::SumMismatch {
+                    stored: branch.node_sum(),
+                    computed: computed_sum,
+                });
+            }
+            let computed = branch_hash(
+                &branch.left.node_hash(),
+                &branch.right.node_hash(),
+                computed_sum.unwrap_or_default(),
+            );
+            if computed != self.hash {
+                kinds.push(IntegrityIssueKind::HashMismatch { computed });
+            }
+        } else if let Some(NodeView::<HASH_SIZE, Vec<u8>>::Leaf(leaf)) = view {
+            // A fresh leaf does not share the cached hash of the audited one
+            let computed = LeafNode::new(leaf.key, leaf.value.clone(), leaf.sum).node_hash();
+            if computed != self.hash {
+                kinds.push(IntegrityIssueKind::HashMismatch { computed });
+            }
+        }
+        if !self.stored {
+            kinds.push(IntegrityIssueKind::MissingFromStore);
+        }
+        if let Some(node) = &self.node {
+            if let Err(MssmtError::CorruptedTree { reason, .. }) =
+                check_node_shape::<HASH_SIZE>(node, self.height)
+            {
+                kinds.push(IntegrityIssueKind::Misplaced { reason });
+            }
+        }
+        kinds
+    }
+}
+
+/// Audits the queued nodes and records the results in `report`, in queue order.
+///
+/// The nodes are independent, so their hashes are recomputed together on `executor` (see
+/// `executor::map`).
+fn audit_batch(
+    executor: &dyn Executor,
+    pending: &mut Vec<AuditedNode>,
+    report: &mut IntegrityReport,
+) {
+    let issues = executor::map(executor, pending, AuditedNode::issues);
+    for (audited, kinds) in pending.drain(..).zip(issues) {
+        report.nodes_checked += audited.node.is_some() as usize;
+        report
+            .issues
+            .extend(kinds.into_iter().map(|kind| IntegrityIssue {
+                height: audited.height,
+                hash: audited.hash,
+                kind,
+            }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::{BranchNode, ComputedNode};
+    use crate::store::{DefaultStore, TreeStoreWriter};
+
+    #[test]
+    fn test_verify_integrity_detects_corruption() -> Result<()> {
+        let leaf = LeafNode::new([1u8; 32], b"value".to_vec(), 10);
+        let original_hash = leaf.node_hash();
+
+        // Clones share the cached hash, so changing the value leaves a stale hash behind
+        let mut corrupted = leaf.clone();
+        corrupted.value = b"forged".to_vec();
+        let corrupted = Arc::new(corrupted);
+
+        let mut store = DefaultStore::new();
+        store.insert_leaf(corrupted.clone())?;
+        let root = Arc::new(BranchNode::new(corrupted, EMPTY_TREE[1].clone()));
+        store.insert_branch(root.clone())?;
+        store.update_root(root)?;
+
+        let tree = FullTree::new(store);
+        let report = tree.verify_integrity()?;
+        assert_eq!(report.nodes_checked, 2);
+        assert_eq!(report.issues.len(), 2);
+        assert_eq!(report.issues[0].height, 1);
+        assert_eq!(report.issues[0].hash, original_hash);
+        assert!(matches!(
+            report.issues[0].kind,
+            IntegrityIssueKind::HashMismatch { .. }
+        ));
+        assert_eq!(
+            report.issues[1].kind,
+            IntegrityIssueKind::Misplaced {
+                reason: "leaf above the leaf level"
+            }
+        );
+
+        // Traversals refuse the misplaced leaf instead of reporting the key as absent
+        assert!(matches!(
+            tree.get_with_proof([1u8; 32]),
+            Err(MssmtError::CorruptedTree { height: 1, .. })
+        ));
+        assert!(matches!(
+            tree.merkle_proof([1u8; 32]),
+            Err(MssmtError::CorruptedTree { height: 1, .. })
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_health_check_detects_missing_nodes() -> Result<()> {
+        let mut tree = FullTree::new(DefaultStore::new());
+        tree.health_check()?;
+        tree.insert([0x00; 32], b"left".to_vec(), 1)?;
+        tree.insert([0x80; 32], b"right".to_vec(), 2)?;
+        tree.health_check()?;
+
+        let root = tree.root()?;
+        let root = root.as_branch().unwrap();
+        let mut store = tree.into_store();
+        let left = store.branches.remove(&root.left.node_hash()).unwrap();
+        let tree = FullTree::new(store);
+        assert!(matches!(
+            tree.health_check(),
+            Err(MssmtError::NodeNotFound(hash)) if hash == left.node_hash()
+        ));
+
+        // A root record whose sum disagrees with its children is reported as corrupted
+        let mut store = tree.into_store();
+        store.insert_branch(left)?;
+        store.update_root(Arc::new(ComputedNode::new(
+            root.node_hash(),
+            root.node_sum() + 5,
+        )))?;
+        let tree = FullTree::new(store);
+        assert!(matches!(
+            tree.health_check(),
+            Err(MssmtError::CorruptedTree { height: 0, .. })
+        ));
+
+        Ok(())
+    }
+}
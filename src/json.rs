@@ -0,0 +1,216 @@
+//! JSON snapshots of a Merkle-Sum Sparse Merkle Tree.
+//!
+//! A snapshot lists every leaf of the tree with its key, value and sum in hex, together with the
+//! root hash and sum it commits to. Snapshots do not depend on the storage backend, so they can be
+//! used to migrate a tree between stores or to hand it to another implementation. Importing a
+//! snapshot rebuilds the tree and checks that it reproduces the recorded root. Leaves with metadata, see
+//! the `metadata` module, carry it in an optional field restored into the new store. Snapshots record the
+//! version of their format, see the `format` module; those written before it was recorded have version 0.
+//!
+//! This module requires the `json` feature.
+
+use crate::error::{MssmtError, Result};
+use crate::format::{check_version, Format};
+use crate::hash_utils::to_array;
+use crate::metadata::LeafMetadata;
+use crate::node::{collect_leaves, NodeHash, Sum, HASH_SIZE};
+use crate::progress::{insert_writes, Progress, ProgressTracker};
+use crate::store::{TreeStore, TreeStoreReader};
+use crate::tree::FullTree;
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+
+/// The serialized form of a tree.
+#[derive(Serialize, Deserialize)]
+struct JsonSnapshot {
+    #[serde(default)]
+    version: u8,
+    root: String,
+    sum: Sum,
+    leaves: Vec<JsonLeaf>,
+}
+
+/// The serialized form of a single leaf.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct JsonLeaf {
+    pub(crate) key: String,
+    pub(crate) value: String,
+    pub(crate) sum: Sum,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) metadata: Option<LeafMetadata>,
+}
+
+impl<S: TreeStoreReader> FullTree<S> {
+    /// Writes a JSON snapshot of the tree.
+    ///
+    /// The snapshot contains the root hash and sum and all leaves in key order, with keys and values
+    /// encoded as hex, and the metadata of the leaves that have any.
+    ///
+    /// # Arguments
+    ///
+    /// - `writer`: The destination of the JSON document.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use mssmt::{DefaultStore, FullTree, Node};
+    ///
+    /// let mut tree = FullTree::new(DefaultStore::new());
+    /// tree.insert([1u8; 32], b"one".to_vec(), 1).unwrap();
+    ///
+    /// let mut json = Vec::new();
+    /// tree.export_json(&mut json).unwrap();
+    ///
+    /// let imported = FullTree::import_json(json.as_slice(), DefaultStore::new()).unwrap();
+    /// assert_eq!(
+    ///     imported.root().unwrap().node_hash(),
+    ///     tree.root().unwrap().node_hash()
+    /// );
+    /// ```
+    pub fn export_json<W: Write>(&self, writer: W) -> Result<()> {
+        let root = self.root()?;
+        let mut leaves = Vec::new();
+        collect_leaves(self.store(), &root, 0, &mut leaves)?;
+
+        let snapshot = JsonSnapshot {
+            version: Format::JsonSnapshot.current_version(),
+            root: root.node_hash().to_string(),
+            sum: root.node_sum(),
+            leaves: leaves
+                .into_iter()
+                .map(|leaf| {
+                    Ok(JsonLeaf {
+                        key: hex::encode(leaf.key),
+                        value: hex::encode(&leaf.value),
+                        sum: leaf.sum,
+                        metadata: self.store().get_leaf_metadata(&leaf.key)?,
+                    })
+                })
+                .collect::<Result<_>>()?,
+        };
+
+        serde_json::to_writer_pretty(writer, &snapshot)
+            .map_err(|err| MssmtError::InvalidEncoding(err.to_string()))
+    }
+}
+
+impl<S: TreeStore> FullTree<S> {
+    /// Rebuilds a tree from a JSON snapshot produced by `export_json`.
+    ///
+    /// The leaves are inserted into a tree over `store`, which should be empty. The rebuilt root
+    /// must match the root recorded in the snapshot.
+    ///
+    /// # Arguments
+    ///
+    /// - `reader`: The source of the JSON document.
+    /// - `store`: The storage backend of the rebuilt tree.
+    ///
+    /// # Returns
+    ///
+    /// - The rebuilt tree.
+    /// - `MssmtError::InvalidEncoding` if the snapshot is malformed or has an unsupported version.
+    /// - `MssmtError::RootHashMismatch` if the rebuilt root differs from the recorded one.
+    pub fn import_json<R: Read>(reader: R, store: S) -> Result<Self> {
+        Self::import_json_with_progress(reader, store, |_| {})
+    }
+
+    /// Rebuilds a tree from a JSON snapshot, reporting progress after each leaf.
+    ///
+    /// Works like `FullTree::import_json`. The snapshot is parsed before the first report, so the number
+    /// of leaves is known in advance.
+    pub fn import_json_with_progress<R: Read>(
+        reader: R,
+        store: S,
+        progress: impl FnMut(Progress),
+    ) -> Result<Self> {
+        let snapshot: JsonSnapshot = serde_json::from_reader(reader)
+            .map_err(|err| MssmtError::InvalidEncoding(err.to_string()))?;
+        check_version(Format::JsonSnapshot, snapshot.version)?;
+        let expected: NodeHash = snapshot.root.parse()?;
+
+        let total = snapshot.leaves.len() as u64;
+        let mut progress = ProgressTracker::new(progress, Some(total), 0);
+        let mut tree = FullTree::new(store);
+        let mut metadata = Vec::new();
+        for leaf in snapshot.leaves {
+            let key = decode_hash(&leaf.key)?;
+            let value = hex::decode(&leaf.value)
+                .map_err(|err| MssmtError::InvalidEncoding(err.to_string()))?;
+            tree.insert(key, value, leaf.sum)?;
+            metadata.extend(leaf.metadata.map(|metadata| (key, metadata)));
+            progress.advance(1, insert_writes(HASH_SIZE));
+        }
+
+        let actual = tree.root()?.node_hash();
+        if actual != expected {
+            return Err(MssmtError::RootHashMismatch { expected, actual });
+        }
+        for (key, metadata) in metadata {
+            tree.store_mut().set_leaf_metadata(&key, Some(metadata))?;
+        }
+        Ok(tree)
+    }
+}
+
+/// The version field of a JSON snapshot, ignoring the rest of the document.
+#[derive(Deserialize)]
+struct VersionProbe {
+    #[serde(default)]
+    version: u8,
+}
+
+/// Returns the format version of a JSON snapshot.
+pub(crate) fn snapshot_version(bytes: &[u8]) -> Result<u8> {
+    serde_json::from_slice::<VersionProbe>(bytes)
+        .map(|probe| probe.version)
+        .map_err(|err| MssmtError::InvalidEncoding(err.to_string()))
+}
+
+/// Upgrades an unversioned JSON snapshot to version 1, which records its version.
+pub(crate) fn upgrade_snapshot(bytes: &[u8]) -> Result<Vec<u8>> {
+    let mut snapshot: JsonSnapshot = serde_json::from_slice(bytes)
+        .map_err(|err| MssmtError::InvalidEncoding(err.to_string()))?;
+    snapshot.version = 1;
+    serde_json::to_vec_pretty(&snapshot).map_err(|err| MssmtError::InvalidEncoding(err.to_string()))
+}
+
+/// Decodes a 32-byte key or hash from hex.
+pub(crate) fn decode_hash(hex_str: &str) -> Result<[u8; 32]> {
+    let bytes = hex::decode(hex_str).map_err(|err| MssmtError::InvalidEncoding(err.to_string()))?;
+    if bytes.len() != 32 {
+        return Err(MssmtError::InvalidEncoding(format!(
+            "expected 32 bytes, got {}",
+            bytes.len()
+        )));
+    }
+    Ok(to_array(&bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::DefaultStore;
+
+    #[test]
+    fn test_import_rejects_tampered_snapshot() -> Result<()> {
+        let mut tree = FullTree::new(DefaultStore::new());
+        tree.insert([1u8; 32], b"one".to_vec(), 1)?;
+        tree.insert([2u8; 32], b"two".to_vec(), 2)?;
+
+        let mut json = Vec::new();
+        tree.export_json(&mut json)?;
+
+        let imported = FullTree::import_json(json.as_slice(), DefaultStore::new())?;
+        assert_eq!(imported.get([2u8; 32])?, Some((b"two".to_vec(), 2)));
+        assert_eq!(imported.total_sum()?, 3);
+
+        // Changing a leaf sum must be caught by the root check
+        let tampered = String::from_utf8(json)
+            .unwrap()
+            .replace("\"sum\": 2", "\"sum\": 5");
+        let result = FullTree::import_json(tampered.as_bytes(), DefaultStore::new());
+        assert!(matches!(result, Err(MssmtError::RootHashMismatch { .. })));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,144 @@
+//! Keys of the Merkle-Sum Sparse Merkle Tree.
+//!
+//! The `Key` newtype distinguishes tree keys from the other 32-byte values handled by the crate, such
+//! as node hashes. Tree methods accept any `impl Into<Key>`, so raw `[u8; 32]` arrays keep working.
+//! Trees with shorter or longer keys use `Key<K>` with the key size in bytes.
+
+use crate::error::MssmtError;
+use crate::hash_utils::to_array;
+use crate::node::HASH_SIZE;
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::str::FromStr;
+
+/// A key locating a leaf in the tree, 256 bits long by default.
+///
+/// The bits of the key, most significant bit of the first byte first, select the path from the root
+/// to the leaf. `K` is the key size in bytes.
+///
+/// # Examples
+///
+/// ```rust
+/// use mssmt::{DefaultStore, FullTree, Key};
+///
+/// let key = Key::hash(b"alice");
+/// let mut tree = FullTree::new(DefaultStore::new());
+/// tree.insert(key, b"balance".to_vec(), 100).unwrap();
+///
+/// let parsed: Key = key.to_string().parse().unwrap();
+/// assert_eq!(tree.get(parsed).unwrap(), Some((b"balance".to_vec(), 100)));
+/// ```
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct Key<const K: usize = HASH_SIZE>(pub [u8; K]);
+
+impl<const K: usize> Key<K> {
+    /// Creates a key from its raw bytes.
+    pub fn new(bytes: [u8; K]) -> Self {
+        Key(bytes)
+    }
+
+    /// Returns the inner byte array.
+    pub fn as_bytes(&self) -> &[u8; K] {
+        &self.0
+    }
+}
+
+impl Key {
+    /// Creates a key by hashing arbitrary data with SHA-256.
+    ///
+    /// See the `keys` module for namespaced and HMAC-keyed derivations.
+    pub fn hash(data: impl AsRef<[u8]>) -> Self {
+        Key(to_array(&Sha256::digest(data.as_ref())))
+    }
+}
+
+impl<const K: usize> Default for Key<K> {
+    fn default() -> Self {
+        Key([0u8; K])
+    }
+}
+
+impl<const K: usize> From<[u8; K]> for Key<K> {
+    fn from(bytes: [u8; K]) -> Self {
+        Key(bytes)
+    }
+}
+
+impl<const K: usize> From<&[u8; K]> for Key<K> {
+    fn from(bytes: &[u8; K]) -> Self {
+        Key(*bytes)
+    }
+}
+
+impl<const K: usize> From<Key<K>> for [u8; K] {
+    fn from(key: Key<K>) -> Self {
+        key.0
+    }
+}
+
+impl<const K: usize> AsRef<[u8]> for Key<K> {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl<const K: usize> fmt::Debug for Key<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Key({})", self)
+    }
+}
+
+impl<const K: usize> fmt::Display for Key<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::LowerHex::fmt(self, f)
+    }
+}
+
+impl<const K: usize> fmt::LowerHex for Key<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&hex::encode(self.0))
+    }
+}
+
+/// Parses a key from `2 * K` hex characters, 64 for 32-byte keys.
+impl<const K: usize> FromStr for Key<K> {
+    type Err = MssmtError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bytes = hex::decode(s).map_err(|err| MssmtError::InvalidEncoding(err.to_string()))?;
+        let bytes: [u8; K] = bytes.try_into().map_err(|bytes: Vec<u8>| {
+            MssmtError::InvalidEncoding(format!("expected {} bytes, got {}", K, bytes.len()))
+        })?;
+        Ok(Key(bytes))
+    }
+}
+
+impl<const K: usize> TryFrom<&str> for Key<K> {
+    type Error = MssmtError;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_conversions() {
+        let key = Key::hash(b"key1");
+        assert_eq!(key.0, to_array(&Sha256::digest(b"key1")));
+        assert_eq!(Key::from(key.0), key);
+        assert_eq!(<[u8; 32]>::from(key), key.0);
+
+        assert_eq!(Key::try_from(key.to_string().as_str()).unwrap(), key);
+        assert_eq!(
+            format!("{:?}", Key::new([0xab; 32])),
+            format!("Key({})", "ab".repeat(32))
+        );
+        assert!("zz".parse::<Key>().is_err());
+        assert!("abcd".parse::<Key>().is_err());
+    }
+}
@@ -0,0 +1,227 @@
+//! Conventions for deriving tree keys from application data.
+//!
+//! Keys of a sparse tree should be uniformly distributed and must not collide across the kinds of data
+//! an application stores, so they are derived by hashing. The constructors below fix one convention
+//! per use, instead of each caller hashing with its own:
+//!
+//! - `Key::from_bytes_sha256` hashes bytes with SHA-256, like `Key::hash`.
+//! - `Key::from_bytes_namespaced` and `Key::from_utf8` hash under a namespace, as the tagged hash
+//!   `SHA256(SHA256(tag) || SHA256(tag) || data)` of BIP-340 with the tag `mssmt/key/<namespace>`. Keys
+//!   of different namespaces never collide, even for equal data.
+//! - `Key::from_bytes_hmac` and `Key::from_utf8_hmac` use HMAC-SHA256 under a secret, so that keys
+//!   cannot be derived, or tested for, by anyone without the secret.
+//!
+//! A `KeyHasher` captures one of these conventions, so that code building keys can be handed the
+//! convention instead of hard-coding it.
+
+use crate::hash_utils::to_array;
+use crate::key::Key;
+use sha2::{Digest, Sha256};
+
+/// The prefix of the tags of namespaced keys.
+const NAMESPACE_TAG_PREFIX: &str = "mssmt/key/";
+
+/// The block size of SHA-256, which HMAC pads its secret to.
+const SHA256_BLOCK_SIZE: usize = 64;
+
+impl Key {
+    /// Derives a key as the SHA-256 hash of `bytes`.
+    pub fn from_bytes_sha256(bytes: impl AsRef<[u8]>) -> Self {
+        Key::hash(bytes)
+    }
+
+    /// Derives a key from `bytes` under `namespace`, see the module documentation.
+    pub fn from_bytes_namespaced(namespace: &str, bytes: impl AsRef<[u8]>) -> Self {
+        let tag = Sha256::new()
+            .chain_update(NAMESPACE_TAG_PREFIX)
+            .chain_update(namespace)
+            .finalize();
+        let digest = Sha256::new()
+            .chain_update(tag)
+            .chain_update(tag)
+            .chain_update(bytes)
+            .finalize();
+        Key(to_array(&digest))
+    }
+
+    /// Derives a key from the string `s` under `namespace`, hashing its UTF-8 bytes.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use mssmt::Key;
+    ///
+    /// let account = Key::from_utf8("accounts", "alice");
+    /// assert_eq!(account, Key::from_utf8("accounts", "alice"));
+    /// assert_ne!(account, Key::from_utf8("assets", "alice"));
+    /// assert_ne!(account, Key::from_bytes_sha256("alice"));
+    /// ```
+    pub fn from_utf8(namespace: &str, s: &str) -> Self {
+        Key::from_bytes_namespaced(namespace, s)
+    }
+
+    /// Derives a key as the HMAC-SHA256 of `bytes` under `secret`.
+    pub fn from_bytes_hmac(secret: &[u8], bytes: impl AsRef<[u8]>) -> Self {
+        Key(hmac_sha256(secret, &[bytes.as_ref()]))
+    }
+
+    /// Derives a key from the string `s` under `namespace` with HMAC-SHA256 under `secret`.
+    ///
+    /// The namespace is length-prefixed, so that no two pairs of namespace and string share their input.
+    pub fn from_utf8_hmac(secret: &[u8], namespace: &str, s: &str) -> Self {
+        let length = (namespace.len() as u64).to_be_bytes();
+        Key(hmac_sha256(
+            secret,
+            &[&length, namespace.as_bytes(), s.as_bytes()],
+        ))
+    }
+}
+
+/// A convention deriving tree keys from application data.
+///
+/// # Examples
+///
+/// ```rust
+/// use mssmt::keys::{KeyHasher, NamespacedKeys};
+/// use mssmt::{DefaultStore, FullTree, Key};
+///
+/// fn credit(tree: &mut FullTree<DefaultStore>, keys: &impl KeyHasher, who: &str, amount: u64) {
+///     tree.insert(keys.key(who.as_bytes()), Vec::new(), amount.into()).unwrap();
+/// }
+///
+/// let mut tree = FullTree::new(DefaultStore::new());
+/// credit(&mut tree, &NamespacedKeys::new("accounts"), "alice", 10);
+/// assert!(tree.contains_key(Key::from_utf8("accounts", "alice")).unwrap());
+/// ```
+pub trait KeyHasher {
+    /// Derives the key of `data`.
+    fn key(&self, data: &[u8]) -> Key;
+}
+
+/// Derives keys with `Key::from_bytes_sha256`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Sha256Keys;
+
+impl KeyHasher for Sha256Keys {
+    fn key(&self, data: &[u8]) -> Key {
+        Key::from_bytes_sha256(data)
+    }
+}
+
+/// Derives keys under a namespace with `Key::from_bytes_namespaced`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NamespacedKeys {
+    namespace: String,
+}
+
+impl NamespacedKeys {
+    /// Creates a hasher deriving keys under `namespace`.
+    pub fn new(namespace: impl Into<String>) -> Self {
+        Self {
+            namespace: namespace.into(),
+        }
+    }
+}
+
+impl KeyHasher for NamespacedKeys {
+    fn key(&self, data: &[u8]) -> Key {
+        Key::from_bytes_namespaced(&self.namespace, data)
+    }
+}
+
+/// Derives keys under a secret with `Key::from_bytes_hmac`.
+#[derive(Clone)]
+pub struct HmacKeys {
+    secret: Vec<u8>,
+}
+
+impl HmacKeys {
+    /// Creates a hasher deriving keys under `secret`.
+    pub fn new(secret: impl Into<Vec<u8>>) -> Self {
+        Self {
+            secret: secret.into(),
+        }
+    }
+}
+
+impl KeyHasher for HmacKeys {
+    fn key(&self, data: &[u8]) -> Key {
+        Key::from_bytes_hmac(&self.secret, data)
+    }
+}
+
+/// Computes the HMAC-SHA256 of the concatenation of `parts` under `secret`, as in RFC 2104.
+fn hmac_sha256(secret: &[u8], parts: &[&[u8]]) -> [u8; 32] {
+    let mut block = [0u8; SHA256_BLOCK_SIZE];
+    if secret.len() > SHA256_BLOCK_SIZE {
+        block[..32].copy_from_slice(&Sha256::digest(secret));
+    } else {
+        block[..secret.len()].copy_from_slice(secret);
+    }
+
+    let mut inner = Sha256::new().chain_update(block.map(|byte| byte ^ 0x36));
+    for part in parts {
+        inner.update(part);
+    }
+    let outer = Sha256::new()
+        .chain_update(block.map(|byte| byte ^ 0x5c))
+        .chain_update(inner.finalize())
+        .finalize();
+    to_array(&outer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_derivations() {
+        // RFC 4231, test cases 1 and 6
+        assert_eq!(
+            Key::from_bytes_hmac(&[0x0b; 20], "Hi There").to_string(),
+            "b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7"
+        );
+        assert_eq!(
+            Key::from_bytes_hmac(
+                &[0xaa; 131],
+                "Test Using Larger Than Block-Size Key - Hash Key First"
+            )
+            .to_string(),
+            "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54"
+        );
+
+        assert_eq!(Key::from_bytes_sha256("alice"), Key::hash("alice"));
+        let tag = Sha256::digest("mssmt/key/accounts");
+        let expected = Sha256::new()
+            .chain_update(tag)
+            .chain_update(tag)
+            .chain_update("alice")
+            .finalize();
+        assert_eq!(Key::from_utf8("accounts", "alice").0, to_array(&expected));
+
+        // The namespace and the string cannot trade bytes
+        assert_ne!(
+            Key::from_utf8_hmac(b"secret", "ab", "c"),
+            Key::from_utf8_hmac(b"secret", "a", "bc")
+        );
+        assert_ne!(
+            Key::from_utf8_hmac(b"secret", "a", "b"),
+            Key::from_utf8_hmac(b"other", "a", "b")
+        );
+
+        let hashers: [&dyn KeyHasher; 3] = [
+            &Sha256Keys,
+            &NamespacedKeys::new("accounts"),
+            &HmacKeys::new(b"secret".to_vec()),
+        ];
+        let keys: Vec<Key> = hashers.iter().map(|hasher| hasher.key(b"alice")).collect();
+        assert_eq!(
+            keys,
+            [
+                Key::hash("alice"),
+                Key::from_utf8("accounts", "alice"),
+                Key::from_bytes_hmac(b"secret", "alice")
+            ]
+        );
+    }
+}
@@ -0,0 +1,382 @@
+//! Trees resolving their nodes by hash on every step, for trees larger than memory.
+//!
+//! A `FullTree` keeps the nodes it loads or writes linked to their children: the root handed to the
+//! store after an update references the whole rebuilt path, and over a store keeping nodes in memory
+//! every branch holds its children. A `LazyTree` instead only holds the commitment to its root. Every
+//! lookup, proof and update walks the path of its key by loading each branch from the store by height
+//! and hash, and only keeps the hashes and sums of its children, so the nodes of a path are released
+//! as soon as the operation completes.
+//!
+//! The top levels are shared by every path and loaded by every operation, so the branches of the top
+//! `cached_levels` levels are kept, as hash references to their children. This bounds the memory of
+//! the tree to `2^cached_levels` branches, whatever the number of leaves in the store.
+
+use crate::commitment::RootCommitment;
+use crate::error::{MssmtError, Result};
+use crate::key::Key;
+use crate::node::{
+    tree_levels, BranchNode, ComputedNode, EmptyTreeOf, LeafNode, LeafValue, Node, NodeHash, Sum,
+    HASH_SIZE,
+};
+use crate::path::{Height, TreePath};
+use crate::proof::Proof;
+use crate::store::{TreeStore, TreeStoreReader};
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+/// The number of top levels a `LazyTree` keeps the branches of unless configured otherwise.
+pub const DEFAULT_CACHED_LEVELS: usize = 16;
+
+/// A tree holding only its root commitment and the branches of its top levels.
+///
+/// `K` is the key size in bytes, 32 by default, and `V` the type of the values, `Vec<u8>` by default.
+/// The tree reads and writes the same nodes as a `FullTree` over the same store, so either can take
+/// over the store of the other. Sums of sibling subtrees combine under `OverflowPolicy::Checked`.
+///
+/// # Examples
+///
+/// ```rust
+/// use mssmt::lazy::LazyTree;
+/// use mssmt::{DefaultStore, FullTree, LeafNode, Node};
+///
+/// let mut tree = FullTree::new(DefaultStore::new());
+/// tree.insert([1u8; 32], b"one".to_vec(), 1).unwrap();
+///
+/// let mut lazy = LazyTree::new(tree.into_store()).unwrap().with_cached_levels(4);
+/// lazy.insert([2u8; 32], b"two".to_vec(), 2).unwrap();
+/// assert_eq!(lazy.get([1u8; 32]).unwrap(), Some((b"one".to_vec(), 1)));
+///
+/// let leaf = LeafNode::new([2u8; 32], b"two".to_vec(), 2);
+/// let proof = lazy.merkle_proof([2u8; 32]).unwrap();
+/// assert!(proof.verify_against([2u8; 32], &leaf, &lazy.commitment()));
+///
+/// // The store can be handed back to a full tree
+/// let tree = FullTree::new(lazy.into_store());
+/// assert_eq!(tree.total_sum().unwrap(), 3);
+/// ```
+pub struct LazyTree<S, const K: usize = HASH_SIZE, V = Vec<u8>> {
+    store: S,
+    root: RootCommitment,
+    cached_levels: usize,
+    // Branches of the top levels by hash, with their children as hash references
+    cache: Mutex<HashMap<NodeHash, Arc<BranchNode>>>,
+    value: PhantomData<fn() -> V>,
+}
+
+/// The path of a key: the hashes of the branches along it, the hashes and sums of their siblings, both
+/// root first, and the leaf at the key, if any.
+struct LazyPath<const K: usize, V> {
+    branches: Vec<NodeHash>,
+    siblings: Vec<(NodeHash, Sum)>,
+    leaf: Option<Arc<LeafNode<K, V>>>,
+}
+
+impl<S: TreeStoreReader<K, V>, const K: usize, V: LeafValue> LazyTree<S, K, V> {
+    /// Creates a tree over the root stored in `store`, keeping the branches of the top
+    /// `DEFAULT_CACHED_LEVELS` levels.
+    pub fn new(store: S) -> Result<Self> {
+        let root = RootCommitment::of(store.root_node()?.as_ref());
+        Ok(Self {
+            store,
+            root,
+            cached_levels: DEFAULT_CACHED_LEVELS,
+            cache: Mutex::new(HashMap::new()),
+            value: PhantomData,
+        })
+    }
+
+    /// Keeps the branches of the top `cached_levels` levels, and none if it is 0.
+    pub fn with_cached_levels(mut self, cached_levels: usize) -> Self {
+        self.cached_levels = cached_levels;
+        self.cache.get_mut().clear();
+        self
+    }
+
+    /// Returns the commitment to the current root.
+    pub fn commitment(&self) -> RootCommitment {
+        self.root
+    }
+
+    /// Returns the number of branches currently cached.
+    pub fn cached_branches(&self) -> usize {
+        self.cache.lock().len()
+    }
+
+    /// Returns a reference to the store.
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// Consumes the tree and returns its store.
+    pub fn into_store(self) -> S {
+        self.store
+    }
+
+    /// Retrieves the value and sum associated with a key.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(Some((value, sum)))` if the key exists, with the stored value and sum.
+    /// - `Ok(None)` if the key does not exist.
+    /// - `MssmtError::NodeNotFound` if a node on the path of the key is missing from the store.
+    pub fn get(&self, key: impl Into<Key<K>>) -> Result<Option<(V, Sum)>> {
+        let path = self.path(&key.into().0)?;
+        Ok(path.leaf.map(|leaf| (leaf.value.clone(), leaf.sum)))
+    }
+
+    /// Generates a Merkle proof for a key against the current root.
+    pub fn merkle_proof(&self, key: impl Into<Key<K>>) -> Result<Proof<K>> {
+        let path = self.path(&key.into().0)?;
+        let nodes = path
+            .siblings
+            .into_iter()
+            .enumerate()
+            .map(|(height, sibling)| child_ref::<K>(height + 1, sibling))
+            .collect();
+        Ok(Proof::new(nodes))
+    }
+
+    /// Walks the path of `key` from the root, loading each branch by hash.
+    fn path(&self, key: &[u8; K]) -> Result<LazyPath<K, V>> {
+        let tree_path = TreePath::from(*key);
+        let mut branches = Vec::with_capacity(tree_levels(K));
+        let mut siblings = Vec::with_capacity(tree_levels(K));
+        let mut node = (self.root.hash, self.root.sum);
+        for height in 0..tree_levels(K) {
+            let (left, right) = self.children(height, &node.0)?;
+            branches.push(node.0);
+            let (child, sibling) = if tree_path.is_left(Height::at(height)) {
+                (left, right)
+            } else {
+                (right, left)
+            };
+            siblings.push(sibling);
+            node = child;
+        }
+
+        let leaf = if EmptyTreeOf::<K>::is_empty_at(tree_levels(K), &node.0) {
+            None
+        } else {
+            let leaf = self
+                .store
+                .get_leaf(&node.0)?
+                .ok_or(MssmtError::NodeNotFound(node.0))?;
+            if leaf.node_hash() != node.0 {
+                return Err(MssmtError::CorruptedTree {
+                    height: tree_levels(K),
+                    hash: node.0,
+                    reason: "stored node does not match its hash",
+                });
+            }
+            Some(leaf).filter(|leaf| leaf.key == *key)
+        };
+        Ok(LazyPath {
+            branches,
+            siblings,
+            leaf,
+        })
+    }
+
+    /// Returns the hashes and sums of the children of the branch at `height` with hash `hash`.
+    fn children(
+        &self,
+        height: usize,
+        hash: &NodeHash,
+    ) -> Result<((NodeHash, Sum), (NodeHash, Sum))> {
+        if EmptyTreeOf::<K>::is_empty_at(height, hash) {
+            let empty = (
+                EmptyTreeOf::<K>::hash_at(height + 1),
+                EmptyTreeOf::<K>::sum_at(height + 1),
+            );
+            return Ok((empty, empty));
+        }
+
+        let cached = height < self.cached_levels;
+        let branch = match cached
+            .then(|| self.cache.lock().get(hash).cloned())
+            .flatten()
+        {
+            Some(branch) => branch,
+            None => {
+                let branch = self
+                    .store
+                    .get_branch_at(height, hash)?
+                    .ok_or(MssmtError::NodeNotFound(*hash))?;
+                if branch.node_hash() != *hash {
+                    return Err(MssmtError::CorruptedTree {
+                        height,
+                        hash: *hash,
+                        reason: "stored node does not match its hash",
+                    });
+                }
+                // Only the references to the children are kept, never the children themselves
+                let branch = Arc::new(branch.to_shallow());
+                if cached {
+                    self.cache.lock().insert(*hash, branch.clone());
+                }
+                branch
+            }
+        };
+        Ok((
+            (branch.left.node_hash(), branch.left.node_sum()),
+            (branch.right.node_hash(), branch.right.node_sum()),
+        ))
+    }
+}
+
+impl<S: TreeStore<K, V>, const K: usize, V: LeafValue> LazyTree<S, K, V> {
+    /// Inserts a key-value-sum entry into the tree.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(Some((value, sum)))` with the previous value and sum if the key was overwritten.
+    /// - `Ok(None)` if the key was not present.
+    /// - `MssmtError::SumOverflow` if the new sums overflow, in which case the tree is unchanged.
+    pub fn insert(
+        &mut self,
+        key: impl Into<Key<K>>,
+        value: impl Into<V>,
+        sum: Sum,
+    ) -> Result<Option<(V, Sum)>> {
+        let key = key.into().0;
+        let leaf = Arc::new(LeafNode::new(key, value.into(), sum));
+        let previous = self.update(&key, Some(leaf))?;
+        Ok(previous.map(|leaf| (leaf.value.clone(), leaf.sum)))
+    }
+
+    /// Deletes a key from the tree, returning the removed leaf if the key was present.
+    pub fn delete(&mut self, key: impl Into<Key<K>>) -> Result<Option<LeafNode<K, V>>> {
+        let key = key.into().0;
+        let removed = self.update(&key, None)?;
+        if let Some(removed) = &removed {
+            self.store.delete_leaf(&removed.node_hash())?;
+        }
+        Ok(removed.map(|leaf| leaf.as_ref().clone()))
+    }
+
+    /// Replaces the leaf at `key` with `leaf`, or with the empty leaf if `None`, and rebuilds its path
+    /// from the hashes and sums of the siblings.
+    fn update(
+        &mut self,
+        key: &[u8; K],
+        leaf: Option<Arc<LeafNode<K, V>>>,
+    ) -> Result<Option<Arc<LeafNode<K, V>>>> {
+        let path = self.path(key)?;
+        let tree_path = TreePath::from(*key);
+        let mut node = match &leaf {
+            Some(leaf) => (leaf.node_hash(), leaf.sum),
+            None => (
+                EmptyTreeOf::<K>::hash_at(tree_levels(K)),
+                EmptyTreeOf::<K>::sum_at(tree_levels(K)),
+            ),
+        };
+        let mut branches = Vec::new();
+        let mut root: Arc<dyn Node> = EmptyTreeOf::<K>::node_at(0);
+        for height in (0..tree_levels(K)).rev() {
+            let sibling = path.siblings[height];
+            let (left, right) = if tree_path.is_left(Height::at(height)) {
+                (node, sibling)
+            } else {
+                (sibling, node)
+            };
+            let empty_child = EmptyTreeOf::<K>::hash_at(height + 1);
+            if left.0 == empty_child && right.0 == empty_child {
+                node = (
+                    EmptyTreeOf::<K>::hash_at(height),
+                    EmptyTreeOf::<K>::sum_at(height),
+                );
+                continue;
+            }
+            left.1.checked_add(right.1).ok_or(MssmtError::SumOverflow)?;
+            let branch = Arc::new(BranchNode::from_child_refs(left, right));
+            node = (branch.node_hash(), branch.node_sum());
+            root = branch.clone();
+            branches.push((height, branch));
+        }
+
+        // The superseded branches of the top levels are never read again
+        {
+            let cache = self.cache.get_mut();
+            for hash in path.branches.iter().take(self.cached_levels) {
+                cache.remove(hash);
+            }
+            for (height, branch) in &branches {
+                if *height < self.cached_levels {
+                    cache.insert(branch.node_hash(), branch.clone());
+                }
+            }
+        }
+        self.store
+            .insert_nodes_at(branches, leaf.into_iter().collect())?;
+        self.store.update_root(root)?;
+        self.root = RootCommitment::new(node.0, node.1);
+        Ok(path.leaf)
+    }
+}
+
+/// Returns the node referenced by `child` at `height`, the precomputed empty subtree if it is empty.
+fn child_ref<const K: usize>(height: usize, child: (NodeHash, Sum)) -> Arc<dyn Node> {
+    if EmptyTreeOf::<K>::is_empty_at(height, &child.0) {
+        EmptyTreeOf::<K>::node_at(height)
+    } else {
+        Arc::new(ComputedNode::new(child.0, child.1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::DefaultStore;
+    use crate::tree::FullTree;
+
+    #[test]
+    fn test_lazy_tree_tracks_a_full_tree() -> Result<()> {
+        let leaves: Vec<LeafNode> = (0..=200u8)
+            .map(|i| LeafNode::new([i; 32], vec![i], i as Sum))
+            .collect();
+        let mut tree = FullTree::from_leaves(DefaultStore::new(), leaves.clone())?;
+        let stored = FullTree::from_leaves(DefaultStore::new(), leaves)?;
+        let mut lazy = LazyTree::new(stored.into_store())?.with_cached_levels(3);
+        assert_eq!(lazy.commitment(), tree.commitment()?);
+
+        for (key, value, sum) in [(7u8, 70u8, 70), (250, 25, 25), (0, 1, 1)] {
+            let previous = lazy.insert([key; 32], vec![value], sum)?;
+            assert_eq!(previous, tree.insert([key; 32], vec![value], sum)?);
+        }
+        for key in [3u8, 250, 251] {
+            let removed = lazy.delete([key; 32])?.map(|leaf| leaf.sum);
+            assert_eq!(removed, tree.delete([key; 32])?.map(|leaf| leaf.sum));
+        }
+        assert_eq!(lazy.commitment(), tree.commitment()?);
+
+        // Only the branches of the top levels stay cached
+        assert!(lazy.cached_branches() > 0 && lazy.cached_branches() < 8);
+        for key in [0u8, 3, 7, 100, 255] {
+            assert_eq!(lazy.get([key; 32])?, tree.get([key; 32])?);
+            let proof = lazy.merkle_proof([key; 32])?;
+            assert_eq!(proof.nodes.len(), tree_levels(32));
+            assert!(proof == tree.merkle_proof([key; 32])?);
+        }
+        assert!(lazy.cached_branches() < 8);
+
+        // The written nodes are those a full tree expects
+        let tree = FullTree::new(lazy.into_store());
+        assert!(tree.verify_integrity()?.is_ok());
+        assert_eq!(tree.get([7u8; 32])?, Some((vec![70], 70)));
+
+        // Deleting every key empties the tree
+        let mut lazy = LazyTree::new(tree.into_store())?;
+        for i in 0..=200u8 {
+            lazy.delete([i; 32])?;
+        }
+        assert_eq!(
+            lazy.commitment(),
+            FullTree::new(DefaultStore::new()).commitment()?
+        );
+        assert_eq!(lazy.cached_branches(), 0);
+
+        Ok(())
+    }
+}
@@ -57,43 +57,245 @@
 //!
 //! ## Modules
 //!
+//! - [`archive`]: Batches of proofs against one root, packed into a single file.
+//! - [`arena`]: Batch inserts reusing their buffers across batches (requires the `arena` feature).
+//! - [`audit`]: Leaf exports proving the total sum of a tree, for proof-of-reserves audits.
+//! - [`backup`]: Incremental backups layered over a base snapshot.
+//! - [`builder`]: A single construction path for configured trees.
+//! - [`cancel`]: Cancellation and resumption of long-running bulk operations.
+//! - [`checkpoint`]: Periodic checkpoints of the roots of a tree, with queries over their timeline.
+//! - [`compat`]: Cross-implementation test vectors (requires the `json` feature).
+//! - [`compare`]: Structural comparison of two trees, down to their first differing subtree.
+//! - [`compact`]: Store compaction removing nodes unreachable from the current root.
+//! - [`config`]: Validation policies applied to inserted leaves.
+//! - [`copy`]: Copying the current version of a tree into another store.
+//! - [`cost`]: Per-operation counts of node reads, writes and hashes, for measuring write amplification.
+//! - [`diff`]: Change sets between two versions of a tree.
+//! - [`error`]: Error types returned by tree, store, and proof operations.
+//! - [`executor`]: Executors running the parallel work of batch operations, on rayon or a single thread.
+//! - [`extremes`]: Leaves with the smallest and largest sums, with an optional index.
+//! - [`forest`]: Many trees keyed by namespace over a single store.
+//! - [`format`]: Versions of the persistent formats and migrations between them.
 //! - [`hash_utils`]: Utility functions for hashing.
+//! - [`history`]: Archived roots of a tree and proofs against past versions.
+//! - [`ingest`]: Streaming NDJSON and CSV ingestion (requires the `json` feature).
+//! - [`integrity`]: Integrity audits recomputing every node of a tree.
+//! - [`key`]: The `Key` newtype identifying leaves.
+//! - [`keys`]: Key derivation conventions: plain, namespaced and HMAC-keyed hashing.
+//! - [`json`]: Portable JSON snapshots of a tree (requires the `json` feature).
+//! - [`lazy`]: Trees resolving their nodes by hash on every step, for trees larger than memory.
+//! - [`list`]: Ordered, paginated listing of keys.
+//! - [`metadata`]: Insertion times, versions and tags kept alongside leaves.
+//! - [`metrics`]: Counters and latencies reported by trees and stores.
+//! - [`nested`]: Child trees committed in parent trees, and proofs across both.
 //! - [`node`]: Node definitions and implementations.
+//! - [`observer`]: Hooks notified when a tree is mutated.
+//! - [`op`]: Tree operations as values and dry runs of them.
+//! - [`path`]: The path from the root to a key, for debugging and explorers, and the `Height` and `TreePath` types of path arithmetic.
+//! - [`parallel`]: Batch inserts updating disjoint subtrees in parallel.
+//! - [`poseidon`]: Poseidon commitments and proofs for SNARK circuits (requires the `poseidon` feature).
+//! - [`prefetch`]: Batch proofs over async stores with concurrent node lookups (requires the `tokio`
+//!   feature).
+//! - [`progress`]: Progress reporting for long-running bulk operations.
 //! - [`proof`]: Merkle proof structures and verification.
+//! - [`proof_cache`]: Caching of generated proofs until the root changes.
+//! - [`proof_source`]: A common interface to local proof generation and remote proof services.
+//! - [`reader`]: Read-only views of a tree for proof-serving components.
+//! - [`replay`]: Binary operation logs and deterministic replay of the trees they produce.
+//! - [`server`]: An HTTP API serving a tree (requires the `server` feature).
+//! - [`shared`]: A thread-safe tree wrapper allowing mutation through shared references.
+//! - [`sparse`]: Partial trees built from proofs, for light clients.
+//! - [`stats`]: Size statistics of a tree.
 //! - [`store`]: Storage interfaces and default implementations.
+//! - [`subtree`]: Verifiable subtrees and range queries by key prefix.
+//! - [`sync`]: Light-client synchronization by streaming the nodes changed between two roots.
+//! - [`tagged`]: Domain-separated commitments with tagged hashes, and versioned hash schemes.
+//! - [`taproot`]: Commitments of tree roots in bitcoin taproot outputs (requires the `bitcoin` feature).
+//! - [`transaction`]: Transactions grouping updates of a tree, whose reads see their own writes.
+//! - [`transition`]: Proofs that one root follows from another by a single leaf update.
 //! - [`tree`]: The main MS-SMT tree implementation.
+//! - [`truncated`]: Trees placing keys by a prefix, for fewer levels and smaller proofs.
+//! - [`versions`]: Several live versions of a tree over one store, with version-aware compaction.
+//! - [`visualize`]: Graphviz and text renderings of a tree for debugging.
+//! - [`walk`]: Depth-first traversal of the nodes of a tree, with pruning.
+//! - [`witness`]: Proofs laid out as SNARK circuit witnesses.
+//! - [`workload`]: Deterministic synthetic trees and operation mixes for comparing stores.
 //!
 //! ## Crate Exports
 //!
 //! The most important types and traits are re-exported at the crate root for convenience:
 //!
 //! - [`FullTree`]: The main tree structure.
+//! - [`SharedTree`]: A tree that can be shared between threads.
 //! - [`DefaultStore`]: The default in-memory storage backend.
 //! - [`LeafNode`], [`BranchNode`]: Node types in the tree.
+//! - [`Key`]: A tree key.
 //! - [`Proof`]: Merkle proof structure.
+//! - [`MssmtError`]: The crate error type.
 //!
 //! ## License
 //!
 //! This project is licensed under the MIT License.
 //!
+//! [`archive`]: crate::archive
+//! [`arena`]: crate::arena
+//! [`audit`]: crate::audit
+//! [`backup`]: crate::backup
+//! [`builder`]: crate::builder
+//! [`cancel`]: crate::cancel
+//! [`checkpoint`]: crate::checkpoint
+//! [`compact`]: crate::compact
+//! [`compare`]: crate::compare
+//! [`compat`]: crate::compat
+//! [`config`]: crate::config
+//! [`copy`]: crate::copy
+//! [`cost`]: crate::cost
+//! [`diff`]: crate::diff
+//! [`error`]: crate::error
+//! [`executor`]: crate::executor
+//! [`extremes`]: crate::extremes
+//! [`forest`]: crate::forest
+//! [`format`]: crate::format
 //! [`hash_utils`]: crate::hash_utils
+//! [`history`]: crate::history
+//! [`ingest`]: crate::ingest
+//! [`integrity`]: crate::integrity
+//! [`json`]: crate::json
+//! [`key`]: crate::key
+//! [`keys`]: crate::keys
+//! [`lazy`]: crate::lazy
+//! [`list`]: crate::list
+//! [`metadata`]: crate::metadata
+//! [`metrics`]: crate::metrics
+//! [`nested`]: crate::nested
 //! [`node`]: crate::node
+//! [`observer`]: crate::observer
+//! [`op`]: crate::op
+//! [`parallel`]: crate::parallel
+//! [`path`]: crate::path
+//! [`poseidon`]: crate::poseidon
+//! [`prefetch`]: crate::prefetch
+//! [`progress`]: crate::progress
 //! [`proof`]: crate::proof
+//! [`proof_cache`]: crate::proof_cache
+//! [`proof_source`]: crate::proof_source
+//! [`reader`]: crate::reader
+//! [`replay`]: crate::replay
+//! [`server`]: crate::server
+//! [`shared`]: crate::shared
+//! [`sparse`]: crate::sparse
+//! [`stats`]: crate::stats
 //! [`store`]: crate::store
+//! [`subtree`]: crate::subtree
+//! [`sync`]: crate::sync
+//! [`tagged`]: crate::tagged
+//! [`taproot`]: crate::taproot
+//! [`transaction`]: crate::transaction
+//! [`transition`]: crate::transition
 //! [`tree`]: crate::tree
+//! [`truncated`]: crate::truncated
+//! [`versions`]: crate::versions
+//! [`visualize`]: crate::visualize
+//! [`walk`]: crate::walk
+//! [`witness`]: crate::witness
+//! [`workload`]: crate::workload
 //! [`FullTree`]: crate::tree::FullTree
+//! [`SharedTree`]: crate::shared::SharedTree
 //! [`DefaultStore`]: crate::store::DefaultStore
 //! [`LeafNode`]: crate::node::LeafNode
 //! [`BranchNode`]: crate::node::BranchNode
+//! [`Key`]: crate::key::Key
 //! [`Proof`]: crate::proof::Proof
+//! [`MssmtError`]: crate::error::MssmtError
 
+#[cfg(all(feature = "grpc", feature = "u128"))]
+compile_error!("the `grpc` feature carries sums as `uint64` and cannot be combined with `u128`");
+
+#[macro_use]
+mod trace;
+
+pub mod archive;
+#[cfg(feature = "arena")]
+pub mod arena;
+pub mod audit;
+pub mod backup;
+pub mod builder;
+pub mod cancel;
+pub mod checkpoint;
+pub mod commitment;
+pub mod compact;
+pub mod compare;
+#[cfg(feature = "json")]
+pub mod compat;
+pub mod config;
+pub mod copy;
+pub mod cost;
+pub mod diff;
+pub mod error;
+pub mod executor;
+pub mod extremes;
+pub mod forest;
+pub mod format;
 pub mod hash_utils;
+pub mod history;
+#[cfg(feature = "json")]
+pub mod ingest;
+pub mod integrity;
+#[cfg(feature = "json")]
+pub mod json;
+pub mod key;
+pub mod keys;
+pub mod lazy;
+pub mod list;
+pub mod metadata;
+pub mod metrics;
+pub mod nested;
 pub mod node;
+pub mod observer;
+pub mod op;
+pub mod parallel;
+pub mod path;
+#[cfg(feature = "poseidon")]
+pub mod poseidon;
+#[cfg(feature = "tokio")]
+pub mod prefetch;
+pub mod progress;
 pub mod proof;
+pub mod proof_cache;
+pub mod proof_source;
+pub mod reader;
+pub mod replay;
+#[cfg(feature = "server")]
+pub mod server;
+pub mod shared;
+pub mod sparse;
+pub mod stats;
 pub mod store;
+pub mod subtree;
+pub mod sync;
+pub mod tagged;
+#[cfg(feature = "bitcoin")]
+pub mod taproot;
+pub mod transaction;
+pub mod transition;
 pub mod tree;
+pub mod truncated;
+pub mod versions;
+pub mod visualize;
+pub mod walk;
+pub mod witness;
+pub mod workload;
 
-pub use crate::node::{BranchNode, LeafNode, Node, NodeHash};
-pub use crate::proof::Proof;
-pub use crate::store::{DefaultStore, TreeStore};
+pub use crate::commitment::RootCommitment;
+pub use crate::error::MssmtError;
+pub use crate::key::Key;
+pub use crate::node::{
+    BranchNode, CompactedLeafNode, EmptyTree, LeafNode, LeafValue, Node, NodeHash, Sum,
+};
+pub use crate::op::Op;
+pub use crate::path::{Height, TreePath};
+pub use crate::proof::{CompressedProof, Proof};
+pub use crate::shared::SharedTree;
+pub use crate::store::{BoxedStore, DefaultStore, TreeStore, TreeStoreReader, TreeStoreWriter};
+pub use crate::tagged::HashScheme;
 pub use crate::tree::FullTree;
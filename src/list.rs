@@ -0,0 +1,291 @@
+//! Ordered listing of the keys of a Merkle-Sum Sparse Merkle Tree.
+//!
+//! Leaves are laid out by key bits, most significant first, so a left-to-right walk of the tree visits
+//! keys in lexicographic order. `FullTree::keys_page` returns one page of that walk at a time, resuming
+//! after a cursor key: subtrees entirely before the cursor are skipped without being loaded, so listing
+//! a page costs a path descent plus the leaves of the page, however large the tree.
+//!
+//! `FullTree::keys` and `FullTree::values` stream the whole walk instead. They load one path at a time,
+//! so they hold no more than the height of the tree in memory, and `keys` never clones a value.
+
+use crate::config::TreeConfig;
+use crate::error::Result;
+use crate::key::Key;
+use crate::node::{
+    bit_index, tree_levels, EmptyTree, EmptyTreeOf, LeafNode, LeafValue, Node, NodeView, Sum,
+    HASH_SIZE, MAX_TREE_LEVELS,
+};
+use crate::store::{resolve_node, TreeStoreReader};
+use crate::tree::FullTree;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+impl<S: TreeStoreReader<K, V>, const K: usize, V: LeafValue> FullTree<S, K, V> {
+    /// Returns an iterator over the keys of the tree, in lexicographic order.
+    ///
+    /// Nodes are loaded as the iterator advances, and an error loading one is yielded once and ends the
+    /// iteration.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use mssmt::{DefaultStore, FullTree, Key};
+    ///
+    /// let mut tree = FullTree::new(DefaultStore::new());
+    /// tree.insert([2u8; 32], b"two".to_vec(), 2).unwrap();
+    /// tree.insert([1u8; 32], b"one".to_vec(), 1).unwrap();
+    ///
+    /// let keys: Vec<Key> = tree.keys().collect::<Result<_, _>>().unwrap();
+    /// assert_eq!(keys, vec![Key::new([1u8; 32]), Key::new([2u8; 32])]);
+    /// ```
+    pub fn keys(&self) -> Keys<'_, S, K, V> {
+        Keys {
+            leaves: LeafStream::new(self),
+        }
+    }
+
+    /// Returns an iterator over the values and sums of the tree, in the lexicographic order of their
+    /// keys.
+    ///
+    /// Nodes are loaded as the iterator advances, and an error loading one is yielded once and ends the
+    /// iteration.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use mssmt::{DefaultStore, FullTree, Sum};
+    ///
+    /// let mut tree = FullTree::new(DefaultStore::new());
+    /// tree.insert([2u8; 32], b"two".to_vec(), 2).unwrap();
+    /// tree.insert([1u8; 32], b"one".to_vec(), 1).unwrap();
+    ///
+    /// let total: Sum = tree.values().map(|entry| entry.unwrap().1).sum();
+    /// assert_eq!(total, 3);
+    /// ```
+    pub fn values(&self) -> Values<'_, S, K, V> {
+        Values {
+            leaves: LeafStream::new(self),
+        }
+    }
+}
+
+/// An iterator over the keys of a tree, see `FullTree::keys`.
+pub struct Keys<'a, S, const K: usize = HASH_SIZE, V = Vec<u8>> {
+    leaves: LeafStream<'a, S, K, V>,
+}
+
+impl<S: TreeStoreReader<K, V>, const K: usize, V: LeafValue> Iterator for Keys<'_, S, K, V> {
+    type Item = Result<Key<K>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.leaves.next_leaf(|leaf| Key(leaf.key))
+    }
+}
+
+/// An iterator over the values and sums of a tree, see `FullTree::values`.
+pub struct Values<'a, S, const K: usize = HASH_SIZE, V = Vec<u8>> {
+    leaves: LeafStream<'a, S, K, V>,
+}
+
+impl<S: TreeStoreReader<K, V>, const K: usize, V: LeafValue> Iterator for Values<'_, S, K, V> {
+    type Item = Result<(V, Sum)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.leaves.next_leaf(|leaf| (leaf.value.clone(), leaf.sum))
+    }
+}
+
+/// A depth-first walk yielding the leaves of a tree in key order, skipping those that read as absent.
+struct LeafStream<'a, S, const K: usize, V> {
+    store: &'a S,
+    config: &'a TreeConfig<K, V>,
+    // The subtrees still to walk with their heights, the next one last
+    pending: Vec<(Arc<dyn Node>, usize)>,
+    started: bool,
+    values: PhantomData<V>,
+}
+
+impl<'a, S: TreeStoreReader<K, V>, const K: usize, V: LeafValue> LeafStream<'a, S, K, V> {
+    fn new(tree: &'a FullTree<S, K, V>) -> Self {
+        Self {
+            store: tree.store(),
+            config: tree.config(),
+            pending: Vec::new(),
+            started: false,
+            values: PhantomData,
+        }
+    }
+
+    /// Walks to the next leaf and maps it with `f`.
+    fn next_leaf<T>(&mut self, f: impl FnOnce(&LeafNode<K, V>) -> T) -> Option<Result<T>> {
+        if !self.started {
+            self.started = true;
+            match self.store.root_node() {
+                Ok(root) => self.pending.push((root, 0)),
+                Err(err) => return Some(Err(err)),
+            }
+        }
+        while let Some((node, height)) = self.pending.pop() {
+            if EmptyTreeOf::<K>::is_empty_at(height, &node.node_hash()) {
+                continue;
+            }
+            let node = match resolve_node(self.store, &node, height) {
+                Ok(node) => node,
+                Err(err) => {
+                    self.pending.clear();
+                    return Some(Err(err));
+                }
+            };
+            if height == tree_levels(K) {
+                match node.as_leaf::<K, V>() {
+                    Some(leaf) if !self.config.reads_as_absent(leaf) => return Some(Ok(f(leaf))),
+                    _ => {}
+                }
+            } else if let Some(branch) = node.as_branch() {
+                self.pending.push((branch.right.clone(), height + 1));
+                self.pending.push((branch.left.clone(), height + 1));
+            }
+        }
+        None
+    }
+}
+
+impl<S: TreeStoreReader> FullTree<S> {
+    /// Returns up to `limit` keys in lexicographic order, starting after `start_after`.
+    ///
+    /// The cursor does not need to be present in the tree. Pass the last key of a page as the cursor of
+    /// the next one; a page shorter than `limit` is the last.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use mssmt::{DefaultStore, FullTree, Key};
+    ///
+    /// let mut tree = FullTree::new(DefaultStore::new());
+    /// for i in 1..=5u8 {
+    ///     tree.insert([i; 32], vec![i], i.into()).unwrap();
+    /// }
+    ///
+    /// let page = tree.keys_page(None, 2).unwrap();
+    /// assert_eq!(page, vec![Key::new([1; 32]), Key::new([2; 32])]);
+    ///
+    /// let page = tree.keys_page(Some(page[1].0), 2).unwrap();
+    /// assert_eq!(page, vec![Key::new([3; 32]), Key::new([4; 32])]);
+    /// ```
+    pub fn keys_page(&self, start_after: Option<[u8; 32]>, limit: usize) -> Result<Vec<Key>> {
+        let mut keys = Vec::with_capacity(limit.min(1024));
+        if limit > 0 {
+            self.collect_page(self.root()?, 0, start_after.as_ref(), limit, &mut keys)?;
+        }
+        Ok(keys)
+    }
+
+    /// Appends the keys of the subtree after `cursor` to `keys`, until `limit` keys are collected.
+    ///
+    /// `cursor` is only set while the walk follows the path of the cursor key; subtrees to its right are
+    /// walked without it, and subtrees to its left are skipped.
+    fn collect_page(
+        &self,
+        node: Arc<dyn Node>,
+        height: usize,
+        cursor: Option<&[u8; 32]>,
+        limit: usize,
+        keys: &mut Vec<Key>,
+    ) -> Result<()> {
+        if keys.len() == limit || EmptyTree::is_empty_at(height, &node.node_hash()) {
+            return Ok(());
+        }
+
+        let node = resolve_node(self.store(), &node, height)?;
+        if height == MAX_TREE_LEVELS {
+            let leaf: &LeafNode = match node.as_view() {
+                NodeView::Leaf(leaf) => leaf,
+                NodeView::Compacted(compacted) => &compacted.leaf,
+                _ => return Ok(()),
+            };
+            if !leaf.is_empty() && cursor.is_none_or(|cursor| leaf.key > *cursor) {
+                keys.push(Key(leaf.key));
+            }
+            return Ok(());
+        }
+
+        let Some(branch) = node.as_branch() else {
+            return Ok(());
+        };
+        match cursor {
+            Some(cursor) if bit_index(height, cursor) == 1 => {
+                self.collect_page(branch.right.clone(), height + 1, Some(cursor), limit, keys)
+            }
+            _ => {
+                self.collect_page(branch.left.clone(), height + 1, cursor, limit, keys)?;
+                self.collect_page(branch.right.clone(), height + 1, None, limit, keys)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::DefaultStore;
+
+    #[test]
+    fn test_keys_page() -> Result<()> {
+        let mut tree = FullTree::new(DefaultStore::new());
+        let mut all: Vec<Key> = (0..50u8).map(|i| Key::hash([i])).collect();
+        for key in &all {
+            tree.insert(*key, vec![1], 1)?;
+        }
+        all.sort();
+
+        // Paging through the tree lists every key once, in order
+        let mut listed = Vec::new();
+        let mut cursor = None;
+        loop {
+            let page = tree.keys_page(cursor, 7)?;
+            listed.extend_from_slice(&page);
+            if page.len() < 7 {
+                break;
+            }
+            cursor = page.last().map(|key| key.0);
+        }
+        assert_eq!(listed, all);
+
+        // A cursor absent from the tree resumes at the next key
+        let mut absent = all[21].0;
+        absent[31] = 0;
+        assert!(absent > all[20].0 && absent < all[21].0);
+        assert_eq!(tree.keys_page(Some(absent), 1)?, vec![all[21]]);
+        assert_eq!(tree.keys_page(Some([0xff; 32]), 10)?, Vec::new());
+        assert_eq!(tree.keys_page(None, 0)?, Vec::new());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_keys_and_values_stream_in_order() -> Result<()> {
+        let mut tree = FullTree::new(DefaultStore::new());
+        assert_eq!(tree.keys().count(), 0);
+
+        let mut all: Vec<Key> = (0..50u8).map(|i| Key::hash([i])).collect();
+        for (i, key) in all.iter().enumerate() {
+            tree.insert(*key, key.0[..4].to_vec(), i as Sum)?;
+        }
+        tree.delete(all.pop().unwrap())?;
+        all.sort();
+
+        let keys = tree.keys().collect::<Result<Vec<_>>>()?;
+        assert_eq!(keys, all);
+        let values = tree.values().collect::<Result<Vec<_>>>()?;
+        let expected: Vec<_> = all
+            .iter()
+            .map(|key| tree.get(*key).map(Option::unwrap))
+            .collect::<Result<_>>()?;
+        assert_eq!(values, expected);
+
+        // Iterators stop early without walking the rest of the tree
+        assert_eq!(tree.keys().nth(3).transpose()?, Some(all[3]));
+
+        Ok(())
+    }
+}
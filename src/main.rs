@@ -0,0 +1,413 @@
+//! Command line interface for the Merkle-Sum Sparse Merkle Tree.
+//!
+//! The tree is persisted in a plain text database file holding one leaf per line as
+//! `<key hex> <value hex> <sum>`. Every invocation rebuilds the tree from that file, applies the
+//! requested command, and writes the file back if the tree changed. Keys, values, hashes and proofs
+//! are read and printed as hex, so the binary can be driven from scripts.
+//!
+//! `mssmt shell` loads the tree once and reads the same commands from standard input, one per line,
+//! which suits experimentation and debugging sessions. With `--memory` the session starts from an
+//! empty tree and never touches the database file.
+
+use clap::{Parser, Subcommand, ValueEnum};
+use mssmt::ingest::IngestFormat;
+use mssmt::node::{Sum, EMPTY_LEAF_NODE};
+use mssmt::{DefaultStore, FullTree, Key, LeafNode, NodeHash, Proof, RootCommitment};
+use std::error::Error;
+use std::fs;
+use std::io::{self, BufRead, BufReader, BufWriter, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
+
+#[derive(Parser)]
+#[command(name = "mssmt", version, about = "Merkle-Sum Sparse Merkle Tree tool")]
+struct Cli {
+    /// Path to the tree database file. It is created on the first write.
+    #[arg(long, global = true, default_value = "mssmt.db")]
+    db: PathBuf,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Inserts or updates a key
+    Insert {
+        /// The 32-byte key as hex
+        key: Key,
+        /// The value as hex
+        value: String,
+        /// The sum associated with the key
+        sum: Sum,
+    },
+    /// Prints the value and sum stored for a key
+    Get {
+        /// The 32-byte key as hex
+        key: Key,
+    },
+    /// Deletes a key
+    Delete {
+        /// The 32-byte key as hex
+        key: Key,
+    },
+    /// Prints the compressed Merkle proof for a key as hex
+    Prove {
+        /// The 32-byte key as hex
+        key: Key,
+    },
+    /// Verifies a compressed proof for a key
+    ///
+    /// Without `--value`, the proof is checked as a non-inclusion proof.
+    Verify {
+        /// The 32-byte key as hex
+        key: Key,
+        /// The encoded compressed proof as hex
+        proof: String,
+        /// The value as hex
+        #[arg(long, requires = "sum")]
+        value: Option<String>,
+        /// The sum associated with the key
+        #[arg(long, requires = "value")]
+        sum: Option<Sum>,
+        /// The root hash as hex, defaulting to the root of the database
+        #[arg(long)]
+        root: Option<String>,
+    },
+    /// Verifies a compressed proof against a published root hash and sum, without a database
+    ///
+    /// The leaf sum is the root sum less the sums of the proof siblings, and is printed once both the
+    /// hash and the sum of the root are checked. An empty value file with a leaf sum of 0 checks a
+    /// non-inclusion proof.
+    VerifyProof {
+        /// The published root hash as hex
+        #[arg(long)]
+        root: NodeHash,
+        /// The published root sum
+        #[arg(long)]
+        sum: Sum,
+        /// The 32-byte key as hex
+        #[arg(long)]
+        key: Key,
+        /// A file holding the raw bytes of the value
+        #[arg(long)]
+        value_file: PathBuf,
+        /// The encoded compressed proof as hex
+        #[arg(long)]
+        proof: String,
+    },
+    /// Prints the root hash and total sum
+    Root,
+    /// Prints the number of leaves and branches and the depth of the leaves
+    Stats,
+    /// Prints every leaf as `<key hex> <value hex> <sum>`, in key order
+    Dump,
+    /// Upgrades a store snapshot, log store or JSON snapshot file to the current format version
+    Migrate {
+        /// The file to upgrade in place
+        file: PathBuf,
+    },
+    /// Bulk-loads records from a newline-delimited JSON or CSV file
+    Import {
+        /// The file to read records from
+        file: PathBuf,
+        /// The record format
+        #[arg(long, value_enum, default_value_t = Format::Ndjson)]
+        format: Format,
+    },
+    /// Runs commands read from standard input, one per line, against a single tree
+    ///
+    /// Empty lines and lines starting with `#` are skipped, and `quit` or `exit` ends the session.
+    Shell {
+        /// Starts from an empty tree and leaves the database file untouched
+        #[arg(long)]
+        memory: bool,
+    },
+}
+
+/// A command line read by `mssmt shell`.
+#[derive(Parser)]
+#[command(no_binary_name = true, disable_version_flag = true)]
+struct ShellLine {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    /// One `{"key": ..., "value": ..., "sum": ...}` object per line
+    Ndjson,
+    /// One `key,value,sum` record per line
+    Csv,
+}
+
+impl From<Format> for IngestFormat {
+    fn from(format: Format) -> Self {
+        match format {
+            Format::Ndjson => IngestFormat::Ndjson,
+            Format::Csv => IngestFormat::Csv,
+        }
+    }
+}
+
+/// The number of records between progress messages during an import.
+const PROGRESS_INTERVAL: u64 = 10_000;
+
+fn main() -> ExitCode {
+    match run(Cli::parse()) {
+        Ok(code) => code,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(cli: Cli) -> Result<ExitCode> {
+    match cli.command {
+        Command::Shell { memory } => shell((!memory).then_some(cli.db.as_path())),
+        command @ Command::VerifyProof { .. } => {
+            execute(&mut FullTree::new(DefaultStore::new()), None, command)
+        }
+        command => execute(&mut load(&cli.db)?, Some(&cli.db), command),
+    }
+}
+
+/// Reads commands from standard input and runs them against one tree until the input ends.
+///
+/// Every change is written back to `db`, unless the session is in memory. A failing command is
+/// reported and the session goes on, but the exit code is then a failure.
+fn shell(db: Option<&Path>) -> Result<ExitCode> {
+    let mut tree = match db {
+        Some(db) => load(db)?,
+        None => FullTree::new(DefaultStore::new()),
+    };
+    let stdin = io::stdin();
+    let interactive = stdin.is_terminal();
+    let mut lines = stdin.lock().lines();
+    let mut code = ExitCode::SUCCESS;
+
+    loop {
+        if interactive {
+            eprint!("mssmt> ");
+            io::stderr().flush()?;
+        }
+        let Some(line) = lines.next() else {
+            break;
+        };
+        let line = line?;
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words[..] {
+            [] => continue,
+            [word, ..] if word.starts_with('#') => continue,
+            ["quit" | "exit"] => break,
+            _ => {}
+        }
+
+        let result = match ShellLine::try_parse_from(words) {
+            Ok(ShellLine { command }) => execute(&mut tree, db, command),
+            Err(err) => {
+                err.print()?;
+                continue;
+            }
+        };
+        match result {
+            Ok(ExitCode::SUCCESS) => {}
+            Ok(failure) => code = failure,
+            Err(err) => {
+                eprintln!("error: {}", err);
+                code = ExitCode::FAILURE;
+            }
+        }
+    }
+
+    Ok(code)
+}
+
+/// Runs a single command against `tree`, writing the tree back to `db` if it changed.
+fn execute(
+    tree: &mut FullTree<DefaultStore>,
+    db: Option<&Path>,
+    command: Command,
+) -> Result<ExitCode> {
+    match command {
+        Command::Insert { key, value, sum } => {
+            tree.insert(key, hex::decode(value)?, sum)?;
+            save(db, tree)?;
+            print_root(tree)?;
+        }
+        Command::Get { key } => match tree.get(key)? {
+            Some((value, sum)) => println!("{} {}", hex::encode(value), sum),
+            None => {
+                eprintln!("key not found");
+                return Ok(ExitCode::FAILURE);
+            }
+        },
+        Command::Delete { key } => {
+            if tree.delete(key)?.is_none() {
+                eprintln!("key not found");
+                return Ok(ExitCode::FAILURE);
+            }
+            save(db, tree)?;
+            print_root(tree)?;
+        }
+        Command::Prove { key } => {
+            let proof = tree.merkle_proof(key)?;
+            println!("{}", proof.to_hex());
+        }
+        Command::Verify {
+            key,
+            proof,
+            value,
+            sum,
+            root,
+        } => {
+            let proof = Proof::from_hex(&proof)?;
+            let leaf = match (value, sum) {
+                (Some(value), Some(sum)) => LeafNode::new(key.0, hex::decode(value)?, sum),
+                _ => EMPTY_LEAF_NODE.clone(),
+            };
+            let root_hash = match root {
+                Some(root) => root.parse::<NodeHash>()?,
+                None => tree.root()?.node_hash(),
+            };
+
+            if let Err(err) = proof.verify_detailed(key, &leaf, root_hash) {
+                println!("invalid: {}", err);
+                return Ok(ExitCode::FAILURE);
+            }
+            println!("valid");
+        }
+        Command::VerifyProof {
+            root,
+            sum,
+            key,
+            value_file,
+            proof,
+        } => {
+            let proof = Proof::from_hex(&proof)?;
+            let value = fs::read(value_file)?;
+            let sibling_sums = proof
+                .nodes
+                .iter()
+                .try_fold(0 as Sum, |total, node| total.checked_add(node.node_sum()));
+            let Some(leaf_sum) = sibling_sums.and_then(|siblings| sum.checked_sub(siblings)) else {
+                println!("invalid: the proof siblings sum to more than the root sum");
+                return Ok(ExitCode::FAILURE);
+            };
+
+            let leaf = LeafNode::new(key.0, value, leaf_sum);
+            if let Err(err) = proof.verify_detailed(key, &leaf, root) {
+                println!("invalid: {}", err);
+                return Ok(ExitCode::FAILURE);
+            }
+            if !proof.verify_against(key, &leaf, &RootCommitment::new(root, sum)) {
+                println!("invalid: root sum mismatch");
+                return Ok(ExitCode::FAILURE);
+            }
+            println!("valid {}", leaf_sum);
+        }
+        Command::Root => print_root(tree)?,
+        Command::Stats => {
+            let stats = tree.stats()?;
+            println!("leaves {}", stats.leaves);
+            println!("branches {}", stats.branches);
+            println!("max_depth {}", stats.max_depth);
+            println!("mean_depth {:.2}", stats.mean_depth);
+            println!("total_sum {}", stats.total_sum);
+        }
+        Command::Dump => write_leaves(io::stdout().lock(), tree)?,
+        Command::Migrate { file } => {
+            let migration = mssmt::format::migrate(file)?;
+            if migration.is_noop() {
+                println!(
+                    "{} already at version {}",
+                    migration.format, migration.to_version
+                );
+            } else {
+                println!(
+                    "{} migrated from version {} to {}",
+                    migration.format, migration.from_version, migration.to_version
+                );
+            }
+        }
+        Command::Import { file, format } => {
+            let reader = BufReader::new(fs::File::open(file)?);
+            let report = tree.ingest(reader, format.into(), |progress| {
+                if progress.items_processed % PROGRESS_INTERVAL == 0 {
+                    eprintln!(
+                        "imported {} records ({:.1?})",
+                        progress.items_processed, progress.elapsed
+                    );
+                }
+            })?;
+            save(db, tree)?;
+            eprintln!("imported {} records", report.records);
+            print_root(tree)?;
+        }
+        Command::Shell { .. } => return Err("already in a shell".into()),
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Rebuilds the tree from the database file, starting empty if the file does not exist.
+fn load(path: &Path) -> Result<FullTree<DefaultStore>> {
+    let mut tree = FullTree::new(DefaultStore::new());
+    if !path.exists() {
+        return Ok(tree);
+    }
+
+    let reader = BufReader::new(fs::File::open(path)?);
+    for (line_number, line) in reader.lines().enumerate() {
+        let line = line?;
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let [key, value, sum] = fields[..] else {
+            return Err(format!("{}:{}: malformed record", path.display(), line_number + 1).into());
+        };
+        tree.insert(key.parse::<Key>()?, hex::decode(value)?, sum.parse()?)?;
+    }
+    Ok(tree)
+}
+
+/// Writes all leaves of the tree to the database file in key order, or does nothing without a file.
+///
+/// The file is written to a temporary path first and then renamed, so an interrupted write never
+/// leaves a truncated database behind.
+fn save(path: Option<&Path>, tree: &FullTree<DefaultStore>) -> Result<()> {
+    let Some(path) = path else {
+        return Ok(());
+    };
+
+    let tmp_path = path.with_extension("tmp");
+    let mut writer = BufWriter::new(fs::File::create(&tmp_path)?);
+    write_leaves(&mut writer, tree)?;
+    writer
+        .into_inner()
+        .map_err(|err| err.into_error())?
+        .sync_all()?;
+    fs::rename(tmp_path, path)?;
+    Ok(())
+}
+
+/// Writes all leaves of the tree as database records, in key order.
+fn write_leaves(mut writer: impl Write, tree: &FullTree<DefaultStore>) -> Result<()> {
+    for leaf in tree.store().leaves_in_key_order() {
+        writeln!(
+            writer,
+            "{} {} {}",
+            hex::encode(leaf.key),
+            hex::encode(&leaf.value),
+            leaf.sum
+        )?;
+    }
+    Ok(())
+}
+
+fn print_root(tree: &FullTree<DefaultStore>) -> Result<()> {
+    let root = tree.root()?;
+    println!("{} {}", root.node_hash(), root.node_sum());
+    Ok(())
+}
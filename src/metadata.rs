@@ -0,0 +1,197 @@
+//! Operational metadata kept alongside the leaves of a tree.
+//!
+//! Tooling often needs to know when an entry was added or last changed, or which process wrote it.
+//! With `FullTree::enable_leaf_metadata`, every insert records a `LeafMetadata` for its key through
+//! `TreeStoreWriter::set_leaf_metadata`, and every delete removes it. The metadata lives in the store
+//! next to the leaf but is never hashed: it is not part of the commitment, proofs do not cover it and
+//! two trees with the same leaves have the same root whatever their metadata.
+//!
+//! Stores without metadata storage drop it, see `TreeStoreWriter::set_leaf_metadata`. `DefaultStore`
+//! keeps it in memory, and JSON snapshots carry it, see `FullTree::export_json`.
+
+use crate::error::Result;
+use crate::key::Key;
+use crate::node::LeafValue;
+use crate::store::{TreeStore, TreeStoreReader};
+use crate::tree::FullTree;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// When and by which version a leaf was written, and an optional tag set by the application.
+///
+/// Times are in seconds since the Unix epoch, and versions are those of `FullTree::root_version` in the
+/// tree that wrote the leaf.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
+pub struct LeafMetadata {
+    /// The time the key was inserted.
+    pub inserted_at: u64,
+    /// The version of the tree after the key was inserted.
+    pub inserted_version: u64,
+    /// The time the leaf of the key was last replaced, or `inserted_at` if it never was.
+    pub updated_at: u64,
+    /// The version of the tree after the leaf of the key was last replaced.
+    pub updated_version: u64,
+    /// A free-form tag, such as the name of the process that inserted the key. Kept across updates.
+    pub tag: Option<String>,
+}
+
+impl<S: TreeStoreReader<K, V>, const K: usize, V: LeafValue> FullTree<S, K, V> {
+    /// Returns the metadata of `key`, or `Ok(None)` if the key has none.
+    ///
+    /// Keys inserted before `FullTree::enable_leaf_metadata` was called, or into a store without
+    /// metadata storage, have no metadata.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use mssmt::{DefaultStore, FullTree};
+    ///
+    /// let mut tree = FullTree::new(DefaultStore::new());
+    /// tree.enable_leaf_metadata();
+    /// tree.insert([1u8; 32], b"one".to_vec(), 1).unwrap();
+    /// tree.insert([2u8; 32], b"two".to_vec(), 2).unwrap();
+    /// tree.set_leaf_tag([2u8; 32], Some("importer".to_string())).unwrap();
+    ///
+    /// let metadata = tree.leaf_metadata([2u8; 32]).unwrap().unwrap();
+    /// assert_eq!(metadata.inserted_version, 2);
+    /// assert_eq!(metadata.tag.as_deref(), Some("importer"));
+    /// ```
+    pub fn leaf_metadata(&self, key: impl Into<Key<K>>) -> Result<Option<LeafMetadata>> {
+        self.store().get_leaf_metadata(&key.into().0)
+    }
+}
+
+impl<S: TreeStore<K, V>, const K: usize, V: LeafValue> FullTree<S, K, V> {
+    /// Sets the tag of the metadata of `key`.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(true)` if the tag was set.
+    /// - `Ok(false)` if the key has no metadata, in which case nothing is written.
+    pub fn set_leaf_tag(&mut self, key: impl Into<Key<K>>, tag: Option<String>) -> Result<bool> {
+        let key = key.into().0;
+        let Some(mut metadata) = self.store().get_leaf_metadata(&key)? else {
+            return Ok(false);
+        };
+        metadata.tag = tag;
+        self.store_mut().set_leaf_metadata(&key, Some(metadata))?;
+        Ok(true)
+    }
+
+    /// Writes the metadata of the keys the last update inserted or deleted, if leaf metadata is enabled.
+    ///
+    /// Called once the update is notified, so that the recorded version is the one it produced.
+    pub(crate) fn stamp_leaf_metadata(&mut self) -> Result<()> {
+        let changes = self.take_metadata_changes();
+        if changes.is_empty() {
+            return Ok(());
+        }
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let version = self.root_version();
+        for change in changes {
+            let (key, metadata) = match change {
+                MetadataChange::Insert { key, replaced } => {
+                    let existing = if replaced {
+                        self.store().get_leaf_metadata(&key)?
+                    } else {
+                        None
+                    };
+                    let metadata = match existing {
+                        Some(existing) => LeafMetadata {
+                            updated_at: now,
+                            updated_version: version,
+                            ..existing
+                        },
+                        None => LeafMetadata {
+                            inserted_at: now,
+                            inserted_version: version,
+                            updated_at: now,
+                            updated_version: version,
+                            tag: None,
+                        },
+                    };
+                    (key, Some(metadata))
+                }
+                MetadataChange::Delete { key } => (key, None),
+            };
+            self.store_mut().set_leaf_metadata(&key, metadata)?;
+        }
+        Ok(())
+    }
+}
+
+/// A key whose metadata an update changes, see `FullTree::stamp_leaf_metadata`.
+pub(crate) enum MetadataChange<const K: usize> {
+    /// `key` was inserted, replacing an earlier leaf if `replaced`.
+    Insert { key: [u8; K], replaced: bool },
+    /// `key` was deleted.
+    Delete { key: [u8; K] },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::Sum;
+    use crate::store::DefaultStore;
+
+    #[test]
+    fn test_leaf_metadata_tracks_inserts_and_deletes() -> Result<()> {
+        let mut tree = FullTree::new(DefaultStore::new());
+        tree.insert([9u8; 32], b"before".to_vec(), 1)?;
+        tree.enable_leaf_metadata();
+        for i in 1..=3u8 {
+            tree.insert([i; 32], vec![i], i as Sum)?;
+        }
+        let root = tree.root()?.node_hash();
+
+        // Keys inserted before enabling have no metadata, and tags require metadata
+        assert_eq!(tree.leaf_metadata([9u8; 32])?, None);
+        assert!(!tree.set_leaf_tag([9u8; 32], Some("lost".to_string()))?);
+
+        assert!(tree.set_leaf_tag([2u8; 32], Some("batch-7".to_string()))?);
+        assert_eq!(tree.root()?.node_hash(), root);
+        tree.insert([2u8; 32], b"updated".to_vec(), 5)?;
+        let metadata = tree.leaf_metadata([2u8; 32])?.unwrap();
+        assert_eq!(
+            (metadata.inserted_version, metadata.updated_version),
+            (3, 5)
+        );
+        assert!(metadata.updated_at >= metadata.inserted_at);
+        assert_eq!(metadata.tag.as_deref(), Some("batch-7"));
+
+        // Deleting a key drops its metadata, and inserting it again starts afresh
+        tree.delete([2u8; 32])?;
+        assert_eq!(tree.leaf_metadata([2u8; 32])?, None);
+        tree.update_sum([3u8; 32], 30)?;
+        tree.insert([2u8; 32], b"again".to_vec(), 2)?;
+        let metadata = tree.leaf_metadata([2u8; 32])?.unwrap();
+        assert_eq!((metadata.inserted_version, metadata.tag), (8, None));
+        assert_eq!(tree.leaf_metadata([3u8; 32])?.unwrap().updated_version, 7);
+
+        // Metadata is not part of the commitment
+        let mut plain = FullTree::new(DefaultStore::new());
+        for key in tree.keys() {
+            let key = key?;
+            let (value, sum) = tree.get(key)?.unwrap();
+            plain.insert(key, value, sum)?;
+        }
+        assert_eq!(plain.root()?.node_hash(), tree.root()?.node_hash());
+
+        #[cfg(feature = "json")]
+        {
+            let mut json = Vec::new();
+            tree.export_json(&mut json)?;
+            let imported = FullTree::import_json(json.as_slice(), DefaultStore::new())?;
+            assert_eq!(
+                imported.leaf_metadata([2u8; 32])?,
+                tree.leaf_metadata([2u8; 32])?
+            );
+            assert_eq!(imported.leaf_metadata([9u8; 32])?, None);
+        }
+
+        Ok(())
+    }
+}
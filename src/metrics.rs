@@ -0,0 +1,244 @@
+//! Operational metrics for trees and stores.
+//!
+//! The `Metrics` trait receives counters and latencies from a `FullTree` (operation latencies and proof
+//! generations), from a `MeteredStore` (node reads and writes) and from a `CachedStore` (cache hits and
+//! misses). Applications implement it to feed their monitoring system of choice; `AtomicMetrics` is a
+//! ready-made implementation keeping in-process counters.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// A tree operation whose latency is reported to `Metrics::operation_completed`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Operation {
+    /// A lookup through `FullTree::get`.
+    Get,
+    /// An insert through `FullTree::insert` or `FullTree::insert_with_proof`.
+    Insert,
+    /// A delete through `FullTree::delete` or `FullTree::delete_with_exclusion_proof`.
+    Delete,
+    /// Proof generation through `FullTree::merkle_proof` or `FullTree::merkle_proofs`.
+    Proof,
+}
+
+impl Operation {
+    /// All operations, in declaration order.
+    pub const ALL: [Operation; 4] = [
+        Operation::Get,
+        Operation::Insert,
+        Operation::Delete,
+        Operation::Proof,
+    ];
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// A sink for tree and store metrics.
+///
+/// All methods have empty default implementations. They are called synchronously on the thread
+/// performing the operation, so implementations should be cheap.
+pub trait Metrics: Send + Sync {
+    /// Called for every branch or leaf lookup, with whether the node was found.
+    fn node_read(&self, _found: bool) {}
+
+    /// Called for every branch or leaf written.
+    fn node_written(&self) {}
+
+    /// Called for every branch or leaf deleted.
+    fn node_deleted(&self) {}
+
+    /// Called for every node lookup served by a cache, with whether it was a hit.
+    fn cache_access(&self, _hit: bool) {}
+
+    /// Called with the number of proofs produced by a proof generation call.
+    fn proofs_generated(&self, _count: usize) {}
+
+    /// Called when a tree operation succeeds, with its duration.
+    fn operation_completed(&self, _operation: Operation, _elapsed: Duration) {}
+}
+
+/// A `Metrics` implementation keeping counters in atomics.
+///
+/// # Examples
+///
+/// ```rust
+/// use mssmt::metrics::{AtomicMetrics, Operation};
+/// use mssmt::store::MeteredStore;
+/// use mssmt::{DefaultStore, FullTree};
+/// use std::sync::Arc;
+///
+/// let metrics = Arc::new(AtomicMetrics::default());
+/// let mut tree = FullTree::new(MeteredStore::new(DefaultStore::new(), metrics.clone()));
+/// tree.set_metrics(metrics.clone());
+///
+/// tree.insert([1u8; 32], b"value".to_vec(), 10).unwrap();
+/// tree.merkle_proof([1u8; 32]).unwrap();
+///
+/// let snapshot = metrics.snapshot();
+/// assert_eq!(snapshot.node_writes, 257);
+/// assert_eq!(snapshot.proofs_generated, 1);
+/// assert_eq!(snapshot.operation(Operation::Insert).count, 1);
+/// ```
+#[derive(Debug, Default)]
+pub struct AtomicMetrics {
+    node_reads: AtomicU64,
+    node_misses: AtomicU64,
+    node_writes: AtomicU64,
+    node_deletes: AtomicU64,
+    cache_hits: AtomicU64,
+    cache_misses: AtomicU64,
+    proofs_generated: AtomicU64,
+    operation_counts: [AtomicU64; 4],
+    operation_nanos: [AtomicU64; 4],
+}
+
+/// The number and combined duration of completed operations of one kind.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct OperationStats {
+    /// The number of completed operations.
+    pub count: u64,
+    /// The combined duration of the completed operations.
+    pub total: Duration,
+}
+
+impl OperationStats {
+    /// Returns the average duration of an operation, or zero if none completed.
+    pub fn mean(&self) -> Duration {
+        if self.count == 0 {
+            return Duration::ZERO;
+        }
+        Duration::from_nanos((self.total.as_nanos() / self.count as u128) as u64)
+    }
+}
+
+/// A point-in-time copy of the counters of an `AtomicMetrics`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MetricsSnapshot {
+    /// Node lookups, including misses.
+    pub node_reads: u64,
+    /// Node lookups that found nothing.
+    pub node_misses: u64,
+    /// Nodes written.
+    pub node_writes: u64,
+    /// Nodes deleted.
+    pub node_deletes: u64,
+    /// Node lookups served from a cache.
+    pub cache_hits: u64,
+    /// Node lookups that missed a cache.
+    pub cache_misses: u64,
+    /// Proofs produced.
+    pub proofs_generated: u64,
+    /// Per-operation statistics, indexed like `Operation::ALL`.
+    pub operations: [OperationStats; 4],
+}
+
+impl MetricsSnapshot {
+    /// Returns the statistics of one kind of operation.
+    pub fn operation(&self, operation: Operation) -> OperationStats {
+        self.operations[operation.index()]
+    }
+}
+
+impl AtomicMetrics {
+    /// Returns the current values of all counters.
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        MetricsSnapshot {
+            node_reads: load(&self.node_reads),
+            node_misses: load(&self.node_misses),
+            node_writes: load(&self.node_writes),
+            node_deletes: load(&self.node_deletes),
+            cache_hits: load(&self.cache_hits),
+            cache_misses: load(&self.cache_misses),
+            proofs_generated: load(&self.proofs_generated),
+            operations: Operation::ALL.map(|operation| OperationStats {
+                count: load(&self.operation_counts[operation.index()]),
+                total: Duration::from_nanos(load(&self.operation_nanos[operation.index()])),
+            }),
+        }
+    }
+}
+
+impl Metrics for AtomicMetrics {
+    fn node_read(&self, found: bool) {
+        self.node_reads.fetch_add(1, Ordering::Relaxed);
+        if !found {
+            self.node_misses.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn node_written(&self) {
+        self.node_writes.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn node_deleted(&self) {
+        self.node_deletes.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn cache_access(&self, hit: bool) {
+        let counter = if hit {
+            &self.cache_hits
+        } else {
+            &self.cache_misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn proofs_generated(&self, count: usize) {
+        self.proofs_generated
+            .fetch_add(count as u64, Ordering::Relaxed);
+    }
+
+    fn operation_completed(&self, operation: Operation, elapsed: Duration) {
+        self.operation_counts[operation.index()].fetch_add(1, Ordering::Relaxed);
+        self.operation_nanos[operation.index()]
+            .fetch_add(elapsed.as_nanos() as u64, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Result;
+    use crate::node::{LeafNode, Node};
+    use crate::store::{CachedStore, DefaultStore, MeteredStore, TreeStoreReader};
+    use crate::tree::FullTree;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_metrics_wiring() -> Result<()> {
+        let metrics = Arc::new(AtomicMetrics::default());
+        let store = MeteredStore::new(DefaultStore::new(), metrics.clone());
+        let store = CachedStore::new(store, 1024).with_metrics(metrics.clone());
+        let mut tree = FullTree::new(store);
+        tree.set_metrics(metrics.clone());
+
+        tree.insert([1u8; 32], b"one".to_vec(), 1)?;
+        tree.insert([2u8; 32], b"two".to_vec(), 2)?;
+        tree.delete([1u8; 32])?;
+        tree.get([2u8; 32])?;
+        tree.merkle_proofs([[1u8; 32], [2u8; 32]])?;
+
+        // Once the cache is cleared, the first lookup misses and reaches the metered store
+        tree.store().clear_cache();
+        let leaf_hash = LeafNode::new([2u8; 32], b"two".to_vec(), 2).node_hash();
+        assert!(tree.store().get_leaf(&leaf_hash)?.is_some());
+        assert!(tree.store().get_leaf(&leaf_hash)?.is_some());
+
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.operation(Operation::Insert).count, 2);
+        assert_eq!(snapshot.operation(Operation::Delete).count, 1);
+        assert_eq!(snapshot.operation(Operation::Get).count, 1);
+        assert_eq!(snapshot.operation(Operation::Proof).count, 1);
+        assert_eq!(snapshot.proofs_generated, 2);
+        assert_eq!(snapshot.node_writes, 2 * 257 + 7);
+        assert_eq!(snapshot.node_deletes, 1);
+        assert_eq!((snapshot.cache_hits, snapshot.cache_misses), (1, 1));
+        // The key index lookup of `get` bypasses the cache
+        assert_eq!(snapshot.node_reads, 2);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,201 @@
+//! Trees of trees for the Merkle-Sum Sparse Merkle Tree.
+//!
+//! A child tree is committed into a parent tree as a leaf whose value is the child root hash and whose
+//! sum is the child root sum, the layout of the universe and multiverse trees of taproot-assets. The
+//! parent root then commits to every leaf of every child, and its sum to their combined sums.
+//!
+//! A `NestedProof` chains a proof in the child tree with a proof of the child root in the parent tree, so
+//! a leaf can be verified against the parent root alone. The registry of a `Forest` uses the same layout,
+//! so `Forest::nested_proof` proves a namespace leaf against the forest root.
+
+use crate::error::{ProofError, Result};
+use crate::forest::Forest;
+use crate::key::Key;
+use crate::node::{LeafNode, Node, NodeHash, Sum};
+use crate::proof::Proof;
+use crate::store::{TreeStore, TreeStoreReader};
+use crate::tree::FullTree;
+
+/// Returns the parent tree leaf committing to a child tree with the given root.
+///
+/// # Examples
+///
+/// ```rust
+/// use mssmt::nested::commitment_leaf;
+/// use mssmt::{DefaultStore, FullTree, Node};
+///
+/// let mut child = FullTree::new(DefaultStore::new());
+/// child.insert([1u8; 32], b"one".to_vec(), 5).unwrap();
+///
+/// let root = child.root().unwrap();
+/// let leaf = commitment_leaf([9u8; 32], root.node_hash(), root.node_sum());
+/// assert_eq!(leaf.value, root.node_hash().as_bytes().to_vec());
+/// assert_eq!(leaf.sum, 5);
+/// ```
+pub fn commitment_leaf(key: impl Into<Key>, root_hash: NodeHash, root_sum: Sum) -> LeafNode {
+    LeafNode::new(key.into().0, root_hash.as_bytes().to_vec(), root_sum)
+}
+
+/// A proof of a leaf in a child tree, chained with a proof of the child root in its parent tree.
+///
+/// # Fields
+///
+/// - `child`: The proof of the leaf in the child tree.
+/// - `parent_key`: The key under which the child root is committed in the parent tree.
+/// - `parent`: The proof of the child root commitment in the parent tree.
+///
+/// # Examples
+///
+/// ```rust
+/// use mssmt::{DefaultStore, FullTree, LeafNode, Node};
+///
+/// let mut child = FullTree::new(DefaultStore::new());
+/// child.insert([1u8; 32], b"one".to_vec(), 5).unwrap();
+///
+/// let mut parent = FullTree::new(DefaultStore::new());
+/// parent.commit_child([9u8; 32], child.root().unwrap().as_ref()).unwrap();
+///
+/// let proof = parent.nested_proof([9u8; 32], &child, [1u8; 32]).unwrap();
+/// let leaf = LeafNode::new([1u8; 32], b"one".to_vec(), 5);
+/// assert!(proof.verify([1u8; 32], &leaf, parent.root().unwrap().node_hash()));
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NestedProof {
+    pub child: Proof,
+    pub parent_key: Key,
+    pub parent: Proof,
+}
+
+impl NestedProof {
+    /// Creates a nested proof from its child and parent proofs.
+    pub fn new(child: Proof, parent_key: impl Into<Key>, parent: Proof) -> Self {
+        Self {
+            child,
+            parent_key: parent_key.into(),
+            parent,
+        }
+    }
+
+    /// Computes the child root hash and sum from the child proof and the given leaf.
+    pub fn child_root(
+        &self,
+        key: impl Into<Key>,
+        leaf: &LeafNode,
+    ) -> std::result::Result<(NodeHash, Sum), ProofError> {
+        self.child.validate()?;
+        self.child.fold_root(key.into().0, leaf)
+    }
+
+    /// Verifies the proof against the root hash of the parent tree.
+    ///
+    /// Returns `true` if both proofs are canonical, the leaf rebuilds the child root, and the commitment
+    /// to that child root rebuilds `parent_root_hash`.
+    pub fn verify(&self, key: impl Into<Key>, leaf: &LeafNode, parent_root_hash: NodeHash) -> bool {
+        self.verify_detailed(key, leaf, parent_root_hash).is_ok()
+    }
+
+    /// Verifies the proof against the root hash of the parent tree, reporting why verification failed.
+    ///
+    /// Errors about the child proof are reported as is. A `RootHashMismatch` from the parent proof means
+    /// the leaf is not part of the child tree committed under `parent_key`.
+    pub fn verify_detailed(
+        &self,
+        key: impl Into<Key>,
+        leaf: &LeafNode,
+        parent_root_hash: NodeHash,
+    ) -> std::result::Result<(), ProofError> {
+        let key = key.into();
+        if !leaf.is_empty() && leaf.key != key.0 {
+            return Err(ProofError::KeyMismatch);
+        }
+        let (child_hash, child_sum) = self.child_root(key, leaf)?;
+        let commitment = commitment_leaf(self.parent_key, child_hash, child_sum);
+        self.parent
+            .verify_detailed(self.parent_key, &commitment, parent_root_hash)
+    }
+}
+
+impl<S: TreeStore> FullTree<S> {
+    /// Commits a child tree root as the leaf at `key`, replacing any previous commitment.
+    ///
+    /// # Returns
+    ///
+    /// - The previously committed value and sum, if any.
+    /// - `MssmtError::SumOverflow` if the child sum would overflow the root sum.
+    pub fn commit_child(
+        &mut self,
+        key: impl Into<Key>,
+        child_root: &dyn Node,
+    ) -> Result<Option<(Vec<u8>, Sum)>> {
+        let leaf = commitment_leaf(key, child_root.node_hash(), child_root.node_sum());
+        self.insert(leaf.key, leaf.value, leaf.sum)
+    }
+}
+
+impl<S: TreeStoreReader> FullTree<S> {
+    /// Generates a proof of `child_key` in `child`, chained with the proof of its commitment at
+    /// `parent_key` in this tree.
+    ///
+    /// The proof only verifies if the current root of `child` is committed at `parent_key`.
+    pub fn nested_proof<C: TreeStoreReader>(
+        &self,
+        parent_key: impl Into<Key>,
+        child: &FullTree<C>,
+        child_key: impl Into<Key>,
+    ) -> Result<NestedProof> {
+        let parent_key = parent_key.into();
+        Ok(NestedProof::new(
+            child.merkle_proof(child_key)?,
+            parent_key,
+            self.merkle_proof(parent_key)?,
+        ))
+    }
+}
+
+impl<S: TreeStoreReader> Forest<S> {
+    /// Generates a proof of `key` in the tree of `namespace`, verifiable against the forest root.
+    pub fn nested_proof(
+        &self,
+        namespace: impl Into<Key>,
+        key: impl Into<Key>,
+    ) -> Result<NestedProof> {
+        let namespace = namespace.into();
+        let registry = FullTree::new(self.store());
+        registry.nested_proof(namespace, &self.tree(namespace)?, key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::DefaultStore;
+
+    #[test]
+    fn test_nested_proofs() -> Result<()> {
+        let mut forest = Forest::new(DefaultStore::new());
+        for i in 0..4u8 {
+            forest
+                .tree_mut([i; 32])?
+                .insert([7u8; 32], vec![i], i as Sum + 1)?;
+        }
+        let forest_root = forest.root()?.node_hash();
+
+        let proof = forest.nested_proof([2u8; 32], [7u8; 32])?;
+        let leaf = LeafNode::new([7u8; 32], vec![2], 3);
+        assert!(proof.verify([7u8; 32], &leaf, forest_root));
+        assert_eq!(proof.child_root([7u8; 32], &leaf)?.1, 3);
+
+        // A leaf of another namespace does not verify under this one
+        let other = LeafNode::new([7u8; 32], vec![1], 2);
+        assert!(matches!(
+            proof.verify_detailed([7u8; 32], &other, forest_root),
+            Err(ProofError::RootHashMismatch { .. })
+        ));
+
+        // Exclusion in a child tree is proven against the parent root too
+        let proof = forest.nested_proof([2u8; 32], [8u8; 32])?;
+        assert!(proof.verify([8u8; 32], &LeafNode::new([0u8; 32], vec![], 0), forest_root));
+
+        Ok(())
+    }
+}
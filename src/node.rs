@@ -1,6 +1,7 @@
 //! Node definitions and implementations for the Merkle-Sum Sparse Merkle Tree.
 //!
-//! This module contains the `Node` trait and concrete implementations for `LeafNode`, `BranchNode`, and `ComputedNode`.
+//! This module contains the `Node` trait and concrete implementations for `LeafNode`, `BranchNode`, `ComputedNode`,
+//! and `CompactedLeafNode`.
 //!
 //! Nodes are the fundamental building blocks of the tree, representing both leaves (data entries) and branches (internal nodes).
 //! Each node maintains its own hash and sum, which are used for efficient proof generation and verification.
@@ -9,15 +10,68 @@ use once_cell::sync::Lazy;
 use parking_lot::RwLock;
 use sha2::{Digest, Sha256};
 use std::any::Any;
+use std::cell::Cell;
+use std::collections::HashMap;
 use std::fmt;
+use std::str::FromStr;
 use std::sync::Arc;
+use subtle::{Choice, ConstantTimeEq};
 
+use crate::config::OverflowPolicy;
+use crate::error::{MssmtError, Result};
+use crate::executor::{self, Executor};
 use crate::hash_utils::to_array;
+use crate::path::{Height, TreePath};
+use crate::proof::DecodeLimits;
+use crate::store::{resolve_node, TreeStoreReader};
 
 pub const HASH_SIZE: usize = 32;
 pub const MAX_TREE_LEVELS: usize = HASH_SIZE * 8; // 256 for 32 bytes
 pub const LAST_BIT_INDEX: usize = MAX_TREE_LEVELS - 1;
 
+/// The integer type of node sums.
+///
+/// Sums are `u64` by default. The `u128` feature widens them to `u128` for trees whose aggregated
+/// amounts exceed 64 bits. Sums are hashed and encoded big-endian on `SUM_SIZE` bytes, so the two
+/// configurations produce different roots and encodings, each deterministic.
+#[cfg(not(feature = "u128"))]
+pub type Sum = u64;
+
+/// The integer type of node sums, widened to `u128` by the `u128` feature.
+#[cfg(feature = "u128")]
+pub type Sum = u128;
+
+/// The signed counterpart of `Sum`, the type of relative sum adjustments.
+#[cfg(not(feature = "u128"))]
+pub type SumDelta = i64;
+
+/// The signed counterpart of `Sum`, the type of relative sum adjustments.
+#[cfg(feature = "u128")]
+pub type SumDelta = i128;
+
+/// The number of bytes a sum is hashed and encoded on.
+pub const SUM_SIZE: usize = std::mem::size_of::<Sum>();
+
+/// Decodes a big-endian sum from the first `SUM_SIZE` bytes of `bytes`.
+///
+/// # Panics
+///
+/// Panics if `bytes` is shorter than `SUM_SIZE`.
+pub(crate) fn decode_sum(bytes: &[u8]) -> Sum {
+    Sum::from_be_bytes(
+        bytes[..SUM_SIZE]
+            .try_into()
+            .expect("slice of SUM_SIZE bytes"),
+    )
+}
+
+/// Returns the number of levels below the root of a tree with `key_size`-byte keys.
+///
+/// Each key bit selects a child, so leaves sit at height `8 * key_size`.
+pub const fn tree_levels(key_size: usize) -> usize {
+    key_size * 8
+}
+
 /// Represents the hash of a node in the MS-SMT.
 #[derive(Clone, Copy, PartialEq, Eq, Hash)]
 pub struct NodeHash(pub [u8; HASH_SIZE]);
@@ -37,6 +91,31 @@ impl NodeHash {
     pub fn as_bytes(&self) -> &[u8; HASH_SIZE] {
         &self.0
     }
+
+    /// Compares two hashes in constant time.
+    ///
+    /// `==` returns as soon as a byte differs, so the time it takes reveals how long a prefix of a
+    /// forged hash is right. Verifiers comparing hashes an adversary controls, such as the root
+    /// reconstructed from an untrusted proof, should use this instead. Proof verification does.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use mssmt::NodeHash;
+    ///
+    /// let hash = NodeHash::new([1u8; 32]);
+    /// assert!(hash.constant_time_eq(&NodeHash::new([1u8; 32])));
+    /// assert!(!hash.constant_time_eq(&NodeHash::zero()));
+    /// ```
+    pub fn constant_time_eq(&self, other: &NodeHash) -> bool {
+        self.ct_eq(other).into()
+    }
+}
+
+impl ConstantTimeEq for NodeHash {
+    fn ct_eq(&self, other: &Self) -> Choice {
+        self.0.ct_eq(&other.0)
+    }
 }
 
 impl fmt::Debug for NodeHash {
@@ -45,6 +124,64 @@ impl fmt::Debug for NodeHash {
     }
 }
 
+/// Formats the hash as lowercase hex.
+///
+/// # Examples
+///
+/// ```rust
+/// use mssmt::NodeHash;
+///
+/// let hash = NodeHash::new([0xab; 32]);
+/// assert_eq!(hash.to_string(), "ab".repeat(32));
+/// assert_eq!(format!("{:x}", hash), hash.to_string());
+/// ```
+impl fmt::Display for NodeHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::LowerHex::fmt(self, f)
+    }
+}
+
+impl fmt::LowerHex for NodeHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&hex::encode(self.0))
+    }
+}
+
+/// Parses a hash from 64 hex characters.
+///
+/// # Examples
+///
+/// ```rust
+/// use mssmt::NodeHash;
+///
+/// let hash: NodeHash = "ab".repeat(32).parse().unwrap();
+/// assert_eq!(hash, NodeHash::new([0xab; 32]));
+/// assert!("abcd".parse::<NodeHash>().is_err());
+/// ```
+impl FromStr for NodeHash {
+    type Err = MssmtError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let bytes = hex::decode(s).map_err(|err| MssmtError::InvalidEncoding(err.to_string()))?;
+        let bytes: [u8; HASH_SIZE] = bytes.try_into().map_err(|bytes: Vec<u8>| {
+            MssmtError::InvalidEncoding(format!(
+                "expected {} bytes, got {}",
+                HASH_SIZE,
+                bytes.len()
+            ))
+        })?;
+        Ok(NodeHash(bytes))
+    }
+}
+
+impl TryFrom<&str> for NodeHash {
+    type Error = MssmtError;
+
+    fn try_from(s: &str) -> std::result::Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
 /// A trait representing a node in the Merkle-Sum Sparse Merkle Tree.
 ///
 /// Nodes can be either leaf nodes containing key-value-sum data or branch nodes pointing to child nodes.
@@ -56,31 +193,135 @@ impl fmt::Debug for NodeHash {
 /// - `node_sum`: Returns the sum associated with the node.
 /// - `copy`: Creates a deep copy of the node.
 /// - `as_any`: Returns a reference to `Any` for downcasting purposes.
-
+///
+/// Code handling nodes matches on `dyn Node::as_view` rather than downcasting through `as_any`.
 pub trait Node: Send + Sync {
     /// Returns the hash of the node.
     fn node_hash(&self) -> NodeHash;
 
     /// Returns the sum of the node.
-    fn node_sum(&self) -> u64;
+    fn node_sum(&self) -> Sum;
 
     /// Creates a deep copy of the node.
     fn copy(&self) -> Box<dyn Node>;
 
     /// Returns a reference to Any, for downcasting.
     fn as_any(&self) -> &dyn Any;
+
+    /// Returns the kind of the node.
+    ///
+    /// The default implementation returns `NodeKind::Other`, for node types defined outside the crate.
+    fn kind(&self) -> NodeKind {
+        NodeKind::Other
+    }
+}
+
+/// The kind of a node, see `Node::kind`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum NodeKind {
+    /// A `LeafNode`.
+    Leaf,
+    /// A `BranchNode`.
+    Branch,
+    /// A `ComputedNode`, carrying only a hash and a sum.
+    Computed,
+    /// A `CompactedLeafNode`.
+    Compacted,
+    /// A node type defined outside the crate.
+    Other,
+}
+
+/// A node borrowed as its concrete type, see `dyn Node::as_view`.
+///
+/// `K` is the key size in bytes and `V` the value type of the leaves, as in the tree holding the node.
+pub enum NodeView<'a, const K: usize = HASH_SIZE, V = Vec<u8>> {
+    /// A leaf.
+    Leaf(&'a LeafNode<K, V>),
+    /// A branch, whose children may be hash references, see `BranchNode::from_child_refs`.
+    Branch(&'a BranchNode),
+    /// A reference to a node by its hash and sum, to be resolved through a store.
+    Computed(&'a ComputedNode),
+    /// A leaf stored above the bottom of the tree.
+    Compacted(&'a CompactedLeafNode<K>),
+    /// A node of a type defined outside the crate, or a leaf with another key size or value type.
+    Other(&'a dyn Node),
 }
 
+impl<'n> dyn Node + 'n {
+    /// Borrows the node as its concrete type.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use mssmt::node::NodeView;
+    /// use mssmt::{DefaultStore, FullTree};
+    ///
+    /// let mut tree = FullTree::new(DefaultStore::new());
+    /// tree.insert([1u8; 32], b"value".to_vec(), 10).unwrap();
+    ///
+    /// let root = tree.root().unwrap();
+    /// let NodeView::Branch(branch) = root.as_view::<32, Vec<u8>>() else {
+    ///     panic!("the root of a non-empty tree is a branch");
+    /// };
+    /// assert_eq!(branch.left.node_sum() + branch.right.node_sum(), 10);
+    /// ```
+    pub fn as_view<const K: usize, V: LeafValue>(&self) -> NodeView<'_, K, V> {
+        let any = self.as_any();
+        let view = match self.kind() {
+            NodeKind::Leaf => any.downcast_ref().map(NodeView::Leaf),
+            NodeKind::Branch => any.downcast_ref().map(NodeView::Branch),
+            NodeKind::Computed => any.downcast_ref().map(NodeView::Computed),
+            NodeKind::Compacted => any.downcast_ref().map(NodeView::Compacted),
+            NodeKind::Other => None,
+        };
+        view.unwrap_or(NodeView::Other(self))
+    }
+
+    /// Returns the node as a leaf with `K`-byte keys and `V` values, if it is one.
+    pub fn as_leaf<const K: usize, V: LeafValue>(&self) -> Option<&LeafNode<K, V>> {
+        match self.as_view() {
+            NodeView::Leaf(leaf) => Some(leaf),
+            _ => None,
+        }
+    }
+
+    /// Returns the node as a branch, if it is one.
+    pub fn as_branch(&self) -> Option<&BranchNode> {
+        match self.kind() {
+            NodeKind::Branch => self.as_any().downcast_ref(),
+            _ => None,
+        }
+    }
+
+    /// Returns `true` if the node is a reference to a node by its hash and sum, see `ComputedNode`.
+    pub fn is_computed(&self) -> bool {
+        self.kind() == NodeKind::Computed
+    }
+}
+
+/// A value that can be stored in a leaf.
+///
+/// Leaves commit to the bytes of their value, so any cheaply clonable type exposing its bytes can be
+/// stored in a tree, such as `Vec<u8>`, `String` or a structured type keeping its serialized form.
+/// The trait is implemented for every such type.
+///
+/// Cloning a leaf clones its value, which copies the whole buffer for `Vec<u8>`. Trees holding large
+/// values should store them as `Arc<[u8]>` instead, so that values are shared by reference count
+/// between the caller, the store and the leaves returned by lookups.
+pub trait LeafValue: AsRef<[u8]> + Clone + Send + Sync + 'static {}
+
+impl<T: AsRef<[u8]> + Clone + Send + Sync + 'static> LeafValue for T {}
+
 /// A leaf node in the Merkle-Sum Sparse Merkle Tree.
 ///
 /// `LeafNode` represents the leaves of the tree and contains the actual key-value data and an associated sum.
-/// Each leaf node is identified by a unique key.
+/// Each leaf node is identified by a unique key of `K` bytes, 32 by default.
 ///
 /// # Fields
 ///
-/// - `key`: A 32-byte array representing the key.
-/// - `value`: A vector of bytes representing the value associated with the key.
-/// - `sum`: A 64-bit unsigned integer representing the sum associated with the key.
+/// - `key`: A `K`-byte array representing the key.
+/// - `value`: The value associated with the key, a `Vec<u8>` by default (see `LeafValue`).
+/// - `sum`: The sum associated with the key, see `Sum`.
 ///
 /// # Examples
 ///
@@ -92,18 +333,17 @@ pub trait Node: Send + Sync {
 /// let sum = 42;
 /// let leaf_node = LeafNode::new(key, value, sum);
 /// ```
-
 #[derive(Clone)]
-pub struct LeafNode {
+pub struct LeafNode<const K: usize = HASH_SIZE, V = Vec<u8>> {
     node_hash: Arc<RwLock<Option<NodeHash>>>,
-    pub key: [u8; HASH_SIZE],
-    pub value: Vec<u8>,
-    pub sum: u64,
+    pub key: [u8; K],
+    pub value: V,
+    pub sum: Sum,
 }
 
-impl LeafNode {
+impl<const K: usize, V: LeafValue> LeafNode<K, V> {
     /// Creates a new `LeafNode`.
-    pub fn new(key: [u8; HASH_SIZE], value: Vec<u8>, sum: u64) -> Self {
+    pub fn new(key: [u8; K], value: V, sum: Sum) -> Self {
         Self {
             node_hash: Arc::new(RwLock::new(None)),
             key,
@@ -114,16 +354,90 @@ impl LeafNode {
 
     /// Checks if the leaf node is empty.
     pub fn is_empty(&self) -> bool {
-        self.value.is_empty() && self.sum == 0
+        self.value.as_ref().is_empty() && self.sum == 0
     }
 
     /// Returns the value of the leaf node.
-    pub fn value(&self) -> &Vec<u8> {
+    pub fn value(&self) -> &V {
         &self.value
     }
+
+    /// Returns the bytes of the value of the leaf node, without copying them.
+    pub fn value_bytes(&self) -> &[u8] {
+        self.value.as_ref()
+    }
+
+    /// Encodes the leaf as its key, its sum as `SUM_SIZE` big-endian bytes and its value.
+    ///
+    /// This is the canonical encoding stores persist and exchange leaves in.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use mssmt::node::{LeafNode, Node};
+    ///
+    /// let leaf = LeafNode::new([1u8; 32], b"value".to_vec(), 10);
+    /// let encoded = leaf.encode();
+    /// assert_eq!(encoded.len(), 32 + mssmt::node::SUM_SIZE + 5);
+    /// assert_eq!(LeafNode::<32>::decode(&encoded).unwrap().node_hash(), leaf.node_hash());
+    /// ```
+    pub fn encode(&self) -> Vec<u8> {
+        let value = self.value.as_ref();
+        let mut bytes = Vec::with_capacity(K + SUM_SIZE + value.len());
+        bytes.extend_from_slice(&self.key);
+        bytes.extend_from_slice(&self.sum.to_be_bytes());
+        bytes.extend_from_slice(value);
+        bytes
+    }
 }
 
-impl Node for LeafNode {
+impl<const K: usize> LeafNode<K> {
+    /// Creates the tombstone a deletion of `key` leaves in trees deleting in `DeleteMode::Tombstone`.
+    ///
+    /// A tombstone has an empty value and a zero sum, so it reads as absent, but unlike the shared empty
+    /// leaf it is bound to its key.
+    pub fn tombstone(key: [u8; K]) -> Self {
+        Self::new(key, Vec::new(), 0)
+    }
+
+    /// Decodes a leaf produced by `encode`.
+    ///
+    /// # Returns
+    ///
+    /// - The decoded leaf.
+    /// - `MssmtError::InvalidEncoding` if `bytes` is too short to hold a key and a sum.
+    pub fn decode(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() < K + SUM_SIZE {
+            return Err(MssmtError::InvalidEncoding(format!(
+                "leaf encoding of {} bytes is too short",
+                bytes.len()
+            )));
+        }
+        let mut key = [0u8; K];
+        key.copy_from_slice(&bytes[..K]);
+        Ok(Self::new(
+            key,
+            bytes[K + SUM_SIZE..].to_vec(),
+            decode_sum(&bytes[K..]),
+        ))
+    }
+
+    /// Decodes a leaf produced by `encode` like `decode`, with a value of at most
+    /// `limits.max_value_len` bytes.
+    ///
+    /// # Returns
+    ///
+    /// - The decoded leaf.
+    /// - `MssmtError::DecodeLimit` if the value is longer than the limits allow.
+    /// - `MssmtError::InvalidEncoding` if `bytes` is too short to hold a key and a sum.
+    pub fn decode_with_limits(bytes: &[u8], limits: &DecodeLimits) -> Result<Self> {
+        let value_len = bytes.len().saturating_sub(K + SUM_SIZE);
+        DecodeLimits::check("leaf value bytes", limits.max_value_len, value_len)?;
+        Self::decode(bytes)
+    }
+}
+
+impl<const K: usize, V: LeafValue> Node for LeafNode<K, V> {
     fn node_hash(&self) -> NodeHash {
         {
             let node_hash = self.node_hash.read();
@@ -132,13 +446,7 @@ impl Node for LeafNode {
             }
         }
 
-        let mut hasher = Sha256::new();
-        hasher.update(self.key);
-        hasher.update(&self.value);
-        hasher.update(self.sum.to_be_bytes());
-        let hash = hasher.finalize();
-
-        let node_hash = NodeHash::new(to_array(&hash));
+        let node_hash = leaf_hash(&self.key, self.value.as_ref(), self.sum);
         {
             let mut node_hash_lock = self.node_hash.write();
             *node_hash_lock = Some(node_hash);
@@ -146,7 +454,7 @@ impl Node for LeafNode {
         node_hash
     }
 
-    fn node_sum(&self) -> u64 {
+    fn node_sum(&self) -> Sum {
         self.sum
     }
 
@@ -157,6 +465,51 @@ impl Node for LeafNode {
     fn as_any(&self) -> &dyn Any {
         self
     }
+
+    fn kind(&self) -> NodeKind {
+        NodeKind::Leaf
+    }
+}
+
+/// Wipes the key, value and sum of the leaf, and forgets its cached hash.
+///
+/// Only this copy is wiped: clones of the leaf, including the ones held by a store, keep their own
+/// buffers unless their value shares this one.
+#[cfg(feature = "zeroize")]
+impl<const K: usize, V: zeroize::Zeroize> zeroize::Zeroize for LeafNode<K, V> {
+    fn zeroize(&mut self) {
+        self.key.zeroize();
+        self.value.zeroize();
+        self.sum.zeroize();
+        self.node_hash = Arc::new(RwLock::new(None));
+    }
+}
+
+/// Leaves holding values wiped on drop, such as `Zeroizing<Vec<u8>>`, wipe their value when they are
+/// dropped. Their key and sum are not wiped, as proofs and branch sums disclose them anyway.
+///
+/// # Examples
+///
+/// ```rust
+/// use mssmt::{DefaultStore, FullTree};
+/// use zeroize::Zeroizing;
+///
+/// let mut tree = FullTree::<_, 32, Zeroizing<Vec<u8>>>::new(DefaultStore::default());
+/// tree.insert([1u8; 32], Zeroizing::new(b"secret".to_vec()), 10).unwrap();
+/// assert_eq!(tree.get([1u8; 32]).unwrap().unwrap().0.as_slice(), b"secret");
+/// ```
+#[cfg(feature = "zeroize")]
+impl<const K: usize, V: zeroize::ZeroizeOnDrop> zeroize::ZeroizeOnDrop for LeafNode<K, V> {}
+
+/// Arbitrary leaves have an arbitrary key, value and sum, including empty leaves and sums up to
+/// `Sum::MAX`.
+#[cfg(feature = "arbitrary")]
+impl<'a, const K: usize, V: LeafValue + arbitrary::Arbitrary<'a>> arbitrary::Arbitrary<'a>
+    for LeafNode<K, V>
+{
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(Self::new(u.arbitrary()?, u.arbitrary()?, u.arbitrary()?))
+    }
 }
 
 /// Represents an empty leaf node.
@@ -183,25 +536,176 @@ pub static EMPTY_LEAF_NODE: Lazy<LeafNode> =
 /// let right_leaf = Arc::new(LeafNode::new([1u8; 32], b"right".to_vec(), 20));
 /// let branch_node = BranchNode::new(left_leaf, right_leaf);
 /// ```
-
 #[derive(Clone)]
 pub struct BranchNode {
     node_hash: Arc<RwLock<Option<NodeHash>>>,
-    sum: Arc<RwLock<Option<u64>>>,
+    sum: Arc<RwLock<Option<Sum>>>,
+    overflow: OverflowPolicy,
     pub left: Arc<dyn Node>,
     pub right: Arc<dyn Node>,
 }
 
 impl BranchNode {
-    /// Creates a new `BranchNode`.
+    /// Creates a new `BranchNode` combining the sums of its children under `OverflowPolicy::Checked`.
     pub fn new(left: Arc<dyn Node>, right: Arc<dyn Node>) -> Self {
+        Self::with_overflow_policy(left, right, OverflowPolicy::Checked)
+    }
+
+    /// Creates a new `BranchNode` combining the sums of its children under `overflow`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use mssmt::config::OverflowPolicy;
+    /// use mssmt::node::{BranchNode, LeafNode, Node, Sum};
+    /// use std::sync::Arc;
+    ///
+    /// let left = Arc::new(LeafNode::new([0u8; 32], b"left".to_vec(), Sum::MAX));
+    /// let right = Arc::new(LeafNode::new([1u8; 32], b"right".to_vec(), 2));
+    /// let branch = BranchNode::with_overflow_policy(left, right, OverflowPolicy::Wrapping);
+    /// assert_eq!(branch.node_sum(), 1);
+    /// ```
+    pub fn with_overflow_policy(
+        left: Arc<dyn Node>,
+        right: Arc<dyn Node>,
+        overflow: OverflowPolicy,
+    ) -> Self {
         Self {
             node_hash: Arc::new(RwLock::new(None)),
             sum: Arc::new(RwLock::new(None)),
+            overflow,
             left,
             right,
         }
     }
+
+    /// Returns the policy combining the sums of the children of the branch.
+    pub fn overflow_policy(&self) -> OverflowPolicy {
+        self.overflow
+    }
+
+    /// Creates a branch that references its children by hash and sum only.
+    ///
+    /// The children are `ComputedNode`s, which the tree replaces with the stored nodes when it descends
+    /// into them. Stores that page nodes in and out of memory can return such branches from
+    /// `get_branch` instead of keeping whole subtrees alive.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use mssmt::node::{BranchNode, LeafNode, Node};
+    /// use std::sync::Arc;
+    ///
+    /// let left = LeafNode::new([0u8; 32], b"left".to_vec(), 10);
+    /// let right = LeafNode::new([1u8; 32], b"right".to_vec(), 20);
+    /// let branch = BranchNode::new(Arc::new(left.clone()), Arc::new(right.clone()));
+    ///
+    /// let referenced = BranchNode::from_child_refs(
+    ///     (left.node_hash(), left.node_sum()),
+    ///     (right.node_hash(), right.node_sum()),
+    /// );
+    /// assert_eq!(referenced.node_hash(), branch.node_hash());
+    /// ```
+    pub fn from_child_refs(left: (NodeHash, Sum), right: (NodeHash, Sum)) -> Self {
+        Self::new(
+            Arc::new(ComputedNode::new(left.0, left.1)),
+            Arc::new(ComputedNode::new(right.0, right.1)),
+        )
+    }
+
+    /// The size of an encoded branch: the hash and sum of both children.
+    pub const ENCODED_SIZE: usize = 2 * (HASH_SIZE + SUM_SIZE);
+
+    /// Encodes the branch as the hash and sum of its left child followed by those of its right child,
+    /// each sum on `SUM_SIZE` big-endian bytes.
+    ///
+    /// This is the canonical encoding stores persist and exchange branches in. It does not include the
+    /// children themselves, which are encoded separately.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use mssmt::node::{BranchNode, LeafNode, Node};
+    /// use std::sync::Arc;
+    ///
+    /// let left = Arc::new(LeafNode::new([0u8; 32], b"left".to_vec(), 10));
+    /// let right = Arc::new(LeafNode::new([1u8; 32], b"right".to_vec(), 20));
+    /// let branch = BranchNode::new(left, right);
+    ///
+    /// let encoded = branch.encode();
+    /// assert_eq!(encoded.len(), BranchNode::ENCODED_SIZE);
+    /// assert_eq!(BranchNode::decode(&encoded).unwrap().node_hash(), branch.node_hash());
+    /// ```
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(Self::ENCODED_SIZE);
+        for child in [&self.left, &self.right] {
+            bytes.extend_from_slice(child.node_hash().as_bytes());
+            bytes.extend_from_slice(&child.node_sum().to_be_bytes());
+        }
+        bytes
+    }
+
+    /// Decodes a branch produced by `encode`, referencing its children by hash.
+    ///
+    /// # Returns
+    ///
+    /// - The decoded branch, see `BranchNode::from_child_refs`.
+    /// - `MssmtError::InvalidEncoding` if `bytes` has the wrong length or the sums of the children
+    ///   overflow.
+    pub fn decode(bytes: &[u8]) -> Result<Self> {
+        Self::decode_with_policy(bytes, OverflowPolicy::Checked)
+    }
+
+    /// Decodes a branch produced by `encode`, combining the sums of its children under `overflow`.
+    ///
+    /// # Returns
+    ///
+    /// - The decoded branch.
+    /// - `MssmtError::InvalidEncoding` if `bytes` has the wrong length or the sums of the children
+    ///   overflow under `OverflowPolicy::Checked`.
+    pub fn decode_with_policy(bytes: &[u8], overflow: OverflowPolicy) -> Result<Self> {
+        if bytes.len() != Self::ENCODED_SIZE {
+            return Err(MssmtError::InvalidEncoding(format!(
+                "branch encoding of {} bytes, expected {}",
+                bytes.len(),
+                Self::ENCODED_SIZE
+            )));
+        }
+        let child = |offset: usize| {
+            let hash = NodeHash::new(to_array(&bytes[offset..offset + HASH_SIZE]));
+            (hash, decode_sum(&bytes[offset + HASH_SIZE..]))
+        };
+        let (left, right) = (child(0), child(HASH_SIZE + SUM_SIZE));
+        if overflow.combine(left.1, right.1).is_none() {
+            return Err(MssmtError::InvalidEncoding(
+                "branch children sums overflow".to_string(),
+            ));
+        }
+        Ok(Self::with_overflow_policy(
+            Arc::new(ComputedNode::new(left.0, left.1)),
+            Arc::new(ComputedNode::new(right.0, right.1)),
+            overflow,
+        ))
+    }
+
+    /// Returns a copy of the branch whose children are replaced by hash references.
+    ///
+    /// The copy shares the cached hash and sum of the original but does not keep its children alive.
+    pub fn to_shallow(&self) -> Self {
+        Self {
+            node_hash: self.node_hash.clone(),
+            sum: self.sum.clone(),
+            overflow: self.overflow,
+            left: Arc::new(ComputedNode::new(
+                self.left.node_hash(),
+                self.left.node_sum(),
+            )),
+            right: Arc::new(ComputedNode::new(
+                self.right.node_hash(),
+                self.right.node_sum(),
+            )),
+        }
+    }
 }
 
 impl Node for BranchNode {
@@ -216,13 +720,7 @@ impl Node for BranchNode {
         let left_hash = self.left.node_hash();
         let right_hash = self.right.node_hash();
 
-        let mut hasher = Sha256::new();
-        hasher.update(left_hash.0);
-        hasher.update(right_hash.0);
-        hasher.update(self.node_sum().to_be_bytes());
-        let hash = hasher.finalize();
-
-        let node_hash = NodeHash::new(to_array(&hash));
+        let node_hash = branch_hash(&left_hash, &right_hash, self.node_sum());
         {
             let mut node_hash_lock = self.node_hash.write();
             *node_hash_lock = Some(node_hash);
@@ -230,7 +728,7 @@ impl Node for BranchNode {
         node_hash
     }
 
-    fn node_sum(&self) -> u64 {
+    fn node_sum(&self) -> Sum {
         {
             let sum = self.sum.read();
             if let Some(sum) = *sum {
@@ -238,7 +736,12 @@ impl Node for BranchNode {
             }
         }
 
-        let sum = self.left.node_sum() + self.right.node_sum();
+        let (left, right) = (self.left.node_sum(), self.right.node_sum());
+        // Only checked branches fail to combine, and they are built over children whose sums fit
+        let sum = self
+            .overflow
+            .combine(left, right)
+            .unwrap_or_else(|| left + right);
         {
             let mut sum_lock = self.sum.write();
             *sum_lock = Some(sum);
@@ -253,20 +756,72 @@ impl Node for BranchNode {
     fn as_any(&self) -> &dyn Any {
         self
     }
+
+    fn kind(&self) -> NodeKind {
+        NodeKind::Branch
+    }
+}
+
+thread_local! {
+    // The node hashes computed on this thread, see `hashes_computed`
+    static HASHES_COMPUTED: Cell<u64> = const { Cell::new(0) };
+}
+
+/// Returns the number of leaf and branch hashes computed on the current thread so far.
+pub(crate) fn hashes_computed() -> u64 {
+    HASHES_COMPUTED.with(Cell::get)
+}
+
+fn count_hash() {
+    HASHES_COMPUTED.with(|count| count.set(count.get() + 1));
+}
+
+/// Computes the hash of a leaf from its key, value and sum, without building a `LeafNode`.
+pub(crate) fn leaf_hash(key: &[u8], value: &[u8], sum: Sum) -> NodeHash {
+    count_hash();
+    let mut hasher = Sha256::new();
+    hasher.update(key);
+    hasher.update(value);
+    hasher.update(sum.to_be_bytes());
+    NodeHash::new(to_array(&hasher.finalize()))
+}
+
+/// Computes the hash of a branch from the hashes of its children and its sum.
+pub(crate) fn branch_hash(left: &NodeHash, right: &NodeHash, sum: Sum) -> NodeHash {
+    count_hash();
+    let mut hasher = Sha256::new();
+    hasher.update(left.0);
+    hasher.update(right.0);
+    hasher.update(sum.to_be_bytes());
+    NodeHash::new(to_array(&hasher.finalize()))
 }
 
 /// Represents a precomputed node.
+///
+/// A `ComputedNode` only carries a hash and a sum. It is used for proof siblings and as a reference to a
+/// child that has not been loaded from the store.
 #[derive(Clone)]
 pub struct ComputedNode {
     hash: NodeHash,
-    sum: u64,
+    sum: Sum,
 }
 
 impl ComputedNode {
     /// Creates a new `ComputedNode`.
-    pub fn new(hash: NodeHash, sum: u64) -> Self {
+    pub fn new(hash: NodeHash, sum: Sum) -> Self {
         Self { hash, sum }
     }
+
+    /// Reduces `node` to its hash and sum, returning it as is if it already is a `ComputedNode`.
+    ///
+    /// Proofs hold their siblings this way, so that they never carry the children or values of a sibling.
+    pub fn pruned(node: Arc<dyn Node>) -> Arc<dyn Node> {
+        if node.is_computed() {
+            node
+        } else {
+            Arc::new(Self::new(node.node_hash(), node.node_sum()))
+        }
+    }
 }
 
 impl Node for ComputedNode {
@@ -274,7 +829,7 @@ impl Node for ComputedNode {
         self.hash
     }
 
-    fn node_sum(&self) -> u64 {
+    fn node_sum(&self) -> Sum {
         self.sum
     }
 
@@ -285,12 +840,136 @@ impl Node for ComputedNode {
     fn as_any(&self) -> &dyn Any {
         self
     }
+
+    fn kind(&self) -> NodeKind {
+        NodeKind::Computed
+    }
 }
 
+/// A leaf node stored above the bottom of the tree in place of a chain of branches.
+///
+/// A subtree containing a single leaf consists of the leaf and one branch per level whose other child is an
+/// empty subtree. `CompactedLeafNode` stands in for such a subtree rooted at `height`: it stores the leaf only,
+/// and its hash is identical to the hash of the equivalent chain of branches. This is the building block of
+/// path-compressed trees.
+///
+/// # Examples
+///
+/// ```rust
+/// use mssmt::node::{CompactedLeafNode, LeafNode, Node};
+/// use mssmt::{DefaultStore, FullTree};
+///
+/// let leaf = LeafNode::new([1u8; 32], b"value".to_vec(), 10);
+/// let compacted = CompactedLeafNode::new(0, leaf);
+///
+/// let mut tree = FullTree::new(DefaultStore::new());
+/// tree.insert([1u8; 32], b"value".to_vec(), 10).unwrap();
+/// assert_eq!(compacted.node_hash(), tree.root().unwrap().node_hash());
+/// ```
+#[derive(Clone)]
+pub struct CompactedLeafNode<const K: usize = HASH_SIZE> {
+    node_hash: Arc<RwLock<Option<NodeHash>>>,
+    height: usize,
+    pub leaf: LeafNode<K>,
+}
+
+impl<const K: usize> CompactedLeafNode<K> {
+    /// Creates a new `CompactedLeafNode` standing in for the subtree rooted at `height` on the path of the leaf key.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `height` is greater than the number of levels of the tree, `MAX_TREE_LEVELS` for
+    /// 32-byte keys.
+    pub fn new(height: usize, leaf: LeafNode<K>) -> Self {
+        assert!(height <= tree_levels(K), "height out of range");
+        Self {
+            node_hash: Arc::new(RwLock::new(None)),
+            height,
+            leaf,
+        }
+    }
+
+    /// Returns the height of the subtree this node stands in for.
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Returns the key of the compacted leaf.
+    pub fn key(&self) -> &[u8; K] {
+        &self.leaf.key
+    }
+
+    /// Expands the node into the equivalent chain of branches, returning the node at `height`.
+    pub fn extract(&self) -> Arc<dyn Node> {
+        let mut current: Arc<dyn Node> = Arc::new(self.leaf.clone());
+        for height in (self.height..tree_levels(K)).rev() {
+            let empty = EmptyTreeOf::<K>::node_at(height + 1);
+            current = if TreePath::from(self.leaf.key).is_left(Height::at(height)) {
+                Arc::new(BranchNode::new(current, empty))
+            } else {
+                Arc::new(BranchNode::new(empty, current))
+            };
+        }
+        current
+    }
+}
+
+impl<const K: usize> Node for CompactedLeafNode<K> {
+    fn node_hash(&self) -> NodeHash {
+        {
+            let node_hash = self.node_hash.read();
+            if let Some(node_hash) = *node_hash {
+                return node_hash;
+            }
+        }
+
+        // Empty siblings have a zero sum, so every branch on the path carries the leaf sum
+        let sum = self.leaf.sum;
+        let mut node_hash = self.leaf.node_hash();
+        for height in (self.height..tree_levels(K)).rev() {
+            let empty_hash = EmptyTreeOf::<K>::hash_at(height + 1);
+            node_hash = if TreePath::from(self.leaf.key).is_left(Height::at(height)) {
+                branch_hash(&node_hash, &empty_hash, sum)
+            } else {
+                branch_hash(&empty_hash, &node_hash, sum)
+            };
+        }
+
+        {
+            let mut node_hash_lock = self.node_hash.write();
+            *node_hash_lock = Some(node_hash);
+        }
+        node_hash
+    }
+
+    fn node_sum(&self) -> Sum {
+        self.leaf.sum
+    }
+
+    fn copy(&self) -> Box<dyn Node> {
+        Box::new(self.clone())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn kind(&self) -> NodeKind {
+        NodeKind::Compacted
+    }
+}
+
+/// The empty leaf as a shared node.
+///
+/// Cloning this `Arc` is cheap, unlike wrapping a clone of `EMPTY_LEAF_NODE` in a new `Arc`.
+pub static EMPTY_LEAF: Lazy<Arc<dyn Node>> = Lazy::new(|| Arc::new(EMPTY_LEAF_NODE.clone()));
+
 /// Initializes the empty tree nodes.
+///
+/// `EMPTY_TREE[height]` is the root of an empty subtree at `height`, and `EMPTY_TREE[MAX_TREE_LEVELS]` is
+/// `EMPTY_LEAF`. Each level shares the node of the level below, so the whole table holds one node per level.
 pub static EMPTY_TREE: Lazy<Vec<Arc<dyn Node>>> = Lazy::new(|| {
-    let mut empty_tree: Vec<Arc<dyn Node>> = Vec::with_capacity(MAX_TREE_LEVELS + 1);
-    empty_tree.resize_with(MAX_TREE_LEVELS + 1, || Arc::new(EMPTY_LEAF_NODE.clone()));
+    let mut empty_tree: Vec<Arc<dyn Node>> = vec![EMPTY_LEAF.clone(); MAX_TREE_LEVELS + 1];
 
     for i in (0..MAX_TREE_LEVELS).rev() {
         let branch = BranchNode::new(empty_tree[i + 1].clone(), empty_tree[i + 1].clone());
@@ -300,14 +979,270 @@ pub static EMPTY_TREE: Lazy<Vec<Arc<dyn Node>>> = Lazy::new(|| {
     empty_tree
 });
 
-/// Returns the bit at a given index in a 32-byte key.
+/// The root hash of an empty tree with 32-byte keys,
+/// `abbe6f3b120cf3cf6aa5cf4b2b985260c713226193ff7c3f507d0c3c02e475fe`.
+///
+/// This is `EmptyTree::hash_at(0)`, hard-coded so that integrators can recognize or cross-check the
+/// sentinel root of an empty tree without building one. It depends on the width of `Sum`, see the
+/// `u128` variant.
+///
+/// # Examples
+///
+/// ```rust
+/// use mssmt::node::EMPTY_ROOT_HASH;
+/// use mssmt::{DefaultStore, FullTree, Node};
+///
+/// let tree = FullTree::new(DefaultStore::new());
+/// assert_eq!(tree.root().unwrap().node_hash(), EMPTY_ROOT_HASH);
+/// ```
+#[cfg(not(feature = "u128"))]
+pub const EMPTY_ROOT_HASH: NodeHash = NodeHash([
+    0xab, 0xbe, 0x6f, 0x3b, 0x12, 0x0c, 0xf3, 0xcf, 0x6a, 0xa5, 0xcf, 0x4b, 0x2b, 0x98, 0x52, 0x60,
+    0xc7, 0x13, 0x22, 0x61, 0x93, 0xff, 0x7c, 0x3f, 0x50, 0x7d, 0x0c, 0x3c, 0x02, 0xe4, 0x75, 0xfe,
+]);
+
+/// The root hash of an empty tree with 32-byte keys and `u128` sums,
+/// `d2844c5a4f3263fa0a30a18892db41ba7b7c6df9ca4c2a95fcf017bbed10dcaa`.
+#[cfg(feature = "u128")]
+pub const EMPTY_ROOT_HASH: NodeHash = NodeHash([
+    0xd2, 0x84, 0x4c, 0x5a, 0x4f, 0x32, 0x63, 0xfa, 0x0a, 0x30, 0xa1, 0x88, 0x92, 0xdb, 0x41, 0xba,
+    0x7b, 0x7c, 0x6d, 0xf9, 0xca, 0x4c, 0x2a, 0x95, 0xfc, 0xf0, 0x17, 0xbb, 0xed, 0x10, 0xdc, 0xaa,
+]);
+
+/// Returns the shared root of an empty subtree at `height`.
 ///
-/// The bits are indexed from 0 (most significant bit) to 255 (least significant bit).
+/// # Panics
+///
+/// Panics if `height` is greater than `MAX_TREE_LEVELS`.
+pub fn empty_node(height: usize) -> Arc<dyn Node> {
+    EMPTY_TREE[height].clone()
+}
+
+/// The empty subtrees of a tree with `K`-byte keys: the shared node and its hash at every height.
+struct EmptyLevels {
+    nodes: Vec<Arc<dyn Node>>,
+    hashes: Vec<NodeHash>,
+}
+
+impl EmptyLevels {
+    fn from_nodes(nodes: Vec<Arc<dyn Node>>) -> Self {
+        let hashes = nodes.iter().map(|node| node.node_hash()).collect();
+        Self { nodes, hashes }
+    }
+
+    fn build<const K: usize>() -> Self {
+        let levels = tree_levels(K);
+        let leaf: Arc<dyn Node> = Arc::new(LeafNode::new([0u8; K], Vec::new(), 0));
+        let mut nodes = vec![leaf; levels + 1];
+        for i in (0..levels).rev() {
+            nodes[i] = Arc::new(BranchNode::new(nodes[i + 1].clone(), nodes[i + 1].clone()));
+        }
+        Self::from_nodes(nodes)
+    }
+}
+
+/// The empty subtrees of trees with 32-byte keys, sharing the nodes of `EMPTY_TREE`.
+static EMPTY_LEVELS: Lazy<EmptyLevels> = Lazy::new(|| EmptyLevels::from_nodes(EMPTY_TREE.clone()));
+
+/// The empty subtrees of trees with other key sizes, built on first use and kept for the process lifetime.
+static OTHER_EMPTY_LEVELS: Lazy<RwLock<HashMap<usize, &'static EmptyLevels>>> =
+    Lazy::new(Default::default);
+
+fn empty_levels<const K: usize>() -> &'static EmptyLevels {
+    if K == HASH_SIZE {
+        return &EMPTY_LEVELS;
+    }
+    if let Some(levels) = OTHER_EMPTY_LEVELS.read().get(&K) {
+        return levels;
+    }
+    OTHER_EMPTY_LEVELS
+        .write()
+        .entry(K)
+        .or_insert_with(|| Box::leak(Box::new(EmptyLevels::build::<K>())))
+}
+
+/// Precomputed hashes and sums of the empty subtrees at every height of a tree with `K`-byte keys.
+///
+/// Comparing a node hash against `hash_at` is the cheapest way to detect an empty subtree, since the
+/// hashes are computed once and never require walking or locking a node. Trees with 32-byte keys use the
+/// `EmptyTree` alias.
+///
+/// # Examples
+///
+/// ```rust
+/// use mssmt::node::{tree_levels, EmptyTreeOf};
+///
+/// // A tree with 20-byte keys has 160 levels, and its empty leaf has a 20-byte zero key
+/// assert_eq!(tree_levels(20), 160);
+/// assert_ne!(EmptyTreeOf::<20>::hash_at(0), EmptyTreeOf::<32>::hash_at(0));
+/// ```
+pub struct EmptyTreeOf<const K: usize>;
+
+/// Precomputed hashes and sums of the empty subtrees of trees with 32-byte keys.
+///
+/// # Examples
+///
+/// ```rust
+/// use mssmt::node::{EmptyTree, MAX_TREE_LEVELS};
+/// use mssmt::{DefaultStore, FullTree, Node};
+///
+/// let tree = FullTree::new(DefaultStore::new());
+/// assert_eq!(tree.root().unwrap().node_hash(), EmptyTree::hash_at(0));
+/// assert_eq!(EmptyTree::sum_at(MAX_TREE_LEVELS), 0);
+/// ```
+pub type EmptyTree = EmptyTreeOf<HASH_SIZE>;
+
+impl<const K: usize> EmptyTreeOf<K> {
+    /// Returns the hash of the empty subtree at `height`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `height` is greater than the number of tree levels.
+    pub fn hash_at(height: usize) -> NodeHash {
+        empty_levels::<K>().hashes[height]
+    }
+
+    /// Returns the sum of the empty subtree at `height`, which is always zero.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `height` is greater than the number of tree levels.
+    pub fn sum_at(height: usize) -> Sum {
+        assert!(height <= tree_levels(K), "invalid height: {}", height);
+        0
+    }
+
+    /// Returns the shared root node of the empty subtree at `height`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `height` is greater than the number of tree levels.
+    pub fn node_at(height: usize) -> Arc<dyn Node> {
+        empty_levels::<K>().nodes[height].clone()
+    }
+
+    /// Returns the shared empty subtrees from `height` down to the leaves.
+    pub(crate) fn nodes_from(height: usize) -> &'static [Arc<dyn Node>] {
+        &empty_levels::<K>().nodes[height..]
+    }
+
+    /// Returns `true` if `hash` is the hash of the empty subtree at `height`.
+    ///
+    /// Heights beyond the number of tree levels never hold an empty subtree.
+    pub fn is_empty_at(height: usize, hash: &NodeHash) -> bool {
+        empty_levels::<K>().hashes.get(height) == Some(hash)
+    }
+}
+
+/// Collects the non-empty leaves of the subtree rooted at `node`, in key order.
+///
+/// `height` is the height of `node` in the tree and is used to skip empty subtrees. Hash-referenced
+/// children are loaded from `store`.
+pub(crate) fn collect_leaves<S: TreeStoreReader<K> + ?Sized, const K: usize>(
+    store: &S,
+    node: &Arc<dyn Node>,
+    height: usize,
+    leaves: &mut Vec<LeafNode<K>>,
+) -> Result<()> {
+    if EmptyTreeOf::<K>::is_empty_at(height, &node.node_hash()) {
+        return Ok(());
+    }
+
+    let node = resolve_node(store, node, height)?;
+    if let Some(branch) = node.as_branch() {
+        collect_leaves(store, &branch.left, height + 1, leaves)?;
+        collect_leaves(store, &branch.right, height + 1, leaves)?;
+    } else if let Some(leaf) = node.as_any().downcast_ref::<LeafNode<K>>() {
+        if !leaf.is_empty() {
+            leaves.push(leaf.clone());
+        }
+    } else if let Some(compacted) = node.as_any().downcast_ref::<CompactedLeafNode<K>>() {
+        leaves.push(compacted.leaf.clone());
+    }
+    Ok(())
+}
+
+/// Creates a branch node combining the sums of its children under `overflow`, failing if they overflow.
+pub(crate) fn new_branch(
+    left: Arc<dyn Node>,
+    right: Arc<dyn Node>,
+    overflow: OverflowPolicy,
+) -> Result<BranchNode> {
+    overflow
+        .combine(left.node_sum(), right.node_sum())
+        .ok_or(MssmtError::SumOverflow)?;
+    Ok(BranchNode::with_overflow_policy(left, right, overflow))
+}
+
+/// Builds the subtree rooted at `height` containing the given leaves, one level at a time.
+///
+/// The leaves must be sorted by key, have unique keys, and share the key prefix leading to the subtree.
+/// Working up from the leaves, the nodes of each level are paired with their siblings and the new
+/// branches are hashed together on `executor` (see `executor::map`) before being passed to `on_branch`,
+/// so branches are reported children first.
+///
+/// # Returns
+///
+/// - The root of the subtree.
+/// - `MssmtError::SumOverflow` if the sums of the leaves overflow.
+pub(crate) fn build_levels(
+    height: usize,
+    leaves: &[LeafNode],
+    executor: &dyn Executor,
+    mut on_branch: impl FnMut(usize, &Arc<BranchNode>),
+) -> Result<Arc<dyn Node>> {
+    if leaves.is_empty() {
+        return Ok(EMPTY_TREE[height].clone());
+    }
+
+    // Each node is kept with a key from its subtree, which carries the path to it
+    let mut level: Vec<([u8; HASH_SIZE], Arc<dyn Node>)> = leaves
+        .iter()
+        .map(|leaf| (leaf.key, Arc::new(leaf.clone()) as Arc<dyn Node>))
+        .collect();
+    executor::map(executor, &level, |(_, node)| node.node_hash());
+
+    for parent_height in (height..MAX_TREE_LEVELS).rev() {
+        let mut parents = Vec::with_capacity(level.len());
+        let mut nodes = level.into_iter().peekable();
+        while let Some((key, node)) = nodes.next() {
+            let empty = EMPTY_TREE[parent_height + 1].clone();
+            let branch = if !TreePath::from(key).is_left(Height::at(parent_height)) {
+                new_branch(empty, node, OverflowPolicy::Checked)?
+            } else {
+                match nodes.next_if(|(next, _)| {
+                    TreePath::from(*next)
+                        .has_prefix(&TreePath::from(key), Height::at(parent_height))
+                }) {
+                    Some((_, sibling)) => new_branch(node, sibling, OverflowPolicy::Checked)?,
+                    None => new_branch(node, empty, OverflowPolicy::Checked)?,
+                }
+            };
+            parents.push((key, Arc::new(branch)));
+        }
+
+        executor::map(executor, &parents, |(_, branch)| branch.node_hash());
+        for (_, branch) in &parents {
+            on_branch(parent_height, branch);
+        }
+        level = parents
+            .into_iter()
+            .map(|(key, branch)| (key, branch as Arc<dyn Node>))
+            .collect();
+    }
+
+    Ok(level.remove(0).1)
+}
+
+/// Returns the bit at a given index in a key.
+///
+/// The bits are indexed from 0 (most significant bit of the first byte) to `8 * K - 1` (least
+/// significant bit of the last byte). `TreePath::bit` reads the same bits with a typed `Height`.
 ///
 /// # Arguments
 ///
-/// - `idx`: The bit index (0..256).
-/// - `key`: A reference to a 32-byte array representing the key.
+/// - `idx`: The bit index (0..256 for 32-byte keys).
+/// - `key`: A reference to the key bytes.
 ///
 /// # Returns
 ///
@@ -322,8 +1257,79 @@ pub static EMPTY_TREE: Lazy<Vec<Arc<dyn Node>>> = Lazy::new(|| {
 /// let bit = bit_index(0, &key); // Most significant bit of the first byte
 /// assert_eq!(bit, 1);
 /// ```
-
-pub fn bit_index(idx: usize, key: &[u8; HASH_SIZE]) -> u8 {
+pub fn bit_index<const K: usize>(idx: usize, key: &[u8; K]) -> u8 {
     let byte_val = key[idx / 8];
     (byte_val >> (7 - (idx % 8))) & 1
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compacted_leaf_matches_branch_chain() {
+        let leaf = LeafNode::new([0b1010_0000; 32], b"value".to_vec(), 7);
+        for height in [0, 1, 100, 255, 256] {
+            let compacted = CompactedLeafNode::new(height, leaf.clone());
+            let extracted = compacted.extract();
+            assert_eq!(compacted.node_hash(), extracted.node_hash());
+            assert_eq!(compacted.node_sum(), extracted.node_sum());
+        }
+    }
+
+    #[test]
+    fn test_empty_tree_shares_nodes() {
+        assert!(Arc::ptr_eq(&empty_node(MAX_TREE_LEVELS), &EMPTY_LEAF));
+        for height in [0, 128, 255] {
+            let branch = EMPTY_TREE[height].as_branch().unwrap();
+            assert!(Arc::ptr_eq(&branch.left, &EMPTY_TREE[height + 1]));
+            assert!(Arc::ptr_eq(&branch.right, &EMPTY_TREE[height + 1]));
+        }
+    }
+
+    #[test]
+    fn test_empty_root_hash_constant() {
+        assert_eq!(EMPTY_ROOT_HASH, EmptyTree::hash_at(0));
+        assert_eq!(EMPTY_ROOT_HASH, EMPTY_TREE[0].node_hash());
+    }
+
+    #[test]
+    fn test_node_encoding() -> Result<()> {
+        let leaf = LeafNode::new([1u8; 32], b"ab".to_vec(), 10);
+        let mut expected = vec![1u8; 32];
+        expected.extend_from_slice(&(10 as Sum).to_be_bytes());
+        expected.extend_from_slice(b"ab");
+        assert_eq!(leaf.encode(), expected);
+        assert_eq!(
+            LeafNode::<32>::decode(&expected)?.node_hash(),
+            leaf.node_hash()
+        );
+        assert!(LeafNode::<32>::decode(&expected[..31 + SUM_SIZE]).is_err());
+        assert_eq!(
+            LeafNode::<20>::decode(&[7u8; 20 + SUM_SIZE])?.key,
+            [7u8; 20]
+        );
+
+        let branch = BranchNode::new(Arc::new(leaf.clone()), EMPTY_TREE[MAX_TREE_LEVELS].clone());
+        let encoded = branch.encode();
+        assert_eq!(encoded[..32], *leaf.node_hash().as_bytes());
+        assert_eq!(encoded[32..32 + SUM_SIZE], (10 as Sum).to_be_bytes());
+        assert_eq!(
+            BranchNode::decode(&encoded)?.node_hash(),
+            branch.node_hash()
+        );
+        assert!(BranchNode::decode(&encoded[1..]).is_err());
+
+        // Children whose sums overflow are rejected
+        let mut overflowing = encoded;
+        let right_sum = 2 * HASH_SIZE + SUM_SIZE;
+        overflowing[32..32 + SUM_SIZE].copy_from_slice(&Sum::MAX.to_be_bytes());
+        overflowing[right_sum..].copy_from_slice(&(1 as Sum).to_be_bytes());
+        assert!(matches!(
+            BranchNode::decode(&overflowing),
+            Err(MssmtError::InvalidEncoding(_))
+        ));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,177 @@
+//! Mutation hooks for the Merkle-Sum Sparse Merkle Tree.
+//!
+//! A `TreeObserver` registered on a `FullTree` is notified after every successful insert, delete and
+//! root change. Observers let applications maintain secondary indexes, write audit logs or invalidate
+//! caches without wrapping every call site that mutates the tree.
+//!
+//! Services that only react to new commitments, such as publishers and anchorers, can instead
+//! subscribe to root changes with `FullTree::subscribe` and receive `RootUpdate`s over a channel.
+
+use crate::key::Key;
+use crate::node::{LeafNode, NodeHash, Sum, HASH_SIZE};
+use std::sync::Arc;
+
+/// Callbacks invoked by a `FullTree` after it has been mutated.
+///
+/// All methods have empty default implementations, so observers only implement the events they need.
+/// Callbacks run synchronously on the mutating thread once the update is written to the store, and
+/// only for updates that succeed. Observers take `&self`; use interior mutability to record state.
+/// `K` is the key size in bytes of the observed tree and `V` the type of its values.
+///
+/// # Examples
+///
+/// ```rust
+/// use mssmt::observer::TreeObserver;
+/// use mssmt::{DefaultStore, FullTree, Key, LeafNode};
+/// use std::sync::atomic::{AtomicU64, Ordering};
+/// use std::sync::Arc;
+///
+/// #[derive(Default)]
+/// struct InsertCounter(AtomicU64);
+///
+/// impl TreeObserver for InsertCounter {
+///     fn on_insert(&self, _key: &Key, _leaf: &LeafNode, _previous: Option<&LeafNode>) {
+///         self.0.fetch_add(1, Ordering::Relaxed);
+///     }
+/// }
+///
+/// let counter = Arc::new(InsertCounter::default());
+/// let mut tree = FullTree::new(DefaultStore::new());
+/// tree.add_observer(counter.clone());
+///
+/// tree.insert([1u8; 32], b"value".to_vec(), 10).unwrap();
+/// assert_eq!(counter.0.load(Ordering::Relaxed), 1);
+/// ```
+pub trait TreeObserver<const K: usize = HASH_SIZE, V = Vec<u8>>: Send + Sync {
+    /// Called after `leaf` was inserted at `key`, with the leaf it replaced, if any.
+    fn on_insert(&self, _key: &Key<K>, _leaf: &LeafNode<K, V>, _previous: Option<&LeafNode<K, V>>) {
+    }
+
+    /// Called after the leaf at `key` was deleted. Deleting an absent key does not notify.
+    fn on_delete(&self, _key: &Key<K>, _removed: &LeafNode<K, V>) {}
+
+    /// Called after the root of the tree changed from `old_root` to `new_root`.
+    fn on_root_change(&self, _old_root: &NodeHash, _new_root: &NodeHash) {}
+}
+
+impl<T: TreeObserver<K, V> + ?Sized, const K: usize, V> TreeObserver<K, V> for Arc<T> {
+    fn on_insert(&self, key: &Key<K>, leaf: &LeafNode<K, V>, previous: Option<&LeafNode<K, V>>) {
+        (**self).on_insert(key, leaf, previous)
+    }
+
+    fn on_delete(&self, key: &Key<K>, removed: &LeafNode<K, V>) {
+        (**self).on_delete(key, removed)
+    }
+
+    fn on_root_change(&self, old_root: &NodeHash, new_root: &NodeHash) {
+        (**self).on_root_change(old_root, new_root)
+    }
+}
+
+/// A root change delivered to the subscribers of a tree, see `FullTree::subscribe`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RootUpdate {
+    /// The hash of the new root.
+    pub root_hash: NodeHash,
+    /// The sum of the new root.
+    pub root_sum: Sum,
+    /// The number of root changes made through the tree, this one included.
+    pub version: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Result;
+    use crate::node::EmptyTree;
+    use crate::store::DefaultStore;
+    use crate::tree::FullTree;
+    use parking_lot::Mutex;
+
+    #[derive(Default)]
+    struct EventLog(Mutex<Vec<String>>);
+
+    impl TreeObserver for EventLog {
+        fn on_insert(&self, key: &Key, leaf: &LeafNode, previous: Option<&LeafNode>) {
+            let previous = previous.map(|leaf| leaf.sum);
+            self.0
+                .lock()
+                .push(format!("insert {} {} {:?}", key.0[0], leaf.sum, previous));
+        }
+
+        fn on_delete(&self, key: &Key, removed: &LeafNode) {
+            self.0
+                .lock()
+                .push(format!("delete {} {}", key.0[0], removed.sum));
+        }
+
+        fn on_root_change(&self, old_root: &NodeHash, new_root: &NodeHash) {
+            assert_ne!(old_root, new_root);
+            self.0.lock().push("root".to_string());
+        }
+    }
+
+    #[test]
+    fn test_observer_events() -> Result<()> {
+        let log = Arc::new(EventLog::default());
+        let mut tree = FullTree::new(DefaultStore::new());
+        tree.add_observer(log.clone());
+
+        tree.insert([1u8; 32], b"one".to_vec(), 1)?;
+        tree.insert([1u8; 32], b"uno".to_vec(), 10)?;
+        // Failed updates and no-op deletes do not notify
+        assert!(tree.insert([2u8; 32], vec![], Sum::MAX).is_err());
+        tree.delete([3u8; 32])?;
+        tree.delete([1u8; 32])?;
+        assert!(tree.is_empty()?);
+
+        assert_eq!(
+            *log.0.lock(),
+            vec![
+                "insert 1 1 None",
+                "root",
+                "insert 1 10 Some(1)",
+                "root",
+                "delete 1 10",
+                "root",
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_root_subscriptions() -> Result<()> {
+        let mut tree = FullTree::new(DefaultStore::new());
+        let updates = tree.subscribe();
+        let dropped = tree.subscribe();
+        drop(dropped);
+
+        tree.insert([1u8; 32], b"one".to_vec(), 1)?;
+        let first = tree.root()?.node_hash();
+        // Unchanged roots are not published
+        tree.insert([1u8; 32], b"one".to_vec(), 1)?;
+        tree.delete([2u8; 32])?;
+        tree.insert([2u8; 32], b"two".to_vec(), 2)?;
+        let second = tree.root()?.node_hash();
+        tree.clear()?;
+
+        let received: Vec<_> = updates.try_iter().collect();
+        assert_eq!(
+            received
+                .iter()
+                .map(|update| (update.root_hash, update.root_sum, update.version))
+                .collect::<Vec<_>>(),
+            [(first, 1, 1), (second, 3, 2), (EmptyTree::hash_at(0), 0, 3),]
+        );
+        assert_eq!(tree.root_version(), 3);
+
+        // Subscribers can consume updates on another thread
+        let updates = tree.subscribe();
+        let consumer = std::thread::spawn(move || updates.recv().map(|update| update.root_sum));
+        tree.insert([3u8; 32], b"three".to_vec(), 3)?;
+        assert_eq!(consumer.join().unwrap(), Ok(3));
+
+        Ok(())
+    }
+}
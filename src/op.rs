@@ -0,0 +1,139 @@
+//! Tree operations as values.
+//!
+//! An `Op` describes a single insert or delete without applying it, so a set of updates can be
+//! passed around, inspected, and evaluated against a tree. `FullTree::root_after` computes the root a
+//! set of operations would produce while leaving the tree untouched.
+
+use crate::error::Result;
+use crate::key::Key;
+use crate::node::{NodeHash, Sum};
+use crate::store::{OverlayStore, TreeStore, TreeStoreReader};
+use crate::tree::FullTree;
+
+/// A single update to a tree.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum Op {
+    /// Inserts or overwrites the leaf at `key`.
+    Insert { key: Key, value: Vec<u8>, sum: Sum },
+    /// Deletes the leaf at `key`, if present.
+    Delete { key: Key },
+}
+
+impl Op {
+    /// Creates an insert operation.
+    pub fn insert(key: impl Into<Key>, value: Vec<u8>, sum: Sum) -> Self {
+        Op::Insert {
+            key: key.into(),
+            value,
+            sum,
+        }
+    }
+
+    /// Creates a delete operation.
+    pub fn delete(key: impl Into<Key>) -> Self {
+        Op::Delete { key: key.into() }
+    }
+
+    /// Returns the key the operation applies to.
+    pub fn key(&self) -> Key {
+        match self {
+            Op::Insert { key, .. } | Op::Delete { key } => *key,
+        }
+    }
+
+    /// Applies the operation to `tree`.
+    pub(crate) fn apply<S: TreeStore>(&self, tree: &mut FullTree<S>) -> Result<()> {
+        match self {
+            Op::Insert { key, value, sum } => {
+                tree.insert(*key, value.clone(), *sum)?;
+            }
+            Op::Delete { key } => {
+                tree.delete(*key)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<S: TreeStoreReader> FullTree<S> {
+    /// Computes the root hash and sum the tree would have after applying `ops` in order.
+    ///
+    /// The operations are applied to an in-memory overlay on top of the store, so nothing is written
+    /// to the store and the tree is left unchanged. This allows committing to a future root, for
+    /// example by signing it, before the updates are actually applied.
+    ///
+    /// # Arguments
+    ///
+    /// - `ops`: The operations to evaluate, in order.
+    ///
+    /// # Returns
+    ///
+    /// - The prospective root hash and sum.
+    /// - `MssmtError::SumOverflow` if the operations would overflow the root sum.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use mssmt::op::Op;
+    /// use mssmt::{DefaultStore, FullTree, Node};
+    ///
+    /// let mut tree = FullTree::new(DefaultStore::new());
+    /// tree.insert([1u8; 32], b"one".to_vec(), 1).unwrap();
+    ///
+    /// let ops = [Op::insert([2u8; 32], b"two".to_vec(), 2), Op::delete([1u8; 32])];
+    /// let (root_hash, sum) = tree.root_after(&ops).unwrap();
+    /// assert_eq!(sum, 2);
+    /// assert_eq!(tree.total_sum().unwrap(), 1);
+    ///
+    /// tree.insert([2u8; 32], b"two".to_vec(), 2).unwrap();
+    /// tree.delete([1u8; 32]).unwrap();
+    /// assert_eq!(tree.root().unwrap().node_hash(), root_hash);
+    /// ```
+    pub fn root_after(&self, ops: &[Op]) -> Result<(NodeHash, Sum)> {
+        let mut staged = FullTree::new(OverlayStore::new(self.store()));
+        for op in ops {
+            op.apply(&mut staged)?;
+        }
+
+        let root = staged.root()?;
+        Ok((root.node_hash(), root.node_sum()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::DefaultStore;
+
+    #[test]
+    fn test_root_after_matches_applied_ops() -> Result<()> {
+        let mut tree = FullTree::new(DefaultStore::new());
+        for i in 0..4u8 {
+            tree.insert([i; 32], vec![i], i as Sum)?;
+        }
+        let root_hash = tree.root()?.node_hash();
+        let branches = tree.store().branches.len();
+
+        let ops = vec![
+            Op::delete([1u8; 32]),
+            Op::insert([2u8; 32], b"updated".to_vec(), 20),
+            Op::insert([9u8; 32], b"new".to_vec(), 9),
+            Op::delete([42u8; 32]),
+        ];
+        let prospective = tree.root_after(&ops)?;
+
+        // Nothing was written
+        assert_eq!(tree.root()?.node_hash(), root_hash);
+        assert_eq!(tree.store().branches.len(), branches);
+        assert_eq!(tree.root_after(&[])?, (root_hash, 6));
+
+        for op in &ops {
+            op.apply(&mut tree)?;
+        }
+        let root = tree.root()?;
+        assert_eq!(prospective, (root.node_hash(), root.node_sum()));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,271 @@
+//! Parallel batch inserts for the Merkle-Sum Sparse Merkle Tree.
+//!
+//! Leaves are placed by key bits, so the subtrees below the first `PARTITION_BITS` levels never share a
+//! node: a batch split by the top bits of its keys can update each of them independently.
+//! `FullTree::par_insert_batch` rebuilds the affected subtrees as tasks of the executor of the tree, see
+//! `FullTree::set_executor`, then writes the new nodes to the store and merges the subtree roots into the
+//! new root on the calling thread.
+
+use crate::error::{MssmtError, Result};
+use crate::executor::Task;
+use crate::key::Key;
+use crate::node::{new_branch, LeafNode, Node, NodeHash, Sum};
+use crate::store::{resolve_node, TreeStore, TreeStoreReader};
+use crate::tree::{merge_leaves, FullTree, SubtreeUpdate};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+/// The number of top key bits a batch is partitioned by, giving `2^PARTITION_BITS` subtrees.
+pub const PARTITION_BITS: usize = 4;
+
+impl<S: TreeStore + Sync> FullTree<S> {
+    /// Inserts a batch of key-value-sum entries, updating disjoint subtrees in parallel.
+    ///
+    /// The batch is partitioned by the top `PARTITION_BITS` bits of the keys and each partition is
+    /// merged into its subtree as a task of the executor of the tree, reading the store concurrently on
+    /// a multi-threaded executor. The resulting
+    /// tree is the same as inserting the entries one by one: a later entry for a key overwrites an
+    /// earlier one, and observers are notified of every inserted leaf and of the root change.
+    ///
+    /// Nothing is written to the store unless the whole batch applies, so a batch overflowing the root
+    /// sum or holding a leaf rejected by the configuration of the tree leaves the tree unchanged.
+    ///
+    /// # Arguments
+    ///
+    /// - `entries`: The keys, values and sums to insert.
+    ///
+    /// # Returns
+    ///
+    /// - The hash of the new root.
+    /// - `MssmtError::SumOverflow` if the batch would overflow the root sum.
+    /// - `MssmtError::InvalidLeaf` if a leaf is rejected by the configuration of the tree.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use mssmt::{DefaultStore, FullTree, Node};
+    ///
+    /// let mut tree = FullTree::new(DefaultStore::new());
+    /// let entries = (0..100u8).map(|i| ([i; 32], vec![i], i.into()));
+    /// let root_hash = tree.par_insert_batch(entries).unwrap();
+    ///
+    /// assert_eq!(tree.root().unwrap().node_hash(), root_hash);
+    /// assert_eq!(tree.get([42u8; 32]).unwrap(), Some((vec![42], 42)));
+    /// assert_eq!(tree.total_sum().unwrap(), 4950);
+    /// ```
+    pub fn par_insert_batch<T: Into<Key>>(
+        &mut self,
+        entries: impl IntoIterator<Item = (T, Vec<u8>, Sum)>,
+    ) -> Result<NodeHash> {
+        let mut batch = BTreeMap::new();
+        for (key, value, sum) in entries {
+            batch.insert(key.into().0, (value, sum));
+        }
+        let leaves: Vec<LeafNode> = batch
+            .into_iter()
+            .map(|(key, (value, sum))| LeafNode::new(key, value, sum))
+            .collect();
+        for leaf in &leaves {
+            self.config().validate(leaf)?;
+        }
+
+        let root = self.root()?;
+        let (old_root_hash, old_root_sum) = (root.node_hash(), root.node_sum());
+        if leaves.is_empty() {
+            return Ok(old_root_hash);
+        }
+
+        let mut subtrees = Vec::with_capacity(1 << PARTITION_BITS);
+        partition_roots(self.store(), root, 0, &mut subtrees)?;
+
+        // The leaves are sorted, so each partition is a contiguous run of them
+        let mut partitions = Vec::with_capacity(subtrees.len());
+        let mut rest = &leaves[..];
+        for index in 0..subtrees.len() {
+            let split = rest.partition_point(|leaf| partition(&leaf.key) == index);
+            let (current, next) = rest.split_at(split);
+            partitions.push(current);
+            rest = next;
+        }
+
+        let overflow = self.config().overflow_policy();
+        let store = self.store();
+        let mut results = Vec::new();
+        results.resize_with(subtrees.len(), || None);
+        let tasks = subtrees
+            .into_iter()
+            .zip(partitions)
+            .zip(results.iter_mut())
+            .map(|((subtree, leaves), result)| {
+                Box::new(move || {
+                    let mut update = SubtreeUpdate::default();
+                    let root = merge_leaves(
+                        store,
+                        subtree,
+                        PARTITION_BITS,
+                        leaves,
+                        overflow,
+                        &mut update,
+                    );
+                    *result = Some(root.map(|root| (root, update)));
+                }) as Task<'_>
+            })
+            .collect();
+        self.executor().run(tasks);
+        let updated = results
+            .into_iter()
+            .map(|result| result.expect("executors run every task"))
+            .collect::<Result<Vec<_>>>()?;
+
+        let (mut level, updates): (Vec<_>, Vec<_>) = updated.into_iter().unzip();
+        let mut top = Vec::with_capacity(level.len());
+        let mut height = PARTITION_BITS;
+        while level.len() > 1 {
+            height -= 1;
+            level = level
+                .chunks(2)
+                .map(|pair| {
+                    let branch = Arc::new(new_branch(pair[0].clone(), pair[1].clone(), overflow)?);
+                    top.push((height, branch.clone()));
+                    Ok(branch as Arc<dyn Node>)
+                })
+                .collect::<Result<_>>()?;
+        }
+        let new_root = level.remove(0);
+        let changes = updates.iter().flat_map(|update| {
+            update.leaves.iter().map(|(leaf, previous)| {
+                (
+                    leaf.sum,
+                    previous.as_ref().map_or(0, |previous| previous.sum),
+                )
+            })
+        });
+        self.config()
+            .check_root_sum(old_root_sum, changes, new_root.node_sum())?;
+        let root_hash = new_root.node_hash();
+
+        let store = self.store_mut();
+        for update in &updates {
+            for (leaf, _) in &update.leaves {
+                store.insert_leaf(leaf.clone())?;
+            }
+            for (height, branch) in &update.branches {
+                store.insert_branch_at(*height, branch.clone())?;
+            }
+        }
+        for (height, branch) in top {
+            store.insert_branch_at(height, branch)?;
+        }
+        store.update_root(new_root.clone())?;
+
+        for update in &updates {
+            for (leaf, previous) in &update.leaves {
+                self.notify_insert(leaf, previous.as_ref());
+            }
+        }
+        self.notify_root_change(old_root_hash, new_root.as_ref());
+        self.stamp_leaf_metadata()?;
+        Ok(root_hash)
+    }
+}
+
+/// Returns the index of the partition holding `key`.
+fn partition(key: &[u8; 32]) -> usize {
+    (key[0] >> (8 - PARTITION_BITS)) as usize
+}
+
+/// Appends the roots of the subtrees at height `PARTITION_BITS` below `node`, in key order.
+fn partition_roots<S: TreeStoreReader + ?Sized>(
+    store: &S,
+    node: Arc<dyn Node>,
+    height: usize,
+    roots: &mut Vec<Arc<dyn Node>>,
+) -> Result<()> {
+    if height == PARTITION_BITS {
+        roots.push(node);
+        return Ok(());
+    }
+
+    let node = resolve_node(store, &node, height)?;
+    let Some(branch) = node.as_branch() else {
+        return Err(MssmtError::NodeNotFound(node.node_hash()));
+    };
+    partition_roots(store, branch.left.clone(), height + 1, roots)?;
+    partition_roots(store, branch.right.clone(), height + 1, roots)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::observer::TreeObserver;
+    use crate::store::DefaultStore;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[derive(Default)]
+    struct Counter {
+        inserts: AtomicUsize,
+        replaced: AtomicUsize,
+        root_changes: AtomicUsize,
+    }
+
+    impl TreeObserver for Counter {
+        fn on_insert(&self, _key: &Key, _leaf: &LeafNode, previous: Option<&LeafNode>) {
+            self.inserts.fetch_add(1, Ordering::Relaxed);
+            if previous.is_some() {
+                self.replaced.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        fn on_root_change(&self, _old_root: &NodeHash, _new_root: &NodeHash) {
+            self.root_changes.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn test_par_insert_batch_matches_sequential_inserts() -> Result<()> {
+        let existing: Vec<_> = (0..20u8).map(|i| (Key::hash([i]), vec![i], 1)).collect();
+        let batch: Vec<_> = (10..200u8)
+            .map(|i| (Key::hash([i]), vec![i, i], i as Sum))
+            .chain([(Key::hash([0]), b"last".to_vec(), 7)])
+            .collect();
+
+        let mut sequential = FullTree::new(DefaultStore::new());
+        let mut parallel = FullTree::new(DefaultStore::new());
+        for (key, value, sum) in &existing {
+            sequential.insert(*key, value.clone(), *sum)?;
+            parallel.insert(*key, value.clone(), *sum)?;
+        }
+        for (key, value, sum) in &batch {
+            sequential.insert(*key, value.clone(), *sum)?;
+        }
+
+        let counter = Arc::new(Counter::default());
+        parallel.add_observer(counter.clone());
+        let root_hash = parallel.par_insert_batch(batch)?;
+        assert_eq!(root_hash, sequential.root()?.node_hash());
+        assert_eq!(parallel.get(Key::hash([0]))?, Some((b"last".to_vec(), 7)));
+        assert_eq!(counter.inserts.load(Ordering::Relaxed), 191);
+        assert_eq!(counter.replaced.load(Ordering::Relaxed), 11);
+        assert_eq!(counter.root_changes.load(Ordering::Relaxed), 1);
+
+        // The written nodes are enough to serve proofs
+        let leaf = LeafNode::new(Key::hash([150]).0, vec![150, 150], 150);
+        let proof = parallel.merkle_proof(Key::hash([150]))?;
+        assert!(proof.verify(Key::hash([150]), &leaf, root_hash));
+
+        // An overflowing batch writes nothing
+        let leaves = parallel.store().leaves.len();
+        assert!(matches!(
+            parallel.par_insert_batch([([0u8; 32], vec![], Sum::MAX)]),
+            Err(MssmtError::SumOverflow)
+        ));
+        assert_eq!(parallel.root()?.node_hash(), root_hash);
+        assert_eq!(parallel.store().leaves.len(), leaves);
+        assert_eq!(
+            parallel.par_insert_batch(Vec::<(Key, _, _)>::new())?,
+            root_hash
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,364 @@
+//! Inspection of the path from the root of a tree to a key.
+//!
+//! `FullTree::path` lists, for each height, the branch taken towards a key and the sibling left behind.
+//! The siblings are those of `FullTree::merkle_proof`, so debuggers and explorers can show where a key
+//! lives and which of its proof nodes are empty subtrees.
+//!
+//! The path arithmetic of the tree is exposed through `Height`, a validated level of a tree, and
+//! `TreePath`, a key read as the directions from the root to its leaf, so that stores and proof code
+//! outside the crate walk trees with the same conventions: the root is at height 0, the leaves at
+//! `tree_levels(K)`, and the key bit at a height, most significant first, picks the child taken there.
+
+use crate::error::{MssmtError, Result};
+use crate::key::Key;
+use crate::node::{tree_levels, EmptyTreeOf, LeafValue, NodeHash, Sum, HASH_SIZE};
+use crate::store::{node_children, TreeStoreReader};
+use crate::tree::FullTree;
+use std::fmt;
+
+/// A level of a tree, from 0 at the root to `tree_levels(K)` at the leaves.
+///
+/// # Examples
+///
+/// ```rust
+/// use mssmt::path::Height;
+///
+/// let height = Height::new::<32>(255).unwrap();
+/// assert_eq!(height.child::<32>(), Some(Height::leaf::<32>()));
+/// assert!(Height::leaf::<32>().child::<32>().is_none());
+/// assert!(Height::new::<32>(257).is_err());
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Height(u16);
+
+impl Height {
+    /// The height of the root.
+    pub const ROOT: Height = Height(0);
+
+    /// Creates the height `height` of a tree with `K`-byte keys.
+    ///
+    /// # Returns
+    ///
+    /// - `MssmtError::InvalidHeight` if `height` is beyond the leaf level.
+    pub fn new<const K: usize>(height: usize) -> Result<Self> {
+        if height > tree_levels(K) {
+            return Err(MssmtError::InvalidHeight(height));
+        }
+        Ok(Self(height as u16))
+    }
+
+    /// Creates a height the caller already bounded by the leaf level, such as the height of a
+    /// traversal.
+    pub(crate) const fn at(height: usize) -> Self {
+        Self(height as u16)
+    }
+
+    /// Returns the height of the leaves of a tree with `K`-byte keys.
+    pub const fn leaf<const K: usize>() -> Self {
+        Self(tree_levels(K) as u16)
+    }
+
+    /// Returns the height as an index.
+    pub const fn get(self) -> usize {
+        self.0 as usize
+    }
+
+    /// Returns `true` if the height is the leaf level of a tree with `K`-byte keys.
+    pub const fn is_leaf_level<const K: usize>(self) -> bool {
+        self.get() == tree_levels(K)
+    }
+
+    /// Returns the height of the children of the nodes at this height, or `None` at the leaf level.
+    pub const fn child<const K: usize>(self) -> Option<Self> {
+        if self.is_leaf_level::<K>() {
+            None
+        } else {
+            Some(Self(self.0 + 1))
+        }
+    }
+
+    /// Returns the height of the parents of the nodes at this height, or `None` at the root.
+    pub const fn parent(self) -> Option<Self> {
+        match self.0 {
+            0 => None,
+            height => Some(Self(height - 1)),
+        }
+    }
+}
+
+impl From<Height> for usize {
+    fn from(height: Height) -> Self {
+        height.get()
+    }
+}
+
+impl fmt::Display for Height {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// A key read as the path from the root to its leaf.
+///
+/// The step at a height goes left if the key bit at that height is 0 and right otherwise. Steps exist
+/// at the heights above the leaf level only, and the methods taking one panic at the leaf level.
+///
+/// # Examples
+///
+/// ```rust
+/// use mssmt::path::{Branch, Height, TreePath};
+///
+/// let path = TreePath::new([0b0100_0000; 32]);
+/// assert!(path.is_left(Height::ROOT));
+/// assert_eq!(path.branch(Height::new::<32>(1).unwrap()), Branch::Right);
+/// // The proof sibling of a right step is the left child
+/// assert_eq!(path.sibling_index(Height::new::<32>(1).unwrap()), 0);
+///
+/// let other = TreePath::new([0b0110_0000; 32]);
+/// assert_eq!(path.divergence(&other), Some(Height::new::<32>(2).unwrap()));
+/// assert!(path.has_prefix(&other, Height::new::<32>(2).unwrap()));
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TreePath<const K: usize = HASH_SIZE>([u8; K]);
+
+impl<const K: usize> TreePath<K> {
+    /// Creates the path to `key`.
+    pub fn new(key: impl Into<Key<K>>) -> Self {
+        Self(key.into().0)
+    }
+
+    /// Returns the key the path leads to.
+    pub fn key(&self) -> Key<K> {
+        Key(self.0)
+    }
+
+    /// Returns the key bit read at `height`, 0 for a left step and 1 for a right step.
+    pub fn bit(&self, height: Height) -> u8 {
+        let height = self.step_index(height);
+        (self.0[height / 8] >> (7 - height % 8)) & 1
+    }
+
+    /// Returns `true` if the path goes to the left child at `height`.
+    pub fn is_left(&self, height: Height) -> bool {
+        self.bit(height) == 0
+    }
+
+    /// Returns the child taken at `height`.
+    pub fn branch(&self, height: Height) -> Branch {
+        if self.is_left(height) {
+            Branch::Left
+        } else {
+            Branch::Right
+        }
+    }
+
+    /// Returns the index of the sibling left behind at `height` among the two children, 0 for the left
+    /// child and 1 for the right one.
+    pub fn sibling_index(&self, height: Height) -> usize {
+        1 - self.bit(height) as usize
+    }
+
+    /// Returns the steps of the path, root first.
+    pub fn branches(&self) -> impl Iterator<Item = Branch> + '_ {
+        (0..tree_levels(K)).map(|height| self.branch(Height::at(height)))
+    }
+
+    /// Returns `true` if the path shares its steps above `height` with `prefix`, that is if their keys
+    /// share their first `height` bits, so that both pass through the same node at `height`.
+    pub fn has_prefix(&self, prefix: &TreePath<K>, height: Height) -> bool {
+        let (bytes, bits) = (height.get() / 8, height.get() % 8);
+        self.0[..bytes] == prefix.0[..bytes]
+            && (bits == 0 || (self.0[bytes] ^ prefix.0[bytes]) >> (8 - bits) == 0)
+    }
+
+    /// Returns the height of the first step on which the paths part, or `None` for paths to the same
+    /// key. Both paths pass through the same branch at that height.
+    pub fn divergence(&self, other: &TreePath<K>) -> Option<Height> {
+        (0..tree_levels(K))
+            .map(Height::at)
+            .find(|height| self.bit(*height) != other.bit(*height))
+    }
+
+    /// Returns the index of the key bit read at `height`.
+    fn step_index(&self, height: Height) -> usize {
+        assert!(
+            height.get() < tree_levels(K),
+            "no step at height {height} of a path of {} steps",
+            tree_levels(K)
+        );
+        height.get()
+    }
+}
+
+impl<const K: usize> From<[u8; K]> for TreePath<K> {
+    fn from(key: [u8; K]) -> Self {
+        Self(key)
+    }
+}
+
+impl<const K: usize> From<Key<K>> for TreePath<K> {
+    fn from(key: Key<K>) -> Self {
+        Self(key.0)
+    }
+}
+
+/// The child of a branch taken on the path to a key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Branch {
+    Left,
+    Right,
+}
+
+impl fmt::Display for Branch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Branch::Left => write!(f, "left"),
+            Branch::Right => write!(f, "right"),
+        }
+    }
+}
+
+/// One step of the path from the root to a key.
+///
+/// # Fields
+///
+/// - `height`: The height of the branch the step starts from, the root being at height 0.
+/// - `branch`: The child taken towards the key, given by the key bit at `height`.
+/// - `sibling_hash`: The hash of the child not taken.
+/// - `sibling_sum`: The sum of the child not taken.
+/// - `sibling_empty`: Whether the child not taken is an empty subtree.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PathStep {
+    pub height: usize,
+    pub branch: Branch,
+    pub sibling_hash: NodeHash,
+    pub sibling_sum: Sum,
+    pub sibling_empty: bool,
+}
+
+impl<S: TreeStoreReader<K, V>, const K: usize, V: LeafValue> FullTree<S, K, V> {
+    /// Returns the path from the root to `key`, one step per height starting at the root.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use mssmt::path::Branch;
+    /// use mssmt::{DefaultStore, FullTree};
+    ///
+    /// let mut tree = FullTree::new(DefaultStore::new());
+    /// tree.insert([0x00; 32], b"left".to_vec(), 1).unwrap();
+    /// tree.insert([0x80; 32], b"right".to_vec(), 2).unwrap();
+    ///
+    /// let path = tree.path([0x80; 32]).unwrap();
+    /// assert_eq!(path.len(), 256);
+    /// assert_eq!(path[0].branch, Branch::Right);
+    /// assert_eq!(path[0].sibling_sum, 1);
+    /// assert!(path[1..].iter().all(|step| step.sibling_empty));
+    /// ```
+    ///
+    /// # Returns
+    ///
+    /// - The steps of the path, whose siblings are the nodes of the Merkle proof of `key`.
+    /// - `MssmtError::NodeNotFound` if a node on the path is missing from the store.
+    pub fn path(&self, key: impl Into<Key<K>>) -> Result<Vec<PathStep>> {
+        let path = TreePath::<K>::new(key);
+        let mut node = self.store().root_node()?;
+        let mut steps = Vec::with_capacity(tree_levels(K));
+        for (height, branch) in path.branches().enumerate() {
+            let (left, right) = node_children(self.store(), &node, height)?;
+            let (child, sibling) = match branch {
+                Branch::Left => (left, right),
+                Branch::Right => (right, left),
+            };
+            let sibling_hash = sibling.node_hash();
+            steps.push(PathStep {
+                height,
+                branch,
+                sibling_hash,
+                sibling_sum: sibling.node_sum(),
+                sibling_empty: EmptyTreeOf::<K>::is_empty_at(height + 1, &sibling_hash),
+            });
+            node = child;
+        }
+        Ok(steps)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::DefaultStore;
+
+    #[test]
+    fn test_path_matches_proof() -> Result<()> {
+        let mut tree = FullTree::new(DefaultStore::new());
+        tree.insert([0x40; 32], b"one".to_vec(), 1)?;
+        tree.insert([0x60; 32], b"two".to_vec(), 2)?;
+        tree.insert([0xc0; 32], b"three".to_vec(), 3)?;
+
+        let path = tree.path([0x40; 32])?;
+        let proof = tree.merkle_proof([0x40; 32])?;
+        assert_eq!(path.len(), proof.nodes.len());
+        for (step, sibling) in path.iter().zip(&proof.nodes) {
+            assert_eq!(
+                (step.sibling_hash, step.sibling_sum),
+                (sibling.node_hash(), sibling.node_sum())
+            );
+        }
+
+        // 0x40 and 0x60 diverge at the third bit, below the sibling holding 0xc0
+        assert_eq!(
+            path[..3].iter().map(|step| step.branch).collect::<Vec<_>>(),
+            [Branch::Left, Branch::Right, Branch::Left]
+        );
+        assert_eq!((path[0].sibling_sum, path[2].sibling_sum), (3, 2));
+        assert_eq!(path.iter().filter(|step| !step.sibling_empty).count(), 2);
+        assert_eq!(
+            path.iter().filter(|step| !step.sibling_empty).count(),
+            proof.non_empty_nodes()
+        );
+
+        // Paths to absent keys end in the empty subtree they would be inserted into
+        let path = tree.path([0x20; 32])?;
+        assert_eq!((path[0].sibling_sum, path[1].sibling_sum), (3, 3));
+        assert!(path[2..].iter().all(|step| step.sibling_empty));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tree_paths_follow_key_bits() -> Result<()> {
+        let path = TreePath::<4>::new([0b1000_0001, 0, 0, 1]);
+        let steps: Vec<Branch> = path.branches().collect();
+        assert_eq!(steps.len(), 32);
+        assert_eq!(
+            (steps[0], steps[7], steps[31]),
+            (Branch::Right, Branch::Right, Branch::Right)
+        );
+        assert_eq!(
+            steps.iter().filter(|step| **step == Branch::Right).count(),
+            3
+        );
+        assert_eq!(path.sibling_index(Height::ROOT), 0);
+        assert_eq!(path.sibling_index(Height::new::<4>(1)?), 1);
+
+        let other = TreePath::<4>::new([0b1000_0001, 0, 0x80, 1]);
+        assert_eq!(path.divergence(&other), Some(Height::new::<4>(16)?));
+        assert!(path.has_prefix(&other, Height::new::<4>(16)?));
+        assert!(!path.has_prefix(&other, Height::new::<4>(17)?));
+        assert_eq!(path.divergence(&path), None);
+
+        assert!(matches!(
+            Height::new::<4>(33),
+            Err(MssmtError::InvalidHeight(33))
+        ));
+        assert_eq!(Height::ROOT.parent(), None);
+        assert_eq!(
+            Height::leaf::<4>().parent().and_then(Height::child::<4>),
+            Some(Height::leaf::<4>())
+        );
+        assert!(std::panic::catch_unwind(|| path.bit(Height::leaf::<4>())).is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,315 @@
+//! Poseidon commitments for the Merkle-Sum Sparse Merkle Tree.
+//!
+//! SHA-256 is expensive to prove inside SNARK circuits. This module commits to the same tree with the
+//! Poseidon permutation over the BN254 scalar field, using the circom parameters, so inclusion and sum
+//! constraints can be checked in a circuit at a fraction of the cost.
+//!
+//! Nodes keep their SHA-256 hashes, which stores use to address them. The Poseidon root and proofs are
+//! computed from the leaves of the tree and committed to separately, for instance next to the SHA-256
+//! root. Hashes are field elements, encoded as 32 big-endian bytes in a `NodeHash`.
+//!
+//! # Layout
+//!
+//! - A value hashes to `v_0 = len`, then `v_i = Poseidon(v_{i-1}, chunk_i)` over its 31-byte big-endian
+//!   chunks, so every chunk fits in a field element.
+//! - A leaf hashes to `Poseidon(key_hi, key_lo, value_hash, sum)`, where `key_hi` and `key_lo` are the
+//!   first and last 16 bytes of the key read as big-endian integers.
+//! - A branch hashes to `Poseidon(left, right, sum)`.
+//!
+//! Like in the SHA-256 tree, empty subtrees are built from the leaf with the all-zero key, an empty value
+//! and a sum of 0.
+
+use crate::error::{MssmtError, ProofError, Result};
+use crate::hash_utils::to_array;
+use crate::key::Key;
+use crate::node::{
+    bit_index, collect_leaves, LeafNode, NodeHash, Sum, EMPTY_LEAF_NODE, MAX_TREE_LEVELS,
+};
+use crate::store::TreeStoreReader;
+use crate::tree::FullTree;
+use ark_bn254::Fr;
+use ark_ff::{BigInteger, PrimeField};
+use light_poseidon::{Poseidon, PoseidonHasher};
+use once_cell::sync::Lazy;
+use std::cell::RefCell;
+
+/// The number of value bytes absorbed per field element.
+pub const VALUE_CHUNK_SIZE: usize = 31;
+
+/// The size of each of the two key limbs.
+const KEY_LIMB_SIZE: usize = 16;
+
+/// Poseidon instances for each input width, reused across calls on a thread.
+struct Hashers {
+    two: Poseidon<Fr>,
+    three: Poseidon<Fr>,
+    four: Poseidon<Fr>,
+}
+
+impl Hashers {
+    fn new() -> Self {
+        let hasher = |inputs| Poseidon::<Fr>::new_circom(inputs).expect("supported circom width");
+        Self {
+            two: hasher(2),
+            three: hasher(3),
+            four: hasher(4),
+        }
+    }
+}
+
+thread_local! {
+    static HASHERS: RefCell<Hashers> = RefCell::new(Hashers::new());
+}
+
+fn poseidon(inputs: &[Fr]) -> Fr {
+    HASHERS.with(|hashers| {
+        let mut hashers = hashers.borrow_mut();
+        let hasher = match inputs.len() {
+            2 => &mut hashers.two,
+            3 => &mut hashers.three,
+            _ => &mut hashers.four,
+        };
+        hasher.hash(inputs).expect("inputs match the hasher width")
+    })
+}
+
+fn to_field(hash: &NodeHash) -> Fr {
+    Fr::from_be_bytes_mod_order(hash.as_bytes())
+}
+
+fn to_hash(element: Fr) -> NodeHash {
+    NodeHash::new(to_array(&element.into_bigint().to_bytes_be()))
+}
+
+/// Returns the Poseidon hash of a leaf value.
+pub fn value_hash(value: &[u8]) -> NodeHash {
+    let hash = value
+        .chunks(VALUE_CHUNK_SIZE)
+        .fold(Fr::from(value.len() as u64), |hash, chunk| {
+            poseidon(&[hash, Fr::from_be_bytes_mod_order(chunk)])
+        });
+    to_hash(hash)
+}
+
+/// Returns the Poseidon hash of a leaf.
+pub fn leaf_hash(leaf: &LeafNode) -> NodeHash {
+    let (key_hi, key_lo) = leaf.key.split_at(KEY_LIMB_SIZE);
+    to_hash(poseidon(&[
+        Fr::from_be_bytes_mod_order(key_hi),
+        Fr::from_be_bytes_mod_order(key_lo),
+        to_field(&value_hash(&leaf.value)),
+        Fr::from(leaf.sum),
+    ]))
+}
+
+/// Returns the Poseidon hash of a branch over its children's hashes and its sum.
+pub fn branch_hash(left: &NodeHash, right: &NodeHash, sum: Sum) -> NodeHash {
+    to_hash(poseidon(&[to_field(left), to_field(right), Fr::from(sum)]))
+}
+
+static EMPTY_HASHES: Lazy<Vec<NodeHash>> = Lazy::new(|| {
+    let mut hashes = vec![leaf_hash(&EMPTY_LEAF_NODE)];
+    for _ in 0..MAX_TREE_LEVELS {
+        let child = hashes[hashes.len() - 1];
+        hashes.push(branch_hash(&child, &child, 0));
+    }
+    hashes.reverse();
+    hashes
+});
+
+/// Returns the Poseidon hash of the empty subtree at `height`, from 0 at the root to 256 at the leaves.
+///
+/// # Panics
+///
+/// Panics if `height` is larger than 256.
+pub fn empty_hash_at(height: usize) -> NodeHash {
+    EMPTY_HASHES[height]
+}
+
+/// Returns the Poseidon hash and sum of the subtree at `height` holding the given leaves.
+///
+/// The leaves must be sorted by key and share the key prefix leading to the subtree.
+fn subtree_root(height: usize, leaves: &[LeafNode]) -> Result<(NodeHash, Sum)> {
+    if leaves.is_empty() {
+        return Ok((empty_hash_at(height), 0));
+    }
+    if height == MAX_TREE_LEVELS {
+        return Ok((leaf_hash(&leaves[0]), leaves[0].sum));
+    }
+    let split = leaves.partition_point(|leaf| bit_index(height, &leaf.key) == 0);
+    let (left, left_sum) = subtree_root(height + 1, &leaves[..split])?;
+    let (right, right_sum) = subtree_root(height + 1, &leaves[split..])?;
+    let sum = left_sum
+        .checked_add(right_sum)
+        .ok_or(MssmtError::SumOverflow)?;
+    Ok((branch_hash(&left, &right, sum), sum))
+}
+
+/// A Merkle proof of a leaf against the Poseidon root of a tree.
+///
+/// # Fields
+///
+/// - `nodes`: The Poseidon hash and sum of each sibling, from the root down to the leaves, like
+///   `Proof::nodes`.
+///
+/// # Examples
+///
+/// ```rust
+/// use mssmt::{DefaultStore, FullTree, LeafNode};
+///
+/// let mut tree = FullTree::new(DefaultStore::new());
+/// tree.insert([1u8; 32], b"one".to_vec(), 1).unwrap();
+/// tree.insert([2u8; 32], b"two".to_vec(), 2).unwrap();
+///
+/// let (root_hash, root_sum) = tree.poseidon_root().unwrap();
+/// let proof = tree.poseidon_proof([1u8; 32]).unwrap();
+/// let leaf = LeafNode::new([1u8; 32], b"one".to_vec(), 1);
+/// assert_eq!(proof.root([1u8; 32], &leaf), Ok((root_hash, root_sum)));
+/// assert!(proof.verify([1u8; 32], &leaf, root_hash));
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PoseidonProof {
+    pub nodes: Vec<(NodeHash, Sum)>,
+}
+
+impl PoseidonProof {
+    /// Creates a proof from its siblings, root first.
+    pub fn new(nodes: Vec<(NodeHash, Sum)>) -> Self {
+        Self { nodes }
+    }
+
+    /// Checks that the proof contains exactly one sibling per tree level.
+    pub fn validate(&self) -> std::result::Result<(), ProofError> {
+        if self.nodes.len() != MAX_TREE_LEVELS {
+            return Err(ProofError::InvalidLength {
+                expected: MAX_TREE_LEVELS,
+                actual: self.nodes.len(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Computes the Poseidon root hash and sum from the proof and the given leaf.
+    pub fn root(
+        &self,
+        key: impl Into<Key>,
+        leaf: &LeafNode,
+    ) -> std::result::Result<(NodeHash, Sum), ProofError> {
+        let key = key.into().0;
+        self.validate()?;
+        if !leaf.is_empty() && leaf.key != key {
+            return Err(ProofError::KeyMismatch);
+        }
+
+        let mut hash = leaf_hash(leaf);
+        let mut sum = leaf.sum;
+        for (height, (sibling_hash, sibling_sum)) in self.nodes.iter().enumerate().rev() {
+            sum = sum
+                .checked_add(*sibling_sum)
+                .ok_or(ProofError::SumOverflow { height })?;
+            hash = if bit_index(height, &key) == 0 {
+                branch_hash(&hash, sibling_hash, sum)
+            } else {
+                branch_hash(sibling_hash, &hash, sum)
+            };
+        }
+        Ok((hash, sum))
+    }
+
+    /// Verifies the proof against a Poseidon root hash.
+    pub fn verify(&self, key: impl Into<Key>, leaf: &LeafNode, root_hash: NodeHash) -> bool {
+        self.verify_detailed(key, leaf, root_hash).is_ok()
+    }
+
+    /// Verifies the proof against a Poseidon root hash, reporting why verification failed.
+    pub fn verify_detailed(
+        &self,
+        key: impl Into<Key>,
+        leaf: &LeafNode,
+        root_hash: NodeHash,
+    ) -> std::result::Result<(), ProofError> {
+        let (hash, _) = self.root(key, leaf)?;
+        if !hash.constant_time_eq(&root_hash) {
+            return Err(ProofError::RootHashMismatch {
+                expected: root_hash,
+                actual: hash,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl<S: TreeStoreReader> FullTree<S> {
+    /// Returns the Poseidon root hash and sum of the tree.
+    ///
+    /// The root is recomputed from all leaves, hashing one branch per level and leaf.
+    pub fn poseidon_root(&self) -> Result<(NodeHash, Sum)> {
+        subtree_root(0, &self.poseidon_leaves()?)
+    }
+
+    /// Generates a proof of `key` against the Poseidon root of the tree.
+    ///
+    /// Like `merkle_proof`, the proof of an absent key verifies with `EMPTY_LEAF_NODE`.
+    pub fn poseidon_proof(&self, key: impl Into<Key>) -> Result<PoseidonProof> {
+        let key = key.into().0;
+        let leaves = self.poseidon_leaves()?;
+        let mut leaves = &leaves[..];
+        let mut nodes = Vec::with_capacity(MAX_TREE_LEVELS);
+        for height in 0..MAX_TREE_LEVELS {
+            let split = leaves.partition_point(|leaf| bit_index(height, &leaf.key) == 0);
+            let (left, right) = leaves.split_at(split);
+            if bit_index(height, &key) == 0 {
+                nodes.push(subtree_root(height + 1, right)?);
+                leaves = left;
+            } else {
+                nodes.push(subtree_root(height + 1, left)?);
+                leaves = right;
+            }
+        }
+        Ok(PoseidonProof::new(nodes))
+    }
+
+    fn poseidon_leaves(&self) -> Result<Vec<LeafNode>> {
+        let mut leaves = Vec::new();
+        collect_leaves(self.store(), &self.root()?, 0, &mut leaves)?;
+        Ok(leaves)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::DefaultStore;
+
+    #[test]
+    fn test_poseidon_commitments() -> Result<()> {
+        let mut tree = FullTree::new(DefaultStore::new());
+        assert_eq!(tree.poseidon_root()?, (empty_hash_at(0), 0));
+
+        let keys = [[1u8; 32], [2u8; 32], [0x80; 32]];
+        for (i, key) in keys.iter().enumerate() {
+            tree.insert(*key, vec![i as u8; 40], i as Sum + 1)?;
+        }
+        let (root_hash, root_sum) = tree.poseidon_root()?;
+        assert_eq!(root_sum, 6);
+        assert_ne!(root_hash, tree.root()?.node_hash());
+
+        let leaf = LeafNode::new([2u8; 32], vec![1; 40], 2);
+        let proof = tree.poseidon_proof([2u8; 32])?;
+        assert_eq!(proof.root([2u8; 32], &leaf), Ok((root_hash, root_sum)));
+
+        // A tampered value or an absent key as present does not verify
+        let wrong = LeafNode::new([2u8; 32], vec![1; 39], 2);
+        assert!(matches!(
+            proof.verify_detailed([2u8; 32], &wrong, root_hash),
+            Err(ProofError::RootHashMismatch { .. })
+        ));
+        let absent = tree.poseidon_proof([3u8; 32])?;
+        assert!(absent.verify([3u8; 32], &EMPTY_LEAF_NODE, root_hash));
+        assert!(!absent.verify([3u8; 32], &LeafNode::new([3u8; 32], vec![], 0), root_hash));
+
+        tree.delete([0x80; 32])?;
+        assert_ne!(tree.poseidon_root()?.0, root_hash);
+        Ok(())
+    }
+}
@@ -0,0 +1,338 @@
+//! Proof generation over async stores with concurrent node lookups.
+//!
+//! Generating a proof walks the path of its key from the root, and every branch on it is a lookup when
+//! the store keeps branches by hash, as disk and network backends do. On a remote store each lookup is a
+//! round trip, and proving many keys one after the other pays one per level and key.
+//!
+//! `AsyncProofBuilder` proves a batch of keys against an `AsyncTreeStore` by descending all of their
+//! paths together, one level at a time, and fetching the branches of a level concurrently as its
+//! `Prefetch` strategy allows. Siblings are never fetched, as their hash and sum are held by their
+//! parent, and empty subtrees are never looked up. A batch thus costs one round trip per level instead
+//! of one per level and key. The levels of a single path stay sequential, since the hash of a branch is
+//! only known once its parent is read.
+//!
+//! This module requires the `tokio` feature.
+
+use crate::config::OverflowPolicy;
+use crate::error::{MssmtError, Result};
+use crate::key::Key;
+use crate::node::{bit_index, BranchNode, EmptyTree, Node, NodeHash, EMPTY_TREE, MAX_TREE_LEVELS};
+use crate::proof::Proof;
+use crate::store::AsyncTreeStore;
+use std::sync::Arc;
+use tokio::task::JoinSet;
+
+/// How the branches needed at one level of a batch are fetched.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Prefetch {
+    /// One lookup at a time, for stores that cannot serve concurrent requests.
+    Sequential,
+    /// Up to `max_in_flight` lookups at a time, each on its own tokio task. 0 is treated as 1.
+    Concurrent {
+        /// The maximum number of lookups in flight.
+        max_in_flight: usize,
+    },
+}
+
+impl Default for Prefetch {
+    fn default() -> Self {
+        Prefetch::Concurrent { max_in_flight: 64 }
+    }
+}
+
+/// Generates proofs from an `AsyncTreeStore`, fetching the branches of a level concurrently.
+///
+/// # Examples
+///
+/// ```rust
+/// use mssmt::prefetch::{AsyncProofBuilder, Prefetch};
+/// use mssmt::store::SpawnBlockingStore;
+/// use mssmt::{DefaultStore, FullTree, LeafNode, Node};
+/// use std::sync::Arc;
+///
+/// let mut tree = FullTree::new(DefaultStore::new());
+/// tree.insert([1u8; 32], b"one".to_vec(), 1).unwrap();
+/// tree.insert([2u8; 32], b"two".to_vec(), 2).unwrap();
+/// let root = tree.root().unwrap().node_hash();
+///
+/// let store = Arc::new(SpawnBlockingStore::new(tree.into_store()));
+/// let builder = AsyncProofBuilder::new(store).with_prefetch(Prefetch::Concurrent { max_in_flight: 8 });
+///
+/// let runtime = tokio::runtime::Runtime::new().unwrap();
+/// let proofs = runtime
+///     .block_on(builder.proofs(&root, &[[1u8; 32].into(), [2u8; 32].into()]))
+///     .unwrap();
+/// let leaf = LeafNode::new([2u8; 32], b"two".to_vec(), 2);
+/// assert!(proofs[1].verify([2u8; 32], &leaf, root));
+/// ```
+pub struct AsyncProofBuilder<A> {
+    store: Arc<A>,
+    prefetch: Prefetch,
+    overflow: OverflowPolicy,
+}
+
+impl<A: AsyncTreeStore + 'static> AsyncProofBuilder<A> {
+    /// Creates a builder generating proofs from `store`, with the default prefetch strategy and
+    /// overflow policy.
+    pub fn new(store: Arc<A>) -> Self {
+        Self {
+            store,
+            prefetch: Prefetch::default(),
+            overflow: OverflowPolicy::default(),
+        }
+    }
+
+    /// Sets how the branches of a level are fetched.
+    pub fn with_prefetch(mut self, prefetch: Prefetch) -> Self {
+        self.prefetch = prefetch;
+        self
+    }
+
+    /// Sets the overflow policy the proofs combine sums under, which must match the tree's.
+    pub fn with_overflow(mut self, overflow: OverflowPolicy) -> Self {
+        self.overflow = overflow;
+        self
+    }
+
+    /// Returns the proof of `key`, or of its absence, in the version with root `root`.
+    ///
+    /// # Returns
+    ///
+    /// - The proof of the key against `root`.
+    /// - `MssmtError::NodeNotFound` if `root`, or a branch under it, is not in the store.
+    pub async fn proof(&self, root: &NodeHash, key: impl Into<Key>) -> Result<Proof> {
+        let mut proofs = self.proofs(root, &[key.into()]).await?;
+        Ok(proofs.remove(0))
+    }
+
+    /// Returns the proofs of `keys`, in the order they were given, in the version with root `root`.
+    ///
+    /// Keys may repeat and do not need to be present.
+    ///
+    /// # Returns
+    ///
+    /// - One proof per key.
+    /// - `MssmtError::NodeNotFound` if `root`, or a branch under it, is not in the store.
+    pub async fn proofs(&self, root: &NodeHash, keys: &[Key]) -> Result<Vec<Proof>> {
+        let mut siblings: Vec<Vec<Arc<dyn Node>>> =
+            vec![Vec::with_capacity(MAX_TREE_LEVELS); keys.len()];
+        let mut frontier = vec![(
+            self.resolve_root(root).await?,
+            (0..keys.len()).collect::<Vec<_>>(),
+        )];
+
+        for height in 0..MAX_TREE_LEVELS {
+            let children = self.children(height, &frontier).await?;
+            let mut next = Vec::with_capacity(frontier.len() * 2);
+            for ((_, indices), (left, right)) in frontier.into_iter().zip(children) {
+                let (left_keys, right_keys): (Vec<usize>, Vec<usize>) = indices
+                    .into_iter()
+                    .partition(|index| bit_index(height, &keys[*index].0) == 0);
+                for index in &left_keys {
+                    siblings[*index].push(right.clone());
+                }
+                for index in &right_keys {
+                    siblings[*index].push(left.clone());
+                }
+                if !left_keys.is_empty() {
+                    next.push((left, left_keys));
+                }
+                if !right_keys.is_empty() {
+                    next.push((right, right_keys));
+                }
+            }
+            frontier = next;
+        }
+
+        Ok(siblings
+            .into_iter()
+            .map(|nodes| Proof::new(nodes).with_overflow(self.overflow))
+            .collect())
+    }
+
+    async fn resolve_root(&self, hash: &NodeHash) -> Result<Arc<dyn Node>> {
+        let root = self.store.root_node().await?;
+        if root.node_hash() == *hash {
+            return Ok(root);
+        }
+        if EmptyTree::is_empty_at(0, hash) {
+            return Ok(EMPTY_TREE[0].clone());
+        }
+        match self.store.get_branch(*hash).await? {
+            Some(branch) => Ok(branch),
+            None => Err(MssmtError::NodeNotFound(*hash)),
+        }
+    }
+
+    /// Returns the children of the frontier nodes at `height`, fetching the branches not in memory.
+    async fn children(
+        &self,
+        height: usize,
+        frontier: &[(Arc<dyn Node>, Vec<usize>)],
+    ) -> Result<Vec<(Arc<dyn Node>, Arc<dyn Node>)>> {
+        let mut children = Vec::with_capacity(frontier.len());
+        let mut missing = Vec::new();
+        for (position, (node, _)) in frontier.iter().enumerate() {
+            let hash = node.node_hash();
+            if EmptyTree::is_empty_at(height, &hash) {
+                let empty = EMPTY_TREE[height + 1].clone();
+                children.push(Some((empty.clone(), empty)));
+            } else if let Some(branch) = node.as_branch() {
+                children.push(Some((branch.left.clone(), branch.right.clone())));
+            } else {
+                children.push(None);
+                missing.push((position, hash));
+            }
+        }
+
+        for (position, branch) in self.fetch(missing).await? {
+            children[position] = Some((branch.left.clone(), branch.right.clone()));
+        }
+        Ok(children
+            .into_iter()
+            .map(|pair| pair.expect("every child pair is resolved"))
+            .collect())
+    }
+
+    /// Fetches the branches with the given hashes, under the prefetch strategy.
+    async fn fetch(&self, hashes: Vec<(usize, NodeHash)>) -> Result<Vec<(usize, Arc<BranchNode>)>> {
+        let found = |position, hash, branch: Option<Arc<BranchNode>>| match branch {
+            Some(branch) => Ok((position, branch)),
+            None => Err(MssmtError::NodeNotFound(hash)),
+        };
+        let max_in_flight = match self.prefetch {
+            Prefetch::Sequential => {
+                let mut branches = Vec::with_capacity(hashes.len());
+                for (position, hash) in hashes {
+                    branches.push(found(position, hash, self.store.get_branch(hash).await?)?);
+                }
+                return Ok(branches);
+            }
+            Prefetch::Concurrent { max_in_flight } => max_in_flight.max(1),
+        };
+
+        let mut branches = Vec::with_capacity(hashes.len());
+        let mut tasks = JoinSet::new();
+        for (position, hash) in hashes {
+            if tasks.len() == max_in_flight {
+                branches.push(join(&mut tasks).await??);
+            }
+            let store = self.store.clone();
+            tasks.spawn(async move { found(position, hash, store.get_branch(hash).await?) });
+        }
+        while !tasks.is_empty() {
+            branches.push(join(&mut tasks).await??);
+        }
+        Ok(branches)
+    }
+}
+
+async fn join<T: 'static>(tasks: &mut JoinSet<T>) -> Result<T> {
+    match tasks.join_next().await {
+        Some(Ok(output)) => Ok(output),
+        Some(Err(err)) => Err(MssmtError::Store(err.to_string())),
+        None => unreachable!("joined an empty task set"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::{LeafNode, Sum};
+    use crate::store::{DefaultStore, TreeStoreReader};
+    use crate::tree::FullTree;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// An async store serving shallow branches, recording how many lookups run at once.
+    struct SlowStore {
+        inner: DefaultStore,
+        in_flight: AtomicUsize,
+        max_in_flight: AtomicUsize,
+    }
+
+    impl AsyncTreeStore for SlowStore {
+        async fn root_node(&self) -> Result<Arc<dyn Node>> {
+            let root = self.inner.root_node()?;
+            match root.as_branch() {
+                Some(branch) => Ok(Arc::new(branch.to_shallow())),
+                None => Ok(root),
+            }
+        }
+
+        async fn get_branch(&self, key: NodeHash) -> Result<Option<Arc<BranchNode>>> {
+            let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+            for _ in 0..8 {
+                tokio::task::yield_now().await;
+            }
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+            let branch = self.inner.get_branch(&key)?;
+            Ok(branch.map(|branch| Arc::new(branch.to_shallow())))
+        }
+
+        async fn get_leaf(&self, key: NodeHash) -> Result<Option<Arc<LeafNode>>> {
+            self.inner.get_leaf(&key)
+        }
+
+        async fn insert_branch(&self, _branch: Arc<BranchNode>) -> Result<()> {
+            Err(MssmtError::Unsupported("insert_branch"))
+        }
+
+        async fn insert_leaf(&self, _leaf: Arc<LeafNode>) -> Result<()> {
+            Err(MssmtError::Unsupported("insert_leaf"))
+        }
+
+        async fn delete_branch(&self, _key: NodeHash) -> Result<()> {
+            Err(MssmtError::Unsupported("delete_branch"))
+        }
+
+        async fn delete_leaf(&self, _key: NodeHash) -> Result<()> {
+            Err(MssmtError::Unsupported("delete_leaf"))
+        }
+
+        async fn update_root(&self, _root: Arc<dyn Node>) -> Result<()> {
+            Err(MssmtError::Unsupported("update_root"))
+        }
+    }
+
+    #[test]
+    fn test_prefetch_strategies_match_tree_proofs() -> Result<()> {
+        let mut tree = FullTree::new(DefaultStore::new());
+        for i in 0..32u8 {
+            tree.insert([i * 8; 32], vec![i], i as Sum)?;
+        }
+        let root = tree.root()?.node_hash();
+        let keys: Vec<Key> = (0..64u8).map(|i| Key([i * 4; 32])).collect();
+        let expected = tree.merkle_proofs(keys.iter().copied())?;
+        let store = Arc::new(SlowStore {
+            inner: tree.into_store(),
+            in_flight: AtomicUsize::new(0),
+            max_in_flight: AtomicUsize::new(0),
+        });
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        for (prefetch, limit) in [
+            (Prefetch::Sequential, 1),
+            (Prefetch::Concurrent { max_in_flight: 4 }, 4),
+        ] {
+            store.max_in_flight.store(0, Ordering::SeqCst);
+            let builder = AsyncProofBuilder::new(store.clone()).with_prefetch(prefetch);
+            let proofs = runtime.block_on(builder.proofs(&root, &keys))?;
+            assert!(proofs == expected);
+            let max_in_flight = store.max_in_flight.load(Ordering::SeqCst);
+            assert!(max_in_flight <= limit);
+            if limit > 1 {
+                assert!(max_in_flight > 1);
+            }
+        }
+
+        let builder = AsyncProofBuilder::new(store.clone());
+        let unknown = NodeHash::new([7u8; 32]);
+        assert!(matches!(
+            runtime.block_on(builder.proof(&unknown, [0u8; 32])),
+            Err(MssmtError::NodeNotFound(hash)) if hash == unknown
+        ));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,118 @@
+//! Progress reporting for long-running bulk operations.
+//!
+//! Bulk inserts, builds from leaves, imports and integrity audits take a `progress` callback, called
+//! with a `Progress` as items are processed, so command line tools and services can show progress bars
+//! and estimate the time left instead of appearing hung. Pass `|_| {}` to ignore it.
+
+use crate::node::tree_levels;
+use std::time::{Duration, Instant};
+
+/// A progress update from a bulk operation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Progress {
+    /// The number of items processed so far, including those skipped when resuming from a checkpoint.
+    pub items_processed: u64,
+    /// The total number of items, if known in advance.
+    pub items_total: Option<u64>,
+    /// The number of nodes written to the store by the operation so far.
+    pub nodes_written: u64,
+    /// The time since the operation started.
+    pub elapsed: Duration,
+    // The number of items already processed when the operation started
+    resumed_from: u64,
+}
+
+impl Progress {
+    /// Estimates the time left, extrapolating the rate at which items have been processed so far.
+    ///
+    /// Returns `None` if the total is unknown or no item has been processed yet.
+    pub fn eta(&self) -> Option<Duration> {
+        let total = self.items_total?;
+        let processed = self.items_processed.checked_sub(self.resumed_from)?;
+        if processed == 0 {
+            return None;
+        }
+        let remaining = total.saturating_sub(self.items_processed);
+        Some(self.elapsed.mul_f64(remaining as f64 / processed as f64))
+    }
+}
+
+/// Counts the items and node writes of a bulk operation and reports them to its callback.
+pub(crate) struct ProgressTracker<F> {
+    callback: F,
+    start: Instant,
+    items_total: Option<u64>,
+    resumed_from: u64,
+    items_processed: u64,
+    nodes_written: u64,
+}
+
+impl<F: FnMut(Progress)> ProgressTracker<F> {
+    /// Starts tracking an operation resuming after `resumed_from` items.
+    pub(crate) fn new(callback: F, items_total: Option<u64>, resumed_from: u64) -> Self {
+        Self {
+            callback,
+            start: Instant::now(),
+            items_total,
+            resumed_from,
+            items_processed: resumed_from,
+            nodes_written: 0,
+        }
+    }
+
+    /// Records `items` processed items and `nodes` written nodes, and reports the progress.
+    pub(crate) fn advance(&mut self, items: u64, nodes: u64) {
+        self.items_processed += items;
+        self.nodes_written += nodes;
+        (self.callback)(Progress {
+            items_processed: self.items_processed,
+            items_total: self.items_total,
+            nodes_written: self.nodes_written,
+            elapsed: self.start.elapsed(),
+            resumed_from: self.resumed_from,
+        });
+    }
+}
+
+/// Returns the number of nodes written by an insert into a tree with `K`-byte keys: one branch per
+/// level, and the leaf.
+pub(crate) fn insert_writes(key_size: usize) -> u64 {
+    tree_levels(key_size) as u64 + 1
+}
+
+/// Returns the exact length of an iterator, if its size hint knows it.
+pub(crate) fn exact_len(iter: &impl Iterator) -> Option<u64> {
+    match iter.size_hint() {
+        (lower, Some(upper)) if lower == upper => Some(lower as u64),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_eta_excludes_resumed_items() {
+        let progress = Progress {
+            items_processed: 60,
+            items_total: Some(100),
+            nodes_written: 0,
+            elapsed: Duration::from_secs(10),
+            resumed_from: 50,
+        };
+        // 10 items in 10 seconds, 40 to go
+        assert_eq!(progress.eta(), Some(Duration::from_secs(40)));
+
+        let mut reports = Vec::new();
+        let mut tracker = ProgressTracker::new(|progress| reports.push(progress), None, 50);
+        tracker.advance(0, 0);
+        tracker.advance(2, 3);
+        assert_eq!(reports[0].eta(), None);
+        assert_eq!(
+            (reports[1].items_processed, reports[1].nodes_written),
+            (52, 3)
+        );
+        assert_eq!(reports[1].eta(), None);
+    }
+}
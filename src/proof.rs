@@ -3,9 +3,25 @@
 //! This module provides the `Proof` struct, which contains the necessary information to verify the inclusion
 //! of a leaf in the tree. It includes methods to compute the root hash from the proof and verify the proof
 //! against a given root hash.
+//!
+//! The `CompressedProof` struct is a compact representation of a proof that replaces siblings belonging to
+//! the empty tree with a bit vector. Its binary encoding is byte-for-byte compatible with the compressed proofs
+//! of lightninglabs' Go mssmt package.
 
-use crate::node::{bit_index, BranchNode, LeafNode, Node, NodeHash, MAX_TREE_LEVELS};
+use crate::config::OverflowPolicy;
+use crate::error::{MssmtError, ProofError, Result};
+use crate::executor::{self, Executor};
+use crate::hash_utils::to_array;
+use crate::key::Key;
+use crate::node::{
+    decode_sum, tree_levels, ComputedNode, LeafNode, LeafValue, Node, NodeHash, Sum, HASH_SIZE,
+    MAX_TREE_LEVELS, SUM_SIZE,
+};
+use crate::path::TreePath;
+use crate::tagged::HashScheme;
+use std::fmt;
 use std::sync::Arc;
+use subtle::ConstantTimeEq;
 
 /// A Merkle proof for verifying the inclusion of a leaf in the Merkle-Sum Sparse Merkle Tree.
 ///
@@ -15,6 +31,14 @@ use std::sync::Arc;
 /// # Fields
 ///
 /// - `nodes`: A vector of `Arc<dyn Node>` representing the sibling nodes along the path from the leaf to the root.
+///   The constructors reduce them to `ComputedNode`s, so a proof only carries the hash and sum of each sibling
+///   and never the leaves, or leaf values, below it.
+/// - `scheme`: The `HashScheme` the proof verifies under, `HashScheme::V0` unless the proof was
+///   generated by `FullTree::scheme_proof` for a tree committing under another scheme.
+/// - `overflow`: The `OverflowPolicy` combining the sums along the path, the one of the tree the proof
+///   was generated from.
+///
+/// `K` is the key size in bytes of the tree, so a canonical proof holds `8 * K` siblings.
 ///
 /// # Examples
 ///
@@ -41,35 +65,147 @@ use std::sync::Arc;
 /// let leaf_node = LeafNode::new(key, value, sum);
 /// assert!(proof.verify(key, &leaf_node, root_hash));
 /// ```
-
-pub struct Proof {
+#[derive(Clone)]
+pub struct Proof<const K: usize = HASH_SIZE> {
     pub nodes: Vec<Arc<dyn Node>>,
+    pub scheme: HashScheme,
+    pub overflow: OverflowPolicy,
 }
 
-impl Proof {
-    /// Creates a new `Proof`.
-    pub fn new(nodes: Vec<Arc<dyn Node>>) -> Self {
-        Self { nodes }
+/// Proofs are equal if they have the same scheme and overflow policy and their siblings have the same hashes and sums,
+/// regardless of node types.
+impl<const K: usize> PartialEq for Proof<K> {
+    fn eq(&self, other: &Self) -> bool {
+        self.scheme == other.scheme
+            && self.overflow == other.overflow
+            && self.nodes.len() == other.nodes.len()
+            && self
+                .nodes
+                .iter()
+                .zip(&other.nodes)
+                .all(|(a, b)| a.node_hash() == b.node_hash() && a.node_sum() == b.node_sum())
     }
+}
 
-    /// Computes the root from the proof and the given leaf.
-    pub fn root(&self, key: [u8; 32], leaf: &LeafNode) -> Arc<dyn Node> {
-        let mut current_node: Arc<dyn Node> = Arc::new(leaf.clone());
-        let total_height = MAX_TREE_LEVELS;
-
-        // Reverse the proof nodes to start from the leaf level
-        for (height_from_leaf, sibling_node) in self.nodes.iter().rev().enumerate() {
-            let height = total_height - height_from_leaf - 1;
-            let bit = bit_index(height, &key);
-            let parent_node = if bit == 0 {
-                Arc::new(BranchNode::new(current_node.clone(), sibling_node.clone()))
+impl<const K: usize> Eq for Proof<K> {}
+
+/// Arbitrary proofs have one sibling per level, most of them empty subtrees and the others arbitrary
+/// hashes and sums, under an arbitrary scheme and overflow policy. They are well-formed, so they encode
+/// and decode, but do not verify against any particular root.
+#[cfg(feature = "arbitrary")]
+impl<'a, const K: usize> arbitrary::Arbitrary<'a> for Proof<K> {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        let scheme: HashScheme = u.arbitrary()?;
+        let mut nodes: Vec<Arc<dyn Node>> = Vec::with_capacity(tree_levels(K));
+        for height in 1..=tree_levels(K) {
+            if u.ratio(7u8, 8u8)? {
+                nodes.push(scheme.empty_node_at::<K>(height));
             } else {
-                Arc::new(BranchNode::new(sibling_node.clone(), current_node.clone()))
-            };
-            current_node = parent_node;
+                let hash = NodeHash::new(u.arbitrary()?);
+                nodes.push(Arc::new(ComputedNode::new(hash, u.arbitrary()?)));
+            }
         }
+        Ok(Self::with_scheme(nodes, scheme).with_overflow(u.arbitrary()?))
+    }
+}
+
+/// Lists the number of siblings and only the siblings that are not empty subtrees, keyed by depth, and
+/// the scheme of proofs not under `HashScheme::V0` and the overflow policy of proofs not under
+/// `OverflowPolicy::Checked`.
+impl<const K: usize> fmt::Debug for Proof<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let non_empty: Vec<String> = self
+            .nodes
+            .iter()
+            .enumerate()
+            .filter(|(i, node)| !self.is_empty_sibling(i + 1, node))
+            .map(|(i, node)| format!("{}: {}/{}", i + 1, node.node_hash(), node.node_sum()))
+            .collect();
 
-        current_node
+        let mut debug = f.debug_struct("Proof");
+        debug
+            .field("len", &self.nodes.len())
+            .field("non_empty", &non_empty);
+        if self.scheme != HashScheme::V0 {
+            debug.field("scheme", &self.scheme);
+        }
+        if self.overflow != OverflowPolicy::Checked {
+            debug.field("overflow", &self.overflow);
+        }
+        debug.finish()
+    }
+}
+
+impl<const K: usize> Proof<K> {
+    /// Creates a new `Proof` verifying under `HashScheme::V0` and `OverflowPolicy::Checked`.
+    pub fn new(nodes: Vec<Arc<dyn Node>>) -> Self {
+        Self::with_scheme(nodes, HashScheme::V0)
+    }
+
+    /// Creates a new `Proof` whose siblings carry their hashes under `scheme`.
+    ///
+    /// The siblings are reduced to their hashes and sums, see `ComputedNode::pruned`.
+    pub fn with_scheme(nodes: Vec<Arc<dyn Node>>, scheme: HashScheme) -> Self {
+        Self {
+            nodes: nodes.into_iter().map(ComputedNode::pruned).collect(),
+            scheme,
+            overflow: OverflowPolicy::Checked,
+        }
+    }
+
+    /// Returns the proof combining the sums along the path under `overflow`.
+    pub fn with_overflow(mut self, overflow: OverflowPolicy) -> Self {
+        self.overflow = overflow;
+        self
+    }
+
+    /// Returns `true` if `node` is the empty subtree at `height` under the scheme of the proof.
+    fn is_empty_sibling(&self, height: usize, node: &Arc<dyn Node>) -> bool {
+        self.scheme.is_empty_at::<K>(height, &node.node_hash())
+    }
+
+    /// Checks that the proof is structurally canonical.
+    ///
+    /// A canonical proof contains exactly one sibling node per tree level. Truncated or padded proofs are
+    /// rejected so that they can never verify, even in edge cases. All verification methods run this check
+    /// before reconstructing the root.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use mssmt::error::ProofError;
+    /// use mssmt::{DefaultStore, FullTree};
+    ///
+    /// let tree = FullTree::new(DefaultStore::new());
+    /// let mut proof = tree.merkle_proof([1u8; 32]).unwrap();
+    /// assert!(proof.validate().is_ok());
+    ///
+    /// proof.nodes.truncate(8);
+    /// assert_eq!(
+    ///     proof.validate(),
+    ///     Err(ProofError::InvalidLength { expected: 256, actual: 8 })
+    /// );
+    /// ```
+    pub fn validate(&self) -> std::result::Result<(), ProofError> {
+        if self.nodes.len() != tree_levels(K) {
+            return Err(ProofError::InvalidLength {
+                expected: tree_levels(K),
+                actual: self.nodes.len(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Computes the root from the proof and the given leaf.
+    ///
+    /// The root is returned as a `ComputedNode` carrying its hash and sum, see `Proof::subtree_root`.
+    /// This does not validate the proof structure; use one of the verification methods for untrusted proofs.
+    pub fn root(
+        &self,
+        key: impl Into<Key<K>>,
+        leaf: &LeafNode<K, impl LeafValue>,
+    ) -> Arc<dyn Node> {
+        self.subtree_root(key, leaf, 0)
     }
 
     /// Verifies the proof against a given root hash.
@@ -84,11 +220,1267 @@ impl Proof {
     ///
     /// # Returns
     ///
-    /// - `true` if the proof is valid and the reconstructed root hash matches the given root hash.
-    /// - `false` otherwise.
+    /// - `true` if the proof is canonical and the reconstructed root hash matches the given root hash.
+    /// - `false` otherwise, including when the sums overflow under the overflow policy of the proof.
+    ///
+    pub fn verify(
+        &self,
+        key: impl Into<Key<K>>,
+        leaf: &LeafNode<K, impl LeafValue>,
+        root_hash: NodeHash,
+    ) -> bool {
+        let key = key.into().0;
+        if self.validate().is_err() {
+            return false;
+        }
+        // Folding checks the sums, which may overflow in untrusted proofs
+        self.fold_root(key, leaf)
+            .is_ok_and(|(hash, _)| hash.constant_time_eq(&root_hash))
+    }
+
+    /// Verifies many proofs against `root_hash` on `executor`, returning the outcome of each, in order.
+    ///
+    /// Each item holds a key, the leaf at the key and its proof, checked as by `Proof::verify`.
+    pub fn verify_batch<V: LeafValue>(
+        executor: &dyn Executor,
+        items: &[(Key<K>, LeafNode<K, V>, Proof<K>)],
+        root_hash: NodeHash,
+    ) -> Vec<bool> {
+        executor::map(executor, items, |(key, leaf, proof)| {
+            proof.verify(*key, leaf, root_hash)
+        })
+    }
+
+    /// Verifies the proof of the leaf with the given value and sum against a given root hash.
+    ///
+    /// Equivalent to `verify` with a leaf built from `key`, `value` and `sum`, but hashes the value
+    /// where it lies instead of copying it into a `LeafNode`, which matters for large values.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use mssmt::{DefaultStore, FullTree, Node};
+    ///
+    /// let mut tree = FullTree::new(DefaultStore::new());
+    /// let value = vec![7u8; 1 << 20];
+    /// tree.insert([1u8; 32], value.clone(), 10).unwrap();
+    ///
+    /// let proof = tree.merkle_proof([1u8; 32]).unwrap();
+    /// let root_hash = tree.root().unwrap().node_hash();
+    /// assert!(proof.verify_value([1u8; 32], &value, 10, root_hash));
+    /// assert!(!proof.verify_value([1u8; 32], &value, 11, root_hash));
+    /// ```
+    pub fn verify_value(
+        &self,
+        key: impl Into<Key<K>>,
+        value: &[u8],
+        sum: Sum,
+        root_hash: NodeHash,
+    ) -> bool {
+        let key = key.into().0;
+        let leaf_hash = self.scheme.domain().leaf_parts_hash(&key, value, sum);
+        self.verify_leaf_hash(key, leaf_hash, sum, root_hash)
+    }
+
+    /// Verifies the proof of a leaf given as any node against a given root hash.
+    ///
+    /// Only the hash and sum of `leaf` are used, so it can be a `ComputedNode` carrying the hash of a
+    /// leaf whose value is not at hand. The hash must be the one of the leaf under the scheme of the
+    /// proof, which is `Node::node_hash` of the `LeafNode` under `HashScheme::V0`.
+    pub fn verify_node(
+        &self,
+        key: impl Into<Key<K>>,
+        leaf: &dyn Node,
+        root_hash: NodeHash,
+    ) -> bool {
+        self.verify_leaf_hash(key.into().0, leaf.node_hash(), leaf.node_sum(), root_hash)
+    }
+
+    fn verify_leaf_hash(
+        &self,
+        key: [u8; K],
+        leaf_hash: NodeHash,
+        leaf_sum: Sum,
+        root_hash: NodeHash,
+    ) -> bool {
+        if self.validate().is_err() {
+            return false;
+        }
+        self.fold_from(key, leaf_hash, leaf_sum, 0)
+            .is_ok_and(|(hash, _)| hash.constant_time_eq(&root_hash))
+    }
+
+    /// Computes the root hash and sum of the tree after replacing the leaf at `key`.
+    ///
+    /// This allows a light client holding only a proof to follow an update without access to any store.
+    /// The proof must have been generated for `key` against the tree containing `old_leaf`; since the
+    /// siblings along the path are unaffected by the update, the same proof also holds for `new_leaf`.
+    ///
+    /// # Arguments
+    ///
+    /// - `key`: The key whose leaf is replaced.
+    /// - `old_leaf`: The leaf currently stored at `key`, or `EMPTY_LEAF_NODE` if the key is absent.
+    /// - `new_leaf`: The replacement leaf, or `EMPTY_LEAF_NODE` to model a deletion.
+    ///
+    /// # Returns
+    ///
+    /// - The root hash and root sum of the updated tree.
+    /// - `MssmtError::InvalidProofLength` if the proof is not canonical.
+    /// - `MssmtError::KeyMismatch` if a non-empty leaf is not stored under `key`.
+    /// - `MssmtError::SumOverflow` if the updated sums overflow.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use mssmt::node::EMPTY_LEAF_NODE;
+    /// use mssmt::{DefaultStore, FullTree, LeafNode, Node};
+    ///
+    /// let mut tree = FullTree::new(DefaultStore::new());
+    /// let key = [1u8; 32];
+    ///
+    /// // Non-inclusion proof for the key before it is inserted
+    /// let proof = tree.merkle_proof(key).unwrap();
+    /// let new_leaf = LeafNode::new(key, b"value".to_vec(), 10);
+    /// let (root_hash, root_sum) = proof
+    ///     .compute_updated_root(key, &EMPTY_LEAF_NODE, &new_leaf)
+    ///     .unwrap();
+    ///
+    /// tree.insert(key, b"value".to_vec(), 10).unwrap();
+    /// assert_eq!(root_hash, tree.root().unwrap().node_hash());
+    /// assert_eq!(root_sum, 10);
+    /// ```
+    pub fn compute_updated_root(
+        &self,
+        key: impl Into<Key<K>>,
+        old_leaf: &LeafNode<K, impl LeafValue>,
+        new_leaf: &LeafNode<K, impl LeafValue>,
+    ) -> Result<(NodeHash, Sum)> {
+        let key = key.into().0;
+        for (is_empty, leaf_key) in [
+            (old_leaf.is_empty(), old_leaf.key),
+            (new_leaf.is_empty(), new_leaf.key),
+        ] {
+            if !is_empty && leaf_key != key {
+                return Err(MssmtError::KeyMismatch);
+            }
+        }
+
+        if self.nodes.len() != tree_levels(K) {
+            return Err(MssmtError::InvalidProofLength {
+                expected: tree_levels(K),
+                actual: self.nodes.len(),
+            });
+        }
+
+        self.fold_root(key, new_leaf)
+            .map_err(|_| MssmtError::SumOverflow)
+    }
+
+    /// Verifies the proof against a given root hash and returns the reconstructed root sum.
+    ///
+    /// A verifier usually needs the sum committed to by the root in addition to the hash. If `expected_sum`
+    /// is provided, the reconstructed root sum must also match it.
+    ///
+    /// # Arguments
+    ///
+    /// - `key`: The key associated with the leaf node.
+    /// - `leaf`: A reference to the `LeafNode` to verify.
+    /// - `root_hash`: The expected root hash of the tree.
+    /// - `expected_sum`: The expected root sum of the tree, if known.
+    ///
+    /// # Returns
+    ///
+    /// - `Some(sum)` with the reconstructed root sum if the proof is valid.
+    /// - `None` if the proof is not canonical, the root hash or the expected sum does not match, or the sums overflow.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use mssmt::{DefaultStore, FullTree, LeafNode, Node};
+    ///
+    /// let mut tree = FullTree::new(DefaultStore::new());
+    /// tree.insert([1u8; 32], b"one".to_vec(), 1).unwrap();
+    /// tree.insert([2u8; 32], b"two".to_vec(), 2).unwrap();
+    ///
+    /// let proof = tree.merkle_proof([1u8; 32]).unwrap();
+    /// let leaf = LeafNode::new([1u8; 32], b"one".to_vec(), 1);
+    /// let root_hash = tree.root().unwrap().node_hash();
+    ///
+    /// assert_eq!(proof.verify_with_sum([1u8; 32], &leaf, root_hash, None), Some(3));
+    /// assert_eq!(proof.verify_with_sum([1u8; 32], &leaf, root_hash, Some(4)), None);
+    /// ```
+    pub fn verify_with_sum(
+        &self,
+        key: impl Into<Key<K>>,
+        leaf: &LeafNode<K, impl LeafValue>,
+        root_hash: NodeHash,
+        expected_sum: Option<Sum>,
+    ) -> Option<Sum> {
+        let key = key.into().0;
+        self.validate().ok()?;
+        let (hash, sum) = self.fold_root(key, leaf).ok()?;
+        let sum_matches = expected_sum.is_none_or(|expected| bool::from(expected.ct_eq(&sum)));
+        if !hash.constant_time_eq(&root_hash) || !sum_matches {
+            return None;
+        }
+        Some(sum)
+    }
+
+    /// Verifies the proof against a given root hash, reporting why verification failed.
+    ///
+    /// # Arguments
+    ///
+    /// - `key`: The key associated with the leaf node.
+    /// - `leaf`: A reference to the `LeafNode` to verify.
+    /// - `root_hash`: The expected root hash of the tree.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(())` if the proof is valid.
+    /// - `Err(ProofError)` describing the first check that failed otherwise.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use mssmt::error::ProofError;
+    /// use mssmt::{DefaultStore, FullTree, LeafNode, Node};
+    ///
+    /// let mut tree = FullTree::new(DefaultStore::new());
+    /// tree.insert([1u8; 32], b"one".to_vec(), 1).unwrap();
+    ///
+    /// let proof = tree.merkle_proof([1u8; 32]).unwrap();
+    /// let root_hash = tree.root().unwrap().node_hash();
+    ///
+    /// let leaf = LeafNode::new([1u8; 32], b"one".to_vec(), 1);
+    /// assert_eq!(proof.verify_detailed([1u8; 32], &leaf, root_hash), Ok(()));
+    ///
+    /// let wrong_leaf = LeafNode::new([1u8; 32], b"two".to_vec(), 1);
+    /// assert!(matches!(
+    ///     proof.verify_detailed([1u8; 32], &wrong_leaf, root_hash),
+    ///     Err(ProofError::RootHashMismatch { .. })
+    /// ));
+    /// ```
+    pub fn verify_detailed(
+        &self,
+        key: impl Into<Key<K>>,
+        leaf: &LeafNode<K, impl LeafValue>,
+        root_hash: NodeHash,
+    ) -> std::result::Result<(), ProofError> {
+        let key = key.into().0;
+        self.validate()?;
+
+        if !leaf.is_empty() && leaf.key != key {
+            return Err(ProofError::KeyMismatch);
+        }
+
+        let (hash, _) = self.fold_root(key, leaf)?;
+        if !hash.constant_time_eq(&root_hash) {
+            return Err(ProofError::RootHashMismatch {
+                expected: root_hash,
+                actual: hash,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Folds the proof nodes over the given leaf, returning the root hash and sum.
+    pub(crate) fn fold_root(
+        &self,
+        key: [u8; K],
+        leaf: &LeafNode<K, impl LeafValue>,
+    ) -> std::result::Result<(NodeHash, Sum), ProofError> {
+        let leaf_hash = self.scheme.domain().leaf_hash(leaf);
+        self.fold_from(key, leaf_hash, leaf.node_sum(), 0)
+    }
+
+    /// Folds the proof nodes below `height` over the leaf with the given hash and sum, returning the hash
+    /// and sum of the subtree at `height` under the scheme of the proof.
+    ///
+    /// Only hashes and sums are carried from one level to the next, so the fold allocates nothing and
+    /// hashes each level once. Every verification method goes through it.
+    fn fold_from(
+        &self,
+        key: [u8; K],
+        leaf_hash: NodeHash,
+        leaf_sum: Sum,
+        height: usize,
+    ) -> std::result::Result<(NodeHash, Sum), ProofError> {
+        let domain = self.scheme.domain();
+        let (mut hash, mut sum) = (leaf_hash, leaf_sum);
+        let levels = self.nodes.len().min(tree_levels(K));
+        for (height, sibling) in self.nodes[..levels].iter().enumerate().skip(height).rev() {
+            sum = self
+                .overflow
+                .combine(sum, sibling.node_sum())
+                .filter(|sum| *sum <= domain.max_sum())
+                .ok_or(ProofError::SumOverflow { height })?;
+            let sibling_hash = sibling.node_hash();
+            hash = if domain.key_bit(height, &key) == 0 {
+                domain.branch_hash(&hash, &sibling_hash, sum)
+            } else {
+                domain.branch_hash(&sibling_hash, &hash, sum)
+            };
+        }
+
+        Ok((hash, sum))
+    }
+
+    /// Replaces the sibling at `height` with a node of the given hash and sum.
+    ///
+    /// When another leaf of the tree changes, the proof of `key` only goes stale in the single sibling
+    /// whose subtree contains the changed leaf, at the height where the two keys' paths diverge (see
+    /// `Proof::divergence_height`). Patching that sibling with the new subtree root keeps a long-lived
+    /// proof in sync with the tree without regenerating it.
+    ///
+    /// # Arguments
+    ///
+    /// - `height`: The height of the sibling in the tree, from 1 below the root to 256 at the leaves.
+    /// - `hash`: The new hash of the sibling subtree.
+    /// - `sum`: The new sum of the sibling subtree.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(())` once the sibling is replaced.
+    /// - `MssmtError::InvalidHeight` if the proof has no sibling at `height`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use mssmt::proof::Proof;
+    /// use mssmt::{DefaultStore, FullTree, LeafNode, Node};
+    ///
+    /// let mut tree = FullTree::new(DefaultStore::new());
+    /// tree.insert([1u8; 32], b"one".to_vec(), 1).unwrap();
+    /// tree.insert([0x80; 32], b"two".to_vec(), 2).unwrap();
+    /// let mut proof = tree.merkle_proof([1u8; 32]).unwrap();
+    ///
+    /// // The other leaf changes, and its new subtree root is published
+    /// tree.insert([0x80; 32], b"three".to_vec(), 3).unwrap();
+    /// let height = Proof::divergence_height([1u8; 32], [0x80; 32]).unwrap();
+    /// let sibling = LeafNode::new([0x80; 32], b"three".to_vec(), 3);
+    /// let sibling = tree.merkle_proof([0x80; 32]).unwrap().subtree_root([0x80; 32], &sibling, height);
+    ///
+    /// proof.update_sibling(height, sibling.node_hash(), sibling.node_sum()).unwrap();
+    /// let leaf = LeafNode::new([1u8; 32], b"one".to_vec(), 1);
+    /// assert!(proof.verify([1u8; 32], &leaf, tree.root().unwrap().node_hash()));
+    /// ```
+    pub fn update_sibling(&mut self, height: usize, hash: NodeHash, sum: Sum) -> Result<()> {
+        if height == 0 || height > self.nodes.len() {
+            return Err(MssmtError::InvalidHeight(height));
+        }
+        self.nodes[height - 1] = if self.scheme.is_empty_at::<K>(height, &hash) {
+            self.scheme.empty_node_at::<K>(height)
+        } else {
+            Arc::new(ComputedNode::new(hash, sum))
+        };
+        Ok(())
+    }
+
+    /// Returns the height at which the paths of two keys diverge, or `None` if the keys are equal.
+    ///
+    /// The proof of `key` holds the subtree containing `other` as its sibling at this height.
+    pub fn divergence_height(key: impl Into<Key<K>>, other: impl Into<Key<K>>) -> Option<usize> {
+        TreePath::<K>::new(key)
+            .divergence(&TreePath::new(other))
+            .map(|height| height.get() + 1)
+    }
+
+    /// Computes the root of the subtree at `height` containing the leaf, using the lower siblings of the proof.
+    ///
+    /// With a `height` of 0 this is the same as `Proof::root`. This does not validate the proof structure.
+    ///
+    /// The subtree root is returned as a `ComputedNode`, whose hash is all zeros if the sums of the
+    /// proof overflow.
+    pub fn subtree_root(
+        &self,
+        key: impl Into<Key<K>>,
+        leaf: &LeafNode<K, impl LeafValue>,
+        height: usize,
+    ) -> Arc<dyn Node> {
+        let leaf_hash = self.scheme.domain().leaf_hash(leaf);
+        let (hash, sum) = self
+            .fold_from(key.into().0, leaf_hash, leaf.node_sum(), height)
+            .unwrap_or((NodeHash::new([0u8; HASH_SIZE]), 0));
+        Arc::new(ComputedNode::new(hash, sum))
+    }
+
+    /// Returns the number of siblings that are not empty subtrees.
+    ///
+    /// Only these siblings carry information, the others are implied by the empty tree.
+    pub fn non_empty_nodes(&self) -> usize {
+        self.nodes
+            .iter()
+            .enumerate()
+            .filter(|(i, node)| !self.is_empty_sibling(i + 1, node))
+            .count()
+    }
+
+    /// Compresses the proof by replacing siblings that belong to the empty tree with a bit vector.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use mssmt::{DefaultStore, FullTree};
+    ///
+    /// let mut tree = FullTree::new(DefaultStore::new());
+    /// tree.insert([1u8; 32], b"one".to_vec(), 1).unwrap();
+    /// tree.insert([2u8; 32], b"two".to_vec(), 2).unwrap();
+    ///
+    /// let proof = tree.merkle_proof([1u8; 32]).unwrap();
+    /// let compressed = proof.compress();
+    /// assert_eq!(compressed.nodes.len(), 1);
+    /// ```
+    pub fn compress(&self) -> CompressedProof {
+        let mut bits = Vec::with_capacity(self.nodes.len());
+        let mut nodes = Vec::new();
+
+        // Compressed proofs list siblings starting at the leaf, while proof nodes start at the root
+        for (height, node) in self.nodes.iter().enumerate().rev() {
+            let is_empty = self.is_empty_sibling(height + 1, node);
+            bits.push(is_empty);
+            if !is_empty {
+                nodes.push(ComputedNode::pruned(node.clone()));
+            }
+        }
+
+        CompressedProof {
+            bits,
+            nodes,
+            scheme: self.scheme,
+            overflow: self.overflow,
+        }
+    }
+}
+
+impl Proof {
+    /// Returns the size in bytes of the proof once compressed and encoded.
+    ///
+    /// This is the cost of distributing the proof, see `CompressedProof::encode`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use mssmt::{DefaultStore, FullTree};
+    ///
+    /// let mut tree = FullTree::new(DefaultStore::new());
+    /// tree.insert([1u8; 32], b"one".to_vec(), 1).unwrap();
+    /// tree.insert([2u8; 32], b"two".to_vec(), 2).unwrap();
+    ///
+    /// let proof = tree.merkle_proof([1u8; 32]).unwrap();
+    /// assert_eq!(proof.non_empty_nodes(), 1);
+    /// assert_eq!(proof.size_bytes(), proof.compress().encode().len());
+    /// ```
+    pub fn size_bytes(&self) -> usize {
+        let scheme_prefix = if self.scheme == HashScheme::V0 { 0 } else { 2 };
+        let overflow_prefix = if self.overflow == OverflowPolicy::Checked {
+            0
+        } else {
+            2
+        };
+        scheme_prefix
+            + overflow_prefix
+            + 2
+            + self.non_empty_nodes() * ENCODED_NODE_SIZE
+            + ENCODED_BITS_SIZE
+    }
+
+    /// Encodes the proof in its compressed form, see `CompressedProof::encode`.
+    pub fn encode(&self) -> Vec<u8> {
+        self.compress().encode()
+    }
+
+    /// Decodes a proof produced by `encode`, under the default `DecodeLimits`.
+    pub fn decode(bytes: &[u8]) -> Result<Self> {
+        CompressedProof::decode(bytes)?.decompress()
+    }
+
+    /// Decodes a proof produced by `encode` under `limits`, see `CompressedProof::decode_with_limits`.
+    ///
+    /// # Returns
+    ///
+    /// - The decoded `Proof`.
+    /// - `MssmtError::DecodeLimit` if the proof declares more nodes than the limits allow.
+    /// - `MssmtError::InvalidEncoding` if the input is not a valid proof encoding.
+    pub fn decode_with_limits(bytes: &[u8], limits: &DecodeLimits) -> Result<Self> {
+        CompressedProof::decode_with_limits(bytes, limits)?.decompress()
+    }
+
+    /// Encodes the proof as a hex string, the hex form of its compressed encoding.
+    ///
+    /// # Examples
     ///
-    pub fn verify(&self, key: [u8; 32], leaf: &LeafNode, root_hash: NodeHash) -> bool {
-        let computed_root = self.root(key, leaf);
-        computed_root.node_hash() == root_hash
+    /// ```rust
+    /// use mssmt::{DefaultStore, FullTree, Proof};
+    ///
+    /// let mut tree = FullTree::new(DefaultStore::new());
+    /// tree.insert([1u8; 32], b"one".to_vec(), 1).unwrap();
+    ///
+    /// let proof = tree.merkle_proof([1u8; 32]).unwrap();
+    /// let hex = proof.to_hex();
+    /// assert_eq!(hex.len(), 2 * proof.size_bytes());
+    /// assert_eq!(Proof::from_hex(&hex).unwrap(), proof);
+    /// ```
+    pub fn to_hex(&self) -> String {
+        self.compress().to_hex()
+    }
+
+    /// Decodes a proof produced by `to_hex`.
+    ///
+    /// # Returns
+    ///
+    /// - The decoded `Proof`.
+    /// - `MssmtError::InvalidEncoding` if the string is not valid hex or not a valid proof encoding.
+    pub fn from_hex(hex_str: &str) -> Result<Self> {
+        CompressedProof::from_hex(hex_str)?.decompress()
+    }
+}
+
+/// Aggregate size statistics over a sample of proofs.
+///
+/// Sizes are those of compressed, encoded proofs (see `Proof::size_bytes`).
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ProofStats {
+    /// The number of proofs sampled.
+    pub proofs: usize,
+    /// The combined size of all sampled proofs.
+    pub total_bytes: usize,
+    /// The size of the smallest proof.
+    pub min_bytes: usize,
+    /// The size of the largest proof.
+    pub max_bytes: usize,
+    /// The average proof size.
+    pub mean_bytes: f64,
+    /// The average number of non-empty siblings per proof.
+    pub mean_non_empty_nodes: f64,
+}
+
+impl ProofStats {
+    /// Computes the statistics of the given proofs.
+    pub fn from_proofs<'a>(proofs: impl IntoIterator<Item = &'a Proof>) -> Self {
+        let mut stats = ProofStats {
+            min_bytes: usize::MAX,
+            ..Default::default()
+        };
+        let mut non_empty_nodes = 0;
+        for proof in proofs {
+            let size = proof.size_bytes();
+            stats.proofs += 1;
+            stats.total_bytes += size;
+            stats.min_bytes = stats.min_bytes.min(size);
+            stats.max_bytes = stats.max_bytes.max(size);
+            non_empty_nodes += proof.non_empty_nodes();
+        }
+
+        if stats.proofs == 0 {
+            return ProofStats::default();
+        }
+        stats.mean_bytes = stats.total_bytes as f64 / stats.proofs as f64;
+        stats.mean_non_empty_nodes = non_empty_nodes as f64 / stats.proofs as f64;
+        stats
+    }
+}
+
+/// The size in bytes of an encoded sibling node (hash and sum).
+const ENCODED_NODE_SIZE: usize = HASH_SIZE + SUM_SIZE;
+
+/// The size in bytes of the packed empty-sibling bit vector.
+const ENCODED_BITS_SIZE: usize = MAX_TREE_LEVELS / 8;
+
+/// The first byte of encoded compressed proofs under a scheme other than `HashScheme::V0`.
+pub const SCHEME_MARKER: u8 = 0xff;
+
+/// The first byte of encoded compressed proofs under an overflow policy other than
+/// `OverflowPolicy::Checked`.
+pub const OVERFLOW_MARKER: u8 = 0xfe;
+
+/// The maximum length of a leaf value decoded under the default `DecodeLimits`, 1 MiB.
+pub const DEFAULT_MAX_VALUE_LEN: usize = 1 << 20;
+
+/// Bounds on the proofs and leaves decoded from untrusted input.
+///
+/// Every length read from the input is checked against these bounds before anything is allocated for
+/// it, so that services decoding proofs received from the network allocate at most a few kilobytes
+/// per proof, whatever the input claims. A proof never has more than `MAX_TREE_LEVELS` non-empty
+/// siblings, which the default limits allow, along with values of up to `DEFAULT_MAX_VALUE_LEN` bytes.
+///
+/// # Examples
+///
+/// ```rust
+/// use mssmt::proof::{CompressedProof, DecodeLimits};
+/// use mssmt::{DefaultStore, FullTree, MssmtError};
+///
+/// let mut tree = FullTree::new(DefaultStore::new());
+/// tree.insert([1u8; 32], b"one".to_vec(), 1).unwrap();
+/// tree.insert([2u8; 32], b"two".to_vec(), 2).unwrap();
+/// let encoded = tree.merkle_proof([1u8; 32]).unwrap().encode();
+///
+/// let limits = DecodeLimits {
+///     max_nodes: 0,
+///     ..DecodeLimits::default()
+/// };
+/// assert!(matches!(
+///     CompressedProof::decode_with_limits(&encoded, &limits),
+///     Err(MssmtError::DecodeLimit { limit: 0, actual: 1, .. })
+/// ));
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct DecodeLimits {
+    /// The maximum number of non-empty siblings of a proof. Limits above `MAX_TREE_LEVELS` allow
+    /// `MAX_TREE_LEVELS`.
+    pub max_nodes: usize,
+    /// The maximum length in bytes of the value of a leaf.
+    pub max_value_len: usize,
+}
+
+impl Default for DecodeLimits {
+    fn default() -> Self {
+        Self {
+            max_nodes: MAX_TREE_LEVELS,
+            max_value_len: DEFAULT_MAX_VALUE_LEN,
+        }
+    }
+}
+
+impl DecodeLimits {
+    /// Returns the length in bytes of the longest compressed proof encoding within the limits,
+    /// including the scheme and overflow prefixes.
+    pub fn max_proof_len(&self) -> usize {
+        4 + 2 + self.max_nodes.min(MAX_TREE_LEVELS) * ENCODED_NODE_SIZE + ENCODED_BITS_SIZE
+    }
+
+    /// Checks that `actual` items of `what` are within `limit`.
+    ///
+    /// # Returns
+    ///
+    /// - `MssmtError::DecodeLimit` if `actual` exceeds `limit`.
+    pub(crate) fn check(what: &'static str, limit: usize, actual: usize) -> Result<()> {
+        if actual > limit {
+            return Err(MssmtError::DecodeLimit {
+                what,
+                limit,
+                actual,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// A compressed Merkle proof.
+///
+/// Since MS-SMT proofs always contain one sibling per level, siblings belonging to the empty tree are
+/// replaced by a bit vector. Both `bits` and `nodes` are ordered starting at the leaf, mirroring the
+/// compressed proofs of lightninglabs' Go mssmt package.
+///
+/// # Fields
+///
+/// - `bits`: One entry per tree level, `true` if the sibling at that level is an empty subtree.
+/// - `nodes`: The non-empty siblings, in the order they appear in `bits`.
+/// - `scheme`: The `HashScheme` of the proof, which determines the empty subtrees.
+/// - `overflow`: The `OverflowPolicy` of the proof.
+///
+/// Compressed proofs hold one bit per level of the tree they were taken from, and decompress into a
+/// `Proof<K>` of the same depth. The binary encoding only covers proofs over 32-byte keys.
+///
+/// # Examples
+///
+/// ```rust
+/// use mssmt::proof::CompressedProof;
+/// use mssmt::{DefaultStore, FullTree, LeafNode, Node};
+///
+/// let mut tree = FullTree::new(DefaultStore::new());
+/// tree.insert([1u8; 32], b"one".to_vec(), 1).unwrap();
+///
+/// let encoded = tree.merkle_proof([1u8; 32]).unwrap().compress().encode();
+/// let proof = CompressedProof::decode(&encoded).unwrap().decompress().unwrap();
+///
+/// let leaf = LeafNode::new([1u8; 32], b"one".to_vec(), 1);
+/// assert!(proof.verify([1u8; 32], &leaf, tree.root().unwrap().node_hash()));
+/// ```
+#[derive(Clone)]
+pub struct CompressedProof {
+    pub bits: Vec<bool>,
+    pub nodes: Vec<Arc<dyn Node>>,
+    pub scheme: HashScheme,
+    pub overflow: OverflowPolicy,
+}
+
+impl CompressedProof {
+    /// Decompresses the proof, restoring the empty-tree siblings.
+    ///
+    /// # Returns
+    ///
+    /// - The full `Proof` over `K`-byte keys, with one sibling per level of the tree.
+    /// - `MssmtError::InvalidProofLength` if the bit vector does not hold one entry per level.
+    /// - `MssmtError::InvalidEncoding` if the bit vector does not match the number of non-empty nodes.
+    pub fn decompress<const K: usize>(&self) -> Result<Proof<K>> {
+        let levels = tree_levels(K);
+        if self.bits.len() != levels {
+            return Err(MssmtError::InvalidProofLength {
+                expected: levels,
+                actual: self.bits.len(),
+            });
+        }
+
+        let mut remaining = self.nodes.iter();
+        let mut nodes = Vec::with_capacity(levels);
+        for (i, is_empty) in self.bits.iter().enumerate() {
+            let height = levels - 1 - i;
+            let node = if *is_empty {
+                self.scheme.empty_node_at::<K>(height + 1)
+            } else {
+                remaining.next().cloned().ok_or_else(|| {
+                    MssmtError::InvalidEncoding("missing non-empty proof node".to_string())
+                })?
+            };
+            nodes.push(node);
+        }
+
+        if remaining.next().is_some() {
+            return Err(MssmtError::InvalidEncoding(
+                "unused non-empty proof nodes".to_string(),
+            ));
+        }
+
+        // Proof nodes start at the root
+        nodes.reverse();
+        Ok(Proof::with_scheme(nodes, self.scheme).with_overflow(self.overflow))
+    }
+
+    /// Encodes the compressed proof.
+    ///
+    /// The layout is a big-endian `u16` node count, followed by each non-empty node as its 32-byte hash and
+    /// big-endian sum on `SUM_SIZE` bytes, followed by the bit vector packed into 32 bytes (least significant bit first).
+    ///
+    /// Proofs under `HashScheme::V0` use this layout as is. Proofs under other schemes are prefixed by the
+    /// `SCHEME_MARKER` byte and the scheme id; since a proof has at most 256 nodes, the first byte of a
+    /// `V0` encoding is never the marker. Likewise, proofs under an overflow policy other than
+    /// `OverflowPolicy::Checked` are prefixed by the `OVERFLOW_MARKER` byte and the policy id, ahead of
+    /// the scheme.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes =
+            Vec::with_capacity(4 + self.nodes.len() * ENCODED_NODE_SIZE + ENCODED_BITS_SIZE);
+        if self.overflow != OverflowPolicy::Checked {
+            bytes.extend_from_slice(&[OVERFLOW_MARKER, self.overflow.id()]);
+        }
+        if self.scheme != HashScheme::V0 {
+            bytes.extend_from_slice(&[SCHEME_MARKER, self.scheme.id()]);
+        }
+        bytes.extend_from_slice(&(self.nodes.len() as u16).to_be_bytes());
+        for node in &self.nodes {
+            bytes.extend_from_slice(node.node_hash().as_bytes());
+            bytes.extend_from_slice(&node.node_sum().to_be_bytes());
+        }
+
+        let mut packed = [0u8; ENCODED_BITS_SIZE];
+        for (i, is_empty) in self.bits.iter().take(MAX_TREE_LEVELS).enumerate() {
+            if *is_empty {
+                packed[i / 8] |= 1 << (i % 8);
+            }
+        }
+        bytes.extend_from_slice(&packed);
+        bytes
+    }
+
+    /// Decodes a compressed proof produced by `encode`, under the default `DecodeLimits`.
+    ///
+    /// Decoding is strict: the input must contain exactly the declared number of nodes followed by the
+    /// bit vector, with no trailing data.
+    pub fn decode(bytes: &[u8]) -> Result<Self> {
+        Self::decode_with_limits(bytes, &DecodeLimits::default())
+    }
+
+    /// Decodes a compressed proof produced by `encode`, with at most `limits.max_nodes` non-empty
+    /// nodes.
+    ///
+    /// The node count is checked before the nodes are read, and the nodes are only allocated once the
+    /// input is known to hold all of them.
+    ///
+    /// # Returns
+    ///
+    /// - The decoded proof.
+    /// - `MssmtError::DecodeLimit` if the proof declares more nodes than the limits allow.
+    /// - `MssmtError::InvalidEncoding` if the input is not a valid compressed proof encoding.
+    pub fn decode_with_limits(bytes: &[u8], limits: &DecodeLimits) -> Result<Self> {
+        let (overflow, bytes) = match bytes {
+            [OVERFLOW_MARKER, id, rest @ ..] => (OverflowPolicy::from_id(*id)?, rest),
+            _ => (OverflowPolicy::Checked, bytes),
+        };
+        let (scheme, bytes) = match bytes {
+            [SCHEME_MARKER, id, rest @ ..] => (HashScheme::from_id(*id)?, rest),
+            _ => (HashScheme::V0, bytes),
+        };
+        if bytes.len() < 2 {
+            return Err(MssmtError::InvalidEncoding(
+                "missing node count".to_string(),
+            ));
+        }
+        let num_nodes = u16::from_be_bytes([bytes[0], bytes[1]]) as usize;
+        DecodeLimits::check(
+            "proof nodes",
+            limits.max_nodes.min(MAX_TREE_LEVELS),
+            num_nodes,
+        )?;
+        let expected_len = 2 + num_nodes * ENCODED_NODE_SIZE + ENCODED_BITS_SIZE;
+        if bytes.len() != expected_len {
+            return Err(MssmtError::InvalidEncoding(format!(
+                "expected {} bytes, got {}",
+                expected_len,
+                bytes.len()
+            )));
+        }
+
+        let mut nodes: Vec<Arc<dyn Node>> = Vec::with_capacity(num_nodes);
+        for chunk in bytes[2..2 + num_nodes * ENCODED_NODE_SIZE].chunks_exact(ENCODED_NODE_SIZE) {
+            let hash = NodeHash::new(to_array(&chunk[..HASH_SIZE]));
+            nodes.push(Arc::new(ComputedNode::new(
+                hash,
+                decode_sum(&chunk[HASH_SIZE..]),
+            )));
+        }
+
+        let packed = &bytes[expected_len - ENCODED_BITS_SIZE..];
+        let bits = (0..MAX_TREE_LEVELS)
+            .map(|i| packed[i / 8] & (1 << (i % 8)) != 0)
+            .collect();
+
+        Ok(Self {
+            bits,
+            nodes,
+            scheme,
+            overflow,
+        })
+    }
+
+    /// Encodes the compressed proof as a hex string, the hex form of `encode`.
+    pub fn to_hex(&self) -> String {
+        hex::encode(self.encode())
+    }
+
+    /// Decodes a compressed proof produced by `to_hex`, under the default `DecodeLimits`.
+    ///
+    /// Both lowercase and uppercase hex are accepted. Strings longer than the hex form of the longest
+    /// proof are rejected with `MssmtError::DecodeLimit` before they are decoded.
+    pub fn from_hex(hex_str: &str) -> Result<Self> {
+        let limits = DecodeLimits::default();
+        DecodeLimits::check(
+            "hex proof length",
+            2 * limits.max_proof_len(),
+            hex_str.len(),
+        )?;
+        let bytes =
+            hex::decode(hex_str).map_err(|err| MssmtError::InvalidEncoding(err.to_string()))?;
+        Self::decode_with_limits(&bytes, &limits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::{EMPTY_LEAF, EMPTY_LEAF_NODE};
+    use crate::store::DefaultStore;
+    use crate::tree::FullTree;
+
+    #[test]
+    fn test_compute_updated_root_follows_tree() -> Result<()> {
+        let mut tree = FullTree::new(DefaultStore::new());
+        let key1 = [1u8; 32];
+        let key2 = [2u8; 32];
+        tree.insert(key1, b"one".to_vec(), 1)?;
+        tree.insert(key2, b"two".to_vec(), 2)?;
+
+        // Update an existing leaf
+        let old_leaf = LeafNode::new(key2, b"two".to_vec(), 2);
+        let new_leaf = LeafNode::new(key2, b"two!".to_vec(), 5);
+        let proof = tree.merkle_proof(key2)?;
+        let updated = proof.compute_updated_root(key2, &old_leaf, &new_leaf)?;
+        tree.insert(key2, b"two!".to_vec(), 5)?;
+        assert_eq!(updated, (tree.root()?.node_hash(), 6));
+
+        // A leaf stored under another key is rejected
+        let result = proof.compute_updated_root(key1, &old_leaf, &new_leaf);
+        assert!(matches!(result, Err(MssmtError::KeyMismatch)));
+
+        // Sums that cannot be committed are rejected
+        let huge_leaf = LeafNode::new(key2, Vec::new(), Sum::MAX);
+        let result = proof.compute_updated_root(key2, &new_leaf, &huge_leaf);
+        assert!(matches!(result, Err(MssmtError::SumOverflow)));
+
+        // Replacing with the empty leaf models a deletion
+        let (_, sum) = proof.compute_updated_root(key2, &new_leaf, &EMPTY_LEAF_NODE)?;
+        assert_eq!(sum, 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_by_reference() -> Result<()> {
+        let mut tree = FullTree::new(DefaultStore::new());
+        tree.insert([1u8; 32], b"one".to_vec(), 1)?;
+        tree.insert([2u8; 32], b"two".to_vec(), 2)?;
+        let leaf = LeafNode::new([1u8; 32], b"one".to_vec(), 1);
+
+        for scheme in [HashScheme::V0, HashScheme::V1, HashScheme::Lnd] {
+            tree.set_hash_scheme(scheme);
+            let root_hash = tree.commitment()?.hash;
+            let proof = tree.scheme_proof([1u8; 32])?;
+            assert!(proof.verify_value([1u8; 32], b"one", 1, root_hash));
+            assert!(!proof.verify_value([1u8; 32], b"two", 1, root_hash));
+
+            let leaf_hash = scheme.domain().leaf_hash(&leaf);
+            assert!(proof.verify_node([1u8; 32], &ComputedNode::new(leaf_hash, 1), root_hash));
+            assert!(!proof.verify_node([1u8; 32], &ComputedNode::new(leaf_hash, 2), root_hash));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_detailed_errors() -> Result<()> {
+        let mut tree = FullTree::new(DefaultStore::new());
+        let key = [1u8; 32];
+        tree.insert(key, b"one".to_vec(), 1)?;
+        let root_hash = tree.root()?.node_hash();
+        let leaf = LeafNode::new(key, b"one".to_vec(), 1);
+
+        let mut proof = tree.merkle_proof(key)?;
+        assert_eq!(
+            proof.verify_detailed([2u8; 32], &leaf, root_hash),
+            Err(ProofError::KeyMismatch)
+        );
+
+        // The root is folded from hashes and sums alone
+        let root = proof.root(key, &leaf);
+        assert_eq!((root.node_hash(), root.node_sum()), (root_hash, 1));
+        assert!(root.is_computed());
+
+        // A sibling with a huge sum overflows at its height
+        proof.nodes[10] = Arc::new(LeafNode::new([3u8; 32], Vec::new(), Sum::MAX));
+        assert_eq!(
+            proof.verify_detailed(key, &leaf, root_hash),
+            Err(ProofError::SumOverflow { height: 10 })
+        );
+        assert_eq!(proof.root(key, &leaf).node_hash(), NodeHash::new([0u8; 32]));
+
+        proof.nodes.pop();
+        assert_eq!(
+            proof.verify_detailed(key, &leaf, root_hash),
+            Err(ProofError::InvalidLength {
+                expected: MAX_TREE_LEVELS,
+                actual: MAX_TREE_LEVELS - 1,
+            })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_non_canonical_proofs_never_verify() -> Result<()> {
+        let mut tree = FullTree::new(DefaultStore::new());
+        let key = [1u8; 32];
+        tree.insert(key, b"one".to_vec(), 1)?;
+        let root_hash = tree.root()?.node_hash();
+        let leaf = LeafNode::new(key, b"one".to_vec(), 1);
+
+        let mut proof = tree.merkle_proof(key)?;
+        proof.nodes.push(EMPTY_LEAF.clone());
+        assert!(!proof.verify(key, &leaf, root_hash));
+        assert_eq!(proof.verify_with_sum(key, &leaf, root_hash, None), None);
+        assert!(matches!(
+            proof.compute_updated_root(key, &leaf, &leaf),
+            Err(MssmtError::InvalidProofLength { .. })
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_compressed_proof_encoding() -> Result<()> {
+        // The proof of an empty tree only contains empty siblings
+        let empty_tree = FullTree::new(DefaultStore::new());
+        let encoded = empty_tree.merkle_proof([1u8; 32])?.compress().encode();
+        let mut expected = vec![0u8, 0u8];
+        expected.extend_from_slice(&[0xff; 32]);
+        assert_eq!(encoded, expected);
+
+        let mut tree = FullTree::new(DefaultStore::new());
+        tree.insert([1u8; 32], b"one".to_vec(), 1)?;
+        tree.insert([2u8; 32], b"two".to_vec(), 2)?;
+        tree.insert([0x80; 32], b"three".to_vec(), 3)?;
+
+        let proof = tree.merkle_proof([1u8; 32])?;
+        let compressed = proof.compress();
+        assert_eq!(compressed.nodes.len(), 2);
+
+        let encoded = compressed.encode();
+        assert_eq!(encoded.len(), 2 + 2 * ENCODED_NODE_SIZE + 32);
+
+        let decoded = CompressedProof::decode(&encoded)?.decompress()?;
+        let leaf = LeafNode::new([1u8; 32], b"one".to_vec(), 1);
+        assert!(decoded.verify([1u8; 32], &leaf, tree.root()?.node_hash()));
+
+        // Trailing data is rejected
+        let mut trailing = encoded.clone();
+        trailing.push(0);
+        assert!(matches!(
+            CompressedProof::decode(&trailing),
+            Err(MssmtError::InvalidEncoding(_))
+        ));
+
+        // The bit vector must account for every non-empty node
+        let mut missing_node = CompressedProof::decode(&encoded)?;
+        missing_node.nodes.pop();
+        assert!(matches!(
+            missing_node.decompress::<32>(),
+            Err(MssmtError::InvalidEncoding(_))
+        ));
+
+        // Proofs over shorter keys compress and decompress at their own depth
+        let mut short = FullTree::new(DefaultStore::<4, Vec<u8>>::default());
+        short.insert([1u8; 4], b"one".to_vec(), 1)?;
+        short.insert([2u8; 4], b"two".to_vec(), 2)?;
+        let short_proof = short.merkle_proof([1u8; 4])?;
+        let short_compressed = short_proof.compress();
+        assert_eq!(short_compressed.bits.len(), tree_levels(4));
+        assert_eq!(short_compressed.decompress::<4>()?, short_proof);
+        assert!(matches!(
+            short_compressed.decompress::<32>(),
+            Err(MssmtError::InvalidProofLength {
+                expected: MAX_TREE_LEVELS,
+                actual: 32,
+            })
+        ));
+        assert!(matches!(
+            compressed.decompress::<4>(),
+            Err(MssmtError::InvalidProofLength {
+                expected: 32,
+                actual: MAX_TREE_LEVELS,
+            })
+        ));
+
+        // Hex strings wrap the binary encoding
+        assert_eq!(compressed.to_hex(), hex::encode(&encoded));
+        assert_eq!(proof.to_hex(), compressed.to_hex());
+        assert_eq!(Proof::from_hex(&proof.to_hex().to_uppercase())?, proof);
+        assert_eq!(
+            CompressedProof::from_hex(&compressed.to_hex())?.encode(),
+            encoded
+        );
+        for invalid in ["zz", "abc", "00"] {
+            assert!(matches!(
+                Proof::from_hex(invalid),
+                Err(MssmtError::InvalidEncoding(_))
+            ));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_decode_limits_bound_untrusted_input() -> Result<()> {
+        let mut tree = FullTree::new(DefaultStore::new());
+        for i in 0..16u8 {
+            tree.insert([i * 16; 32], vec![i], 1)?;
+        }
+        let proof = tree.merkle_proof([0u8; 32])?;
+        let encoded = proof.encode();
+        assert_eq!(Proof::decode(&encoded)?, proof);
+
+        let limits = DecodeLimits {
+            max_nodes: 4,
+            max_value_len: 4,
+        };
+        assert_eq!(Proof::decode_with_limits(&encoded, &limits)?, proof);
+        let limits = DecodeLimits {
+            max_nodes: 3,
+            ..limits
+        };
+        assert!(matches!(
+            Proof::decode_with_limits(&encoded, &limits),
+            Err(MssmtError::DecodeLimit {
+                limit: 3,
+                actual: 4,
+                ..
+            })
+        ));
+
+        // Node counts beyond the tree height are rejected before the length is checked
+        let mut forged = vec![0x01, 0x01];
+        forged.extend_from_slice(&[0u8; 64]);
+        assert!(matches!(
+            CompressedProof::decode(&forged),
+            Err(MssmtError::DecodeLimit {
+                limit: MAX_TREE_LEVELS,
+                actual: 0x0101,
+                ..
+            })
+        ));
+        let longest = "00".repeat(DecodeLimits::default().max_proof_len() + 1);
+        assert!(matches!(
+            Proof::from_hex(&longest),
+            Err(MssmtError::DecodeLimit { .. })
+        ));
+
+        let leaf = LeafNode::new([1u8; 32], vec![7u8; 5], 1).encode();
+        assert!(matches!(
+            LeafNode::<32>::decode_with_limits(&leaf, &limits),
+            Err(MssmtError::DecodeLimit {
+                limit: 4,
+                actual: 5,
+                ..
+            })
+        ));
+        assert!(LeafNode::<32>::decode_with_limits(&leaf, &DecodeLimits::default()).is_ok());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_proof_siblings_are_pruned() -> Result<()> {
+        let mut tree = FullTree::new(DefaultStore::new());
+        tree.insert([1u8; 32], b"one".to_vec(), 1)?;
+        tree.insert([0x80; 32], b"secret".to_vec(), 2)?;
+
+        let proof = tree.merkle_proof([1u8; 32])?;
+        assert!(proof.nodes.iter().all(|node| node.is_computed()));
+        assert!(proof.compress().nodes.iter().all(|node| node.is_computed()));
+        let (_, _, with_value) = tree.get_with_proof([1u8; 32])?.unwrap();
+        assert!(with_value.nodes.iter().all(|node| node.is_computed()));
+
+        // The sibling still commits to the leaf it hides
+        let sibling = LeafNode::new([0x80; 32], b"secret".to_vec(), 2);
+        let sibling = tree
+            .merkle_proof([0x80; 32])?
+            .subtree_root([0x80; 32], &sibling, 1);
+        assert_eq!(proof.nodes[0].node_hash(), sibling.node_hash());
+        assert_eq!(proof.nodes[0].node_sum(), 2);
+        let leaf = LeafNode::new([1u8; 32], b"one".to_vec(), 1);
+        assert!(proof.verify([1u8; 32], &leaf, tree.root()?.node_hash()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_proof_equality_and_debug() -> Result<()> {
+        let mut tree = FullTree::new(DefaultStore::new());
+        tree.insert([1u8; 32], b"one".to_vec(), 1)?;
+        tree.insert([0x80; 32], b"two".to_vec(), 2)?;
+
+        // Decoded siblings are computed nodes, but compare equal to the original branches
+        let proof = tree.merkle_proof([1u8; 32])?;
+        let decoded = CompressedProof::decode(&proof.compress().encode())?.decompress()?;
+        assert_eq!(decoded, proof);
+        assert_eq!(proof.clone(), proof);
+        assert_ne!(tree.merkle_proof([0x80; 32])?, proof);
+
+        // Only the single non-empty sibling is rendered
+        let debug = format!("{:?}", proof);
+        assert!(debug.starts_with("Proof { len: 256, non_empty: [\"1: "));
+        assert_eq!(debug.matches(": ").count(), 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_proof_stats() -> Result<()> {
+        let mut tree = FullTree::new(DefaultStore::new());
+        assert_eq!(
+            tree.proof_stats(Vec::<[u8; 32]>::new())?,
+            ProofStats::default()
+        );
+
+        let empty_stats = tree.proof_stats([[1u8; 32]])?;
+        assert_eq!(empty_stats.min_bytes, 2 + 32);
+        assert_eq!(empty_stats.mean_non_empty_nodes, 0.0);
+
+        tree.insert([1u8; 32], b"one".to_vec(), 1)?;
+        tree.insert([2u8; 32], b"two".to_vec(), 2)?;
+        tree.insert([0x80; 32], b"three".to_vec(), 3)?;
+
+        // Keys sharing a long prefix need more siblings than the lone key on the right
+        let stats = tree.proof_stats([[1u8; 32], [2u8; 32], [0x80; 32]])?;
+        assert_eq!(stats.proofs, 3);
+        assert_eq!(stats.min_bytes, 2 + ENCODED_NODE_SIZE + 32);
+        assert_eq!(stats.max_bytes, 2 + 2 * ENCODED_NODE_SIZE + 32);
+        assert_eq!(stats.total_bytes, 2 * stats.max_bytes + stats.min_bytes);
+        assert!((stats.mean_non_empty_nodes - 5.0 / 3.0).abs() < f64::EPSILON);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_update_sibling_tracks_tree() -> Result<()> {
+        let mut tree = FullTree::new(DefaultStore::new());
+        let key = [1u8; 32];
+        let leaf = LeafNode::new(key, b"one".to_vec(), 1);
+        tree.insert(key, b"one".to_vec(), 1)?;
+        let mut proof = tree.merkle_proof(key)?;
+
+        // Insert, update and delete other leaves, patching the proof after each change
+        let changes: [([u8; 32], Option<Sum>); 4] = [
+            ([2u8; 32], Some(2)),
+            ([0x80; 32], Some(3)),
+            ([2u8; 32], Some(20)),
+            ([2u8; 32], None),
+        ];
+        for (other, sum) in changes {
+            let other_leaf = match sum {
+                Some(sum) => {
+                    tree.insert(other, vec![0], sum)?;
+                    LeafNode::new(other, vec![0], sum)
+                }
+                None => {
+                    tree.delete(other)?;
+                    EMPTY_LEAF_NODE.clone()
+                }
+            };
+            let root_hash = tree.root()?.node_hash();
+            assert!(!proof.verify(key, &leaf, root_hash));
+
+            let height = Proof::divergence_height(key, other).unwrap();
+            let sibling = tree
+                .merkle_proof(other)?
+                .subtree_root(other, &other_leaf, height);
+            proof.update_sibling(height, sibling.node_hash(), sibling.node_sum())?;
+            assert!(proof.verify(key, &leaf, root_hash));
+            assert_eq!(proof, tree.merkle_proof(key)?);
+        }
+
+        assert_eq!(Proof::divergence_height(key, key), None);
+        assert!(matches!(
+            proof.update_sibling(0, NodeHash::new([0u8; 32]), 0),
+            Err(MssmtError::InvalidHeight(0))
+        ));
+        assert!(matches!(
+            proof.update_sibling(257, NodeHash::new([0u8; 32]), 0),
+            Err(MssmtError::InvalidHeight(257))
+        ));
+
+        Ok(())
+    }
+
+    #[cfg(feature = "arbitrary")]
+    #[test]
+    fn test_arbitrary_proofs_and_ops_round_trip() -> Result<()> {
+        use crate::op::Op;
+        use crate::replay::{decode_ops, encode_ops};
+        use arbitrary::{Arbitrary, Unstructured};
+        use sha2::{Digest, Sha256};
+
+        for seed in 0u32..256 {
+            let input: Vec<u8> = (0u32..64)
+                .flat_map(|i| Sha256::digest([seed.to_be_bytes(), i.to_be_bytes()].concat()))
+                .collect();
+            let mut u = Unstructured::new(&input);
+
+            let proof = Proof::arbitrary(&mut u).expect("arbitrary proof");
+            assert_eq!(proof.nodes.len(), MAX_TREE_LEVELS);
+            assert_eq!(Proof::decode(&proof.encode())?, proof);
+            assert_eq!(Proof::from_hex(&proof.to_hex())?, proof);
+
+            let ops = Vec::<Op>::arbitrary(&mut u).expect("arbitrary ops");
+            assert_eq!(decode_ops(&encode_ops(&ops))?, ops);
+        }
+
+        Ok(())
     }
 }
@@ -0,0 +1,232 @@
+//! Caching of generated proofs between root changes.
+//!
+//! Proof-serving endpoints answer the same keys over and over between two commitments, walking the
+//! same paths of the same store each time. A `ProofCache` keeps the generated proofs in an LRU cache
+//! keyed by the root they verify against and the key they prove. The cache only ever holds proofs of
+//! one root: as soon as it is asked about another root, it drops every proof of the previous one, so a
+//! stale proof can never be served. Registered as a `TreeObserver`, it also drops them as soon as the
+//! tree changes, instead of holding on to them until the next lookup.
+
+use crate::error::Result;
+use crate::key::Key;
+use crate::node::{LeafValue, NodeHash, HASH_SIZE};
+use crate::observer::TreeObserver;
+use crate::proof::Proof;
+use crate::store::{CacheStats, TreeStoreReader};
+use crate::tree::FullTree;
+use lru::LruCache;
+use parking_lot::Mutex;
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// An LRU cache of proofs for the current root of a tree.
+///
+/// `K` is the key size in bytes of the tree, 32 by default. The cache is shared by reference, so one
+/// instance can serve every reader of a `SharedTree`.
+///
+/// # Examples
+///
+/// ```rust
+/// use mssmt::proof_cache::ProofCache;
+/// use mssmt::{DefaultStore, FullTree};
+/// use std::sync::Arc;
+///
+/// let cache = Arc::new(ProofCache::new(1024));
+/// let mut tree = FullTree::new(DefaultStore::new());
+/// tree.add_observer(cache.clone());
+/// tree.insert([1u8; 32], b"value".to_vec(), 10).unwrap();
+///
+/// tree.cached_merkle_proof(&cache, [1u8; 32]).unwrap();
+/// tree.cached_merkle_proof(&cache, [1u8; 32]).unwrap();
+/// assert_eq!(cache.stats().hits, 1);
+///
+/// // The root changed, so the cached proof is gone
+/// tree.insert([2u8; 32], b"other".to_vec(), 5).unwrap();
+/// assert!(cache.is_empty());
+/// ```
+pub struct ProofCache<const K: usize = HASH_SIZE> {
+    entries: Mutex<Entries<K>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+/// The cached proofs and the root they verify against.
+struct Entries<const K: usize> {
+    root: Option<NodeHash>,
+    proofs: LruCache<[u8; K], Proof<K>>,
+}
+
+impl<const K: usize> ProofCache<K> {
+    /// Creates a new cache holding up to `capacity` proofs. A capacity of zero is treated as one.
+    pub fn new(capacity: usize) -> Self {
+        let capacity = NonZeroUsize::new(capacity).unwrap_or(NonZeroUsize::MIN);
+        Self {
+            entries: Mutex::new(Entries {
+                root: None,
+                proofs: LruCache::new(capacity),
+            }),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns the cached proof of `key` against `root`, if any.
+    ///
+    /// Looking up a root other than the cached one drops every cached proof.
+    pub fn get(&self, root: &NodeHash, key: impl Into<Key<K>>) -> Option<Proof<K>> {
+        let key = key.into().0;
+        let mut entries = self.entries.lock();
+        entries.advance(root);
+        let proof = entries.proofs.get(&key).cloned();
+        let counter = if proof.is_some() {
+            &self.hits
+        } else {
+            &self.misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        proof
+    }
+
+    /// Caches the proof of `key` against `root`, dropping the proofs of any other root.
+    pub fn insert(&self, root: &NodeHash, key: impl Into<Key<K>>, proof: Proof<K>) {
+        let key = key.into().0;
+        let mut entries = self.entries.lock();
+        entries.advance(root);
+        entries.proofs.put(key, proof);
+    }
+
+    /// Returns the cached proof of `key` against `root`, or generates it with `generate` and caches it.
+    ///
+    /// The lock is not held while `generate` runs, so concurrent misses on the same key may each
+    /// generate the proof.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `generate`, in which case nothing is cached.
+    pub fn get_or_insert_with(
+        &self,
+        root: &NodeHash,
+        key: impl Into<Key<K>>,
+        generate: impl FnOnce() -> Result<Proof<K>>,
+    ) -> Result<Proof<K>> {
+        let key = key.into();
+        if let Some(proof) = self.get(root, key) {
+            return Ok(proof);
+        }
+        let proof = generate()?;
+        self.insert(root, key, proof.clone());
+        Ok(proof)
+    }
+
+    /// Drops every cached proof.
+    pub fn invalidate(&self) {
+        let mut entries = self.entries.lock();
+        entries.root = None;
+        entries.proofs.clear();
+    }
+
+    /// Returns the root the cached proofs verify against, `None` if nothing was cached since the last
+    /// invalidation.
+    pub fn root(&self) -> Option<NodeHash> {
+        self.entries.lock().root
+    }
+
+    /// Returns the number of cached proofs.
+    pub fn len(&self) -> usize {
+        self.entries.lock().proofs.len()
+    }
+
+    /// Returns `true` if no proof is cached.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the hit and miss counters of the lookups.
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl<const K: usize> Entries<K> {
+    /// Drops the cached proofs if they verify against another root than `root`.
+    fn advance(&mut self, root: &NodeHash) {
+        if self.root.as_ref() != Some(root) {
+            self.proofs.clear();
+            self.root = Some(*root);
+        }
+    }
+}
+
+impl<const K: usize, V> TreeObserver<K, V> for ProofCache<K> {
+    fn on_root_change(&self, _old_root: &NodeHash, _new_root: &NodeHash) {
+        self.invalidate();
+    }
+}
+
+impl<S: TreeStoreReader<K, V>, const K: usize, V: LeafValue> FullTree<S, K, V> {
+    /// Generates a Merkle proof for `key` like `merkle_proof`, serving it from `cache` when it was
+    /// already generated for the current root.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the root or the nodes on the path of the key cannot be read.
+    pub fn cached_merkle_proof(
+        &self,
+        cache: &ProofCache<K>,
+        key: impl Into<Key<K>>,
+    ) -> Result<Proof<K>> {
+        let key = key.into();
+        let root = self.root()?.node_hash();
+        cache.get_or_insert_with(&root, key, || self.merkle_proof(key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::LeafNode;
+    use crate::store::DefaultStore;
+
+    #[test]
+    fn test_proofs_are_dropped_when_the_root_changes() -> Result<()> {
+        let cache = ProofCache::new(2);
+        let mut tree = FullTree::new(DefaultStore::new());
+        tree.insert([1u8; 32], b"one".to_vec(), 1)?;
+        tree.insert([2u8; 32], b"two".to_vec(), 2)?;
+        let old_root = tree.root()?.node_hash();
+
+        // Repeated lookups are served from the cache, up to its capacity
+        for key in [[1u8; 32], [2u8; 32], [3u8; 32], [1u8; 32]] {
+            tree.cached_merkle_proof(&cache, key)?;
+        }
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.stats(), CacheStats { hits: 0, misses: 4 });
+        let proof = tree.cached_merkle_proof(&cache, [1u8; 32])?;
+        assert_eq!(cache.stats().hits, 1);
+        assert!(proof.verify(
+            [1u8; 32],
+            &LeafNode::new([1u8; 32], b"one".to_vec(), 1),
+            old_root
+        ));
+
+        // Without an observer, the first lookup against the new root drops the old proofs
+        tree.insert([1u8; 32], b"changed".to_vec(), 3)?;
+        let new_root = tree.root()?.node_hash();
+        let proof = tree.cached_merkle_proof(&cache, [1u8; 32])?;
+        assert_eq!(cache.root(), Some(new_root));
+        assert_eq!(cache.len(), 1);
+        let leaf = LeafNode::new([1u8; 32], b"changed".to_vec(), 3);
+        assert!(proof.verify([1u8; 32], &leaf, new_root));
+        assert!(cache.get(&old_root, [2u8; 32]).is_none());
+        assert_eq!(cache.root(), Some(old_root));
+
+        cache.invalidate();
+        assert!(cache.is_empty());
+        assert_eq!(cache.root(), None);
+
+        Ok(())
+    }
+}
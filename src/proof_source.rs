@@ -0,0 +1,243 @@
+//! A common interface to local and remote proof providers.
+//!
+//! Applications proving keys against a commitment either hold the tree themselves or ask the service
+//! that does. `ProofSource` covers both: `FullTree` and `SharedTree` generate proofs locally, and with
+//! the `grpc` feature, `RemoteProofSource` fetches them from a `ProofServer` run next to the tree. Code
+//! written against `ProofSource` moves between the two without changes.
+//!
+//! Proof servers holding many versions of a tree in one store do not need a tree handle per version:
+//! `ProofBuilder` proves keys against any root still in a store, descending from it by hash lookups.
+//!
+//! Proofs are requested against an explicit root, so a source never answers for another version of the
+//! tree than the one the caller holds a commitment to. Sources do not have to be trusted: the returned
+//! proof is checked against that root with `Proof::verify` like any other.
+
+use crate::config::OverflowPolicy;
+use crate::error::Result;
+use crate::key::Key;
+use crate::node::{bit_index, tree_levels, LeafValue, NodeHash, HASH_SIZE};
+use crate::proof::Proof;
+use crate::shared::SharedTree;
+use crate::store::{node_children, resolve_root, TreeStore, TreeStoreReader};
+use crate::tree::FullTree;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+#[cfg(feature = "grpc")]
+mod remote;
+
+#[cfg(feature = "grpc")]
+pub use remote::{proto, ProofServer, RemoteProofSource};
+
+/// A provider of Merkle proofs against a given root.
+///
+/// `K` is the key size in bytes of the tree, 32 by default.
+///
+/// # Examples
+///
+/// ```rust
+/// use mssmt::proof_source::ProofSource;
+/// use mssmt::{DefaultStore, FullTree, LeafNode, Node, NodeHash};
+///
+/// fn prove(source: &dyn ProofSource, root: NodeHash, key: [u8; 32], leaf: &LeafNode) -> bool {
+///     match source.fetch_proof(&root, key.into()) {
+///         Ok(proof) => proof.verify(key, leaf, root),
+///         Err(_) => false,
+///     }
+/// }
+///
+/// let mut tree = FullTree::new(DefaultStore::new());
+/// tree.insert([1u8; 32], b"value".to_vec(), 10).unwrap();
+/// let root = tree.root().unwrap().node_hash();
+///
+/// let leaf = LeafNode::new([1u8; 32], b"value".to_vec(), 10);
+/// assert!(prove(&tree, root, [1u8; 32], &leaf));
+/// ```
+pub trait ProofSource<const K: usize = HASH_SIZE> {
+    /// Returns the proof of `key`, or of its absence, in the version of the tree with root `root`.
+    ///
+    /// # Returns
+    ///
+    /// - The proof of the key against `root`.
+    /// - `MssmtError::NodeNotFound` if the source does not hold the version with root `root`.
+    fn fetch_proof(&self, root: &NodeHash, key: Key<K>) -> Result<Proof<K>>;
+}
+
+impl<S: TreeStoreReader<K, V>, const K: usize, V: LeafValue> ProofSource<K> for FullTree<S, K, V> {
+    /// Proves the key against the current root, or against a past root whose branches are still in the
+    /// store.
+    fn fetch_proof(&self, root: &NodeHash, key: Key<K>) -> Result<Proof<K>> {
+        let current = self.root()?;
+        if current.node_hash() == *root {
+            return self.merkle_proof(key);
+        }
+        let node = resolve_root(self.store(), root)?;
+        self.merkle_proof_at(node, &key.0)
+    }
+}
+
+/// Generates proofs from a store against any of the roots it holds, without a `FullTree`.
+///
+/// The proof of a key is collected by descending from the requested root, loading each branch on the
+/// path by its hash, so any version whose branches are still in the store can be proven against.
+/// `K` is the key size in bytes of the tree, 32 by default, and `V` the type of its values.
+///
+/// # Examples
+///
+/// ```rust
+/// use mssmt::proof_source::ProofBuilder;
+/// use mssmt::{DefaultStore, FullTree, LeafNode, Node};
+///
+/// let mut tree = FullTree::new(DefaultStore::new());
+/// tree.insert([1u8; 32], b"old".to_vec(), 1).unwrap();
+/// let old_root = tree.root().unwrap().node_hash();
+/// tree.insert([1u8; 32], b"new".to_vec(), 2).unwrap();
+///
+/// let builder = ProofBuilder::new(tree.store());
+/// let proof = builder.proof(&old_root, [1u8; 32]).unwrap();
+/// let leaf = LeafNode::new([1u8; 32], b"old".to_vec(), 1);
+/// assert!(proof.verify([1u8; 32], &leaf, old_root));
+/// ```
+pub struct ProofBuilder<'a, S: ?Sized, const K: usize = HASH_SIZE, V = Vec<u8>> {
+    store: &'a S,
+    overflow: OverflowPolicy,
+    _values: PhantomData<fn() -> V>,
+}
+
+impl<'a, S: TreeStoreReader<K, V> + ?Sized, const K: usize, V: LeafValue>
+    ProofBuilder<'a, S, K, V>
+{
+    /// Creates a builder generating proofs from `store`, for trees with the default overflow policy.
+    pub fn new(store: &'a S) -> Self {
+        Self {
+            store,
+            overflow: OverflowPolicy::default(),
+            _values: PhantomData,
+        }
+    }
+
+    /// Sets the overflow policy the proofs combine sums under, which must match the tree's.
+    pub fn with_overflow(mut self, overflow: OverflowPolicy) -> Self {
+        self.overflow = overflow;
+        self
+    }
+
+    /// Returns the proof of `key`, or of its absence, in the version with root `root`.
+    ///
+    /// # Returns
+    ///
+    /// - The proof of the key against `root`.
+    /// - `MssmtError::NodeNotFound` if `root`, or a branch under it, is not in the store.
+    pub fn proof(&self, root: &NodeHash, key: impl Into<Key<K>>) -> Result<Proof<K>> {
+        let key = key.into().0;
+        let mut node = resolve_root(self.store, root)?;
+        let mut nodes = Vec::with_capacity(tree_levels(K));
+        for height in 0..tree_levels(K) {
+            let (left, right) = node_children(self.store, &node, height)?;
+            node = if bit_index(height, &key) == 0 {
+                nodes.push(right);
+                left
+            } else {
+                nodes.push(left);
+                right
+            };
+        }
+        Ok(Proof::new(nodes).with_overflow(self.overflow))
+    }
+}
+
+impl<S: TreeStoreReader<K, V> + ?Sized, const K: usize, V: LeafValue> ProofSource<K>
+    for ProofBuilder<'_, S, K, V>
+{
+    fn fetch_proof(&self, root: &NodeHash, key: Key<K>) -> Result<Proof<K>> {
+        self.proof(root, key)
+    }
+}
+
+impl<S: TreeStore> ProofSource for SharedTree<S> {
+    fn fetch_proof(&self, root: &NodeHash, key: Key) -> Result<Proof> {
+        self.read(|tree| tree.fetch_proof(root, key))
+    }
+}
+
+impl<P: ProofSource<K> + ?Sized, const K: usize> ProofSource<K> for &P {
+    fn fetch_proof(&self, root: &NodeHash, key: Key<K>) -> Result<Proof<K>> {
+        (**self).fetch_proof(root, key)
+    }
+}
+
+impl<P: ProofSource<K> + ?Sized, const K: usize> ProofSource<K> for Box<P> {
+    fn fetch_proof(&self, root: &NodeHash, key: Key<K>) -> Result<Proof<K>> {
+        (**self).fetch_proof(root, key)
+    }
+}
+
+impl<P: ProofSource<K> + ?Sized, const K: usize> ProofSource<K> for Arc<P> {
+    fn fetch_proof(&self, root: &NodeHash, key: Key<K>) -> Result<Proof<K>> {
+        (**self).fetch_proof(root, key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::MssmtError;
+    use crate::node::{LeafNode, EMPTY_LEAF_NODE};
+    use crate::store::DefaultStore;
+
+    #[test]
+    fn test_local_sources_prove_against_the_requested_root() -> Result<()> {
+        let mut tree = FullTree::new(DefaultStore::new());
+        tree.insert([1u8; 32], b"old".to_vec(), 1)?;
+        let old_root = tree.root()?.node_hash();
+        tree.insert([1u8; 32], b"new".to_vec(), 2)?;
+        tree.insert([2u8; 32], b"two".to_vec(), 3)?;
+        let new_root = tree.root()?.node_hash();
+
+        let old = LeafNode::new([1u8; 32], b"old".to_vec(), 1);
+        let new = LeafNode::new([1u8; 32], b"new".to_vec(), 2);
+        let source: Box<dyn ProofSource> = Box::new(SharedTree::from_tree(tree));
+        let proof = source.fetch_proof(&new_root, [1u8; 32].into())?;
+        assert!(proof.verify([1u8; 32], &new, new_root));
+
+        // Past roots still in the store are served too
+        let proof = source.fetch_proof(&old_root, [1u8; 32].into())?;
+        assert!(proof.verify([1u8; 32], &old, old_root));
+        let proof = source.fetch_proof(&old_root, [2u8; 32].into())?;
+        assert!(proof.verify([2u8; 32], &EMPTY_LEAF_NODE, old_root));
+
+        let unknown = NodeHash::new([7u8; 32]);
+        assert!(matches!(
+            source.fetch_proof(&unknown, [1u8; 32].into()),
+            Err(MssmtError::NodeNotFound(hash)) if hash == unknown
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_proof_builder_serves_every_root_of_a_store() -> Result<()> {
+        let mut tree = FullTree::new(DefaultStore::new());
+        let mut roots = vec![tree.root()?.node_hash()];
+        for i in 1..=8u8 {
+            tree.insert([i; 32], vec![i], i.into())?;
+            roots.push(tree.root()?.node_hash());
+        }
+
+        let builder = ProofBuilder::new(tree.store());
+        for (version, root) in roots.iter().enumerate() {
+            for i in 1..=8u8 {
+                let proof = builder.fetch_proof(root, [i; 32].into())?;
+                let leaf = if usize::from(i) <= version {
+                    LeafNode::new([i; 32], vec![i], i.into())
+                } else {
+                    EMPTY_LEAF_NODE.clone()
+                };
+                assert!(proof.verify([i; 32], &leaf, *root));
+                assert_eq!(proof, tree.fetch_proof(root, [i; 32].into())?);
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,186 @@
+//! A gRPC client and server for fetching proofs from another process.
+//!
+//! `ProofServer` exposes a `SharedTree` as a gRPC service, and `RemoteProofSource` implements
+//! `ProofSource` on top of a connection to such a service. The protocol is defined in
+//! `proto/proof.proto`.
+
+use super::ProofSource;
+use crate::error::{MssmtError, Result};
+use crate::key::Key;
+use crate::node::NodeHash;
+use crate::proof::{CompressedProof, Proof};
+use crate::shared::SharedTree;
+use crate::store::remote::{decode_hash, error_status, invalid_argument, status_error};
+use crate::store::TreeStore;
+use std::sync::Arc;
+use tokio::runtime::Runtime;
+use tonic::transport::Channel;
+use tonic::{Code, Request, Response, Status};
+
+/// The generated protocol messages and service definitions.
+pub mod proto {
+    tonic::include_proto!("mssmt.proof.v1");
+}
+
+use proto::proof_service_client::ProofServiceClient;
+use proto::proof_service_server::{ProofService, ProofServiceServer};
+
+/// A `ProofSource` backed by a remote `ProofServer`.
+///
+/// Each proof is a blocking round trip, run on a runtime owned by the client, so a `RemoteProofSource`
+/// must not be used from within an asynchronous task; wrap calls in `spawn_blocking` instead.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use mssmt::proof_source::{ProofSource, RemoteProofSource};
+/// use mssmt::NodeHash;
+///
+/// let source = RemoteProofSource::connect("http://127.0.0.1:50052").unwrap();
+/// let root = NodeHash::new([0u8; 32]);
+/// let proof = source.fetch_proof(&root, [1u8; 32].into());
+/// ```
+pub struct RemoteProofSource {
+    client: ProofServiceClient<Channel>,
+    runtime: Runtime,
+}
+
+impl RemoteProofSource {
+    /// Connects to the `ProofServer` listening at `endpoint`, such as `http://127.0.0.1:50052`.
+    ///
+    /// # Returns
+    ///
+    /// - The connected source.
+    /// - `MssmtError::Store` if the connection cannot be established.
+    pub fn connect(endpoint: impl Into<String>) -> Result<Self> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        let client = runtime
+            .block_on(ProofServiceClient::connect(endpoint.into()))
+            .map_err(|err| MssmtError::Store(err.to_string()))?;
+        Ok(Self { client, runtime })
+    }
+}
+
+impl ProofSource for RemoteProofSource {
+    fn fetch_proof(&self, root: &NodeHash, key: Key) -> Result<Proof> {
+        let request = proto::GetProofRequest {
+            root: root.as_bytes().to_vec(),
+            key: key.0.to_vec(),
+        };
+        let mut client = self.client.clone();
+        let response = self
+            .runtime
+            .block_on(client.get_proof(request))
+            .map(Response::into_inner)
+            .map_err(|status| match status.code() {
+                Code::NotFound => MssmtError::NodeNotFound(*root),
+                _ => status_error("get_proof", status),
+            })?;
+        CompressedProof::decode(&response.proof)?.decompress()
+    }
+}
+
+/// A gRPC service serving the proofs of a `SharedTree` to `RemoteProofSource` clients.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use mssmt::proof_source::ProofServer;
+/// use mssmt::{DefaultStore, SharedTree};
+/// use std::sync::Arc;
+///
+/// # async fn serve() -> Result<(), Box<dyn std::error::Error>> {
+/// let tree = Arc::new(SharedTree::new(DefaultStore::new()));
+/// tonic::transport::Server::builder()
+///     .add_service(ProofServer::new(tree).into_service())
+///     .serve("127.0.0.1:50052".parse()?)
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct ProofServer<S> {
+    tree: Arc<SharedTree<S>>,
+}
+
+impl<S: TreeStore + Send + Sync + 'static> ProofServer<S> {
+    /// Creates a server for `tree`.
+    pub fn new(tree: Arc<SharedTree<S>>) -> Self {
+        Self { tree }
+    }
+
+    /// Returns the tonic service to register with a `tonic::transport::Server`.
+    pub fn into_service(self) -> ProofServiceServer<Self> {
+        ProofServiceServer::new(self)
+    }
+}
+
+#[tonic::async_trait]
+impl<S: TreeStore + Send + Sync + 'static> ProofService for ProofServer<S> {
+    async fn get_proof(
+        &self,
+        request: Request<proto::GetProofRequest>,
+    ) -> std::result::Result<Response<proto::GetProofResponse>, Status> {
+        let request = request.get_ref();
+        let root = decode_hash(&request.root).map_err(invalid_argument)?;
+        let key = decode_hash(&request.key).map_err(invalid_argument)?;
+        let proof = self
+            .tree
+            .fetch_proof(&NodeHash::new(root), Key(key))
+            .map_err(|err| match err {
+                MssmtError::NodeNotFound(hash) => Status::not_found(hash.to_string()),
+                err => error_status(err),
+            })?;
+        Ok(Response::new(proto::GetProofResponse {
+            proof: proof.compress().encode(),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::{LeafNode, Sum};
+    use tonic::transport::server::TcpIncoming;
+
+    #[test]
+    fn test_remote_proofs_match_local_ones() -> Result<()> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()?;
+        let listener = runtime.block_on(tokio::net::TcpListener::bind("127.0.0.1:0"))?;
+        let endpoint = format!("http://{}", listener.local_addr()?);
+        let incoming = TcpIncoming::from_listener(listener, true, None)
+            .map_err(|err| MssmtError::Store(err.to_string()))?;
+        let tree = Arc::new(SharedTree::new(crate::store::DefaultStore::new()));
+        for i in 0..4u8 {
+            tree.insert([i; 32], vec![i], i as Sum)?;
+        }
+        runtime.spawn(
+            tonic::transport::Server::builder()
+                .add_service(ProofServer::new(tree.clone()).into_service())
+                .serve_with_incoming(incoming),
+        );
+
+        let remote = RemoteProofSource::connect(endpoint)?;
+        let root = tree.root()?.node_hash();
+        let proof = remote.fetch_proof(&root, [1u8; 32].into())?;
+        assert!(proof.verify([1u8; 32], &LeafNode::new([1u8; 32], vec![1], 1), root));
+        assert_eq!(
+            proof.compress().encode(),
+            tree.fetch_proof(&root, [1u8; 32].into())?
+                .compress()
+                .encode()
+        );
+
+        let unknown = NodeHash::new([7u8; 32]);
+        assert!(matches!(
+            remote.fetch_proof(&unknown, [1u8; 32].into()),
+            Err(MssmtError::NodeNotFound(hash)) if hash == unknown
+        ));
+
+        Ok(())
+    }
+}
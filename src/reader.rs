@@ -0,0 +1,110 @@
+//! Read-only views of a tree.
+//!
+//! A `TreeReader` borrows the store of a tree and only exposes lookups, the root and proof generation.
+//! It can be handed to a proof-serving component without giving it the ability to mutate the
+//! commitment, and it is `Copy`, so one view can be shared by many such components.
+
+use crate::error::Result;
+use crate::key::Key;
+use crate::node::{LeafValue, Node, Sum, HASH_SIZE};
+use crate::proof::Proof;
+use crate::store::TreeStoreReader;
+use crate::tree::FullTree;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+/// A read-only view of a tree over a borrowed store.
+///
+/// # Examples
+///
+/// ```rust
+/// use mssmt::{DefaultStore, FullTree, LeafNode, Node};
+///
+/// let mut tree = FullTree::new(DefaultStore::new());
+/// tree.insert([1u8; 32], b"one".to_vec(), 1).unwrap();
+///
+/// let reader = tree.reader();
+/// let proof = reader.merkle_proof([1u8; 32]).unwrap();
+/// let leaf = LeafNode::new([1u8; 32], b"one".to_vec(), 1);
+/// assert!(proof.verify([1u8; 32], &leaf, reader.root().unwrap().node_hash()));
+/// assert_eq!(reader.get([1u8; 32]).unwrap(), Some((b"one".to_vec(), 1)));
+/// ```
+pub struct TreeReader<'a, S, const K: usize = HASH_SIZE, V = Vec<u8>> {
+    store: &'a S,
+    value: PhantomData<fn() -> V>,
+}
+
+impl<'a, S: TreeStoreReader<K, V>, const K: usize, V: LeafValue> TreeReader<'a, S, K, V> {
+    /// Creates a read-only view of the tree stored in `store`.
+    pub fn new(store: &'a S) -> Self {
+        Self {
+            store,
+            value: PhantomData,
+        }
+    }
+
+    fn tree(&self) -> FullTree<&'a S, K, V> {
+        FullTree::new(self.store)
+    }
+
+    /// Returns the root node of the tree, see `FullTree::root`.
+    pub fn root(&self) -> Result<Arc<dyn Node>> {
+        self.tree().root()
+    }
+
+    /// Retrieves the value and sum associated with a key, see `FullTree::get`.
+    pub fn get(&self, key: impl Into<Key<K>>) -> Result<Option<(V, Sum)>> {
+        self.tree().get(key)
+    }
+
+    /// Generates a Merkle proof for a key, see `FullTree::merkle_proof`.
+    pub fn merkle_proof(&self, key: impl Into<Key<K>>) -> Result<Proof<K>> {
+        self.tree().merkle_proof(key)
+    }
+}
+
+impl<S, const K: usize, V> Clone for TreeReader<'_, S, K, V> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<S, const K: usize, V> Copy for TreeReader<'_, S, K, V> {}
+
+impl<S: TreeStoreReader<K, V>, const K: usize, V: LeafValue> FullTree<S, K, V> {
+    /// Returns a read-only view of the tree, borrowing its store.
+    ///
+    /// The view does not share the observers, metrics and configuration of the tree.
+    pub fn reader(&self) -> TreeReader<'_, S, K, V> {
+        TreeReader::new(self.store())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::DefaultStore;
+
+    #[test]
+    fn test_reader_follows_tree() -> Result<()> {
+        let mut tree = FullTree::new(DefaultStore::new());
+        tree.insert([1u8; 32], b"one".to_vec(), 1)?;
+        tree.insert([2u8; 32], b"two".to_vec(), 2)?;
+
+        let reader = tree.reader();
+        let copy = reader;
+        assert_eq!(reader.root()?.node_hash(), tree.root()?.node_hash());
+        assert_eq!(copy.get([2u8; 32])?, Some((b"two".to_vec(), 2)));
+        assert_eq!(copy.get([3u8; 32])?, None);
+        assert_eq!(
+            reader.merkle_proof([2u8; 32])?,
+            tree.merkle_proof([2u8; 32])?
+        );
+
+        // Readers borrow the store, so mutations are seen by the readers created after them
+        tree.delete([2u8; 32])?;
+        assert_eq!(TreeReader::new(tree.store()).get([2u8; 32])?, None);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,211 @@
+//! Deterministic replay of operation sequences.
+//!
+//! A tree is fully determined by the operations applied to it, in order. `encode_ops` writes such a
+//! sequence in a compact binary operation log and `decode_ops` reads it back, and `replay` rebuilds the
+//! tree it produces in any store. Bug reports attach the shortest trace reproducing an issue, and the
+//! same trace replayed by another implementation of the tree must reach the same roots.
+//!
+//! The log is meant to be cut, concatenated and mutated by hand or by a fuzzer: after its header, it is
+//! a plain sequence of operations without a count or checksum, so every prefix ending on an operation
+//! boundary is a valid log, and every decodable byte string is a sequence of operations to replay.
+
+use crate::error::{MssmtError, Result};
+use crate::format::{check_version, header, Format, HEADER_SIZE};
+use crate::key::Key;
+use crate::node::Sum;
+use crate::op::Op;
+use crate::store::{Cursor, TreeStore};
+use crate::tree::FullTree;
+
+/// The magic bytes at the start of every operation log, followed by the format version.
+pub(crate) const OPS_MAGIC: &[u8; 8] = b"MSSMTOPS";
+
+/// The header of operation logs written by this release.
+const OPS_HEADER: [u8; HEADER_SIZE] = header(OPS_MAGIC, Format::OpLog.current_version());
+
+/// The tag of an insert in an operation log.
+const INSERT_TAG: u8 = 1;
+
+/// The tag of a delete in an operation log.
+const DELETE_TAG: u8 = 2;
+
+/// Encodes `ops` as an operation log.
+///
+/// The log is the header `MSSMTOPS` followed by the format version byte, then each operation in order:
+/// an insert as the byte 1, the key, the sum as a big-endian `u128`, the length of the value as a
+/// big-endian `u32` and the value, and a delete as the byte 2 and the key. Sums take 16 bytes whatever
+/// the width of `Sum`, so logs are portable between builds with and without the `u128` feature.
+///
+/// # Examples
+///
+/// ```rust
+/// use mssmt::op::Op;
+/// use mssmt::replay::{decode_ops, encode_ops, replay};
+/// use mssmt::{DefaultStore, FullTree, Node};
+///
+/// let ops = vec![
+///     Op::insert([1u8; 32], b"one".to_vec(), 1),
+///     Op::insert([2u8; 32], b"two".to_vec(), 2),
+///     Op::delete([1u8; 32]),
+/// ];
+/// let log = encode_ops(&ops);
+/// assert_eq!(decode_ops(&log).unwrap(), ops);
+///
+/// let tree = replay(&decode_ops(&log).unwrap(), DefaultStore::new()).unwrap();
+/// assert_eq!(tree.total_sum().unwrap(), 2);
+/// ```
+#[allow(clippy::useless_conversion)] // `Sum` is already `u128` with the `u128` feature
+pub fn encode_ops(ops: &[Op]) -> Vec<u8> {
+    let mut bytes = OPS_HEADER.to_vec();
+    for op in ops {
+        match op {
+            Op::Insert { key, value, sum } => {
+                bytes.push(INSERT_TAG);
+                bytes.extend_from_slice(&key.0);
+                bytes.extend_from_slice(&u128::from(*sum).to_be_bytes());
+                bytes.extend_from_slice(&(value.len() as u32).to_be_bytes());
+                bytes.extend_from_slice(value);
+            }
+            Op::Delete { key } => {
+                bytes.push(DELETE_TAG);
+                bytes.extend_from_slice(&key.0);
+            }
+        }
+    }
+    bytes
+}
+
+/// Decodes an operation log written by `encode_ops`.
+///
+/// Decoding never allocates more than the size of the input, so arbitrary inputs are safe to decode.
+///
+/// # Returns
+///
+/// - The operations, in order.
+/// - `MssmtError::InvalidEncoding` if the input is not an operation log, has an unsupported version,
+///   ends within an operation, has an unknown operation tag, or holds a sum that does not fit `Sum`.
+pub fn decode_ops(bytes: &[u8]) -> Result<Vec<Op>> {
+    if !bytes.starts_with(OPS_MAGIC) || bytes.len() < HEADER_SIZE {
+        return Err(invalid("not an operation log"));
+    }
+    check_version(Format::OpLog, bytes[HEADER_SIZE - 1])?;
+
+    let mut body = Cursor::new(&bytes[HEADER_SIZE..], Format::OpLog);
+    let mut ops = Vec::new();
+    while let Some(&tag) = body.remaining().first() {
+        body.take(1)?;
+        let key = Key(body.take_array()?);
+        ops.push(match tag {
+            INSERT_TAG => {
+                let sum = u128::from_be_bytes(body.take_array()?);
+                let sum = Sum::try_from(sum).map_err(|_| invalid("sum does not fit `Sum`"))?;
+                let len = u32::from_be_bytes(body.take_array()?) as usize;
+                let value = body.take(len)?.to_vec();
+                Op::Insert { key, value, sum }
+            }
+            DELETE_TAG => Op::Delete { key },
+            _ => return Err(invalid("unknown operation tag")),
+        });
+    }
+    Ok(ops)
+}
+
+/// Applies `ops` in order to a new tree over `store`, and returns the tree.
+///
+/// The store should be empty, so that the tree only holds what the operations produce.
+///
+/// # Returns
+///
+/// - The tree after the last operation.
+/// - The error of the first failing operation, such as `MssmtError::SumOverflow`.
+pub fn replay<S: TreeStore>(ops: &[Op], store: S) -> Result<FullTree<S>> {
+    replay_with(ops, store, |_, _| Ok(()))
+}
+
+/// Works like `replay`, calling `step` with the index of each operation and the tree after it.
+///
+/// Differential replay compares the root after every operation with the roots another implementation
+/// reaches, and stops at the first operation they disagree on by returning an error from `step`.
+///
+/// # Examples
+///
+/// ```rust
+/// use mssmt::op::Op;
+/// use mssmt::replay::replay_with;
+/// use mssmt::{DefaultStore, Node};
+///
+/// let ops = [Op::insert([1u8; 32], b"one".to_vec(), 1), Op::delete([1u8; 32])];
+/// let mut roots = Vec::new();
+/// replay_with(&ops, DefaultStore::new(), |_, tree| {
+///     roots.push(tree.root()?.node_hash());
+///     Ok(())
+/// })
+/// .unwrap();
+/// assert_eq!(roots.len(), 2);
+/// ```
+pub fn replay_with<S: TreeStore>(
+    ops: &[Op],
+    store: S,
+    mut step: impl FnMut(usize, &FullTree<S>) -> Result<()>,
+) -> Result<FullTree<S>> {
+    let mut tree = FullTree::new(store);
+    for (index, op) in ops.iter().enumerate() {
+        op.apply(&mut tree)?;
+        step(index, &tree)?;
+    }
+    Ok(tree)
+}
+
+fn invalid(message: &str) -> MssmtError {
+    MssmtError::InvalidEncoding(message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::DefaultStore;
+
+    #[test]
+    fn test_replay_reproduces_the_tree() -> Result<()> {
+        let mut ops = Vec::new();
+        let mut tree = FullTree::new(DefaultStore::new());
+        for i in 0..64u32 {
+            let key = [(i * 37 % 256) as u8; 32];
+            let op = if i % 5 == 4 {
+                Op::delete([(i * 11 % 256) as u8; 32])
+            } else {
+                Op::insert(key, i.to_be_bytes().to_vec(), Sum::from(i))
+            };
+            op.apply(&mut tree)?;
+            ops.push(op);
+        }
+
+        let log = encode_ops(&ops);
+        assert_eq!(crate::format::detect(&log)?, (Format::OpLog, 1));
+        let replayed = replay(&decode_ops(&log)?, DefaultStore::new())?;
+        assert_eq!(replayed.root()?.node_hash(), tree.root()?.node_hash());
+
+        // Every prefix on an operation boundary is a log of the first operations
+        let prefix = encode_ops(&ops[..10]);
+        assert_eq!(decode_ops(&log[..prefix.len()])?, ops[..10]);
+        assert_eq!(decode_ops(&OPS_HEADER)?, []);
+
+        // Truncated operations, unknown tags and foreign inputs are rejected
+        for input in [
+            &log[..prefix.len() + 1],
+            &[&prefix[..], &[9u8; 33]].concat(),
+            b"MSSMTSNP",
+        ] {
+            assert!(matches!(
+                decode_ops(input),
+                Err(MssmtError::InvalidEncoding(_))
+            ));
+        }
+        assert!(matches!(
+            decode_ops(&log[..prefix.len() + 1]),
+            Err(MssmtError::InvalidEncoding(message)) if message == "truncated operation log"
+        ));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,359 @@
+//! An HTTP API serving a Merkle-Sum Sparse Merkle Tree.
+//!
+//! `router` builds an axum `Router` exposing a `SharedTree` over REST, so the crate can be deployed as a
+//! standalone commitment service. Keys, values and hashes are hex encoded, and proofs are returned in
+//! the compressed encoding of `CompressedProof::encode`.
+//!
+//! | Method   | Path            | Description                                                      |
+//! |----------|-----------------|------------------------------------------------------------------|
+//! | `GET`    | `/root`         | The root hash and sum.                                           |
+//! | `GET`    | `/leaves/{key}` | The value and sum stored at a key, or 404.                       |
+//! | `PUT`    | `/leaves/{key}` | Inserts `{"value": "<hex>", "sum": <n>}` and returns the new root. |
+//! | `DELETE` | `/leaves/{key}` | Deletes a key and returns the new root.                          |
+//! | `GET`    | `/proofs/{key}` | A proof for the key with the root it verifies against. With `?format=binary`, the raw encoded proof. |
+//!
+//! Proofs are cached until the root changes, see `ProofCache`, so repeated requests for the same keys
+//! between two updates do not walk the store again.
+//!
+//! Errors are returned as `{"error": "<message>"}` with a 400 status for malformed requests, 422 for
+//! updates that would overflow the root sum, and 500 for store failures.
+//!
+//! This module requires the `server` feature.
+
+use crate::error::MssmtError;
+use crate::key::Key;
+use crate::node::Sum;
+use crate::proof_cache::ProofCache;
+use crate::shared::SharedTree;
+use crate::store::TreeStore;
+use axum::extract::{FromRef, Path, Query, State};
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// Builds the HTTP API for `tree`.
+///
+/// Tree operations run on the blocking thread pool of the tokio runtime, so stores doing blocking
+/// I/O do not stall the server.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use mssmt::{DefaultStore, SharedTree};
+/// use std::sync::Arc;
+///
+/// # async fn serve() -> std::io::Result<()> {
+/// let tree = Arc::new(SharedTree::new(DefaultStore::new()));
+/// let listener = tokio::net::TcpListener::bind("127.0.0.1:8080").await?;
+/// axum::serve(listener, mssmt::server::router(tree)).await
+/// # }
+/// ```
+pub fn router<S: TreeStore + Send + Sync + 'static>(tree: Arc<SharedTree<S>>) -> Router {
+    Router::new()
+        .route("/root", get(get_root::<S>))
+        .route(
+            "/leaves/{key}",
+            get(get_leaf::<S>)
+                .put(put_leaf::<S>)
+                .delete(delete_leaf::<S>),
+        )
+        .route("/proofs/{key}", get(get_proof::<S>))
+        .with_state(ServerState {
+            tree,
+            proofs: Arc::new(ProofCache::new(PROOF_CACHE_CAPACITY)),
+        })
+}
+
+/// The number of proofs cached by the server.
+const PROOF_CACHE_CAPACITY: usize = 4096;
+
+/// The state shared by the handlers.
+struct ServerState<S> {
+    tree: Arc<SharedTree<S>>,
+    proofs: Arc<ProofCache>,
+}
+
+impl<S> Clone for ServerState<S> {
+    fn clone(&self) -> Self {
+        Self {
+            tree: self.tree.clone(),
+            proofs: self.proofs.clone(),
+        }
+    }
+}
+
+impl<S> FromRef<ServerState<S>> for Arc<SharedTree<S>> {
+    fn from_ref(state: &ServerState<S>) -> Self {
+        state.tree.clone()
+    }
+}
+
+#[derive(Serialize)]
+struct RootResponse {
+    root: String,
+    sum: Sum,
+}
+
+#[derive(Serialize)]
+struct LeafResponse {
+    key: String,
+    value: String,
+    sum: Sum,
+}
+
+#[derive(Deserialize)]
+struct PutLeafRequest {
+    value: String,
+    sum: Sum,
+}
+
+#[derive(Serialize)]
+struct ProofResponse {
+    key: String,
+    root: String,
+    proof: String,
+}
+
+#[derive(Deserialize)]
+struct ProofQuery {
+    format: Option<String>,
+}
+
+/// An error response.
+struct ApiError(StatusCode, String);
+
+impl From<MssmtError> for ApiError {
+    fn from(err: MssmtError) -> Self {
+        let status = match err {
+            MssmtError::InvalidEncoding(_) => StatusCode::BAD_REQUEST,
+            MssmtError::SumOverflow | MssmtError::InvalidLeaf(_) => {
+                StatusCode::UNPROCESSABLE_ENTITY
+            }
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        ApiError(status, err.to_string())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        #[derive(Serialize)]
+        struct ErrorResponse {
+            error: String,
+        }
+
+        (self.0, Json(ErrorResponse { error: self.1 })).into_response()
+    }
+}
+
+type ApiResult<T> = std::result::Result<T, ApiError>;
+
+/// Runs a tree operation on the blocking thread pool.
+async fn blocking<S, T>(
+    tree: Arc<SharedTree<S>>,
+    f: impl FnOnce(&SharedTree<S>) -> crate::error::Result<T> + Send + 'static,
+) -> ApiResult<T>
+where
+    S: Send + Sync + 'static,
+    T: Send + 'static,
+{
+    tokio::task::spawn_blocking(move || f(&tree))
+        .await
+        .map_err(|err| ApiError(StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))?
+        .map_err(ApiError::from)
+}
+
+fn root_response(root: &dyn crate::node::Node) -> RootResponse {
+    RootResponse {
+        root: root.node_hash().to_string(),
+        sum: root.node_sum(),
+    }
+}
+
+async fn get_root<S: TreeStore + Send + Sync + 'static>(
+    State(tree): State<Arc<SharedTree<S>>>,
+) -> ApiResult<Json<RootResponse>> {
+    let root = blocking(tree, |tree| tree.root()).await?;
+    Ok(Json(root_response(root.as_ref())))
+}
+
+async fn get_leaf<S: TreeStore + Send + Sync + 'static>(
+    State(tree): State<Arc<SharedTree<S>>>,
+    Path(key): Path<String>,
+) -> ApiResult<Response> {
+    let key: Key = key.parse()?;
+    let leaf = blocking(tree, move |tree| tree.get(key)).await?;
+    Ok(match leaf {
+        Some((value, sum)) => Json(LeafResponse {
+            key: key.to_string(),
+            value: hex::encode(value),
+            sum,
+        })
+        .into_response(),
+        None => ApiError(StatusCode::NOT_FOUND, format!("key {} not found", key)).into_response(),
+    })
+}
+
+async fn put_leaf<S: TreeStore + Send + Sync + 'static>(
+    State(tree): State<Arc<SharedTree<S>>>,
+    Path(key): Path<String>,
+    Json(request): Json<PutLeafRequest>,
+) -> ApiResult<Json<RootResponse>> {
+    let key: Key = key.parse()?;
+    let value = hex::decode(&request.value)
+        .map_err(|err| ApiError(StatusCode::BAD_REQUEST, format!("invalid value: {}", err)))?;
+    let root = blocking(tree, move |tree| {
+        tree.write(|tree| {
+            tree.insert(key, value, request.sum)?;
+            tree.root()
+        })
+    })
+    .await?;
+    Ok(Json(root_response(root.as_ref())))
+}
+
+async fn delete_leaf<S: TreeStore + Send + Sync + 'static>(
+    State(tree): State<Arc<SharedTree<S>>>,
+    Path(key): Path<String>,
+) -> ApiResult<Json<RootResponse>> {
+    let key: Key = key.parse()?;
+    let root = blocking(tree, move |tree| {
+        tree.write(|tree| {
+            tree.delete(key)?;
+            tree.root()
+        })
+    })
+    .await?;
+    Ok(Json(root_response(root.as_ref())))
+}
+
+async fn get_proof<S: TreeStore + Send + Sync + 'static>(
+    State(state): State<ServerState<S>>,
+    Path(key): Path<String>,
+    Query(query): Query<ProofQuery>,
+) -> ApiResult<Response> {
+    let key: Key = key.parse()?;
+    // The proof and the root are read under the same lock so that they always match
+    let proofs = state.proofs;
+    let (root, proof) = blocking(state.tree, move |tree| {
+        tree.read(|tree| {
+            let root = tree.root()?;
+            let proof =
+                proofs.get_or_insert_with(&root.node_hash(), key, || tree.merkle_proof(key))?;
+            Ok((root, proof))
+        })
+    })
+    .await?;
+    let encoded = proof.compress().encode();
+
+    Ok(match query.format.as_deref() {
+        Some("binary") => (
+            [(header::CONTENT_TYPE, "application/octet-stream")],
+            encoded,
+        )
+            .into_response(),
+        None | Some("hex") => Json(ProofResponse {
+            key: key.to_string(),
+            root: root.node_hash().to_string(),
+            proof: hex::encode(encoded),
+        })
+        .into_response(),
+        Some(format) => ApiError(
+            StatusCode::BAD_REQUEST,
+            format!("unknown proof format {}", format),
+        )
+        .into_response(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::LeafNode;
+    use crate::proof::CompressedProof;
+    use crate::store::DefaultStore;
+    use axum::body::{to_bytes, Body};
+    use axum::http::Request;
+    use tower::ServiceExt;
+
+    async fn send(router: &Router, method: &str, uri: &str, body: &str) -> (StatusCode, Vec<u8>) {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let response = router.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, body.to_vec())
+    }
+
+    #[test]
+    fn test_http_api() {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let tree = Arc::new(SharedTree::new(DefaultStore::new()));
+            let router = router(tree.clone());
+            let key = "01".repeat(32);
+
+            let (status, body) = send(
+                &router,
+                "PUT",
+                &format!("/leaves/{}", key),
+                r#"{"value": "6f6e65", "sum": 1}"#,
+            )
+            .await;
+            assert_eq!(status, StatusCode::OK);
+            let root: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!(root["sum"], 1);
+            assert_eq!(root["root"], tree.root().unwrap().node_hash().to_string());
+
+            let (status, body) = send(&router, "GET", &format!("/leaves/{}", key), "").await;
+            assert_eq!(status, StatusCode::OK);
+            let leaf: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!(leaf["value"], "6f6e65");
+
+            // Binary proofs decode and verify against the root
+            let (status, body) = send(
+                &router,
+                "GET",
+                &format!("/proofs/{}?format=binary", key),
+                "",
+            )
+            .await;
+            assert_eq!(status, StatusCode::OK);
+            let proof = CompressedProof::decode(&body)
+                .unwrap()
+                .decompress()
+                .unwrap();
+            let leaf = LeafNode::new([1u8; 32], b"one".to_vec(), 1);
+            assert!(proof.verify([1u8; 32], &leaf, tree.root().unwrap().node_hash()));
+
+            let (status, _) =
+                send(&router, "GET", &format!("/leaves/{}", "02".repeat(32)), "").await;
+            assert_eq!(status, StatusCode::NOT_FOUND);
+            let (status, _) = send(&router, "GET", "/leaves/zz", "").await;
+            assert_eq!(status, StatusCode::BAD_REQUEST);
+            let (status, _) = send(
+                &router,
+                "PUT",
+                &format!("/leaves/{}", "02".repeat(32)),
+                &format!(r#"{{"value": "", "sum": {}}}"#, Sum::MAX),
+            )
+            .await;
+            assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+            let (status, body) = send(&router, "DELETE", &format!("/leaves/{}", key), "").await;
+            assert_eq!(status, StatusCode::OK);
+            let root: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!(root["sum"], 0);
+        });
+    }
+}
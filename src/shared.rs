@@ -0,0 +1,247 @@
+//! A thread-safe wrapper around `FullTree`.
+//!
+//! The `SharedTree` struct allows a tree to be shared between threads without external locking.
+//! The tree sits behind a single read-write lock: reads run concurrently with each other, but a write
+//! holds the lock exclusively for its whole update, path traversal included, so reads wait for the
+//! write in progress and writes wait for the reads in progress. Readers therefore observe either the
+//! previous or the new root, never a partially applied update.
+//!
+//! Readers that must keep being served while a writer builds the next root can instead open pinned
+//! versions of a `VersionedStore` with `FullTree::open_at`.
+
+use crate::error::Result;
+use crate::key::Key;
+use crate::node::{LeafNode, Node, Sum};
+use crate::proof::Proof;
+use crate::store::TreeStore;
+use crate::tree::FullTree;
+use parking_lot::RwLock;
+use std::sync::Arc;
+
+/// A Merkle-Sum Sparse Merkle Tree that can be read and updated through shared references.
+///
+/// Reads share the lock, while writes take it exclusively for the duration of the update.
+///
+/// # Type Parameters
+///
+/// - `S`: The storage backend implementing the `TreeStore` trait.
+///
+/// # Examples
+///
+/// ```rust
+/// use mssmt::{DefaultStore, SharedTree};
+/// use std::sync::Arc;
+/// use std::thread;
+///
+/// let tree = Arc::new(SharedTree::new(DefaultStore::new()));
+///
+/// let writer = {
+///     let tree = tree.clone();
+///     thread::spawn(move || tree.insert([1u8; 32], b"value".to_vec(), 10).unwrap())
+/// };
+/// writer.join().unwrap();
+///
+/// assert_eq!(tree.get([1u8; 32]).unwrap(), Some((b"value".to_vec(), 10)));
+/// ```
+pub struct SharedTree<S> {
+    tree: RwLock<FullTree<S>>,
+}
+
+impl<S: TreeStore> SharedTree<S> {
+    /// Creates a new `SharedTree` with the given storage backend.
+    pub fn new(store: S) -> Self {
+        Self::from_tree(FullTree::new(store))
+    }
+
+    /// Wraps an existing `FullTree`.
+    pub fn from_tree(tree: FullTree<S>) -> Self {
+        Self {
+            tree: RwLock::new(tree),
+        }
+    }
+
+    /// Consumes the `SharedTree`, returning the wrapped `FullTree`.
+    pub fn into_inner(self) -> FullTree<S> {
+        self.tree.into_inner()
+    }
+
+    /// Returns the root node of the MS-SMT.
+    pub fn root(&self) -> Result<Arc<dyn Node>> {
+        self.tree.read().root()
+    }
+
+    /// Retrieves the value and sum associated with a key.
+    ///
+    /// See [`FullTree::get`].
+    pub fn get(&self, key: impl Into<Key>) -> Result<Option<(Vec<u8>, Sum)>> {
+        let key = key.into().0;
+        self.tree.read().get(key)
+    }
+
+    /// Generates a Merkle proof for a given key.
+    ///
+    /// See [`FullTree::merkle_proof`].
+    pub fn merkle_proof(&self, key: impl Into<Key>) -> Result<Proof> {
+        let key = key.into().0;
+        self.tree.read().merkle_proof(key)
+    }
+
+    /// Returns the total sum of all values in the tree.
+    pub fn total_sum(&self) -> Result<Sum> {
+        self.tree.read().total_sum()
+    }
+
+    /// Inserts a key-value-sum entry into the tree.
+    ///
+    /// Concurrent writers are serialized. See [`FullTree::insert`].
+    pub fn insert(
+        &self,
+        key: impl Into<Key>,
+        value: Vec<u8>,
+        sum: Sum,
+    ) -> Result<Option<(Vec<u8>, Sum)>> {
+        let key = key.into().0;
+        self.tree.write().insert(key, value, sum)
+    }
+
+    /// Inserts a key-value-sum entry unless the key is already present.
+    ///
+    /// Exactly one of several writers concurrently claiming a key succeeds. See
+    /// [`FullTree::insert_if_absent`].
+    pub fn insert_if_absent(&self, key: impl Into<Key>, value: Vec<u8>, sum: Sum) -> Result<bool> {
+        let key = key.into().0;
+        self.tree.write().insert_if_absent(key, value, sum)
+    }
+
+    /// Replaces the entry at `key` with `new` if it currently holds `expected`.
+    ///
+    /// No other writer can change the entry between the comparison and the write. See
+    /// [`FullTree::compare_and_swap`].
+    pub fn compare_and_swap(
+        &self,
+        key: impl Into<Key>,
+        expected: Option<(Vec<u8>, Sum)>,
+        new: Option<(Vec<u8>, Sum)>,
+    ) -> Result<bool> {
+        let key = key.into().0;
+        self.tree.write().compare_and_swap(key, expected, new)
+    }
+
+    /// Deletes a key from the tree.
+    ///
+    /// Concurrent writers are serialized. See [`FullTree::delete`].
+    pub fn delete(&self, key: impl Into<Key>) -> Result<Option<LeafNode>> {
+        let key = key.into().0;
+        self.tree.write().delete(key)
+    }
+
+    /// Reads, transforms and writes the entry at `key` while holding the write lock.
+    ///
+    /// No other writer can change the entry between the read and the write. See [`FullTree::modify`].
+    pub fn modify(
+        &self,
+        key: impl Into<Key>,
+        f: impl FnOnce(Option<(Vec<u8>, Sum)>) -> Option<(Vec<u8>, Sum)>,
+    ) -> Result<()> {
+        let key = key.into().0;
+        self.tree.write().modify(key, f)
+    }
+
+    /// Runs a closure with shared access to the wrapped tree.
+    ///
+    /// This allows several reads to observe the same root.
+    pub fn read<R>(&self, f: impl FnOnce(&FullTree<S>) -> R) -> R {
+        f(&self.tree.read())
+    }
+
+    /// Runs a closure with exclusive access to the wrapped tree.
+    ///
+    /// This allows several updates to be applied without other writers interleaving.
+    pub fn write<R>(&self, f: impl FnOnce(&mut FullTree<S>) -> R) -> R {
+        f(&mut self.tree.write())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::DefaultStore;
+    use std::thread;
+
+    #[test]
+    fn test_concurrent_readers_and_writers() -> Result<()> {
+        let tree = SharedTree::new(DefaultStore::new());
+
+        thread::scope(|scope| {
+            for i in 0..4u8 {
+                let tree = &tree;
+                scope.spawn(move || {
+                    for j in 0..8u8 {
+                        tree.insert([i * 8 + j; 32], vec![i, j], 1).unwrap();
+                        // Readers run alongside the writers
+                        assert_eq!(tree.get([i * 8 + j; 32]).unwrap(), Some((vec![i, j], 1)));
+                    }
+                });
+            }
+        });
+
+        assert_eq!(tree.total_sum()?, 32);
+        Ok(())
+    }
+
+    #[test]
+    fn test_concurrent_modify_does_not_lose_updates() -> Result<()> {
+        let tree = SharedTree::new(DefaultStore::new());
+
+        thread::scope(|scope| {
+            for _ in 0..4 {
+                let tree = &tree;
+                scope.spawn(move || {
+                    for _ in 0..16 {
+                        tree.modify([1u8; 32], |entry| match entry {
+                            Some((value, sum)) => Some((value, sum + 1)),
+                            None => Some((b"counter".to_vec(), 1)),
+                        })
+                        .unwrap();
+                    }
+                });
+            }
+        });
+
+        assert_eq!(tree.get([1u8; 32])?, Some((b"counter".to_vec(), 64)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_concurrent_conditional_writes() -> Result<()> {
+        let tree = SharedTree::new(DefaultStore::new());
+
+        let claimed: usize = thread::scope(|scope| {
+            let writers: Vec<_> = (0..4u8)
+                .map(|i| {
+                    let tree = &tree;
+                    scope.spawn(move || {
+                        // Every writer claims the same key, then increments a counter optimistically
+                        let claimed = tree.insert_if_absent([1u8; 32], vec![i], 1).unwrap();
+                        for _ in 0..16 {
+                            loop {
+                                let read = tree.get([2u8; 32]).unwrap();
+                                let next = read.as_ref().map_or(1, |(_, sum)| sum + 1);
+                                let new = Some((b"counter".to_vec(), next));
+                                if tree.compare_and_swap([2u8; 32], read, new).unwrap() {
+                                    break;
+                                }
+                            }
+                        }
+                        claimed as usize
+                    })
+                })
+                .collect();
+            writers.into_iter().map(|w| w.join().unwrap()).sum()
+        });
+
+        assert_eq!(claimed, 1);
+        assert_eq!(tree.get([2u8; 32])?, Some((b"counter".to_vec(), 64)));
+        Ok(())
+    }
+}
@@ -0,0 +1,301 @@
+//! Partial trees built from proofs, for light clients.
+//!
+//! A light client tracks a tree by its root commitment and never holds the full tree. A `SparseTree`
+//! starts from that commitment and learns the paths of the keys it cares about from verified proofs,
+//! keeping every subtree off those paths as the hash and sum the proofs give for it. Lookups of known
+//! keys are answered locally, and updates of known keys rebuild the root from the siblings along their
+//! path, so the client follows the tree through its own updates without the server.
+//!
+//! Keys whose path leaves the known part of the tree cannot be read or updated: their lookups and
+//! updates fail with `MssmtError::NodeNotFound`, naming the subtree a proof is missing for.
+
+use crate::commitment::RootCommitment;
+use crate::error::{MssmtError, Result};
+use crate::key::Key;
+use crate::node::{
+    bit_index, new_branch, tree_levels, BranchNode, ComputedNode, EmptyTreeOf, LeafNode, LeafValue,
+    Node, Sum, HASH_SIZE,
+};
+use crate::proof::Proof;
+use crate::store::{DefaultStore, TreeStoreWriter};
+use crate::tree::FullTree;
+use std::sync::Arc;
+
+/// A tree known only along the paths of the keys it has proofs for.
+///
+/// `K` is the key size in bytes, 32 by default, and `V` the type of the values, `Vec<u8>` by default.
+///
+/// # Examples
+///
+/// ```rust
+/// use mssmt::sparse::SparseTree;
+/// use mssmt::{DefaultStore, FullTree, LeafNode};
+///
+/// let mut tree = FullTree::new(DefaultStore::new());
+/// tree.insert([1u8; 32], b"one".to_vec(), 1).unwrap();
+/// tree.insert([2u8; 32], b"two".to_vec(), 2).unwrap();
+///
+/// // The client only holds the commitment and the proof of its key
+/// let leaf = LeafNode::new([1u8; 32], b"one".to_vec(), 1);
+/// let proof = tree.merkle_proof([1u8; 32]).unwrap();
+/// let mut sparse = SparseTree::from_proofs(
+///     tree.commitment().unwrap(),
+///     [([1u8; 32].into(), Some(leaf), proof)],
+/// )
+/// .unwrap();
+/// assert_eq!(sparse.get([1u8; 32]).unwrap(), Some((b"one".to_vec(), 1)));
+/// assert!(sparse.get([2u8; 32]).is_err());
+///
+/// // Both sides apply the same update and reach the same root
+/// let root = sparse.insert([1u8; 32], b"uno".to_vec(), 5).unwrap();
+/// tree.insert([1u8; 32], b"uno".to_vec(), 5).unwrap();
+/// assert_eq!(root, tree.commitment().unwrap());
+/// ```
+pub struct SparseTree<const K: usize = HASH_SIZE, V = Vec<u8>> {
+    tree: FullTree<DefaultStore<K, V>, K, V>,
+}
+
+impl<const K: usize, V: LeafValue> SparseTree<K, V> {
+    /// Creates a tree committed to by `commitment`, with no known key yet.
+    ///
+    /// The empty tree is fully known, so every key of a tree created from the empty root can be read
+    /// and updated without proofs.
+    pub fn new(commitment: RootCommitment) -> Self {
+        let mut store = DefaultStore::<K, V>::default();
+        if !EmptyTreeOf::<K>::is_empty_at(0, &commitment.hash) {
+            store.root = Some(Arc::new(ComputedNode::new(commitment.hash, commitment.sum)));
+        }
+        Self {
+            tree: FullTree::new(store),
+        }
+    }
+
+    /// Creates a tree committed to by `commitment` and learns the paths of `proofs`, see
+    /// `SparseTree::add_proof`.
+    ///
+    /// Each proof comes with its key and the leaf at the key, `None` for a non-inclusion proof.
+    pub fn from_proofs(
+        commitment: RootCommitment,
+        proofs: impl IntoIterator<Item = (Key<K>, Option<LeafNode<K, V>>, Proof<K>)>,
+    ) -> Result<Self> {
+        let mut sparse = Self::new(commitment);
+        for (key, leaf, proof) in proofs {
+            sparse.add_proof(key, leaf, &proof)?;
+        }
+        Ok(sparse)
+    }
+
+    /// Returns the commitment to the current root.
+    pub fn commitment(&self) -> Result<RootCommitment> {
+        Ok(RootCommitment::of(self.tree.root()?.as_ref()))
+    }
+
+    /// Verifies the proof of `key` against the current root and learns the path of the key.
+    ///
+    /// `leaf` is the leaf at the key, or `None` if the proof shows the key is absent. Parts of the path
+    /// already known are kept, so proofs can be added in any order.
+    ///
+    /// # Returns
+    ///
+    /// - `MssmtError::KeyMismatch` if `leaf` is not stored under `key`.
+    /// - `MssmtError::RootHashMismatch` if the proof does not verify against the current root, in which
+    ///   case nothing is learned.
+    pub fn add_proof(
+        &mut self,
+        key: impl Into<Key<K>>,
+        leaf: Option<LeafNode<K, V>>,
+        proof: &Proof<K>,
+    ) -> Result<()> {
+        let key = key.into().0;
+        let commitment = self.commitment()?;
+        let empty = LeafNode::<K>::new([0u8; K], Vec::new(), 0);
+        let verified = match &leaf {
+            Some(leaf) if leaf.key != key => return Err(MssmtError::KeyMismatch),
+            Some(leaf) => proof.verify_against(key, leaf, &commitment),
+            None => proof.verify_against(key, &empty, &commitment),
+        };
+        if !verified {
+            let actual = match &leaf {
+                Some(leaf) => proof.root(key, leaf).node_hash(),
+                None => proof.root(key, &empty).node_hash(),
+            };
+            return Err(MssmtError::RootHashMismatch {
+                expected: commitment.hash,
+                actual,
+            });
+        }
+
+        let leaves: Vec<_> = leaf.map(Arc::new).into_iter().collect();
+        let leaf: Arc<dyn Node> = match leaves.first() {
+            Some(leaf) => leaf.clone(),
+            None => EmptyTreeOf::<K>::node_at(tree_levels(K)),
+        };
+        let mut branches = Vec::new();
+        let root = learn_path(
+            Some(self.tree.root()?),
+            0,
+            &key,
+            &leaf,
+            proof,
+            &mut branches,
+        )?;
+        let store = self.tree.store_mut();
+        store.insert_nodes(branches, leaves)?;
+        store.update_root(root)
+    }
+
+    /// Returns `true` if the path of `key` is known, so that it can be read and updated.
+    pub fn is_known(&self, key: impl Into<Key<K>>) -> Result<bool> {
+        match self.tree.contains_key(key) {
+            Ok(_) => Ok(true),
+            Err(MssmtError::NodeNotFound(_)) => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Retrieves the value and sum of a known key, or `Ok(None)` if the proofs show it is absent.
+    ///
+    /// # Returns
+    ///
+    /// - `MssmtError::NodeNotFound` if the path of the key is not known.
+    pub fn get(&self, key: impl Into<Key<K>>) -> Result<Option<(V, Sum)>> {
+        self.tree.get(key)
+    }
+
+    /// Generates a proof of a known key against the current root.
+    pub fn merkle_proof(&self, key: impl Into<Key<K>>) -> Result<Proof<K>> {
+        self.tree.merkle_proof(key)
+    }
+
+    /// Inserts or replaces the leaf of a known key and returns the commitment to the new root.
+    ///
+    /// # Returns
+    ///
+    /// - `MssmtError::NodeNotFound` if the path of the key is not known, in which case the tree is
+    ///   unchanged.
+    pub fn insert(
+        &mut self,
+        key: impl Into<Key<K>>,
+        value: impl Into<V>,
+        sum: Sum,
+    ) -> Result<RootCommitment> {
+        self.tree.insert(key, value, sum)?;
+        self.commitment()
+    }
+
+    /// Deletes a known key and returns the commitment to the new root.
+    ///
+    /// # Returns
+    ///
+    /// - `MssmtError::NodeNotFound` if the path of the key is not known, in which case the tree is
+    ///   unchanged.
+    pub fn delete(&mut self, key: impl Into<Key<K>>) -> Result<RootCommitment> {
+        self.tree.delete(key)?;
+        self.commitment()
+    }
+}
+
+/// Learns the path of `key` below `node` at `height`, `None` if the subtree is unknown, taking the
+/// siblings of unknown subtrees from `proof`. The new branches are collected bottom-up.
+fn learn_path<const K: usize>(
+    node: Option<Arc<dyn Node>>,
+    height: usize,
+    key: &[u8; K],
+    leaf: &Arc<dyn Node>,
+    proof: &Proof<K>,
+    branches: &mut Vec<Arc<BranchNode>>,
+) -> Result<Arc<dyn Node>> {
+    if height == tree_levels(K) {
+        return Ok(leaf.clone());
+    }
+    let bit = bit_index(height, key);
+    let branch = node.as_ref().and_then(|node| node.as_branch());
+    let (child, sibling) = match branch {
+        Some(branch) if bit == 0 => (Some(branch.left.clone()), branch.right.clone()),
+        Some(branch) => (Some(branch.right.clone()), branch.left.clone()),
+        // Only the hash and sum of a sibling are learned, even from a proof built by hand
+        None => {
+            let sibling = proof.nodes[height].clone();
+            let sibling = if EmptyTreeOf::<K>::is_empty_at(height + 1, &sibling.node_hash()) {
+                EmptyTreeOf::<K>::node_at(height + 1)
+            } else {
+                ComputedNode::pruned(sibling)
+            };
+            (None, sibling)
+        }
+    };
+    let child = learn_path(child, height + 1, key, leaf, proof, branches)?;
+    let (left, right) = if bit == 0 {
+        (child, sibling)
+    } else {
+        (sibling, child)
+    };
+    let branch = Arc::new(new_branch(left, right, proof.overflow)?);
+    branches.push(branch.clone());
+    Ok(branch)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sparse_tree_follows_updates_of_known_keys() -> Result<()> {
+        let mut tree = FullTree::new(DefaultStore::new());
+        for i in 1..=20u8 {
+            tree.insert([i; 32], vec![i], i as Sum)?;
+        }
+        let known = [[3u8; 32], [7u8; 32], [99u8; 32]];
+        let proofs = known.iter().map(|key| {
+            let leaf = tree
+                .get(*key)
+                .unwrap()
+                .map(|(value, sum)| LeafNode::new(*key, value, sum));
+            (Key(*key), leaf, tree.merkle_proof(*key).unwrap())
+        });
+        let mut sparse = SparseTree::from_proofs(tree.commitment()?, proofs.collect::<Vec<_>>())?;
+
+        assert_eq!(sparse.get([3u8; 32])?, Some((vec![3], 3)));
+        assert_eq!(sparse.get([99u8; 32])?, None);
+        assert!(!sparse.is_known([4u8; 32])?);
+        assert!(matches!(
+            sparse.insert([4u8; 32], vec![4], 4),
+            Err(MssmtError::NodeNotFound(_))
+        ));
+
+        // Updates of known keys track the root of the full tree
+        for (key, value, sum) in [([7u8; 32], vec![70], 70), ([99u8; 32], vec![9], 9)] {
+            assert_eq!(
+                sparse.insert(key, value.clone(), sum)?,
+                tree.insert(key, value, sum)
+                    .and_then(|_| tree.commitment())?
+            );
+        }
+        tree.delete([3u8; 32])?;
+        assert_eq!(sparse.delete([3u8; 32])?, tree.commitment()?);
+        let leaf = LeafNode::new([7u8; 32], vec![70], 70);
+        assert!(sparse.merkle_proof([7u8; 32])?.verify_against(
+            [7u8; 32],
+            &leaf,
+            &tree.commitment()?
+        ));
+
+        // Proofs of later keys are verified against the current root
+        let stale = tree.merkle_proof([4u8; 32])?;
+        tree.insert([20u8; 32], vec![0], 1)?;
+        let leaf = LeafNode::new([4u8; 32], vec![4], 4);
+        assert!(matches!(
+            sparse.add_proof(
+                [4u8; 32],
+                Some(leaf.clone()),
+                &tree.merkle_proof([4u8; 32])?
+            ),
+            Err(MssmtError::RootHashMismatch { .. })
+        ));
+        sparse.add_proof([4u8; 32], Some(leaf), &stale)?;
+        assert_eq!(sparse.get([4u8; 32])?, Some((vec![4], 4)));
+        assert_eq!(sparse.get([7u8; 32])?, Some((vec![70], 70)));
+
+        Ok(())
+    }
+}
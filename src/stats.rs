@@ -0,0 +1,159 @@
+//! Size statistics of a tree.
+//!
+//! `FullTree::stats` reports the number of leaves and branches reachable from the root, how deep the
+//! leaves sit once single-leaf chains are collapsed, and how many nodes the store holds in total,
+//! including the superseded ones left behind by updates, with the bytes they use if the store tracks
+//! them. These numbers size storage and estimate proof sizes without writing a custom walker.
+
+use crate::error::{MssmtError, Result};
+use crate::node::{EmptyTreeOf, LeafValue, Node, Sum};
+use crate::store::{resolve_node, TreeStoreReader};
+use crate::tree::FullTree;
+use std::sync::Arc;
+
+/// Statistics of a tree, see `FullTree::stats`.
+///
+/// The depth of a leaf is the number of non-empty siblings on its path, which is its depth in the
+/// path-compressed tree and the number of nodes a compressed proof of the leaf carries.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TreeStats {
+    /// The number of non-empty leaves.
+    pub leaves: usize,
+    /// The number of non-empty branches reachable from the root.
+    pub branches: usize,
+    /// The greatest depth of a leaf.
+    pub max_depth: usize,
+    /// The average depth of the leaves, 0 for an empty tree.
+    pub mean_depth: f64,
+    /// The number of branches in the store, if the store can list them.
+    pub store_branches: Option<usize>,
+    /// The number of leaves in the store, if the store can list them.
+    pub store_leaves: Option<usize>,
+    /// The bytes used by the nodes of the store, if the store tracks them.
+    pub store_bytes: Option<u64>,
+    /// The sum of the tree.
+    pub total_sum: Sum,
+}
+
+impl<S: TreeStoreReader<K, V>, const K: usize, V: LeafValue> FullTree<S, K, V> {
+    /// Computes the statistics of the tree.
+    ///
+    /// Every non-empty node reachable from the root is visited once. The store node counts and bytes
+    /// come from `TreeStoreReader::stats`, and are `None` for stores that cannot list their nodes.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use mssmt::{DefaultStore, FullTree};
+    ///
+    /// let mut tree = FullTree::new(DefaultStore::new());
+    /// tree.insert([0x00; 32], b"a".to_vec(), 1).unwrap();
+    /// tree.insert([0x80; 32], b"b".to_vec(), 2).unwrap();
+    /// tree.insert([0xc0; 32], b"c".to_vec(), 3).unwrap();
+    ///
+    /// let stats = tree.stats().unwrap();
+    /// assert_eq!((stats.leaves, stats.total_sum), (3, 6));
+    /// assert_eq!(stats.max_depth, 2);
+    /// assert_eq!(stats.store_leaves, Some(3));
+    /// ```
+    pub fn stats(&self) -> Result<TreeStats> {
+        let root = self.root()?;
+        let store_stats = supported(self.store().stats())?;
+        let mut stats = TreeStats {
+            total_sum: root.node_sum(),
+            store_branches: store_stats.map(|store_stats| store_stats.branches),
+            store_leaves: store_stats.map(|store_stats| store_stats.leaves),
+            store_bytes: store_stats.and_then(|store_stats| store_stats.bytes_used),
+            ..Default::default()
+        };
+
+        let mut total_depth = 0;
+        self.count_nodes(&root, 0, 0, &mut stats, &mut total_depth)?;
+        if stats.leaves > 0 {
+            stats.mean_depth = total_depth as f64 / stats.leaves as f64;
+        }
+        Ok(stats)
+    }
+
+    /// Counts the nodes below `node`, at `height`, whose path so far has `depth` non-empty siblings.
+    fn count_nodes(
+        &self,
+        node: &Arc<dyn Node>,
+        height: usize,
+        depth: usize,
+        stats: &mut TreeStats,
+        total_depth: &mut usize,
+    ) -> Result<()> {
+        if EmptyTreeOf::<K>::is_empty_at(height, &node.node_hash()) {
+            return Ok(());
+        }
+
+        let node = resolve_node(self.store(), node, height)?;
+        let Some(branch) = node.as_branch() else {
+            stats.leaves += 1;
+            stats.max_depth = stats.max_depth.max(depth);
+            *total_depth += depth;
+            return Ok(());
+        };
+
+        stats.branches += 1;
+        let empty_child_hash = EmptyTreeOf::<K>::hash_at(height + 1);
+        let left_empty = branch.left.node_hash() == empty_child_hash;
+        let right_empty = branch.right.node_hash() == empty_child_hash;
+        let child_depth = if left_empty || right_empty {
+            depth
+        } else {
+            depth + 1
+        };
+        self.count_nodes(&branch.left, height + 1, child_depth, stats, total_depth)?;
+        self.count_nodes(&branch.right, height + 1, child_depth, stats, total_depth)
+    }
+}
+
+/// Maps `MssmtError::Unsupported` to `None`.
+fn supported<T>(result: Result<T>) -> Result<Option<T>> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(MssmtError::Unsupported(_)) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::DefaultStore;
+
+    #[test]
+    fn test_depths_match_proof_sizes() -> Result<()> {
+        let mut tree = FullTree::new(DefaultStore::new());
+        assert_eq!(
+            tree.stats()?,
+            TreeStats {
+                store_branches: Some(0),
+                store_leaves: Some(0),
+                ..Default::default()
+            }
+        );
+
+        let keys: Vec<[u8; 32]> = (1..=12u8).map(|i| [i * 19; 32]).collect();
+        for (i, key) in keys.iter().enumerate() {
+            tree.insert(*key, vec![i as u8], i as Sum)?;
+        }
+        tree.insert(keys[0], b"updated".to_vec(), 100)?;
+
+        let stats = tree.stats()?;
+        let proofs = tree.merkle_proofs(keys.iter().copied())?;
+        let depths: Vec<usize> = proofs.iter().map(|proof| proof.non_empty_nodes()).collect();
+        assert_eq!(stats.leaves, 12);
+        assert_eq!(stats.max_depth, *depths.iter().max().unwrap());
+        assert_eq!(stats.mean_depth, depths.iter().sum::<usize>() as f64 / 12.0);
+        assert_eq!(stats.total_sum, tree.total_sum()?);
+
+        // The store keeps the superseded leaf and path
+        assert_eq!(stats.store_leaves, Some(13));
+        assert!(stats.store_branches.unwrap() > stats.branches);
+
+        Ok(())
+    }
+}
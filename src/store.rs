@@ -1,78 +1,933 @@
 //! Storage interfaces and default implementations for the Merkle-Sum Sparse Merkle Tree.
 //!
-//! This module defines the `TreeStore` trait, which specifies the storage backend interface for the tree,
-//! and provides the `DefaultStore`, an in-memory implementation suitable for testing and small datasets.
+//! This module defines the `TreeStoreReader` and `TreeStoreWriter` traits, which specify the storage backend
+//! interface for the tree, the combined `TreeStore` trait, and provides the `DefaultStore`, an in-memory implementation suitable for testing and small datasets.
+//! The `ConcurrentStore` is an in-memory variant that can be shared between threads, and `CachedStore` is an
+//! LRU caching decorator for slow backends. `LogStore` persists every write to an append-only log file and
+//! recovers the last committed root after a crash, and `OverlayStore` stages writes in memory on top of another
+//! store until they are committed or discarded. `MeteredStore` reports node traffic to a `Metrics` sink, and
+//! `EncryptedStore` seals leaf values with an AEAD before they reach another store. The persistent backends
+//! can compress large leaf values through a `ValueCompression`, and `ShardedStore` spreads nodes over several
+//! backends written concurrently. `FilteredStore` keeps a counting Bloom filter over the leaf keys of
+//! another store, so that lookups of absent keys return without walking the tree.
+//! `StoreSnapshot` copies every node of a store into a single versioned binary file and back, for backups
+//! and for cloning a store into another backend. Persistent formats are versioned, see the `format` module.
+//! `TreeStore` is object safe, and `BoxedStore` boxes any store for backends chosen at runtime. Every store
+//! reports its node counts and space usage through `TreeStoreReader::stats`, and answers liveness probes
+//! through `TreeStoreReader::health_check`.
+//! With the `grpc` feature, `RemoteStore` and `StoreServer` share one store between processes, and with the
+//! `redis` feature, `RedisStore` keeps the tree in a Redis server.
+//! With the `tokio` feature, `SpawnBlockingStore` serves any store through the `AsyncTreeStore` trait, and
+//! with the `zeroize` feature, `ScrubbingStore` wipes the leaves deleted from another store.
 
-use crate::node::{BranchNode, LeafNode, Node, NodeHash};
-use anyhow::Result;
-use std::collections::HashMap;
+use crate::error::{MssmtError, Result};
+use crate::metadata::LeafMetadata;
+use crate::node::{
+    tree_levels, BranchNode, EmptyTreeOf, LeafNode, LeafValue, Node, NodeHash, NodeKind, HASH_SIZE,
+};
+use crate::path::{Height, TreePath};
+use std::collections::{BTreeSet, HashMap};
 use std::sync::Arc;
 
-/// A trait defining the storage backend interface for the Merkle-Sum Sparse Merkle Tree.
+#[cfg(feature = "tokio")]
+mod blocking;
+mod cached;
+mod compression;
+mod concurrent;
+mod encrypted;
+mod filtered;
+mod log;
+mod metered;
+#[cfg(any(feature = "redis", feature = "grpc"))]
+mod node_cache;
+mod overlay;
+#[cfg(feature = "redis")]
+mod redis;
+#[cfg(feature = "grpc")]
+pub(crate) mod remote;
+#[cfg(feature = "zeroize")]
+mod scrubbing;
+mod sharded;
+mod snapshot;
+
+#[cfg(feature = "tokio")]
+pub use blocking::{AsyncTreeStore, SpawnBlockingStore};
+pub use cached::{CacheStats, CachedStore};
+pub use compression::{ValueCompression, ValueCompressor};
+pub use concurrent::ConcurrentStore;
+pub use encrypted::{EncryptedStore, LeafCipher};
+pub use filtered::FilteredStore;
+pub use log::LogStore;
+pub(crate) use log::LOG_MAGIC;
+pub use metered::MeteredStore;
+pub use overlay::OverlayStore;
+#[cfg(feature = "redis")]
+pub use redis::RedisStore;
+#[cfg(feature = "grpc")]
+pub use remote::{proto, RemoteStore, StoreServer};
+#[cfg(feature = "zeroize")]
+pub use scrubbing::ScrubbingStore;
+pub use sharded::ShardedStore;
+pub use snapshot::StoreSnapshot;
+pub(crate) use snapshot::{
+    read_checked, read_nodes, write_nodes, Cursor, HashingWriter, SNAPSHOT_MAGIC,
+};
+
+/// A trait defining the read side of the storage backend interface for the Merkle-Sum Sparse Merkle Tree.
 ///
-/// Implementors of this trait provide methods for storing and retrieving nodes in the tree.
-/// This abstraction allows the tree to use various storage mechanisms, such as in-memory stores, databases, or key-value stores.
+/// Implementors of this trait provide methods for retrieving nodes from the tree.
+/// Read-only operations such as `get` and proof generation only require this trait, so they can run
+/// over shared references to a store.
 ///
 /// # Required Methods
 ///
 /// - `root_node`: Returns the root node of the tree.
 /// - `get_branch`: Retrieves a branch node by its hash.
+/// - `get_branch_at`: Retrieves a branch node by its height and hash (optional, defaults to
+///   `get_branch`).
 /// - `get_leaf`: Retrieves a leaf node by its hash.
-/// - `insert_branch`: Inserts or updates a branch node.
-/// - `insert_leaf`: Inserts or updates a leaf node.
-/// - `delete_branch`: Deletes a branch node.
-/// - `delete_leaf`: Deletes a leaf node.
-/// - `update_root`: Updates the root node.
+/// - `get_leaf_by_key`: Retrieves the current leaf node for a key (optional, defaults to `None`).
+/// - `may_contain_key`: Rules out keys without a leaf in the store (optional, defaults to `true`).
+/// - `get_children`: Retrieves the children of a branch node by its hash (optional, defaults to
+///   `get_branch`).
+/// - `branch_hashes`: Lists the hashes of all stored branch nodes (optional, defaults to unsupported).
+/// - `leaf_hashes`: Lists the hashes of all stored leaf nodes (optional, defaults to unsupported).
+/// - `get_leaf_metadata`: Retrieves the metadata of a key (optional, defaults to `None`).
+/// - `preload`: Pulls the top levels below some roots into memory (optional, defaults to doing
+///   nothing).
+/// - `stats`: Counts the stored nodes and the bytes they use (optional, defaults to counting the
+///   listed hashes).
+/// - `health_check`: Checks that the store still serves the root (optional, defaults to reading the
+///   root and its children back).
 ///
-pub trait TreeStore {
+/// `K` is the key size in bytes of the leaves in the store, 32 by default, and `V` the type of their
+/// values, `Vec<u8>` by default.
+pub trait TreeStoreReader<const K: usize = HASH_SIZE, V = Vec<u8>> {
     /// Returns the root node of the tree.
     fn root_node(&self) -> Result<Arc<dyn Node>>;
 
     /// Gets a branch node by its hash.
     fn get_branch(&self, key: &NodeHash) -> Result<Option<Arc<BranchNode>>>;
 
+    /// Gets the branch node at `height` with hash `hash`.
+    ///
+    /// Tree operations know the height of every node they load and fetch branches through this method,
+    /// so that stores addressing nodes by height and hash, to shard by level or to answer empty subtrees
+    /// without a lookup, need no wrapper around the nodes. Leaves always stand at `tree_levels(K)` and
+    /// are fetched with `get_leaf`. The default implementation ignores the height and calls `get_branch`.
+    fn get_branch_at(&self, _height: usize, hash: &NodeHash) -> Result<Option<Arc<BranchNode>>> {
+        self.get_branch(hash)
+    }
+
     /// Gets a leaf node by its hash.
-    fn get_leaf(&self, key: &NodeHash) -> Result<Option<Arc<LeafNode>>>;
+    fn get_leaf(&self, key: &NodeHash) -> Result<Option<Arc<LeafNode<K, V>>>>;
 
+    /// Gets the current leaf node for a key, if the store maintains a key index.
+    ///
+    /// Lookups trust the returned leaf without walking the path, so implementations must only return
+    /// leaves committed by the current root, not the nodes of an update whose root was never written.
+    /// Stores without a key index can rely on the default implementation, which returns `Ok(None)`.
+    /// In that case the tree falls back to walking the path from the root.
+    fn get_leaf_by_key(&self, _key: &[u8; K]) -> Result<Option<Arc<LeafNode<K, V>>>> {
+        Ok(None)
+    }
+
+    /// Returns `false` if the store holds no leaf for `key`, so that lookups of absent keys can skip the
+    /// path walk.
+    ///
+    /// A `true` answer only means the key may be present. The default implementation always returns
+    /// `Ok(true)`; stores keeping a filter over their leaf keys, such as `FilteredStore`, answer `false`
+    /// for most absent keys. Implementations must never return `false` for a key with a stored leaf.
+    fn may_contain_key(&self, _key: &[u8; K]) -> Result<bool> {
+        Ok(true)
+    }
+
+    /// Gets the children of the branch node at `height` with hash `hash`.
+    ///
+    /// Children may be hash references (see `BranchNode::from_child_refs`), to be resolved at
+    /// `height + 1`. Stores keeping parent to children adjacency can override this method to serve
+    /// traversals such as proof generation without building `BranchNode`s. The default implementation
+    /// returns the children of the empty subtree for its hash, and otherwise those of the branch
+    /// returned by `get_branch_at`.
+    ///
+    /// # Returns
+    ///
+    /// - The left and right children of the branch.
+    /// - `MssmtError::NodeNotFound` if the branch is not in the store.
+    fn get_children(
+        &self,
+        height: usize,
+        hash: &NodeHash,
+    ) -> Result<(Arc<dyn Node>, Arc<dyn Node>)> {
+        if EmptyTreeOf::<K>::is_empty_at(height, hash) {
+            let empty = EmptyTreeOf::<K>::node_at(height + 1);
+            return Ok((empty.clone(), empty));
+        }
+        match self.get_branch_at(height, hash)? {
+            Some(branch) => Ok((branch.left.clone(), branch.right.clone())),
+            None => Err(MssmtError::NodeNotFound(*hash)),
+        }
+    }
+
+    /// Returns the hashes of all branch nodes in the store.
+    ///
+    /// Listing is needed by maintenance operations such as compaction. The order is up to the store;
+    /// callers that export the listing sort it first. The default implementation returns
+    /// `MssmtError::Unsupported`.
+    fn branch_hashes(&self) -> Result<Vec<NodeHash>> {
+        Err(MssmtError::Unsupported("branch_hashes"))
+    }
+
+    /// Returns the hashes of all leaf nodes in the store.
+    ///
+    /// The default implementation returns `MssmtError::Unsupported`.
+    fn leaf_hashes(&self) -> Result<Vec<NodeHash>> {
+        Err(MssmtError::Unsupported("leaf_hashes"))
+    }
+
+    /// Gets the metadata of `key`, see `FullTree::enable_leaf_metadata`.
+    ///
+    /// The default implementation returns `Ok(None)`, for stores without metadata storage.
+    fn get_leaf_metadata(&self, _key: &[u8; K]) -> Result<Option<LeafMetadata>> {
+        Ok(None)
+    }
+
+    /// Pulls the branches of the top `depth` levels below each of `roots` into memory, the roots
+    /// included, so that the first traversals of those trees do not pay cold reads.
+    ///
+    /// A process serving proofs from a disk or network backend calls it at startup, usually through
+    /// `FullTree::warm_cache`. Preloading is best effort: branches missing from the store are skipped.
+    /// The default implementation does nothing, which suits stores holding their nodes in memory;
+    /// caching stores such as `CachedStore` override it.
+    fn preload(&self, _roots: &[NodeHash], _depth: usize) -> Result<()> {
+        Ok(())
+    }
+
+    /// Returns the number of nodes in the store and, if the store tracks it, the space they use.
+    ///
+    /// Operators poll it to follow the growth of a backend, superseded nodes included until compaction
+    /// removes them. The default implementation counts the hashes returned by `branch_hashes` and
+    /// `leaf_hashes`, and reports no byte count.
+    fn stats(&self) -> Result<StoreStats> {
+        Ok(StoreStats {
+            branches: self.branch_hashes()?.len(),
+            leaves: self.leaf_hashes()?.len(),
+            bytes_used: None,
+        })
+    }
+
+    /// Checks that the store can still serve the tree, so that partial corruption or a lost backend is
+    /// detected before it fails a proof.
+    ///
+    /// The check is meant to be cheap enough for a liveness probe; `FullTree::verify_integrity` audits
+    /// every node instead. The default implementation reads the root and its two children back from the
+    /// store and checks that the sum of the root is the sum of its children.
+    ///
+    /// # Returns
+    ///
+    /// - `MssmtError::NodeNotFound` if the root or one of its children is missing from the store.
+    /// - `MssmtError::CorruptedTree` if the sum of the root does not match its children.
+    /// - Any error of the store, such as an unreachable server.
+    fn health_check(&self) -> Result<()> {
+        let root = self.root_node()?;
+        let hash = root.node_hash();
+        let (left, right) = self.get_children(0, &hash)?;
+        for child in [&left, &right] {
+            let child_hash = child.node_hash();
+            if !EmptyTreeOf::<K>::is_empty_at(1, &child_hash)
+                && self.get_branch_at(1, &child_hash)?.is_none()
+            {
+                return Err(MssmtError::NodeNotFound(child_hash));
+            }
+        }
+        if left.node_sum().checked_add(right.node_sum()) != Some(root.node_sum()) {
+            return Err(MssmtError::CorruptedTree {
+                height: 0,
+                hash,
+                reason: "root sum does not match its children",
+            });
+        }
+        Ok(())
+    }
+}
+
+/// The node counts and space usage of a store, see `TreeStoreReader::stats`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StoreStats {
+    /// The number of branches in the store.
+    pub branches: usize,
+    /// The number of leaves in the store.
+    pub leaves: usize,
+    /// The number of bytes the nodes take up in the backend, if the store tracks it.
+    pub bytes_used: Option<u64>,
+}
+
+/// A trait defining the write side of the storage backend interface for the Merkle-Sum Sparse Merkle Tree.
+///
+/// # Required Methods
+///
+/// - `insert_branch`: Inserts or updates a branch node.
+/// - `insert_branch_at`: Inserts or updates a branch node at a height (optional, defaults to
+///   `insert_branch`).
+/// - `insert_leaf`: Inserts or updates a leaf node.
+/// - `insert_nodes`: Inserts a batch of nodes (optional, defaults to inserting them one by one).
+/// - `insert_nodes_at`: Inserts a batch of nodes with the heights of the branches (optional, defaults
+///   to `insert_nodes`).
+/// - `delete_branch`: Deletes a branch node (optional, defaults to keeping the node).
+/// - `delete_branch_at`: Deletes a branch node at a height (optional, defaults to `delete_branch`).
+/// - `delete_leaf`: Deletes a leaf node (optional, defaults to keeping the node).
+/// - `set_leaf_metadata`: Sets or removes the metadata of a key (optional, defaults to dropping it).
+/// - `update_root`: Updates the root node.
+/// - `compare_and_update_root`: Updates the root node if it has not changed (optional, defaults to
+///   unsupported).
+///
+/// A new backend thus only needs `root_node`, `get_branch`, `get_leaf`, `insert_branch`, `insert_leaf`
+/// and `update_root` to hold a tree.
+///
+/// Tree operations write and delete branches through the `_at` methods, which pass the height of the
+/// branch along, so a backend addressing nodes by height and hash overrides `get_branch_at`,
+/// `insert_branch_at`, `insert_nodes_at` and `delete_branch_at`. It still implements the hash-only
+/// methods, which store decorators and maintenance operations such as compaction use, for instance by
+/// probing the levels or through an index from hash to height.
+pub trait TreeStoreWriter<const K: usize = HASH_SIZE, V = Vec<u8>> {
     /// Inserts or updates a branch node.
     fn insert_branch(&mut self, branch: Arc<BranchNode>) -> Result<()>;
 
+    /// Inserts or updates the branch node at `height`.
+    ///
+    /// The default implementation ignores the height and calls `insert_branch`.
+    fn insert_branch_at(&mut self, _height: usize, branch: Arc<BranchNode>) -> Result<()> {
+        self.insert_branch(branch)
+    }
+
     /// Inserts or updates a leaf node.
-    fn insert_leaf(&mut self, leaf: Arc<LeafNode>) -> Result<()>;
+    fn insert_leaf(&mut self, leaf: Arc<LeafNode<K, V>>) -> Result<()>;
+
+    /// Inserts `leaves`, then `branches` in order, so that no branch is written before the nodes it
+    /// may reference.
+    ///
+    /// Tree operations collect the nodes of an update and insert them with a single call once the
+    /// update is computed, bottom-up. The default implementation inserts them one by one; backends
+    /// paying a round trip or a sync per write override it to write the batch at once.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error of the store, after which the batch may be partially written. Nodes
+    /// written without the root update referencing them are removed by compaction.
+    fn insert_nodes(
+        &mut self,
+        branches: Vec<Arc<BranchNode>>,
+        leaves: Vec<Arc<LeafNode<K, V>>>,
+    ) -> Result<()> {
+        for leaf in leaves {
+            self.insert_leaf(leaf)?;
+        }
+        for branch in branches {
+            self.insert_branch(branch)?;
+        }
+        Ok(())
+    }
+
+    /// Inserts `leaves`, then `branches` in order like `insert_nodes`, each branch with its height.
+    ///
+    /// Tree operations insert the nodes of their updates with this method. The default implementation
+    /// drops the heights and calls `insert_nodes`, so that backends batching `insert_nodes` keep doing
+    /// so.
+    fn insert_nodes_at(
+        &mut self,
+        branches: Vec<(usize, Arc<BranchNode>)>,
+        leaves: Vec<Arc<LeafNode<K, V>>>,
+    ) -> Result<()> {
+        let branches = branches.into_iter().map(|(_, branch)| branch).collect();
+        self.insert_nodes(branches, leaves)
+    }
 
     /// Deletes a branch node.
-    fn delete_branch(&mut self, key: &NodeHash) -> Result<()>;
+    ///
+    /// The default implementation keeps the node, which suits append-only backends. Nodes superseded
+    /// by updates then stay in the store, and compaction cannot reclaim them.
+    fn delete_branch(&mut self, _key: &NodeHash) -> Result<()> {
+        Ok(())
+    }
+
+    /// Deletes the branch node at `height` with hash `key`.
+    ///
+    /// The default implementation ignores the height and calls `delete_branch`.
+    fn delete_branch_at(&mut self, _height: usize, key: &NodeHash) -> Result<()> {
+        self.delete_branch(key)
+    }
 
     /// Deletes a leaf node.
-    fn delete_leaf(&mut self, key: &NodeHash) -> Result<()>;
+    ///
+    /// The default implementation keeps the node, like `delete_branch`. Stores maintaining a key index
+    /// for `TreeStoreReader::get_leaf_by_key` must override it, so that deleted keys leave the index.
+    fn delete_leaf(&mut self, _key: &NodeHash) -> Result<()> {
+        Ok(())
+    }
+
+    /// Sets the metadata of `key`, or removes it if `metadata` is `None`.
+    ///
+    /// Metadata is not part of any node, so it is written outside of `insert_nodes`. The default
+    /// implementation drops it, and `TreeStoreReader::get_leaf_metadata` then returns `Ok(None)`.
+    fn set_leaf_metadata(&mut self, _key: &[u8; K], _metadata: Option<LeafMetadata>) -> Result<()> {
+        Ok(())
+    }
 
     /// Updates the root node.
     fn update_root(&mut self, root: Arc<dyn Node>) -> Result<()>;
+
+    /// Updates the root node to `root` if the current root hash is `expected`, as a single atomic step.
+    ///
+    /// Writers sharing a backend stage their nodes, then swap in the root they derived from the root
+    /// they read. A writer whose swap fails lost a race to another writer and retries from the new
+    /// root, instead of silently discarding the other writer's update. The nodes it wrote are
+    /// unreachable and removed by compaction.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(true)` if the root was updated.
+    /// - `Ok(false)` if the current root is not `expected`, in which case the root is unchanged.
+    /// - `MssmtError::Unsupported` if the store cannot compare and update its root atomically.
+    fn compare_and_update_root(
+        &mut self,
+        _expected: NodeHash,
+        _root: Arc<dyn Node>,
+    ) -> Result<bool> {
+        Err(MssmtError::Unsupported("compare_and_update_root"))
+    }
+}
+
+/// A trait defining the full storage backend interface for the Merkle-Sum Sparse Merkle Tree.
+///
+/// `TreeStore` combines `TreeStoreReader` and `TreeStoreWriter` and is implemented automatically for
+/// every type implementing both. This abstraction allows the tree to use various storage mechanisms,
+/// such as in-memory stores, databases, or key-value stores.
+pub trait TreeStore<const K: usize = HASH_SIZE, V = Vec<u8>>:
+    TreeStoreReader<K, V> + TreeStoreWriter<K, V>
+{
+}
+
+impl<T: TreeStoreReader<K, V> + TreeStoreWriter<K, V>, const K: usize, V> TreeStore<K, V> for T {}
+
+/// Helpers available on every store, built on the methods of `TreeStoreReader`.
+///
+/// # Examples
+///
+/// ```rust
+/// use mssmt::store::StoreExt;
+/// use mssmt::{DefaultStore, FullTree, Node};
+///
+/// let mut tree = FullTree::new(DefaultStore::new());
+/// tree.insert([1u8; 32], b"value".to_vec(), 10).unwrap();
+/// let root = tree.root().unwrap().node_hash();
+///
+/// let node = tree.store().get_node(&root).unwrap().unwrap();
+/// assert_eq!(node.node_sum(), 10);
+/// ```
+pub trait StoreExt<const K: usize = HASH_SIZE, V: LeafValue = Vec<u8>>:
+    TreeStoreReader<K, V>
+{
+    /// Gets a branch or leaf node by its hash, whichever the store holds.
+    ///
+    /// Empty subtrees are never written to a store, so their hashes yield `Ok(None)`.
+    fn get_node(&self, hash: &NodeHash) -> Result<Option<Arc<dyn Node>>> {
+        if let Some(branch) = self.get_branch(hash)? {
+            return Ok(Some(branch));
+        }
+        Ok(self.get_leaf(hash)?.map(|leaf| leaf as Arc<dyn Node>))
+    }
+
+    /// Returns `true` if the store holds a branch or leaf with hash `hash`.
+    fn contains_node(&self, hash: &NodeHash) -> Result<bool> {
+        Ok(self.get_node(hash)?.is_some())
+    }
+}
+
+impl<S: TreeStoreReader<K, V> + ?Sized, const K: usize, V: LeafValue> StoreExt<K, V> for S {}
+
+impl<S: TreeStoreReader<K, V> + ?Sized, const K: usize, V> TreeStoreReader<K, V> for &S {
+    fn root_node(&self) -> Result<Arc<dyn Node>> {
+        (**self).root_node()
+    }
+
+    fn get_branch(&self, key: &NodeHash) -> Result<Option<Arc<BranchNode>>> {
+        (**self).get_branch(key)
+    }
+
+    fn get_branch_at(&self, height: usize, hash: &NodeHash) -> Result<Option<Arc<BranchNode>>> {
+        (**self).get_branch_at(height, hash)
+    }
+
+    fn get_leaf(&self, key: &NodeHash) -> Result<Option<Arc<LeafNode<K, V>>>> {
+        (**self).get_leaf(key)
+    }
+
+    fn get_leaf_by_key(&self, key: &[u8; K]) -> Result<Option<Arc<LeafNode<K, V>>>> {
+        (**self).get_leaf_by_key(key)
+    }
+
+    fn may_contain_key(&self, key: &[u8; K]) -> Result<bool> {
+        (**self).may_contain_key(key)
+    }
+
+    fn get_children(
+        &self,
+        height: usize,
+        hash: &NodeHash,
+    ) -> Result<(Arc<dyn Node>, Arc<dyn Node>)> {
+        (**self).get_children(height, hash)
+    }
+
+    fn branch_hashes(&self) -> Result<Vec<NodeHash>> {
+        (**self).branch_hashes()
+    }
+
+    fn leaf_hashes(&self) -> Result<Vec<NodeHash>> {
+        (**self).leaf_hashes()
+    }
+
+    fn get_leaf_metadata(&self, key: &[u8; K]) -> Result<Option<LeafMetadata>> {
+        (**self).get_leaf_metadata(key)
+    }
+
+    fn preload(&self, roots: &[NodeHash], depth: usize) -> Result<()> {
+        (**self).preload(roots, depth)
+    }
+
+    fn stats(&self) -> Result<StoreStats> {
+        (**self).stats()
+    }
+
+    fn health_check(&self) -> Result<()> {
+        (**self).health_check()
+    }
+}
+
+impl<S: TreeStoreReader<K, V> + ?Sized, const K: usize, V> TreeStoreReader<K, V> for &mut S {
+    fn root_node(&self) -> Result<Arc<dyn Node>> {
+        (**self).root_node()
+    }
+
+    fn get_branch(&self, key: &NodeHash) -> Result<Option<Arc<BranchNode>>> {
+        (**self).get_branch(key)
+    }
+
+    fn get_branch_at(&self, height: usize, hash: &NodeHash) -> Result<Option<Arc<BranchNode>>> {
+        (**self).get_branch_at(height, hash)
+    }
+
+    fn get_leaf(&self, key: &NodeHash) -> Result<Option<Arc<LeafNode<K, V>>>> {
+        (**self).get_leaf(key)
+    }
+
+    fn get_leaf_by_key(&self, key: &[u8; K]) -> Result<Option<Arc<LeafNode<K, V>>>> {
+        (**self).get_leaf_by_key(key)
+    }
+
+    fn may_contain_key(&self, key: &[u8; K]) -> Result<bool> {
+        (**self).may_contain_key(key)
+    }
+
+    fn get_children(
+        &self,
+        height: usize,
+        hash: &NodeHash,
+    ) -> Result<(Arc<dyn Node>, Arc<dyn Node>)> {
+        (**self).get_children(height, hash)
+    }
+
+    fn branch_hashes(&self) -> Result<Vec<NodeHash>> {
+        (**self).branch_hashes()
+    }
+
+    fn leaf_hashes(&self) -> Result<Vec<NodeHash>> {
+        (**self).leaf_hashes()
+    }
+
+    fn get_leaf_metadata(&self, key: &[u8; K]) -> Result<Option<LeafMetadata>> {
+        (**self).get_leaf_metadata(key)
+    }
+
+    fn preload(&self, roots: &[NodeHash], depth: usize) -> Result<()> {
+        (**self).preload(roots, depth)
+    }
+
+    fn stats(&self) -> Result<StoreStats> {
+        (**self).stats()
+    }
+
+    fn health_check(&self) -> Result<()> {
+        (**self).health_check()
+    }
+}
+
+impl<S: TreeStoreWriter<K, V> + ?Sized, const K: usize, V> TreeStoreWriter<K, V> for &mut S {
+    fn insert_branch(&mut self, branch: Arc<BranchNode>) -> Result<()> {
+        (**self).insert_branch(branch)
+    }
+
+    fn insert_branch_at(&mut self, height: usize, branch: Arc<BranchNode>) -> Result<()> {
+        (**self).insert_branch_at(height, branch)
+    }
+
+    fn insert_leaf(&mut self, leaf: Arc<LeafNode<K, V>>) -> Result<()> {
+        (**self).insert_leaf(leaf)
+    }
+
+    fn insert_nodes(
+        &mut self,
+        branches: Vec<Arc<BranchNode>>,
+        leaves: Vec<Arc<LeafNode<K, V>>>,
+    ) -> Result<()> {
+        (**self).insert_nodes(branches, leaves)
+    }
+
+    fn insert_nodes_at(
+        &mut self,
+        branches: Vec<(usize, Arc<BranchNode>)>,
+        leaves: Vec<Arc<LeafNode<K, V>>>,
+    ) -> Result<()> {
+        (**self).insert_nodes_at(branches, leaves)
+    }
+
+    fn delete_branch(&mut self, key: &NodeHash) -> Result<()> {
+        (**self).delete_branch(key)
+    }
+
+    fn delete_branch_at(&mut self, height: usize, key: &NodeHash) -> Result<()> {
+        (**self).delete_branch_at(height, key)
+    }
+
+    fn delete_leaf(&mut self, key: &NodeHash) -> Result<()> {
+        (**self).delete_leaf(key)
+    }
+
+    fn set_leaf_metadata(&mut self, key: &[u8; K], metadata: Option<LeafMetadata>) -> Result<()> {
+        (**self).set_leaf_metadata(key, metadata)
+    }
+
+    fn update_root(&mut self, root: Arc<dyn Node>) -> Result<()> {
+        (**self).update_root(root)
+    }
+
+    fn compare_and_update_root(&mut self, expected: NodeHash, root: Arc<dyn Node>) -> Result<bool> {
+        (**self).compare_and_update_root(expected, root)
+    }
+}
+
+impl<S: TreeStoreReader<K, V> + ?Sized, const K: usize, V> TreeStoreReader<K, V> for Box<S> {
+    fn root_node(&self) -> Result<Arc<dyn Node>> {
+        (**self).root_node()
+    }
+
+    fn get_branch(&self, key: &NodeHash) -> Result<Option<Arc<BranchNode>>> {
+        (**self).get_branch(key)
+    }
+
+    fn get_branch_at(&self, height: usize, hash: &NodeHash) -> Result<Option<Arc<BranchNode>>> {
+        (**self).get_branch_at(height, hash)
+    }
+
+    fn get_leaf(&self, key: &NodeHash) -> Result<Option<Arc<LeafNode<K, V>>>> {
+        (**self).get_leaf(key)
+    }
+
+    fn get_leaf_by_key(&self, key: &[u8; K]) -> Result<Option<Arc<LeafNode<K, V>>>> {
+        (**self).get_leaf_by_key(key)
+    }
+
+    fn may_contain_key(&self, key: &[u8; K]) -> Result<bool> {
+        (**self).may_contain_key(key)
+    }
+
+    fn get_children(
+        &self,
+        height: usize,
+        hash: &NodeHash,
+    ) -> Result<(Arc<dyn Node>, Arc<dyn Node>)> {
+        (**self).get_children(height, hash)
+    }
+
+    fn branch_hashes(&self) -> Result<Vec<NodeHash>> {
+        (**self).branch_hashes()
+    }
+
+    fn leaf_hashes(&self) -> Result<Vec<NodeHash>> {
+        (**self).leaf_hashes()
+    }
+
+    fn get_leaf_metadata(&self, key: &[u8; K]) -> Result<Option<LeafMetadata>> {
+        (**self).get_leaf_metadata(key)
+    }
+
+    fn preload(&self, roots: &[NodeHash], depth: usize) -> Result<()> {
+        (**self).preload(roots, depth)
+    }
+
+    fn stats(&self) -> Result<StoreStats> {
+        (**self).stats()
+    }
+
+    fn health_check(&self) -> Result<()> {
+        (**self).health_check()
+    }
+}
+
+impl<S: TreeStoreWriter<K, V> + ?Sized, const K: usize, V> TreeStoreWriter<K, V> for Box<S> {
+    fn insert_branch(&mut self, branch: Arc<BranchNode>) -> Result<()> {
+        (**self).insert_branch(branch)
+    }
+
+    fn insert_branch_at(&mut self, height: usize, branch: Arc<BranchNode>) -> Result<()> {
+        (**self).insert_branch_at(height, branch)
+    }
+
+    fn insert_leaf(&mut self, leaf: Arc<LeafNode<K, V>>) -> Result<()> {
+        (**self).insert_leaf(leaf)
+    }
+
+    fn insert_nodes(
+        &mut self,
+        branches: Vec<Arc<BranchNode>>,
+        leaves: Vec<Arc<LeafNode<K, V>>>,
+    ) -> Result<()> {
+        (**self).insert_nodes(branches, leaves)
+    }
+
+    fn insert_nodes_at(
+        &mut self,
+        branches: Vec<(usize, Arc<BranchNode>)>,
+        leaves: Vec<Arc<LeafNode<K, V>>>,
+    ) -> Result<()> {
+        (**self).insert_nodes_at(branches, leaves)
+    }
+
+    fn delete_branch(&mut self, key: &NodeHash) -> Result<()> {
+        (**self).delete_branch(key)
+    }
+
+    fn delete_branch_at(&mut self, height: usize, key: &NodeHash) -> Result<()> {
+        (**self).delete_branch_at(height, key)
+    }
+
+    fn delete_leaf(&mut self, key: &NodeHash) -> Result<()> {
+        (**self).delete_leaf(key)
+    }
+
+    fn set_leaf_metadata(&mut self, key: &[u8; K], metadata: Option<LeafMetadata>) -> Result<()> {
+        (**self).set_leaf_metadata(key, metadata)
+    }
+
+    fn update_root(&mut self, root: Arc<dyn Node>) -> Result<()> {
+        (**self).update_root(root)
+    }
+
+    fn compare_and_update_root(&mut self, expected: NodeHash, root: Arc<dyn Node>) -> Result<bool> {
+        (**self).compare_and_update_root(expected, root)
+    }
+}
+
+/// A store selected at runtime, such as from a configuration file.
+///
+/// `TreeStore` is object safe, so any store can be boxed and trees over different backends share the
+/// `FullTree<BoxedStore>` type. See `FullTree::boxed`.
+pub type BoxedStore<const K: usize = HASH_SIZE, V = Vec<u8>> =
+    Box<dyn TreeStore<K, V> + Send + Sync>;
+
+/// Resolves a root node by its hash.
+///
+/// The empty tree root is never written to a store, so it is recognized by its hash.
+pub(crate) fn resolve_root<S: TreeStoreReader<K, V> + ?Sized, const K: usize, V>(
+    store: &S,
+    hash: &NodeHash,
+) -> Result<Arc<dyn Node>> {
+    let root = store.root_node()?;
+    if root.node_hash() == *hash {
+        return Ok(root);
+    }
+    if *hash == EmptyTreeOf::<K>::hash_at(0) {
+        return Ok(EmptyTreeOf::<K>::node_at(0));
+    }
+    match store.get_branch_at(0, hash)? {
+        Some(branch) => Ok(branch),
+        None => Err(MssmtError::NodeNotFound(*hash)),
+    }
+}
+
+/// Returns the children of the branch `node` at `height`.
+///
+/// The children of a hash-referenced branch are fetched with `TreeStoreReader::get_children`.
+pub(crate) fn node_children<S: TreeStoreReader<K, V> + ?Sized, const K: usize, V>(
+    store: &S,
+    node: &Arc<dyn Node>,
+    height: usize,
+) -> Result<(Arc<dyn Node>, Arc<dyn Node>)> {
+    check_node_shape::<K>(node, height)?;
+    match node.as_branch() {
+        Some(branch) => Ok((branch.left.clone(), branch.right.clone())),
+        None => store.get_children(height, &node.node_hash()),
+    }
+}
+
+/// Checks that `node` can stand at `height` of a tree with `K`-byte keys.
+///
+/// Traversals run this check on every node they step through, so that a store returning an
+/// inconsistent node graph fails with `MssmtError::CorruptedTree` instead of yielding wrong results.
+///
+/// # Returns
+///
+/// - `MssmtError::InvalidHeight` if `height` is beyond the leaf level.
+/// - `MssmtError::CorruptedTree` if a leaf stands above the leaf level or a branch at the leaf level.
+pub(crate) fn check_node_shape<const K: usize>(node: &Arc<dyn Node>, height: usize) -> Result<()> {
+    let levels = tree_levels(K);
+    let reason = match node.kind() {
+        _ if height > levels => return Err(MssmtError::InvalidHeight(height)),
+        NodeKind::Leaf if height < levels => "leaf above the leaf level",
+        NodeKind::Branch if height == levels => "branch at the leaf level",
+        _ => return Ok(()),
+    };
+    Err(MssmtError::CorruptedTree {
+        height,
+        hash: node.node_hash(),
+        reason,
+    })
+}
+
+/// Resolves a hash-referenced node through the store.
+///
+/// Branches may reference their children by hash only (see `BranchNode::from_child_refs`). Such a
+/// reference at `height` is replaced by the stored leaf or branch, and any other node is returned as is.
+///
+/// # Returns
+///
+/// - `MssmtError::NodeNotFound` if the referenced node is not in the store.
+/// - `MssmtError::CorruptedTree` if the node cannot stand at `height`, see `check_node_shape`, or the
+///   store returned a node of another hash than the reference.
+pub(crate) fn resolve_node<S: TreeStoreReader<K, V> + ?Sized, const K: usize, V: LeafValue>(
+    store: &S,
+    node: &Arc<dyn Node>,
+    height: usize,
+) -> Result<Arc<dyn Node>> {
+    let resolved = load_node(store, node, height)?;
+    check_node_shape::<K>(&resolved, height)?;
+    if resolved.node_hash() != node.node_hash() {
+        return Err(MssmtError::CorruptedTree {
+            height,
+            hash: node.node_hash(),
+            reason: "stored node does not match its hash",
+        });
+    }
+    Ok(resolved)
+}
+
+/// Resolves a hash-referenced node through the store like `resolve_node`, without checking the result.
+///
+/// Integrity audits load nodes this way, to report what is wrong with them instead of failing.
+pub(crate) fn load_node<S: TreeStoreReader<K, V> + ?Sized, const K: usize, V: LeafValue>(
+    store: &S,
+    node: &Arc<dyn Node>,
+    height: usize,
+) -> Result<Arc<dyn Node>> {
+    if !node.is_computed() {
+        return Ok(node.clone());
+    }
+
+    let hash = node.node_hash();
+    if EmptyTreeOf::<K>::is_empty_at(height, &hash) {
+        return Ok(EmptyTreeOf::<K>::node_at(height));
+    }
+    trace_event!(height, hash = %hash, "loading node from store");
+    let resolved: Option<Arc<dyn Node>> = if height == tree_levels(K) {
+        store.get_leaf(&hash)?.map(|leaf| leaf as Arc<dyn Node>)
+    } else {
+        store
+            .get_branch_at(height, &hash)?
+            .map(|branch| branch as Arc<dyn Node>)
+    };
+    resolved.ok_or(MssmtError::NodeNotFound(hash))
+}
+
+/// Returns `true` if `leaf` is the leaf of its key in the tree with root hash `root`.
+///
+/// Stores with a key index call it when a root is committed, so that the index only points at
+/// committed leaves.
+pub(crate) fn is_committed_leaf<S: TreeStoreReader<K, V> + ?Sized, const K: usize, V: LeafValue>(
+    store: &S,
+    root: &NodeHash,
+    leaf: &LeafNode<K, V>,
+) -> Result<bool> {
+    let path = TreePath::from(leaf.key);
+    let mut hash = *root;
+    for height in 0..tree_levels(K) {
+        let (left, right) = match store.get_children(height, &hash) {
+            Ok(children) => children,
+            Err(MssmtError::NodeNotFound(_)) => return Ok(false),
+            Err(err) => return Err(err),
+        };
+        let child = if path.is_left(Height::at(height)) {
+            left
+        } else {
+            right
+        };
+        hash = child.node_hash();
+        if EmptyTreeOf::<K>::is_empty_at(height + 1, &hash) {
+            return Ok(false);
+        }
+    }
+    Ok(hash == leaf.node_hash())
 }
 
 /// An in-memory implementation of `TreeStore` using hash maps.
 ///
 /// `DefaultStore` is suitable for testing, examples, and small datasets.
-/// It stores nodes in memory using `HashMap` collections.
+/// It stores nodes in memory using `HashMap` collections. Stores for keys of other sizes than 32 bytes,
+/// or for other value types than `Vec<u8>`, are created with `DefaultStore::<K, V>::default()`.
 ///
 /// # Fields
 ///
 /// - `branches`: A `HashMap` storing branch nodes indexed by their hash.
 /// - `leaves`: A `HashMap` storing leaf nodes indexed by their hash.
+/// - `keys`: A `HashMap` indexing the current leaf node of each key. Leaves are indexed when the root
+///   committing them is written, so that a failed `compare_and_update_root` leaves the index untouched.
+/// - `metadata`: A `HashMap` holding the metadata of each key, see `FullTree::enable_leaf_metadata`.
+/// - `ordered_keys`: The keys of `keys` in lexicographic order, if the ordered index is enabled.
 /// - `root`: An optional root node of the tree.
 ///
+/// The hash maps iterate in an order that differs between runs, so the store lists its nodes through
+/// `branch_hashes` and `leaf_hashes` in ascending hash order, and its current leaves through
+/// `leaves_in_key_order` in key order, for exports that must be reproducible byte for byte.
+///
 /// # Examples
 ///
 /// ```rust
 /// use mssmt::store::DefaultStore;
 ///
 /// let store = DefaultStore::new();
+/// let store_20: DefaultStore<20> = DefaultStore::default();
 /// ```
-#[derive(Default)]
-pub struct DefaultStore {
+pub struct DefaultStore<const K: usize = HASH_SIZE, V = Vec<u8>> {
     pub branches: HashMap<NodeHash, Arc<BranchNode>>,
-    pub leaves: HashMap<NodeHash, Arc<LeafNode>>,
+    pub leaves: HashMap<NodeHash, Arc<LeafNode<K, V>>>,
+    pub keys: HashMap<[u8; K], Arc<LeafNode<K, V>>>,
+    pub metadata: HashMap<[u8; K], LeafMetadata>,
+    pub ordered_keys: Option<BTreeSet<[u8; K]>>,
     pub root: Option<Arc<dyn Node>>,
+    // The leaves written since the last root update, by hash, indexed once a root commits them
+    pending_leaves: HashMap<NodeHash, Arc<LeafNode<K, V>>>,
+}
+
+impl<const K: usize, V> Default for DefaultStore<K, V> {
+    fn default() -> Self {
+        Self {
+            branches: HashMap::new(),
+            leaves: HashMap::new(),
+            keys: HashMap::new(),
+            metadata: HashMap::new(),
+            ordered_keys: None,
+            root: None,
+            pending_leaves: HashMap::new(),
+        }
+    }
 }
 
 impl DefaultStore {
@@ -81,18 +936,63 @@ impl DefaultStore {
         Self {
             branches: HashMap::new(),
             leaves: HashMap::new(),
+            keys: HashMap::new(),
+            metadata: HashMap::new(),
+            ordered_keys: None,
             root: None,
+            pending_leaves: HashMap::new(),
         }
     }
 }
 
-impl TreeStore for DefaultStore {
+impl<const K: usize, V> DefaultStore<K, V> {
+    /// Keeps an ordered index of the keys next to the key index, so that `leaves_in_key_order` walks
+    /// it instead of sorting the keys on every call.
+    ///
+    /// The index costs a key per leaf and a logarithmic update per leaf write, and is built from the
+    /// keys already in the store.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use mssmt::{DefaultStore, FullTree};
+    ///
+    /// let mut tree = FullTree::new(DefaultStore::new().with_ordered_index());
+    /// tree.insert([2u8; 32], b"two".to_vec(), 2).unwrap();
+    /// tree.insert([1u8; 32], b"one".to_vec(), 1).unwrap();
+    ///
+    /// let keys: Vec<_> = tree.store().leaves_in_key_order().map(|leaf| leaf.key).collect();
+    /// assert_eq!(keys, [[1u8; 32], [2u8; 32]]);
+    /// ```
+    pub fn with_ordered_index(mut self) -> Self {
+        self.ordered_keys = Some(self.keys.keys().copied().collect());
+        self
+    }
+
+    /// Returns the current leaf of every indexed key, in lexicographic key order.
+    ///
+    /// Leaves that read as absent, such as tombstones, are included. Without the ordered index, the
+    /// keys are sorted on every call.
+    pub fn leaves_in_key_order(&self) -> impl Iterator<Item = &Arc<LeafNode<K, V>>> + '_ {
+        let keys: Vec<&[u8; K]> = match &self.ordered_keys {
+            Some(ordered_keys) => ordered_keys.iter().collect(),
+            None => {
+                let mut keys: Vec<_> = self.keys.keys().collect();
+                keys.sort_unstable();
+                keys
+            }
+        };
+        keys.into_iter().filter_map(|key| self.keys.get(key))
+    }
+}
+
+impl<const K: usize, V> TreeStoreReader<K, V> for DefaultStore<K, V> {
     fn root_node(&self) -> Result<Arc<dyn Node>> {
         if let Some(root) = &self.root {
             Ok(root.clone())
         } else {
             // Return empty tree root
-            Ok(crate::node::EMPTY_TREE[0].clone())
+            Ok(EmptyTreeOf::<K>::node_at(0))
         }
     }
 
@@ -100,18 +1000,52 @@ impl TreeStore for DefaultStore {
         Ok(self.branches.get(key).cloned())
     }
 
-    fn get_leaf(&self, key: &NodeHash) -> Result<Option<Arc<LeafNode>>> {
+    fn get_leaf(&self, key: &NodeHash) -> Result<Option<Arc<LeafNode<K, V>>>> {
         Ok(self.leaves.get(key).cloned())
     }
 
+    fn get_leaf_by_key(&self, key: &[u8; K]) -> Result<Option<Arc<LeafNode<K, V>>>> {
+        Ok(self.keys.get(key).cloned())
+    }
+
+    fn branch_hashes(&self) -> Result<Vec<NodeHash>> {
+        Ok(sorted_hashes(self.branches.keys()))
+    }
+
+    fn leaf_hashes(&self) -> Result<Vec<NodeHash>> {
+        Ok(sorted_hashes(self.leaves.keys()))
+    }
+
+    fn stats(&self) -> Result<StoreStats> {
+        Ok(StoreStats {
+            branches: self.branches.len(),
+            leaves: self.leaves.len(),
+            bytes_used: None,
+        })
+    }
+
+    fn get_leaf_metadata(&self, key: &[u8; K]) -> Result<Option<LeafMetadata>> {
+        Ok(self.metadata.get(key).cloned())
+    }
+}
+
+/// Returns `hashes` in ascending byte order.
+fn sorted_hashes<'a>(hashes: impl Iterator<Item = &'a NodeHash>) -> Vec<NodeHash> {
+    let mut hashes: Vec<NodeHash> = hashes.copied().collect();
+    hashes.sort_unstable_by(|a, b| a.as_bytes().cmp(b.as_bytes()));
+    hashes
+}
+
+impl<const K: usize, V: LeafValue> TreeStoreWriter<K, V> for DefaultStore<K, V> {
     fn insert_branch(&mut self, branch: Arc<BranchNode>) -> Result<()> {
         let key = branch.node_hash();
         self.branches.insert(key, branch);
         Ok(())
     }
 
-    fn insert_leaf(&mut self, leaf: Arc<LeafNode>) -> Result<()> {
+    fn insert_leaf(&mut self, leaf: Arc<LeafNode<K, V>>) -> Result<()> {
         let key = leaf.node_hash();
+        self.pending_leaves.insert(key, leaf.clone());
         self.leaves.insert(key, leaf);
         Ok(())
     }
@@ -122,12 +1056,385 @@ impl TreeStore for DefaultStore {
     }
 
     fn delete_leaf(&mut self, key: &NodeHash) -> Result<()> {
-        self.leaves.remove(key);
+        self.pending_leaves.remove(key);
+        if let Some(leaf) = self.leaves.remove(key) {
+            // Only drop the index entry if it still points at the deleted leaf
+            if let Some(indexed) = self.keys.get(&leaf.key) {
+                if indexed.node_hash() == *key {
+                    self.keys.remove(&leaf.key);
+                    self.metadata.remove(&leaf.key);
+                    if let Some(ordered_keys) = &mut self.ordered_keys {
+                        ordered_keys.remove(&leaf.key);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn set_leaf_metadata(&mut self, key: &[u8; K], metadata: Option<LeafMetadata>) -> Result<()> {
+        match metadata {
+            Some(metadata) => self.metadata.insert(*key, metadata),
+            None => self.metadata.remove(key),
+        };
         Ok(())
     }
 
     fn update_root(&mut self, root: Arc<dyn Node>) -> Result<()> {
+        let hash = root.node_hash();
         self.root = Some(root);
+        self.index_pending_leaves(&hash)
+    }
+
+    fn compare_and_update_root(&mut self, expected: NodeHash, root: Arc<dyn Node>) -> Result<bool> {
+        if self.root_node()?.node_hash() != expected {
+            self.pending_leaves.clear();
+            return Ok(false);
+        }
+        self.update_root(root)?;
+        Ok(true)
+    }
+}
+
+impl<const K: usize, V: LeafValue> DefaultStore<K, V> {
+    /// Indexes the leaves written since the last root update that the root `root` commits, and drops
+    /// the others, such as the leaves of an update that failed before its root was written.
+    fn index_pending_leaves(&mut self, root: &NodeHash) -> Result<()> {
+        for (_, leaf) in std::mem::take(&mut self.pending_leaves) {
+            if !is_committed_leaf(self, root, &leaf)? {
+                continue;
+            }
+            if let Some(ordered_keys) = &mut self.ordered_keys {
+                ordered_keys.insert(leaf.key);
+            }
+            self.keys.insert(leaf.key, leaf);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::Sum;
+    use crate::tree::FullTree;
+
+    /// A store implementing only the required methods.
+    #[derive(Default)]
+    struct MinimalStore {
+        branches: HashMap<NodeHash, Arc<BranchNode>>,
+        leaves: HashMap<NodeHash, Arc<LeafNode>>,
+        root: Option<Arc<dyn Node>>,
+    }
+
+    impl TreeStoreReader for MinimalStore {
+        fn root_node(&self) -> Result<Arc<dyn Node>> {
+            Ok(self
+                .root
+                .clone()
+                .unwrap_or_else(|| EmptyTreeOf::<32>::node_at(0)))
+        }
+
+        fn get_branch(&self, key: &NodeHash) -> Result<Option<Arc<BranchNode>>> {
+            Ok(self.branches.get(key).cloned())
+        }
+
+        fn get_leaf(&self, key: &NodeHash) -> Result<Option<Arc<LeafNode>>> {
+            Ok(self.leaves.get(key).cloned())
+        }
+    }
+
+    impl TreeStoreWriter for MinimalStore {
+        fn insert_branch(&mut self, branch: Arc<BranchNode>) -> Result<()> {
+            self.branches.insert(branch.node_hash(), branch);
+            Ok(())
+        }
+
+        fn insert_leaf(&mut self, leaf: Arc<LeafNode>) -> Result<()> {
+            self.leaves.insert(leaf.node_hash(), leaf);
+            Ok(())
+        }
+
+        fn update_root(&mut self, root: Arc<dyn Node>) -> Result<()> {
+            self.root = Some(root);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_minimal_store_holds_a_tree() -> Result<()> {
+        let mut tree = FullTree::new(MinimalStore::default());
+        let mut reference = FullTree::new(DefaultStore::new());
+        for i in 0..16u8 {
+            tree.insert([i; 32], vec![i], i as Sum)?;
+            reference.insert([i; 32], vec![i], i as Sum)?;
+        }
+        tree.delete([3u8; 32])?;
+        reference.delete([3u8; 32])?;
+        assert!(tree.equals(&reference)?);
+        assert_eq!(tree.get([3u8; 32])?, None);
+        assert_eq!(tree.get([4u8; 32])?, Some((vec![4], 4)));
+
+        // Superseded nodes are kept by the default deletes
+        assert!(tree.store().leaves.len() > reference.store().leaves.len());
+
+        let root = tree.root()?.node_hash();
+        let leaf = LeafNode::new([4u8; 32], vec![4], 4);
+        assert!(tree.store().contains_node(&root)?);
+        assert!(tree.store().contains_node(&leaf.node_hash())?);
+        assert!(tree
+            .store()
+            .get_node(&EmptyTreeOf::<32>::hash_at(0))?
+            .is_none());
+
+        let mut copy = MinimalStore::default();
+        let branches = tree.store().branches.values().cloned().collect();
+        let leaves = tree.store().leaves.values().cloned().collect();
+        copy.insert_nodes(branches, leaves)?;
+        copy.update_root(tree.root()?)?;
+        assert!(FullTree::new(copy).equals(&reference)?);
+
+        Ok(())
+    }
+
+    /// A store counting the batches it is given, and the nodes written outside of them.
+    #[derive(Default)]
+    struct BatchingStore {
+        inner: MinimalStore,
+        batches: usize,
+        single_writes: usize,
+    }
+
+    impl TreeStoreReader for BatchingStore {
+        fn root_node(&self) -> Result<Arc<dyn Node>> {
+            self.inner.root_node()
+        }
+
+        fn get_branch(&self, key: &NodeHash) -> Result<Option<Arc<BranchNode>>> {
+            self.inner.get_branch(key)
+        }
+
+        fn get_leaf(&self, key: &NodeHash) -> Result<Option<Arc<LeafNode>>> {
+            self.inner.get_leaf(key)
+        }
+    }
+
+    impl TreeStoreWriter for BatchingStore {
+        fn insert_branch(&mut self, branch: Arc<BranchNode>) -> Result<()> {
+            self.single_writes += 1;
+            self.inner.insert_branch(branch)
+        }
+
+        fn insert_leaf(&mut self, leaf: Arc<LeafNode>) -> Result<()> {
+            self.single_writes += 1;
+            self.inner.insert_leaf(leaf)
+        }
+
+        fn insert_nodes(
+            &mut self,
+            branches: Vec<Arc<BranchNode>>,
+            leaves: Vec<Arc<LeafNode>>,
+        ) -> Result<()> {
+            self.batches += 1;
+            self.inner.insert_nodes(branches, leaves)
+        }
+
+        fn update_root(&mut self, root: Arc<dyn Node>) -> Result<()> {
+            self.inner.update_root(root)
+        }
+    }
+
+    #[test]
+    fn test_tree_operations_write_one_batch() -> Result<()> {
+        let mut tree = FullTree::new(BatchingStore::default());
+        for i in 0..8u8 {
+            tree.insert([i; 32], vec![i], i as Sum)?;
+        }
+        tree.update_sum([1u8; 32], 10)?;
+        tree.delete([2u8; 32])?;
+        assert_eq!(tree.store().batches, 10);
+        assert_eq!(tree.store().single_writes, 0);
+
+        Ok(())
+    }
+
+    /// A store addressing branches by height and hash, with one map per level.
+    struct LevelStore {
+        levels: Vec<HashMap<NodeHash, Arc<BranchNode>>>,
+        inner: MinimalStore,
+    }
+
+    impl Default for LevelStore {
+        fn default() -> Self {
+            Self {
+                levels: vec![HashMap::new(); tree_levels(32)],
+                inner: MinimalStore::default(),
+            }
+        }
+    }
+
+    impl TreeStoreReader for LevelStore {
+        fn root_node(&self) -> Result<Arc<dyn Node>> {
+            self.inner.root_node()
+        }
+
+        fn get_branch(&self, key: &NodeHash) -> Result<Option<Arc<BranchNode>>> {
+            Ok(self.levels.iter().find_map(|level| level.get(key).cloned()))
+        }
+
+        fn get_branch_at(&self, height: usize, hash: &NodeHash) -> Result<Option<Arc<BranchNode>>> {
+            Ok(self.levels[height].get(hash).cloned())
+        }
+
+        fn get_leaf(&self, key: &NodeHash) -> Result<Option<Arc<LeafNode>>> {
+            self.inner.get_leaf(key)
+        }
+    }
+
+    impl TreeStoreWriter for LevelStore {
+        fn insert_branch(&mut self, _branch: Arc<BranchNode>) -> Result<()> {
+            Err(MssmtError::Unsupported("insert_branch"))
+        }
+
+        fn insert_branch_at(&mut self, height: usize, branch: Arc<BranchNode>) -> Result<()> {
+            self.levels[height].insert(branch.node_hash(), branch);
+            Ok(())
+        }
+
+        fn insert_leaf(&mut self, leaf: Arc<LeafNode>) -> Result<()> {
+            self.inner.insert_leaf(leaf)
+        }
+
+        fn insert_nodes_at(
+            &mut self,
+            branches: Vec<(usize, Arc<BranchNode>)>,
+            leaves: Vec<Arc<LeafNode>>,
+        ) -> Result<()> {
+            for leaf in leaves {
+                self.insert_leaf(leaf)?;
+            }
+            for (height, branch) in branches {
+                self.insert_branch_at(height, branch)?;
+            }
+            Ok(())
+        }
+
+        fn delete_branch(&mut self, _key: &NodeHash) -> Result<()> {
+            Err(MssmtError::Unsupported("delete_branch"))
+        }
+
+        fn delete_branch_at(&mut self, height: usize, key: &NodeHash) -> Result<()> {
+            self.levels[height].remove(key);
+            Ok(())
+        }
+
+        fn update_root(&mut self, root: Arc<dyn Node>) -> Result<()> {
+            self.inner.update_root(root)
+        }
+    }
+
+    #[test]
+    fn test_tree_operations_pass_node_heights() -> Result<()> {
+        let leaves = (0..32u8).map(|i| LeafNode::new([i; 32], vec![i], i as Sum));
+        let mut tree = FullTree::from_leaves(LevelStore::default(), leaves.clone())?;
+        let mut reference = FullTree::from_leaves(DefaultStore::new(), leaves)?;
+        let config = crate::config::TreeConfig::default()
+            .with_node_retention(crate::config::NodeRetention::Prune);
+        tree.set_config(config.clone());
+        reference.set_config(config);
+
+        tree.insert([40u8; 32], vec![40], 40)?;
+        tree.update_sum([1u8; 32], 10)?;
+        tree.delete([2u8; 32])?;
+        tree.par_insert_batch((50..60u8).map(|i| ([i; 32], vec![i], 1)))?;
+        reference.insert([40u8; 32], vec![40], 40)?;
+        reference.update_sum([1u8; 32], 10)?;
+        reference.delete([2u8; 32])?;
+        reference.par_insert_batch((50..60u8).map(|i| ([i; 32], vec![i], 1)))?;
+        assert!(tree.equals(&reference)?);
+
+        // Every branch sits in the map of its level, and pruning found each superseded one there
+        let root = tree.root()?.node_hash();
+        assert!(tree.store().levels[0].contains_key(&root));
+        let stored: usize = tree.store().levels.iter().map(HashMap::len).sum();
+        assert_eq!(stored, reference.store().branches.len());
+        let leaf = LeafNode::new([4u8; 32], vec![4], 4);
+        assert!(tree.merkle_proof([4u8; 32])?.verify([4u8; 32], &leaf, root));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_exports_do_not_depend_on_insertion_order() -> Result<()> {
+        const DELETED: [u8; 32] = [74u8; 32];
+        let keys: Vec<[u8; 32]> = (0..32u8).map(|i| [i.wrapping_mul(37); 32]).collect();
+        let build = |store: DefaultStore, keys: &mut dyn Iterator<Item = &[u8; 32]>| {
+            let mut tree = FullTree::new(store);
+            for key in keys {
+                tree.insert(*key, key.to_vec(), key[0] as Sum)?;
+            }
+            tree.delete(DELETED)?;
+            tree.compact()?;
+            Ok::<_, MssmtError>(tree)
+        };
+
+        let forward = build(DefaultStore::new(), &mut keys.iter())?;
+        let backward = build(
+            DefaultStore::new().with_ordered_index(),
+            &mut keys.iter().rev(),
+        )?;
+
+        let in_order = |tree: &FullTree<DefaultStore>| -> Vec<[u8; 32]> {
+            tree.store()
+                .leaves_in_key_order()
+                .map(|leaf| leaf.key)
+                .collect()
+        };
+        let mut sorted: Vec<_> = keys.iter().copied().filter(|key| *key != DELETED).collect();
+        sorted.sort();
+        assert_eq!(in_order(&forward), sorted);
+        assert_eq!(in_order(&backward), sorted);
+
+        let hashes = forward.store().branch_hashes()?;
+        assert!(hashes.windows(2).all(|w| w[0].as_bytes() < w[1].as_bytes()));
+        assert_eq!(hashes, backward.store().branch_hashes()?);
+        assert_eq!(
+            forward.store().leaf_hashes()?,
+            backward.store().leaf_hashes()?
+        );
+
+        let export = |tree: &FullTree<DefaultStore>| -> Result<Vec<u8>> {
+            let mut bytes = Vec::new();
+            StoreSnapshot::capture(tree.store())?.write_to(&mut bytes)?;
+            Ok(bytes)
+        };
+        assert_eq!(export(&forward)?, export(&backward)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_key_index_only_follows_committed_roots() -> Result<()> {
+        let mut tree = FullTree::new(DefaultStore::new());
+        tree.insert([1u8; 32], b"one".to_vec(), 1)?;
+
+        // A writer that lost the race wrote its nodes, but not its root
+        let mut stale = FullTree::new(DefaultStore::new());
+        stale.insert([1u8; 32], b"stale".to_vec(), 5)?;
+        let store = tree.store_mut();
+        store.insert_nodes(
+            stale.store().branches.values().cloned().collect(),
+            stale.store().leaves.values().cloned().collect(),
+        )?;
+        assert!(!store
+            .compare_and_update_root(FullTree::<DefaultStore>::empty_root_hash(), stale.root()?)?);
+        assert_eq!(tree.get([1u8; 32])?, Some((b"one".to_vec(), 1)));
+
+        // Nor do the next commits index the stale leaf
+        tree.insert([2u8; 32], b"two".to_vec(), 2)?;
+        assert_eq!(tree.get([1u8; 32])?, Some((b"one".to_vec(), 1)));
+        assert!(tree.store().keys.values().all(|leaf| leaf.sum != 5));
+
         Ok(())
     }
 }
@@ -0,0 +1,184 @@
+//! Async access to sync stores for tokio services.
+
+use crate::error::{MssmtError, Result};
+use crate::node::{BranchNode, LeafNode, Node, NodeHash};
+use crate::store::TreeStore;
+use parking_lot::RwLock;
+use std::future::Future;
+use std::sync::Arc;
+
+/// The async counterpart of `TreeStore`, for storage backends used from async services.
+///
+/// Methods take `&self` and owned arguments so that stores can be shared between tasks, and their
+/// futures are `Send` so they can be awaited on a multi-threaded runtime.
+pub trait AsyncTreeStore: Send + Sync {
+    /// Returns the root node of the tree.
+    fn root_node(&self) -> impl Future<Output = Result<Arc<dyn Node>>> + Send;
+
+    /// Gets a branch node by its hash.
+    fn get_branch(
+        &self,
+        key: NodeHash,
+    ) -> impl Future<Output = Result<Option<Arc<BranchNode>>>> + Send;
+
+    /// Gets a leaf node by its hash.
+    fn get_leaf(&self, key: NodeHash)
+        -> impl Future<Output = Result<Option<Arc<LeafNode>>>> + Send;
+
+    /// Inserts or updates a branch node.
+    fn insert_branch(&self, branch: Arc<BranchNode>) -> impl Future<Output = Result<()>> + Send;
+
+    /// Inserts or updates a leaf node.
+    fn insert_leaf(&self, leaf: Arc<LeafNode>) -> impl Future<Output = Result<()>> + Send;
+
+    /// Deletes a branch node.
+    fn delete_branch(&self, key: NodeHash) -> impl Future<Output = Result<()>> + Send;
+
+    /// Deletes a leaf node.
+    fn delete_leaf(&self, key: NodeHash) -> impl Future<Output = Result<()>> + Send;
+
+    /// Updates the root node.
+    fn update_root(&self, root: Arc<dyn Node>) -> impl Future<Output = Result<()>> + Send;
+}
+
+/// An `AsyncTreeStore` running the calls of a sync `TreeStore` on tokio's blocking thread pool.
+///
+/// Existing backends, whose calls may block on disk or network I/O, can be used from async services
+/// without stalling the executor. Reads run concurrently and writes are serialized by a lock, and the
+/// store can be cloned cheaply to share it between tasks.
+///
+/// # Examples
+///
+/// ```rust
+/// use mssmt::store::{AsyncTreeStore, SpawnBlockingStore};
+/// use mssmt::{DefaultStore, FullTree, Node};
+///
+/// let mut tree = FullTree::new(DefaultStore::new());
+/// tree.insert([1u8; 32], b"one".to_vec(), 1).unwrap();
+/// let store = SpawnBlockingStore::new(tree.into_store());
+///
+/// let runtime = tokio::runtime::Runtime::new().unwrap();
+/// let root = runtime.block_on(store.root_node()).unwrap();
+/// assert_eq!(root.node_sum(), 1);
+/// ```
+pub struct SpawnBlockingStore<S> {
+    inner: Arc<RwLock<S>>,
+}
+
+impl<S> SpawnBlockingStore<S> {
+    /// Wraps `store` for use from async code.
+    pub fn new(store: S) -> Self {
+        Self {
+            inner: Arc::new(RwLock::new(store)),
+        }
+    }
+
+    /// Returns the wrapped store, shared with the clones of this store.
+    pub fn inner(&self) -> &Arc<RwLock<S>> {
+        &self.inner
+    }
+}
+
+impl<S> Clone for SpawnBlockingStore<S> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<S: TreeStore + Send + Sync + 'static> SpawnBlockingStore<S> {
+    async fn read<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&S) -> Result<T> + Send + 'static,
+    ) -> Result<T> {
+        let inner = self.inner.clone();
+        tokio::task::spawn_blocking(move || f(&inner.read()))
+            .await
+            .map_err(|err| MssmtError::Store(err.to_string()))?
+    }
+
+    async fn write<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&mut S) -> Result<T> + Send + 'static,
+    ) -> Result<T> {
+        let inner = self.inner.clone();
+        tokio::task::spawn_blocking(move || f(&mut inner.write()))
+            .await
+            .map_err(|err| MssmtError::Store(err.to_string()))?
+    }
+}
+
+impl<S: TreeStore + Send + Sync + 'static> AsyncTreeStore for SpawnBlockingStore<S> {
+    async fn root_node(&self) -> Result<Arc<dyn Node>> {
+        self.read(|store| store.root_node()).await
+    }
+
+    async fn get_branch(&self, key: NodeHash) -> Result<Option<Arc<BranchNode>>> {
+        self.read(move |store| store.get_branch(&key)).await
+    }
+
+    async fn get_leaf(&self, key: NodeHash) -> Result<Option<Arc<LeafNode>>> {
+        self.read(move |store| store.get_leaf(&key)).await
+    }
+
+    async fn insert_branch(&self, branch: Arc<BranchNode>) -> Result<()> {
+        self.write(move |store| store.insert_branch(branch)).await
+    }
+
+    async fn insert_leaf(&self, leaf: Arc<LeafNode>) -> Result<()> {
+        self.write(move |store| store.insert_leaf(leaf)).await
+    }
+
+    async fn delete_branch(&self, key: NodeHash) -> Result<()> {
+        self.write(move |store| store.delete_branch(&key)).await
+    }
+
+    async fn delete_leaf(&self, key: NodeHash) -> Result<()> {
+        self.write(move |store| store.delete_leaf(&key)).await
+    }
+
+    async fn update_root(&self, root: Arc<dyn Node>) -> Result<()> {
+        self.write(move |store| store.update_root(root)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::DefaultStore;
+    use crate::tree::FullTree;
+
+    #[test]
+    fn test_spawn_blocking_store() -> Result<()> {
+        let mut tree = FullTree::new(DefaultStore::new());
+        tree.insert([1u8; 32], b"one".to_vec(), 1)?;
+        tree.insert([2u8; 32], b"two".to_vec(), 2)?;
+        let root = tree.root()?;
+        let store = SpawnBlockingStore::new(tree.into_store());
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            // Concurrent tasks share the store
+            let tasks: Vec<_> = (0..4)
+                .map(|_| {
+                    let store = store.clone();
+                    let hash = root.node_hash();
+                    tokio::spawn(async move { store.get_branch(hash).await })
+                })
+                .collect();
+            for task in tasks {
+                let branch = task.await.unwrap()?.unwrap();
+                assert_eq!(branch.node_sum(), 3);
+            }
+
+            let leaf = Arc::new(LeafNode::new([3u8; 32], b"three".to_vec(), 3));
+            store.insert_leaf(leaf.clone()).await?;
+            assert!(store.get_leaf(leaf.node_hash()).await?.is_some());
+            store.delete_leaf(leaf.node_hash()).await?;
+            assert!(store.get_leaf(leaf.node_hash()).await?.is_none());
+            assert_eq!(store.root_node().await?.node_hash(), root.node_hash());
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,302 @@
+//! An LRU caching decorator for slow storage backends.
+
+use crate::error::Result;
+use crate::metadata::LeafMetadata;
+use crate::metrics::Metrics;
+use crate::node::{BranchNode, EmptyTree, LeafNode, Node, NodeHash, MAX_TREE_LEVELS};
+use crate::store::{StoreStats, TreeStoreReader, TreeStoreWriter};
+use lru::LruCache;
+use parking_lot::Mutex;
+use std::collections::HashSet;
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// Hit and miss counters of a `CachedStore`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Number of node lookups served from the cache.
+    pub hits: u64,
+    /// Number of node lookups forwarded to the inner store.
+    pub misses: u64,
+}
+
+/// A `TreeStore` decorator that keeps recently used nodes in an LRU cache.
+///
+/// `CachedStore` sits in front of a disk or network backend and serves hot branch and leaf lookups
+/// from memory. Writes go through to the inner store and keep the cache up to date.
+///
+/// # Type Parameters
+///
+/// - `S`: The wrapped storage backend.
+///
+/// # Examples
+///
+/// ```rust
+/// use mssmt::store::CachedStore;
+/// use mssmt::{DefaultStore, Node, TreeStoreReader, TreeStoreWriter};
+/// use mssmt::LeafNode;
+/// use std::sync::Arc;
+///
+/// let mut store = CachedStore::new(DefaultStore::new(), 1024);
+/// let leaf = Arc::new(LeafNode::new([1u8; 32], b"value".to_vec(), 10));
+/// store.insert_leaf(leaf.clone()).unwrap();
+///
+/// assert!(store.get_leaf(&leaf.node_hash()).unwrap().is_some());
+/// assert_eq!(store.cache_stats().hits, 1);
+/// ```
+pub struct CachedStore<S> {
+    inner: S,
+    branches: Mutex<LruCache<NodeHash, Arc<BranchNode>>>,
+    leaves: Mutex<LruCache<NodeHash, Arc<LeafNode>>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    metrics: Option<Arc<dyn Metrics>>,
+}
+
+impl<S> CachedStore<S> {
+    /// Creates a new `CachedStore` wrapping `inner`.
+    ///
+    /// `capacity` is the maximum number of branches and the maximum number of leaves kept in memory.
+    /// A capacity of zero is treated as one.
+    pub fn new(inner: S, capacity: usize) -> Self {
+        let capacity = NonZeroUsize::new(capacity).unwrap_or(NonZeroUsize::MIN);
+        Self {
+            inner,
+            branches: Mutex::new(LruCache::new(capacity)),
+            leaves: Mutex::new(LruCache::new(capacity)),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            metrics: None,
+        }
+    }
+
+    /// Reports every cache hit and miss to `metrics`, in addition to the built-in counters.
+    pub fn with_metrics(mut self, metrics: Arc<dyn Metrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Returns a reference to the wrapped store.
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// Consumes the `CachedStore`, returning the wrapped store.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    /// Returns the current cache hit and miss counters.
+    pub fn cache_stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Drops all cached nodes and resets the counters.
+    pub fn clear_cache(&self) {
+        self.branches.lock().clear();
+        self.leaves.lock().clear();
+        self.hits.store(0, Ordering::Relaxed);
+        self.misses.store(0, Ordering::Relaxed);
+    }
+
+    fn record(&self, hit: bool) {
+        let counter = if hit { &self.hits } else { &self.misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+        if let Some(metrics) = &self.metrics {
+            metrics.cache_access(hit);
+        }
+    }
+}
+
+impl<S: TreeStoreReader> TreeStoreReader for CachedStore<S> {
+    fn root_node(&self) -> Result<Arc<dyn Node>> {
+        self.inner.root_node()
+    }
+
+    fn get_branch(&self, key: &NodeHash) -> Result<Option<Arc<BranchNode>>> {
+        if let Some(branch) = self.branches.lock().get(key) {
+            self.record(true);
+            return Ok(Some(branch.clone()));
+        }
+
+        self.record(false);
+        let branch = self.inner.get_branch(key)?;
+        if let Some(branch) = &branch {
+            self.branches.lock().put(*key, branch.clone());
+        }
+        Ok(branch)
+    }
+
+    fn get_leaf(&self, key: &NodeHash) -> Result<Option<Arc<LeafNode>>> {
+        if let Some(leaf) = self.leaves.lock().get(key) {
+            self.record(true);
+            return Ok(Some(leaf.clone()));
+        }
+
+        self.record(false);
+        let leaf = self.inner.get_leaf(key)?;
+        if let Some(leaf) = &leaf {
+            self.leaves.lock().put(*key, leaf.clone());
+        }
+        Ok(leaf)
+    }
+
+    fn get_leaf_by_key(&self, key: &[u8; 32]) -> Result<Option<Arc<LeafNode>>> {
+        self.inner.get_leaf_by_key(key)
+    }
+
+    fn may_contain_key(&self, key: &[u8; 32]) -> Result<bool> {
+        self.inner.may_contain_key(key)
+    }
+
+    fn branch_hashes(&self) -> Result<Vec<NodeHash>> {
+        self.inner.branch_hashes()
+    }
+
+    fn leaf_hashes(&self) -> Result<Vec<NodeHash>> {
+        self.inner.leaf_hashes()
+    }
+
+    fn stats(&self) -> Result<StoreStats> {
+        self.inner.stats()
+    }
+
+    fn health_check(&self) -> Result<()> {
+        self.inner.health_check()
+    }
+
+    fn get_leaf_metadata(&self, key: &[u8; 32]) -> Result<Option<LeafMetadata>> {
+        self.inner.get_leaf_metadata(key)
+    }
+
+    /// Loads the top `depth` levels below `roots` into the branch cache, without counting the lookups
+    /// in `CachedStore::cache_stats`.
+    ///
+    /// The branches are cached deepest first, so that when they outnumber the capacity, the upper
+    /// levels, which every traversal goes through, are the ones kept.
+    fn preload(&self, roots: &[NodeHash], depth: usize) -> Result<()> {
+        let mut loaded = Vec::new();
+        let mut level: HashSet<NodeHash> = roots.iter().copied().collect();
+        for height in 0..depth.min(MAX_TREE_LEVELS) {
+            let mut next = HashSet::new();
+            for hash in level {
+                if EmptyTree::is_empty_at(height, &hash) {
+                    continue;
+                }
+                let cached = self.branches.lock().peek(&hash).cloned();
+                let branch = match cached {
+                    Some(branch) => branch,
+                    None => match self.inner.get_branch_at(height, &hash)? {
+                        Some(branch) => branch,
+                        None => continue,
+                    },
+                };
+                next.insert(branch.left.node_hash());
+                next.insert(branch.right.node_hash());
+                loaded.push((hash, branch));
+            }
+            level = next;
+        }
+
+        let mut branches = self.branches.lock();
+        for (hash, branch) in loaded.into_iter().rev() {
+            branches.put(hash, branch);
+        }
+        Ok(())
+    }
+}
+
+impl<S: TreeStoreWriter> TreeStoreWriter for CachedStore<S> {
+    fn insert_branch(&mut self, branch: Arc<BranchNode>) -> Result<()> {
+        self.inner.insert_branch(branch.clone())?;
+        self.branches.lock().put(branch.node_hash(), branch);
+        Ok(())
+    }
+
+    fn insert_leaf(&mut self, leaf: Arc<LeafNode>) -> Result<()> {
+        self.inner.insert_leaf(leaf.clone())?;
+        self.leaves.lock().put(leaf.node_hash(), leaf);
+        Ok(())
+    }
+
+    fn delete_branch(&mut self, key: &NodeHash) -> Result<()> {
+        self.branches.lock().pop(key);
+        self.inner.delete_branch(key)
+    }
+
+    fn delete_leaf(&mut self, key: &NodeHash) -> Result<()> {
+        self.leaves.lock().pop(key);
+        self.inner.delete_leaf(key)
+    }
+
+    fn set_leaf_metadata(&mut self, key: &[u8; 32], metadata: Option<LeafMetadata>) -> Result<()> {
+        self.inner.set_leaf_metadata(key, metadata)
+    }
+
+    fn update_root(&mut self, root: Arc<dyn Node>) -> Result<()> {
+        self.inner.update_root(root)
+    }
+
+    fn compare_and_update_root(&mut self, expected: NodeHash, root: Arc<dyn Node>) -> Result<bool> {
+        self.inner.compare_and_update_root(expected, root)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::DefaultStore;
+    use crate::tree::FullTree;
+
+    #[test]
+    fn test_cache_eviction_and_counters() -> Result<()> {
+        let mut tree = FullTree::new(CachedStore::new(DefaultStore::new(), 1));
+        tree.insert([1u8; 32], b"one".to_vec(), 1)?;
+        tree.insert([2u8; 32], b"two".to_vec(), 2)?;
+
+        let first = LeafNode::new([1u8; 32], b"one".to_vec(), 1).node_hash();
+        let second = LeafNode::new([2u8; 32], b"two".to_vec(), 2).node_hash();
+
+        let store = tree.store();
+        // Only the most recently written leaf fits in the cache
+        assert!(store.get_leaf(&second)?.is_some());
+        assert!(store.get_leaf(&first)?.is_some());
+        assert_eq!(store.cache_stats(), CacheStats { hits: 1, misses: 1 });
+
+        // The miss pulled the first leaf into the cache
+        assert!(store.get_leaf(&first)?.is_some());
+        assert_eq!(store.cache_stats(), CacheStats { hits: 2, misses: 1 });
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_preload_keeps_the_top_levels() -> Result<()> {
+        let mut tree = FullTree::new(CachedStore::new(DefaultStore::new(), 8));
+        for i in 0..64u8 {
+            tree.insert([i.wrapping_mul(4); 32], vec![i], 1)?;
+        }
+        let root = tree.root()?;
+        let top = root.as_branch().unwrap();
+        let (left, right) = (top.left.node_hash(), top.right.node_hash());
+        let deep = tree.path([0u8; 32])?[20].sibling_hash;
+        let store = tree.store();
+        store.clear_cache();
+
+        // Four levels hold more branches than the capacity, and the upper ones are kept
+        store.preload(&[root.node_hash()], 4)?;
+        assert_eq!(store.cache_stats(), CacheStats::default());
+        for hash in [root.node_hash(), left, right] {
+            assert!(store.get_branch(&hash)?.is_some());
+        }
+        assert_eq!(store.cache_stats(), CacheStats { hits: 3, misses: 0 });
+        store.get_branch(&deep)?;
+        assert_eq!(store.cache_stats().misses, 1);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,116 @@
+//! Compression of leaf values in persistent stores.
+
+use crate::error::{MssmtError, Result};
+use crate::node::{LeafNode, SUM_SIZE};
+use std::sync::Arc;
+
+/// A compression algorithm for leaf values, such as zstd or snappy.
+///
+/// The crate does not ship an implementation, so deployments can use the compression crate of their
+/// choice. A store must be reopened with the same algorithm its values were compressed with.
+pub trait ValueCompressor: Send + Sync {
+    /// Compresses `value`.
+    fn compress(&self, value: &[u8]) -> Result<Vec<u8>>;
+
+    /// Decompresses the output of `compress`.
+    fn decompress(&self, compressed: &[u8]) -> Result<Vec<u8>>;
+}
+
+/// Compression of the leaf values persisted by `LogStore` and `RedisStore`.
+///
+/// Values of at least `threshold` bytes are compressed when they are written, unless compression does
+/// not make them smaller. Hashes are computed over the uncompressed values, so compression is invisible
+/// to the tree, its roots and its proofs.
+///
+/// # Examples
+///
+/// ```rust,ignore
+/// use mssmt::store::{LogStore, ValueCompression, ValueCompressor};
+/// use mssmt::{FullTree, MssmtError};
+///
+/// struct Zstd;
+///
+/// impl ValueCompressor for Zstd {
+///     fn compress(&self, value: &[u8]) -> mssmt::error::Result<Vec<u8>> {
+///         zstd::encode_all(value, 3).map_err(MssmtError::Io)
+///     }
+///
+///     fn decompress(&self, compressed: &[u8]) -> mssmt::error::Result<Vec<u8>> {
+///         zstd::decode_all(compressed).map_err(MssmtError::Io)
+///     }
+/// }
+///
+/// let store = LogStore::open_with_compression("assets.log", ValueCompression::new(Zstd, 256))?;
+/// let mut tree = FullTree::new(store);
+/// ```
+#[derive(Clone)]
+pub struct ValueCompression {
+    compressor: Arc<dyn ValueCompressor>,
+    threshold: usize,
+}
+
+impl ValueCompression {
+    /// Compresses values of at least `threshold` bytes with `compressor`.
+    pub fn new(compressor: impl ValueCompressor + 'static, threshold: usize) -> Self {
+        Self {
+            compressor: Arc::new(compressor),
+            threshold,
+        }
+    }
+
+    /// Returns the size from which values are compressed.
+    pub fn threshold(&self) -> usize {
+        self.threshold
+    }
+
+    /// Compresses `value`, or returns `None` if it is below the threshold or does not shrink.
+    pub(crate) fn compress(&self, value: &[u8]) -> Result<Option<Vec<u8>>> {
+        if value.len() < self.threshold {
+            return Ok(None);
+        }
+        let compressed = self.compressor.compress(value)?;
+        Ok((compressed.len() < value.len()).then_some(compressed))
+    }
+
+    /// Encodes `leaf` like `LeafNode::encode` with its value compressed, or returns `None` if the value
+    /// is not compressed.
+    pub(crate) fn encode_leaf(&self, leaf: &LeafNode) -> Result<Option<Vec<u8>>> {
+        Ok(self
+            .compress(&leaf.value)?
+            .map(|compressed| LeafNode::new(leaf.key, compressed, leaf.sum).encode()))
+    }
+
+    /// Decodes a leaf encoded by `encode_leaf`.
+    pub(crate) fn decode_leaf(&self, bytes: &[u8]) -> Result<LeafNode> {
+        let compressed = LeafNode::<32>::decode(bytes)?;
+        let value = self.compressor.decompress(&compressed.value)?;
+        Ok(LeafNode::new(compressed.key, value, compressed.sum))
+    }
+}
+
+/// Decodes a compressed leaf, failing if the store was opened without compression.
+pub(crate) fn decode_compressed_leaf(
+    compression: Option<&ValueCompression>,
+    bytes: &[u8],
+) -> Result<LeafNode> {
+    required(compression, bytes.len().saturating_sub(32 + SUM_SIZE))?.decode_leaf(bytes)
+}
+
+/// Decompresses a value, failing if the store was opened without compression.
+pub(crate) fn decompress_value(
+    compression: Option<&ValueCompression>,
+    compressed: &[u8],
+) -> Result<Vec<u8>> {
+    required(compression, compressed.len())?
+        .compressor
+        .decompress(compressed)
+}
+
+fn required(compression: Option<&ValueCompression>, len: usize) -> Result<&ValueCompression> {
+    compression.ok_or_else(|| {
+        MssmtError::InvalidEncoding(format!(
+            "compressed value of {} bytes in a store opened without compression",
+            len
+        ))
+    })
+}
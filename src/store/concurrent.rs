@@ -0,0 +1,237 @@
+//! A concurrent in-memory store backed by `DashMap`.
+
+use crate::error::Result;
+use crate::node::{BranchNode, LeafNode, Node, NodeHash, EMPTY_TREE};
+use crate::store::{is_committed_leaf, StoreStats, TreeStoreReader, TreeStoreWriter};
+use dashmap::DashMap;
+use parking_lot::RwLock;
+use std::sync::Arc;
+
+/// An in-memory implementation of `TreeStore` that can be read and written through shared references.
+///
+/// `ConcurrentStore` is a variant of `DefaultStore` backed by sharded `DashMap` collections, so that
+/// several threads can generate proofs or look up nodes in parallel without serializing on a single lock.
+/// `TreeStoreWriter` is also implemented for `&ConcurrentStore`, which allows several `FullTree` handles
+/// to share one store.
+///
+/// # Examples
+///
+/// ```rust
+/// use mssmt::store::ConcurrentStore;
+/// use mssmt::FullTree;
+/// use std::thread;
+///
+/// let store = ConcurrentStore::new();
+/// FullTree::new(&store).insert([1u8; 32], b"value".to_vec(), 10).unwrap();
+///
+/// thread::scope(|scope| {
+///     for _ in 0..4 {
+///         scope.spawn(|| {
+///             let tree = FullTree::new(&store);
+///             let proof = tree.merkle_proof([1u8; 32]).unwrap();
+///             assert_eq!(proof.nodes.len(), 256);
+///         });
+///     }
+/// });
+/// ```
+#[derive(Default)]
+pub struct ConcurrentStore {
+    branches: DashMap<NodeHash, Arc<BranchNode>>,
+    leaves: DashMap<NodeHash, Arc<LeafNode>>,
+    keys: DashMap<[u8; 32], Arc<LeafNode>>,
+    // The leaves written since they were last checked against a committed root, by hash
+    pending_leaves: DashMap<NodeHash, Arc<LeafNode>>,
+    root: RwLock<Option<Arc<dyn Node>>>,
+}
+
+impl ConcurrentStore {
+    /// Creates a new `ConcurrentStore`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of branch nodes in the store.
+    pub fn branch_count(&self) -> usize {
+        self.branches.len()
+    }
+
+    /// Returns the number of leaf nodes in the store.
+    pub fn leaf_count(&self) -> usize {
+        self.leaves.len()
+    }
+
+    /// Indexes the pending leaves that the root `root` commits, and drops the others.
+    ///
+    /// Called with the root lock held, so that no other root is committed while the index is updated.
+    /// The pending leaves of a handle whose update has not been committed yet are dropped as well, and
+    /// are then found by walking from the root instead.
+    fn index_pending_leaves(&self, root: &NodeHash) -> Result<()> {
+        let pending: Vec<Arc<LeafNode>> = self
+            .pending_leaves
+            .iter()
+            .map(|entry| entry.value().clone())
+            .collect();
+        for leaf in pending {
+            self.pending_leaves.remove(&leaf.node_hash());
+            if is_committed_leaf(self, root, &leaf)? {
+                self.keys.insert(leaf.key, leaf);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl TreeStoreReader for ConcurrentStore {
+    fn root_node(&self) -> Result<Arc<dyn Node>> {
+        if let Some(root) = &*self.root.read() {
+            Ok(root.clone())
+        } else {
+            // Return empty tree root
+            Ok(EMPTY_TREE[0].clone())
+        }
+    }
+
+    fn get_branch(&self, key: &NodeHash) -> Result<Option<Arc<BranchNode>>> {
+        Ok(self.branches.get(key).map(|branch| branch.clone()))
+    }
+
+    fn get_leaf(&self, key: &NodeHash) -> Result<Option<Arc<LeafNode>>> {
+        Ok(self.leaves.get(key).map(|leaf| leaf.clone()))
+    }
+
+    fn get_leaf_by_key(&self, key: &[u8; 32]) -> Result<Option<Arc<LeafNode>>> {
+        Ok(self.keys.get(key).map(|leaf| leaf.clone()))
+    }
+
+    fn branch_hashes(&self) -> Result<Vec<NodeHash>> {
+        Ok(self.branches.iter().map(|entry| *entry.key()).collect())
+    }
+
+    fn leaf_hashes(&self) -> Result<Vec<NodeHash>> {
+        Ok(self.leaves.iter().map(|entry| *entry.key()).collect())
+    }
+
+    fn stats(&self) -> Result<StoreStats> {
+        Ok(StoreStats {
+            branches: self.branches.len(),
+            leaves: self.leaves.len(),
+            bytes_used: None,
+        })
+    }
+}
+
+impl TreeStoreWriter for &ConcurrentStore {
+    fn insert_branch(&mut self, branch: Arc<BranchNode>) -> Result<()> {
+        self.branches.insert(branch.node_hash(), branch);
+        Ok(())
+    }
+
+    fn insert_leaf(&mut self, leaf: Arc<LeafNode>) -> Result<()> {
+        self.pending_leaves.insert(leaf.node_hash(), leaf.clone());
+        self.leaves.insert(leaf.node_hash(), leaf);
+        Ok(())
+    }
+
+    fn delete_branch(&mut self, key: &NodeHash) -> Result<()> {
+        self.branches.remove(key);
+        Ok(())
+    }
+
+    fn delete_leaf(&mut self, key: &NodeHash) -> Result<()> {
+        self.pending_leaves.remove(key);
+        if let Some((_, leaf)) = self.leaves.remove(key) {
+            // Only drop the index entry if it still points at the deleted leaf
+            self.keys
+                .remove_if(&leaf.key, |_, indexed| indexed.node_hash() == *key);
+        }
+        Ok(())
+    }
+
+    fn update_root(&mut self, root: Arc<dyn Node>) -> Result<()> {
+        let hash = root.node_hash();
+        let mut current = self.root.write();
+        *current = Some(root);
+        self.index_pending_leaves(&hash)
+    }
+
+    fn compare_and_update_root(&mut self, expected: NodeHash, root: Arc<dyn Node>) -> Result<bool> {
+        // The comparison and the update happen under one lock, so handles cannot interleave
+        let mut current = self.root.write();
+        let current_hash = match &*current {
+            Some(current) => current.node_hash(),
+            None => EMPTY_TREE[0].node_hash(),
+        };
+        if current_hash != expected {
+            return Ok(false);
+        }
+        let hash = root.node_hash();
+        *current = Some(root);
+        self.index_pending_leaves(&hash)?;
+        Ok(true)
+    }
+}
+
+impl TreeStoreWriter for ConcurrentStore {
+    fn insert_branch(&mut self, branch: Arc<BranchNode>) -> Result<()> {
+        (&*self).insert_branch(branch)
+    }
+
+    fn insert_leaf(&mut self, leaf: Arc<LeafNode>) -> Result<()> {
+        (&*self).insert_leaf(leaf)
+    }
+
+    fn delete_branch(&mut self, key: &NodeHash) -> Result<()> {
+        (&*self).delete_branch(key)
+    }
+
+    fn delete_leaf(&mut self, key: &NodeHash) -> Result<()> {
+        (&*self).delete_leaf(key)
+    }
+
+    fn update_root(&mut self, root: Arc<dyn Node>) -> Result<()> {
+        (&*self).update_root(root)
+    }
+
+    fn compare_and_update_root(&mut self, expected: NodeHash, root: Arc<dyn Node>) -> Result<bool> {
+        (&*self).compare_and_update_root(expected, root)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::OverlayStore;
+    use crate::tree::FullTree;
+
+    #[test]
+    fn test_compare_and_update_root_detects_concurrent_writers() -> Result<()> {
+        let store = ConcurrentStore::new();
+        FullTree::new(&store).insert([1u8; 32], b"one".to_vec(), 1)?;
+        let base = store.root_node()?.node_hash();
+
+        // Two writers stage an update on the same root
+        let mut first = FullTree::new(OverlayStore::new(&store));
+        first.insert([2u8; 32], b"two".to_vec(), 2)?;
+        let mut second = FullTree::new(OverlayStore::new(&store));
+        second.insert([3u8; 32], b"three".to_vec(), 3)?;
+
+        // The first writer wins and its nodes land in the base
+        let mut first = first.into_store();
+        assert!(first.compare_and_commit(base)?);
+        // The second writer lost the race and must not clobber the first update
+        let mut second = second.into_store();
+        assert!(!second.compare_and_commit(base)?);
+
+        let tree = FullTree::new(&store);
+        let root = tree.root()?.node_hash();
+        assert_eq!(tree.root()?.node_sum(), 3);
+        for (key, value, sum) in [([1u8; 32], b"one", 1), ([2u8; 32], b"two", 2)] {
+            assert_eq!(tree.get(key)?, Some((value.to_vec(), sum)));
+            let leaf = LeafNode::new(key, value.to_vec(), sum);
+            assert!(tree.merkle_proof(key)?.verify(key, &leaf, root));
+        }
+        assert_eq!(tree.get([3u8; 32])?, None);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,385 @@
+//! A store decorator encrypting leaf records at rest.
+
+use crate::error::{MssmtError, Result};
+use crate::node::{decode_sum, BranchNode, LeafNode, Node, NodeHash, Sum, HASH_SIZE, SUM_SIZE};
+use crate::store::{TreeStoreReader, TreeStoreWriter};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// An authenticated encryption scheme with associated data, such as AES-GCM or ChaCha20-Poly1305.
+///
+/// The crate does not ship an implementation, so deployments can use the audited AEAD crate of their
+/// choice. Implementations own their key and nonces: `seal` must use a fresh nonce for every call and
+/// carry it in its output, for `open` to find it.
+pub trait LeafCipher: Send + Sync {
+    /// Encrypts `plaintext`, authenticating it together with `associated_data`.
+    fn seal(&self, plaintext: &[u8], associated_data: &[u8]) -> Result<Vec<u8>>;
+
+    /// Decrypts the output of `seal`, failing if it or `associated_data` was tampered with.
+    fn open(&self, sealed: &[u8], associated_data: &[u8]) -> Result<Vec<u8>>;
+}
+
+/// A `TreeStore` decorator encrypting the values of leaves before handing them to the inner store.
+///
+/// Each leaf is written to the inner store as a sealed leaf with the same key, whose value is the value
+/// sealed with the `LeafCipher`, authenticated together with the key and the sum of the sealed leaf. With
+/// `seal_sums`, the sum of the leaf is sealed along with its value and the sealed leaf has a zero sum. The
+/// hash of the plaintext leaf is not recorded in the sealed leaf, so it cannot be used to test guesses of
+/// its value and sum.
+///
+/// Branches are written in their shallow form, as child hashes and sums, so no plaintext leaf reaches the
+/// inner store. Their contents stay readable though: a branch sum reveals the sum of a leaf whose sibling
+/// is empty, and the parent of a leaf holds its plaintext hash, against which guesses of a low-entropy
+/// value and sum can still be tested offline, sealed sums or not.
+///
+/// Sealed leaves are addressed by their own hash in the inner store. The decorator indexes them by the
+/// hash of their plaintext leaf, rebuilding the index when it is created by listing the leaves of the
+/// inner store with `TreeStoreReader::leaf_hashes` and decrypting each of them. Leaves read back are
+/// decrypted and checked against the hash they were requested under.
+///
+/// # Examples
+///
+/// ```rust,ignore
+/// use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+/// use chacha20poly1305::{ChaCha20Poly1305, Nonce};
+/// use mssmt::store::{EncryptedStore, LeafCipher};
+/// use mssmt::{DefaultStore, FullTree, MssmtError};
+///
+/// struct ChaCha(ChaCha20Poly1305);
+///
+/// impl LeafCipher for ChaCha {
+///     fn seal(&self, msg: &[u8], aad: &[u8]) -> mssmt::error::Result<Vec<u8>> {
+///         let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+///         let sealed = self.0.encrypt(&nonce, Payload { msg, aad }).map_err(|err| MssmtError::Store(err.to_string()))?;
+///         Ok([nonce.as_slice(), &sealed].concat())
+///     }
+///
+///     fn open(&self, sealed: &[u8], aad: &[u8]) -> mssmt::error::Result<Vec<u8>> {
+///         let (nonce, msg) = sealed.split_at(12);
+///         self.0.decrypt(Nonce::from_slice(nonce), Payload { msg, aad }).map_err(|err| MssmtError::Store(err.to_string()))
+///     }
+/// }
+///
+/// let cipher = ChaCha(ChaCha20Poly1305::new(&ChaCha20Poly1305::generate_key(&mut OsRng)));
+/// let mut tree = FullTree::new(EncryptedStore::new(DefaultStore::new(), cipher)?);
+/// tree.insert([1u8; 32], b"asset metadata".to_vec(), 10)?;
+/// ```
+pub struct EncryptedStore<S, C> {
+    inner: S,
+    cipher: C,
+    seal_sums: bool,
+    // The hash of the sealed leaf stored for each plaintext leaf hash
+    sealed_hashes: HashMap<NodeHash, NodeHash>,
+}
+
+impl<S: TreeStoreReader, C: LeafCipher> EncryptedStore<S, C> {
+    /// Creates a new `EncryptedStore` over `inner`, indexing the sealed leaves it already holds.
+    ///
+    /// # Returns
+    ///
+    /// - The store.
+    /// - `MssmtError::Unsupported` if the inner store cannot list its leaves.
+    /// - The cipher's error, or `MssmtError::Store`, if the inner store holds a leaf that this cipher did
+    ///   not seal.
+    pub fn new(inner: S, cipher: C) -> Result<Self> {
+        let mut store = Self {
+            inner,
+            cipher,
+            seal_sums: false,
+            sealed_hashes: HashMap::new(),
+        };
+        for sealed_hash in store.inner.leaf_hashes()? {
+            let sealed = store
+                .inner
+                .get_leaf(&sealed_hash)?
+                .ok_or(MssmtError::NodeNotFound(sealed_hash))?;
+            let hash = store.open(&sealed)?.node_hash();
+            store.sealed_hashes.insert(hash, sealed_hash);
+        }
+        Ok(store)
+    }
+}
+
+impl<S, C: LeafCipher> EncryptedStore<S, C> {
+    /// Also seals the sums of leaves written from now on, and not only their values.
+    ///
+    /// Leaves written either way can be read back, whatever this setting. The sums of leaves still show
+    /// through the branches above them, as described on `EncryptedStore`.
+    pub fn with_sealed_sums(mut self) -> Self {
+        self.seal_sums = true;
+        self
+    }
+
+    /// Returns a reference to the wrapped store.
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// Consumes the `EncryptedStore`, returning the wrapped store.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    /// Seals `leaf` into the leaf written to the inner store.
+    fn seal(&self, leaf: &LeafNode) -> Result<LeafNode> {
+        let mut plaintext = Vec::with_capacity(1 + SUM_SIZE + leaf.value.len());
+        let sealed_sum = if self.seal_sums {
+            plaintext.push(1);
+            plaintext.extend_from_slice(&leaf.sum.to_be_bytes());
+            0
+        } else {
+            plaintext.push(0);
+            leaf.sum
+        };
+        plaintext.extend_from_slice(&leaf.value);
+
+        let sealed = self
+            .cipher
+            .seal(&plaintext, &associated_data(&leaf.key, sealed_sum))?;
+        Ok(LeafNode::new(leaf.key, sealed, sealed_sum))
+    }
+
+    /// Opens a sealed leaf into the plaintext leaf it was sealed from.
+    fn open(&self, sealed: &LeafNode) -> Result<LeafNode> {
+        let failed = || {
+            MssmtError::Store(format!(
+                "sealed leaf {} failed authentication",
+                sealed.node_hash()
+            ))
+        };
+        let plaintext = self
+            .cipher
+            .open(&sealed.value, &associated_data(&sealed.key, sealed.sum))?;
+        let (sum, value) = match plaintext.split_first() {
+            Some((0, value)) => (sealed.sum, value),
+            Some((1, rest)) if rest.len() >= SUM_SIZE => {
+                (decode_sum(&rest[..SUM_SIZE]), &rest[SUM_SIZE..])
+            }
+            _ => return Err(failed()),
+        };
+
+        Ok(LeafNode::new(sealed.key, value.to_vec(), sum))
+    }
+}
+
+/// Binds a sealed value to the key and the sum in clear of its sealed leaf.
+fn associated_data(key: &[u8; HASH_SIZE], sum: Sum) -> [u8; HASH_SIZE + SUM_SIZE] {
+    let mut data = [0u8; HASH_SIZE + SUM_SIZE];
+    data[..HASH_SIZE].copy_from_slice(key);
+    data[HASH_SIZE..].copy_from_slice(&sum.to_be_bytes());
+    data
+}
+
+impl<S: TreeStoreReader, C: LeafCipher> TreeStoreReader for EncryptedStore<S, C> {
+    fn root_node(&self) -> Result<Arc<dyn Node>> {
+        self.inner.root_node()
+    }
+
+    fn get_branch(&self, key: &NodeHash) -> Result<Option<Arc<BranchNode>>> {
+        self.inner.get_branch(key)
+    }
+
+    fn get_leaf(&self, key: &NodeHash) -> Result<Option<Arc<LeafNode>>> {
+        let Some(sealed_hash) = self.sealed_hashes.get(key) else {
+            return Ok(None);
+        };
+        let Some(sealed) = self.inner.get_leaf(sealed_hash)? else {
+            return Ok(None);
+        };
+        let leaf = self.open(&sealed)?;
+        if leaf.node_hash() != *key {
+            return Err(MssmtError::Store(format!(
+                "sealed leaf {} failed authentication",
+                key
+            )));
+        }
+        Ok(Some(Arc::new(leaf)))
+    }
+
+    fn get_leaf_by_key(&self, key: &[u8; 32]) -> Result<Option<Arc<LeafNode>>> {
+        match self.inner.get_leaf_by_key(key)? {
+            Some(sealed) => self.open(&sealed).map(|leaf| Some(Arc::new(leaf))),
+            None => Ok(None),
+        }
+    }
+
+    fn may_contain_key(&self, key: &[u8; 32]) -> Result<bool> {
+        self.inner.may_contain_key(key)
+    }
+
+    fn get_children(
+        &self,
+        height: usize,
+        hash: &NodeHash,
+    ) -> Result<(Arc<dyn Node>, Arc<dyn Node>)> {
+        self.inner.get_children(height, hash)
+    }
+
+    fn branch_hashes(&self) -> Result<Vec<NodeHash>> {
+        self.inner.branch_hashes()
+    }
+
+    fn leaf_hashes(&self) -> Result<Vec<NodeHash>> {
+        Ok(self.sealed_hashes.keys().copied().collect())
+    }
+}
+
+impl<S: TreeStoreWriter, C: LeafCipher> TreeStoreWriter for EncryptedStore<S, C> {
+    fn insert_branch(&mut self, branch: Arc<BranchNode>) -> Result<()> {
+        self.inner.insert_branch(Arc::new(branch.to_shallow()))
+    }
+
+    fn insert_leaf(&mut self, leaf: Arc<LeafNode>) -> Result<()> {
+        let hash = leaf.node_hash();
+        if self.sealed_hashes.contains_key(&hash) {
+            return Ok(());
+        }
+        let sealed = self.seal(&leaf)?;
+        let sealed_hash = sealed.node_hash();
+        self.inner.insert_leaf(Arc::new(sealed))?;
+        self.sealed_hashes.insert(hash, sealed_hash);
+        Ok(())
+    }
+
+    fn delete_branch(&mut self, key: &NodeHash) -> Result<()> {
+        self.inner.delete_branch(key)
+    }
+
+    fn delete_leaf(&mut self, key: &NodeHash) -> Result<()> {
+        if let Some(sealed_hash) = self.sealed_hashes.remove(key) {
+            self.inner.delete_leaf(&sealed_hash)?;
+        }
+        Ok(())
+    }
+
+    fn update_root(&mut self, root: Arc<dyn Node>) -> Result<()> {
+        match root.as_branch() {
+            Some(branch) => self.inner.update_root(Arc::new(branch.to_shallow())),
+            None => self.inner.update_root(root),
+        }
+    }
+
+    fn compare_and_update_root(&mut self, expected: NodeHash, root: Arc<dyn Node>) -> Result<bool> {
+        match root.as_branch() {
+            Some(branch) => self
+                .inner
+                .compare_and_update_root(expected, Arc::new(branch.to_shallow())),
+            None => self.inner.compare_and_update_root(expected, root),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::{DefaultStore, LogStore};
+    use crate::tree::FullTree;
+    use sha2::{Digest, Sha256};
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    /// A stand-in AEAD for tests only: a SHA-256 keystream and a truncated SHA-256 tag.
+    struct TestCipher {
+        key: [u8; 32],
+        nonces: AtomicU64,
+    }
+
+    impl TestCipher {
+        fn new(key: u8) -> Self {
+            Self {
+                key: [key; 32],
+                nonces: AtomicU64::new(0),
+            }
+        }
+
+        fn apply(&self, nonce: &[u8], data: &mut [u8]) {
+            for (i, chunk) in data.chunks_mut(32).enumerate() {
+                let pad =
+                    Sha256::digest([&self.key[..], nonce, &(i as u64).to_be_bytes()].concat());
+                chunk
+                    .iter_mut()
+                    .zip(pad)
+                    .for_each(|(byte, pad)| *byte ^= pad);
+            }
+        }
+
+        fn tag(&self, nonce: &[u8], ciphertext: &[u8], aad: &[u8]) -> Vec<u8> {
+            Sha256::digest([&self.key[..], nonce, aad, ciphertext].concat())[..16].to_vec()
+        }
+    }
+
+    impl LeafCipher for TestCipher {
+        fn seal(&self, plaintext: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+            let nonce = self.nonces.fetch_add(1, Ordering::Relaxed).to_be_bytes();
+            let mut ciphertext = plaintext.to_vec();
+            self.apply(&nonce, &mut ciphertext);
+            let tag = self.tag(&nonce, &ciphertext, aad);
+            Ok([&nonce[..], &ciphertext, &tag].concat())
+        }
+
+        fn open(&self, sealed: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+            let (nonce, rest) = sealed.split_at(8);
+            let (ciphertext, tag) = rest.split_at(rest.len() - 16);
+            if self.tag(nonce, ciphertext, aad) != tag {
+                return Err(MssmtError::Store("authentication failed".to_string()));
+            }
+            let mut plaintext = ciphertext.to_vec();
+            self.apply(nonce, &mut plaintext);
+            Ok(plaintext)
+        }
+    }
+
+    #[test]
+    fn test_leaves_are_sealed_at_rest() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("mssmt-encrypted-{}.log", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let secret = b"plaintext asset metadata".to_vec();
+
+        let store = EncryptedStore::new(LogStore::open(&path)?, TestCipher::new(7))?;
+        let mut tree = FullTree::new(store.with_sealed_sums());
+        let mut plain = FullTree::new(DefaultStore::new());
+        for i in 0..8u8 {
+            let value = [secret.clone(), vec![i]].concat();
+            tree.insert([i; 32], value.clone(), i as Sum + 1)?;
+            plain.insert([i; 32], value, i as Sum + 1)?;
+        }
+        tree.delete([3u8; 32])?;
+        plain.delete([3u8; 32])?;
+        let root_hash = tree.root()?.node_hash();
+        assert_eq!(root_hash, plain.root()?.node_hash());
+        drop(tree);
+
+        // Neither values nor leaf sums reach the disk, and sealed leaves do not carry the plaintext
+        // leaf hashes that would let values be guessed
+        let bytes = std::fs::read(&path)?;
+        assert!(!bytes.windows(secret.len()).any(|window| window == secret));
+        let plain_hashes = plain.store().leaf_hashes()?;
+        let log = LogStore::open(&path)?;
+        for hash in log.leaf_hashes()? {
+            let sealed = log.get_leaf(&hash)?.unwrap();
+            assert_eq!(sealed.sum, 0);
+            assert!(plain_hashes.iter().all(|plain_hash| !sealed
+                .value
+                .windows(HASH_SIZE)
+                .any(|window| window == plain_hash.as_bytes())));
+        }
+
+        // The index is rebuilt on reopening, and leaves decrypt to the committed ones
+        let tree = FullTree::new(EncryptedStore::new(log, TestCipher::new(7))?);
+        assert_eq!(tree.root()?.node_hash(), root_hash);
+        assert_eq!(
+            tree.get([5u8; 32])?,
+            Some(([secret.clone(), vec![5]].concat(), 6))
+        );
+        assert_eq!(tree.get([3u8; 32])?, None);
+        assert!(tree.verify_integrity()?.is_ok());
+
+        // Another key fails authentication while indexing
+        assert!(matches!(
+            EncryptedStore::new(LogStore::open(&path)?, TestCipher::new(8)),
+            Err(MssmtError::Store(_))
+        ));
+
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,300 @@
+//! A store decorator keeping a counting Bloom filter over leaf keys.
+
+use crate::error::Result;
+use crate::metadata::LeafMetadata;
+use crate::node::{BranchNode, LeafNode, Node, NodeHash};
+use crate::store::{StoreStats, TreeStore, TreeStoreReader, TreeStoreWriter};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// A `TreeStore` decorator answering `may_contain_key` from a counting Bloom filter over the keys of the
+/// leaves in the inner store.
+///
+/// In a sparse tree almost every key is absent, and looking one up walks its whole path down from the
+/// root. With a `FilteredStore` in front of a disk or network backend, `FullTree::get` and
+/// `FullTree::contains_key` return `None` and `false` for most absent keys without reading any node.
+/// Keys that pass the filter, present or false positives, are looked up as usual.
+///
+/// The filter counts the stored leaves of every key rather than setting bits, so deleting a leaf takes
+/// its key back out of the filter. Counters saturate instead of overflowing, and a saturated counter is
+/// never decremented, which can only add false positives. The filter is built by listing the leaves of
+/// the inner store when the decorator is created, and must see every later write, so the inner store
+/// should not be written to behind its back.
+///
+/// # Type Parameters
+///
+/// - `S`: The wrapped storage backend.
+///
+/// # Examples
+///
+/// ```rust
+/// use mssmt::store::FilteredStore;
+/// use mssmt::{DefaultStore, FullTree, TreeStoreReader};
+///
+/// let store = FilteredStore::new(DefaultStore::new(), 1000, 0.01).unwrap();
+/// let mut tree = FullTree::new(store);
+/// tree.insert([1u8; 32], b"value".to_vec(), 10).unwrap();
+///
+/// assert!(tree.store().may_contain_key(&[1u8; 32]).unwrap());
+/// assert_eq!(tree.get([2u8; 32]).unwrap(), None);
+/// ```
+pub struct FilteredStore<S> {
+    inner: S,
+    filter: KeyFilter,
+    rejected: AtomicU64,
+}
+
+impl<S> FilteredStore<S> {
+    /// Creates a new `FilteredStore` wrapping `inner`, adding the keys of its leaves to the filter.
+    ///
+    /// The filter is sized for `expected_keys` leaves at a false positive rate of `false_positive_rate`,
+    /// which is clamped between one in a billion and one half. The rate grows past the target once the
+    /// store holds more leaves than expected, superseded leaves not yet compacted included.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the leaves of `inner` cannot be listed or read.
+    pub fn new<const K: usize, V>(
+        inner: S,
+        expected_keys: usize,
+        false_positive_rate: f64,
+    ) -> Result<Self>
+    where
+        S: TreeStoreReader<K, V>,
+    {
+        let mut filter = KeyFilter::new(expected_keys, false_positive_rate);
+        for hash in inner.leaf_hashes()? {
+            if let Some(leaf) = inner.get_leaf(&hash)? {
+                filter.insert(&leaf.key);
+            }
+        }
+        Ok(Self {
+            inner,
+            filter,
+            rejected: AtomicU64::new(0),
+        })
+    }
+
+    /// Returns a reference to the wrapped store.
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// Consumes the `FilteredStore`, returning the wrapped store.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    /// Returns the number of keys the filter has ruled out since the store was created.
+    pub fn rejected_lookups(&self) -> u64 {
+        self.rejected.load(Ordering::Relaxed)
+    }
+}
+
+impl<S: TreeStoreReader<K, V>, const K: usize, V> TreeStoreReader<K, V> for FilteredStore<S> {
+    fn root_node(&self) -> Result<Arc<dyn Node>> {
+        self.inner.root_node()
+    }
+
+    fn get_branch(&self, key: &NodeHash) -> Result<Option<Arc<BranchNode>>> {
+        self.inner.get_branch(key)
+    }
+
+    fn get_leaf(&self, key: &NodeHash) -> Result<Option<Arc<LeafNode<K, V>>>> {
+        self.inner.get_leaf(key)
+    }
+
+    fn get_leaf_by_key(&self, key: &[u8; K]) -> Result<Option<Arc<LeafNode<K, V>>>> {
+        if !self.filter.contains(key) {
+            return Ok(None);
+        }
+        self.inner.get_leaf_by_key(key)
+    }
+
+    fn may_contain_key(&self, key: &[u8; K]) -> Result<bool> {
+        if !self.filter.contains(key) {
+            self.rejected.fetch_add(1, Ordering::Relaxed);
+            return Ok(false);
+        }
+        self.inner.may_contain_key(key)
+    }
+
+    fn get_children(
+        &self,
+        height: usize,
+        hash: &NodeHash,
+    ) -> Result<(Arc<dyn Node>, Arc<dyn Node>)> {
+        self.inner.get_children(height, hash)
+    }
+
+    fn branch_hashes(&self) -> Result<Vec<NodeHash>> {
+        self.inner.branch_hashes()
+    }
+
+    fn leaf_hashes(&self) -> Result<Vec<NodeHash>> {
+        self.inner.leaf_hashes()
+    }
+
+    fn stats(&self) -> Result<StoreStats> {
+        self.inner.stats()
+    }
+
+    fn health_check(&self) -> Result<()> {
+        self.inner.health_check()
+    }
+
+    fn get_leaf_metadata(&self, key: &[u8; K]) -> Result<Option<LeafMetadata>> {
+        self.inner.get_leaf_metadata(key)
+    }
+}
+
+impl<S: TreeStore<K, V>, const K: usize, V> TreeStoreWriter<K, V> for FilteredStore<S> {
+    fn insert_branch(&mut self, branch: Arc<BranchNode>) -> Result<()> {
+        self.inner.insert_branch(branch)
+    }
+
+    fn insert_leaf(&mut self, leaf: Arc<LeafNode<K, V>>) -> Result<()> {
+        // Rewriting a stored leaf counts its key twice, which can only cause false positives
+        let key = leaf.key;
+        self.inner.insert_leaf(leaf)?;
+        self.filter.insert(&key);
+        Ok(())
+    }
+
+    fn delete_branch(&mut self, key: &NodeHash) -> Result<()> {
+        self.inner.delete_branch(key)
+    }
+
+    fn delete_leaf(&mut self, key: &NodeHash) -> Result<()> {
+        let leaf = self.inner.get_leaf(key)?;
+        self.inner.delete_leaf(key)?;
+        if let Some(leaf) = leaf {
+            self.filter.remove(&leaf.key);
+        }
+        Ok(())
+    }
+
+    fn set_leaf_metadata(&mut self, key: &[u8; K], metadata: Option<LeafMetadata>) -> Result<()> {
+        self.inner.set_leaf_metadata(key, metadata)
+    }
+
+    fn update_root(&mut self, root: Arc<dyn Node>) -> Result<()> {
+        self.inner.update_root(root)
+    }
+
+    fn compare_and_update_root(&mut self, expected: NodeHash, root: Arc<dyn Node>) -> Result<bool> {
+        self.inner.compare_and_update_root(expected, root)
+    }
+}
+
+/// A counting Bloom filter over byte keys.
+struct KeyFilter {
+    counters: Vec<u8>,
+    hashes: u32,
+}
+
+impl KeyFilter {
+    fn new(expected_keys: usize, false_positive_rate: f64) -> Self {
+        let keys = expected_keys.max(1) as f64;
+        let rate = false_positive_rate.clamp(1e-9, 0.5);
+        let ln2 = std::f64::consts::LN_2;
+        let counters = (-keys * rate.ln() / (ln2 * ln2)).ceil().max(64.0) as usize;
+        let hashes = ((counters as f64 / keys) * ln2).round().clamp(1.0, 32.0) as u32;
+        Self {
+            counters: vec![0; counters],
+            hashes,
+        }
+    }
+
+    /// Returns the counter indexes of `key`, by double hashing.
+    fn indexes(&self, key: &[u8]) -> impl Iterator<Item = usize> {
+        let seeded = |seed: u8| {
+            let mut hasher = DefaultHasher::new();
+            seed.hash(&mut hasher);
+            key.hash(&mut hasher);
+            hasher.finish()
+        };
+        let (first, step) = (seeded(0), seeded(1) | 1);
+        let len = self.counters.len() as u64;
+        (0..u64::from(self.hashes))
+            .map(move |i| (first.wrapping_add(i.wrapping_mul(step)) % len) as usize)
+    }
+
+    fn insert(&mut self, key: &[u8]) {
+        for index in self.indexes(key).collect::<Vec<_>>() {
+            let counter = &mut self.counters[index];
+            *counter = counter.saturating_add(1);
+        }
+    }
+
+    fn remove(&mut self, key: &[u8]) {
+        for index in self.indexes(key).collect::<Vec<_>>() {
+            let counter = &mut self.counters[index];
+            // A saturated counter may stand for more leaves than it can count
+            if *counter != u8::MAX {
+                *counter = counter.saturating_sub(1);
+            }
+        }
+    }
+
+    fn contains(&self, key: &[u8]) -> bool {
+        self.indexes(key).all(|index| self.counters[index] > 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::Metrics;
+    use crate::node::Sum;
+    use crate::store::{DefaultStore, MeteredStore};
+    use crate::tree::FullTree;
+
+    #[derive(Default)]
+    struct Reads(AtomicU64);
+
+    impl Metrics for Reads {
+        fn node_read(&self, _found: bool) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn test_absent_keys_skip_the_store() -> Result<()> {
+        let mut base = FullTree::new(DefaultStore::new());
+        base.insert([1u8; 32], b"kept".to_vec(), 1)?;
+        base.insert([2u8; 32], b"deleted".to_vec(), 2)?;
+
+        // Leaves already in the inner store are added to the filter
+        let reads = Arc::new(Reads::default());
+        let metered = MeteredStore::new(base.into_store(), reads.clone());
+        let mut tree = FullTree::new(FilteredStore::new(metered, 1000, 0.001)?);
+        assert_eq!(tree.get([1u8; 32])?, Some((b"kept".to_vec(), 1 as Sum)));
+
+        // Absent keys are answered without reading a node
+        tree.delete([2u8; 32])?;
+        tree.compact()?;
+        let before = reads.0.load(Ordering::Relaxed);
+        for byte in 2..=255u8 {
+            assert!(!tree.contains_key([byte; 32])?);
+            assert_eq!(tree.get([byte; 32])?, None);
+        }
+        assert_eq!(tree.store().rejected_lookups(), 2 * 254);
+        assert_eq!(reads.0.load(Ordering::Relaxed), before);
+
+        // Present keys still pass after inserts and deletes
+        for byte in 3..=64u8 {
+            tree.insert([byte; 32], vec![byte], byte as Sum)?;
+        }
+        tree.delete([3u8; 32])?;
+        assert!(tree.contains_key([1u8; 32])?);
+        assert!(!tree.contains_key([3u8; 32])?);
+        for byte in 4..=64u8 {
+            assert_eq!(tree.get([byte; 32])?, Some((vec![byte], byte as Sum)));
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,697 @@
+//! A durable store backed by an append-only log file.
+
+use crate::error::{MssmtError, Result};
+use crate::format::{check_version, header, Format, HEADER_SIZE};
+use crate::hash_utils::to_array;
+use crate::node::{
+    decode_sum, BranchNode, EmptyTree, LeafNode, Node, NodeHash, Sum, EMPTY_TREE, HASH_SIZE,
+    SUM_SIZE,
+};
+use crate::store::compression::{decode_compressed_leaf, decompress_value};
+use crate::store::{StoreStats, TreeStoreReader, TreeStoreWriter, ValueCompression};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// The magic bytes at the start of every log file, followed by the format version.
+pub(crate) const LOG_MAGIC: &[u8; 8] = b"MSSMTLOG";
+
+/// The header of log files written by this release.
+const LOG_HEADER: [u8; HEADER_SIZE] = header(LOG_MAGIC, Format::LogStore.current_version());
+
+/// The number of checksum bytes following each record.
+const CHECKSUM_SIZE: usize = 4;
+
+const TAG_BRANCH: u8 = 1;
+const TAG_LEAF: u8 = 2;
+const TAG_DELETE_BRANCH: u8 = 3;
+const TAG_DELETE_LEAF: u8 = 4;
+const TAG_ROOT: u8 = 5;
+const TAG_COMPRESSED_LEAF: u8 = 6;
+const TAG_LEAF_REF: u8 = 7;
+const TAG_VALUE: u8 = 8;
+const TAG_COMPRESSED_VALUE: u8 = 9;
+
+/// A `TreeStore` that persists every write to an append-only log file.
+///
+/// Node writes and deletions are appended to the log as they happen, and `update_root` appends a commit
+/// record and syncs the file to disk. When the log is opened, it is replayed up to the last commit record:
+/// writes that were not followed by a root update, as well as a record torn by a crash, are discarded and
+/// truncated away. Since the tree only updates the root after writing a complete path, every tree update
+/// is therefore durable and atomic once it returns.
+///
+/// All nodes are kept in memory, with branches referencing their children by hash. The log is never
+/// rewritten, so it grows with every update. Opened with `open_with_compression`, the store writes large
+/// leaf values compressed to the log, and keeps them uncompressed in memory. With `with_value_dedup`, each
+/// distinct leaf value is written to the log once, while leaves referencing it are live.
+///
+/// # Examples
+///
+/// ```rust
+/// use mssmt::store::LogStore;
+/// use mssmt::{FullTree, Node};
+///
+/// let path = std::env::temp_dir().join(format!("mssmt-doc-{}.log", std::process::id()));
+/// # let _ = std::fs::remove_file(&path);
+///
+/// let mut tree = FullTree::new(LogStore::open(&path).unwrap());
+/// tree.insert([1u8; 32], b"value".to_vec(), 10).unwrap();
+/// let root_hash = tree.root().unwrap().node_hash();
+/// drop(tree);
+///
+/// let reopened = FullTree::new(LogStore::open(&path).unwrap());
+/// assert_eq!(reopened.root().unwrap().node_hash(), root_hash);
+/// # std::fs::remove_file(&path).unwrap();
+/// ```
+pub struct LogStore {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    branches: HashMap<NodeHash, Arc<BranchNode>>,
+    leaves: HashMap<NodeHash, Arc<LeafNode>>,
+    root: Arc<dyn Node>,
+    compression: Option<ValueCompression>,
+    dedup: bool,
+    // The values written by `with_value_dedup`, by value hash
+    values: HashMap<NodeHash, SharedValue>,
+    // The value hash of each leaf referencing a shared value, by leaf hash
+    leaf_values: HashMap<NodeHash, NodeHash>,
+}
+
+/// A leaf value written once to the log and referenced by leaves.
+struct SharedValue {
+    value: Vec<u8>,
+    refs: usize,
+}
+
+/// A single log record.
+enum Record {
+    Branch(BranchNode),
+    Leaf(LeafNode),
+    // A leaf encoded by `ValueCompression::encode_leaf`
+    CompressedLeaf(Vec<u8>),
+    // A leaf referencing the value with the given hash
+    LeafRef {
+        key: [u8; 32],
+        sum: Sum,
+        value_hash: NodeHash,
+    },
+    Value(NodeHash, Vec<u8>),
+    CompressedValue(NodeHash, Vec<u8>),
+    DeleteBranch(NodeHash),
+    DeleteLeaf(NodeHash),
+    Root(NodeHash),
+}
+
+impl LogStore {
+    /// Opens the log at `path`, creating it if it does not exist, and recovers the last committed root.
+    ///
+    /// # Returns
+    ///
+    /// - The recovered store.
+    /// - `MssmtError::InvalidEncoding` if the file is not a log, has an unsupported version, contains
+    ///   compressed leaves or the committed root is missing. Older versions can be upgraded with
+    ///   `format::migrate`.
+    /// - `MssmtError::Io` if the file cannot be read or written.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Self::open_with(path.as_ref(), None)
+    }
+
+    /// Opens the log at `path` like `open`, compressing leaf values written from now on with `compression`.
+    ///
+    /// Logs written with compression must be reopened with the same compression algorithm. Leaves written
+    /// before compression was enabled stay readable.
+    pub fn open_with_compression(
+        path: impl AsRef<Path>,
+        compression: ValueCompression,
+    ) -> Result<Self> {
+        Self::open_with(path.as_ref(), Some(compression))
+    }
+
+    fn open_with(path: &Path, compression: Option<ValueCompression>) -> Result<Self> {
+        let path = path.to_path_buf();
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)?;
+        if bytes.is_empty() {
+            file.write_all(&LOG_HEADER)?;
+            file.sync_all()?;
+            bytes.extend_from_slice(&LOG_HEADER);
+        }
+        if bytes.len() < HEADER_SIZE || !bytes.starts_with(LOG_MAGIC) {
+            return Err(MssmtError::InvalidEncoding(format!(
+                "{} is not a log store",
+                path.display()
+            )));
+        }
+        check_version(Format::LogStore, bytes[HEADER_SIZE - 1])?;
+        if bytes[HEADER_SIZE - 1] < Format::LogStore.current_version() {
+            // Later versions only add record types, so older logs are upgraded by their header
+            file.seek(SeekFrom::Start(0))?;
+            file.write_all(&LOG_HEADER)?;
+            file.sync_all()?;
+        }
+
+        let mut store = Self {
+            path,
+            writer: BufWriter::new(file.try_clone()?),
+            branches: HashMap::new(),
+            leaves: HashMap::new(),
+            root: EMPTY_TREE[0].clone(),
+            compression,
+            dedup: false,
+            values: HashMap::new(),
+            leaf_values: HashMap::new(),
+        };
+
+        // Writes only take effect once a root record commits them
+        let mut pending = Vec::new();
+        let mut root_hash = EmptyTree::hash_at(0);
+        let mut committed_len = LOG_HEADER.len();
+        let mut offset = LOG_HEADER.len();
+        while let Some((record, len)) = decode_record(&bytes[offset..]) {
+            offset += len;
+            match record {
+                Record::Root(hash) => {
+                    for record in pending.drain(..) {
+                        store.apply(record)?;
+                    }
+                    root_hash = hash;
+                    committed_len = offset;
+                }
+                record => pending.push(record),
+            }
+        }
+
+        // Drop the uncommitted tail so that new records follow the last commit
+        debug_event!(
+            path = %store.path.display(),
+            root = %root_hash,
+            discarded_bytes = bytes.len() - committed_len,
+            "log replayed"
+        );
+        if committed_len < bytes.len() {
+            file.set_len(committed_len as u64)?;
+            file.sync_all()?;
+        }
+        store.writer.seek(SeekFrom::Start(committed_len as u64))?;
+
+        if root_hash != EmptyTree::hash_at(0) {
+            store.root = match store.branches.get(&root_hash) {
+                Some(branch) => branch.clone(),
+                None => {
+                    return Err(MssmtError::InvalidEncoding(format!(
+                        "committed root {} is missing from the log",
+                        root_hash
+                    )))
+                }
+            };
+        }
+        Ok(store)
+    }
+
+    /// Writes each distinct leaf value to the log once, with leaves referencing it by hash.
+    ///
+    /// A value is written again only after every leaf referencing it was deleted, so trees whose leaves
+    /// share few values produce much smaller logs. Logs written with deduplication are read by any
+    /// `LogStore`, whether or not it deduplicates its own writes.
+    pub fn with_value_dedup(mut self) -> Self {
+        self.dedup = true;
+        self
+    }
+
+    /// Returns the path of the log file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the number of distinct values referenced by leaves.
+    pub fn shared_values(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Records that the leaf `leaf_hash` references the value `value_hash`.
+    fn reference_value(&mut self, leaf_hash: NodeHash, value_hash: NodeHash) {
+        if self.leaf_values.insert(leaf_hash, value_hash).is_none() {
+            if let Some(shared) = self.values.get_mut(&value_hash) {
+                shared.refs += 1;
+            }
+        }
+    }
+
+    /// Drops the reference of the leaf `leaf_hash`, forgetting its value once it is unreferenced.
+    fn release_value(&mut self, leaf_hash: &NodeHash) {
+        let Some(value_hash) = self.leaf_values.remove(leaf_hash) else {
+            return;
+        };
+        if let Some(shared) = self.values.get_mut(&value_hash) {
+            shared.refs -= 1;
+            if shared.refs == 0 {
+                self.values.remove(&value_hash);
+            }
+        }
+    }
+
+    /// Writes `leaf` as a reference to its value, writing the value first if it is not shared yet.
+    fn append_leaf_ref(&mut self, leaf: &LeafNode) -> Result<()> {
+        let value_hash = NodeHash::new(Sha256::digest(&leaf.value).into());
+        if !self.values.contains_key(&value_hash) {
+            let compressed = match &self.compression {
+                Some(compression) => compression.compress(&leaf.value)?,
+                None => None,
+            };
+            match compressed {
+                Some(bytes) => self.append(&Record::CompressedValue(value_hash, bytes))?,
+                None => self.append(&Record::Value(value_hash, leaf.value.clone()))?,
+            }
+            self.values.insert(
+                value_hash,
+                SharedValue {
+                    value: leaf.value.clone(),
+                    refs: 0,
+                },
+            );
+        }
+        self.append(&Record::LeafRef {
+            key: leaf.key,
+            sum: leaf.sum,
+            value_hash,
+        })?;
+        self.reference_value(leaf.node_hash(), value_hash);
+        Ok(())
+    }
+
+    /// Applies a replayed node record to the in-memory state.
+    fn apply(&mut self, record: Record) -> Result<()> {
+        match record {
+            Record::Branch(branch) => {
+                self.branches.insert(branch.node_hash(), Arc::new(branch));
+            }
+            Record::Leaf(leaf) => {
+                self.leaves.insert(leaf.node_hash(), Arc::new(leaf));
+            }
+            Record::CompressedLeaf(bytes) => {
+                let leaf = decode_compressed_leaf(self.compression.as_ref(), &bytes)?;
+                self.leaves.insert(leaf.node_hash(), Arc::new(leaf));
+            }
+            Record::LeafRef {
+                key,
+                sum,
+                value_hash,
+            } => {
+                let value = match self.values.get(&value_hash) {
+                    Some(shared) => shared.value.clone(),
+                    None => {
+                        return Err(MssmtError::InvalidEncoding(format!(
+                            "leaf references the missing value {}",
+                            value_hash
+                        )))
+                    }
+                };
+                let leaf = LeafNode::new(key, value, sum);
+                self.reference_value(leaf.node_hash(), value_hash);
+                self.leaves.insert(leaf.node_hash(), Arc::new(leaf));
+            }
+            Record::Value(value_hash, value) => {
+                self.values
+                    .entry(value_hash)
+                    .or_insert(SharedValue { value, refs: 0 });
+            }
+            Record::CompressedValue(value_hash, bytes) => {
+                let value = decompress_value(self.compression.as_ref(), &bytes)?;
+                self.values
+                    .entry(value_hash)
+                    .or_insert(SharedValue { value, refs: 0 });
+            }
+            Record::DeleteBranch(hash) => {
+                self.branches.remove(&hash);
+            }
+            Record::DeleteLeaf(hash) => {
+                self.leaves.remove(&hash);
+                self.release_value(&hash);
+            }
+            Record::Root(_) => {}
+        }
+        Ok(())
+    }
+
+    /// Appends a record to the log without syncing it.
+    fn append(&mut self, record: &Record) -> Result<()> {
+        let payload = encode_record(record);
+        let checksum = Sha256::digest(&payload);
+        self.writer
+            .write_all(&(payload.len() as u32).to_be_bytes())?;
+        self.writer.write_all(&payload)?;
+        self.writer.write_all(&checksum[..CHECKSUM_SIZE])?;
+        Ok(())
+    }
+}
+
+impl TreeStoreReader for LogStore {
+    fn root_node(&self) -> Result<Arc<dyn Node>> {
+        Ok(self.root.clone())
+    }
+
+    fn get_branch(&self, key: &NodeHash) -> Result<Option<Arc<BranchNode>>> {
+        Ok(self.branches.get(key).cloned())
+    }
+
+    fn get_leaf(&self, key: &NodeHash) -> Result<Option<Arc<LeafNode>>> {
+        Ok(self.leaves.get(key).cloned())
+    }
+
+    fn branch_hashes(&self) -> Result<Vec<NodeHash>> {
+        Ok(self.branches.keys().copied().collect())
+    }
+
+    fn leaf_hashes(&self) -> Result<Vec<NodeHash>> {
+        Ok(self.leaves.keys().copied().collect())
+    }
+
+    /// Counts the live nodes, and reports the size of the log, buffered records included.
+    ///
+    /// The log is never rewritten, so its size grows with the write history rather than with the tree.
+    fn stats(&self) -> Result<StoreStats> {
+        let file_len = self.writer.get_ref().metadata()?.len();
+        Ok(StoreStats {
+            branches: self.branches.len(),
+            leaves: self.leaves.len(),
+            bytes_used: Some(file_len + self.writer.buffer().len() as u64),
+        })
+    }
+}
+
+impl TreeStoreWriter for LogStore {
+    fn insert_branch(&mut self, branch: Arc<BranchNode>) -> Result<()> {
+        let shallow = branch.to_shallow();
+        self.append(&Record::Branch(shallow.clone()))?;
+        self.branches.insert(shallow.node_hash(), Arc::new(shallow));
+        Ok(())
+    }
+
+    fn insert_leaf(&mut self, leaf: Arc<LeafNode>) -> Result<()> {
+        if self.dedup {
+            self.append_leaf_ref(&leaf)?;
+            self.leaves.insert(leaf.node_hash(), leaf);
+            return Ok(());
+        }
+        let compressed = match &self.compression {
+            Some(compression) => compression.encode_leaf(&leaf)?,
+            None => None,
+        };
+        match compressed {
+            Some(bytes) => self.append(&Record::CompressedLeaf(bytes))?,
+            None => self.append(&Record::Leaf((*leaf).clone()))?,
+        }
+        self.leaves.insert(leaf.node_hash(), leaf);
+        Ok(())
+    }
+
+    fn delete_branch(&mut self, key: &NodeHash) -> Result<()> {
+        self.append(&Record::DeleteBranch(*key))?;
+        self.branches.remove(key);
+        Ok(())
+    }
+
+    fn delete_leaf(&mut self, key: &NodeHash) -> Result<()> {
+        self.append(&Record::DeleteLeaf(*key))?;
+        self.leaves.remove(key);
+        self.release_value(key);
+        Ok(())
+    }
+
+    fn update_root(&mut self, root: Arc<dyn Node>) -> Result<()> {
+        self.append(&Record::Root(root.node_hash()))?;
+        self.writer.flush()?;
+        self.writer.get_ref().sync_data()?;
+
+        self.root = match root.as_branch() {
+            Some(branch) => Arc::new(branch.to_shallow()),
+            None => root,
+        };
+        Ok(())
+    }
+
+    fn compare_and_update_root(&mut self, expected: NodeHash, root: Arc<dyn Node>) -> Result<bool> {
+        if self.root.node_hash() != expected {
+            return Ok(false);
+        }
+        self.update_root(root)?;
+        Ok(true)
+    }
+}
+
+/// Encodes the payload of a record.
+fn encode_record(record: &Record) -> Vec<u8> {
+    let mut payload = Vec::new();
+    match record {
+        Record::Branch(branch) => {
+            payload.push(TAG_BRANCH);
+            payload.extend_from_slice(&branch.encode());
+        }
+        Record::Leaf(leaf) => {
+            payload.push(TAG_LEAF);
+            payload.extend_from_slice(&leaf.encode());
+        }
+        Record::CompressedLeaf(bytes) => {
+            payload.push(TAG_COMPRESSED_LEAF);
+            payload.extend_from_slice(bytes);
+        }
+        Record::LeafRef {
+            key,
+            sum,
+            value_hash,
+        } => {
+            payload.push(TAG_LEAF_REF);
+            payload.extend_from_slice(key);
+            payload.extend_from_slice(&sum.to_be_bytes());
+            payload.extend_from_slice(value_hash.as_bytes());
+        }
+        Record::Value(value_hash, value) => {
+            payload.push(TAG_VALUE);
+            payload.extend_from_slice(value_hash.as_bytes());
+            payload.extend_from_slice(value);
+        }
+        Record::CompressedValue(value_hash, bytes) => {
+            payload.push(TAG_COMPRESSED_VALUE);
+            payload.extend_from_slice(value_hash.as_bytes());
+            payload.extend_from_slice(bytes);
+        }
+        Record::DeleteBranch(hash) => {
+            payload.push(TAG_DELETE_BRANCH);
+            payload.extend_from_slice(hash.as_bytes());
+        }
+        Record::DeleteLeaf(hash) => {
+            payload.push(TAG_DELETE_LEAF);
+            payload.extend_from_slice(hash.as_bytes());
+        }
+        Record::Root(hash) => {
+            payload.push(TAG_ROOT);
+            payload.extend_from_slice(hash.as_bytes());
+        }
+    }
+    payload
+}
+
+/// Decodes the record at the start of `bytes`, returning it with its encoded length.
+///
+/// Returns `None` for a truncated, corrupted or unknown record, which ends the replay.
+fn decode_record(bytes: &[u8]) -> Option<(Record, usize)> {
+    let len = u32::from_be_bytes(bytes.get(..4)?.try_into().ok()?) as usize;
+    let payload = bytes.get(4..4 + len)?;
+    let checksum = bytes.get(4 + len..4 + len + CHECKSUM_SIZE)?;
+    if Sha256::digest(payload)[..CHECKSUM_SIZE] != *checksum {
+        return None;
+    }
+
+    let (tag, body) = payload.split_first()?;
+    let hash_at = |offset: usize| -> Option<NodeHash> {
+        Some(NodeHash::new(to_array(
+            body.get(offset..offset + HASH_SIZE)?,
+        )))
+    };
+
+    let record = match *tag {
+        TAG_BRANCH => Record::Branch(BranchNode::decode(body).ok()?),
+        TAG_LEAF => Record::Leaf(LeafNode::decode(body).ok()?),
+        TAG_COMPRESSED_LEAF => {
+            LeafNode::<32>::decode(body).ok()?;
+            Record::CompressedLeaf(body.to_vec())
+        }
+        TAG_LEAF_REF if body.len() == 32 + SUM_SIZE + HASH_SIZE => Record::LeafRef {
+            key: to_array(&body[..32]),
+            sum: decode_sum(&body[32..32 + SUM_SIZE]),
+            value_hash: hash_at(32 + SUM_SIZE)?,
+        },
+        TAG_VALUE => Record::Value(hash_at(0)?, body[HASH_SIZE..].to_vec()),
+        TAG_COMPRESSED_VALUE => Record::CompressedValue(hash_at(0)?, body[HASH_SIZE..].to_vec()),
+        TAG_DELETE_BRANCH if body.len() == HASH_SIZE => Record::DeleteBranch(hash_at(0)?),
+        TAG_DELETE_LEAF if body.len() == HASH_SIZE => Record::DeleteLeaf(hash_at(0)?),
+        TAG_ROOT if body.len() == HASH_SIZE => Record::Root(hash_at(0)?),
+        _ => return None,
+    };
+    Some((record, 4 + len + CHECKSUM_SIZE))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::{DefaultStore, ValueCompressor};
+    use crate::tree::FullTree;
+
+    #[test]
+    fn test_recovers_last_committed_root() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("mssmt-log-{}.log", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let mut tree = FullTree::new(LogStore::open(&path)?);
+        tree.insert([1u8; 32], b"one".to_vec(), 1)?;
+        tree.insert([2u8; 32], b"two".to_vec(), 2)?;
+        tree.delete([1u8; 32])?;
+        let root_hash = tree.root()?.node_hash();
+
+        // Simulate a crash in the middle of an update: node writes without a commit, then a torn record
+        tree.store_mut()
+            .insert_leaf(Arc::new(LeafNode::new([3u8; 32], b"three".to_vec(), 3)))?;
+        tree.store_mut()
+            .writer
+            .write_all(&[0, 0, 0, 40, TAG_ROOT])?;
+        tree.store_mut().writer.flush()?;
+        let crashed_len = std::fs::metadata(&path)?.len();
+        drop(tree);
+
+        let mut tree = FullTree::new(LogStore::open(&path)?);
+        assert_eq!(tree.root()?.node_hash(), root_hash);
+        assert_eq!(tree.get([1u8; 32])?, None);
+        assert_eq!(tree.get([2u8; 32])?, Some((b"two".to_vec(), 2)));
+        assert!(std::fs::metadata(&path)?.len() < crashed_len);
+        let stats = tree.store().stats()?;
+        assert_eq!(stats.leaves, 1);
+        assert_eq!(stats.bytes_used, Some(std::fs::metadata(&path)?.len()));
+
+        // The recovered log accepts new commits
+        tree.insert([4u8; 32], b"four".to_vec(), 4)?;
+        let root_hash = tree.root()?.node_hash();
+        drop(tree);
+        let tree = FullTree::new(LogStore::open(&path)?);
+        assert_eq!(tree.root()?.node_hash(), root_hash);
+        assert_eq!(tree.total_sum()?, 6);
+
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+
+    /// Run-length encoding, standing in for a real compression algorithm.
+    struct RunLength;
+
+    impl ValueCompressor for RunLength {
+        fn compress(&self, value: &[u8]) -> Result<Vec<u8>> {
+            let mut compressed = Vec::new();
+            for run in value.chunk_by(|a, b| a == b) {
+                for chunk in run.chunks(u8::MAX as usize) {
+                    compressed.extend_from_slice(&[chunk.len() as u8, chunk[0]]);
+                }
+            }
+            Ok(compressed)
+        }
+
+        fn decompress(&self, compressed: &[u8]) -> Result<Vec<u8>> {
+            Ok(compressed
+                .chunks(2)
+                .flat_map(|run| std::iter::repeat_n(run[1], run[0] as usize))
+                .collect())
+        }
+    }
+
+    #[test]
+    fn test_compressed_values() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("mssmt-log-{}.zlog", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let compression = || ValueCompression::new(RunLength, 64);
+        let large = vec![b'a'; 4096];
+
+        let mut tree = FullTree::new(LogStore::open_with_compression(&path, compression())?);
+        tree.insert([1u8; 32], large.clone(), 1)?;
+        tree.insert([2u8; 32], b"below the threshold".to_vec(), 2)?;
+        let root_hash = tree.root()?.node_hash();
+        drop(tree);
+        let bytes = std::fs::read(&path)?;
+        assert!(!bytes
+            .windows(large.len())
+            .any(|window| window == large.as_slice()));
+
+        // Hashes cover the uncompressed values
+        let mut plain = FullTree::new(DefaultStore::new());
+        plain.insert([1u8; 32], large.clone(), 1)?;
+        plain.insert([2u8; 32], b"below the threshold".to_vec(), 2)?;
+        assert_eq!(plain.root()?.node_hash(), root_hash);
+
+        let tree = FullTree::new(LogStore::open_with_compression(&path, compression())?);
+        assert_eq!(tree.root()?.node_hash(), root_hash);
+        assert_eq!(tree.get([1u8; 32])?, Some((large, 1)));
+        drop(tree);
+        assert!(matches!(
+            LogStore::open(&path),
+            Err(MssmtError::InvalidEncoding(_))
+        ));
+
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_value_dedup() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("mssmt-log-{}.dlog", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let shared = vec![b's'; 1024];
+        let count_shared = |path: &Path| -> Result<usize> {
+            let bytes = std::fs::read(path)?;
+            Ok(bytes
+                .windows(shared.len())
+                .filter(|window| *window == shared.as_slice())
+                .count())
+        };
+
+        let mut tree = FullTree::new(LogStore::open(&path)?.with_value_dedup());
+        for i in 1..=3u8 {
+            tree.insert([i; 32], shared.clone(), i as Sum)?;
+        }
+        tree.insert([4u8; 32], b"unique".to_vec(), 4)?;
+        assert_eq!(count_shared(&path)?, 1);
+        assert_eq!(tree.store().shared_values(), 2);
+        tree.delete([1u8; 32])?;
+        let root_hash = tree.root()?.node_hash();
+        drop(tree);
+
+        // Any store replays the references, and keeps counting them
+        let mut tree = FullTree::new(LogStore::open(&path)?);
+        assert_eq!(tree.root()?.node_hash(), root_hash);
+        assert_eq!(tree.get([2u8; 32])?, Some((shared.clone(), 2)));
+        assert_eq!(tree.store().shared_values(), 2);
+        tree.delete([2u8; 32])?;
+        tree.delete([3u8; 32])?;
+        assert_eq!(tree.store().shared_values(), 1);
+        drop(tree);
+
+        // An unreferenced value is written again when it is shared anew
+        let mut tree = FullTree::new(LogStore::open(&path)?.with_value_dedup());
+        tree.insert([5u8; 32], shared.clone(), 5)?;
+        tree.insert([6u8; 32], shared.clone(), 6)?;
+        assert_eq!(count_shared(&path)?, 2);
+        drop(tree);
+        let tree = FullTree::new(LogStore::open(&path)?);
+        assert_eq!(tree.get([6u8; 32])?, Some((shared, 6)));
+        assert_eq!(tree.total_sum()?, 15);
+
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+}
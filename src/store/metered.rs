@@ -0,0 +1,137 @@
+//! A store decorator reporting node reads and writes to a `Metrics` sink.
+
+use crate::error::Result;
+use crate::metadata::LeafMetadata;
+use crate::metrics::Metrics;
+use crate::node::{BranchNode, LeafNode, Node, NodeHash};
+use crate::store::{StoreStats, TreeStoreReader, TreeStoreWriter};
+use std::sync::Arc;
+
+/// A `TreeStore` decorator counting the node reads, writes and deletes reaching the inner store.
+///
+/// Stack it directly above the backend to measure backend traffic, or above a `CachedStore` to measure
+/// the traffic generated by the tree.
+///
+/// # Type Parameters
+///
+/// - `S`: The wrapped storage backend.
+pub struct MeteredStore<S> {
+    inner: S,
+    metrics: Arc<dyn Metrics>,
+}
+
+impl<S> MeteredStore<S> {
+    /// Creates a new `MeteredStore` wrapping `inner` and reporting to `metrics`.
+    pub fn new(inner: S, metrics: Arc<dyn Metrics>) -> Self {
+        Self { inner, metrics }
+    }
+
+    /// Returns a reference to the wrapped store.
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// Consumes the `MeteredStore`, returning the wrapped store.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S: TreeStoreReader> TreeStoreReader for MeteredStore<S> {
+    fn root_node(&self) -> Result<Arc<dyn Node>> {
+        self.inner.root_node()
+    }
+
+    fn get_branch(&self, key: &NodeHash) -> Result<Option<Arc<BranchNode>>> {
+        let branch = self.inner.get_branch(key)?;
+        self.metrics.node_read(branch.is_some());
+        Ok(branch)
+    }
+
+    fn get_leaf(&self, key: &NodeHash) -> Result<Option<Arc<LeafNode>>> {
+        let leaf = self.inner.get_leaf(key)?;
+        self.metrics.node_read(leaf.is_some());
+        Ok(leaf)
+    }
+
+    fn get_leaf_by_key(&self, key: &[u8; 32]) -> Result<Option<Arc<LeafNode>>> {
+        let leaf = self.inner.get_leaf_by_key(key)?;
+        self.metrics.node_read(leaf.is_some());
+        Ok(leaf)
+    }
+
+    fn may_contain_key(&self, key: &[u8; 32]) -> Result<bool> {
+        self.inner.may_contain_key(key)
+    }
+
+    fn branch_hashes(&self) -> Result<Vec<NodeHash>> {
+        self.inner.branch_hashes()
+    }
+
+    fn leaf_hashes(&self) -> Result<Vec<NodeHash>> {
+        self.inner.leaf_hashes()
+    }
+
+    fn stats(&self) -> Result<StoreStats> {
+        self.inner.stats()
+    }
+
+    fn health_check(&self) -> Result<()> {
+        self.inner.health_check()
+    }
+
+    fn get_leaf_metadata(&self, key: &[u8; 32]) -> Result<Option<LeafMetadata>> {
+        self.inner.get_leaf_metadata(key)
+    }
+}
+
+impl<S: TreeStoreWriter> TreeStoreWriter for MeteredStore<S> {
+    fn insert_branch(&mut self, branch: Arc<BranchNode>) -> Result<()> {
+        self.inner.insert_branch(branch)?;
+        self.metrics.node_written();
+        Ok(())
+    }
+
+    fn insert_leaf(&mut self, leaf: Arc<LeafNode>) -> Result<()> {
+        self.inner.insert_leaf(leaf)?;
+        self.metrics.node_written();
+        Ok(())
+    }
+
+    fn insert_nodes(
+        &mut self,
+        branches: Vec<Arc<BranchNode>>,
+        leaves: Vec<Arc<LeafNode>>,
+    ) -> Result<()> {
+        let count = branches.len() + leaves.len();
+        self.inner.insert_nodes(branches, leaves)?;
+        for _ in 0..count {
+            self.metrics.node_written();
+        }
+        Ok(())
+    }
+
+    fn delete_branch(&mut self, key: &NodeHash) -> Result<()> {
+        self.inner.delete_branch(key)?;
+        self.metrics.node_deleted();
+        Ok(())
+    }
+
+    fn delete_leaf(&mut self, key: &NodeHash) -> Result<()> {
+        self.inner.delete_leaf(key)?;
+        self.metrics.node_deleted();
+        Ok(())
+    }
+
+    fn set_leaf_metadata(&mut self, key: &[u8; 32], metadata: Option<LeafMetadata>) -> Result<()> {
+        self.inner.set_leaf_metadata(key, metadata)
+    }
+
+    fn update_root(&mut self, root: Arc<dyn Node>) -> Result<()> {
+        self.inner.update_root(root)
+    }
+
+    fn compare_and_update_root(&mut self, expected: NodeHash, root: Arc<dyn Node>) -> Result<bool> {
+        self.inner.compare_and_update_root(expected, root)
+    }
+}
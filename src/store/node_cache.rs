@@ -0,0 +1,100 @@
+//! A cache of the branches decoded by remote stores, holding them by weak reference.
+
+use crate::node::{BranchNode, NodeHash};
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::sync::{Arc, Weak};
+
+/// The number of entries below which dead entries are not purged.
+const MIN_PURGE_LEN: usize = 1024;
+
+/// Branches recently constructed by a store, keyed by hash.
+///
+/// Stores that decode branches from the network construct a new node, and later hash it again, on every
+/// lookup. Tree operations running concurrently over the same hot paths, such as proof generation under
+/// load, look up the same branches repeatedly; the cache hands them the node that is already in use
+/// instead. Branches are held by weak reference, so the cache never keeps a node alive on its own and
+/// needs no capacity: a branch is served from it only while some traversal still holds it.
+///
+/// Branches are content-addressed, so a cached branch never goes stale. Stores still forget a branch
+/// when they delete it, so that lookups after a deletion miss as they would without the cache.
+pub(crate) struct WeakBranchCache {
+    branches: Mutex<HashMap<NodeHash, Weak<BranchNode>>>,
+    // The number of entries from which dead entries are purged on the next insertion
+    purge_len: Mutex<usize>,
+}
+
+impl WeakBranchCache {
+    pub(crate) fn new() -> Self {
+        Self {
+            branches: Mutex::new(HashMap::new()),
+            purge_len: Mutex::new(MIN_PURGE_LEN),
+        }
+    }
+
+    /// Returns the branch with hash `hash` if it is still alive.
+    pub(crate) fn get(&self, hash: &NodeHash) -> Option<Arc<BranchNode>> {
+        self.branches.lock().get(hash).and_then(Weak::upgrade)
+    }
+
+    /// Records `branch` under `hash`, and returns it.
+    pub(crate) fn insert(&self, hash: NodeHash, branch: Arc<BranchNode>) -> Arc<BranchNode> {
+        let mut branches = self.branches.lock();
+        branches.insert(hash, Arc::downgrade(&branch));
+
+        // Dead entries are purged once the map doubles, which amortizes the scan over the insertions
+        let mut purge_len = self.purge_len.lock();
+        if branches.len() >= *purge_len {
+            branches.retain(|_, branch| branch.strong_count() > 0);
+            *purge_len = (2 * branches.len()).max(MIN_PURGE_LEN);
+        }
+        branch
+    }
+
+    /// Forgets the branch with hash `hash`.
+    pub(crate) fn remove(&self, hash: &NodeHash) {
+        self.branches.lock().remove(hash);
+    }
+
+    /// Returns the number of entries, alive or not.
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.branches.lock().len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::{ComputedNode, Node, Sum};
+
+    fn branch(i: u8) -> Arc<BranchNode> {
+        Arc::new(BranchNode::new(
+            Arc::new(ComputedNode::new(NodeHash::new([i; 32]), i as Sum)),
+            Arc::new(ComputedNode::new(NodeHash::new([!i; 32]), 1)),
+        ))
+    }
+
+    #[test]
+    fn test_serves_live_branches_only() {
+        let cache = WeakBranchCache::new();
+        let live = branch(1);
+        let hash = live.node_hash();
+        cache.insert(hash, live.clone());
+        assert!(Arc::ptr_eq(&cache.get(&hash).unwrap(), &live));
+
+        cache.remove(&hash);
+        assert!(cache.get(&hash).is_none());
+        cache.insert(hash, live.clone());
+        drop(live);
+        assert!(cache.get(&hash).is_none());
+
+        // Dead entries do not accumulate
+        for i in 0..4 * MIN_PURGE_LEN {
+            let mut hash = [0u8; 32];
+            hash[..8].copy_from_slice(&(i as u64).to_be_bytes());
+            cache.insert(NodeHash::new(hash), branch(i as u8));
+        }
+        assert!(cache.len() <= MIN_PURGE_LEN);
+    }
+}
@@ -0,0 +1,304 @@
+//! A staging layer for speculative updates on top of another store.
+
+use crate::error::Result;
+use crate::node::{BranchNode, LeafNode, Node, NodeHash};
+use crate::store::{TreeStoreReader, TreeStoreWriter};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+/// A `TreeStore` that records writes in memory on top of a read-only base store.
+///
+/// Reads see the staged writes first and fall through to the base store. Nothing reaches the base
+/// store until `commit` is called, and `discard` drops the staged writes, so a tree over an
+/// `OverlayStore` can be updated speculatively, for example to find out the root a batch of updates
+/// would produce, without touching the canonical tree.
+///
+/// The base only needs to implement `TreeStoreReader`, so a shared reference to another tree's store
+/// works as a base for dry runs. Committing requires a writable base.
+///
+/// # Type Parameters
+///
+/// - `S`: The underlying storage backend.
+///
+/// # Examples
+///
+/// ```rust
+/// use mssmt::store::OverlayStore;
+/// use mssmt::{DefaultStore, FullTree, Node};
+///
+/// let mut tree = FullTree::new(DefaultStore::new());
+/// tree.insert([1u8; 32], b"one".to_vec(), 1).unwrap();
+/// let root_hash = tree.root().unwrap().node_hash();
+///
+/// // Stage an update on top of the canonical tree
+/// let mut staged = FullTree::new(OverlayStore::new(tree.store()));
+/// staged.insert([2u8; 32], b"two".to_vec(), 2).unwrap();
+/// assert_eq!(staged.root().unwrap().node_sum(), 3);
+///
+/// // The canonical tree is untouched
+/// assert_eq!(tree.root().unwrap().node_hash(), root_hash);
+/// ```
+pub struct OverlayStore<S> {
+    base: S,
+    // `None` marks a node deleted in the overlay
+    branches: HashMap<NodeHash, Option<Arc<BranchNode>>>,
+    leaves: HashMap<NodeHash, Option<Arc<LeafNode>>>,
+    root: Option<Arc<dyn Node>>,
+}
+
+impl<S> OverlayStore<S> {
+    /// Creates an empty overlay on top of `base`.
+    pub fn new(base: S) -> Self {
+        Self {
+            base,
+            branches: HashMap::new(),
+            leaves: HashMap::new(),
+            root: None,
+        }
+    }
+
+    /// Returns a reference to the base store.
+    pub fn base(&self) -> &S {
+        &self.base
+    }
+
+    /// Consumes the overlay, dropping any staged writes and returning the base store.
+    pub fn into_base(self) -> S {
+        self.base
+    }
+
+    /// Returns `true` if the overlay holds writes that have not been committed.
+    pub fn has_changes(&self) -> bool {
+        !self.branches.is_empty() || !self.leaves.is_empty() || self.root.is_some()
+    }
+
+    /// Drops all staged writes, so that reads see the base store again.
+    pub fn discard(&mut self) {
+        self.branches.clear();
+        self.leaves.clear();
+        self.root = None;
+    }
+}
+
+impl<S: TreeStoreWriter> OverlayStore<S> {
+    /// Applies all staged writes to the base store and clears the overlay.
+    ///
+    /// Nodes are written before the root, so the base store never references a node it does not hold.
+    /// If the base store fails part way, the overlay keeps its writes and `commit` can be retried.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use mssmt::store::OverlayStore;
+    /// use mssmt::{DefaultStore, FullTree};
+    ///
+    /// let mut staged = FullTree::new(OverlayStore::new(DefaultStore::new()));
+    /// staged.insert([1u8; 32], b"one".to_vec(), 1).unwrap();
+    ///
+    /// let mut overlay = staged.into_store();
+    /// overlay.commit().unwrap();
+    ///
+    /// let tree = FullTree::new(overlay.into_base());
+    /// assert_eq!(tree.get([1u8; 32]).unwrap(), Some((b"one".to_vec(), 1)));
+    /// ```
+    pub fn commit(&mut self) -> Result<()> {
+        self.write_staged_nodes()?;
+        if let Some(root) = &self.root {
+            self.base.update_root(root.clone())?;
+        }
+        self.delete_staged_nodes()?;
+
+        self.discard();
+        Ok(())
+    }
+
+    /// Applies all staged writes to the base store if its root is still `expected`, the root the
+    /// staged update started from, and clears the overlay.
+    ///
+    /// The staged nodes are written first, then the root is swapped with
+    /// `TreeStoreWriter::compare_and_update_root`. If another writer updated the base in between, the
+    /// swap fails and the staged deletions are not applied, since the new root may still reference the
+    /// deleted nodes. The overlay then keeps its writes, to be discarded before the update is retried
+    /// from the new root, and the nodes already written are left for compaction to remove.
+    ///
+    /// # Returns
+    ///
+    /// - `true` if the update was applied, `false` if the base root is no longer `expected`.
+    /// - `MssmtError::Unsupported` if the base store cannot compare and swap its root.
+    pub fn compare_and_commit(&mut self, expected: NodeHash) -> Result<bool> {
+        self.write_staged_nodes()?;
+        if let Some(root) = &self.root {
+            if !self.base.compare_and_update_root(expected, root.clone())? {
+                return Ok(false);
+            }
+        }
+        self.delete_staged_nodes()?;
+
+        self.discard();
+        Ok(true)
+    }
+
+    fn write_staged_nodes(&mut self) -> Result<()> {
+        for branch in self.branches.values().flatten() {
+            self.base.insert_branch(branch.clone())?;
+        }
+        for leaf in self.leaves.values().flatten() {
+            self.base.insert_leaf(leaf.clone())?;
+        }
+        Ok(())
+    }
+
+    fn delete_staged_nodes(&mut self) -> Result<()> {
+        for (hash, branch) in &self.branches {
+            if branch.is_none() {
+                self.base.delete_branch(hash)?;
+            }
+        }
+        for (hash, leaf) in &self.leaves {
+            if leaf.is_none() {
+                self.base.delete_leaf(hash)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<S: TreeStoreReader> TreeStoreReader for OverlayStore<S> {
+    fn root_node(&self) -> Result<Arc<dyn Node>> {
+        match &self.root {
+            Some(root) => Ok(root.clone()),
+            None => self.base.root_node(),
+        }
+    }
+
+    fn get_branch(&self, key: &NodeHash) -> Result<Option<Arc<BranchNode>>> {
+        match self.branches.get(key) {
+            Some(branch) => Ok(branch.clone()),
+            None => self.base.get_branch(key),
+        }
+    }
+
+    fn get_leaf(&self, key: &NodeHash) -> Result<Option<Arc<LeafNode>>> {
+        match self.leaves.get(key) {
+            Some(leaf) => Ok(leaf.clone()),
+            None => self.base.get_leaf(key),
+        }
+    }
+
+    fn get_leaf_by_key(&self, key: &[u8; 32]) -> Result<Option<Arc<LeafNode>>> {
+        // The key index of the base store is stale once leaves are staged, so fall back to a path walk
+        if self.leaves.is_empty() {
+            self.base.get_leaf_by_key(key)
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn may_contain_key(&self, key: &[u8; 32]) -> Result<bool> {
+        let staged = self.leaves.values().flatten().any(|leaf| &leaf.key == key);
+        Ok(staged || self.base.may_contain_key(key)?)
+    }
+
+    fn branch_hashes(&self) -> Result<Vec<NodeHash>> {
+        Ok(merge_hashes(self.base.branch_hashes()?, &self.branches))
+    }
+
+    fn leaf_hashes(&self) -> Result<Vec<NodeHash>> {
+        Ok(merge_hashes(self.base.leaf_hashes()?, &self.leaves))
+    }
+}
+
+impl<S> TreeStoreWriter for OverlayStore<S> {
+    fn insert_branch(&mut self, branch: Arc<BranchNode>) -> Result<()> {
+        self.branches.insert(branch.node_hash(), Some(branch));
+        Ok(())
+    }
+
+    fn insert_leaf(&mut self, leaf: Arc<LeafNode>) -> Result<()> {
+        self.leaves.insert(leaf.node_hash(), Some(leaf));
+        Ok(())
+    }
+
+    fn delete_branch(&mut self, key: &NodeHash) -> Result<()> {
+        self.branches.insert(*key, None);
+        Ok(())
+    }
+
+    fn delete_leaf(&mut self, key: &NodeHash) -> Result<()> {
+        self.leaves.insert(*key, None);
+        Ok(())
+    }
+
+    fn update_root(&mut self, root: Arc<dyn Node>) -> Result<()> {
+        self.root = Some(root);
+        Ok(())
+    }
+}
+
+/// Combines the hashes listed by the base store with the nodes staged in the overlay.
+fn merge_hashes<T>(base: Vec<NodeHash>, staged: &HashMap<NodeHash, Option<T>>) -> Vec<NodeHash> {
+    let mut hashes: HashSet<NodeHash> = base
+        .into_iter()
+        .filter(|hash| !matches!(staged.get(hash), Some(None)))
+        .collect();
+    hashes.extend(
+        staged
+            .iter()
+            .filter(|(_, node)| node.is_some())
+            .map(|(hash, _)| *hash),
+    );
+    hashes.into_iter().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::DefaultStore;
+    use crate::tree::FullTree;
+
+    #[test]
+    fn test_overlay_commit_and_discard() -> Result<()> {
+        let mut tree = FullTree::new(DefaultStore::new());
+        tree.insert([1u8; 32], b"one".to_vec(), 1)?;
+        tree.insert([2u8; 32], b"two".to_vec(), 2)?;
+        let base_root = tree.root()?.node_hash();
+
+        // The expected root after the staged updates
+        let mut expected = FullTree::new(DefaultStore::new());
+        expected.insert([2u8; 32], b"two".to_vec(), 2)?;
+        expected.insert([3u8; 32], b"three".to_vec(), 3)?;
+        let expected_root = expected.root()?.node_hash();
+
+        let mut staged = FullTree::new(OverlayStore::new(tree.into_store()));
+        staged.delete([1u8; 32])?;
+        staged.insert([3u8; 32], b"three".to_vec(), 3)?;
+        assert_eq!(staged.root()?.node_hash(), expected_root);
+        assert_eq!(staged.get([1u8; 32])?, None);
+        assert_eq!(staged.store().base().root_node()?.node_hash(), base_root);
+
+        // Discarding restores the base view
+        let mut overlay = staged.into_store();
+        assert!(overlay.has_changes());
+        overlay.discard();
+        let mut staged = FullTree::new(overlay);
+        assert_eq!(staged.root()?.node_hash(), base_root);
+        assert_eq!(staged.get([1u8; 32])?, Some((b"one".to_vec(), 1)));
+
+        // Committing writes the staged updates through to the base store
+        staged.delete([1u8; 32])?;
+        staged.insert([3u8; 32], b"three".to_vec(), 3)?;
+        let mut overlay = staged.into_store();
+        overlay.commit()?;
+        assert!(!overlay.has_changes());
+
+        let mut tree = FullTree::new(overlay.into_base());
+        assert_eq!(tree.root()?.node_hash(), expected_root);
+        assert_eq!(tree.get([1u8; 32])?, None);
+        assert_eq!(tree.get([3u8; 32])?, Some((b"three".to_vec(), 3)));
+        assert!(tree.verify_integrity()?.is_ok());
+        tree.compact()?;
+        assert_eq!(tree.store().leaves.len(), 2);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,445 @@
+//! A store persisting nodes in Redis.
+
+use crate::error::{MssmtError, Result};
+use crate::format::{check_version, Format, Migration};
+use crate::hash_utils::to_array;
+use crate::node::{BranchNode, EmptyTree, LeafNode, Node, NodeHash, EMPTY_TREE, HASH_SIZE};
+use crate::store::compression::decode_compressed_leaf;
+use crate::store::node_cache::WeakBranchCache;
+use crate::store::{StoreStats, TreeStoreReader, TreeStoreWriter, ValueCompression};
+use parking_lot::Mutex;
+use redis::{Connection, RedisError};
+use std::sync::Arc;
+
+/// A `TreeStore` keeping its nodes in a Redis server.
+///
+/// Branches and leaves live in two Redis hashes keyed by node hash, and the root hash in a separate
+/// key, all under a common namespace so that several trees can share one server. Nodes are stored in
+/// their shallow form, with branches referencing their children by hash and sum.
+///
+/// The root pointer is updated optimistically: `update_root` only succeeds if the root is still the
+/// one this store last read or wrote, checked atomically with `WATCH`/`MULTI`. When several instances
+/// update the same tree, the losing update fails with `MssmtError::Store` instead of silently
+/// overwriting the other one, and can be retried from the new root. The nodes written by a failed
+/// update are left behind for `FullTree::compact` to remove.
+///
+/// Decoded branches are shared by the lookups running while they are in use, through a cache holding
+/// them by weak reference, which spares concurrent traversals of the same paths a round trip per level.
+///
+/// With `with_compression`, large leaf values are compressed and kept in a third Redis hash, under
+/// `<namespace>:compressed_leaves`.
+///
+/// The format version of the namespace is kept under `<namespace>:version` and recorded with every root
+/// update. Namespaces written before it was recorded have version 0, and `RedisStore::migrate` upgrades
+/// them without waiting for an update.
+///
+/// This store requires the `redis` feature.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use mssmt::store::RedisStore;
+/// use mssmt::FullTree;
+///
+/// let store = RedisStore::open("redis://127.0.0.1/", "assets").unwrap();
+/// let mut tree = FullTree::new(store);
+/// tree.insert([1u8; 32], b"value".to_vec(), 10).unwrap();
+/// ```
+pub struct RedisStore {
+    connection: Mutex<Connection>,
+    branches_key: String,
+    leaves_key: String,
+    compressed_leaves_key: String,
+    root_key: String,
+    version_key: String,
+    // The root hash last read or written, which `update_root` expects to replace
+    observed_root: Mutex<Option<NodeHash>>,
+    compression: Option<ValueCompression>,
+    branch_cache: WeakBranchCache,
+}
+
+impl RedisStore {
+    /// Connects to the Redis server at `url` and opens the tree stored under `namespace`.
+    ///
+    /// A namespace without any data holds the empty tree.
+    ///
+    /// # Returns
+    ///
+    /// - The opened store.
+    /// - `MssmtError::InvalidEncoding` if the namespace has an unsupported format version.
+    /// - `MssmtError::Store` if the server cannot be reached.
+    pub fn open(url: &str, namespace: &str) -> Result<Self> {
+        let connection = redis::Client::open(url)
+            .and_then(|client| client.get_connection())
+            .map_err(redis_error)?;
+        let mut store = Self {
+            connection: Mutex::new(connection),
+            branches_key: format!("{}:branches", namespace),
+            leaves_key: format!("{}:leaves", namespace),
+            compressed_leaves_key: format!("{}:compressed_leaves", namespace),
+            root_key: format!("{}:root", namespace),
+            version_key: format!("{}:version", namespace),
+            observed_root: Mutex::new(None),
+            compression: None,
+            branch_cache: WeakBranchCache::new(),
+        };
+        check_version(Format::RedisStore, store.version()?)?;
+        Ok(store)
+    }
+
+    /// Compresses leaf values written from now on with `compression`.
+    ///
+    /// Namespaces written with compression must be opened with the same compression algorithm. Leaves
+    /// written before compression was enabled stay readable.
+    pub fn with_compression(mut self, compression: ValueCompression) -> Self {
+        self.compression = Some(compression);
+        self
+    }
+
+    /// Returns the format version of the namespace, 0 if it was never recorded.
+    pub fn version(&mut self) -> Result<u8> {
+        let version: Option<u8> = redis::cmd("GET")
+            .arg(&self.version_key)
+            .query(self.connection.get_mut())
+            .map_err(redis_error)?;
+        Ok(version.unwrap_or(0))
+    }
+
+    /// Upgrades the namespace to the current format version.
+    ///
+    /// Later versions only add to the layout of earlier ones, so this only records the version.
+    pub fn migrate(&mut self) -> Result<Migration> {
+        let from_version = self.version()?;
+        let to_version = Format::RedisStore.current_version();
+        check_version(Format::RedisStore, from_version)?;
+        if from_version < to_version {
+            redis::cmd("SET")
+                .arg(&self.version_key)
+                .arg(to_version)
+                .query::<()>(self.connection.get_mut())
+                .map_err(redis_error)?;
+        }
+        Ok(Migration {
+            format: Format::RedisStore,
+            from_version,
+            to_version,
+        })
+    }
+
+    fn get_root_hash(connection: &mut Connection, root_key: &str) -> Result<NodeHash> {
+        let hash: Option<Vec<u8>> = redis::cmd("GET")
+            .arg(root_key)
+            .query(connection)
+            .map_err(redis_error)?;
+        match hash {
+            Some(hash) => decode_hash(&hash),
+            None => Ok(EmptyTree::hash_at(0)),
+        }
+    }
+
+    fn hash_get(&self, key: &str, hash: &NodeHash) -> Result<Option<Vec<u8>>> {
+        redis::cmd("HGET")
+            .arg(key)
+            .arg(hash.as_bytes().as_slice())
+            .query(&mut *self.connection.lock())
+            .map_err(redis_error)
+    }
+
+    fn hash_keys(&self, key: &str) -> Result<Vec<NodeHash>> {
+        let hashes: Vec<Vec<u8>> = redis::cmd("HKEYS")
+            .arg(key)
+            .query(&mut *self.connection.lock())
+            .map_err(redis_error)?;
+        hashes.iter().map(|hash| decode_hash(hash)).collect()
+    }
+
+    fn hash_set(&mut self, key: &str, hash: &NodeHash, value: Vec<u8>) -> Result<()> {
+        redis::cmd("HSET")
+            .arg(key)
+            .arg(hash.as_bytes().as_slice())
+            .arg(value)
+            .query(self.connection.get_mut())
+            .map_err(redis_error)
+    }
+
+    /// Returns the Redis hash a leaf is kept in, and its encoding there.
+    fn encode_leaf(&self, leaf: &LeafNode) -> Result<(&str, Vec<u8>)> {
+        let compressed = match &self.compression {
+            Some(compression) => compression.encode_leaf(leaf)?,
+            None => None,
+        };
+        Ok(match compressed {
+            Some(bytes) => (&self.compressed_leaves_key, bytes),
+            None => (&self.leaves_key, leaf.encode()),
+        })
+    }
+
+    fn hash_delete(&mut self, key: &str, hash: &NodeHash) -> Result<()> {
+        redis::cmd("HDEL")
+            .arg(key)
+            .arg(hash.as_bytes().as_slice())
+            .query(self.connection.get_mut())
+            .map_err(redis_error)
+    }
+}
+
+impl TreeStoreReader for RedisStore {
+    fn root_node(&self) -> Result<Arc<dyn Node>> {
+        let root_hash = Self::get_root_hash(&mut self.connection.lock(), &self.root_key)?;
+        *self.observed_root.lock() = Some(root_hash);
+        if root_hash == EmptyTree::hash_at(0) {
+            return Ok(EMPTY_TREE[0].clone());
+        }
+        match self.get_branch(&root_hash)? {
+            Some(root) => Ok(root),
+            None => Err(MssmtError::NodeNotFound(root_hash)),
+        }
+    }
+
+    fn get_branch(&self, key: &NodeHash) -> Result<Option<Arc<BranchNode>>> {
+        if let Some(branch) = self.branch_cache.get(key) {
+            return Ok(Some(branch));
+        }
+        match self.hash_get(&self.branches_key, key)? {
+            Some(bytes) => {
+                let branch = BranchNode::decode(&bytes).map_err(|_| {
+                    MssmtError::InvalidEncoding(format!("malformed branch {}", key))
+                })?;
+                Ok(Some(self.branch_cache.insert(*key, Arc::new(branch))))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn get_leaf(&self, key: &NodeHash) -> Result<Option<Arc<LeafNode>>> {
+        if let Some(bytes) = self.hash_get(&self.leaves_key, key)? {
+            return Ok(Some(Arc::new(LeafNode::decode(&bytes).map_err(|_| {
+                MssmtError::InvalidEncoding(format!("malformed leaf {}", key))
+            })?)));
+        }
+        match self.hash_get(&self.compressed_leaves_key, key)? {
+            Some(bytes) => Ok(Some(Arc::new(decode_compressed_leaf(
+                self.compression.as_ref(),
+                &bytes,
+            )?))),
+            None => Ok(None),
+        }
+    }
+
+    fn branch_hashes(&self) -> Result<Vec<NodeHash>> {
+        self.hash_keys(&self.branches_key)
+    }
+
+    fn leaf_hashes(&self) -> Result<Vec<NodeHash>> {
+        let mut hashes = self.hash_keys(&self.leaves_key)?;
+        hashes.extend(self.hash_keys(&self.compressed_leaves_key)?);
+        Ok(hashes)
+    }
+
+    /// Counts the nodes with `HLEN`, and reports the memory the node hashes take up in the server as
+    /// estimated by `MEMORY USAGE`.
+    fn stats(&self) -> Result<StoreStats> {
+        let mut connection = self.connection.lock();
+        let mut count = |key: &str| -> Result<(usize, u64)> {
+            let len: usize = redis::cmd("HLEN")
+                .arg(key)
+                .query(&mut *connection)
+                .map_err(redis_error)?;
+            let bytes: Option<u64> = redis::cmd("MEMORY")
+                .arg("USAGE")
+                .arg(key)
+                .query(&mut *connection)
+                .map_err(redis_error)?;
+            Ok((len, bytes.unwrap_or(0)))
+        };
+        let (branches, branch_bytes) = count(&self.branches_key)?;
+        let (leaves, leaf_bytes) = count(&self.leaves_key)?;
+        let (compressed_leaves, compressed_bytes) = count(&self.compressed_leaves_key)?;
+        Ok(StoreStats {
+            branches,
+            leaves: leaves + compressed_leaves,
+            bytes_used: Some(branch_bytes + leaf_bytes + compressed_bytes),
+        })
+    }
+}
+
+impl TreeStoreWriter for RedisStore {
+    fn insert_branch(&mut self, branch: Arc<BranchNode>) -> Result<()> {
+        let key = self.branches_key.clone();
+        self.hash_set(&key, &branch.node_hash(), branch.encode())
+    }
+
+    fn insert_leaf(&mut self, leaf: Arc<LeafNode>) -> Result<()> {
+        let (key, bytes) = self.encode_leaf(&leaf)?;
+        let key = key.to_string();
+        self.hash_set(&key, &leaf.node_hash(), bytes)
+    }
+
+    /// Writes the batch in a single pipeline, one round trip instead of one per node.
+    fn insert_nodes(
+        &mut self,
+        branches: Vec<Arc<BranchNode>>,
+        leaves: Vec<Arc<LeafNode>>,
+    ) -> Result<()> {
+        let mut pipeline = redis::pipe();
+        for leaf in &leaves {
+            let (key, bytes) = self.encode_leaf(leaf)?;
+            pipeline
+                .cmd("HSET")
+                .arg(key)
+                .arg(leaf.node_hash().as_bytes().as_slice())
+                .arg(bytes)
+                .ignore();
+        }
+        for branch in &branches {
+            pipeline
+                .cmd("HSET")
+                .arg(&self.branches_key)
+                .arg(branch.node_hash().as_bytes().as_slice())
+                .arg(branch.encode())
+                .ignore();
+        }
+        pipeline
+            .query::<()>(self.connection.get_mut())
+            .map_err(redis_error)
+    }
+
+    fn delete_branch(&mut self, key: &NodeHash) -> Result<()> {
+        let branches_key = self.branches_key.clone();
+        self.branch_cache.remove(key);
+        self.hash_delete(&branches_key, key)
+    }
+
+    fn delete_leaf(&mut self, key: &NodeHash) -> Result<()> {
+        let leaves_key = self.leaves_key.clone();
+        let compressed_leaves_key = self.compressed_leaves_key.clone();
+        self.hash_delete(&leaves_key, key)?;
+        self.hash_delete(&compressed_leaves_key, key)
+    }
+
+    fn update_root(&mut self, root: Arc<dyn Node>) -> Result<()> {
+        let observed = *self.observed_root.get_mut();
+        if !self.swap_root(observed, root.node_hash())? {
+            return Err(concurrent_update());
+        }
+        Ok(())
+    }
+
+    fn compare_and_update_root(&mut self, expected: NodeHash, root: Arc<dyn Node>) -> Result<bool> {
+        self.swap_root(Some(expected), root.node_hash())
+    }
+}
+
+impl RedisStore {
+    /// Sets the root hash to `new_root` if it is `expected`, or unconditionally if `expected` is `None`.
+    ///
+    /// Returns `false` if the root was not `expected`, or was changed by another client while it was
+    /// being replaced.
+    fn swap_root(&mut self, expected: Option<NodeHash>, new_root: NodeHash) -> Result<bool> {
+        let connection = self.connection.get_mut();
+
+        // Watching the root makes the transaction below abort if another client changes it
+        redis::cmd("WATCH")
+            .arg(&self.root_key)
+            .query::<()>(connection)
+            .map_err(redis_error)?;
+        let current = Self::get_root_hash(connection, &self.root_key)?;
+        if expected.is_some_and(|expected| expected != current) {
+            redis::cmd("UNWATCH")
+                .query::<()>(connection)
+                .map_err(redis_error)?;
+            return Ok(false);
+        }
+
+        let committed: Option<()> = redis::pipe()
+            .atomic()
+            .cmd("SET")
+            .arg(&self.root_key)
+            .arg(new_root.as_bytes().as_slice())
+            .ignore()
+            .cmd("SET")
+            .arg(&self.version_key)
+            .arg(Format::RedisStore.current_version())
+            .ignore()
+            .query(connection)
+            .map_err(redis_error)?;
+        if committed.is_none() {
+            return Ok(false);
+        }
+
+        *self.observed_root.get_mut() = Some(new_root);
+        Ok(true)
+    }
+}
+
+fn decode_hash(bytes: &[u8]) -> Result<NodeHash> {
+    if bytes.len() != HASH_SIZE {
+        return Err(MssmtError::InvalidEncoding(format!(
+            "expected a {}-byte hash, got {} bytes",
+            HASH_SIZE,
+            bytes.len()
+        )));
+    }
+    Ok(NodeHash::new(to_array(bytes)))
+}
+
+fn redis_error(err: RedisError) -> MssmtError {
+    MssmtError::Store(err.to_string())
+}
+
+fn concurrent_update() -> MssmtError {
+    MssmtError::Store("the root was updated concurrently".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::Sum;
+    use crate::store::DefaultStore;
+    use crate::tree::FullTree;
+
+    /// Runs against the server named by `MSSMT_REDIS_URL`, and is skipped when it is not set.
+    #[test]
+    fn test_redis_store_round_trip() -> Result<()> {
+        let Ok(url) = std::env::var("MSSMT_REDIS_URL") else {
+            return Ok(());
+        };
+        let namespace = format!("mssmt-test-{}", std::process::id());
+
+        let mut tree = FullTree::new(RedisStore::open(&url, &namespace)?);
+        let mut local = FullTree::new(DefaultStore::new());
+        for i in 0..4u8 {
+            tree.insert([i; 32], vec![i], i as Sum)?;
+            local.insert([i; 32], vec![i], i as Sum)?;
+        }
+        tree.delete([1u8; 32])?;
+        local.delete([1u8; 32])?;
+        assert_eq!(tree.root()?.node_hash(), local.root()?.node_hash());
+
+        // A second instance sees the tree, and a stale instance cannot overwrite its update
+        let mut other = FullTree::new(RedisStore::open(&url, &namespace)?);
+        assert_eq!(other.get([3u8; 32])?, Some((vec![3], 3)));
+        other.insert([9u8; 32], vec![9], 9)?;
+        assert_eq!(
+            other.store_mut().version()?,
+            Format::RedisStore.current_version()
+        );
+        assert!(other.store_mut().migrate()?.is_noop());
+        assert!(matches!(
+            tree.store_mut().update_root(local.root()?),
+            Err(MssmtError::Store(_))
+        ));
+        assert_eq!(tree.get([9u8; 32])?, Some((vec![9], 9)));
+
+        // Compare-and-update only replaces the root it expects
+        let current = tree.root()?.node_hash();
+        let previous = local.root()?;
+        let store = tree.store_mut();
+        assert!(!store.compare_and_update_root(previous.node_hash(), previous.clone())?);
+        assert!(store.compare_and_update_root(current, previous.clone())?);
+        assert_eq!(tree.root()?.node_hash(), previous.node_hash());
+
+        tree.clear()?;
+        Ok(())
+    }
+}
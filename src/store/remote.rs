@@ -0,0 +1,514 @@
+//! A gRPC client and server for sharing one store between processes.
+//!
+//! `StoreServer` exposes any local `TreeStore` as a gRPC service, and `RemoteStore` implements the store
+//! traits on top of a connection to such a service. Several stateless frontends, each running its own
+//! `FullTree` over a `RemoteStore`, can then share one authoritative backend. The protocol is defined in
+//! `proto/store.proto`; nodes are exchanged in their shallow form, with branches referencing their
+//! children by hash and sum.
+//!
+//! This module requires the `grpc` feature.
+
+use crate::error::{MssmtError, Result};
+use crate::hash_utils::to_array;
+use crate::node::{BranchNode, EmptyTree, LeafNode, Node, NodeHash, EMPTY_TREE, HASH_SIZE};
+use crate::store::node_cache::WeakBranchCache;
+use crate::store::{TreeStore, TreeStoreReader, TreeStoreWriter};
+use parking_lot::RwLock;
+use std::sync::Arc;
+use tokio::runtime::Runtime;
+use tonic::transport::Channel;
+use tonic::{Code, Request, Response, Status};
+
+/// The generated protocol messages and service definitions.
+pub mod proto {
+    tonic::include_proto!("mssmt.store.v1");
+}
+
+use proto::tree_store_service_client::TreeStoreServiceClient;
+use proto::tree_store_service_server::{TreeStoreService, TreeStoreServiceServer};
+
+/// A `TreeStore` backed by a remote `StoreServer`.
+///
+/// Each store call is a blocking round trip, run on a runtime owned by the client. The store traits are
+/// synchronous, so a `RemoteStore` must not be used from within an asynchronous task; wrap tree
+/// operations in `spawn_blocking` instead. Branches still in use by another traversal are served without
+/// a round trip, and stacking a `CachedStore` on top avoids fetching hot nodes repeatedly.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use mssmt::store::RemoteStore;
+/// use mssmt::FullTree;
+///
+/// let store = RemoteStore::connect("http://127.0.0.1:50051").unwrap();
+/// let mut tree = FullTree::new(store);
+/// tree.insert([1u8; 32], b"value".to_vec(), 10).unwrap();
+/// ```
+pub struct RemoteStore {
+    client: TreeStoreServiceClient<Channel>,
+    runtime: Runtime,
+    branch_cache: WeakBranchCache,
+}
+
+impl RemoteStore {
+    /// Connects to the `StoreServer` listening at `endpoint`, such as `http://127.0.0.1:50051`.
+    ///
+    /// # Returns
+    ///
+    /// - The connected store.
+    /// - `MssmtError::Store` if the connection cannot be established.
+    pub fn connect(endpoint: impl Into<String>) -> Result<Self> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        let client = runtime
+            .block_on(TreeStoreServiceClient::connect(endpoint.into()))
+            .map_err(|err| MssmtError::Store(err.to_string()))?;
+        Ok(Self {
+            client,
+            runtime,
+            branch_cache: WeakBranchCache::new(),
+        })
+    }
+}
+
+/// Runs a call on the client, mapping the status of a failed call to an error.
+macro_rules! call {
+    ($store:expr, $method:ident, $request:expr) => {{
+        let mut client = $store.client.clone();
+        $store
+            .runtime
+            .block_on(client.$method($request))
+            .map(Response::into_inner)
+            .map_err(|status| status_error(stringify!($method), status))
+    }};
+}
+
+impl TreeStoreReader for RemoteStore {
+    fn root_node(&self) -> Result<Arc<dyn Node>> {
+        let root = call!(self, get_root, proto::Empty {})?;
+        let root = decode_branch(&root)?;
+        if root.node_hash() == EmptyTree::hash_at(0) {
+            return Ok(EMPTY_TREE[0].clone());
+        }
+        Ok(Arc::new(root))
+    }
+
+    fn get_branch(&self, key: &NodeHash) -> Result<Option<Arc<BranchNode>>> {
+        if let Some(branch) = self.branch_cache.get(key) {
+            return Ok(Some(branch));
+        }
+        let response = call!(self, get_branch, encode_hash(key.as_bytes()))?;
+        match response.branch {
+            Some(branch) => Ok(Some(
+                self.branch_cache
+                    .insert(*key, Arc::new(decode_branch(&branch)?)),
+            )),
+            None => Ok(None),
+        }
+    }
+
+    fn get_leaf(&self, key: &NodeHash) -> Result<Option<Arc<LeafNode>>> {
+        let response = call!(self, get_leaf, encode_hash(key.as_bytes()))?;
+        match response.leaf {
+            Some(leaf) => Ok(Some(Arc::new(decode_leaf(leaf)?))),
+            None => Ok(None),
+        }
+    }
+
+    fn get_leaf_by_key(&self, key: &[u8; 32]) -> Result<Option<Arc<LeafNode>>> {
+        let response = call!(self, get_leaf_by_key, encode_hash(key))?;
+        match response.leaf {
+            Some(leaf) => Ok(Some(Arc::new(decode_leaf(leaf)?))),
+            None => Ok(None),
+        }
+    }
+
+    fn branch_hashes(&self) -> Result<Vec<NodeHash>> {
+        let response = call!(self, branch_hashes, proto::Empty {})?;
+        decode_hash_list(response)
+    }
+
+    fn leaf_hashes(&self) -> Result<Vec<NodeHash>> {
+        let response = call!(self, leaf_hashes, proto::Empty {})?;
+        decode_hash_list(response)
+    }
+}
+
+impl TreeStoreWriter for RemoteStore {
+    fn insert_branch(&mut self, branch: Arc<BranchNode>) -> Result<()> {
+        call!(self, insert_branch, encode_branch(&branch))?;
+        Ok(())
+    }
+
+    fn insert_leaf(&mut self, leaf: Arc<LeafNode>) -> Result<()> {
+        call!(self, insert_leaf, encode_leaf(&leaf))?;
+        Ok(())
+    }
+
+    fn delete_branch(&mut self, key: &NodeHash) -> Result<()> {
+        self.branch_cache.remove(key);
+        call!(self, delete_branch, encode_hash(key.as_bytes()))?;
+        Ok(())
+    }
+
+    fn delete_leaf(&mut self, key: &NodeHash) -> Result<()> {
+        call!(self, delete_leaf, encode_hash(key.as_bytes()))?;
+        Ok(())
+    }
+
+    fn update_root(&mut self, root: Arc<dyn Node>) -> Result<()> {
+        let root = root
+            .as_branch()
+            .ok_or_else(|| MssmtError::Store("root is not a branch".to_string()))?;
+        call!(self, update_root, encode_branch(root))?;
+        Ok(())
+    }
+
+    fn compare_and_update_root(&mut self, expected: NodeHash, root: Arc<dyn Node>) -> Result<bool> {
+        let root = root
+            .as_branch()
+            .ok_or_else(|| MssmtError::Store("root is not a branch".to_string()))?;
+        let request = proto::CompareAndUpdateRootRequest {
+            expected: expected.as_bytes().to_vec(),
+            root: Some(encode_branch(root)),
+        };
+        Ok(call!(self, compare_and_update_root, request)?.updated)
+    }
+}
+
+/// A gRPC service exposing a local store to `RemoteStore` clients.
+///
+/// Reads run concurrently, while writes are serialized by a lock around the store.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use mssmt::store::StoreServer;
+/// use mssmt::DefaultStore;
+///
+/// # async fn serve() -> Result<(), Box<dyn std::error::Error>> {
+/// tonic::transport::Server::builder()
+///     .add_service(StoreServer::new(DefaultStore::new()).into_service())
+///     .serve("127.0.0.1:50051".parse()?)
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct StoreServer<S> {
+    store: Arc<RwLock<S>>,
+}
+
+impl<S: TreeStore + Send + Sync + 'static> StoreServer<S> {
+    /// Creates a server for `store`.
+    pub fn new(store: S) -> Self {
+        Self::from_shared(Arc::new(RwLock::new(store)))
+    }
+
+    /// Creates a server for a store that is also accessed locally.
+    pub fn from_shared(store: Arc<RwLock<S>>) -> Self {
+        Self { store }
+    }
+
+    /// Returns the tonic service to register with a `tonic::transport::Server`.
+    pub fn into_service(self) -> TreeStoreServiceServer<Self> {
+        TreeStoreServiceServer::new(self)
+    }
+}
+
+type ServiceResult<T> = std::result::Result<Response<T>, Status>;
+
+#[tonic::async_trait]
+impl<S: TreeStore + Send + Sync + 'static> TreeStoreService for StoreServer<S> {
+    async fn get_root(&self, _request: Request<proto::Empty>) -> ServiceResult<proto::Branch> {
+        let root = self.store.read().root_node().map_err(error_status)?;
+        let root = root
+            .as_branch()
+            .ok_or_else(|| Status::internal("root is not a branch"))?;
+        Ok(Response::new(encode_branch(root)))
+    }
+
+    async fn get_branch(
+        &self,
+        request: Request<proto::Hash>,
+    ) -> ServiceResult<proto::GetBranchResponse> {
+        let hash = request_hash(&request).map_err(invalid_argument)?;
+        let branch = self
+            .store
+            .read()
+            .get_branch(&NodeHash::new(hash))
+            .map_err(error_status)?;
+        Ok(Response::new(proto::GetBranchResponse {
+            branch: branch.map(|branch| encode_branch(&branch)),
+        }))
+    }
+
+    async fn get_leaf(
+        &self,
+        request: Request<proto::Hash>,
+    ) -> ServiceResult<proto::GetLeafResponse> {
+        let hash = request_hash(&request).map_err(invalid_argument)?;
+        let leaf = self
+            .store
+            .read()
+            .get_leaf(&NodeHash::new(hash))
+            .map_err(error_status)?;
+        Ok(Response::new(proto::GetLeafResponse {
+            leaf: leaf.map(|leaf| encode_leaf(&leaf)),
+        }))
+    }
+
+    async fn get_leaf_by_key(
+        &self,
+        request: Request<proto::Hash>,
+    ) -> ServiceResult<proto::GetLeafResponse> {
+        let key = request_hash(&request).map_err(invalid_argument)?;
+        let leaf = self
+            .store
+            .read()
+            .get_leaf_by_key(&key)
+            .map_err(error_status)?;
+        Ok(Response::new(proto::GetLeafResponse {
+            leaf: leaf.map(|leaf| encode_leaf(&leaf)),
+        }))
+    }
+
+    async fn branch_hashes(
+        &self,
+        _request: Request<proto::Empty>,
+    ) -> ServiceResult<proto::HashList> {
+        let hashes = self.store.read().branch_hashes().map_err(error_status)?;
+        Ok(Response::new(encode_hash_list(&hashes)))
+    }
+
+    async fn leaf_hashes(&self, _request: Request<proto::Empty>) -> ServiceResult<proto::HashList> {
+        let hashes = self.store.read().leaf_hashes().map_err(error_status)?;
+        Ok(Response::new(encode_hash_list(&hashes)))
+    }
+
+    async fn insert_branch(&self, request: Request<proto::Branch>) -> ServiceResult<proto::Empty> {
+        let branch = decode_branch(request.get_ref()).map_err(invalid_argument)?;
+        self.store
+            .write()
+            .insert_branch(Arc::new(branch))
+            .map_err(error_status)?;
+        Ok(Response::new(proto::Empty {}))
+    }
+
+    async fn insert_leaf(&self, request: Request<proto::Leaf>) -> ServiceResult<proto::Empty> {
+        let leaf = decode_leaf(request.into_inner()).map_err(invalid_argument)?;
+        self.store
+            .write()
+            .insert_leaf(Arc::new(leaf))
+            .map_err(error_status)?;
+        Ok(Response::new(proto::Empty {}))
+    }
+
+    async fn delete_branch(&self, request: Request<proto::Hash>) -> ServiceResult<proto::Empty> {
+        let hash = request_hash(&request).map_err(invalid_argument)?;
+        self.store
+            .write()
+            .delete_branch(&NodeHash::new(hash))
+            .map_err(error_status)?;
+        Ok(Response::new(proto::Empty {}))
+    }
+
+    async fn delete_leaf(&self, request: Request<proto::Hash>) -> ServiceResult<proto::Empty> {
+        let hash = request_hash(&request).map_err(invalid_argument)?;
+        self.store
+            .write()
+            .delete_leaf(&NodeHash::new(hash))
+            .map_err(error_status)?;
+        Ok(Response::new(proto::Empty {}))
+    }
+
+    async fn update_root(&self, request: Request<proto::Branch>) -> ServiceResult<proto::Empty> {
+        let root = decode_root(request.get_ref()).map_err(invalid_argument)?;
+        self.store.write().update_root(root).map_err(error_status)?;
+        Ok(Response::new(proto::Empty {}))
+    }
+
+    async fn compare_and_update_root(
+        &self,
+        request: Request<proto::CompareAndUpdateRootRequest>,
+    ) -> ServiceResult<proto::CompareAndUpdateRootResponse> {
+        let request = request.get_ref();
+        let expected = decode_hash(&request.expected).map_err(invalid_argument)?;
+        let root = request
+            .root
+            .as_ref()
+            .ok_or_else(|| Status::invalid_argument("missing root"))?;
+        let root = decode_root(root).map_err(invalid_argument)?;
+        let updated = self
+            .store
+            .write()
+            .compare_and_update_root(NodeHash::new(expected), root)
+            .map_err(error_status)?;
+        Ok(Response::new(proto::CompareAndUpdateRootResponse {
+            updated,
+        }))
+    }
+}
+
+fn encode_hash(hash: &[u8; HASH_SIZE]) -> proto::Hash {
+    proto::Hash {
+        hash: hash.to_vec(),
+    }
+}
+
+pub(crate) fn decode_hash(bytes: &[u8]) -> Result<[u8; HASH_SIZE]> {
+    if bytes.len() != HASH_SIZE {
+        return Err(MssmtError::InvalidEncoding(format!(
+            "expected a {}-byte hash, got {} bytes",
+            HASH_SIZE,
+            bytes.len()
+        )));
+    }
+    Ok(to_array(bytes))
+}
+
+fn encode_hash_list(hashes: &[NodeHash]) -> proto::HashList {
+    proto::HashList {
+        hashes: hashes.iter().map(|hash| hash.as_bytes().to_vec()).collect(),
+    }
+}
+
+fn decode_hash_list(list: proto::HashList) -> Result<Vec<NodeHash>> {
+    list.hashes
+        .iter()
+        .map(|hash| decode_hash(hash).map(NodeHash::new))
+        .collect()
+}
+
+fn encode_branch(branch: &BranchNode) -> proto::Branch {
+    proto::Branch {
+        left_hash: branch.left.node_hash().as_bytes().to_vec(),
+        left_sum: branch.left.node_sum(),
+        right_hash: branch.right.node_hash().as_bytes().to_vec(),
+        right_sum: branch.right.node_sum(),
+    }
+}
+
+fn decode_branch(branch: &proto::Branch) -> Result<BranchNode> {
+    let left = NodeHash::new(decode_hash(&branch.left_hash)?);
+    let right = NodeHash::new(decode_hash(&branch.right_hash)?);
+    if branch.left_sum.checked_add(branch.right_sum).is_none() {
+        return Err(MssmtError::SumOverflow);
+    }
+    Ok(BranchNode::from_child_refs(
+        (left, branch.left_sum),
+        (right, branch.right_sum),
+    ))
+}
+
+/// Decodes a root, which is the shared empty tree root when it has the hash of the empty tree.
+fn decode_root(branch: &proto::Branch) -> Result<Arc<dyn Node>> {
+    let root = decode_branch(branch)?;
+    if root.node_hash() == EmptyTree::hash_at(0) {
+        Ok(EMPTY_TREE[0].clone())
+    } else {
+        Ok(Arc::new(root))
+    }
+}
+
+fn encode_leaf(leaf: &LeafNode) -> proto::Leaf {
+    proto::Leaf {
+        key: leaf.key.to_vec(),
+        value: leaf.value.clone(),
+        sum: leaf.sum,
+    }
+}
+
+fn decode_leaf(leaf: proto::Leaf) -> Result<LeafNode> {
+    Ok(LeafNode::new(decode_hash(&leaf.key)?, leaf.value, leaf.sum))
+}
+
+fn request_hash(request: &Request<proto::Hash>) -> Result<[u8; HASH_SIZE]> {
+    decode_hash(&request.get_ref().hash)
+}
+
+/// Maps a store error to the status returned to the client.
+pub(crate) fn error_status(err: MssmtError) -> Status {
+    match err {
+        MssmtError::Unsupported(method) => Status::unimplemented(method),
+        err => Status::internal(err.to_string()),
+    }
+}
+
+pub(crate) fn invalid_argument(err: MssmtError) -> Status {
+    Status::invalid_argument(err.to_string())
+}
+
+/// Maps the status of a failed call back to a store error.
+pub(crate) fn status_error(method: &'static str, status: Status) -> MssmtError {
+    match status.code() {
+        Code::Unimplemented => MssmtError::Unsupported(method),
+        code => MssmtError::Store(format!(
+            "{} failed ({:?}): {}",
+            method,
+            code,
+            status.message()
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::Sum;
+    use crate::store::DefaultStore;
+    use crate::tree::FullTree;
+    use tonic::transport::server::TcpIncoming;
+
+    #[test]
+    fn test_remote_store_round_trip() -> Result<()> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()?;
+        let listener = runtime.block_on(tokio::net::TcpListener::bind("127.0.0.1:0"))?;
+        let endpoint = format!("http://{}", listener.local_addr()?);
+        let incoming = TcpIncoming::from_listener(listener, true, None)
+            .map_err(|err| MssmtError::Store(err.to_string()))?;
+        runtime.spawn(
+            tonic::transport::Server::builder()
+                .add_service(StoreServer::new(DefaultStore::new()).into_service())
+                .serve_with_incoming(incoming),
+        );
+
+        // Two frontends share the backend
+        let mut writer = FullTree::new(RemoteStore::connect(endpoint.clone())?);
+        let reader = FullTree::new(RemoteStore::connect(endpoint)?);
+        assert!(reader.is_empty()?);
+
+        let mut local = FullTree::new(DefaultStore::new());
+        for i in 0..4u8 {
+            writer.insert([i; 32], vec![i], i as Sum)?;
+            local.insert([i; 32], vec![i], i as Sum)?;
+        }
+        writer.delete([2u8; 32])?;
+        local.delete([2u8; 32])?;
+
+        let root_hash = local.root()?.node_hash();
+        assert_eq!(reader.root()?.node_hash(), root_hash);
+        assert_eq!(reader.get([3u8; 32])?, Some((vec![3], 3)));
+        assert_eq!(reader.get([2u8; 32])?, None);
+
+        let leaf = LeafNode::new([1u8; 32], vec![1], 1);
+        assert!(reader
+            .merkle_proof([1u8; 32])?
+            .verify([1u8; 32], &leaf, root_hash));
+        assert!(reader.verify_integrity()?.is_ok());
+
+        // Root swaps expecting a stale root are refused
+        let root = writer.root()?;
+        let store = writer.store_mut();
+        assert!(!store.compare_and_update_root(EmptyTree::hash_at(0), root.clone())?);
+        assert!(store.compare_and_update_root(root_hash, root)?);
+
+        writer.clear()?;
+        assert!(reader.is_empty()?);
+
+        Ok(())
+    }
+}
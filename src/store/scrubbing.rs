@@ -0,0 +1,245 @@
+//! A store decorator wiping the values of deleted leaves.
+
+use crate::error::Result;
+use crate::metadata::LeafMetadata;
+use crate::node::{BranchNode, LeafNode, LeafValue, Node, NodeHash, HASH_SIZE};
+use crate::store::{StoreStats, TreeStore, TreeStoreReader, TreeStoreWriter};
+use parking_lot::Mutex;
+use std::sync::Arc;
+use zeroize::Zeroize;
+
+/// A `TreeStore` decorator wiping the leaves deleted from the inner store, for trees committing to
+/// secret-bearing values.
+///
+/// Deleting a leaf from an in-memory store only drops the store's reference to it, leaving the value in
+/// memory until the allocator reuses it. `ScrubbingStore` takes the leaf out of the inner store and wipes
+/// its key, value and sum with `Zeroize` as soon as no one else holds it. Leaves still referenced, by a
+/// caller or by the superseded branches that in-memory stores keep until the tree is compacted, are wiped
+/// on a later write once their last other reference is dropped, on `scrub_pending`, or when the store is
+/// dropped.
+///
+/// Only the copy held by the inner store is wiped: values cloned out of the tree by lookups are owned by
+/// the caller, who should hold them in `Zeroizing` wrappers. Persistent backends keep their own copies,
+/// on disk or in a server, which are outside the reach of this decorator.
+///
+/// This type requires the `zeroize` feature.
+///
+/// # Type Parameters
+///
+/// - `S`: The wrapped storage backend.
+/// - `K`: The key size in bytes, 32 by default.
+/// - `V`: The type of the leaf values, `Vec<u8>` by default.
+///
+/// # Examples
+///
+/// ```rust
+/// use mssmt::store::ScrubbingStore;
+/// use mssmt::{DefaultStore, FullTree};
+///
+/// let mut tree = FullTree::new(ScrubbingStore::new(DefaultStore::default()));
+/// tree.insert([1u8; 32], b"secret".to_vec(), 10).unwrap();
+/// tree.delete([1u8; 32]).unwrap();
+///
+/// // The branches of the previous versions of the tree still hold the deleted leaf
+/// assert_eq!(tree.store().scrub_pending(), 1);
+/// tree.compact().unwrap();
+/// assert_eq!(tree.store().scrub_pending(), 0);
+/// ```
+pub struct ScrubbingStore<S, const K: usize = HASH_SIZE, V: Zeroize = Vec<u8>> {
+    inner: S,
+    // Deleted leaves still referenced outside the store when they were deleted
+    pending: Mutex<Vec<Arc<LeafNode<K, V>>>>,
+}
+
+impl<S, const K: usize, V: Zeroize> ScrubbingStore<S, K, V> {
+    /// Creates a new `ScrubbingStore` wrapping `inner`.
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            pending: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Returns a reference to the wrapped store.
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// Wipes the deleted leaves that are no longer referenced, and returns the number of deleted leaves
+    /// still waiting for their last reference to be dropped.
+    pub fn scrub_pending(&self) -> usize {
+        let mut pending = self.pending.lock();
+        pending.retain_mut(|leaf| match Arc::get_mut(leaf) {
+            Some(leaf) => {
+                leaf.zeroize();
+                false
+            }
+            None => true,
+        });
+        pending.len()
+    }
+}
+
+impl<S, const K: usize, V: Zeroize> Drop for ScrubbingStore<S, K, V> {
+    fn drop(&mut self) {
+        self.scrub_pending();
+    }
+}
+
+impl<S: TreeStoreReader<K, V>, const K: usize, V: LeafValue + Zeroize> TreeStoreReader<K, V>
+    for ScrubbingStore<S, K, V>
+{
+    fn root_node(&self) -> Result<Arc<dyn Node>> {
+        self.inner.root_node()
+    }
+
+    fn get_branch(&self, key: &NodeHash) -> Result<Option<Arc<BranchNode>>> {
+        self.inner.get_branch(key)
+    }
+
+    fn get_leaf(&self, key: &NodeHash) -> Result<Option<Arc<LeafNode<K, V>>>> {
+        self.inner.get_leaf(key)
+    }
+
+    fn get_leaf_by_key(&self, key: &[u8; K]) -> Result<Option<Arc<LeafNode<K, V>>>> {
+        self.inner.get_leaf_by_key(key)
+    }
+
+    fn may_contain_key(&self, key: &[u8; K]) -> Result<bool> {
+        self.inner.may_contain_key(key)
+    }
+
+    fn get_children(
+        &self,
+        height: usize,
+        hash: &NodeHash,
+    ) -> Result<(Arc<dyn Node>, Arc<dyn Node>)> {
+        self.inner.get_children(height, hash)
+    }
+
+    fn branch_hashes(&self) -> Result<Vec<NodeHash>> {
+        self.inner.branch_hashes()
+    }
+
+    fn leaf_hashes(&self) -> Result<Vec<NodeHash>> {
+        self.inner.leaf_hashes()
+    }
+
+    fn stats(&self) -> Result<StoreStats> {
+        self.inner.stats()
+    }
+
+    fn health_check(&self) -> Result<()> {
+        self.inner.health_check()
+    }
+
+    fn get_leaf_metadata(&self, key: &[u8; K]) -> Result<Option<LeafMetadata>> {
+        self.inner.get_leaf_metadata(key)
+    }
+}
+
+impl<S: TreeStore<K, V>, const K: usize, V: LeafValue + Zeroize> TreeStoreWriter<K, V>
+    for ScrubbingStore<S, K, V>
+{
+    fn insert_branch(&mut self, branch: Arc<BranchNode>) -> Result<()> {
+        self.inner.insert_branch(branch)
+    }
+
+    fn insert_leaf(&mut self, leaf: Arc<LeafNode<K, V>>) -> Result<()> {
+        self.inner.insert_leaf(leaf)
+    }
+
+    fn insert_nodes(
+        &mut self,
+        branches: Vec<Arc<BranchNode>>,
+        leaves: Vec<Arc<LeafNode<K, V>>>,
+    ) -> Result<()> {
+        self.inner.insert_nodes(branches, leaves)
+    }
+
+    fn delete_branch(&mut self, key: &NodeHash) -> Result<()> {
+        self.inner.delete_branch(key)
+    }
+
+    fn delete_leaf(&mut self, key: &NodeHash) -> Result<()> {
+        let leaf = self.inner.get_leaf(key)?;
+        self.inner.delete_leaf(key)?;
+        if let Some(leaf) = leaf {
+            self.pending.lock().push(leaf);
+        }
+        self.scrub_pending();
+        Ok(())
+    }
+
+    fn set_leaf_metadata(&mut self, key: &[u8; K], metadata: Option<LeafMetadata>) -> Result<()> {
+        self.inner.set_leaf_metadata(key, metadata)
+    }
+
+    fn update_root(&mut self, root: Arc<dyn Node>) -> Result<()> {
+        self.inner.update_root(root)?;
+        self.scrub_pending();
+        Ok(())
+    }
+
+    fn compare_and_update_root(&mut self, expected: NodeHash, root: Arc<dyn Node>) -> Result<bool> {
+        let updated = self.inner.compare_and_update_root(expected, root)?;
+        self.scrub_pending();
+        Ok(updated)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::DefaultStore;
+    use crate::tree::FullTree;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// A value counting the times it is wiped.
+    #[derive(Clone)]
+    struct Tracked(Vec<u8>, Arc<AtomicUsize>);
+
+    impl AsRef<[u8]> for Tracked {
+        fn as_ref(&self) -> &[u8] {
+            &self.0
+        }
+    }
+
+    impl Zeroize for Tracked {
+        fn zeroize(&mut self) {
+            self.0.zeroize();
+            self.1.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn test_scrubs_deleted_leaves() -> Result<()> {
+        let wiped = Arc::new(AtomicUsize::new(0));
+        let value = |bytes: &[u8]| Tracked(bytes.to_vec(), wiped.clone());
+        let mut tree =
+            FullTree::<_, 32, Tracked>::new(ScrubbingStore::new(DefaultStore::default()));
+        tree.insert([1u8; 32], value(b"first"), 1)?;
+        tree.insert([2u8; 32], value(b"second"), 2)?;
+
+        // The leaf is wiped once the superseded branches holding it are deleted
+        tree.delete([1u8; 32])?;
+        assert_eq!(tree.store().scrub_pending(), 1);
+        assert_eq!(wiped.load(Ordering::SeqCst), 0);
+
+        // A leaf held by the caller is wiped once released
+        let held = tree.store().get_leaf_by_key(&[2u8; 32])?.unwrap();
+        tree.clear()?;
+        assert_eq!(wiped.load(Ordering::SeqCst), 1);
+        assert_eq!(tree.store().scrub_pending(), 1);
+        drop(held);
+        assert_eq!(tree.store().scrub_pending(), 0);
+        assert_eq!(wiped.load(Ordering::SeqCst), 2);
+
+        // Wiping the stored copy leaves the tree and its lookups intact
+        tree.insert([3u8; 32], value(b"third"), 3)?;
+        assert_eq!(tree.get([3u8; 32])?.unwrap().0 .0, b"third");
+        assert_eq!(tree.total_sum()?, 3);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,387 @@
+//! A store partitioning nodes across several inner stores.
+
+use crate::error::{MssmtError, Result};
+use crate::node::{BranchNode, EmptyTree, LeafNode, Node, NodeHash};
+use crate::store::{TreeStore, TreeStoreReader, TreeStoreWriter};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::thread;
+
+/// A `TreeStore` partitioning nodes across several inner stores by the prefix of their hash.
+///
+/// Each node lives in exactly one shard, so the shards of a large tree can be spread over several
+/// disks, files or servers. Inserted nodes are staged per shard and written to all shards concurrently,
+/// one thread per shard, when the root is updated or on `flush`, so that a bulk build or batch insert
+/// against a slow backend pays for the slowest shard rather than for the sum of all of them. Node
+/// listings, used by compaction and integrity audits, query the shards concurrently as well. Deletions
+/// go through to their shard immediately.
+///
+/// The root is committed to every shard, the first shard last, with a copy of the root branch in the
+/// shards not owning it, so that every shard can be reopened on its own. The root of the first shard
+/// is the root of the store, so a sharded store must always be reopened with its shards in the same
+/// order. Root updates are not conditional: `compare_and_update_root` is unsupported.
+///
+/// Leaves are placed by hash rather than by key, so the leaves of a key can live in several shards and
+/// no shard's key index is authoritative: lookups by key walk the path from the root instead.
+///
+/// # Type Parameters
+///
+/// - `S`: The storage backend of the shards.
+///
+/// # Examples
+///
+/// ```rust
+/// use mssmt::store::ShardedStore;
+/// use mssmt::{DefaultStore, FullTree};
+///
+/// let shards = (0..4).map(|_| DefaultStore::new()).collect();
+/// let mut tree = FullTree::new(ShardedStore::new(shards).unwrap());
+/// for i in 0..16u8 {
+///     tree.insert([i; 32], vec![i], 1).unwrap();
+/// }
+///
+/// assert_eq!(tree.total_sum().unwrap(), 16);
+/// assert!(tree.store().shards().iter().all(|shard| !shard.leaves.is_empty()));
+/// ```
+pub struct ShardedStore<S> {
+    shards: Vec<Shard<S>>,
+    // The hash of the root branch copied into the shards not owning it
+    root_copy: Option<NodeHash>,
+}
+
+/// A shard and the writes staged for it.
+struct Shard<S> {
+    store: S,
+    // Nodes inserted since the last flush
+    branches: HashMap<NodeHash, Arc<BranchNode>>,
+    leaves: HashMap<NodeHash, Arc<LeafNode>>,
+}
+
+impl<S> ShardedStore<S> {
+    /// Returns the inner stores, in shard order.
+    ///
+    /// Writes staged since the last root update have not reached them yet.
+    pub fn shards(&self) -> Vec<&S> {
+        self.shards.iter().map(|shard| &shard.store).collect()
+    }
+
+    /// Consumes the store, returning the inner stores in shard order and dropping staged writes.
+    pub fn into_shards(self) -> Vec<S> {
+        self.shards.into_iter().map(|shard| shard.store).collect()
+    }
+
+    /// Returns the index of the shard owning the node with hash `hash`.
+    fn shard_index(&self, hash: &NodeHash) -> usize {
+        let prefix = u64::from_be_bytes(hash.0[..8].try_into().expect("8-byte prefix"));
+        (prefix % self.shards.len() as u64) as usize
+    }
+
+    fn shard(&self, hash: &NodeHash) -> &Shard<S> {
+        &self.shards[self.shard_index(hash)]
+    }
+
+    fn shard_mut(&mut self, hash: &NodeHash) -> &mut Shard<S> {
+        let index = self.shard_index(hash);
+        &mut self.shards[index]
+    }
+}
+
+impl<S: TreeStoreReader> ShardedStore<S> {
+    /// Creates a store partitioning nodes across `shards`.
+    ///
+    /// # Returns
+    ///
+    /// - The sharded store, holding the tree whose root is stored in the first shard.
+    /// - `MssmtError::Store` if `shards` is empty.
+    pub fn new(shards: Vec<S>) -> Result<Self> {
+        if shards.is_empty() {
+            return Err(MssmtError::Store(
+                "a sharded store needs at least one shard".to_string(),
+            ));
+        }
+        let mut store = Self {
+            shards: shards
+                .into_iter()
+                .map(|store| Shard {
+                    store,
+                    branches: HashMap::new(),
+                    leaves: HashMap::new(),
+                })
+                .collect(),
+            root_copy: None,
+        };
+        let root_hash = store.shards[0].store.root_node()?.node_hash();
+        store.root_copy = (root_hash != EmptyTree::hash_at(0)).then_some(root_hash);
+        Ok(store)
+    }
+}
+
+impl<S: TreeStoreWriter> Shard<S> {
+    fn has_changes(&self) -> bool {
+        !self.branches.is_empty() || !self.leaves.is_empty()
+    }
+
+    /// Writes the staged nodes, keeping them if the store fails so that the flush can be retried.
+    fn flush(&mut self) -> Result<()> {
+        for branch in self.branches.values() {
+            self.store.insert_branch(branch.clone())?;
+        }
+        for leaf in self.leaves.values() {
+            self.store.insert_leaf(leaf.clone())?;
+        }
+        self.branches.clear();
+        self.leaves.clear();
+        Ok(())
+    }
+
+    /// Commits `root` to the shard, copying the root branch into it unless it owns it already.
+    fn commit_root(&mut self, owned: bool, root: &Arc<dyn Node>) -> Result<()> {
+        if !owned {
+            if let Some(branch) = root.as_branch() {
+                self.store.insert_branch(Arc::new(branch.clone()))?;
+            }
+        }
+        self.store.update_root(root.clone())
+    }
+}
+
+impl<S: TreeStore + Send> ShardedStore<S> {
+    /// Writes the nodes staged for every shard, concurrently.
+    ///
+    /// Updating the root flushes the store, so this is only needed after writing nodes without a root
+    /// update, such as when copying nodes into the store.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(())` once every shard holds its staged nodes.
+    /// - The first error returned by a shard, in which case the nodes of the failed shards stay staged.
+    pub fn flush(&mut self) -> Result<()> {
+        for_each_shard(&mut self.shards, |_, shard| {
+            if shard.has_changes() {
+                shard.flush()
+            } else {
+                Ok(())
+            }
+        })
+    }
+
+    /// Drops the copies of the previous root from the shards not owning it, once `root` replaced it.
+    fn drop_root_copies(&mut self, root: NodeHash) -> Result<()> {
+        let previous = self.root_copy.filter(|previous| *previous != root);
+        if let Some(previous) = previous {
+            let owner = self.shard_index(&previous);
+            for_each_shard(&mut self.shards, |index, shard| {
+                if index == owner {
+                    Ok(())
+                } else {
+                    shard.store.delete_branch(&previous)
+                }
+            })?;
+        }
+        self.root_copy = (root != EmptyTree::hash_at(0)).then_some(root);
+        Ok(())
+    }
+}
+
+impl<S: TreeStoreReader + Sync> TreeStoreReader for ShardedStore<S> {
+    fn root_node(&self) -> Result<Arc<dyn Node>> {
+        self.shards[0].store.root_node()
+    }
+
+    fn get_branch(&self, key: &NodeHash) -> Result<Option<Arc<BranchNode>>> {
+        let shard = self.shard(key);
+        match shard.branches.get(key) {
+            Some(branch) => Ok(Some(branch.clone())),
+            None => shard.store.get_branch(key),
+        }
+    }
+
+    fn get_leaf(&self, key: &NodeHash) -> Result<Option<Arc<LeafNode>>> {
+        let shard = self.shard(key);
+        match shard.leaves.get(key) {
+            Some(leaf) => Ok(Some(leaf.clone())),
+            None => shard.store.get_leaf(key),
+        }
+    }
+
+    fn branch_hashes(&self) -> Result<Vec<NodeHash>> {
+        list_hashes(&self.shards, |shard| {
+            merge_hashes(shard.store.branch_hashes()?, &shard.branches)
+        })
+    }
+
+    fn leaf_hashes(&self) -> Result<Vec<NodeHash>> {
+        list_hashes(&self.shards, |shard| {
+            merge_hashes(shard.store.leaf_hashes()?, &shard.leaves)
+        })
+    }
+}
+
+impl<S: TreeStore + Send> TreeStoreWriter for ShardedStore<S> {
+    fn insert_branch(&mut self, branch: Arc<BranchNode>) -> Result<()> {
+        let hash = branch.node_hash();
+        self.shard_mut(&hash).branches.insert(hash, branch);
+        Ok(())
+    }
+
+    fn insert_leaf(&mut self, leaf: Arc<LeafNode>) -> Result<()> {
+        let hash = leaf.node_hash();
+        self.shard_mut(&hash).leaves.insert(hash, leaf);
+        Ok(())
+    }
+
+    fn delete_branch(&mut self, key: &NodeHash) -> Result<()> {
+        let shard = self.shard_mut(key);
+        shard.branches.remove(key);
+        shard.store.delete_branch(key)
+    }
+
+    fn delete_leaf(&mut self, key: &NodeHash) -> Result<()> {
+        let shard = self.shard_mut(key);
+        shard.leaves.remove(key);
+        shard.store.delete_leaf(key)
+    }
+
+    fn update_root(&mut self, root: Arc<dyn Node>) -> Result<()> {
+        self.flush()?;
+        let hash = root.node_hash();
+        let owner = self.shard_index(&hash);
+
+        // The first shard is committed last, so it never references a root other shards dropped
+        let (first, others) = self.shards.split_first_mut().expect("at least one shard");
+        for_each_shard(others, |index, shard| {
+            shard.commit_root(index + 1 == owner, &root)
+        })?;
+        first.commit_root(owner == 0, &root)?;
+        self.drop_root_copies(hash)
+    }
+}
+
+/// Runs `f` on every shard with its index, concurrently, and returns the first error.
+fn for_each_shard<S: Send>(
+    shards: &mut [Shard<S>],
+    f: impl Fn(usize, &mut Shard<S>) -> Result<()> + Sync,
+) -> Result<()> {
+    let Some((last, others)) = shards.split_last_mut() else {
+        return Ok(());
+    };
+    let last_index = others.len();
+    thread::scope(|scope| {
+        let f = &f;
+        let handles: Vec<_> = others
+            .iter_mut()
+            .enumerate()
+            .map(|(index, shard)| scope.spawn(move || f(index, shard)))
+            .collect();
+        let mut result = f(last_index, last);
+        for handle in handles {
+            let shard_result = handle
+                .join()
+                .unwrap_or_else(|panic| std::panic::resume_unwind(panic));
+            result = result.and(shard_result);
+        }
+        result
+    })
+}
+
+/// Lists node hashes from every shard concurrently, without duplicates.
+fn list_hashes<S: Sync>(
+    shards: &[Shard<S>],
+    list: impl Fn(&Shard<S>) -> Result<Vec<NodeHash>> + Sync,
+) -> Result<Vec<NodeHash>> {
+    let lists = thread::scope(|scope| {
+        let list = &list;
+        let handles: Vec<_> = shards
+            .iter()
+            .map(|shard| scope.spawn(move || list(shard)))
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| {
+                handle
+                    .join()
+                    .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+            })
+            .collect::<Result<Vec<_>>>()
+    })?;
+    // Staged nodes may already be stored, and every shard holds a copy of the root
+    let hashes: HashSet<NodeHash> = lists.into_iter().flatten().collect();
+    Ok(hashes.into_iter().collect())
+}
+
+/// Combines the hashes listed by a shard with the nodes staged for it.
+fn merge_hashes<T>(
+    mut stored: Vec<NodeHash>,
+    staged: &HashMap<NodeHash, T>,
+) -> Result<Vec<NodeHash>> {
+    stored.extend(staged.keys());
+    Ok(stored)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::Sum;
+    use crate::store::{DefaultStore, LogStore};
+    use crate::tree::FullTree;
+
+    #[test]
+    fn test_sharded_store_matches_single_store() -> Result<()> {
+        let shards = (0..4).map(|_| DefaultStore::new()).collect();
+        let mut tree = FullTree::new(ShardedStore::new(shards)?);
+        let mut single = FullTree::new(DefaultStore::new());
+        for i in 0..32u8 {
+            tree.insert([i; 32], vec![i], i as Sum)?;
+            single.insert([i; 32], vec![i], i as Sum)?;
+        }
+        tree.delete([7u8; 32])?;
+        single.delete([7u8; 32])?;
+        tree.compact()?;
+        single.compact()?;
+
+        assert_eq!(tree.root()?.node_hash(), single.root()?.node_hash());
+        assert_eq!(tree.get([9u8; 32])?, Some((vec![9], 9)));
+        assert!(tree.verify_integrity()?.is_ok());
+
+        // Every node lives in one shard, apart from the copies of the root
+        let shards = tree.store().shards();
+        let leaves: usize = shards.iter().map(|shard| shard.leaves.len()).sum();
+        assert_eq!(leaves, single.store().leaves.len());
+        let branches: usize = shards.iter().map(|shard| shard.branches.len()).sum();
+        assert_eq!(branches, single.store().branches.len() + shards.len() - 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sharded_log_stores_reopen() -> Result<()> {
+        let paths: Vec<_> = (0..3)
+            .map(|i| {
+                std::env::temp_dir().join(format!("mssmt-shard-{}-{}.log", std::process::id(), i))
+            })
+            .collect();
+        let open = |paths: &[std::path::PathBuf]| -> Result<ShardedStore<LogStore>> {
+            ShardedStore::new(paths.iter().map(LogStore::open).collect::<Result<_>>()?)
+        };
+        for path in &paths {
+            let _ = std::fs::remove_file(path);
+        }
+
+        let mut tree = FullTree::new(open(&paths)?);
+        for i in 0..8u8 {
+            tree.insert([i; 32], vec![i], 1)?;
+        }
+        let root_hash = tree.root()?.node_hash();
+        drop(tree);
+
+        let tree = FullTree::new(open(&paths)?);
+        assert_eq!(tree.root()?.node_hash(), root_hash);
+        assert_eq!(tree.get([5u8; 32])?, Some((vec![5], 1)));
+        assert!(tree.verify_integrity()?.is_ok());
+
+        for path in &paths {
+            std::fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,381 @@
+//! Binary snapshots of the nodes of a store.
+
+use crate::error::{MssmtError, Result};
+use crate::format::{check_version, header, Format, HEADER_SIZE};
+use crate::node::{BranchNode, EmptyTree, LeafNode, Node, NodeHash, EMPTY_TREE, HASH_SIZE};
+use crate::store::{DefaultStore, TreeStoreReader, TreeStoreWriter};
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+/// The magic bytes at the start of every snapshot, followed by the format version.
+pub(crate) const SNAPSHOT_MAGIC: &[u8; 8] = b"MSSMTSNP";
+
+/// The header of snapshots written by this release.
+const SNAPSHOT_HEADER: [u8; HEADER_SIZE] =
+    header(SNAPSHOT_MAGIC, Format::StoreSnapshot.current_version());
+
+/// Every node and the root of a store, in a form any backend can be restored from.
+///
+/// A snapshot holds all the nodes of the store, including those of older versions of the tree, so that
+/// a restored store is interchangeable with the original. Branches reference their children by hash.
+///
+/// The binary encoding written by `write_to` is the header `MSSMTSNP` followed by the format version
+/// byte, the root hash, the number of branches as a big-endian `u64` and each branch as the hashes and
+/// sums of its children, the number of leaves and each leaf prefixed by its encoded length as a
+/// big-endian `u32`, and finally the SHA-256 digest of everything before it.
+///
+/// # Examples
+///
+/// ```rust
+/// use mssmt::store::StoreSnapshot;
+/// use mssmt::{DefaultStore, FullTree, Node};
+///
+/// let mut tree = FullTree::new(DefaultStore::new());
+/// tree.insert([1u8; 32], b"value".to_vec(), 10).unwrap();
+///
+/// let mut bytes = Vec::new();
+/// StoreSnapshot::capture(tree.store()).unwrap().write_to(&mut bytes).unwrap();
+///
+/// let mut store = DefaultStore::new();
+/// StoreSnapshot::read_from(bytes.as_slice()).unwrap().restore(&mut store).unwrap();
+/// let restored = FullTree::new(store);
+/// assert_eq!(restored.root().unwrap().node_hash(), tree.root().unwrap().node_hash());
+/// ```
+#[derive(Clone)]
+pub struct StoreSnapshot {
+    /// The hash of the root node.
+    pub root: NodeHash,
+    /// The branches of the store, sorted by hash.
+    pub branches: Vec<Arc<BranchNode>>,
+    /// The leaves of the store, sorted by hash.
+    pub leaves: Vec<Arc<LeafNode>>,
+}
+
+impl StoreSnapshot {
+    /// Captures every node and the root of `store`.
+    ///
+    /// # Returns
+    ///
+    /// - The snapshot of the store.
+    /// - `MssmtError::Unsupported` if the store cannot list its nodes.
+    pub fn capture<S: TreeStoreReader + ?Sized>(store: &S) -> Result<Self> {
+        let mut branch_hashes = store.branch_hashes()?;
+        let mut leaf_hashes = store.leaf_hashes()?;
+        branch_hashes.sort_by(|a, b| a.as_bytes().cmp(b.as_bytes()));
+        leaf_hashes.sort_by(|a, b| a.as_bytes().cmp(b.as_bytes()));
+
+        let mut branches = Vec::with_capacity(branch_hashes.len());
+        for hash in branch_hashes {
+            let branch = store
+                .get_branch(&hash)?
+                .ok_or(MssmtError::NodeNotFound(hash))?;
+            branches.push(Arc::new(branch.to_shallow()));
+        }
+        let mut leaves = Vec::with_capacity(leaf_hashes.len());
+        for hash in leaf_hashes {
+            leaves.push(
+                store
+                    .get_leaf(&hash)?
+                    .ok_or(MssmtError::NodeNotFound(hash))?,
+            );
+        }
+
+        Ok(Self {
+            root: store.root_node()?.node_hash(),
+            branches,
+            leaves,
+        })
+    }
+
+    /// Writes every node of the snapshot to `store`, then makes its root the root of the store.
+    ///
+    /// # Returns
+    ///
+    /// - `MssmtError::InvalidEncoding` if the root is not among the branches of the snapshot.
+    pub fn restore<S: TreeStoreWriter + ?Sized>(&self, store: &mut S) -> Result<()> {
+        let root: Arc<dyn Node> = if self.root == EmptyTree::hash_at(0) {
+            EMPTY_TREE[0].clone()
+        } else {
+            self.branches
+                .iter()
+                .find(|branch| branch.node_hash() == self.root)
+                .ok_or_else(|| {
+                    MssmtError::InvalidEncoding(format!(
+                        "snapshot root {} is missing from its branches",
+                        self.root
+                    ))
+                })?
+                .clone()
+        };
+
+        for leaf in &self.leaves {
+            store.insert_leaf(leaf.clone())?;
+        }
+        for branch in &self.branches {
+            store.insert_branch(branch.clone())?;
+        }
+        store.update_root(root)
+    }
+
+    /// Writes the binary encoding of the snapshot to `writer`.
+    pub fn write_to<W: Write>(&self, writer: W) -> Result<()> {
+        let mut writer = HashingWriter::new(writer);
+        writer.write_all(&SNAPSHOT_HEADER)?;
+        writer.write_all(self.root.as_bytes())?;
+        write_nodes(&mut writer, &self.branches, &self.leaves)?;
+        writer.finish()
+    }
+
+    /// Reads a snapshot written by `write_to`.
+    ///
+    /// # Returns
+    ///
+    /// - The decoded snapshot.
+    /// - `MssmtError::InvalidEncoding` if the input is not a snapshot, has an unsupported version, is
+    ///   truncated or fails its checksum. Older versions can be upgraded with `format::migrate`.
+    pub fn read_from<R: Read>(reader: R) -> Result<Self> {
+        let bytes = read_checked(reader, Format::StoreSnapshot, SNAPSHOT_MAGIC)?;
+        let mut body = Cursor::new(&bytes, Format::StoreSnapshot);
+        let root = NodeHash::new(body.take_array()?);
+        let (branches, leaves) = read_nodes(&mut body)?;
+        body.finish()?;
+
+        Ok(Self {
+            root,
+            branches,
+            leaves,
+        })
+    }
+}
+
+/// Writes the counted branches and length-prefixed leaves of a snapshot.
+pub(crate) fn write_nodes<W: Write>(
+    writer: &mut HashingWriter<W>,
+    branches: &[Arc<BranchNode>],
+    leaves: &[Arc<LeafNode>],
+) -> Result<()> {
+    writer.write_all(&(branches.len() as u64).to_be_bytes())?;
+    for branch in branches {
+        writer.write_all(&branch.encode())?;
+    }
+    writer.write_all(&(leaves.len() as u64).to_be_bytes())?;
+    for leaf in leaves {
+        let encoded = leaf.encode();
+        writer.write_all(&(encoded.len() as u32).to_be_bytes())?;
+        writer.write_all(&encoded)?;
+    }
+    Ok(())
+}
+
+/// The branches and leaves of a snapshot.
+type Nodes = (Vec<Arc<BranchNode>>, Vec<Arc<LeafNode>>);
+
+/// Reads the branches and leaves written by `write_nodes`.
+pub(crate) fn read_nodes(body: &mut Cursor<'_>) -> Result<Nodes> {
+    let branch_count = u64::from_be_bytes(body.take_array()?);
+    let mut branches = Vec::new();
+    for _ in 0..branch_count {
+        let branch = BranchNode::decode(body.take(BranchNode::ENCODED_SIZE)?)?;
+        branches.push(Arc::new(branch));
+    }
+    let leaf_count = u64::from_be_bytes(body.take_array()?);
+    let mut leaves = Vec::new();
+    for _ in 0..leaf_count {
+        let len = u32::from_be_bytes(body.take_array()?) as usize;
+        leaves.push(Arc::new(LeafNode::decode(body.take(len)?)?));
+    }
+    Ok((branches, leaves))
+}
+
+/// Reads a snapshot-like file in `format`, checks its header, version and checksum, and returns the
+/// bytes between the header and the checksum.
+pub(crate) fn read_checked<R: Read>(
+    mut reader: R,
+    format: Format,
+    magic: &[u8; 8],
+) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    if bytes.len() < HEADER_SIZE + HASH_SIZE || !bytes.starts_with(magic) {
+        return Err(MssmtError::InvalidEncoding(format!("not a {}", format)));
+    }
+    check_version(format, bytes[HEADER_SIZE - 1])?;
+
+    let (body, checksum) = bytes.split_at(bytes.len() - HASH_SIZE);
+    if Sha256::digest(body).as_slice() != checksum {
+        return Err(MssmtError::InvalidEncoding(format!(
+            "{} checksum mismatch",
+            format
+        )));
+    }
+    Ok(body[HEADER_SIZE..].to_vec())
+}
+
+impl DefaultStore {
+    /// Saves every node and the root of the store to a snapshot file at `path`.
+    ///
+    /// See `StoreSnapshot` for the file format.
+    pub fn save_to(&self, path: impl AsRef<Path>) -> Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        StoreSnapshot::capture(self)?.write_to(&mut writer)?;
+        writer.flush()?;
+        writer.get_ref().sync_all()?;
+        Ok(())
+    }
+
+    /// Loads a store from a snapshot file written by `save_to`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use mssmt::{DefaultStore, FullTree};
+    ///
+    /// let path = std::env::temp_dir().join(format!("mssmt-doc-{}.snapshot", std::process::id()));
+    ///
+    /// let mut tree = FullTree::new(DefaultStore::new());
+    /// tree.insert([1u8; 32], b"value".to_vec(), 10).unwrap();
+    /// tree.store().save_to(&path).unwrap();
+    ///
+    /// let loaded = FullTree::new(DefaultStore::load_from(&path).unwrap());
+    /// assert_eq!(loaded.get([1u8; 32]).unwrap(), Some((b"value".to_vec(), 10)));
+    /// # std::fs::remove_file(&path).unwrap();
+    /// ```
+    pub fn load_from(path: impl AsRef<Path>) -> Result<Self> {
+        let snapshot = StoreSnapshot::read_from(BufReader::new(File::open(path)?))?;
+        let mut store = Self::new();
+        snapshot.restore(&mut store)?;
+        Ok(store)
+    }
+}
+
+/// A writer appending the SHA-256 digest of everything written through it on `finish`.
+pub(crate) struct HashingWriter<W> {
+    inner: W,
+    hasher: Sha256,
+}
+
+impl<W: Write> HashingWriter<W> {
+    pub(crate) fn new(inner: W) -> Self {
+        Self {
+            inner,
+            hasher: Sha256::new(),
+        }
+    }
+
+    pub(crate) fn write_all(&mut self, bytes: &[u8]) -> Result<()> {
+        self.hasher.update(bytes);
+        self.inner.write_all(bytes)?;
+        Ok(())
+    }
+
+    pub(crate) fn finish(mut self) -> Result<()> {
+        self.inner.write_all(&self.hasher.finalize())?;
+        Ok(())
+    }
+}
+
+/// Reads consecutive fields from a byte slice holding data of `format`, which decoding errors name.
+pub(crate) struct Cursor<'a> {
+    bytes: &'a [u8],
+    format: Format,
+}
+
+impl<'a> Cursor<'a> {
+    pub(crate) fn new(bytes: &'a [u8], format: Format) -> Self {
+        Self { bytes, format }
+    }
+
+    /// Returns the bytes not read yet.
+    pub(crate) fn remaining(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    pub(crate) fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.bytes.len() < len {
+            return Err(MssmtError::InvalidEncoding(format!(
+                "truncated {}",
+                self.format
+            )));
+        }
+        let (head, tail) = self.bytes.split_at(len);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    pub(crate) fn take_array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let mut array = [0u8; N];
+        array.copy_from_slice(self.take(N)?);
+        Ok(array)
+    }
+
+    /// Checks that every byte was read.
+    pub(crate) fn finish(self) -> Result<()> {
+        if !self.bytes.is_empty() {
+            return Err(MssmtError::InvalidEncoding(format!(
+                "trailing bytes in {}",
+                self.format
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::Sum;
+    use crate::tree::FullTree;
+
+    #[test]
+    fn test_snapshot_roundtrip() -> Result<()> {
+        let mut tree = FullTree::new(DefaultStore::new());
+        for i in 0..16u8 {
+            tree.insert([i; 32], vec![i; i as usize], i as Sum)?;
+        }
+        tree.delete([3u8; 32])?;
+        let root_hash = tree.root()?.node_hash();
+
+        let path = std::env::temp_dir().join(format!("mssmt-snapshot-{}.bin", std::process::id()));
+        tree.store().save_to(&path)?;
+        let loaded = FullTree::new(DefaultStore::load_from(&path)?);
+        std::fs::remove_file(&path)?;
+
+        assert_eq!(loaded.root()?.node_hash(), root_hash);
+        assert_eq!(loaded.store().branches.len(), tree.store().branches.len());
+        assert_eq!(loaded.store().leaves.len(), 15);
+        assert_eq!(loaded.get([7u8; 32])?, Some((vec![7; 7], 7)));
+        assert!(loaded.contains_key([15u8; 32])?);
+        assert!(loaded.verify_integrity()?.is_ok());
+
+        // The empty store round-trips too
+        let mut bytes = Vec::new();
+        StoreSnapshot::capture(&DefaultStore::new())?.write_to(&mut bytes)?;
+        let mut store = DefaultStore::new();
+        StoreSnapshot::read_from(bytes.as_slice())?.restore(&mut store)?;
+        assert!(FullTree::new(store).is_empty()?);
+
+        // Corrupted, truncated and future snapshots are rejected
+        let mut bytes = Vec::new();
+        StoreSnapshot::capture(tree.store())?.write_to(&mut bytes)?;
+        let mut corrupted = bytes.clone();
+        corrupted[100] ^= 1;
+        let mut future = bytes.clone();
+        future[8] = 2;
+        for input in [
+            &corrupted[..],
+            &bytes[..bytes.len() - 1],
+            &future[..],
+            b"log",
+        ] {
+            assert!(matches!(
+                StoreSnapshot::read_from(input),
+                Err(MssmtError::InvalidEncoding(_))
+            ));
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,513 @@
+//! Extraction of verifiable subtrees by key prefix.
+//!
+//! A `Subtree` is a standalone slice of a larger tree: the leaves whose keys share a bit prefix, together
+//! with the root hash and sum of the subtree and the siblings linking it to the root of the full tree.
+//! A service can hand out subtrees to downstream consumers, who can check them against the published root.
+//! `FullTree::range` returns the leaves under a prefix alone, without the proof material, and
+//! `FullTree::sum_of_prefix` their committed sum. `FullTree::delete_prefix` removes all of them at once.
+
+use crate::error::{MssmtError, Result};
+use crate::executor::SingleThreaded;
+use crate::key::Key;
+use crate::node::{
+    branch_hash, build_levels, collect_leaves, BranchNode, EmptyTree, LeafNode, Node, NodeHash,
+    Sum, EMPTY_TREE, HASH_SIZE, MAX_TREE_LEVELS,
+};
+use crate::path::{Height, TreePath};
+use crate::store::{resolve_node, TreeStore, TreeStoreReader};
+use crate::tree::FullTree;
+use std::sync::Arc;
+
+/// A verifiable slice of a tree, containing all leaves under a key prefix.
+///
+/// # Fields
+///
+/// - `prefix`: A key whose first `height` bits select the subtree. The remaining bits are zero.
+/// - `height`: The height of the subtree root, equal to the number of prefix bits.
+/// - `root_hash`: The hash of the subtree root.
+/// - `sum`: The sum of the subtree root.
+/// - `leaves`: The non-empty leaves of the subtree, in key order.
+/// - `siblings`: The siblings along the path from the tree root down to the subtree root.
+#[derive(Clone)]
+pub struct Subtree {
+    pub prefix: [u8; 32],
+    pub height: usize,
+    pub root_hash: NodeHash,
+    pub sum: Sum,
+    pub leaves: Vec<LeafNode>,
+    pub siblings: Vec<Arc<dyn Node>>,
+}
+
+impl Subtree {
+    /// Verifies the subtree against the root hash of the full tree.
+    ///
+    /// The subtree root is rebuilt from the leaves and must match `root_hash` and `sum` of the subtree, and
+    /// the siblings must link it to `tree_root_hash`.
+    pub fn verify(&self, tree_root_hash: NodeHash) -> bool {
+        if self.height > MAX_TREE_LEVELS || self.siblings.len() != self.height {
+            return false;
+        }
+
+        let mut leaves = self.leaves.clone();
+        leaves.sort_by_key(|leaf| leaf.key);
+        leaves.dedup_by_key(|leaf| leaf.key);
+        if leaves.len() != self.leaves.len()
+            || leaves.iter().any(|leaf| {
+                leaf.is_empty()
+                    || !TreePath::from(leaf.key)
+                        .has_prefix(&TreePath::from(self.prefix), Height::at(self.height))
+            })
+        {
+            return false;
+        }
+
+        let Ok(root) = build_levels(self.height, &leaves, &SingleThreaded, |_, _| {}) else {
+            return false;
+        };
+        if root.node_hash() != self.root_hash || root.node_sum() != self.sum {
+            return false;
+        }
+
+        let mut hash = self.root_hash;
+        let mut sum = self.sum;
+        for (height, sibling) in self.siblings.iter().enumerate().rev() {
+            sum = match sum.checked_add(sibling.node_sum()) {
+                Some(sum) => sum,
+                None => return false,
+            };
+            let sibling_hash = sibling.node_hash();
+            hash = if TreePath::from(self.prefix).is_left(Height::at(height)) {
+                branch_hash(&hash, &sibling_hash, sum)
+            } else {
+                branch_hash(&sibling_hash, &hash, sum)
+            };
+        }
+
+        hash == tree_root_hash
+    }
+}
+
+impl<S: TreeStoreReader> FullTree<S> {
+    /// Extracts the subtree containing every leaf whose key starts with the given bit prefix.
+    ///
+    /// # Arguments
+    ///
+    /// - `prefix`: A key whose first `prefix_bits` bits select the subtree.
+    /// - `prefix_bits`: The number of prefix bits, which is also the height of the subtree root.
+    ///
+    /// # Returns
+    ///
+    /// - The extracted `Subtree`.
+    /// - `MssmtError::InvalidHeight` if `prefix_bits` is larger than `MAX_TREE_LEVELS`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use mssmt::{DefaultStore, FullTree, Node};
+    ///
+    /// let mut tree = FullTree::new(DefaultStore::new());
+    /// tree.insert([0x00; 32], b"a".to_vec(), 1).unwrap();
+    /// tree.insert([0x01; 32], b"b".to_vec(), 2).unwrap();
+    /// tree.insert([0xff; 32], b"c".to_vec(), 3).unwrap();
+    ///
+    /// // All keys starting with a zero bit
+    /// let subtree = tree.subtree([0u8; 32], 1).unwrap();
+    /// assert_eq!(subtree.leaves.len(), 2);
+    /// assert_eq!(subtree.sum, 3);
+    /// assert!(subtree.verify(tree.root().unwrap().node_hash()));
+    /// ```
+    pub fn subtree(&self, prefix: [u8; 32], prefix_bits: usize) -> Result<Subtree> {
+        if prefix_bits > MAX_TREE_LEVELS {
+            return Err(MssmtError::InvalidHeight(prefix_bits));
+        }
+
+        let mut node = self.root()?;
+        let mut siblings = Vec::with_capacity(prefix_bits);
+        for height in 0..prefix_bits {
+            node = resolve_node(self.store(), &node, height)?;
+            if let Some(branch) = node.as_branch() {
+                let (next, sibling) = if TreePath::from(prefix).is_left(Height::at(height)) {
+                    (branch.left.clone(), branch.right.clone())
+                } else {
+                    (branch.right.clone(), branch.left.clone())
+                };
+                siblings.push(sibling);
+                node = next;
+            } else {
+                siblings.push(EMPTY_TREE[height + 1].clone());
+            }
+        }
+
+        let mut leaves = Vec::new();
+        collect_leaves(self.store(), &node, prefix_bits, &mut leaves)?;
+
+        // Normalize the prefix so that bits below the subtree root are zero
+        let mut normalized = [0u8; 32];
+        for idx in 0..prefix_bits {
+            if !TreePath::from(prefix).is_left(Height::at(idx)) {
+                normalized[idx / 8] |= 1 << (7 - idx % 8);
+            }
+        }
+
+        Ok(Subtree {
+            prefix: normalized,
+            height: prefix_bits,
+            root_hash: node.node_hash(),
+            sum: node.node_sum(),
+            leaves,
+            siblings,
+        })
+    }
+
+    /// Returns every leaf whose key starts with the given bit prefix, in key order.
+    ///
+    /// Only the subtree under the prefix is visited, so scoped queries on large trees do not scan the
+    /// other leaves.
+    ///
+    /// # Arguments
+    ///
+    /// - `prefix`: A key whose first `prefix_bits` bits select the leaves.
+    /// - `prefix_bits`: The number of prefix bits. With 0 bits, every leaf is returned.
+    ///
+    /// # Returns
+    ///
+    /// - The leaves under the prefix.
+    /// - `MssmtError::InvalidHeight` if `prefix_bits` is larger than `MAX_TREE_LEVELS`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use mssmt::{DefaultStore, FullTree};
+    ///
+    /// let mut tree = FullTree::new(DefaultStore::new());
+    /// tree.insert([0x00; 32], b"a".to_vec(), 1).unwrap();
+    /// tree.insert([0x01; 32], b"b".to_vec(), 2).unwrap();
+    /// tree.insert([0xff; 32], b"c".to_vec(), 3).unwrap();
+    ///
+    /// // All keys whose first byte is 0x01
+    /// let leaves = tree.range([0x01; 32], 8).unwrap();
+    /// assert_eq!(leaves.len(), 1);
+    /// assert_eq!(leaves[0].value, b"b".to_vec());
+    /// ```
+    pub fn range(&self, prefix: impl Into<Key>, prefix_bits: usize) -> Result<Vec<LeafNode>> {
+        let mut leaves = Vec::new();
+        if let Some(node) = self.prefix_node(&prefix.into().0, prefix_bits)? {
+            collect_leaves(self.store(), &node, prefix_bits, &mut leaves)?;
+        }
+        Ok(leaves)
+    }
+
+    /// Returns the sum of every leaf whose key starts with the given bit prefix.
+    ///
+    /// The sum is committed in the branch at the end of the prefix path, so only the branches along that
+    /// path are read, whatever the number of leaves under the prefix.
+    ///
+    /// # Arguments
+    ///
+    /// - `prefix`: A key whose first `prefix_bits` bits select the leaves.
+    /// - `prefix_bits`: The number of prefix bits. With 0 bits, this is the sum of the tree.
+    ///
+    /// # Returns
+    ///
+    /// - The sum of the leaves under the prefix.
+    /// - `MssmtError::InvalidHeight` if `prefix_bits` is larger than `MAX_TREE_LEVELS`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use mssmt::{DefaultStore, FullTree};
+    ///
+    /// let mut tree = FullTree::new(DefaultStore::new());
+    /// tree.insert([0x00; 32], b"a".to_vec(), 1).unwrap();
+    /// tree.insert([0x01; 32], b"b".to_vec(), 2).unwrap();
+    /// tree.insert([0xff; 32], b"c".to_vec(), 3).unwrap();
+    ///
+    /// // Keys whose first byte is below 0x80
+    /// assert_eq!(tree.sum_of_prefix([0x00; 32], 1).unwrap(), 3);
+    /// ```
+    pub fn sum_of_prefix(&self, prefix: impl Into<Key>, prefix_bits: usize) -> Result<Sum> {
+        Ok(self
+            .prefix_node(&prefix.into().0, prefix_bits)?
+            .map_or(0, |node| node.node_sum()))
+    }
+
+    /// Returns the node at the end of the prefix path, or `None` if the path leads into an empty subtree.
+    fn prefix_node(&self, prefix: &[u8; 32], prefix_bits: usize) -> Result<Option<Arc<dyn Node>>> {
+        if prefix_bits > MAX_TREE_LEVELS {
+            return Err(MssmtError::InvalidHeight(prefix_bits));
+        }
+
+        let mut node = self.root()?;
+        for height in 0..prefix_bits {
+            if EmptyTree::is_empty_at(height, &node.node_hash()) {
+                return Ok(None);
+            }
+            node = resolve_node(self.store(), &node, height)?;
+            let Some(branch) = node.as_branch() else {
+                return Err(MssmtError::NodeNotFound(node.node_hash()));
+            };
+            node = if TreePath::from(*prefix).is_left(Height::at(height)) {
+                branch.left.clone()
+            } else {
+                branch.right.clone()
+            };
+        }
+        Ok(Some(node))
+    }
+}
+
+impl<S: TreeStore> FullTree<S> {
+    /// Removes every leaf whose key starts with the given bit prefix.
+    ///
+    /// The subtree under the prefix is replaced by the empty subtree at its height and only the branches
+    /// above it are rewritten, as a single update of the tree. The nodes of the removed subtree are
+    /// deleted from the store, and observers are notified of each removed leaf.
+    ///
+    /// # Arguments
+    ///
+    /// - `prefix`: A key whose first `prefix_bits` bits select the leaves.
+    /// - `prefix_bits`: The number of prefix bits. With 0 bits, every leaf is removed.
+    ///
+    /// # Returns
+    ///
+    /// - The number of removed leaves.
+    /// - `MssmtError::InvalidHeight` if `prefix_bits` is larger than `MAX_TREE_LEVELS`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use mssmt::{DefaultStore, FullTree};
+    ///
+    /// let mut tree = FullTree::new(DefaultStore::new());
+    /// tree.insert([0x00; 32], b"a".to_vec(), 1).unwrap();
+    /// tree.insert([0x01; 32], b"b".to_vec(), 2).unwrap();
+    /// tree.insert([0xff; 32], b"c".to_vec(), 3).unwrap();
+    ///
+    /// // Remove all keys starting with a zero bit
+    /// assert_eq!(tree.delete_prefix([0u8; 32], 1).unwrap(), 2);
+    /// assert_eq!(tree.total_sum().unwrap(), 3);
+    /// assert_eq!(tree.get([0x01; 32]).unwrap(), None);
+    /// ```
+    pub fn delete_prefix(&mut self, prefix: impl Into<Key>, prefix_bits: usize) -> Result<usize> {
+        if prefix_bits > MAX_TREE_LEVELS {
+            return Err(MssmtError::InvalidHeight(prefix_bits));
+        }
+        let prefix = prefix.into().0;
+        let root = self.root()?;
+        let (old_root_hash, old_root_sum) = (root.node_hash(), root.node_sum());
+        let mut removed = Vec::new();
+        let new_root = self.delete_prefix_at(root, 0, &prefix, prefix_bits, &mut removed)?;
+        let changes = removed.iter().map(|leaf| (0, leaf.sum));
+        self.config()
+            .check_root_sum(old_root_sum, changes, new_root.node_sum())?;
+        self.store_mut().update_root(new_root.clone())?;
+
+        for leaf in &removed {
+            self.notify_delete(leaf);
+        }
+        self.notify_root_change(old_root_hash, new_root.as_ref());
+        self.stamp_leaf_metadata()?;
+        Ok(removed.len())
+    }
+
+    /// Replaces the subtree at the end of the prefix path below `node` with the empty subtree, returning
+    /// the new node at `height`.
+    fn delete_prefix_at(
+        &mut self,
+        node: Arc<dyn Node>,
+        height: usize,
+        prefix: &[u8; 32],
+        prefix_bits: usize,
+        removed: &mut Vec<LeafNode>,
+    ) -> Result<Arc<dyn Node>> {
+        if EmptyTree::is_empty_at(height, &node.node_hash()) {
+            return Ok(node);
+        }
+        if height == prefix_bits {
+            remove_subtree(self.store_mut(), &node, height, removed)?;
+            return Ok(EMPTY_TREE[height].clone());
+        }
+
+        let node = resolve_node(self.store(), &node, height)?;
+        let Some(branch) = node.as_branch() else {
+            return Err(MssmtError::NodeNotFound(node.node_hash()));
+        };
+        let (left, right) = if TreePath::from(*prefix).is_left(Height::at(height)) {
+            let left = branch.left.clone();
+            let left = self.delete_prefix_at(left, height + 1, prefix, prefix_bits, removed)?;
+            (left, branch.right.clone())
+        } else {
+            let right = branch.right.clone();
+            let right = self.delete_prefix_at(right, height + 1, prefix, prefix_bits, removed)?;
+            (branch.left.clone(), right)
+        };
+
+        if removed.is_empty() {
+            return Ok(node);
+        }
+        let empty_child_hash = EmptyTree::hash_at(height + 1);
+        if left.node_hash() == empty_child_hash && right.node_hash() == empty_child_hash {
+            return Ok(EMPTY_TREE[height].clone());
+        }
+        let overflow = self.config().overflow_policy();
+        let new_branch = Arc::new(BranchNode::with_overflow_policy(left, right, overflow));
+        self.store_mut()
+            .insert_branch_at(height, new_branch.clone())?;
+        Ok(new_branch)
+    }
+}
+
+/// Deletes the nodes of the subtree rooted at `node` from `store`, appending its leaves to `removed`.
+fn remove_subtree<S: TreeStore + ?Sized>(
+    store: &mut S,
+    node: &Arc<dyn Node>,
+    height: usize,
+    removed: &mut Vec<LeafNode>,
+) -> Result<()> {
+    if EmptyTree::is_empty_at(height, &node.node_hash()) {
+        return Ok(());
+    }
+
+    let node = resolve_node(store, node, height)?;
+    if let Some(branch) = node.as_branch() {
+        remove_subtree(store, &branch.left, height + 1, removed)?;
+        remove_subtree(store, &branch.right, height + 1, removed)?;
+        store.delete_branch_at(height, &branch.node_hash())?;
+    } else if let Some(leaf) = node.as_leaf::<HASH_SIZE, Vec<u8>>() {
+        store.delete_leaf(&leaf.node_hash())?;
+        removed.push(leaf.clone());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::DefaultStore;
+
+    #[test]
+    fn test_subtree_extraction() -> Result<()> {
+        let mut tree = FullTree::new(DefaultStore::new());
+        tree.insert([0x10; 32], b"a".to_vec(), 1)?;
+        tree.insert([0x1f; 32], b"b".to_vec(), 2)?;
+        tree.insert([0x20; 32], b"c".to_vec(), 3)?;
+        let root_hash = tree.root()?.node_hash();
+
+        // Keys starting with 0b0001
+        let subtree = tree.subtree([0x10; 32], 4)?;
+        assert_eq!(subtree.prefix[0], 0x10);
+        assert_eq!(subtree.prefix[1], 0x00);
+        assert_eq!(subtree.leaves.len(), 2);
+        assert_eq!(subtree.sum, 3);
+        assert!(subtree.verify(root_hash));
+
+        // Tampering with a leaf invalidates the slice
+        let mut tampered = subtree.clone();
+        tampered.leaves[0].sum = 5;
+        assert!(!tampered.verify(root_hash));
+
+        // An empty slice is still verifiable
+        let empty = tree.subtree([0xf0; 32], 4)?;
+        assert!(empty.leaves.is_empty());
+        assert!(empty.verify(root_hash));
+
+        assert!(matches!(
+            tree.subtree([0u8; 32], MAX_TREE_LEVELS + 1),
+            Err(MssmtError::InvalidHeight(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_range() -> Result<()> {
+        let mut tree = FullTree::new(DefaultStore::new());
+        for (i, first) in [0x10u8, 0x1f, 0x20, 0x80, 0x81].iter().enumerate() {
+            let mut key = [i as u8; 32];
+            key[0] = *first;
+            tree.insert(key, vec![i as u8], i as Sum)?;
+        }
+
+        // Keys starting with 0b0001, and with 0b1000000
+        let keys =
+            |leaves: Vec<LeafNode>| leaves.iter().map(|leaf| leaf.key[0]).collect::<Vec<_>>();
+        assert_eq!(keys(tree.range([0x10; 32], 4)?), vec![0x10, 0x1f]);
+        assert_eq!(keys(tree.range([0x80; 32], 7)?), vec![0x80, 0x81]);
+        assert!(tree.range([0x40; 32], 2)?.is_empty());
+        assert_eq!(tree.range([0u8; 32], 0)?.len(), 5);
+
+        // Ranges match the leaves of the verifiable subtree
+        assert_eq!(
+            keys(tree.range([0x10; 32], 4)?),
+            keys(tree.subtree([0x10; 32], 4)?.leaves)
+        );
+
+        let mut key = [4u8; 32];
+        key[0] = 0x81;
+        assert_eq!(tree.range(key, MAX_TREE_LEVELS)?.len(), 1);
+        assert!(matches!(
+            tree.range([0u8; 32], MAX_TREE_LEVELS + 1),
+            Err(MssmtError::InvalidHeight(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sum_of_prefix() -> Result<()> {
+        let mut tree = FullTree::new(DefaultStore::new());
+        tree.insert([0x10; 32], b"a".to_vec(), 1)?;
+        tree.insert([0x1f; 32], b"b".to_vec(), 2)?;
+        tree.insert([0x20; 32], b"c".to_vec(), 3)?;
+        tree.insert([0x90; 32], b"d".to_vec(), 4)?;
+
+        assert_eq!(tree.sum_of_prefix([0x10; 32], 4)?, 3);
+        assert_eq!(tree.sum_of_prefix([0x00; 32], 1)?, 6);
+        assert_eq!(tree.sum_of_prefix([0xf0; 32], 4)?, 0);
+        assert_eq!(tree.sum_of_prefix([0u8; 32], 0)?, 10);
+        assert_eq!(tree.sum_of_prefix([0x1f; 32], MAX_TREE_LEVELS)?, 2);
+        assert_eq!(
+            tree.sum_of_prefix([0x10; 32], 4)?,
+            tree.subtree([0x10; 32], 4)?.sum
+        );
+        assert!(matches!(
+            tree.sum_of_prefix([0u8; 32], MAX_TREE_LEVELS + 1),
+            Err(MssmtError::InvalidHeight(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_delete_prefix() -> Result<()> {
+        let mut tree = FullTree::new(DefaultStore::new());
+        let mut expected = FullTree::new(DefaultStore::new());
+        for first in [0x10u8, 0x1f, 0x20, 0x80] {
+            tree.insert([first; 32], vec![first], first as Sum)?;
+        }
+        expected.insert([0x20; 32], vec![0x20], 0x20)?;
+        expected.insert([0x80; 32], vec![0x80], 0x80)?;
+
+        // Removing keys starting with 0b0001 matches deleting them one by one
+        assert_eq!(tree.delete_prefix([0x10; 32], 4)?, 2);
+        assert_eq!(tree.root()?.node_hash(), expected.root()?.node_hash());
+        assert!(tree.range([0x10; 32], 4)?.is_empty());
+        assert_eq!(tree.get([0x1f; 32])?, None);
+        assert_eq!(tree.store().leaves.len(), 2);
+        assert!(tree.verify_integrity()?.is_ok());
+
+        // An empty prefix leaves the tree unchanged
+        let version = tree.root_version();
+        assert_eq!(tree.delete_prefix([0xf0; 32], 4)?, 0);
+        assert_eq!(tree.root_version(), version);
+
+        assert_eq!(tree.delete_prefix([0u8; 32], 0)?, 2);
+        assert!(tree.is_empty()?);
+        assert!(matches!(
+            tree.delete_prefix([0u8; 32], MAX_TREE_LEVELS + 1),
+            Err(MssmtError::InvalidHeight(_))
+        ));
+
+        Ok(())
+    }
+}
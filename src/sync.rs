@@ -0,0 +1,351 @@
+//! Light-client synchronization by node deltas.
+//!
+//! A thin client tracking a large tree keeps its own partial copy of the nodes of the tree rather than
+//! downloading it again after every update. Given the root the client last synchronized to,
+//! `FullTree::sync_delta` streams the nodes of the current version that the client does not hold yet:
+//! the leaves and branches on the paths that changed, children first. Every other node of the new
+//! version is shared with the old one, so the delta grows with the size of the change, not of the tree.
+//!
+//! `FullTree::apply_sync_delta` writes a delta into the store of the client. The delta does not have to
+//! be trusted: every branch must reference children the client already holds or received before it,
+//! and the delta must end at the root the client expects, so a delta that is incomplete or built from
+//! another version is rejected without changing the root of the client. A client synchronizing for the
+//! first time, or whose last root the server no longer holds, starts from the empty root.
+
+use crate::commitment::RootCommitment;
+use crate::error::{MssmtError, Result};
+use crate::node::{
+    tree_levels, BranchNode, ComputedNode, EmptyTreeOf, LeafNode, LeafValue, Node, NodeHash,
+    HASH_SIZE,
+};
+use crate::store::{node_children, resolve_node, resolve_root, TreeStore, TreeStoreReader};
+use crate::tree::FullTree;
+use std::sync::Arc;
+
+/// A node streamed from a server to a light client.
+///
+/// Branches reference their children by hash and sum only, the children are streamed separately.
+pub enum SyncNode<const K: usize = HASH_SIZE, V = Vec<u8>> {
+    /// A branch at `height`, 0 being the root.
+    Branch {
+        /// The height of the branch.
+        height: usize,
+        /// The branch, with its children as hash references.
+        branch: Arc<BranchNode>,
+    },
+    /// A leaf.
+    Leaf(Arc<LeafNode<K, V>>),
+}
+
+impl<const K: usize, V> Clone for SyncNode<K, V> {
+    fn clone(&self) -> Self {
+        match self {
+            SyncNode::Branch { height, branch } => SyncNode::Branch {
+                height: *height,
+                branch: branch.clone(),
+            },
+            SyncNode::Leaf(leaf) => SyncNode::Leaf(leaf.clone()),
+        }
+    }
+}
+
+impl<const K: usize, V: LeafValue> SyncNode<K, V> {
+    /// Returns the hash of the node.
+    pub fn node_hash(&self) -> NodeHash {
+        match self {
+            SyncNode::Branch { branch, .. } => branch.node_hash(),
+            SyncNode::Leaf(leaf) => leaf.node_hash(),
+        }
+    }
+}
+
+impl<S: TreeStoreReader<K, V>, const K: usize, V: LeafValue> FullTree<S, K, V> {
+    /// Streams to `emit` the nodes a client synchronized to the root `from` needs to reach the current
+    /// root, children first, and returns the current root.
+    ///
+    /// The walk visits left children before right ones, so the stream order depends only on the two
+    /// roots and the leaves come out in lexicographic key order.
+    ///
+    /// # Returns
+    ///
+    /// - The commitment to the current root, which the client passes to `FullTree::apply_sync_delta`.
+    /// - `MssmtError::NodeNotFound` if `from` is not in the store, for example after compaction. The
+    ///   client then synchronizes from the empty root, `FullTree::empty_root_hash`.
+    /// - The first error of `emit`, which stops the stream.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use mssmt::{DefaultStore, FullTree, Node};
+    ///
+    /// let mut server = FullTree::new(DefaultStore::new());
+    /// server.insert([1u8; 32], b"one".to_vec(), 1).unwrap();
+    ///
+    /// // The first synchronization starts from the empty root
+    /// let mut client = FullTree::new(DefaultStore::new());
+    /// let mut nodes = Vec::new();
+    /// let from = client.root().unwrap().node_hash();
+    /// let target = server.sync_delta(from, |node| Ok(nodes.push(node))).unwrap();
+    /// client.apply_sync_delta(&target, nodes).unwrap();
+    ///
+    /// // Later ones only carry the changed paths
+    /// server.insert([2u8; 32], b"two".to_vec(), 2).unwrap();
+    /// let mut nodes = Vec::new();
+    /// let from = client.root().unwrap().node_hash();
+    /// let target = server.sync_delta(from, |node| Ok(nodes.push(node))).unwrap();
+    /// client.apply_sync_delta(&target, nodes).unwrap();
+    ///
+    /// assert_eq!(client.get([2u8; 32]).unwrap(), Some((b"two".to_vec(), 2)));
+    /// assert_eq!(client.root().unwrap().node_hash(), server.root().unwrap().node_hash());
+    /// ```
+    pub fn sync_delta(
+        &self,
+        from: NodeHash,
+        mut emit: impl FnMut(SyncNode<K, V>) -> Result<()>,
+    ) -> Result<RootCommitment> {
+        let old = resolve_root(self.store(), &from)?;
+        let new = self.root()?;
+        let overflow = self.config().overflow_policy();
+        walk_changes(
+            self.store(),
+            Some(old),
+            Some(new.clone()),
+            0,
+            &mut |height, node, children| {
+                let node = match children {
+                    Some((left, right)) => SyncNode::Branch {
+                        height,
+                        branch: Arc::new(BranchNode::with_overflow_policy(
+                            Arc::new(ComputedNode::new(left.node_hash(), left.node_sum())),
+                            Arc::new(ComputedNode::new(right.node_hash(), right.node_sum())),
+                            overflow,
+                        )),
+                    },
+                    None => match node.as_leaf::<K, V>() {
+                        Some(leaf) => SyncNode::Leaf(Arc::new(leaf.clone())),
+                        None => return Err(MssmtError::NodeNotFound(node.node_hash())),
+                    },
+                };
+                emit(node)
+            },
+            &mut |_| Ok(()),
+        )?;
+        Ok(RootCommitment::of(new.as_ref()))
+    }
+}
+
+impl<S: TreeStore<K, V>, const K: usize, V: LeafValue> FullTree<S, K, V> {
+    /// Writes the nodes of a delta streamed by `FullTree::sync_delta` into the store, and makes `target`
+    /// the root of the tree.
+    ///
+    /// Nodes are written as they arrive, so a delta can be applied from a network stream without being
+    /// buffered. The leaves the new root no longer references are deleted, like updates through the tree
+    /// delete the leaves they replace, and the superseded branches are left to compaction.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(())` once the tree is at `target`.
+    /// - `MssmtError::NodeNotFound` if a branch references a child that is neither in the store nor
+    ///   earlier in the delta, or if the delta does not contain the branch of `target`.
+    /// - `MssmtError::InvalidHeight` if a branch is at or below the leaf level.
+    /// - `MssmtError::InvalidEncoding` if the root of the delta does not have the sum of `target`.
+    ///
+    /// The root is left unchanged on error. The nodes already written are unreachable and removed by
+    /// compaction.
+    pub fn apply_sync_delta(
+        &mut self,
+        target: &RootCommitment,
+        nodes: impl IntoIterator<Item = SyncNode<K, V>>,
+    ) -> Result<()> {
+        let levels = tree_levels(K);
+        let mut new_root: Option<Arc<dyn Node>> = None;
+        for node in nodes {
+            match node {
+                SyncNode::Leaf(leaf) => self.store_mut().insert_leaf(leaf)?,
+                SyncNode::Branch { height, branch } => {
+                    if height >= levels {
+                        return Err(MssmtError::InvalidHeight(height));
+                    }
+                    for child in [&branch.left, &branch.right] {
+                        let hash = child.node_hash();
+                        let present = EmptyTreeOf::<K>::is_empty_at(height + 1, &hash)
+                            || if height + 1 == levels {
+                                self.store().get_leaf(&hash)?.is_some()
+                            } else {
+                                self.store().get_branch(&hash)?.is_some()
+                            };
+                        if !present {
+                            return Err(MssmtError::NodeNotFound(hash));
+                        }
+                    }
+                    if height == 0 && branch.node_hash() == target.hash {
+                        new_root = Some(branch.clone());
+                    }
+                    self.store_mut().insert_branch(branch)?;
+                }
+            }
+        }
+
+        let old_root = self.root()?;
+        let new_root = match new_root {
+            Some(root) => root,
+            None if old_root.node_hash() == target.hash => old_root.clone(),
+            None if target.hash == EmptyTreeOf::<K>::hash_at(0) => EmptyTreeOf::<K>::node_at(0),
+            None => return Err(MssmtError::NodeNotFound(target.hash)),
+        };
+        if new_root.node_sum() != target.sum {
+            return Err(MssmtError::InvalidEncoding(format!(
+                "synchronized root has sum {}, expected {}",
+                new_root.node_sum(),
+                target.sum
+            )));
+        }
+
+        let mut stale = Vec::new();
+        walk_changes(
+            self.store(),
+            Some(old_root.clone()),
+            Some(new_root.clone()),
+            0,
+            &mut |_, _, _| Ok(()),
+            &mut |leaf| {
+                stale.push(leaf.node_hash());
+                Ok(())
+            },
+        )?;
+        for hash in &stale {
+            self.store_mut().delete_leaf(hash)?;
+        }
+        self.store_mut().update_root(new_root.clone())?;
+        self.notify_root_change(old_root.node_hash(), new_root.as_ref());
+        Ok(())
+    }
+}
+
+/// Walks the subtrees `old` and `new` at `height` side by side, skipping the subtrees they share.
+///
+/// Every node of `new` not shared with `old` is passed to `fresh`, children first, along with its
+/// children if it is a branch. Every leaf of `old` not shared with `new` is passed to `stale`. Empty
+/// subtrees are passed to neither.
+#[allow(clippy::type_complexity)]
+pub(crate) fn walk_changes<S: TreeStoreReader<K, V> + ?Sized, const K: usize, V: LeafValue>(
+    store: &S,
+    old: Option<Arc<dyn Node>>,
+    new: Option<Arc<dyn Node>>,
+    height: usize,
+    fresh: &mut impl FnMut(usize, &Arc<dyn Node>, Option<(Arc<dyn Node>, Arc<dyn Node>)>) -> Result<()>,
+    stale: &mut impl FnMut(&LeafNode<K, V>) -> Result<()>,
+) -> Result<()> {
+    let non_empty = |node: Option<Arc<dyn Node>>| {
+        node.filter(|node| !EmptyTreeOf::<K>::is_empty_at(height, &node.node_hash()))
+    };
+    let (old, new) = (non_empty(old), non_empty(new));
+    match (&old, &new) {
+        (None, None) => return Ok(()),
+        (Some(old), Some(new)) if old.node_hash() == new.node_hash() => return Ok(()),
+        _ => {}
+    }
+
+    if height == tree_levels(K) {
+        if let Some(old) = old {
+            let old = resolve_node(store, &old, height)?;
+            if let Some(leaf) = old.as_leaf::<K, V>() {
+                stale(leaf)?;
+            }
+        }
+        if let Some(new) = new {
+            fresh(height, &resolve_node(store, &new, height)?, None)?;
+        }
+        return Ok(());
+    }
+
+    let old_children = old
+        .map(|old| node_children(store, &old, height))
+        .transpose()?;
+    let new_children = new
+        .as_ref()
+        .map(|new| node_children(store, new, height))
+        .transpose()?;
+    let (old_left, old_right) = old_children.unzip();
+    let (new_left, new_right) = new_children.clone().unzip();
+    walk_changes(store, old_left, new_left, height + 1, fresh, stale)?;
+    walk_changes(store, old_right, new_right, height + 1, fresh, stale)?;
+    if let Some(new) = new {
+        fresh(height, &new, new_children)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::Sum;
+    use crate::store::DefaultStore;
+
+    fn delta(
+        server: &FullTree<DefaultStore>,
+        client: &FullTree<DefaultStore>,
+    ) -> Result<(RootCommitment, Vec<SyncNode>)> {
+        let mut nodes = Vec::new();
+        let target = server.sync_delta(client.root()?.node_hash(), |node| {
+            nodes.push(node);
+            Ok(())
+        })?;
+        Ok((target, nodes))
+    }
+
+    #[test]
+    fn test_client_tracks_server_through_deltas() -> Result<()> {
+        let mut server = FullTree::new(DefaultStore::new());
+        for i in 0..64u8 {
+            server.insert([i.wrapping_mul(41); 32], vec![i], i as Sum)?;
+        }
+        let mut client = FullTree::new(DefaultStore::new());
+        let (target, nodes) = delta(&server, &client)?;
+        let full_sync = nodes.len();
+        client.apply_sync_delta(&target, nodes)?;
+        assert_eq!(RootCommitment::of(client.root()?.as_ref()), target);
+        assert_eq!(client.get([41u8; 32])?, Some((vec![1], 1)));
+
+        // Later deltas only carry the changed paths, and deleted keys disappear from the client
+        server.insert([41u8; 32], b"changed".to_vec(), 100)?;
+        server.insert([0xab; 32], b"new".to_vec(), 7)?;
+        server.delete([82u8; 32])?;
+        let (target, nodes) = delta(&server, &client)?;
+        assert!(nodes.len() < full_sync);
+
+        // An incomplete delta is rejected without moving the root
+        let before = client.root()?.node_hash();
+        let truncated = nodes[1..].to_vec();
+        assert!(matches!(
+            client.apply_sync_delta(&target, truncated),
+            Err(MssmtError::NodeNotFound(_))
+        ));
+        assert_eq!(client.root()?.node_hash(), before);
+        let inflated = RootCommitment::new(target.hash, target.sum + 1);
+        assert!(matches!(
+            client.apply_sync_delta(&inflated, nodes.clone()),
+            Err(MssmtError::InvalidEncoding(_))
+        ));
+
+        client.apply_sync_delta(&target, nodes)?;
+        assert_eq!(RootCommitment::of(client.root()?.as_ref()), target);
+        assert_eq!(client.get([41u8; 32])?, Some((b"changed".to_vec(), 100)));
+        assert_eq!(client.get([0xab; 32])?, Some((b"new".to_vec(), 7)));
+        assert_eq!(client.get([82u8; 32])?, None);
+        assert!(client.verify_integrity()?.is_ok());
+
+        // Nothing to send once synchronized, and emptying the tree is synchronized too
+        assert!(delta(&server, &client)?.1.is_empty());
+        for i in 0..64u8 {
+            server.delete([i.wrapping_mul(41); 32])?;
+        }
+        server.delete([0xab; 32])?;
+        let (target, nodes) = delta(&server, &client)?;
+        client.apply_sync_delta(&target, nodes)?;
+        assert!(client.is_empty()?);
+        assert_eq!(client.get([0u8; 32])?, None);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,653 @@
+//! Domain-separated commitments for the Merkle-Sum Sparse Merkle Tree.
+//!
+//! Nodes hash with plain SHA-256: a leaf hashes `key || value || sum` and a branch hashes
+//! `left || right || sum`. With 32-byte keys, a leaf whose value is 32 bytes long has the same preimage
+//! layout as a branch, so a crafted leaf can be passed off as a branch and the other way around.
+//!
+//! This module commits to the same tree under a `HashDomain`. The tagged domain hashes leaves and
+//! branches with BIP-340 style tagged hashes, `SHA-256(SHA-256(tag) || SHA-256(tag) || preimage)`, with
+//! distinct leaf and branch tags derived from an application tag, so commitments of different node
+//! kinds and of different applications can never collide. The legacy domain reproduces the plain
+//! SHA-256 commitments of the tree, for compatibility with existing roots and proofs.
+//!
+//! Like the Poseidon commitments, nodes keep their SHA-256 hashes, which stores use to address them.
+//! Domain roots and proofs are computed from the leaves of the tree.
+//!
+//! The lnd domain reproduces the commitments of lightninglabs' Go mssmt package, as used by tapd. It
+//! differs from the legacy domain in two ways: leaves hash `value || sum` without their key, and key bits
+//! are read least significant bit first within each byte. Sums are committed to as 8 bytes, so the
+//! domain only commits to sums up to `u64::MAX`. Branches, empty subtrees and the encoding of compressed
+//! proofs are otherwise the same, so roots and proofs produced by tapd check out under
+//! `HashScheme::Lnd`.
+//!
+//! `HashScheme` versions the hashing: a tree records the scheme it commits under, and its proofs carry the
+//! scheme they verify under, so commitments made before a scheme change keep verifying.
+
+use crate::commitment::RootCommitment;
+use crate::error::{MssmtError, ProofError, Result};
+use crate::hash_utils::to_array;
+use crate::key::Key;
+use crate::node::{
+    bit_index, branch_hash, collect_leaves, leaf_hash, tree_levels, ComputedNode, EmptyTreeOf,
+    LeafNode, LeafValue, Node, NodeHash, Sum, HASH_SIZE, SUM_SIZE,
+};
+use crate::proof::Proof;
+use crate::store::TreeStoreReader;
+use crate::tree::FullTree;
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// The application tag of `DomainTags::default`.
+pub const DEFAULT_APP_TAG: &str = "mssmt";
+
+/// The hashed leaf and branch tags of a tagged domain.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DomainTags {
+    leaf: [u8; HASH_SIZE],
+    branch: [u8; HASH_SIZE],
+}
+
+impl DomainTags {
+    /// Derives the tags `<app_tag>/leaf` and `<app_tag>/branch` from an application tag.
+    pub fn new(app_tag: &str) -> Self {
+        Self::from_tags(
+            format!("{app_tag}/leaf").as_bytes(),
+            format!("{app_tag}/branch").as_bytes(),
+        )
+    }
+
+    /// Uses the given leaf and branch tags as they are.
+    ///
+    /// # Panics
+    ///
+    /// Panics if both tags are equal, which would defeat the separation of leaves and branches.
+    pub fn from_tags(leaf_tag: &[u8], branch_tag: &[u8]) -> Self {
+        assert_ne!(leaf_tag, branch_tag, "leaf and branch tags must differ");
+        Self {
+            leaf: to_array(&Sha256::digest(leaf_tag)),
+            branch: to_array(&Sha256::digest(branch_tag)),
+        }
+    }
+}
+
+impl Default for DomainTags {
+    fn default() -> Self {
+        Self::new(DEFAULT_APP_TAG)
+    }
+}
+
+/// Computes the tagged hash of the concatenation of `parts` under the hashed tag `tag`.
+fn tagged_hash(tag: &[u8; HASH_SIZE], parts: &[&[u8]]) -> NodeHash {
+    let mut hasher = Sha256::new();
+    hasher.update(tag);
+    hasher.update(tag);
+    for part in parts {
+        hasher.update(part);
+    }
+    NodeHash::new(to_array(&hasher.finalize()))
+}
+
+/// The hashing domain of a commitment.
+///
+/// # Examples
+///
+/// ```rust
+/// use mssmt::tagged::{DomainTags, HashDomain};
+/// use mssmt::{DefaultStore, FullTree, LeafNode, Node};
+///
+/// let mut tree = FullTree::new(DefaultStore::new());
+/// tree.insert([1u8; 32], b"one".to_vec(), 1).unwrap();
+///
+/// // The legacy domain is the commitment of the tree itself
+/// let (legacy_root, _) = tree.domain_root(&HashDomain::Legacy).unwrap();
+/// assert_eq!(legacy_root, tree.root().unwrap().node_hash());
+///
+/// let domain = HashDomain::Tagged(DomainTags::new("my-app"));
+/// let (root_hash, root_sum) = tree.domain_root(&domain).unwrap();
+/// let proof = tree.domain_proof([1u8; 32], &domain).unwrap();
+/// let leaf = LeafNode::new([1u8; 32], b"one".to_vec(), 1);
+/// assert_eq!(domain.proof_root(&proof, [1u8; 32], &leaf), Ok((root_hash, root_sum)));
+/// assert!(!proof.verify([1u8; 32], &leaf, root_hash));
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum HashDomain {
+    /// Plain SHA-256, as used by the nodes of the tree.
+    #[default]
+    Legacy,
+    /// Tagged SHA-256 separating leaves, branches and applications.
+    Tagged(DomainTags),
+    /// Plain SHA-256 with the leaf hashing and key bit order of lightninglabs' Go mssmt package.
+    Lnd,
+}
+
+/// Returns the last 8 bytes of the big-endian encoding of `sum`, as the Go package commits to `u64` sums.
+fn lnd_sum_bytes(sum: Sum) -> [u8; 8] {
+    sum.to_be_bytes()[SUM_SIZE - 8..]
+        .try_into()
+        .expect("sums are at least 8 bytes")
+}
+
+impl HashDomain {
+    /// Returns the hash of `leaf` in this domain.
+    pub fn leaf_hash<const K: usize>(&self, leaf: &LeafNode<K, impl LeafValue>) -> NodeHash {
+        match self {
+            HashDomain::Legacy => leaf.node_hash(),
+            _ => self.leaf_parts_hash(&leaf.key, leaf.value_bytes(), leaf.sum),
+        }
+    }
+
+    /// Returns the hash in this domain of the leaf with the given key, value and sum.
+    pub fn leaf_parts_hash(&self, key: &[u8], value: &[u8], sum: Sum) -> NodeHash {
+        match self {
+            HashDomain::Legacy => leaf_hash(key, value, sum),
+            HashDomain::Tagged(tags) => tagged_hash(&tags.leaf, &[key, value, &sum.to_be_bytes()]),
+            HashDomain::Lnd => {
+                let mut hasher = Sha256::new();
+                hasher.update(value);
+                hasher.update(lnd_sum_bytes(sum));
+                NodeHash::new(to_array(&hasher.finalize()))
+            }
+        }
+    }
+
+    /// Returns the hash of a branch over its children's hashes and its sum in this domain.
+    pub fn branch_hash(&self, left: &NodeHash, right: &NodeHash, sum: Sum) -> NodeHash {
+        match self {
+            HashDomain::Legacy => branch_hash(left, right, sum),
+            HashDomain::Tagged(tags) => tagged_hash(
+                &tags.branch,
+                &[left.as_bytes(), right.as_bytes(), &sum.to_be_bytes()],
+            ),
+            HashDomain::Lnd => {
+                let mut hasher = Sha256::new();
+                hasher.update(left.as_bytes());
+                hasher.update(right.as_bytes());
+                hasher.update(lnd_sum_bytes(sum));
+                NodeHash::new(to_array(&hasher.finalize()))
+            }
+        }
+    }
+
+    /// Returns the bit of `key` selecting the child at `height` in this domain, 0 for the left child.
+    pub fn key_bit<const K: usize>(&self, height: usize, key: &[u8; K]) -> u8 {
+        match self {
+            HashDomain::Lnd => (key[height / 8] >> (height % 8)) & 1,
+            _ => bit_index(height, key),
+        }
+    }
+
+    /// Returns the largest sum this domain commits to.
+    pub fn max_sum(&self) -> Sum {
+        match self {
+            HashDomain::Lnd => Sum::MAX >> (8 * (SUM_SIZE - 8)),
+            _ => Sum::MAX,
+        }
+    }
+
+    /// Sorts leaves in the order this domain visits them, which is key order except for
+    /// `HashDomain::Lnd`.
+    fn sort_leaves<const K: usize>(&self, leaves: &mut [LeafNode<K>]) {
+        if *self == HashDomain::Lnd {
+            leaves.sort_by_key(|leaf| leaf.key.map(u8::reverse_bits));
+        }
+    }
+
+    /// Returns the hashes of the empty subtrees of a tree with `K`-byte keys in this domain, from the
+    /// root at index 0 down to the empty leaf.
+    pub fn empty_hashes<const K: usize>(&self) -> Vec<NodeHash> {
+        let empty_leaf = LeafNode::<K>::new([0u8; K], Vec::new(), 0);
+        let mut hashes = vec![self.leaf_hash(&empty_leaf)];
+        for _ in 0..tree_levels(K) {
+            let child = hashes[hashes.len() - 1];
+            hashes.push(self.branch_hash(&child, &child, 0));
+        }
+        hashes.reverse();
+        hashes
+    }
+
+    /// Computes the root hash and sum in this domain from a proof produced by
+    /// `FullTree::domain_proof` and the given leaf.
+    pub fn proof_root<const K: usize>(
+        &self,
+        proof: &Proof<K>,
+        key: impl Into<Key<K>>,
+        leaf: &LeafNode<K, impl LeafValue>,
+    ) -> std::result::Result<(NodeHash, Sum), ProofError> {
+        let key = key.into().0;
+        proof.validate()?;
+        if !leaf.is_empty() && leaf.key != key {
+            return Err(ProofError::KeyMismatch);
+        }
+
+        let mut hash = self.leaf_hash(leaf);
+        let mut sum = leaf.sum;
+        for (height, sibling) in proof.nodes.iter().enumerate().rev() {
+            sum = sum
+                .checked_add(sibling.node_sum())
+                .filter(|sum| *sum <= self.max_sum())
+                .ok_or(ProofError::SumOverflow { height })?;
+            let sibling_hash = sibling.node_hash();
+            hash = if self.key_bit(height, &key) == 0 {
+                self.branch_hash(&hash, &sibling_hash, sum)
+            } else {
+                self.branch_hash(&sibling_hash, &hash, sum)
+            };
+        }
+        Ok((hash, sum))
+    }
+
+    /// Verifies a proof produced by `FullTree::domain_proof` against a root hash of this domain.
+    pub fn verify<const K: usize>(
+        &self,
+        proof: &Proof<K>,
+        key: impl Into<Key<K>>,
+        leaf: &LeafNode<K, impl LeafValue>,
+        root_hash: NodeHash,
+    ) -> bool {
+        matches!(self.proof_root(proof, key, leaf), Ok((hash, _)) if hash.constant_time_eq(&root_hash))
+    }
+
+    /// Returns the hash and sum of the subtree at `height` holding the given leaves.
+    ///
+    /// The leaves must be sorted by `HashDomain::sort_leaves` and share the key prefix leading to the
+    /// subtree.
+    fn subtree_root<const K: usize>(
+        &self,
+        empty_hashes: &[NodeHash],
+        height: usize,
+        leaves: &[LeafNode<K>],
+    ) -> Result<(NodeHash, Sum)> {
+        if leaves.is_empty() {
+            return Ok((empty_hashes[height], 0));
+        }
+        if height == tree_levels(K) {
+            if leaves[0].sum > self.max_sum() {
+                return Err(MssmtError::SumOverflow);
+            }
+            return Ok((self.leaf_hash(&leaves[0]), leaves[0].sum));
+        }
+        let split = leaves.partition_point(|leaf| self.key_bit(height, &leaf.key) == 0);
+        let (left, left_sum) = self.subtree_root(empty_hashes, height + 1, &leaves[..split])?;
+        let (right, right_sum) = self.subtree_root(empty_hashes, height + 1, &leaves[split..])?;
+        let sum = left_sum
+            .checked_add(right_sum)
+            .filter(|sum| *sum <= self.max_sum())
+            .ok_or(MssmtError::SumOverflow)?;
+        Ok((self.branch_hash(&left, &right, sum), sum))
+    }
+}
+
+/// The tags of the `HashScheme::V1` domain.
+static V1_TAGS: Lazy<DomainTags> = Lazy::new(DomainTags::default);
+
+/// The empty subtree hashes of the schemes other than `HashScheme::V0` by scheme id and key size, built
+/// on first use and kept for the process lifetime.
+static SCHEME_EMPTY_HASHES: Lazy<RwLock<HashMap<(HashScheme, usize), EmptyHashes>>> =
+    Lazy::new(Default::default);
+
+/// The empty subtree hashes of a scheme, from the root down to the empty leaf.
+type EmptyHashes = &'static [NodeHash];
+
+/// A versioned hashing scheme, recorded with trees and proofs so that commitments made under one
+/// scheme keep verifying once a newer scheme is introduced.
+///
+/// Nodes always hash with `V0`, which stores use to address them. A tree committing under another
+/// scheme publishes `FullTree::commitment` and `FullTree::scheme_proof` instead of its root and
+/// `merkle_proof`, and its proofs carry the scheme they verify under.
+///
+/// # Examples
+///
+/// ```rust
+/// use mssmt::tagged::HashScheme;
+/// use mssmt::{DefaultStore, FullTree, LeafNode};
+///
+/// let mut tree = FullTree::new(DefaultStore::new());
+/// tree.set_hash_scheme(HashScheme::V1);
+/// tree.insert([1u8; 32], b"one".to_vec(), 1).unwrap();
+///
+/// let root_hash = tree.commitment().unwrap().hash;
+/// let proof = tree.scheme_proof([1u8; 32]).unwrap();
+/// assert_eq!(proof.scheme, HashScheme::V1);
+/// assert!(proof.verify([1u8; 32], &LeafNode::new([1u8; 32], b"one".to_vec(), 1), root_hash));
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum HashScheme {
+    /// Plain SHA-256, the `HashDomain::Legacy` domain.
+    #[default]
+    V0,
+    /// Tagged SHA-256 with the default `DomainTags`.
+    V1,
+    /// The `HashDomain::Lnd` hashing of lightninglabs' Go mssmt package, to check commitments made by
+    /// tapd.
+    Lnd,
+}
+
+impl HashScheme {
+    /// Returns the identifier of the scheme in encodings.
+    pub const fn id(self) -> u8 {
+        match self {
+            HashScheme::V0 => 0,
+            HashScheme::V1 => 1,
+            HashScheme::Lnd => 2,
+        }
+    }
+
+    /// Returns the scheme with the given identifier.
+    ///
+    /// # Returns
+    ///
+    /// - `MssmtError::InvalidEncoding` if the identifier is unknown.
+    pub fn from_id(id: u8) -> Result<Self> {
+        match id {
+            0 => Ok(HashScheme::V0),
+            1 => Ok(HashScheme::V1),
+            2 => Ok(HashScheme::Lnd),
+            _ => Err(MssmtError::InvalidEncoding(format!(
+                "unknown hash scheme {id}"
+            ))),
+        }
+    }
+
+    /// Returns the hashing domain of the scheme.
+    pub fn domain(self) -> HashDomain {
+        match self {
+            HashScheme::V0 => HashDomain::Legacy,
+            HashScheme::V1 => HashDomain::Tagged(*V1_TAGS),
+            HashScheme::Lnd => HashDomain::Lnd,
+        }
+    }
+
+    /// Returns the hash of the empty subtree at `height` of a tree with `K`-byte keys.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `height` is greater than the number of tree levels.
+    pub fn empty_hash_at<const K: usize>(self, height: usize) -> NodeHash {
+        match self {
+            HashScheme::V0 => EmptyTreeOf::<K>::hash_at(height),
+            scheme => scheme_empty_hashes::<K>(scheme)[height],
+        }
+    }
+
+    /// Returns `true` if `hash` is the hash of the empty subtree at `height` under this scheme.
+    pub fn is_empty_at<const K: usize>(self, height: usize, hash: &NodeHash) -> bool {
+        match self {
+            HashScheme::V0 => EmptyTreeOf::<K>::is_empty_at(height, hash),
+            scheme => scheme_empty_hashes::<K>(scheme).get(height) == Some(hash),
+        }
+    }
+
+    /// Returns the root of the empty subtree at `height` under this scheme.
+    ///
+    /// `V0` shares the nodes of the empty tree, other schemes return a `ComputedNode`.
+    pub fn empty_node_at<const K: usize>(self, height: usize) -> Arc<dyn Node> {
+        match self {
+            HashScheme::V0 => EmptyTreeOf::<K>::node_at(height),
+            _ => Arc::new(ComputedNode::new(self.empty_hash_at::<K>(height), 0)),
+        }
+    }
+}
+
+fn scheme_empty_hashes<const K: usize>(scheme: HashScheme) -> &'static [NodeHash] {
+    if let Some(hashes) = SCHEME_EMPTY_HASHES.read().get(&(scheme, K)) {
+        return hashes;
+    }
+    SCHEME_EMPTY_HASHES
+        .write()
+        .entry((scheme, K))
+        .or_insert_with(|| Box::leak(scheme.domain().empty_hashes::<K>().into_boxed_slice()))
+}
+
+impl<S: TreeStoreReader<K>, const K: usize> FullTree<S, K> {
+    /// Returns the root hash and sum of the tree in `domain`.
+    ///
+    /// The root is recomputed from all leaves, hashing one branch per level and leaf.
+    pub fn domain_root(&self, domain: &HashDomain) -> Result<(NodeHash, Sum)> {
+        domain.subtree_root(&domain.empty_hashes::<K>(), 0, &self.domain_leaves(domain)?)
+    }
+
+    /// Generates a proof of `key` against the root of the tree in `domain`.
+    ///
+    /// The siblings carry their hashes in `domain`, so the proof verifies with `HashDomain::verify`
+    /// rather than `Proof::verify`, unless the domain is `HashDomain::Legacy`. Like `merkle_proof`, the
+    /// proof of an absent key verifies with the empty leaf.
+    pub fn domain_proof(&self, key: impl Into<Key<K>>, domain: &HashDomain) -> Result<Proof<K>> {
+        let key = key.into().0;
+        let empty_hashes = domain.empty_hashes::<K>();
+        let leaves = self.domain_leaves(domain)?;
+        let mut leaves = &leaves[..];
+        let mut nodes: Vec<Arc<dyn Node>> = Vec::with_capacity(tree_levels(K));
+        for height in 0..tree_levels(K) {
+            let split = leaves.partition_point(|leaf| domain.key_bit(height, &leaf.key) == 0);
+            let (left, right) = leaves.split_at(split);
+            let sibling = if domain.key_bit(height, &key) == 0 {
+                leaves = left;
+                right
+            } else {
+                leaves = right;
+                left
+            };
+            let (hash, sum) = domain.subtree_root(&empty_hashes, height + 1, sibling)?;
+            nodes.push(Arc::new(ComputedNode::new(hash, sum)));
+        }
+        Ok(Proof::new(nodes))
+    }
+
+    /// Returns the root hash and sum of the tree under its hash scheme, see `FullTree::set_hash_scheme`.
+    ///
+    /// Under `HashScheme::V0` this is the hash and sum of the root node.
+    pub fn commitment(&self) -> Result<RootCommitment> {
+        match self.hash_scheme() {
+            HashScheme::V0 => Ok(RootCommitment::of(self.root()?.as_ref())),
+            scheme => Ok(self.domain_root(&scheme.domain())?.into()),
+        }
+    }
+
+    /// Generates a proof of `key` against `FullTree::commitment`, tagged with the hash scheme of the tree.
+    ///
+    /// Under `HashScheme::V0` this is `merkle_proof`.
+    pub fn scheme_proof(&self, key: impl Into<Key<K>>) -> Result<Proof<K>> {
+        match self.hash_scheme() {
+            HashScheme::V0 => self.merkle_proof(key),
+            scheme => {
+                let mut proof = self.domain_proof(key, &scheme.domain())?;
+                proof.scheme = scheme;
+                Ok(proof)
+            }
+        }
+    }
+
+    /// Returns the leaves of the tree in the order `domain` visits them.
+    fn domain_leaves(&self, domain: &HashDomain) -> Result<Vec<LeafNode<K>>> {
+        let mut leaves = Vec::new();
+        collect_leaves(self.store(), &self.root()?, 0, &mut leaves)?;
+        domain.sort_leaves(&mut leaves);
+        Ok(leaves)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::EMPTY_LEAF_NODE;
+    use crate::proof::{CompressedProof, SCHEME_MARKER};
+    use crate::store::DefaultStore;
+
+    #[test]
+    fn test_domain_commitments() -> Result<()> {
+        let mut tree = FullTree::new(DefaultStore::new());
+        let tagged = HashDomain::Tagged(DomainTags::default());
+        assert_eq!(
+            tree.domain_root(&tagged)?,
+            (tagged.empty_hashes::<32>()[0], 0)
+        );
+
+        let keys = [[1u8; 32], [2u8; 32], [0x80; 32]];
+        for (i, key) in keys.iter().enumerate() {
+            tree.insert(*key, vec![i as u8; 32], i as Sum + 1)?;
+        }
+
+        // The legacy domain reproduces the tree commitment and its proofs
+        let root = tree.root()?;
+        assert_eq!(
+            tree.domain_root(&HashDomain::Legacy)?,
+            (root.node_hash(), root.node_sum())
+        );
+        assert_eq!(
+            tree.domain_proof([2u8; 32], &HashDomain::Legacy)?,
+            tree.merkle_proof([2u8; 32])?
+        );
+
+        let (root_hash, root_sum) = tree.domain_root(&tagged)?;
+        assert_eq!(root_sum, 6);
+        assert_ne!(root_hash, root.node_hash());
+        let leaf = LeafNode::new([2u8; 32], vec![1; 32], 2);
+        let proof = tree.domain_proof([2u8; 32], &tagged)?;
+        assert!(tagged.verify(&proof, [2u8; 32], &leaf, root_hash));
+        assert!(!HashDomain::Legacy.verify(&proof, [2u8; 32], &leaf, root_hash));
+        let absent = tree.domain_proof([3u8; 32], &tagged)?;
+        assert!(tagged.verify(&absent, [3u8; 32], &EMPTY_LEAF_NODE, root_hash));
+
+        // Applications and node kinds are separated
+        let other = HashDomain::Tagged(DomainTags::new("other"));
+        assert_ne!(tree.domain_root(&other)?.0, root_hash);
+        let left = NodeHash::new([1u8; 32]);
+        let right = NodeHash::new([2u8; 32]);
+        let crafted = LeafNode::new(*left.as_bytes(), right.as_bytes().to_vec(), 3);
+        assert_eq!(
+            HashDomain::Legacy.leaf_hash(&crafted),
+            HashDomain::Legacy.branch_hash(&left, &right, 3)
+        );
+        assert_ne!(
+            tagged.leaf_hash(&crafted),
+            tagged.branch_hash(&left, &right, 3)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_hash_schemes() -> Result<()> {
+        let mut tree = FullTree::new(DefaultStore::new());
+        for i in 1..=4u8 {
+            tree.insert([i; 32], vec![i], i as Sum)?;
+        }
+        let root = tree.root()?;
+        assert_eq!(tree.hash_scheme(), HashScheme::V0);
+        assert_eq!(tree.commitment()?, RootCommitment::of(root.as_ref()));
+        assert_eq!(tree.scheme_proof([2u8; 32])?, tree.merkle_proof([2u8; 32])?);
+
+        tree.set_hash_scheme(HashScheme::V1);
+        let (root_hash, root_sum) = tree.commitment()?.into();
+        assert_eq!(
+            (root_hash, root_sum),
+            tree.domain_root(&HashDomain::Tagged(DomainTags::default()))?
+        );
+        assert_eq!(root_sum, 10);
+
+        // Proofs carry their scheme through verification, compression and encoding
+        let leaf = LeafNode::new([2u8; 32], vec![2], 2);
+        let proof = tree.scheme_proof([2u8; 32])?;
+        assert_eq!(proof.scheme, HashScheme::V1);
+        assert!(proof.verify([2u8; 32], &leaf, root_hash));
+        assert_eq!(proof.root([2u8; 32], &leaf).node_hash(), root_hash);
+        assert_eq!(
+            proof.verify_with_sum([2u8; 32], &leaf, root_hash, None),
+            Some(10)
+        );
+        assert_eq!(
+            proof.non_empty_nodes(),
+            tree.merkle_proof([2u8; 32])?.non_empty_nodes()
+        );
+
+        let encoded = proof.compress().encode();
+        assert_eq!(proof.size_bytes(), encoded.len());
+        assert_eq!(encoded[..2], [SCHEME_MARKER, 1]);
+        assert_eq!(
+            encoded.len(),
+            tree.merkle_proof([2u8; 32])?.compress().encode().len() + 2
+        );
+        let decoded = CompressedProof::decode(&encoded)?.decompress()?;
+        assert_eq!(decoded, proof);
+        assert!(decoded.verify([2u8; 32], &leaf, root_hash));
+
+        // The same siblings under another scheme do not verify
+        let mut downgraded = proof.clone();
+        downgraded.scheme = HashScheme::V0;
+        assert!(!downgraded.verify([2u8; 32], &leaf, root_hash));
+        assert!(!proof.verify([2u8; 32], &leaf, root.node_hash()));
+
+        let mut unknown = encoded.clone();
+        unknown[1] = 9;
+        assert!(matches!(
+            CompressedProof::decode(&unknown),
+            Err(MssmtError::InvalidEncoding(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_lnd_domain() -> Result<()> {
+        let sha = |parts: &[&[u8]]| {
+            let mut hasher = Sha256::new();
+            parts.iter().for_each(|part| hasher.update(part));
+            NodeHash::new(to_array(&hasher.finalize()))
+        };
+
+        // Leaves commit to their value and a u64 sum only, and key bits are read LSB first
+        let lnd = HashDomain::Lnd;
+        let key = [0x01; 32];
+        let leaf = LeafNode::new(key, b"asset".to_vec(), 7);
+        assert_eq!(lnd.leaf_hash(&leaf), sha(&[b"asset", &7u64.to_be_bytes()]));
+        assert_eq!((lnd.key_bit(0, &key), lnd.key_bit(7, &key)), (1, 0));
+        assert_eq!(HashDomain::Legacy.key_bit(0, &key), 0);
+
+        // A single leaf folds up its path by hand
+        let mut empty = vec![sha(&[&0u64.to_be_bytes()])];
+        for _ in 0..256 {
+            let child = empty[empty.len() - 1];
+            empty.push(sha(&[
+                child.as_bytes(),
+                child.as_bytes(),
+                &0u64.to_be_bytes(),
+            ]));
+        }
+        empty.reverse();
+        assert_eq!(lnd.empty_hashes::<32>(), empty);
+        let mut expected = lnd.leaf_hash(&leaf);
+        for height in (0..256).rev() {
+            let sibling = empty[height + 1];
+            let (left, right) = if lnd.key_bit(height, &key) == 0 {
+                (expected, sibling)
+            } else {
+                (sibling, expected)
+            };
+            expected = sha(&[left.as_bytes(), right.as_bytes(), &7u64.to_be_bytes()]);
+        }
+
+        let mut tree = FullTree::new(DefaultStore::new());
+        tree.set_hash_scheme(HashScheme::Lnd);
+        tree.insert(key, b"asset".to_vec(), 7)?;
+        assert_eq!(tree.commitment()?, RootCommitment::new(expected, 7));
+
+        // Keys whose bits differ only in their order within a byte land on different sides
+        for i in 1..=6u8 {
+            tree.insert([i.reverse_bits(); 32], vec![i], i as Sum)?;
+        }
+        let commitment = tree.commitment()?;
+        for i in 1..=6u8 {
+            let key = [i.reverse_bits(); 32];
+            let leaf = LeafNode::new(key, vec![i], i as Sum);
+            let proof = tree.scheme_proof(key)?;
+            assert_eq!(proof.scheme, HashScheme::Lnd);
+            assert!(proof.verify_against(key, &leaf, &commitment));
+            assert_eq!(Proof::from_hex(&proof.to_hex())?, proof);
+        }
+        Ok(())
+    }
+}
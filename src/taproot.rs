@@ -0,0 +1,216 @@
+//! Bitcoin taproot commitments of Merkle-Sum Sparse Merkle Tree roots.
+//!
+//! Taproot assets anchors the root of an MS-SMT in a bitcoin transaction output: the root hash and sum
+//! are placed in a tapscript leaf, and the internal key of the output is tweaked with the tapscript tree
+//! holding that leaf. Anyone given the internal key and the root can then check that an output commits
+//! to the tree, without the leaf ever being revealed on chain.
+//!
+//! The leaf script follows the taproot-assets layout: the commitment version, the 32-byte marker
+//! `sha256("taproot-assets")`, the root hash and the root sum as a big-endian `u64` (a `u128` with the
+//! `u128` feature). The leaf may share the tapscript tree with a sibling, such as a spending script,
+//! given by its tapscript node hash.
+
+use crate::error::Result;
+use crate::node::{decode_sum, Node, NodeHash, Sum, SUM_SIZE};
+use crate::store::TreeStoreReader;
+use crate::tree::FullTree;
+use bitcoin::key::{TapTweak, TweakedPublicKey, UntweakedPublicKey};
+use bitcoin::secp256k1::{Secp256k1, Verification};
+use bitcoin::taproot::{LeafVersion, TapLeafHash, TapNodeHash};
+use bitcoin::{Script, ScriptBuf, TxOut};
+use once_cell::sync::Lazy;
+use sha2::{Digest, Sha256};
+
+/// The marker identifying taproot assets commitment leaves, `sha256("taproot-assets")`.
+pub static TAPROOT_ASSETS_MARKER: Lazy<[u8; 32]> =
+    Lazy::new(|| Sha256::digest(b"taproot-assets").into());
+
+/// The size of a commitment leaf script: version, marker, root hash and root sum.
+pub const COMMITMENT_SCRIPT_SIZE: usize = 1 + 32 + 32 + SUM_SIZE;
+
+/// A commitment of a tree root in a taproot output.
+///
+/// # Fields
+///
+/// - `version`: The commitment version, the first byte of the leaf script.
+/// - `root_hash`: The root hash of the committed tree.
+/// - `root_sum`: The root sum of the committed tree.
+/// - `sibling`: The tapscript node sharing the tapscript tree with the commitment leaf, if any.
+///
+/// # Examples
+///
+/// ```rust
+/// use bitcoin::secp256k1::{Secp256k1, SecretKey};
+/// use bitcoin::{Amount, TxOut};
+/// use mssmt::taproot::TaprootCommitment;
+/// use mssmt::{DefaultStore, FullTree};
+///
+/// let mut tree = FullTree::new(DefaultStore::new());
+/// tree.insert([1u8; 32], b"asset".to_vec(), 100).unwrap();
+///
+/// let secp = Secp256k1::new();
+/// let secret = SecretKey::from_slice(&[7u8; 32]).unwrap();
+/// let (internal_key, _) = secret.x_only_public_key(&secp);
+///
+/// let commitment = tree.taproot_commitment().unwrap();
+/// let output = TxOut {
+///     value: Amount::from_sat(1_000),
+///     script_pubkey: commitment.script_pubkey(&secp, internal_key),
+/// };
+/// assert!(commitment.verify_output(&secp, internal_key, &output));
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TaprootCommitment {
+    pub version: u8,
+    pub root_hash: NodeHash,
+    pub root_sum: Sum,
+    pub sibling: Option<TapNodeHash>,
+}
+
+impl TaprootCommitment {
+    /// The commitment version used by default.
+    pub const DEFAULT_VERSION: u8 = 0;
+
+    /// Creates a commitment to the given root hash and sum, with the default version and no sibling.
+    pub fn new(root_hash: NodeHash, root_sum: Sum) -> Self {
+        Self {
+            version: Self::DEFAULT_VERSION,
+            root_hash,
+            root_sum,
+            sibling: None,
+        }
+    }
+
+    /// Creates a commitment to the given root node.
+    pub fn from_root(root: &dyn Node) -> Self {
+        Self::new(root.node_hash(), root.node_sum())
+    }
+
+    /// Returns the commitment with `sibling` sharing its tapscript tree.
+    pub fn with_sibling(mut self, sibling: TapNodeHash) -> Self {
+        self.sibling = Some(sibling);
+        self
+    }
+
+    /// Returns the tapscript leaf script holding the commitment.
+    pub fn leaf_script(&self) -> ScriptBuf {
+        let mut script = Vec::with_capacity(COMMITMENT_SCRIPT_SIZE);
+        script.push(self.version);
+        script.extend_from_slice(&*TAPROOT_ASSETS_MARKER);
+        script.extend_from_slice(self.root_hash.as_bytes());
+        script.extend_from_slice(&self.root_sum.to_be_bytes());
+        ScriptBuf::from_bytes(script)
+    }
+
+    /// Parses a commitment from a tapscript leaf script, returning `None` if it is not a commitment.
+    pub fn from_leaf_script(script: &Script) -> Option<Self> {
+        let bytes = script.as_bytes();
+        if bytes.len() != COMMITMENT_SCRIPT_SIZE || bytes[1..33] != *TAPROOT_ASSETS_MARKER {
+            return None;
+        }
+        let mut root_hash = [0u8; 32];
+        root_hash.copy_from_slice(&bytes[33..65]);
+        Some(Self {
+            version: bytes[0],
+            root_hash: NodeHash::new(root_hash),
+            root_sum: decode_sum(&bytes[65..]),
+            sibling: None,
+        })
+    }
+
+    /// Returns the hash of the commitment leaf.
+    pub fn leaf_hash(&self) -> TapLeafHash {
+        TapLeafHash::from_script(&self.leaf_script(), LeafVersion::TapScript)
+    }
+
+    /// Returns the merkle root of the tapscript tree holding the commitment leaf and its sibling.
+    pub fn merkle_root(&self) -> TapNodeHash {
+        let leaf = TapNodeHash::from(self.leaf_hash());
+        match self.sibling {
+            Some(sibling) => TapNodeHash::from_node_hashes(leaf, sibling),
+            None => leaf,
+        }
+    }
+
+    /// Tweaks `internal_key` with the tapscript tree of the commitment, returning the output key.
+    pub fn output_key<C: Verification>(
+        &self,
+        secp: &Secp256k1<C>,
+        internal_key: UntweakedPublicKey,
+    ) -> TweakedPublicKey {
+        internal_key.tap_tweak(secp, Some(self.merkle_root())).0
+    }
+
+    /// Returns the pay-to-taproot output script committing to the tree under `internal_key`.
+    pub fn script_pubkey<C: Verification>(
+        &self,
+        secp: &Secp256k1<C>,
+        internal_key: UntweakedPublicKey,
+    ) -> ScriptBuf {
+        ScriptBuf::new_p2tr_tweaked(self.output_key(secp, internal_key))
+    }
+
+    /// Returns `true` if `output` pays to `internal_key` tweaked with this commitment.
+    pub fn verify_output<C: Verification>(
+        &self,
+        secp: &Secp256k1<C>,
+        internal_key: UntweakedPublicKey,
+        output: &TxOut,
+    ) -> bool {
+        output.script_pubkey == self.script_pubkey(secp, internal_key)
+    }
+}
+
+impl<S: TreeStoreReader> FullTree<S> {
+    /// Returns the taproot commitment to the current root of the tree.
+    pub fn taproot_commitment(&self) -> Result<TaprootCommitment> {
+        Ok(TaprootCommitment::from_root(self.root()?.as_ref()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::DefaultStore;
+    use bitcoin::secp256k1::SecretKey;
+    use bitcoin::Amount;
+
+    #[test]
+    fn test_taproot_commitment() -> Result<()> {
+        let mut tree = FullTree::new(DefaultStore::new());
+        tree.insert([1u8; 32], b"asset".to_vec(), 100)?;
+        let commitment = tree.taproot_commitment()?;
+
+        let script = commitment.leaf_script();
+        assert_eq!(script.len(), COMMITMENT_SCRIPT_SIZE);
+        assert_eq!(script.as_bytes()[65..], (100 as Sum).to_be_bytes());
+        assert_eq!(
+            TaprootCommitment::from_leaf_script(&script),
+            Some(commitment)
+        );
+        assert_eq!(TaprootCommitment::from_leaf_script(Script::new()), None);
+
+        let secp = Secp256k1::new();
+        let secret = SecretKey::from_slice(&[7u8; 32]).expect("valid secret key");
+        let (internal_key, _) = secret.x_only_public_key(&secp);
+        let sibling = TapNodeHash::from_script(Script::new(), LeafVersion::TapScript);
+        let commitment = commitment.with_sibling(sibling);
+        let output = TxOut {
+            value: Amount::from_sat(1_000),
+            script_pubkey: commitment.script_pubkey(&secp, internal_key),
+        };
+        assert!(commitment.verify_output(&secp, internal_key, &output));
+
+        // The output no longer matches once the tree changes
+        tree.insert([2u8; 32], b"asset".to_vec(), 1)?;
+        let updated = tree.taproot_commitment()?.with_sibling(sibling);
+        assert!(!updated.verify_output(&secp, internal_key, &output));
+        assert!(!TaprootCommitment {
+            sibling: None,
+            ..commitment
+        }
+        .verify_output(&secp, internal_key, &output));
+
+        Ok(())
+    }
+}
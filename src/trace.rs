@@ -0,0 +1,28 @@
+//! Internal tracing macros.
+//!
+//! With the `tracing` feature enabled, these macros forward to the `tracing` crate. Without it they
+//! expand to nothing, so instrumented code carries no cost and no dependency.
+
+/// Enters a debug-level span lasting until the end of the enclosing scope.
+macro_rules! debug_span {
+    ($name:expr $(, $($fields:tt)*)?) => {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!($name $(, $($fields)*)?).entered();
+    };
+}
+
+/// Emits a debug-level event.
+macro_rules! debug_event {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        tracing::debug!($($arg)*);
+    };
+}
+
+/// Emits a trace-level event, used for per-node details.
+macro_rules! trace_event {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        tracing::trace!($($arg)*);
+    };
+}
@@ -0,0 +1,191 @@
+//! Transactions grouping several updates of a tree, with reads of their own writes.
+//!
+//! `FullTree::update` runs a closure over a `Transaction`, a tree whose writes are staged in an
+//! `OverlayStore` on top of the store of the tree. Reads inside the closure, such as `get` and
+//! `merkle_proof`, see the staged writes, so a proof generated mid-transaction verifies against the
+//! root the transaction has reached so far. The staged writes reach the store only if the closure
+//! returns `Ok`, and are dropped otherwise, so the tree moves from its old root to the final root of
+//! the transaction in one step.
+
+use crate::error::Result;
+use crate::key::Key;
+use crate::node::LeafNode;
+use crate::observer::TreeObserver;
+use crate::store::{OverlayStore, TreeStore};
+use crate::tree::FullTree;
+use std::sync::{Arc, Mutex};
+
+/// A tree staging the writes of a transaction on top of the store of another tree, see
+/// `FullTree::update`.
+pub type Transaction<'a, S> = FullTree<OverlayStore<&'a mut S>>;
+
+impl<S: TreeStore> FullTree<S> {
+    /// Runs `f` over a transaction on the tree and commits its writes if it returns `Ok`.
+    ///
+    /// The transaction has the configuration and executor of the tree. Its reads see its own writes,
+    /// and nothing is written to the store before `f` returns. If `f` fails, its writes are dropped
+    /// and the tree is unchanged. Otherwise they are written to the store, and the observers of the
+    /// tree are notified of each insert and delete, then of the root change, as if the updates had been
+    /// applied to the tree directly.
+    ///
+    /// # Returns
+    ///
+    /// - The output of `f`.
+    /// - The error of `f`, or of the store if committing fails, in which case the store may hold some
+    ///   of the staged nodes but still has the old root.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use mssmt::{DefaultStore, FullTree, LeafNode};
+    ///
+    /// let mut tree = FullTree::new(DefaultStore::new());
+    /// tree.insert([1u8; 32], b"one".to_vec(), 1).unwrap();
+    ///
+    /// let (proof, root_hash) = tree
+    ///     .update(|tx| {
+    ///         tx.insert([2u8; 32], b"two".to_vec(), 2)?;
+    ///         // The proof covers the insert above, which is not committed yet
+    ///         Ok((tx.merkle_proof([2u8; 32])?, tx.root()?.node_hash()))
+    ///     })
+    ///     .unwrap();
+    ///
+    /// let leaf = LeafNode::new([2u8; 32], b"two".to_vec(), 2);
+    /// assert!(proof.verify([2u8; 32], &leaf, root_hash));
+    /// assert_eq!(tree.root().unwrap().node_hash(), root_hash);
+    /// ```
+    pub fn update<T>(&mut self, f: impl FnOnce(&mut Transaction<'_, S>) -> Result<T>) -> Result<T> {
+        let old_root_hash = self.root()?.node_hash();
+        let config = self.config().clone();
+        let executor = self.executor().clone();
+        let events = Arc::new(TransactionEvents::default());
+
+        let mut transaction = FullTree::new(OverlayStore::new(self.store_mut()));
+        transaction.set_config(config);
+        transaction.set_executor(executor);
+        transaction.add_observer(events.clone());
+        let output = f(&mut transaction)?;
+        let new_root = transaction.root()?;
+        transaction.into_store().commit()?;
+
+        let events = std::mem::take(&mut *events.0.lock().expect("transaction events lock"));
+        for event in events {
+            match event {
+                TransactionEvent::Insert { leaf, previous } => {
+                    self.notify_insert(&leaf, previous.as_ref())
+                }
+                TransactionEvent::Delete { removed } => self.notify_delete(&removed),
+            }
+        }
+        self.notify_root_change(old_root_hash, new_root.as_ref());
+        self.stamp_leaf_metadata()?;
+        Ok(output)
+    }
+}
+
+/// A leaf update made in a transaction, replayed to the observers of the tree once committed.
+enum TransactionEvent {
+    Insert {
+        leaf: LeafNode,
+        previous: Option<LeafNode>,
+    },
+    Delete {
+        removed: LeafNode,
+    },
+}
+
+/// Records the leaf updates of a transaction, in order.
+#[derive(Default)]
+struct TransactionEvents(Mutex<Vec<TransactionEvent>>);
+
+impl TreeObserver for TransactionEvents {
+    fn on_insert(&self, _key: &Key, leaf: &LeafNode, previous: Option<&LeafNode>) {
+        let event = TransactionEvent::Insert {
+            leaf: leaf.clone(),
+            previous: previous.cloned(),
+        };
+        self.0.lock().expect("transaction events lock").push(event);
+    }
+
+    fn on_delete(&self, _key: &Key, removed: &LeafNode) {
+        let event = TransactionEvent::Delete {
+            removed: removed.clone(),
+        };
+        self.0.lock().expect("transaction events lock").push(event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::MssmtError;
+    use crate::node::{NodeHash, Sum};
+    use crate::store::DefaultStore;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[derive(Default)]
+    struct Counter {
+        updates: AtomicUsize,
+        roots: AtomicUsize,
+    }
+
+    impl TreeObserver for Counter {
+        fn on_insert(&self, _key: &Key, _leaf: &LeafNode, _previous: Option<&LeafNode>) {
+            self.updates.fetch_add(1, Ordering::Relaxed);
+        }
+
+        fn on_delete(&self, _key: &Key, _removed: &LeafNode) {
+            self.updates.fetch_add(1, Ordering::Relaxed);
+        }
+
+        fn on_root_change(&self, _old_root: &NodeHash, _new_root: &NodeHash) {
+            self.roots.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn test_transactions_read_their_writes() -> Result<()> {
+        let mut tree = FullTree::new(DefaultStore::new());
+        for i in 1..=4u8 {
+            tree.insert([i; 32], vec![i], i as Sum)?;
+        }
+        let counter = Arc::new(Counter::default());
+        tree.add_observer(counter.clone());
+        let old_root = tree.root()?.node_hash();
+
+        // A failing transaction leaves the tree and its observers untouched
+        let failed: Result<()> = tree.update(|tx| {
+            tx.delete([1u8; 32])?;
+            assert_eq!(tx.get([1u8; 32])?, None);
+            Err(MssmtError::KeyMismatch)
+        });
+        assert!(matches!(failed, Err(MssmtError::KeyMismatch)));
+        assert_eq!(tree.root()?.node_hash(), old_root);
+        assert_eq!(tree.get([1u8; 32])?, Some((vec![1], 1)));
+        assert_eq!(counter.updates.load(Ordering::Relaxed), 0);
+
+        let root = tree.update(|tx| {
+            tx.delete([1u8; 32])?;
+            tx.insert([2u8; 32], vec![20], 20)?;
+            tx.insert([9u8; 32], vec![9], 9)?;
+            assert_eq!(tx.get([1u8; 32])?, None);
+            assert_eq!(tx.get([2u8; 32])?, Some((vec![20], 20)));
+            let leaf = LeafNode::new([9u8; 32], vec![9], 9);
+            let root = tx.root()?.node_hash();
+            assert!(tx.merkle_proof([9u8; 32])?.verify([9u8; 32], &leaf, root));
+            Ok(root)
+        })?;
+
+        let mut expected = FullTree::new(DefaultStore::new());
+        for (key, value, sum) in [(2u8, 20u8, 20), (3, 3, 3), (4, 4, 4), (9, 9, 9)] {
+            expected.insert([key; 32], vec![value], sum)?;
+        }
+        assert_eq!(root, expected.root()?.node_hash());
+        assert!(tree.equals(&expected)?);
+        assert_eq!(tree.get([1u8; 32])?, None);
+        assert_eq!(counter.updates.load(Ordering::Relaxed), 3);
+        assert_eq!(counter.roots.load(Ordering::Relaxed), 1);
+
+        Ok(())
+    }
+}
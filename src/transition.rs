@@ -0,0 +1,617 @@
+//! Proofs that one root follows from another by a single leaf update.
+//!
+//! An inclusion proof shows what a tree contains; a `TransitionProof` shows how it changed. It carries
+//! the leaf at a key before and after an update together with the siblings along the path of the key,
+//! which the update leaves untouched. Folding the old leaf over the siblings must give the old root and
+//! folding the new leaf over the same siblings must give the new root, so a verifier holding only the two
+//! root commitments learns that exactly this leaf changed and nothing else did. Consensus-critical
+//! consumers can validate state transitions this way without access to the tree.
+//!
+//! A `BatchTransitionProof` does the same for a set of updates, such as the state changes of a block.
+//! The paths of the updated keys share their upper levels, so it carries every subtree hanging off the
+//! union of the paths once, instead of one full set of siblings per key.
+
+use crate::commitment::RootCommitment;
+use crate::config::OverflowPolicy;
+use crate::error::{MssmtError, Result};
+use crate::key::Key;
+use crate::node::{
+    bit_index, branch_hash, tree_levels, EmptyTreeOf, LeafNode, LeafValue, Node, NodeHash, Sum,
+    HASH_SIZE,
+};
+use crate::op::Op;
+use crate::proof::Proof;
+use crate::store::{node_children, TreeStore, TreeStoreReader};
+use crate::tree::FullTree;
+use std::collections::btree_map::Entry;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+/// A proof that a root follows from another by replacing the leaf at one key.
+///
+/// `None` leaves stand for an absent key, so insertions of new keys have no old leaf and deletions
+/// have no new leaf. Tombstones are part of the commitment, so in `DeleteMode::Tombstone` they are
+/// carried as leaves: the old leaf of a tombstoned key and the new leaf of a deletion are tombstones.
+/// `K` is the key size in bytes and `V` the type of the leaf values.
+///
+/// # Examples
+///
+/// ```rust
+/// use mssmt::{DefaultStore, FullTree};
+///
+/// let mut tree = FullTree::new(DefaultStore::new());
+/// tree.insert([1u8; 32], b"one".to_vec(), 1).unwrap();
+/// let before = tree.commitment().unwrap();
+///
+/// let transition = tree
+///     .update_with_transition_proof([2u8; 32], Some((b"two".to_vec(), 2)))
+///     .unwrap();
+/// let after = tree.commitment().unwrap();
+///
+/// assert!(transition.verify(&before, &after));
+/// assert!(!transition.verify(&after, &before));
+/// ```
+#[derive(Clone)]
+pub struct TransitionProof<const K: usize = HASH_SIZE, V = Vec<u8>> {
+    /// The key whose leaf is replaced.
+    pub key: Key<K>,
+    /// The leaf at the key before the update, `None` if the key was absent.
+    pub old_leaf: Option<LeafNode<K, V>>,
+    /// The leaf at the key after the update, `None` if the key was deleted.
+    pub new_leaf: Option<LeafNode<K, V>>,
+    /// The siblings along the path of the key, shared by both roots.
+    pub proof: Proof<K>,
+}
+
+impl<const K: usize, V: LeafValue> TransitionProof<K, V> {
+    /// Verifies that `new_root` is `old_root` with the leaf at the key replaced, and nothing else.
+    ///
+    /// Both the hash and the sum of each root are checked. Transitions that do not change the leaf are
+    /// rejected, as they prove no update.
+    pub fn verify(&self, old_root: &RootCommitment, new_root: &RootCommitment) -> bool {
+        self.new_root(old_root).is_ok_and(|computed| {
+            computed.hash.constant_time_eq(&new_root.hash) && computed.sum == new_root.sum
+        })
+    }
+
+    /// Verifies the old side of the transition against `old_root` and returns the root the update
+    /// leads to, letting a light client holding only `old_root` follow the update.
+    ///
+    /// # Returns
+    ///
+    /// - The commitment to the root after the update.
+    /// - `MssmtError::KeyMismatch` if a leaf is not stored under the key of the transition.
+    /// - `MssmtError::InvalidLeaf` if the old and new leaves are the same.
+    /// - `MssmtError::RootHashMismatch` if the old leaf and the siblings do not give `old_root`.
+    /// - `MssmtError::InvalidProofLength` or `MssmtError::SumOverflow` for malformed proofs.
+    pub fn new_root(&self, old_root: &RootCommitment) -> Result<RootCommitment> {
+        let key = self.key.0;
+        for leaf in [&self.old_leaf, &self.new_leaf].into_iter().flatten() {
+            if leaf.key != key {
+                return Err(MssmtError::KeyMismatch);
+            }
+        }
+        let old_hash = self.old_leaf.as_ref().map(|leaf| leaf.node_hash());
+        if old_hash == self.new_leaf.as_ref().map(|leaf| leaf.node_hash()) {
+            return Err(MssmtError::InvalidLeaf(
+                "the transition does not change the leaf".to_string(),
+            ));
+        }
+
+        let empty = LeafNode::<K>::new([0u8; K], Vec::new(), 0);
+        let old_matches = match &self.old_leaf {
+            Some(leaf) => self.proof.verify_against(key, leaf, old_root),
+            None => self.proof.verify_against(key, &empty, old_root),
+        };
+        if !old_matches {
+            return Err(MssmtError::RootHashMismatch {
+                expected: old_root.hash,
+                actual: match &self.old_leaf {
+                    Some(leaf) => self.proof.root(key, leaf).node_hash(),
+                    None => self.proof.root(key, &empty).node_hash(),
+                },
+            });
+        }
+
+        let (hash, sum) = match &self.new_leaf {
+            Some(leaf) => self.proof.compute_updated_root(key, &empty, leaf)?,
+            None => self.proof.compute_updated_root(key, &empty, &empty)?,
+        };
+        Ok(RootCommitment::new(hash, sum))
+    }
+}
+
+impl<S: TreeStoreReader<K, V>, const K: usize, V: LeafValue> FullTree<S, K, V> {
+    /// Proves the transition from the current root to the root the tree would have after setting the
+    /// leaf at `key` to `new`, or deleting it if `new` is `None`. The tree is not modified.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the nodes on the path of the key cannot be read.
+    pub fn transition_proof(
+        &self,
+        key: impl Into<Key<K>>,
+        new: Option<(V, Sum)>,
+    ) -> Result<TransitionProof<K, V>> {
+        let key = key.into();
+        let old_leaf = self.raw_leaf(&key.0)?;
+        let new_leaf = self.leaf_after(key, old_leaf.is_some(), new);
+        Ok(TransitionProof {
+            key,
+            old_leaf,
+            new_leaf,
+            proof: self.merkle_proof(key)?,
+        })
+    }
+
+    /// Returns the leaf at `key` after setting it to `new`, or deleting it if `new` is `None`, from a
+    /// position holding a leaf if `occupied`. Deletions of a leaf leave its tombstone in
+    /// `DeleteMode::Tombstone`.
+    fn leaf_after(
+        &self,
+        key: Key<K>,
+        occupied: bool,
+        new: Option<(V, Sum)>,
+    ) -> Option<LeafNode<K, V>> {
+        match new {
+            Some((value, sum)) => Some(LeafNode::new(key.0, value, sum)),
+            None if occupied => self.config().tombstone(key.0),
+            None => None,
+        }
+    }
+}
+
+impl<S: TreeStore<K, V>, const K: usize, V: LeafValue> FullTree<S, K, V> {
+    /// Sets the leaf at `key` to `new`, or deletes it if `new` is `None`, and returns the proof of the
+    /// transition from the previous root to the new one.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of `FullTree::insert` and `FullTree::delete`, in which case the tree is not
+    /// modified.
+    pub fn update_with_transition_proof(
+        &mut self,
+        key: impl Into<Key<K>>,
+        new: Option<(V, Sum)>,
+    ) -> Result<TransitionProof<K, V>> {
+        let key = key.into();
+        let transition = self.transition_proof(key, new.clone())?;
+        match new {
+            Some((value, sum)) => {
+                self.insert(key, value, sum)?;
+            }
+            None => {
+                self.delete(key)?;
+            }
+        }
+        Ok(transition)
+    }
+}
+
+/// The change of the leaf at one key in a `BatchTransitionProof`.
+///
+/// `None` leaves stand for an absent key, as in `TransitionProof`.
+#[derive(Clone)]
+pub struct LeafTransition<const K: usize = HASH_SIZE, V = Vec<u8>> {
+    /// The key whose leaf changes.
+    pub key: Key<K>,
+    /// The leaf at the key before the updates, `None` if the key was absent.
+    pub old_leaf: Option<LeafNode<K, V>>,
+    /// The leaf at the key after the updates, `None` if the key is absent afterwards.
+    pub new_leaf: Option<LeafNode<K, V>>,
+}
+
+/// A proof that a root follows from another by a set of leaf updates, and nothing else.
+///
+/// The transitions are sorted by key, one per updated key. The siblings are the roots of the subtrees
+/// hanging off the union of the paths of the keys, in depth-first order from left to right, and are
+/// shared by both roots. A declared delete of an absent key is a transition from `None` to `None`,
+/// which proves the key was absent.
+///
+/// # Examples
+///
+/// ```rust
+/// use mssmt::op::Op;
+/// use mssmt::{DefaultStore, FullTree};
+///
+/// let mut tree = FullTree::new(DefaultStore::new());
+/// tree.insert([1u8; 32], b"one".to_vec(), 1).unwrap();
+/// let before = tree.commitment().unwrap();
+///
+/// let ops = [Op::insert([2u8; 32], b"two".to_vec(), 2), Op::delete([1u8; 32])];
+/// let proof = tree.apply_with_transition_proof(&ops).unwrap();
+/// let after = tree.commitment().unwrap();
+///
+/// assert!(proof.verify_ops(&ops, &before, &after));
+/// assert!(!proof.verify_ops(&ops[..1], &before, &after));
+/// ```
+#[derive(Clone)]
+pub struct BatchTransitionProof<const K: usize = HASH_SIZE, V = Vec<u8>> {
+    /// The leaf changes, sorted by key.
+    pub transitions: Vec<LeafTransition<K, V>>,
+    /// The subtrees off the paths of the updated keys, depth-first from left to right.
+    pub siblings: Vec<Arc<dyn Node>>,
+    /// How sums are combined, which must be the overflow policy of the tree.
+    pub overflow: OverflowPolicy,
+}
+
+/// The hashes and sums of a subtree before and after the updates.
+type RootPair = ((NodeHash, Sum), (NodeHash, Sum));
+
+impl<const K: usize, V: LeafValue> BatchTransitionProof<K, V> {
+    /// Verifies that `new_root` is `old_root` with the leaves of the transitions replaced, and nothing
+    /// else. Both the hash and the sum of each root are checked.
+    pub fn verify(&self, old_root: &RootCommitment, new_root: &RootCommitment) -> bool {
+        self.new_root(old_root).is_ok_and(|computed| {
+            computed.hash.constant_time_eq(&new_root.hash) && computed.sum == new_root.sum
+        })
+    }
+
+    /// Verifies the old side of the transitions against `old_root` and returns the root the updates
+    /// lead to.
+    ///
+    /// # Returns
+    ///
+    /// - The commitment to the root after the updates.
+    /// - `MssmtError::KeyMismatch` if a leaf is not stored under the key of its transition.
+    /// - `MssmtError::InvalidEncoding` if the transitions are not sorted by key or repeat a key.
+    /// - `MssmtError::RootHashMismatch` if the old leaves and the siblings do not give `old_root`.
+    /// - `MssmtError::InvalidProofLength` if there are too few or too many siblings.
+    /// - `MssmtError::SumOverflow` if the sums overflow under the overflow policy of the proof.
+    pub fn new_root(&self, old_root: &RootCommitment) -> Result<RootCommitment> {
+        let (old, new) = self.roots()?;
+        if !old.hash.constant_time_eq(&old_root.hash) || old.sum != old_root.sum {
+            return Err(MssmtError::RootHashMismatch {
+                expected: old_root.hash,
+                actual: old.hash,
+            });
+        }
+        Ok(new)
+    }
+
+    /// Computes the roots before and after the updates from the transitions and the siblings.
+    ///
+    /// This does not check the roots against anything; use `verify` or `new_root` for untrusted proofs.
+    pub fn roots(&self) -> Result<(RootCommitment, RootCommitment)> {
+        for (index, transition) in self.transitions.iter().enumerate() {
+            let leaves = [&transition.old_leaf, &transition.new_leaf];
+            if leaves
+                .into_iter()
+                .flatten()
+                .any(|leaf| leaf.key != transition.key.0)
+            {
+                return Err(MssmtError::KeyMismatch);
+            }
+            if index > 0 && self.transitions[index - 1].key >= transition.key {
+                return Err(MssmtError::InvalidEncoding(
+                    "transitions are not sorted by key".to_string(),
+                ));
+            }
+        }
+
+        let mut siblings = self.siblings.iter();
+        let (old, new) = self.fold(0, &self.transitions, &mut siblings)?;
+        if siblings.len() > 0 {
+            return Err(MssmtError::InvalidProofLength {
+                expected: self.siblings.len() - siblings.len(),
+                actual: self.siblings.len(),
+            });
+        }
+        Ok((
+            RootCommitment::new(old.0, old.1),
+            RootCommitment::new(new.0, new.1),
+        ))
+    }
+
+    /// Folds the transitions below `height`, all sharing the path to the subtree at `height`, with the
+    /// siblings they consume.
+    fn fold<'a>(
+        &self,
+        height: usize,
+        transitions: &[LeafTransition<K, V>],
+        siblings: &mut impl ExactSizeIterator<Item = &'a Arc<dyn Node>>,
+    ) -> Result<RootPair> {
+        if transitions.is_empty() {
+            let sibling = siblings.next().ok_or(MssmtError::InvalidProofLength {
+                expected: self.siblings.len() + 1,
+                actual: self.siblings.len(),
+            })?;
+            let subtree = (sibling.node_hash(), sibling.node_sum());
+            return Ok((subtree, subtree));
+        }
+        if height == tree_levels(K) {
+            let leaf = |leaf: &Option<LeafNode<K, V>>| match leaf {
+                Some(leaf) => (leaf.node_hash(), leaf.sum),
+                None => (EmptyTreeOf::<K>::hash_at(height), 0),
+            };
+            let transition = &transitions[0];
+            return Ok((leaf(&transition.old_leaf), leaf(&transition.new_leaf)));
+        }
+
+        let split = transitions.partition_point(|t| bit_index(height, &t.key.0) == 0);
+        let (left, right) = transitions.split_at(split);
+        let (old_left, new_left) = self.fold(height + 1, left, siblings)?;
+        let (old_right, new_right) = self.fold(height + 1, right, siblings)?;
+        let combine = |left: (NodeHash, Sum), right: (NodeHash, Sum)| {
+            let sum = self
+                .overflow
+                .combine(left.1, right.1)
+                .ok_or(MssmtError::SumOverflow)?;
+            Ok::<_, MssmtError>((branch_hash(&left.0, &right.0, sum), sum))
+        };
+        Ok((combine(old_left, old_right)?, combine(new_left, new_right)?))
+    }
+}
+
+impl BatchTransitionProof {
+    /// Verifies the proof like `verify`, and that its transitions are exactly the effect of applying
+    /// `ops` in order.
+    pub fn verify_ops(
+        &self,
+        ops: &[Op],
+        old_root: &RootCommitment,
+        new_root: &RootCommitment,
+    ) -> bool {
+        let declared = final_leaves(ops);
+        let matches = declared.len() == self.transitions.len()
+            && declared
+                .iter()
+                .zip(&self.transitions)
+                .all(|((key, leaf), transition)| {
+                    // Deletions leave either no leaf or a tombstone
+                    let new_leaf = transition.new_leaf.as_ref();
+                    *key == transition.key
+                        && match (leaf, new_leaf) {
+                            (Some((value, sum)), Some(new_leaf)) => {
+                                (value.as_slice(), *sum)
+                                    == (new_leaf.value.as_slice(), new_leaf.sum)
+                            }
+                            (None, new_leaf) => new_leaf.is_none_or(|leaf| leaf.is_empty()),
+                            (Some(_), None) => false,
+                        }
+                });
+        matches && self.verify(old_root, new_root)
+    }
+}
+
+/// Returns the leaf each key of `ops` ends up with once they are applied in order, sorted by key.
+fn final_leaves(ops: &[Op]) -> BTreeMap<Key, Option<(Vec<u8>, Sum)>> {
+    let mut leaves = BTreeMap::new();
+    for op in ops {
+        let leaf = match op {
+            Op::Insert { value, sum, .. } => Some((value.clone(), *sum)),
+            Op::Delete { .. } => None,
+        };
+        leaves.insert(op.key(), leaf);
+    }
+    leaves
+}
+
+impl<S: TreeStoreReader> FullTree<S> {
+    /// Proves the transition from the current root to the root the tree would have after applying
+    /// `ops` in order. The tree is not modified.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the nodes on the paths of the keys cannot be read.
+    pub fn batch_transition_proof(&self, ops: &[Op]) -> Result<BatchTransitionProof> {
+        // The leaves are replayed key by key, so that deletions leave tombstones where the tree would
+        let mut leaves: BTreeMap<Key, (Option<LeafNode>, Option<LeafNode>)> = BTreeMap::new();
+        for op in ops {
+            let key = op.key();
+            let (_, new_leaf) = match leaves.entry(key) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => {
+                    let old_leaf = self.raw_leaf(&key.0)?;
+                    let new_leaf = old_leaf.clone();
+                    entry.insert((old_leaf, new_leaf))
+                }
+            };
+            let new = match op {
+                Op::Insert { value, sum, .. } => Some((value.clone(), *sum)),
+                Op::Delete { .. } => None,
+            };
+            *new_leaf = self.leaf_after(key, new_leaf.is_some(), new);
+        }
+        let transitions: Vec<LeafTransition> = leaves
+            .into_iter()
+            .map(|(key, (old_leaf, new_leaf))| LeafTransition {
+                key,
+                old_leaf,
+                new_leaf,
+            })
+            .collect();
+
+        let mut siblings = Vec::new();
+        self.collect_siblings(self.root()?, 0, &transitions, &mut siblings)?;
+        Ok(BatchTransitionProof {
+            transitions,
+            siblings,
+            overflow: self.config().overflow_policy(),
+        })
+    }
+
+    /// Appends the subtrees off the paths of the transitions below `node` to `siblings`, in the order
+    /// `BatchTransitionProof::fold` consumes them.
+    fn collect_siblings(
+        &self,
+        node: Arc<dyn Node>,
+        height: usize,
+        transitions: &[LeafTransition],
+        siblings: &mut Vec<Arc<dyn Node>>,
+    ) -> Result<()> {
+        if transitions.is_empty() {
+            siblings.push(node);
+            return Ok(());
+        }
+        if height == tree_levels(HASH_SIZE) {
+            return Ok(());
+        }
+        let (left, right) = node_children(self.store(), &node, height)?;
+        let split = transitions.partition_point(|t| bit_index(height, &t.key.0) == 0);
+        let (left_transitions, right_transitions) = transitions.split_at(split);
+        self.collect_siblings(left, height + 1, left_transitions, siblings)?;
+        self.collect_siblings(right, height + 1, right_transitions, siblings)
+    }
+}
+
+impl<S: TreeStore> FullTree<S> {
+    /// Applies `ops` in order and returns the proof of the transition from the previous root to the
+    /// new one.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of `FullTree::insert` and `FullTree::delete`. The operations before the
+    /// failing one stay applied.
+    pub fn apply_with_transition_proof(&mut self, ops: &[Op]) -> Result<BatchTransitionProof> {
+        let proof = self.batch_transition_proof(ops)?;
+        for op in ops {
+            op.apply(self)?;
+        }
+        Ok(proof)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{DeleteMode, TreeConfig};
+    use crate::store::DefaultStore;
+
+    #[test]
+    fn test_transition_proofs() -> Result<()> {
+        let mut tree = FullTree::new(DefaultStore::new());
+        for i in 1..=8u8 {
+            tree.insert([i; 32], vec![i], i as Sum)?;
+        }
+
+        // Insertions, updates and deletions each prove their transition
+        let updates = [
+            ([9u8; 32], Some((b"new".to_vec(), 9 as Sum))),
+            ([3u8; 32], Some((b"changed".to_vec(), 30 as Sum))),
+            ([5u8; 32], None),
+        ];
+        for (key, new) in updates {
+            let before = tree.commitment()?;
+            let transition = tree.update_with_transition_proof(key, new)?;
+            let after = tree.commitment()?;
+            assert!(transition.verify(&before, &after));
+            assert_eq!(transition.new_root(&before)?, after);
+
+            // Any other claimed leaf or root is rejected
+            let mut forged = transition.clone();
+            forged.new_leaf = Some(LeafNode::new(key, b"forged".to_vec(), 1));
+            assert!(!forged.verify(&before, &after));
+            let mut forged = transition.clone();
+            forged.old_leaf = Some(LeafNode::new([7u8; 32], vec![7], 7));
+            assert!(matches!(
+                forged.new_root(&before),
+                Err(MssmtError::KeyMismatch)
+            ));
+            let wrong_sum = RootCommitment::new(after.hash, after.sum + 1);
+            assert!(!transition.verify(&before, &wrong_sum));
+        }
+
+        // Transitions that change nothing are rejected
+        let before = tree.commitment()?;
+        let noop = tree.transition_proof([1u8; 32], Some((vec![1], 1)))?;
+        assert!(matches!(
+            noop.new_root(&before),
+            Err(MssmtError::InvalidLeaf(_))
+        ));
+        let noop = tree.transition_proof([5u8; 32], None)?;
+        assert!(!noop.verify(&before, &before));
+
+        // Tombstones are carried as the leaves they are in the commitment
+        let mut tree = FullTree::new(DefaultStore::new());
+        tree.set_config(TreeConfig::default().with_delete_mode(DeleteMode::Tombstone));
+        tree.insert([1u8; 32], vec![1], 1)?;
+        tree.insert([2u8; 32], vec![2], 2)?;
+        let updates = [
+            ([1u8; 32], None),
+            ([1u8; 32], Some((b"back".to_vec(), 10 as Sum))),
+            ([1u8; 32], None),
+        ];
+        for (key, new) in updates {
+            let before = tree.commitment()?;
+            let transition = tree.update_with_transition_proof(key, new)?;
+            let after = tree.commitment()?;
+            assert!(transition.verify(&before, &after));
+        }
+        let tombstone = tree.transition_proof([1u8; 32], Some((vec![1], 1)))?;
+        let old_hash = tombstone.old_leaf.map(|leaf| leaf.node_hash());
+        assert_eq!(old_hash, Some(LeafNode::tombstone([1u8; 32]).node_hash()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_batch_transition_proofs() -> Result<()> {
+        let mut tree = FullTree::new(DefaultStore::new());
+        for i in 1..=16u8 {
+            tree.insert([i * 8; 32], vec![i], i as Sum)?;
+        }
+        let before = tree.commitment()?;
+
+        // Later operations on a key override earlier ones, and deleting an absent key proves absence
+        let ops = [
+            Op::insert([8u8; 32], b"first".to_vec(), 1),
+            Op::insert([200u8; 32], b"new".to_vec(), 5),
+            Op::delete([16u8; 32]),
+            Op::delete([3u8; 32]),
+            Op::insert([8u8; 32], b"second".to_vec(), 2),
+        ];
+        let proof = tree.apply_with_transition_proof(&ops)?;
+        let after = tree.commitment()?;
+        assert_eq!(proof.transitions.len(), 4);
+        assert!(proof.verify_ops(&ops, &before, &after));
+        assert_eq!(proof.new_root(&before)?, after);
+
+        // The paths share their siblings
+        let separate = proof.transitions.len() * tree_levels(HASH_SIZE);
+        assert!(proof.siblings.len() < separate);
+
+        // Other declared operations, roots or proof data are rejected
+        assert!(!proof.verify_ops(&ops[..4], &before, &after));
+        assert!(!proof.verify_ops(&ops[2..], &before, &after));
+        assert!(!proof.verify(&after, &after));
+        let mut forged = proof.clone();
+        forged.siblings[0] = Arc::new(LeafNode::new([1u8; 32], b"forged".to_vec(), 0));
+        assert!(!forged.verify(&before, &after));
+        let mut forged = proof.clone();
+        forged.transitions.swap(0, 1);
+        assert!(matches!(
+            forged.roots(),
+            Err(MssmtError::InvalidEncoding(_))
+        ));
+        let mut forged = proof.clone();
+        forged.siblings.pop();
+        assert!(matches!(
+            forged.roots(),
+            Err(MssmtError::InvalidProofLength { .. })
+        ));
+
+        // An empty batch proves the root is unchanged
+        let proof = tree.batch_transition_proof(&[])?;
+        assert!(proof.verify_ops(&[], &after, &after));
+
+        // Deletions in tombstone mode leave tombstones, which later batches start from
+        tree.set_config(TreeConfig::default().with_delete_mode(DeleteMode::Tombstone));
+        let batches = [
+            vec![Op::delete([8u8; 32]), Op::delete([3u8; 32])],
+            vec![
+                Op::insert([8u8; 32], b"back".to_vec(), 8),
+                Op::insert([4u8; 32], b"new".to_vec(), 4),
+                Op::delete([4u8; 32]),
+            ],
+        ];
+        for ops in batches {
+            let before = tree.commitment()?;
+            let proof = tree.apply_with_transition_proof(&ops)?;
+            let after = tree.commitment()?;
+            assert!(proof.verify_ops(&ops, &before, &after));
+        }
+
+        Ok(())
+    }
+}
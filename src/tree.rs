@@ -4,11 +4,29 @@
 //! and computing the total sum of the tree. It operates over a generic storage backend that implements
 //! the `TreeStore` trait.
 
-use crate::node::{bit_index, BranchNode, LeafNode, Node, EMPTY_LEAF_NODE, MAX_TREE_LEVELS};
-use crate::proof::Proof;
-use crate::store::TreeStore;
-use anyhow::Result;
+use crate::cancel::{CancellationToken, Checkpoint};
+use crate::config::{DeleteMode, NodeRetention, TreeConfig};
+use crate::error::{MssmtError, Result};
+use crate::executor::{default_executor, Executor};
+use crate::extremes::SumIndex;
+use crate::history::ArchivedRoot;
+use crate::key::Key;
+use crate::metadata::MetadataChange;
+use crate::metrics::{Metrics, Operation};
+use crate::node::{
+    build_levels, new_branch, tree_levels, BranchNode, EmptyTreeOf, LeafNode, LeafValue, Node,
+    NodeHash, Sum, SumDelta, HASH_SIZE,
+};
+use crate::observer::{RootUpdate, TreeObserver};
+use crate::path::{Height, TreePath};
+use crate::progress::{Progress, ProgressTracker};
+use crate::proof::{Proof, ProofStats};
+use crate::store::{node_children, resolve_node, BoxedStore, TreeStore, TreeStoreReader};
+use crate::tagged::HashScheme;
+use std::fmt;
+use std::sync::mpsc;
 use std::sync::Arc;
+use std::time::Instant;
 
 /// A full Merkle-Sum Sparse Merkle Tree.
 ///
@@ -17,54 +35,992 @@ use std::sync::Arc;
 ///
 /// # Type Parameters
 ///
-/// - `S`: The storage backend implementing the `TreeStore` trait.
+/// - `S`: The storage backend. Read operations require `TreeStoreReader`, while mutations require the
+///   combined `TreeStore` trait.
+/// - `K`: The key size in bytes, 32 by default. The tree has `8 * K` levels, and the key size is
+///   inferred from the store.
+/// - `V`: The type of the leaf values, `Vec<u8>` by default. Values are hashed by their bytes, so a
+///   tree over `String` or `Arc<[u8]>` values has the same root as one over the same bytes as
+///   `Vec<u8>`. The value type is inferred from the store as well.
 ///
 /// # Examples
 ///
 /// ```rust
-/// use mssmt::{DefaultStore, FullTree};
+/// use mssmt::{DefaultStore, FullTree, LeafNode};
 ///
 /// // Initialize a new tree with the default in-memory store
 /// let store = DefaultStore::new();
 /// let tree = FullTree::new(store);
+///
+/// // Trees with 20-byte keys have 160 levels
+/// let mut tree = FullTree::new(DefaultStore::<20>::default());
+/// tree.insert([7u8; 20], b"value".to_vec(), 10).unwrap();
+/// let proof = tree.merkle_proof([7u8; 20]).unwrap();
+/// assert_eq!(proof.nodes.len(), 160);
 /// ```
-pub struct FullTree<S: TreeStore> {
+pub struct FullTree<S, const K: usize = HASH_SIZE, V = Vec<u8>> {
     store: S,
+    observers: Vec<Box<dyn TreeObserver<K, V>>>,
+    subscribers: Vec<mpsc::Sender<RootUpdate>>,
+    root_version: u64,
+    metrics: Option<Arc<dyn Metrics>>,
+    config: TreeConfig<K, V>,
+    hash_scheme: HashScheme,
+    sum_index: Option<SumIndex>,
+    root_history: Option<Vec<ArchivedRoot>>,
+    metadata_changes: Option<Vec<MetadataChange<K>>>,
+    executor: Arc<dyn Executor>,
+}
+
+/// Summarizes the tree as its root hash and sum, its number of leaves and the type of its store.
+///
+/// Counting the leaves walks the whole tree, so formatting a large tree over a slow store is slow.
+/// Errors reading the tree are printed in place of the values they prevent reading.
+///
+/// # Examples
+///
+/// ```rust
+/// use mssmt::{DefaultStore, FullTree};
+///
+/// let mut tree = FullTree::new(DefaultStore::new());
+/// tree.insert([1u8; 32], b"value".to_vec(), 10).unwrap();
+/// let summary = format!("{:?}", tree);
+/// assert!(summary.contains("root_sum: 10, leaves: 1"));
+/// assert!(summary.contains("store: mssmt::store::DefaultStore"));
+/// ```
+impl<S: TreeStoreReader<K, V>, const K: usize, V: LeafValue> fmt::Debug for FullTree<S, K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("FullTree");
+        match self.root() {
+            Ok(root) => debug
+                .field("root_hash", &root.node_hash())
+                .field("root_sum", &root.node_sum()),
+            Err(err) => debug.field("root", &format_args!("{}", err)),
+        };
+        match self
+            .keys()
+            .try_fold(0u64, |leaves, key| key.map(|_| leaves + 1))
+        {
+            Ok(leaves) => debug.field("leaves", &leaves),
+            Err(err) => debug.field("leaves", &format_args!("{}", err)),
+        };
+        debug
+            .field("store", &format_args!("{}", std::any::type_name::<S>()))
+            .finish()
+    }
+}
+
+impl<S: TreeStoreReader<K, V>, const K: usize, V: LeafValue> FullTree<S, K, V> {
+    /// Creates a new `FullTree` with the given storage backend.
+    ///
+    /// # Arguments
+    ///
+    /// - `store`: An instance of a storage backend implementing the `TreeStore` trait.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use mssmt::{DefaultStore, FullTree};
+    ///
+    /// let store = DefaultStore::new();
+    /// let tree = FullTree::new(store);
+    /// ```
+    pub fn new(store: S) -> Self {
+        Self {
+            store,
+            observers: Vec::new(),
+            subscribers: Vec::new(),
+            root_version: 0,
+            metrics: None,
+            config: TreeConfig::default(),
+            hash_scheme: HashScheme::V0,
+            sum_index: None,
+            root_history: None,
+            metadata_changes: None,
+            executor: default_executor(),
+        }
+    }
+
+    /// Returns the root hash of an empty tree with `K`-byte keys.
+    ///
+    /// For 32-byte keys this is `EMPTY_ROOT_HASH`. Every tree starts with this root and returns to it
+    /// once all its leaves are deleted.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use mssmt::node::EMPTY_ROOT_HASH;
+    /// use mssmt::{DefaultStore, FullTree, Node};
+    ///
+    /// let mut tree = FullTree::new(DefaultStore::new());
+    /// tree.insert([1u8; 32], b"one".to_vec(), 1).unwrap();
+    /// tree.delete([1u8; 32]).unwrap();
+    ///
+    /// assert_eq!(tree.root().unwrap().node_hash(), FullTree::<DefaultStore>::empty_root_hash());
+    /// assert_eq!(FullTree::<DefaultStore>::empty_root_hash(), EMPTY_ROOT_HASH);
+    /// ```
+    pub fn empty_root_hash() -> NodeHash {
+        EmptyTreeOf::<K>::hash_at(0)
+    }
+
+    /// Returns the hash of an empty subtree at `height` in a tree with `K`-byte keys.
+    ///
+    /// Height 0 is the root and height `8 * K` the leaves, where the empty subtree is the empty leaf.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `height` is greater than `8 * K`.
+    pub fn empty_hash_at(height: usize) -> NodeHash {
+        EmptyTreeOf::<K>::hash_at(height)
+    }
+}
+
+impl<const K: usize, V: LeafValue> FullTree<BoxedStore<K, V>, K, V> {
+    /// Creates a new `FullTree` over a boxed storage backend.
+    ///
+    /// Trees over different backends have the same type, so the backend can be chosen at runtime.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use mssmt::store::{BoxedStore, ConcurrentStore};
+    /// use mssmt::{DefaultStore, FullTree};
+    ///
+    /// let shared = true;
+    /// let mut tree: FullTree<BoxedStore> = if shared {
+    ///     FullTree::boxed(ConcurrentStore::new())
+    /// } else {
+    ///     FullTree::boxed(DefaultStore::new())
+    /// };
+    /// tree.insert([1u8; 32], b"one".to_vec(), 1).unwrap();
+    /// ```
+    pub fn boxed(store: impl TreeStore<K, V> + Send + Sync + 'static) -> Self {
+        Self::new(Box::new(store))
+    }
+}
+
+impl<S, const K: usize, V> FullTree<S, K, V> {
+    /// Returns a reference to the underlying storage backend.
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// Consumes the tree, returning the underlying storage backend.
+    pub fn into_store(self) -> S {
+        self.store
+    }
+
+    /// Registers an observer notified after every insert, delete and root change.
+    ///
+    /// Observers are called in registration order. See `TreeObserver` for details.
+    pub fn add_observer(&mut self, observer: impl TreeObserver<K, V> + 'static) {
+        self.observers.push(Box::new(observer));
+    }
+
+    /// Registers an already boxed observer, see `FullTree::add_observer`.
+    pub(crate) fn add_boxed_observer(&mut self, observer: Box<dyn TreeObserver<K, V>>) {
+        self.observers.push(observer);
+    }
+
+    /// Returns a channel receiving a `RootUpdate` after every root change of the tree.
+    ///
+    /// Updates are sent after the observers are notified, in the order of the root changes. Dropping
+    /// the receiver ends the subscription.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use mssmt::{DefaultStore, FullTree};
+    ///
+    /// let mut tree = FullTree::new(DefaultStore::new());
+    /// let updates = tree.subscribe();
+    ///
+    /// tree.insert([1u8; 32], b"one".to_vec(), 1).unwrap();
+    /// tree.insert([2u8; 32], b"two".to_vec(), 2).unwrap();
+    ///
+    /// let update = updates.try_iter().last().unwrap();
+    /// assert_eq!((update.root_sum, update.version), (3, 2));
+    /// ```
+    pub fn subscribe(&mut self) -> mpsc::Receiver<RootUpdate> {
+        let (sender, receiver) = mpsc::channel();
+        self.subscribers.push(sender);
+        receiver
+    }
+
+    /// Returns the number of root changes made through the tree since it was created.
+    pub fn root_version(&self) -> u64 {
+        self.root_version
+    }
+
+    /// Reports operation latencies and proof generations to `metrics`.
+    ///
+    /// Node reads and writes are reported by the store, see `MeteredStore`.
+    pub fn set_metrics(&mut self, metrics: Arc<dyn Metrics>) {
+        self.metrics = Some(metrics);
+    }
+
+    /// Runs the parallel work of batch operations, such as integrity audits and parallel batch inserts,
+    /// on `executor` instead of `default_executor`.
+    pub fn set_executor(&mut self, executor: Arc<dyn Executor>) {
+        self.executor = executor;
+    }
+
+    /// Returns the executor running the parallel work of batch operations, see `FullTree::set_executor`.
+    pub fn executor(&self) -> &Arc<dyn Executor> {
+        &self.executor
+    }
+
+    /// Sets the validation policy applied to every inserted leaf, see `TreeConfig`.
+    pub fn set_config(&mut self, config: TreeConfig<K, V>) {
+        self.config = config;
+    }
+
+    /// Returns the validation policy applied to every inserted leaf.
+    pub fn config(&self) -> &TreeConfig<K, V> {
+        &self.config
+    }
+
+    /// Sets the hash scheme the tree commits under, see `HashScheme`.
+    ///
+    /// The scheme only affects `FullTree::commitment` and `FullTree::scheme_proof`: nodes keep their
+    /// `HashScheme::V0` hashes, so the scheme can be changed without rewriting the store.
+    pub fn set_hash_scheme(&mut self, hash_scheme: HashScheme) {
+        self.hash_scheme = hash_scheme;
+    }
+
+    /// Returns the hash scheme the tree commits under, `HashScheme::V0` by default.
+    pub fn hash_scheme(&self) -> HashScheme {
+        self.hash_scheme
+    }
+
+    /// Keeps the smallest and largest leaf sums under each branch, so that `FullTree::max_sum_leaf` and
+    /// `FullTree::min_sum_leaf` descend from the root instead of scanning every leaf.
+    ///
+    /// The index is filled by the first query and extended on demand after updates. It is held in
+    /// memory and only grows, by one entry per branch the queries have visited.
+    pub fn enable_sum_index(&mut self) {
+        if self.sum_index.is_none() {
+            self.sum_index = Some(SumIndex::default());
+        }
+    }
+
+    /// Records a `LeafMetadata` for every key the tree inserts, and removes it when the key is deleted,
+    /// see the `metadata` module.
+    ///
+    /// Keys already in the tree get metadata the next time they are inserted.
+    pub fn enable_leaf_metadata(&mut self) {
+        if self.metadata_changes.is_none() {
+            self.metadata_changes = Some(Vec::new());
+        }
+    }
+
+    /// Returns the metadata changes of the last update, leaving none pending.
+    pub(crate) fn take_metadata_changes(&mut self) -> Vec<MetadataChange<K>> {
+        self.metadata_changes
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    /// Returns the sum index of the tree, if enabled.
+    pub(crate) fn sum_index(&self) -> Option<&SumIndex> {
+        self.sum_index.as_ref()
+    }
+
+    /// Returns the archived roots of the tree, if the root history is enabled.
+    pub(crate) fn root_history_mut(&mut self) -> &mut Option<Vec<ArchivedRoot>> {
+        &mut self.root_history
+    }
+
+    /// Returns the archived roots of the tree, oldest first, or an empty slice if the root history is not
+    /// enabled, see `FullTree::enable_root_history`.
+    pub fn root_history(&self) -> &[ArchivedRoot] {
+        self.root_history.as_deref().unwrap_or_default()
+    }
+
+    fn record_operation(&self, operation: Operation, start: Instant) {
+        if let Some(metrics) = &self.metrics {
+            metrics.operation_completed(operation, start.elapsed());
+        }
+    }
+
+    fn record_proofs(&self, count: usize, start: Instant) {
+        if let Some(metrics) = &self.metrics {
+            metrics.proofs_generated(count);
+            metrics.operation_completed(Operation::Proof, start.elapsed());
+        }
+    }
+
+    /// Returns a mutable reference to the underlying storage backend.
+    pub(crate) fn store_mut(&mut self) -> &mut S {
+        &mut self.store
+    }
+}
+
+/// The number of levels `FullTree::warm_cache` preloads, at most 4095 branches.
+pub const WARM_CACHE_DEPTH: usize = 12;
+
+impl<S: TreeStoreReader<K, V>, const K: usize, V: LeafValue> FullTree<S, K, V> {
+    /// Returns the root node of the MS-SMT.
+    pub fn root(&self) -> Result<Arc<dyn Node>> {
+        self.store.root_node()
+    }
+
+    /// Pulls the top `WARM_CACHE_DEPTH` levels of the tree into the memory of the store, see
+    /// `TreeStoreReader::preload`.
+    ///
+    /// Called once after process start, it spares the first proofs the cold reads of the levels every
+    /// path goes through. Stores without a cache ignore it.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use mssmt::store::CachedStore;
+    /// use mssmt::{DefaultStore, FullTree, Node, TreeStoreReader};
+    ///
+    /// let mut tree = FullTree::new(CachedStore::new(DefaultStore::new(), 1024));
+    /// tree.insert([1u8; 32], b"one".to_vec(), 1).unwrap();
+    /// tree.store().clear_cache();
+    ///
+    /// tree.warm_cache().unwrap();
+    /// let root = tree.root().unwrap().node_hash();
+    /// assert!(tree.store().get_branch(&root).unwrap().is_some());
+    /// assert_eq!(tree.store().cache_stats().hits, 1);
+    /// ```
+    pub fn warm_cache(&self) -> Result<()> {
+        let root_hash = self.store.root_node()?.node_hash();
+        self.store.preload(&[root_hash], WARM_CACHE_DEPTH)
+    }
+
+    /// Retrieves the value and sum associated with a key.
+    ///
+    /// # Arguments
+    ///
+    /// - `key`: The key, as a `Key` or a 32-byte array to retrieve.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(Some((value, sum)))` if the key exists, with the stored value and sum.
+    /// - `Ok(None)` if the key does not exist.
+    ///
+    pub fn get(&self, key: impl Into<Key<K>>) -> Result<Option<(V, Sum)>> {
+        let key = key.into().0;
+        debug_span!("get", key = %hex::encode(&key[..4]));
+        // Stores with a key index can answer point lookups without a path traversal
+        let start = Instant::now();
+        if !self.store.may_contain_key(&key)? {
+            debug_event!(found = false, filtered = true, "lookup finished");
+            self.record_operation(Operation::Get, start);
+            return Ok(None);
+        }
+        // Empty leaves, such as tombstones, may read as absent
+        if let Some(leaf_node) = self
+            .store
+            .get_leaf_by_key(&key)?
+            .filter(|leaf| !self.config.reads_as_absent(leaf))
+        {
+            debug_event!(found = true, indexed = true, "lookup finished");
+            self.record_operation(Operation::Get, start);
+            return Ok(Some((leaf_node.value.clone(), leaf_node.sum)));
+        }
+
+        let node = self.store.root_node()?;
+        let result = self.get_at_node(node, 0, &key)?;
+        debug_event!(found = result.is_some(), indexed = false, "lookup finished");
+        self.record_operation(Operation::Get, start);
+        Ok(result)
+    }
+
+    /// Returns `true` if the tree holds a leaf for `key`.
+    ///
+    /// Stores with a key index, such as `DefaultStore`, answer in constant time, and stores with a key
+    /// filter, such as `FilteredStore`, rule out most absent keys; other keys are checked by walking the
+    /// path of the key.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use mssmt::{DefaultStore, FullTree};
+    ///
+    /// let mut tree = FullTree::new(DefaultStore::new());
+    /// tree.insert([1u8; 32], b"value".to_vec(), 10).unwrap();
+    ///
+    /// assert!(tree.contains_key([1u8; 32]).unwrap());
+    /// assert!(!tree.contains_key([2u8; 32]).unwrap());
+    /// ```
+    pub fn contains_key(&self, key: impl Into<Key<K>>) -> Result<bool> {
+        let key = key.into().0;
+        if !self.store.may_contain_key(&key)? {
+            return Ok(false);
+        }
+        if let Some(leaf) = self.store.get_leaf_by_key(&key)? {
+            return Ok(!self.config.reads_as_absent(&leaf));
+        }
+        Ok(self
+            .get_at_node(self.store.root_node()?, 0, &key)?
+            .is_some())
+    }
+
+    /// Retrieves the values and sums of many keys in a single traversal.
+    ///
+    /// The keys are sorted so that keys sharing a path prefix are looked up together: every branch on
+    /// the combined paths is loaded from the store only once, and empty subtrees are not descended into.
+    ///
+    /// # Returns
+    ///
+    /// - One entry per key, in the order the keys were given, `None` for absent keys.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use mssmt::{DefaultStore, FullTree};
+    ///
+    /// let mut tree = FullTree::new(DefaultStore::new());
+    /// tree.insert([1u8; 32], b"one".to_vec(), 1).unwrap();
+    /// tree.insert([2u8; 32], b"two".to_vec(), 2).unwrap();
+    ///
+    /// let entries = tree.get_many([[2u8; 32], [3u8; 32], [1u8; 32]]).unwrap();
+    /// assert_eq!(
+    ///     entries,
+    ///     [Some((b"two".to_vec(), 2)), None, Some((b"one".to_vec(), 1))]
+    /// );
+    /// ```
+    pub fn get_many(
+        &self,
+        keys: impl IntoIterator<Item = impl Into<Key<K>>>,
+    ) -> Result<Vec<Option<(V, Sum)>>> {
+        let mut keys: Vec<(usize, [u8; K])> = keys
+            .into_iter()
+            .enumerate()
+            .map(|(index, key)| (index, key.into().0))
+            .collect();
+        keys.sort_unstable_by_key(|(_, key)| *key);
+        debug_span!("get_many", keys = keys.len());
+        let start = Instant::now();
+
+        let mut entries = vec![None; keys.len()];
+        if !keys.is_empty() {
+            let root = self.store.root_node()?;
+            self.get_many_at_node(root, 0, &keys, &mut entries)?;
+        }
+        self.record_operation(Operation::Get, start);
+        Ok(entries)
+    }
+
+    /// Looks up the sorted `keys` below `node`, storing the entries found at their index in `entries`.
+    fn get_many_at_node(
+        &self,
+        node: Arc<dyn Node>,
+        height: usize,
+        keys: &[(usize, [u8; K])],
+        entries: &mut [Option<(V, Sum)>],
+    ) -> Result<()> {
+        if EmptyTreeOf::<K>::is_empty_at(height, &node.node_hash()) {
+            return Ok(());
+        }
+        if height == tree_levels(K) {
+            let node = resolve_node(&self.store, &node, height)?;
+            if let Some(leaf) = node
+                .as_leaf::<K, V>()
+                .filter(|leaf| !self.config.reads_as_absent(leaf))
+            {
+                for (index, key) in keys {
+                    if leaf.key == *key {
+                        entries[*index] = Some((leaf.value.clone(), leaf.sum));
+                    }
+                }
+            }
+            return Ok(());
+        }
+
+        let (left, right) = node_children(&self.store, &node, height)?;
+        let split =
+            keys.partition_point(|(_, key)| TreePath::from(*key).is_left(Height::at(height)));
+        let (left_keys, right_keys) = keys.split_at(split);
+        if !left_keys.is_empty() {
+            self.get_many_at_node(left, height + 1, left_keys, entries)?;
+        }
+        if !right_keys.is_empty() {
+            self.get_many_at_node(right, height + 1, right_keys, entries)?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn get_at_node(
+        &self,
+        node: Arc<dyn Node>,
+        height: usize,
+        key: &[u8; K],
+    ) -> Result<Option<(V, Sum)>> {
+        let node = resolve_node(&self.store, &node, height)?;
+        if height == tree_levels(K) {
+            if let Some(leaf_node) = node.as_leaf::<K, V>() {
+                // The empty leaf carries the all-zero key, which must not be reported as present
+                if leaf_node.key == *key && !self.config.reads_as_absent(leaf_node) {
+                    return Ok(Some((leaf_node.value.clone(), leaf_node.sum)));
+                }
+            }
+            return Ok(None);
+        }
+
+        let is_left = TreePath::from(*key).is_left(Height::at(height));
+
+        if let Some(branch_node) = node.as_branch() {
+            if is_left {
+                self.get_at_node(branch_node.left.clone(), height + 1, key)
+            } else {
+                self.get_at_node(branch_node.right.clone(), height + 1, key)
+            }
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Returns the leaf stored at the position of `key`, including tombstones and other leaves that
+    /// read as absent, but not the shared empty leaf of unoccupied positions.
+    pub(crate) fn raw_leaf(&self, key: &[u8; K]) -> Result<Option<LeafNode<K, V>>> {
+        let levels = tree_levels(K);
+        let mut node = self.store.root_node()?;
+        for height in 0..levels {
+            if EmptyTreeOf::<K>::is_empty_at(height, &node.node_hash()) {
+                return Ok(None);
+            }
+            let (left, right) = node_children(&self.store, &node, height)?;
+            node = if TreePath::from(*key).is_left(Height::at(height)) {
+                left
+            } else {
+                right
+            };
+        }
+        let node = resolve_node(&self.store, &node, levels)?;
+        Ok(node
+            .as_leaf::<K, V>()
+            .filter(|leaf| leaf.key == *key && !(leaf.is_empty() && leaf.key == [0u8; K]))
+            .cloned())
+    }
+
+    /// Generates a Merkle proof for a given key.
+    ///
+    /// The proof can be used to verify the inclusion and sum of the key's value in the tree without having access to the entire tree.
+    ///
+    /// # Arguments
+    ///
+    /// - `key`: The key, as a `Key` or a 32-byte array for which to generate the proof.
+    ///
+    /// # Returns
+    ///
+    /// - A `Proof` struct containing the necessary nodes for verification.
+    pub fn merkle_proof(&self, key: impl Into<Key<K>>) -> Result<Proof<K>> {
+        let key = key.into().0;
+        debug_span!("merkle_proof", key = %hex::encode(&key[..4]));
+        let start = Instant::now();
+        let proof = self.merkle_proof_at(self.store.root_node()?, &key)?;
+        debug_event!(non_empty = proof.non_empty_nodes(), "proof generated");
+        self.record_proofs(1, start);
+        Ok(proof)
+    }
+
+    /// Generates a Merkle proof for `key` in the version of the tree with root `root`.
+    pub(crate) fn merkle_proof_at(&self, root: Arc<dyn Node>, key: &[u8; K]) -> Result<Proof<K>> {
+        let mut proof_nodes = Vec::new();
+        self.generate_proof(root, 0, key, &mut proof_nodes)?;
+        Ok(Proof::new(proof_nodes).with_overflow(self.config.overflow_policy()))
+    }
+
+    /// Retrieves the value and sum of a key together with its Merkle proof.
+    ///
+    /// The leaf is read and the proof collected in a single walk from the root, instead of a `get`
+    /// followed by a `merkle_proof`.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(Some((value, sum, proof)))` if the key exists.
+    /// - `Ok(None)` if the key does not exist.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use mssmt::{DefaultStore, FullTree, LeafNode, Node};
+    ///
+    /// let mut tree = FullTree::new(DefaultStore::new());
+    /// tree.insert([1u8; 32], b"value".to_vec(), 10).unwrap();
+    ///
+    /// let (value, sum, proof) = tree.get_with_proof([1u8; 32]).unwrap().unwrap();
+    /// let leaf = LeafNode::new([1u8; 32], value, sum);
+    /// assert!(proof.verify([1u8; 32], &leaf, tree.root().unwrap().node_hash()));
+    /// assert!(tree.get_with_proof([2u8; 32]).unwrap().is_none());
+    /// ```
+    pub fn get_with_proof(&self, key: impl Into<Key<K>>) -> Result<Option<(V, Sum, Proof<K>)>> {
+        let key = key.into().0;
+        debug_span!("get_with_proof", key = %hex::encode(&key[..4]));
+        let start = Instant::now();
+        let levels = tree_levels(K);
+        let mut node = self.store.root_node()?;
+        let mut siblings = Vec::with_capacity(levels);
+        for height in 0..levels {
+            let (left, right) = node_children(&self.store, &node, height)?;
+            node = if TreePath::from(key).is_left(Height::at(height)) {
+                siblings.push(right);
+                left
+            } else {
+                siblings.push(left);
+                right
+            };
+        }
+
+        let node = resolve_node(&self.store, &node, levels)?;
+        let result = node
+            .as_leaf::<K, V>()
+            .filter(|leaf| leaf.key == key && !self.config.reads_as_absent(leaf))
+            .map(|leaf| {
+                (
+                    leaf.value.clone(),
+                    leaf.sum,
+                    Proof::new(siblings).with_overflow(self.config.overflow_policy()),
+                )
+            });
+        debug_event!(found = result.is_some(), "lookup finished");
+        self.record_proofs(1, start);
+        Ok(result)
+    }
+
+    fn generate_proof(
+        &self,
+        node: Arc<dyn Node>,
+        height: usize,
+        key: &[u8; K],
+        proof_nodes: &mut Vec<Arc<dyn Node>>,
+    ) -> Result<()> {
+        if height == tree_levels(K) {
+            return Ok(());
+        }
+
+        let (left, right) = node_children(&self.store, &node, height)?;
+        if TreePath::from(*key).is_left(Height::at(height)) {
+            proof_nodes.push(right);
+            self.generate_proof(left, height + 1, key, proof_nodes)?;
+        } else {
+            proof_nodes.push(left);
+            self.generate_proof(right, height + 1, key, proof_nodes)?;
+        }
+
+        Ok(())
+    }
+
+    /// Generates Merkle proofs for many keys in a single traversal.
+    ///
+    /// The keys are sorted so that keys sharing a path prefix are handled together: every branch on
+    /// the combined paths is loaded from the store only once, and empty subtrees are not descended into.
+    /// This is considerably faster than calling `merkle_proof` for each key when proving many keys.
+    ///
+    /// # Arguments
+    ///
+    /// - `keys`: The keys to generate proofs for. Keys may repeat and do not need to be present.
+    ///
+    /// # Returns
+    ///
+    /// - One `Proof` per key, in the order the keys were given.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use mssmt::{DefaultStore, FullTree};
+    ///
+    /// let mut tree = FullTree::new(DefaultStore::new());
+    /// tree.insert([1u8; 32], b"one".to_vec(), 1).unwrap();
+    /// tree.insert([2u8; 32], b"two".to_vec(), 2).unwrap();
+    ///
+    /// let proofs = tree.merkle_proofs([[2u8; 32], [1u8; 32]]).unwrap();
+    /// assert_eq!(proofs[0], tree.merkle_proof([2u8; 32]).unwrap());
+    /// assert_eq!(proofs[1], tree.merkle_proof([1u8; 32]).unwrap());
+    /// ```
+    pub fn merkle_proofs(
+        &self,
+        keys: impl IntoIterator<Item = impl Into<Key<K>>>,
+    ) -> Result<Vec<Proof<K>>> {
+        let mut keys: Vec<(usize, [u8; K])> = keys
+            .into_iter()
+            .enumerate()
+            .map(|(index, key)| (index, key.into().0))
+            .collect();
+        keys.sort_unstable_by_key(|(_, key)| *key);
+        debug_span!("merkle_proofs", keys = keys.len());
+        let start = Instant::now();
+
+        let mut proof_nodes = vec![Vec::with_capacity(tree_levels(K)); keys.len()];
+        if !keys.is_empty() {
+            let root = self.store.root_node()?;
+            self.generate_proofs(root, 0, &keys, &mut proof_nodes)?;
+        }
+        self.record_proofs(keys.len(), start);
+        let overflow = self.config.overflow_policy();
+        Ok(proof_nodes
+            .into_iter()
+            .map(|nodes| Proof::new(nodes).with_overflow(overflow))
+            .collect())
+    }
+
+    /// Appends the siblings below `node` to the proofs of `keys`, which must be sorted.
+    fn generate_proofs(
+        &self,
+        node: Arc<dyn Node>,
+        height: usize,
+        keys: &[(usize, [u8; K])],
+        proof_nodes: &mut [Vec<Arc<dyn Node>>],
+    ) -> Result<()> {
+        if height == tree_levels(K) {
+            return Ok(());
+        }
+
+        // Every sibling below an empty subtree is itself empty
+        if EmptyTreeOf::<K>::is_empty_at(height, &node.node_hash()) {
+            for (index, _) in keys {
+                proof_nodes[*index]
+                    .extend(EmptyTreeOf::<K>::nodes_from(height + 1).iter().cloned());
+            }
+            return Ok(());
+        }
+
+        let (left, right) = node_children(&self.store, &node, height)?;
+
+        // Sorted keys going left come before the keys going right
+        let split =
+            keys.partition_point(|(_, key)| TreePath::from(*key).is_left(Height::at(height)));
+        let (left_keys, right_keys) = keys.split_at(split);
+        for (index, _) in left_keys {
+            proof_nodes[*index].push(right.clone());
+        }
+        for (index, _) in right_keys {
+            proof_nodes[*index].push(left.clone());
+        }
+
+        if !left_keys.is_empty() {
+            self.generate_proofs(left, height + 1, left_keys, proof_nodes)?;
+        }
+        if !right_keys.is_empty() {
+            self.generate_proofs(right, height + 1, right_keys, proof_nodes)?;
+        }
+        Ok(())
+    }
+
+    /// Returns `true` if the tree contains no leaves.
+    ///
+    /// The root is compared against the precomputed root of the empty tree. In `DeleteMode::Tombstone`
+    /// a tree holding only tombstones is empty too, which is checked by walking to its first leaf that
+    /// does not read as absent.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use mssmt::{DefaultStore, FullTree};
+    ///
+    /// let mut tree = FullTree::new(DefaultStore::new());
+    /// assert!(tree.is_empty().unwrap());
+    ///
+    /// tree.insert([1u8; 32], b"value".to_vec(), 10).unwrap();
+    /// assert!(!tree.is_empty().unwrap());
+    ///
+    /// tree.delete([1u8; 32]).unwrap();
+    /// assert!(tree.is_empty().unwrap());
+    /// ```
+    pub fn is_empty(&self) -> Result<bool> {
+        if self.root()?.node_hash() == EmptyTreeOf::<K>::hash_at(0) {
+            return Ok(true);
+        }
+        if self.config.delete_mode() != DeleteMode::Tombstone {
+            return Ok(false);
+        }
+        Ok(self.keys().next().transpose()?.is_none())
+    }
+
+    /// Returns the total sum of all values in the tree.
+    ///
+    /// # Returns
+    ///
+    /// - The sum of all `sum` values associated with the keys in the tree.
+    ///
+    pub fn total_sum(&self) -> Result<Sum> {
+        let root = self.root()?;
+        Ok(root.node_sum())
+    }
+}
+
+impl<S: TreeStoreReader> FullTree<S> {
+    /// Generates proofs for a sample of keys and returns their size statistics.
+    ///
+    /// Proof sizes depend on how densely the tree is populated around each key, so sampling keys that
+    /// are representative of the expected lookups estimates the bandwidth needed to distribute proofs.
+    /// Keys do not need to be present in the tree, in which case their non-inclusion proofs are measured.
+    ///
+    /// # Arguments
+    ///
+    /// - `sample_keys`: The keys to generate proofs for.
+    ///
+    /// # Returns
+    ///
+    /// - The `ProofStats` of the generated proofs.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use mssmt::{DefaultStore, FullTree};
+    ///
+    /// let mut tree = FullTree::new(DefaultStore::new());
+    /// tree.insert([1u8; 32], b"one".to_vec(), 1).unwrap();
+    /// tree.insert([2u8; 32], b"two".to_vec(), 2).unwrap();
+    ///
+    /// let stats = tree.proof_stats([[1u8; 32], [2u8; 32]]).unwrap();
+    /// assert_eq!(stats.proofs, 2);
+    /// assert_eq!(stats.mean_non_empty_nodes, 1.0);
+    /// ```
+    pub fn proof_stats(
+        &self,
+        sample_keys: impl IntoIterator<Item = impl Into<Key>>,
+    ) -> Result<ProofStats> {
+        let proofs = self.merkle_proofs(sample_keys)?;
+        Ok(ProofStats::from_proofs(&proofs))
+    }
 }
 
 impl<S: TreeStore> FullTree<S> {
-    /// Creates a new `FullTree` with the given storage backend.
+    /// Builds a tree holding the given leaves in `store`.
     ///
-    /// # Arguments
+    /// The tree is built bottom-up one level at a time instead of by repeated inserts, so each branch is
+    /// hashed and written once. The branches of a level are hashed together on `default_executor`, in
+    /// parallel when the `rayon` feature is enabled, or on another executor with `FullTree::from_leaves_on`.
+    /// Empty leaves are skipped, and a later leaf for a key replaces an earlier one.
     ///
-    /// - `store`: An instance of a storage backend implementing the `TreeStore` trait.
+    /// The root of `store` is replaced, so the store should not hold another tree.
+    ///
+    /// # Returns
+    ///
+    /// - The tree over `store`.
+    /// - `MssmtError::SumOverflow` if the sums of the leaves overflow.
     ///
     /// # Examples
     ///
     /// ```rust
-    /// use mssmt::{DefaultStore, FullTree};
+    /// use mssmt::{DefaultStore, FullTree, LeafNode, Node};
     ///
-    /// let store = DefaultStore::new();
-    /// let tree = FullTree::new(store);
+    /// let leaves = (0..4u8).map(|i| LeafNode::new([i; 32], vec![i], i.into()));
+    /// let tree = FullTree::from_leaves(DefaultStore::new(), leaves).unwrap();
+    ///
+    /// let mut inserted = FullTree::new(DefaultStore::new());
+    /// for i in 0..4u8 {
+    ///     inserted.insert([i; 32], vec![i], i.into()).unwrap();
+    /// }
+    /// assert_eq!(tree.root().unwrap().node_hash(), inserted.root().unwrap().node_hash());
     /// ```
-    pub fn new(store: S) -> Self {
-        Self { store }
+    pub fn from_leaves(store: S, leaves: impl IntoIterator<Item = LeafNode>) -> Result<Self> {
+        Self::from_leaves_on(store, leaves, default_executor())
     }
 
-    /// Returns the root node of the MS-SMT.
-    pub fn root(&self) -> Result<Arc<dyn Node>> {
-        self.store.root_node()
+    /// Builds a tree holding the given leaves in `store`, hashing on `executor`.
+    ///
+    /// Works like `FullTree::from_leaves`, and the tree runs its batch operations on `executor` too.
+    pub fn from_leaves_on(
+        store: S,
+        leaves: impl IntoIterator<Item = LeafNode>,
+        executor: Arc<dyn Executor>,
+    ) -> Result<Self> {
+        let token = CancellationToken::new();
+        let mut checkpoint = Checkpoint::default();
+        Self::build_from_leaves(store, leaves, executor, &token, &mut checkpoint, |_| {})
+    }
+
+    /// Builds a tree holding the given leaves in `store`, until done or cancelled.
+    ///
+    /// Works like `FullTree::from_leaves`, checking `token` once the tree is built in memory and between
+    /// node writes. The root of `store` is only updated once every node is written, and the first
+    /// `checkpoint.completed` node writes are skipped, so a cancelled build is resumed by calling this
+    /// again with the same leaves and checkpoint. Pass the store by mutable reference to keep it when the
+    /// build is cancelled.
+    ///
+    /// `progress` is called after each node write, counting the nodes to write as the items of the build.
+    ///
+    /// # Returns
+    ///
+    /// - The tree over `store`.
+    /// - `MssmtError::SumOverflow` if the sums of the leaves overflow.
+    /// - `MssmtError::Cancelled` if the token was cancelled before the root was updated.
+    pub fn from_leaves_cancellable(
+        store: S,
+        leaves: impl IntoIterator<Item = LeafNode>,
+        token: &CancellationToken,
+        checkpoint: &mut Checkpoint,
+        progress: impl FnMut(Progress),
+    ) -> Result<Self> {
+        Self::build_from_leaves(
+            store,
+            leaves,
+            default_executor(),
+            token,
+            checkpoint,
+            progress,
+        )
+    }
+
+    /// Builds a tree holding `leaves`, see `FullTree::from_leaves_cancellable`.
+    fn build_from_leaves(
+        mut store: S,
+        leaves: impl IntoIterator<Item = LeafNode>,
+        executor: Arc<dyn Executor>,
+        token: &CancellationToken,
+        checkpoint: &mut Checkpoint,
+        progress: impl FnMut(Progress),
+    ) -> Result<Self> {
+        let mut leaves: Vec<LeafNode> =
+            leaves.into_iter().filter(|leaf| !leaf.is_empty()).collect();
+        // The sort is stable, so the last leaf for a key is the last of its run
+        leaves.sort_by_key(|leaf| leaf.key);
+        leaves.reverse();
+        leaves.dedup_by_key(|leaf| leaf.key);
+        leaves.reverse();
+
+        let mut branches = Vec::new();
+        let root = build_levels(0, &leaves, executor.as_ref(), |height, branch| {
+            branches.push((height, branch.clone()))
+        })?;
+        // Leaves are written before branches, so the checkpoint covers all leaves before any branch
+        let leaf_count = leaves.len();
+        let total = (leaf_count + branches.len()) as u64;
+        let mut progress = ProgressTracker::new(progress, Some(total), checkpoint.completed as u64);
+        for leaf in leaves.into_iter().skip(checkpoint.completed) {
+            token.check()?;
+            store.insert_leaf(Arc::new(leaf))?;
+            checkpoint.completed += 1;
+            progress.advance(1, 1);
+        }
+        for (height, branch) in branches.into_iter().skip(checkpoint.completed - leaf_count) {
+            token.check()?;
+            store.insert_branch_at(height, branch)?;
+            checkpoint.completed += 1;
+            progress.advance(1, 1);
+        }
+        token.check()?;
+        store.update_root(root)?;
+        let mut tree = Self::new(store);
+        tree.set_executor(executor);
+        Ok(tree)
     }
+}
 
+impl<S: TreeStore<K, V>, const K: usize, V: LeafValue> FullTree<S, K, V> {
     /// Inserts a key-value-sum entry into the tree.
     ///
     /// If the key already exists, its value and sum are updated.
     ///
     /// # Arguments
     ///
-    /// - `key`: A 32-byte array representing the key.
-    /// - `value`: A vector of bytes representing the value associated with the key.
-    /// - `sum`: A 64-bit unsigned integer representing the sum associated with the key.
+    /// - `key`: The key, as a `Key` or a 32-byte array.
+    /// - `value`: The value associated with the key, converted into the value type of the tree.
+    /// - `sum`: The sum associated with the key.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(Some((value, sum)))` with the previous value and sum if the key was overwritten.
+    /// - `Ok(None)` if the key was not present.
+    /// - `MssmtError::InvalidLeaf` if the leaf is rejected by the configuration of the tree.
     ///
     /// # Examples
     ///
@@ -77,296 +1033,944 @@ impl<S: TreeStore> FullTree<S> {
     /// let key = to_array(&Sha256::digest(b"key1"));
     /// let value = b"value1".to_vec();
     /// let sum = 10;
-    /// tree.insert(key, value, sum).unwrap();
+    /// assert_eq!(tree.insert(key, value, sum).unwrap(), None);
+    ///
+    /// let previous = tree.insert(key, b"value2".to_vec(), 20).unwrap();
+    /// assert_eq!(previous, Some((b"value1".to_vec(), 10)));
+    /// ```
+    pub fn insert(
+        &mut self,
+        key: impl Into<Key<K>>,
+        value: impl Into<V>,
+        sum: Sum,
+    ) -> Result<Option<(V, Sum)>> {
+        let key = key.into().0;
+        let (previous, _) = self.insert_leaf_node(key, value.into(), sum, &mut Vec::new())?;
+        Ok(previous.map(|leaf| (leaf.value, leaf.sum)))
+    }
+
+    /// Inserts a key-value-sum entry and returns the new root hash together with a proof for the
+    /// inserted leaf.
+    ///
+    /// The sibling nodes are collected while the path is rebuilt, so no second traversal is needed
+    /// to produce the proof.
+    ///
+    /// # Arguments
+    ///
+    /// - `key`: The key, as a `Key` or a 32-byte array.
+    /// - `value`: The value associated with the key, converted into the value type of the tree.
+    /// - `sum`: The sum associated with the key.
+    ///
+    /// # Returns
+    ///
+    /// - The hash of the new root and a `Proof` of the inserted leaf against it.
+    /// - `MssmtError::InvalidLeaf` if the leaf is rejected by the configuration of the tree.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use mssmt::{DefaultStore, FullTree, LeafNode};
+    ///
+    /// let mut tree = FullTree::new(DefaultStore::new());
+    /// let (root_hash, proof) = tree.insert_with_proof([1u8; 32], b"value".to_vec(), 10).unwrap();
+    ///
+    /// let leaf = LeafNode::new([1u8; 32], b"value".to_vec(), 10);
+    /// assert!(proof.verify([1u8; 32], &leaf, root_hash));
     /// ```
-    pub fn insert(&mut self, key: [u8; 32], value: Vec<u8>, sum: u64) -> Result<()> {
+    pub fn insert_with_proof(
+        &mut self,
+        key: impl Into<Key<K>>,
+        value: impl Into<V>,
+        sum: Sum,
+    ) -> Result<(NodeHash, Proof<K>)> {
+        let key = key.into().0;
+        let mut siblings = Vec::with_capacity(tree_levels(K));
+        let (_, root_hash) = self.insert_leaf_node(key, value.into(), sum, &mut siblings)?;
+        Ok((
+            root_hash,
+            Proof::new(siblings).with_overflow(self.config.overflow_policy()),
+        ))
+    }
+
+    /// Inserts a leaf, returning the replaced leaf and the new root hash.
+    ///
+    /// The siblings along the path are appended to `siblings` in root-first order.
+    fn insert_leaf_node(
+        &mut self,
+        key: [u8; K],
+        value: V,
+        sum: Sum,
+        siblings: &mut Vec<Arc<dyn Node>>,
+    ) -> Result<(Option<LeafNode<K, V>>, NodeHash)> {
+        debug_span!("insert", key = %hex::encode(&key[..4]), sum);
+        let start = Instant::now();
         let leaf_node = Arc::new(LeafNode::new(key, value, sum));
+        self.config.validate(&leaf_node)?;
 
         let root = self.store.root_node()?;
-        let new_root = self.insert_at_node(root, 0, &key, leaf_node.clone())?;
-        self.store.update_root(new_root)?;
-
-        Ok(())
+        let (old_root_hash, old_root_sum) = (root.node_hash(), root.node_sum());
+        let mut previous = None;
+        let mut writes = PathWrites::default();
+        let new_root = self.insert_at_node(
+            root,
+            0,
+            &key,
+            leaf_node.clone(),
+            &mut previous,
+            siblings,
+            &mut writes,
+        )?;
+        let replaced_sum = previous.as_ref().map_or(0, |leaf| leaf.sum);
+        self.config
+            .check_root_sum(old_root_sum, [(sum, replaced_sum)], new_root.node_sum())?;
+        let root_hash = new_root.node_hash();
+
+        // The nodes are only written once the whole path has been rebuilt without overflowing
+        let PathWrites {
+            branches,
+            superseded,
+        } = writes;
+        self.store
+            .insert_nodes_at(branches, vec![leaf_node.clone()])?;
+        self.store.update_root(new_root.clone())?;
+
+        debug_event!(root = %root_hash, replaced = previous.is_some(), "leaf inserted");
+        self.record_operation(Operation::Insert, start);
+        self.notify_insert(&leaf_node, previous.as_ref());
+        self.notify_root_change(old_root_hash, new_root.as_ref());
+        self.stamp_leaf_metadata()?;
+        self.prune_superseded(&superseded)?;
+        Ok((previous, root_hash))
     }
+
+    #[allow(clippy::too_many_arguments)]
     fn insert_at_node(
         &mut self,
         node: Arc<dyn Node>,
         height: usize,
-        key: &[u8; 32],
-        leaf_node: Arc<LeafNode>,
+        key: &[u8; K],
+        leaf_node: Arc<LeafNode<K, V>>,
+        previous: &mut Option<LeafNode<K, V>>,
+        siblings: &mut Vec<Arc<dyn Node>>,
+        writes: &mut PathWrites,
     ) -> Result<Arc<dyn Node>> {
-        if height == MAX_TREE_LEVELS {
-            self.store.insert_leaf(leaf_node.clone())?;
+        let node = resolve_node(&self.store, &node, height)?;
+        if height == tree_levels(K) {
+            if let Some(existing) = node.as_leaf::<K, V>() {
+                if existing.key == *key && !self.config.reads_as_absent(existing) {
+                    *previous = Some(existing.clone());
+                }
+            }
+            writes
+                .superseded
+                .push((height, node.node_hash(), leaf_node.node_hash()));
             return Ok(leaf_node);
         }
 
-        let bit = bit_index(height, key);
+        let is_left = TreePath::from(*key).is_left(Height::at(height));
 
-        if let Some(branch_node) = node.as_any().downcast_ref::<BranchNode>() {
+        if let Some(branch_node) = node.as_branch() {
             let left = branch_node.left.clone();
             let right = branch_node.right.clone();
 
             let new_left;
             let new_right;
 
-            if bit == 0 {
-                new_left = self.insert_at_node(left, height + 1, key, leaf_node)?;
+            if is_left {
+                siblings.push(right.clone());
+                new_left = self.insert_at_node(
+                    left,
+                    height + 1,
+                    key,
+                    leaf_node,
+                    previous,
+                    siblings,
+                    writes,
+                )?;
                 new_right = right;
             } else {
+                siblings.push(left.clone());
                 new_left = left;
-                new_right = self.insert_at_node(right, height + 1, key, leaf_node)?;
+                new_right = self.insert_at_node(
+                    right,
+                    height + 1,
+                    key,
+                    leaf_node,
+                    previous,
+                    siblings,
+                    writes,
+                )?;
             }
 
-            let new_branch = Arc::new(BranchNode::new(new_left, new_right));
-            self.store.insert_branch(new_branch.clone())?;
+            let new_branch = Arc::new(new_branch(
+                new_left,
+                new_right,
+                self.config.overflow_policy(),
+            )?);
+            trace_event!(height, hash = %new_branch.node_hash(), "branch written");
+            writes.branches.push((height, new_branch.clone()));
+            writes
+                .superseded
+                .push((height, node.node_hash(), new_branch.node_hash()));
             Ok(new_branch)
-        } else if let Some(leaf_node_existing_ref) = node.as_any().downcast_ref::<LeafNode>() {
-            let leaf_node_existing = leaf_node_existing_ref.clone();
-
-            if leaf_node_existing.key == *key {
-                // Replace the existing leaf node
-                self.store.insert_leaf(leaf_node.clone())?;
-                Ok(leaf_node)
-            } else {
-                // Need to split and create a branch
-                let existing_key = leaf_node_existing.key;
-                let mut current_height = height;
-
-                let mut current_node = node;
-                let new_leaf_node = leaf_node;
-
-                loop {
-                    if current_height >= MAX_TREE_LEVELS {
-                        break;
-                    }
-
-                    let existing_bit = bit_index(current_height, &existing_key);
-                    let new_bit = bit_index(current_height, key);
-
-                    if existing_bit != new_bit {
-                        let left_node;
-                        let right_node;
-
-                        if new_bit == 0 {
-                            left_node = self.insert_at_node(
-                                Arc::new(EMPTY_LEAF_NODE.clone()),
-                                current_height + 1,
-                                key,
-                                new_leaf_node.clone(),
-                            )?;
-                            right_node = self.insert_at_node(
-                                Arc::new(EMPTY_LEAF_NODE.clone()),
-                                current_height + 1,
-                                &existing_key,
-                                Arc::new(leaf_node_existing.clone()),
-                            )?;
-                        } else {
-                            left_node = self.insert_at_node(
-                                Arc::new(EMPTY_LEAF_NODE.clone()),
-                                current_height + 1,
-                                &existing_key,
-                                Arc::new(leaf_node_existing.clone()),
-                            )?;
-                            right_node = self.insert_at_node(
-                                Arc::new(EMPTY_LEAF_NODE.clone()),
-                                current_height + 1,
-                                key,
-                                new_leaf_node.clone(),
-                            )?;
-                        }
-
-                        let new_branch = Arc::new(BranchNode::new(left_node, right_node));
-                        self.store.insert_branch(new_branch.clone())?;
-                        return Ok(new_branch);
-                    } else {
-                        current_height += 1;
-                        current_node = Arc::new(BranchNode::new(
-                            Arc::new(EMPTY_LEAF_NODE.clone()),
-                            Arc::new(EMPTY_LEAF_NODE.clone()),
-                        ));
-                    }
-                }
-
-                Ok(current_node)
-            }
         } else {
-            Ok(leaf_node)
+            // The tree always stores full-depth paths, so every inner node is a branch
+            Err(MssmtError::CorruptedTree {
+                height,
+                hash: node.node_hash(),
+                reason: "leaf above the leaf level",
+            })
         }
     }
 
-    /// Retrieves the value and sum associated with a key.
+    /// Resets the tree to the empty root and removes every node from the store.
     ///
-    /// # Arguments
+    /// The store must be able to list its nodes (see `TreeStoreReader::branch_hashes`), otherwise
+    /// `MssmtError::Unsupported` is returned and the tree is left unchanged.
     ///
-    /// - `key`: A 32-byte array representing the key to retrieve.
+    /// # Examples
     ///
-    /// # Returns
+    /// ```rust
+    /// use mssmt::{DefaultStore, FullTree};
     ///
-    /// - `Ok(Some((value, sum)))` if the key exists, where `value` is a `Vec<u8>` and `sum` is a `u64`.
-    /// - `Ok(None)` if the key does not exist.
+    /// let mut tree = FullTree::new(DefaultStore::new());
+    /// tree.insert([1u8; 32], b"value".to_vec(), 10).unwrap();
     ///
-    pub fn get(&self, key: [u8; 32]) -> Result<Option<(Vec<u8>, u64)>> {
-        let node = self.store.root_node()?;
-        self.get_at_node(node, 0, &key)
+    /// tree.clear().unwrap();
+    /// assert!(tree.is_empty().unwrap());
+    /// assert!(tree.store().leaves.is_empty());
+    /// ```
+    pub fn clear(&mut self) -> Result<()> {
+        debug_span!("clear");
+        let old_root_hash = self.store.root_node()?.node_hash();
+        let branches = self.store.branch_hashes()?;
+        let leaves = self.store.leaf_hashes()?;
+
+        for hash in &branches {
+            self.store.delete_branch(hash)?;
+        }
+        for hash in &leaves {
+            self.store.delete_leaf(hash)?;
+        }
+        debug_event!(
+            branches = branches.len(),
+            leaves = leaves.len(),
+            "store cleared"
+        );
+        let empty_root = EmptyTreeOf::<K>::node_at(0);
+        self.store.update_root(empty_root.clone())?;
+
+        self.notify_root_change(old_root_hash, empty_root.as_ref());
+        Ok(())
     }
 
-    fn get_at_node(
-        &self,
-        node: Arc<dyn Node>,
-        height: usize,
-        key: &[u8; 32],
-    ) -> Result<Option<(Vec<u8>, u64)>> {
-        if height == MAX_TREE_LEVELS {
-            if let Some(leaf_node) = node.as_any().downcast_ref::<LeafNode>() {
-                if leaf_node.key == *key {
-                    return Ok(Some((leaf_node.value.clone(), leaf_node.sum)));
-                }
-            }
-            return Ok(None);
+    /// Notifies the observers that `leaf` was inserted, replacing `previous`.
+    pub(crate) fn notify_insert(
+        &mut self,
+        leaf: &LeafNode<K, V>,
+        previous: Option<&LeafNode<K, V>>,
+    ) {
+        if let Some(changes) = &mut self.metadata_changes {
+            changes.push(MetadataChange::Insert {
+                key: leaf.key,
+                replaced: previous.is_some(),
+            });
         }
+        for observer in &self.observers {
+            observer.on_insert(&Key(leaf.key), leaf, previous);
+        }
+    }
 
-        let bit = bit_index(height, key);
+    /// Notifies the observers that `removed` was deleted.
+    pub(crate) fn notify_delete(&mut self, removed: &LeafNode<K, V>) {
+        if let Some(changes) = &mut self.metadata_changes {
+            changes.push(MetadataChange::Delete { key: removed.key });
+        }
+        for observer in &self.observers {
+            observer.on_delete(&Key(removed.key), removed);
+        }
+    }
 
-        if let Some(branch_node) = node.as_any().downcast_ref::<BranchNode>() {
-            if bit == 0 {
-                self.get_at_node(branch_node.left.clone(), height + 1, key)
+    /// Notifies the observers and subscribers of a root change, unless the root is unchanged.
+    pub(crate) fn notify_root_change(&mut self, old_root_hash: NodeHash, new_root: &dyn Node) {
+        let new_root_hash = new_root.node_hash();
+        if old_root_hash == new_root_hash {
+            return;
+        }
+        for observer in &self.observers {
+            observer.on_root_change(&old_root_hash, &new_root_hash);
+        }
+
+        self.root_version += 1;
+        let update = RootUpdate {
+            root_hash: new_root_hash,
+            root_sum: new_root.node_sum(),
+            version: self.root_version,
+        };
+        if let Some(history) = &mut self.root_history {
+            history.push(ArchivedRoot::from(update));
+        }
+        self.subscribers
+            .retain(|subscriber| subscriber.send(update).is_ok());
+    }
+
+    /// Deletes the nodes an update superseded, under `NodeRetention::Prune`.
+    ///
+    /// A node at a position of the path is only referenced by the versions holding it there, so it can
+    /// go once the new version replaced it, unless an archived root still references it. Nodes the
+    /// update rebuilt identically and empty subtrees, which are never stored, are skipped.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error of the store. The update has taken effect by then, and the remaining
+    /// nodes are left to compaction.
+    fn prune_superseded(&mut self, superseded: &[Superseded]) -> Result<()> {
+        if self.config.node_retention() != NodeRetention::Prune || self.root_history.is_some() {
+            return Ok(());
+        }
+        for &(height, old, new) in superseded {
+            if old == new || EmptyTreeOf::<K>::is_empty_at(height, &old) {
+                continue;
+            }
+            if height == tree_levels(K) {
+                self.store.delete_leaf(&old)?;
             } else {
-                self.get_at_node(branch_node.right.clone(), height + 1, key)
+                self.store.delete_branch_at(height, &old)?;
             }
-        } else {
-            Ok(None)
         }
+        Ok(())
     }
 
     /// Deletes a key from the tree.
     ///
-    /// If the key does not exist, the tree remains unchanged.
+    /// If the key does not exist, the tree remains unchanged. Trees deleting in
+    /// `DeleteMode::Tombstone` replace the leaf with a tombstone instead of the empty leaf.
     ///
     /// # Arguments
     ///
-    /// - `key`: A 32-byte array representing the key to delete.
+    /// - `key`: The key, as a `Key` or a 32-byte array to delete.
     ///
-    pub fn delete(&mut self, key: [u8; 32]) -> Result<()> {
-        let root = self.store.root_node()?;
-        let new_root = self.delete_at_node(root, 0, &key)?;
-        self.store.update_root(new_root)?;
+    /// # Returns
+    ///
+    /// - `Ok(Some(leaf))` with the removed leaf if the key was present.
+    /// - `Ok(None)` if the key was not present.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use mssmt::{DefaultStore, FullTree};
+    ///
+    /// let mut tree = FullTree::new(DefaultStore::new());
+    /// tree.insert([1u8; 32], b"value".to_vec(), 10).unwrap();
+    ///
+    /// let removed = tree.delete([1u8; 32]).unwrap().unwrap();
+    /// assert_eq!(removed.sum, 10);
+    /// assert!(tree.delete([1u8; 32]).unwrap().is_none());
+    /// ```
+    pub fn delete(&mut self, key: impl Into<Key<K>>) -> Result<Option<LeafNode<K, V>>> {
+        let key = key.into().0;
+        let tombstone = self.config.tombstone(key).map(Arc::new);
+        let (removed, _) = self.delete_leaf_node(key, tombstone, &mut Vec::new())?;
+        Ok(removed)
+    }
 
-        Ok(())
+    /// Deletes a key and returns the new root hash together with a non-inclusion proof for the key.
+    ///
+    /// The siblings along the path are not affected by the deletion, so they are collected on the
+    /// way down and form a proof that the key maps to the empty leaf under the new root.
+    ///
+    /// # Arguments
+    ///
+    /// - `key`: The key, as a `Key` or a 32-byte array to delete.
+    ///
+    /// # Returns
+    ///
+    /// - The hash of the new root and a `Proof` that verifies against it with `EMPTY_LEAF_NODE`, or with
+    ///   the tombstone of the key in `DeleteMode::Tombstone`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use mssmt::node::EMPTY_LEAF_NODE;
+    /// use mssmt::{DefaultStore, FullTree};
+    ///
+    /// let mut tree = FullTree::new(DefaultStore::new());
+    /// tree.insert([1u8; 32], b"value".to_vec(), 10).unwrap();
+    ///
+    /// let (root_hash, proof) = tree.delete_with_exclusion_proof([1u8; 32]).unwrap();
+    /// assert!(proof.verify([1u8; 32], &EMPTY_LEAF_NODE, root_hash));
+    /// ```
+    pub fn delete_with_exclusion_proof(
+        &mut self,
+        key: impl Into<Key<K>>,
+    ) -> Result<(NodeHash, Proof<K>)> {
+        let key = key.into().0;
+        let mut siblings = Vec::with_capacity(tree_levels(K));
+        let tombstone = self.config.tombstone(key).map(Arc::new);
+        let (_, root_hash) = self.delete_leaf_node(key, tombstone, &mut siblings)?;
+        Ok((
+            root_hash,
+            Proof::new(siblings).with_overflow(self.config.overflow_policy()),
+        ))
+    }
+
+    /// Deletes a leaf, returning the removed leaf and the new root hash.
+    ///
+    /// A non-empty leaf is replaced with `tombstone` if given, and with the empty leaf otherwise. The
+    /// siblings along the path are appended to `siblings` in root-first order.
+    pub(crate) fn delete_leaf_node(
+        &mut self,
+        key: [u8; K],
+        tombstone: Option<Arc<LeafNode<K, V>>>,
+        siblings: &mut Vec<Arc<dyn Node>>,
+    ) -> Result<(Option<LeafNode<K, V>>, NodeHash)> {
+        debug_span!("delete", key = %hex::encode(&key[..4]));
+        let start = Instant::now();
+        let root = self.store.root_node()?;
+        let (old_root_hash, old_root_sum) = (root.node_hash(), root.node_sum());
+        let mut removed = None;
+        let mut writes = PathWrites::default();
+        let new_root = self.delete_at_node(
+            root,
+            0,
+            &key,
+            tombstone.as_ref(),
+            &mut removed,
+            siblings,
+            &mut writes,
+        )?;
+        let removed_sum = removed.as_ref().map_or(0, |leaf| leaf.sum);
+        self.config
+            .check_root_sum(old_root_sum, [(0, removed_sum)], new_root.node_sum())?;
+        let root_hash = new_root.node_hash();
+        let PathWrites {
+            branches,
+            superseded,
+        } = writes;
+        let leaves = tombstone
+            .filter(|_| removed.is_some())
+            .into_iter()
+            .collect();
+        self.store.insert_nodes_at(branches, leaves)?;
+        self.store.update_root(new_root.clone())?;
+
+        debug_event!(root = %root_hash, removed = removed.is_some(), "leaf deleted");
+        self.record_operation(Operation::Delete, start);
+        if let Some(removed) = &removed {
+            self.notify_delete(removed);
+        }
+        self.notify_root_change(old_root_hash, new_root.as_ref());
+        self.stamp_leaf_metadata()?;
+        self.prune_superseded(&superseded)?;
+        Ok((removed, root_hash))
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn delete_at_node(
         &mut self,
         node: Arc<dyn Node>,
         height: usize,
-        key: &[u8; 32],
+        key: &[u8; K],
+        tombstone: Option<&Arc<LeafNode<K, V>>>,
+        removed: &mut Option<LeafNode<K, V>>,
+        siblings: &mut Vec<Arc<dyn Node>>,
+        writes: &mut PathWrites,
     ) -> Result<Arc<dyn Node>> {
-        if height == MAX_TREE_LEVELS {
-            if let Some(leaf_node) = node.as_any().downcast_ref::<LeafNode>() {
+        let node = resolve_node(&self.store, &node, height)?;
+        if height == tree_levels(K) {
+            if let Some(leaf_node) = node.as_leaf::<K, V>() {
                 if leaf_node.key == *key {
+                    // Tombstones are only purged by compaction
+                    if tombstone.is_some() && leaf_node.is_empty() {
+                        return Ok(node);
+                    }
                     self.store.delete_leaf(&leaf_node.node_hash())?;
-                    return Ok(Arc::new(EMPTY_LEAF_NODE.clone()));
+                    if !self.config.reads_as_absent(leaf_node) {
+                        *removed = Some(leaf_node.clone());
+                    }
+                    return Ok(match tombstone {
+                        Some(tombstone) => tombstone.clone() as Arc<dyn Node>,
+                        None => EmptyTreeOf::<K>::node_at(tree_levels(K)),
+                    });
                 }
             }
             return Ok(node);
         }
 
-        let bit = bit_index(height, key);
+        let is_left = TreePath::from(*key).is_left(Height::at(height));
 
-        if let Some(branch_node) = node.as_any().downcast_ref::<BranchNode>() {
+        if let Some(branch_node) = node.as_branch() {
             let new_left;
             let new_right;
 
-            if bit == 0 {
-                new_left = self.delete_at_node(branch_node.left.clone(), height + 1, key)?;
+            if is_left {
+                siblings.push(branch_node.right.clone());
+                new_left = self.delete_at_node(
+                    branch_node.left.clone(),
+                    height + 1,
+                    key,
+                    tombstone,
+                    removed,
+                    siblings,
+                    writes,
+                )?;
                 new_right = branch_node.right.clone();
             } else {
+                siblings.push(branch_node.left.clone());
                 new_left = branch_node.left.clone();
-                new_right = self.delete_at_node(branch_node.right.clone(), height + 1, key)?;
+                new_right = self.delete_at_node(
+                    branch_node.right.clone(),
+                    height + 1,
+                    key,
+                    tombstone,
+                    removed,
+                    siblings,
+                    writes,
+                )?;
             }
 
-            let new_branch = Arc::new(BranchNode::new(new_left.clone(), new_right.clone()));
-            self.store.insert_branch(new_branch.clone())?;
-
-            // If both children are empty, return empty node
-            if new_left.node_hash() == EMPTY_LEAF_NODE.node_hash()
-                && new_right.node_hash() == EMPTY_LEAF_NODE.node_hash()
+            // If both children are empty, the whole subtree is empty
+            let empty_child_hash = EmptyTreeOf::<K>::hash_at(height + 1);
+            if new_left.node_hash() == empty_child_hash && new_right.node_hash() == empty_child_hash
             {
-                Ok(Arc::new(EMPTY_LEAF_NODE.clone()))
-            } else {
-                Ok(new_branch)
+                let empty = EmptyTreeOf::<K>::node_at(height);
+                writes
+                    .superseded
+                    .push((height, node.node_hash(), empty.node_hash()));
+                return Ok(empty);
             }
+
+            let new_branch = Arc::new(BranchNode::with_overflow_policy(
+                new_left,
+                new_right,
+                self.config.overflow_policy(),
+            ));
+            trace_event!(height, hash = %new_branch.node_hash(), "branch written");
+            writes.branches.push((height, new_branch.clone()));
+            writes
+                .superseded
+                .push((height, node.node_hash(), new_branch.node_hash()));
+            Ok(new_branch)
         } else {
             Ok(node)
         }
     }
 
-    /// Generates a Merkle proof for a given key.
+    /// Sets the sum of the leaf at `key`, keeping its value.
     ///
-    /// The proof can be used to verify the inclusion and sum of the key's value in the tree without having access to the entire tree.
+    /// The leaf is read and its path rewritten in a single traversal, instead of a `get` followed by
+    /// an `insert` of the same value.
     ///
-    /// # Arguments
+    /// # Returns
+    ///
+    /// - `Ok(Some(previous_sum))` once the sum is updated.
+    /// - `Ok(None)` if the key is not present, in which case the tree is unchanged.
+    /// - `MssmtError::SumOverflow` if the sum of the tree would overflow.
+    /// - `MssmtError::InvalidLeaf` if the updated leaf is rejected by the configuration of the tree.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use mssmt::{DefaultStore, FullTree};
+    ///
+    /// let mut tree = FullTree::new(DefaultStore::new());
+    /// tree.insert([1u8; 32], b"account".to_vec(), 10).unwrap();
+    ///
+    /// assert_eq!(tree.update_sum([1u8; 32], 25).unwrap(), Some(10));
+    /// assert_eq!(tree.get([1u8; 32]).unwrap(), Some((b"account".to_vec(), 25)));
+    /// assert_eq!(tree.update_sum([2u8; 32], 5).unwrap(), None);
+    /// ```
+    pub fn update_sum(&mut self, key: impl Into<Key<K>>, new_sum: Sum) -> Result<Option<Sum>> {
+        let key = key.into().0;
+        let (previous, _) = self.rewrite_leaf(key, |existing| {
+            Ok(existing.map(|leaf| LeafNode::new(key, leaf.value.clone(), new_sum)))
+        })?;
+        Ok(previous.map(|leaf| leaf.sum))
+    }
+
+    /// Adds `delta` to the sum of the leaf at `key`, keeping its value.
+    ///
+    /// Works like `FullTree::update_sum`, with the new sum computed from the current one.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(Some(new_sum))` once the sum is updated.
+    /// - `Ok(None)` if the key is not present, in which case the tree is unchanged.
+    /// - `MssmtError::SumOverflow` if the leaf sum would go below zero or overflow, or the sum of the
+    ///   tree would overflow.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use mssmt::{DefaultStore, FullTree, MssmtError};
+    ///
+    /// let mut tree = FullTree::new(DefaultStore::new());
+    /// tree.insert([1u8; 32], b"account".to_vec(), 10).unwrap();
+    ///
+    /// assert_eq!(tree.adjust_sum([1u8; 32], -4).unwrap(), Some(6));
+    /// assert_eq!(tree.adjust_sum([1u8; 32], 10).unwrap(), Some(16));
+    /// assert!(matches!(tree.adjust_sum([1u8; 32], -17), Err(MssmtError::SumOverflow)));
+    /// assert_eq!(tree.total_sum().unwrap(), 16);
+    /// ```
+    pub fn adjust_sum(&mut self, key: impl Into<Key<K>>, delta: SumDelta) -> Result<Option<Sum>> {
+        let key = key.into().0;
+        let (_, updated) = self.rewrite_leaf(key, |existing| {
+            let Some(leaf) = existing else {
+                return Ok(None);
+            };
+            let sum = leaf
+                .sum
+                .checked_add_signed(delta)
+                .ok_or(MssmtError::SumOverflow)?;
+            Ok(Some(LeafNode::new(key, leaf.value.clone(), sum)))
+        })?;
+        Ok(updated)
+    }
+
+    /// Reads, transforms and writes the entry at `key` in a single traversal of its path.
+    ///
+    /// `f` receives the current value and sum, or `None` if the key is absent, and returns the new
+    /// entry, or `None` to delete the key. This gives upsert and merge semantics without looking the
+    /// key up before writing it.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(())` once the entry is written, or immediately if `f` returns `None` for an absent key.
+    /// - `MssmtError::SumOverflow` if the sum of the tree would overflow.
+    /// - `MssmtError::InvalidLeaf` if the new leaf is rejected by the configuration of the tree.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use mssmt::{DefaultStore, FullTree, Sum};
+    ///
+    /// let mut tree = FullTree::new(DefaultStore::new());
+    /// let deposit = |entry: Option<(Vec<u8>, Sum)>| match entry {
+    ///     Some((value, sum)) => Some((value, sum + 5)),
+    ///     None => Some((b"account".to_vec(), 5)),
+    /// };
+    ///
+    /// tree.modify([1u8; 32], deposit).unwrap();
+    /// tree.modify([1u8; 32], deposit).unwrap();
+    /// assert_eq!(tree.get([1u8; 32]).unwrap(), Some((b"account".to_vec(), 10)));
+    ///
+    /// tree.modify([1u8; 32], |_| None).unwrap();
+    /// assert!(tree.is_empty().unwrap());
+    /// ```
+    pub fn modify(
+        &mut self,
+        key: impl Into<Key<K>>,
+        f: impl FnOnce(Option<(V, Sum)>) -> Option<(V, Sum)>,
+    ) -> Result<()> {
+        let key = key.into().0;
+        self.rewrite_leaf(key, |existing| {
+            let entry = existing.map(|leaf| (leaf.value.clone(), leaf.sum));
+            Ok(f(entry).map(|(value, sum)| LeafNode::new(key, value, sum)))
+        })?;
+        Ok(())
+    }
+
+    /// Inserts a key-value-sum entry unless the key is already present.
+    ///
+    /// Through a `SharedTree`, the check and the insertion happen under the same write lock, so exactly
+    /// one of several concurrent writers claiming a key succeeds.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(true)` if the entry was inserted.
+    /// - `Ok(false)` if the key was present, in which case the tree is unchanged.
+    /// - `MssmtError::InvalidLeaf` if the leaf is rejected by the configuration of the tree.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use mssmt::{DefaultStore, FullTree};
+    ///
+    /// let mut tree = FullTree::new(DefaultStore::new());
+    /// assert!(tree.insert_if_absent([1u8; 32], b"first".to_vec(), 10).unwrap());
+    /// assert!(!tree.insert_if_absent([1u8; 32], b"second".to_vec(), 20).unwrap());
+    /// assert_eq!(tree.get([1u8; 32]).unwrap(), Some((b"first".to_vec(), 10)));
+    /// ```
+    pub fn insert_if_absent(
+        &mut self,
+        key: impl Into<Key<K>>,
+        value: impl Into<V>,
+        sum: Sum,
+    ) -> Result<bool> {
+        let key = key.into().0;
+        if self.contains_key(key)? {
+            return Ok(false);
+        }
+        self.insert_leaf_node(key, value.into(), sum, &mut Vec::new())?;
+        Ok(true)
+    }
+
+    /// Replaces the entry at `key` with `new` if it currently holds `expected`.
+    ///
+    /// `expected` is the value and sum the caller last read, or `None` if it read the key as absent, and
+    /// `new` the entry to write, or `None` to delete the key. Values are compared by their bytes. This
+    /// gives optimistic updates: a writer reads an entry, computes its replacement without holding a lock,
+    /// and retries from a fresh read if another writer changed the entry in between.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(true)` if the entry matched `expected` and was replaced.
+    /// - `Ok(false)` if it did not match, in which case the tree is unchanged.
+    /// - `MssmtError::SumOverflow` if the sum of the tree would overflow.
+    /// - `MssmtError::InvalidLeaf` if the new leaf is rejected by the configuration of the tree.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use mssmt::{DefaultStore, FullTree};
+    ///
+    /// let mut tree = FullTree::new(DefaultStore::new());
+    /// tree.insert([1u8; 32], b"v1".to_vec(), 10).unwrap();
+    ///
+    /// let read = tree.get([1u8; 32]).unwrap();
+    /// assert!(tree.compare_and_swap([1u8; 32], read.clone(), Some((b"v2".to_vec(), 20))).unwrap());
+    /// // The entry changed since it was read
+    /// assert!(!tree.compare_and_swap([1u8; 32], read, None).unwrap());
+    /// assert_eq!(tree.get([1u8; 32]).unwrap(), Some((b"v2".to_vec(), 20)));
+    /// ```
+    pub fn compare_and_swap(
+        &mut self,
+        key: impl Into<Key<K>>,
+        expected: Option<(V, Sum)>,
+        new: Option<(V, Sum)>,
+    ) -> Result<bool> {
+        let key = key.into().0;
+        let current = self.get(key)?;
+        let matches = match (&current, &expected) {
+            (Some((value, sum)), Some((expected_value, expected_sum))) => {
+                value.as_ref() == expected_value.as_ref() && sum == expected_sum
+            }
+            (None, None) => true,
+            _ => false,
+        };
+        if !matches {
+            return Ok(false);
+        }
+        match new {
+            Some((value, sum)) => {
+                self.insert_leaf_node(key, value, sum, &mut Vec::new())?;
+            }
+            None if current.is_some() => {
+                self.delete(key)?;
+            }
+            None => {}
+        }
+        Ok(true)
+    }
+
+    /// Replaces the leaf at `key` with the leaf returned by `update`, in a single traversal of its path.
     ///
-    /// - `key`: A 32-byte array representing the key for which to generate the proof.
+    /// `update` receives the current leaf, if any, and returns the new leaf, or `None` to remove the
+    /// key. The path is rebuilt in memory and only written once its sums are known not to overflow.
     ///
     /// # Returns
     ///
-    /// - A `Proof` struct containing the necessary nodes for verification.
-    pub fn merkle_proof(&self, key: [u8; 32]) -> Result<Proof> {
-        let node = self.store.root_node()?;
-        let mut proof_nodes = Vec::new();
-        self.generate_proof(node, 0, &key, &mut proof_nodes)?;
-        Ok(Proof::new(proof_nodes))
-    }
-
-    fn generate_proof(
-        &self,
-        node: Arc<dyn Node>,
-        height: usize,
-        key: &[u8; 32],
-        proof_nodes: &mut Vec<Arc<dyn Node>>,
-    ) -> Result<()> {
-        if height == MAX_TREE_LEVELS {
-            return Ok(());
+    /// - The previous leaf and the sum of the new leaf. The tree is unchanged if both are `None`.
+    fn rewrite_leaf(
+        &mut self,
+        key: [u8; K],
+        update: impl FnOnce(Option<&LeafNode<K, V>>) -> Result<Option<LeafNode<K, V>>>,
+    ) -> Result<(Option<LeafNode<K, V>>, Option<Sum>)> {
+        let start = Instant::now();
+        let levels = tree_levels(K);
+        let root = self.store.root_node()?;
+        let (old_root_hash, old_root_sum) = (root.node_hash(), root.node_sum());
+
+        // Resolve the branches along the path, root first
+        let mut path = Vec::with_capacity(levels);
+        let mut node = root;
+        for height in 0..levels {
+            node = resolve_node(&self.store, &node, height)?;
+            let next = match node.as_branch() {
+                Some(branch) if TreePath::from(key).is_left(Height::at(height)) => {
+                    branch.left.clone()
+                }
+                Some(branch) => branch.right.clone(),
+                None => {
+                    return Err(MssmtError::CorruptedTree {
+                        height,
+                        hash: node.node_hash(),
+                        reason: "leaf above the leaf level",
+                    })
+                }
+            };
+            path.push(node);
+            node = next;
+        }
+        let node = resolve_node(&self.store, &node, levels)?;
+        let existing = node
+            .as_leaf::<K, V>()
+            .filter(|leaf| leaf.key == key && !self.config.reads_as_absent(leaf))
+            .cloned();
+
+        let updated = update(existing.as_ref())?.map(Arc::new);
+        if existing.is_none() && updated.is_none() {
+            return Ok((None, None));
+        }
+        if let Some(leaf) = &updated {
+            self.config.validate(leaf)?;
         }
 
-        let bit = bit_index(height, key);
-
-        if let Some(branch_node) = node.as_any().downcast_ref::<BranchNode>() {
-            if bit == 0 {
-                proof_nodes.push(branch_node.right.clone());
-                self.generate_proof(branch_node.left.clone(), height + 1, key, proof_nodes)?;
+        // Rebuild the path bottom-up, collapsing subtrees left empty by a removal
+        let mut current: Arc<dyn Node> = match &updated {
+            Some(leaf) => leaf.clone(),
+            None => EmptyTreeOf::<K>::node_at(levels),
+        };
+        let mut branches = Vec::with_capacity(levels);
+        let mut superseded = Vec::with_capacity(levels + 1);
+        if let (Some(old), Some(new)) = (&existing, &updated) {
+            superseded.push((levels, old.node_hash(), new.node_hash()));
+        }
+        for (height, branch) in path.iter().enumerate().rev() {
+            let branch = branch.as_branch().expect("path holds branches");
+            let (left, right) = if TreePath::from(key).is_left(Height::at(height)) {
+                (current, branch.right.clone())
             } else {
-                proof_nodes.push(branch_node.left.clone());
-                self.generate_proof(branch_node.right.clone(), height + 1, key, proof_nodes)?;
+                (branch.left.clone(), current)
+            };
+            let empty_child_hash = EmptyTreeOf::<K>::hash_at(height + 1);
+            current = if left.node_hash() == empty_child_hash
+                && right.node_hash() == empty_child_hash
+            {
+                EmptyTreeOf::<K>::node_at(height)
+            } else {
+                let new_branch = Arc::new(new_branch(left, right, self.config.overflow_policy())?);
+                branches.push((height, new_branch.clone()));
+                new_branch
+            };
+            superseded.push((height, branch.node_hash(), current.node_hash()));
+        }
+        let change = (
+            updated.as_ref().map_or(0, |leaf| leaf.sum),
+            existing.as_ref().map_or(0, |leaf| leaf.sum),
+        );
+        self.config
+            .check_root_sum(old_root_sum, [change], current.node_sum())?;
+
+        match (&existing, &updated) {
+            (_, Some(leaf)) => self.store.insert_nodes_at(branches, vec![leaf.clone()])?,
+            (Some(leaf), None) => {
+                self.store.insert_nodes_at(branches, Vec::new())?;
+                self.store.delete_leaf(&leaf.node_hash())?;
             }
-        } else {
-            // Push default empty node as sibling if no branch node exists
-            proof_nodes.push(Arc::new(crate::node::EMPTY_LEAF_NODE.clone()));
-            self.generate_proof(node.clone(), height + 1, key, proof_nodes)?;
+            (None, None) => self.store.insert_nodes_at(branches, Vec::new())?,
         }
+        self.store.update_root(current.clone())?;
 
-        Ok(())
+        match &updated {
+            Some(leaf) => {
+                self.record_operation(Operation::Insert, start);
+                self.notify_insert(leaf, existing.as_ref());
+            }
+            None => {
+                self.record_operation(Operation::Delete, start);
+                if let Some(removed) = &existing {
+                    self.notify_delete(removed);
+                }
+            }
+        }
+        self.notify_root_change(old_root_hash, current.as_ref());
+        self.stamp_leaf_metadata()?;
+        self.prune_superseded(&superseded)?;
+        Ok((existing, updated.map(|leaf| leaf.sum)))
     }
+}
 
-    /// Returns the total sum of all values in the tree.
-    ///
-    /// # Returns
-    ///
-    /// - The sum of all `sum` values associated with the keys in the tree.
-    ///
-    pub fn total_sum(&self) -> Result<u64> {
-        let root = self.root()?;
-        Ok(root.node_sum())
+/// A node replaced by an update: its height, its hash and the hash of the node now at its position.
+type Superseded = (usize, NodeHash, NodeHash);
+
+/// The branches written by an update of a path, and the nodes they supersede.
+///
+/// The branches are collected bottom-up with their heights and flushed with a single
+/// `TreeStoreWriter::insert_nodes_at` once the whole path is rebuilt.
+#[derive(Default)]
+struct PathWrites {
+    branches: Vec<(usize, Arc<BranchNode>)>,
+    superseded: Vec<Superseded>,
+}
+
+/// The nodes written by the update of one subtree.
+#[derive(Default)]
+pub(crate) struct SubtreeUpdate {
+    /// The inserted leaves in key order, with the leaves they replaced.
+    pub(crate) leaves: Vec<(Arc<LeafNode>, Option<LeafNode>)>,
+    /// The rebuilt branches with their heights, children before parents.
+    pub(crate) branches: Vec<(usize, Arc<BranchNode>)>,
+}
+
+/// Inserts the sorted, unique `leaves` into the subtree rooted at `node`, returning the new subtree root.
+/// The new branches combine the sums of their children under `overflow`.
+///
+/// The new nodes are recorded in `update` instead of being written, so the subtree can be rebuilt while
+/// other threads read the same store.
+pub(crate) fn merge_leaves<S: TreeStoreReader + ?Sized>(
+    store: &S,
+    node: Arc<dyn Node>,
+    height: usize,
+    leaves: &[LeafNode],
+    overflow: crate::config::OverflowPolicy,
+    update: &mut SubtreeUpdate,
+) -> Result<Arc<dyn Node>> {
+    if leaves.is_empty() {
+        return Ok(node);
+    }
+
+    let node = resolve_node(store, &node, height)?;
+    if height == crate::node::MAX_TREE_LEVELS {
+        let leaf = Arc::new(leaves[0].clone());
+        let previous = node
+            .as_leaf::<HASH_SIZE, Vec<u8>>()
+            .filter(|existing| !existing.is_empty() && existing.key == leaf.key)
+            .cloned();
+        update.leaves.push((leaf.clone(), previous));
+        return Ok(leaf);
     }
+
+    // The tree always stores full-depth paths, so every inner node is a branch
+    let Some(branch) = node.as_branch() else {
+        return Err(MssmtError::CorruptedTree {
+            height,
+            hash: node.node_hash(),
+            reason: "leaf above the leaf level",
+        });
+    };
+    let split = leaves.partition_point(|leaf| TreePath::from(leaf.key).is_left(Height::at(height)));
+    let left = merge_leaves(
+        store,
+        branch.left.clone(),
+        height + 1,
+        &leaves[..split],
+        overflow,
+        update,
+    )?;
+    let right = merge_leaves(
+        store,
+        branch.right.clone(),
+        height + 1,
+        &leaves[split..],
+        overflow,
+        update,
+    )?;
+
+    let branch = Arc::new(new_branch(left, right, overflow)?);
+    update.branches.push((height, branch.clone()));
+    Ok(branch)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::hash_utils::to_array;
-    use crate::store::DefaultStore;
-    use anyhow::Result;
+    use crate::node::{EmptyTree, EMPTY_LEAF_NODE};
+    use crate::shared::SharedTree;
+    use crate::store::{CachedStore, ConcurrentStore, DefaultStore, TreeStoreWriter};
     use sha2::{Digest, Sha256};
+    use std::collections::HashMap;
 
     #[test]
     fn test_full_tree_operations() -> Result<()> {
@@ -516,4 +2120,649 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_get_with_key_index() -> Result<()> {
+        let mut tree = FullTree::new(DefaultStore::new());
+
+        let key1 = to_array(&Sha256::digest(b"key1"));
+        tree.insert(key1, b"value1".to_vec(), 10)?;
+        tree.insert(key1, b"value1-updated".to_vec(), 15)?;
+        assert_eq!(tree.get(key1)?, Some((b"value1-updated".to_vec(), 15)));
+
+        assert!(tree.contains_key(key1)?);
+        assert_eq!(tree.store().keys.len(), 1);
+
+        tree.delete(key1)?;
+        assert_eq!(tree.get(key1)?, None);
+        assert!(!tree.contains_key(key1)?);
+        assert!(tree.store().keys.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_insert_sum_overflow() -> Result<()> {
+        let mut tree = FullTree::new(DefaultStore::new());
+
+        let key1 = to_array(&Sha256::digest(b"key1"));
+        tree.insert(key1, b"value1".to_vec(), Sum::MAX)?;
+
+        let key2 = to_array(&Sha256::digest(b"key2"));
+        let result = tree.insert(key2, b"value2".to_vec(), 1);
+        assert!(matches!(result, Err(MssmtError::SumOverflow)));
+
+        // The failed insert must leave the tree untouched
+        assert_eq!(tree.get(key2)?, None);
+        assert_eq!(tree.total_sum()?, Sum::MAX);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_only_tree_over_shared_store() -> Result<()> {
+        let mut tree = FullTree::new(DefaultStore::new());
+        let key1 = to_array(&Sha256::digest(b"key1"));
+        tree.insert(key1, b"value1".to_vec(), 10)?;
+
+        // A reader only needs a shared reference to the store
+        let reader = FullTree::new(tree.store());
+        assert_eq!(reader.get(key1)?, Some((b"value1".to_vec(), 10)));
+        assert_eq!(reader.root()?.node_hash(), tree.root()?.node_hash());
+
+        let proof = reader.merkle_proof(key1)?;
+        let leaf_node = LeafNode::new(key1, b"value1".to_vec(), 10);
+        assert!(proof.verify(key1, &leaf_node, tree.root()?.node_hash()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_delete_restores_empty_root() -> Result<()> {
+        let mut tree = FullTree::new(DefaultStore::new());
+        let key1 = to_array(&Sha256::digest(b"key1"));
+        let key2 = to_array(&Sha256::digest(b"key2"));
+
+        tree.insert(key1, b"value1".to_vec(), 10)?;
+        let single_root = tree.root()?.node_hash();
+
+        tree.insert(key2, b"value2".to_vec(), 20)?;
+        tree.delete(key2)?;
+        assert_eq!(tree.root()?.node_hash(), single_root);
+
+        tree.delete(key1)?;
+        assert!(tree.is_empty()?);
+        assert_eq!(tree.root()?.node_hash(), EmptyTree::hash_at(0));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_prune_removes_superseded_nodes() -> Result<()> {
+        let config = TreeConfig::default().with_node_retention(NodeRetention::Prune);
+        let mut tree = FullTree::new(DefaultStore::new());
+        tree.set_config(config.clone());
+        let keys: Vec<[u8; 32]> = (0..16u32)
+            .map(|i| to_array(&Sha256::digest(i.to_be_bytes())))
+            .collect();
+        for (i, key) in keys.iter().enumerate() {
+            tree.insert(*key, vec![i as u8], i as Sum + 1)?;
+        }
+        tree.insert(keys[3], b"updated".to_vec(), 100)?;
+        tree.update_sum(keys[4], 200)?;
+        for key in &keys[8..] {
+            tree.delete(*key)?;
+        }
+
+        // The store holds exactly the nodes of a tree built from the remaining leaves
+        let leaves = tree.store().leaves.values().map(|leaf| (**leaf).clone());
+        let fresh = FullTree::from_leaves(DefaultStore::new(), leaves.collect::<Vec<_>>())?;
+        assert_eq!(tree.root()?.node_hash(), fresh.root()?.node_hash());
+        assert_eq!(tree.store().leaves.len(), 8);
+        assert_eq!(tree.store().branches.len(), fresh.store().branches.len());
+        assert!(tree
+            .store()
+            .branches
+            .keys()
+            .all(|hash| fresh.store().branches.contains_key(hash)));
+
+        for key in &keys[..8] {
+            tree.delete(*key)?;
+        }
+        assert!(tree.store().leaves.is_empty() && tree.store().branches.is_empty());
+
+        // Archived roots keep their nodes
+        let mut tree = FullTree::new(DefaultStore::new());
+        tree.set_config(config);
+        tree.enable_root_history()?;
+        tree.insert(keys[0], vec![0], 1)?;
+        tree.delete(keys[0])?;
+        assert_eq!(tree.store().branches.len(), 256);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_leaves_matches_inserts() -> Result<()> {
+        let mut inserted = FullTree::new(DefaultStore::new());
+        let mut leaves = vec![LeafNode::new([9u8; 32], b"replaced".to_vec(), 1)];
+        for i in 0..64u8 {
+            let key = to_array(&Sha256::digest([i]));
+            inserted.insert(key, vec![i], i as Sum)?;
+            leaves.push(LeafNode::new(key, vec![i], i as Sum));
+        }
+        inserted.insert([9u8; 32], b"kept".to_vec(), 2)?;
+        leaves.push(LeafNode::new([9u8; 32], b"kept".to_vec(), 2));
+        leaves.push(EMPTY_LEAF_NODE.clone());
+
+        let tree = FullTree::from_leaves(DefaultStore::new(), leaves)?;
+        assert_eq!(tree.root()?.node_hash(), inserted.root()?.node_hash());
+        assert_eq!(tree.get([9u8; 32])?, Some((b"kept".to_vec(), 2)));
+        assert_eq!(tree.store().leaves.len(), 65);
+        assert!(tree.verify_integrity()?.is_ok());
+
+        let empty = FullTree::from_leaves(DefaultStore::new(), [])?;
+        assert_eq!(empty.root()?.node_hash(), EmptyTree::hash_at(0));
+
+        let overflowing = [
+            LeafNode::new([1u8; 32], vec![], Sum::MAX),
+            LeafNode::new([2u8; 32], vec![], 1),
+        ];
+        assert!(matches!(
+            FullTree::from_leaves(DefaultStore::new(), overflowing),
+            Err(MssmtError::SumOverflow)
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_clear() -> Result<()> {
+        let mut tree = FullTree::new(DefaultStore::new());
+        tree.insert([1u8; 32], b"one".to_vec(), 1)?;
+        tree.insert([2u8; 32], b"two".to_vec(), 2)?;
+
+        tree.clear()?;
+        assert!(tree.is_empty()?);
+        assert_eq!(tree.get([1u8; 32])?, None);
+        assert!(tree.store().branches.is_empty());
+        assert!(tree.store().keys.is_empty());
+
+        // The tree remains usable after being cleared
+        tree.insert([3u8; 32], b"three".to_vec(), 3)?;
+        assert_eq!(tree.total_sum()?, 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_insert_with_proof() -> Result<()> {
+        let mut tree = FullTree::new(DefaultStore::new());
+        let key1 = to_array(&Sha256::digest(b"key1"));
+        let key2 = to_array(&Sha256::digest(b"key2"));
+        tree.insert(key1, b"value1".to_vec(), 10)?;
+
+        let (root_hash, proof) = tree.insert_with_proof(key2, b"value2".to_vec(), 20)?;
+        assert_eq!(root_hash, tree.root()?.node_hash());
+        assert_eq!(proof, tree.merkle_proof(key2)?);
+
+        let leaf_node = LeafNode::new(key2, b"value2".to_vec(), 20);
+        assert!(proof.verify(key2, &leaf_node, root_hash));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_merkle_proofs_match_single_proofs() -> Result<()> {
+        let mut tree = FullTree::new(DefaultStore::new());
+        assert_eq!(
+            tree.merkle_proofs([[1u8; 32]])?,
+            vec![tree.merkle_proof([1u8; 32])?]
+        );
+
+        for i in 0..32u8 {
+            tree.insert(Key::hash([i]), vec![i], i as Sum)?;
+        }
+
+        // Present, missing and repeated keys, in no particular order
+        let keys: Vec<Key> = (0..40u8)
+            .rev()
+            .chain([3, 3])
+            .map(|i| Key::hash([i]))
+            .collect();
+        let proofs = tree.merkle_proofs(keys.iter().copied())?;
+        assert_eq!(proofs.len(), keys.len());
+        for (key, proof) in keys.iter().zip(&proofs) {
+            assert_eq!(*proof, tree.merkle_proof(*key)?);
+        }
+        assert!(tree.merkle_proofs(Vec::<Key>::new())?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_delete_with_exclusion_proof() -> Result<()> {
+        let mut tree = FullTree::new(DefaultStore::new());
+        let key1 = to_array(&Sha256::digest(b"key1"));
+        let key2 = to_array(&Sha256::digest(b"key2"));
+        tree.insert(key1, b"value1".to_vec(), 10)?;
+        tree.insert(key2, b"value2".to_vec(), 20)?;
+
+        let (root_hash, proof) = tree.delete_with_exclusion_proof(key2)?;
+        assert_eq!(root_hash, tree.root()?.node_hash());
+        assert!(proof.verify(key2, &EMPTY_LEAF_NODE, root_hash));
+
+        // The removed leaf no longer verifies against the new root
+        let leaf_node = LeafNode::new(key2, b"value2".to_vec(), 20);
+        assert!(!proof.verify(key2, &leaf_node, root_hash));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_twenty_byte_keys() -> Result<()> {
+        let mut tree = FullTree::new(DefaultStore::<20>::default());
+        for i in 1..=4u8 {
+            tree.insert([i; 20], vec![i], i as Sum)?;
+        }
+        assert_eq!(tree.get([3u8; 20])?, Some((vec![3], 3)));
+        assert_eq!(tree.get([9u8; 20])?, None);
+        assert_eq!(tree.total_sum()?, 10);
+
+        let root_hash = tree.root()?.node_hash();
+        let proof = tree.merkle_proof([3u8; 20])?;
+        assert_eq!(proof.nodes.len(), 160);
+        assert!(proof.verify([3u8; 20], &LeafNode::new([3u8; 20], vec![3], 3), root_hash));
+        assert_eq!(tree.merkle_proofs([[3u8; 20]])?, vec![proof.clone()]);
+
+        // The empty tree of 20-byte keys differs from the 32-byte one
+        let empty = EmptyTreeOf::<20>::node_at(160).node_hash();
+        let (_, exclusion) = tree.delete_with_exclusion_proof([9u8; 20])?;
+        assert_eq!(exclusion, tree.merkle_proof([9u8; 20])?);
+        assert_ne!(EmptyTreeOf::<20>::hash_at(0), EmptyTree::hash_at(0));
+
+        for i in 1..=4u8 {
+            tree.delete([i; 20])?;
+        }
+        assert!(tree.is_empty()?);
+        assert_eq!(tree.merkle_proof([3u8; 20])?.nodes[159].node_hash(), empty);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_generic_values() -> Result<()> {
+        let mut bytes = FullTree::new(DefaultStore::new());
+        let mut strings = FullTree::new(DefaultStore::<32, String>::default());
+        let mut shared = FullTree::new(DefaultStore::<32, Arc<[u8]>>::default());
+        for i in 1..=4u8 {
+            let value = format!("value{i}");
+            bytes.insert([i; 32], value.clone().into_bytes(), i as Sum)?;
+            shared.insert([i; 32], value.as_bytes(), i as Sum)?;
+            strings.insert([i; 32], value, i as Sum)?;
+        }
+
+        // Values are hashed by their bytes, so the roots agree
+        let root_hash = bytes.root()?.node_hash();
+        assert_eq!(strings.root()?.node_hash(), root_hash);
+        assert_eq!(shared.root()?.node_hash(), root_hash);
+        assert_eq!(strings.get([2u8; 32])?, Some(("value2".to_string(), 2)));
+        assert_eq!(strings.get([9u8; 32])?, None);
+        assert_eq!(
+            strings.insert([2u8; 32], "new".to_string(), 5)?,
+            Some(("value2".to_string(), 2))
+        );
+
+        let leaf = LeafNode::new([3u8; 32], "value3".to_string(), 3);
+        let proof = strings.merkle_proof([3u8; 32])?;
+        assert!(proof.verify([3u8; 32], &leaf, strings.root()?.node_hash()));
+
+        assert_eq!(
+            strings.delete([3u8; 32])?.map(|leaf| leaf.value),
+            Some(leaf.value)
+        );
+        assert_eq!(strings.delete([3u8; 32])?.map(|leaf| leaf.sum), None);
+        assert_eq!(strings.total_sum()?, 10);
+
+        // Shared values are reference counted rather than copied
+        let large: Arc<[u8]> = Arc::from(vec![7u8; 1 << 20]);
+        shared.insert([7u8; 32], large.clone(), 7)?;
+        let (value, _) = shared.get([7u8; 32])?.unwrap();
+        assert!(Arc::ptr_eq(&value, &large));
+        shared.insert([8u8; 32], b"converted".to_vec(), 8)?;
+        let removed = shared.delete([8u8; 32])?.unwrap();
+        assert_eq!(removed.value_bytes(), b"converted");
+
+        Ok(())
+    }
+
+    /// A store that keeps branches with hash-referenced children only, like a paging backend would.
+    #[derive(Default)]
+    struct ShallowStore {
+        inner: DefaultStore,
+    }
+
+    impl TreeStoreReader for ShallowStore {
+        fn root_node(&self) -> Result<Arc<dyn Node>> {
+            self.inner.root_node()
+        }
+
+        fn get_branch(&self, key: &NodeHash) -> Result<Option<Arc<BranchNode>>> {
+            self.inner.get_branch(key)
+        }
+
+        fn get_leaf(&self, key: &NodeHash) -> Result<Option<Arc<LeafNode>>> {
+            self.inner.get_leaf(key)
+        }
+
+        fn branch_hashes(&self) -> Result<Vec<NodeHash>> {
+            self.inner.branch_hashes()
+        }
+
+        fn leaf_hashes(&self) -> Result<Vec<NodeHash>> {
+            self.inner.leaf_hashes()
+        }
+    }
+
+    impl TreeStoreWriter for ShallowStore {
+        fn insert_branch(&mut self, branch: Arc<BranchNode>) -> Result<()> {
+            self.inner.insert_branch(Arc::new(branch.to_shallow()))
+        }
+
+        fn insert_leaf(&mut self, leaf: Arc<LeafNode>) -> Result<()> {
+            self.inner.insert_leaf(leaf)
+        }
+
+        fn delete_branch(&mut self, key: &NodeHash) -> Result<()> {
+            self.inner.delete_branch(key)
+        }
+
+        fn delete_leaf(&mut self, key: &NodeHash) -> Result<()> {
+            self.inner.delete_leaf(key)
+        }
+
+        fn update_root(&mut self, root: Arc<dyn Node>) -> Result<()> {
+            match root.as_branch() {
+                Some(branch) => self.inner.update_root(Arc::new(branch.to_shallow())),
+                None => self.inner.update_root(root),
+            }
+        }
+    }
+
+    #[test]
+    fn test_hash_referenced_children() -> Result<()> {
+        let mut shallow = FullTree::new(ShallowStore::default());
+        let mut full = FullTree::new(DefaultStore::new());
+        for i in 1..=4u8 {
+            shallow.insert([i; 32], vec![i], i as Sum)?;
+            full.insert([i; 32], vec![i], i as Sum)?;
+        }
+        shallow.delete([2u8; 32])?;
+        full.delete([2u8; 32])?;
+
+        // Children are loaded from the store on demand and yield the same tree
+        let root_hash = shallow.root()?.node_hash();
+        assert_eq!(root_hash, full.root()?.node_hash());
+        assert_eq!(shallow.get([3u8; 32])?, Some((vec![3], 3)));
+        assert_eq!(shallow.get([2u8; 32])?, None);
+
+        let proof = shallow.merkle_proof([3u8; 32])?;
+        assert_eq!(proof, full.merkle_proof([3u8; 32])?);
+        assert!(proof.verify([3u8; 32], &LeafNode::new([3u8; 32], vec![3], 3), root_hash));
+
+        assert!(shallow.verify_integrity()?.is_ok());
+        shallow.compact()?;
+        assert_eq!(shallow.get([4u8; 32])?, Some((vec![4], 4)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_boxed_stores() -> Result<()> {
+        let backends: Vec<BoxedStore> = vec![
+            Box::new(DefaultStore::new()),
+            Box::new(ConcurrentStore::new()),
+            Box::new(CachedStore::new(DefaultStore::new(), 16)),
+        ];
+        let mut roots = Vec::new();
+        for store in backends {
+            let mut tree = FullTree::new(store);
+            tree.insert([1u8; 32], b"one".to_vec(), 1)?;
+            tree.insert([2u8; 32], b"two".to_vec(), 2)?;
+            tree.delete([1u8; 32])?;
+            assert_eq!(tree.get([2u8; 32])?, Some((b"two".to_vec(), 2)));
+            roots.push(tree.root()?.node_hash());
+        }
+        assert!(roots.windows(2).all(|pair| pair[0] == pair[1]));
+
+        // Boxed trees can be shared between threads
+        let store: BoxedStore = Box::new(DefaultStore::new());
+        let tree = SharedTree::new(store);
+        std::thread::scope(|scope| {
+            let insert = scope.spawn(|| tree.insert([3u8; 32], b"three".to_vec(), 3));
+            assert!(insert.join().unwrap().is_ok());
+        });
+        assert_eq!(tree.total_sum()?, 3);
+
+        Ok(())
+    }
+
+    type Children = (Arc<dyn Node>, Arc<dyn Node>);
+
+    /// A store serving branch children from an adjacency map, and failing branch lookups.
+    #[derive(Default)]
+    struct AdjacencyStore {
+        inner: ShallowStore,
+        children: HashMap<NodeHash, Children>,
+    }
+
+    impl TreeStoreReader for AdjacencyStore {
+        fn root_node(&self) -> Result<Arc<dyn Node>> {
+            self.inner.root_node()
+        }
+
+        fn get_branch(&self, key: &NodeHash) -> Result<Option<Arc<BranchNode>>> {
+            panic!("branch {key} loaded instead of its children");
+        }
+
+        fn get_leaf(&self, key: &NodeHash) -> Result<Option<Arc<LeafNode>>> {
+            self.inner.get_leaf(key)
+        }
+
+        fn get_children(&self, height: usize, hash: &NodeHash) -> Result<Children> {
+            match self.children.get(hash) {
+                Some((left, right)) => Ok((left.clone(), right.clone())),
+                None => self.inner.get_children(height, hash),
+            }
+        }
+    }
+
+    #[test]
+    fn test_get_children_serves_proofs() -> Result<()> {
+        let mut full = FullTree::new(DefaultStore::new());
+        let mut adjacency = AdjacencyStore::default();
+        for i in 1..=4u8 {
+            full.insert([i; 32], vec![i], i as Sum)?;
+            adjacency
+                .inner
+                .insert_leaf(Arc::new(LeafNode::new([i; 32], vec![i], i as Sum)))?;
+        }
+        for hash in full.store().branch_hashes()? {
+            let branch = full.store().get_branch(&hash)?.unwrap().to_shallow();
+            adjacency
+                .children
+                .insert(hash, (branch.left.clone(), branch.right.clone()));
+        }
+        adjacency.inner.update_root(full.root()?)?;
+        let tree = FullTree::new(adjacency);
+
+        let keys = [[3u8; 32], [9u8; 32]];
+        assert_eq!(tree.merkle_proof(keys[0])?, full.merkle_proof(keys[0])?);
+        assert_eq!(tree.merkle_proofs(keys)?, full.merkle_proofs(keys)?);
+        assert_eq!(tree.path(keys[1])?, full.path(keys[1])?);
+
+        // The default implementation reads the branch, and knows the empty subtrees
+        let root = full.root()?;
+        let (left, right) = full.store().get_children(0, &root.node_hash())?;
+        assert_eq!(left.node_sum() + right.node_sum(), full.total_sum()?);
+        let empty = EmptyTree::hash_at(5);
+        assert_eq!(
+            full.store().get_children(5, &empty)?.0.node_hash(),
+            EmptyTree::hash_at(6)
+        );
+        assert!(matches!(
+            full.store().get_children(1, &NodeHash::new([7u8; 32])),
+            Err(MssmtError::NodeNotFound(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_update_sum_matches_insert() -> Result<()> {
+        let mut tree = FullTree::new(DefaultStore::new());
+        let mut expected = FullTree::new(DefaultStore::new());
+        for i in 1..=4u8 {
+            tree.insert([i; 32], vec![i], i as Sum)?;
+            expected.insert([i; 32], vec![i], i as Sum)?;
+        }
+
+        assert_eq!(tree.update_sum([2u8; 32], 20)?, Some(2));
+        assert_eq!(tree.adjust_sum([3u8; 32], -3)?, Some(0));
+        expected.insert([2u8; 32], vec![2], 20)?;
+        expected.insert([3u8; 32], vec![3], 0)?;
+        assert_eq!(tree.root()?.node_hash(), expected.root()?.node_hash());
+        assert!(tree.verify_integrity()?.is_ok());
+
+        // Failed adjustments and absent keys leave the tree unchanged
+        let root_hash = tree.root()?.node_hash();
+        assert!(matches!(
+            tree.adjust_sum([1u8; 32], -2),
+            Err(MssmtError::SumOverflow)
+        ));
+        assert!(matches!(
+            tree.update_sum([1u8; 32], Sum::MAX),
+            Err(MssmtError::SumOverflow)
+        ));
+        assert_eq!(tree.adjust_sum([9u8; 32], 1)?, None);
+        assert_eq!(tree.root()?.node_hash(), root_hash);
+        assert_eq!(tree.get([1u8; 32])?, Some((vec![1], 1)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_modify_upserts_and_deletes() -> Result<()> {
+        let mut tree = FullTree::new(DefaultStore::new());
+        tree.insert([1u8; 32], b"one".to_vec(), 1)?;
+
+        tree.modify([2u8; 32], |entry| {
+            assert!(entry.is_none());
+            Some((b"two".to_vec(), 2))
+        })?;
+        tree.modify([1u8; 32], |entry| {
+            entry.map(|(value, sum)| (value, sum * 10))
+        })?;
+        assert_eq!(tree.get([1u8; 32])?, Some((b"one".to_vec(), 10)));
+        assert_eq!(tree.get([2u8; 32])?, Some((b"two".to_vec(), 2)));
+
+        // Deleting through modify matches a plain delete
+        let mut expected = FullTree::new(DefaultStore::new());
+        expected.insert([2u8; 32], b"two".to_vec(), 2)?;
+        tree.modify([1u8; 32], |_| None)?;
+        assert_eq!(tree.root()?.node_hash(), expected.root()?.node_hash());
+        assert_eq!(tree.get([1u8; 32])?, None);
+
+        // Leaving an absent key absent is not a root change
+        let version = tree.root_version();
+        tree.modify([3u8; 32], |_| None)?;
+        assert_eq!(tree.root_version(), version);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_conditional_writes() -> Result<()> {
+        let mut tree = FullTree::new(DefaultStore::new());
+        assert!(tree.insert_if_absent([1u8; 32], b"one".to_vec(), 1)?);
+        let version = tree.root_version();
+        assert!(!tree.insert_if_absent([1u8; 32], b"uno".to_vec(), 1)?);
+
+        // Mismatched expectations leave the tree unchanged
+        assert!(!tree.compare_and_swap([1u8; 32], None, Some((b"x".to_vec(), 9)))?);
+        assert!(!tree.compare_and_swap([1u8; 32], Some((b"one".to_vec(), 2)), None)?);
+        assert!(!tree.compare_and_swap([2u8; 32], Some((b"one".to_vec(), 1)), None)?);
+        assert_eq!(tree.root_version(), version);
+
+        // Matching expectations insert, update and delete
+        assert!(tree.compare_and_swap([2u8; 32], None, Some((b"two".to_vec(), 2)))?);
+        assert!(tree.compare_and_swap(
+            [1u8; 32],
+            Some((b"one".to_vec(), 1)),
+            Some((b"1".to_vec(), 10))
+        )?);
+        assert!(tree.compare_and_swap([2u8; 32], Some((b"two".to_vec(), 2)), None)?);
+        assert!(tree.compare_and_swap([3u8; 32], None, None)?);
+
+        let mut expected = FullTree::new(DefaultStore::new());
+        expected.insert([1u8; 32], b"1".to_vec(), 10)?;
+        assert_eq!(tree.root()?.node_hash(), expected.root()?.node_hash());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_with_proof_matches_separate_calls() -> Result<()> {
+        let mut tree = FullTree::new(DefaultStore::new());
+        for i in 1..=8u8 {
+            tree.insert([i * 31; 32], vec![i], i as Sum)?;
+        }
+
+        for i in 1..=8u8 {
+            let key = [i * 31; 32];
+            let (value, sum, proof) = tree.get_with_proof(key)?.expect("key is present");
+            assert_eq!(tree.get(key)?, Some((value, sum)));
+            assert_eq!(proof, tree.merkle_proof(key)?);
+        }
+        assert!(tree.get_with_proof([0u8; 32])?.is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_many_matches_get() -> Result<()> {
+        let mut tree = FullTree::new(DefaultStore::new());
+        for i in 1..=16u8 {
+            tree.insert([i * 13; 32], vec![i], i as Sum)?;
+        }
+
+        let keys: Vec<[u8; 32]> = (0..=19u8)
+            .rev()
+            .map(|i| [i * 13; 32])
+            .chain([[26u8; 32]])
+            .collect();
+        let entries = tree.get_many(keys.iter().copied())?;
+        assert_eq!(entries.len(), keys.len());
+        for (key, entry) in keys.iter().zip(&entries) {
+            assert_eq!(*entry, tree.get(*key)?);
+        }
+        assert!(tree.get_many(Vec::<[u8; 32]>::new())?.is_empty());
+
+        Ok(())
+    }
+
+    #[cfg(feature = "u128")]
+    #[test]
+    fn test_sums_beyond_u64() -> Result<()> {
+        let mut tree = FullTree::new(DefaultStore::new());
+        tree.insert([1u8; 32], b"a".to_vec(), u64::MAX.into())?;
+        tree.insert([2u8; 32], b"b".to_vec(), u64::MAX.into())?;
+        assert_eq!(tree.total_sum()?, 2 * Sum::from(u64::MAX));
+
+        let leaf = LeafNode::new([2u8; 32], b"b".to_vec(), u64::MAX.into());
+        let proof = Proof::from_hex(&tree.merkle_proof([2u8; 32])?.to_hex())?;
+        assert!(proof.verify([2u8; 32], &leaf, tree.root()?.node_hash()));
+
+        Ok(())
+    }
 }
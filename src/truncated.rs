@@ -0,0 +1,258 @@
+//! Trees of truncated depth for the Merkle-Sum Sparse Merkle Tree.
+//!
+//! A full tree has one level per key bit, so every insert rewrites and every proof carries 256 nodes.
+//! When keys are already unique in their first few bytes, as in permissioned settings where keys are
+//! assigned rather than derived from untrusted input, a `TruncatedTree` places leaves by a key prefix
+//! of `P` bytes instead, in a tree of `8 * P` levels.
+//!
+//! This gives up the sparsity guarantee of the full tree: two keys sharing a prefix would compete for
+//! the same leaf. The full key is therefore committed in the leaf value, and inserting a key whose prefix
+//! is taken by another key fails with `MssmtError::KeyCollision` instead of overwriting it.
+
+use crate::error::{MssmtError, Result};
+use crate::hash_utils::to_array;
+use crate::key::Key;
+use crate::node::{LeafNode, Node, Sum, HASH_SIZE};
+use crate::proof::Proof;
+use crate::store::{TreeStore, TreeStoreReader};
+use crate::tree::FullTree;
+use std::sync::Arc;
+
+/// Returns the prefix of `key` placing its leaf in a tree truncated to `P` bytes.
+///
+/// # Panics
+///
+/// Panics if `P` is larger than the key size.
+pub fn key_prefix<const P: usize>(key: impl Into<Key>) -> [u8; P] {
+    let mut prefix = [0u8; P];
+    prefix.copy_from_slice(&key.into().0[..P]);
+    prefix
+}
+
+/// Returns the leaf committed for `key` in a tree truncated to `P` bytes.
+///
+/// The leaf sits at the key prefix and its value is the full key followed by `value`. Proofs of a
+/// `TruncatedTree` are verified against this leaf.
+///
+/// # Examples
+///
+/// ```rust
+/// use mssmt::truncated::{key_prefix, truncated_leaf, TruncatedTree};
+/// use mssmt::{DefaultStore, Node};
+///
+/// let mut tree = TruncatedTree::new(DefaultStore::<8>::default());
+/// tree.insert([1u8; 32], b"one".to_vec(), 1).unwrap();
+///
+/// let proof = tree.merkle_proof([1u8; 32]).unwrap();
+/// assert_eq!(proof.nodes.len(), 64);
+///
+/// let leaf = truncated_leaf::<8>([1u8; 32], b"one".to_vec(), 1);
+/// let root_hash = tree.root().unwrap().node_hash();
+/// assert!(proof.verify(key_prefix::<8>([1u8; 32]), &leaf, root_hash));
+/// ```
+pub fn truncated_leaf<const P: usize>(
+    key: impl Into<Key>,
+    value: Vec<u8>,
+    sum: Sum,
+) -> LeafNode<P> {
+    let key = key.into();
+    let mut committed = Vec::with_capacity(HASH_SIZE + value.len());
+    committed.extend_from_slice(key.as_bytes());
+    committed.extend_from_slice(&value);
+    LeafNode::new(key_prefix(key), committed, sum)
+}
+
+/// Splits a committed leaf value into the full key and the value.
+fn split_leaf_value(committed: &[u8]) -> Result<(Key, &[u8])> {
+    if committed.len() < HASH_SIZE {
+        return Err(MssmtError::InvalidEncoding(format!(
+            "truncated leaf value of {} bytes does not hold a key",
+            committed.len()
+        )));
+    }
+    let (key, value) = committed.split_at(HASH_SIZE);
+    Ok((Key(to_array(key)), value))
+}
+
+/// A Merkle-Sum Sparse Merkle Tree placing 32-byte keys by their first `P` bytes.
+///
+/// The tree has `8 * P` levels, 64 by default, so inserts rebuild and proofs carry that many nodes
+/// instead of 256. Keys must be unique in their prefix: inserting a key whose prefix already holds
+/// another key fails with `MssmtError::KeyCollision`.
+///
+/// # Type Parameters
+///
+/// - `S`: The storage backend, holding leaves with `P`-byte keys.
+/// - `P`: The prefix size in bytes, at most 32.
+///
+/// # Examples
+///
+/// ```rust
+/// use mssmt::truncated::TruncatedTree;
+/// use mssmt::{DefaultStore, MssmtError};
+///
+/// let mut tree = TruncatedTree::new(DefaultStore::<8>::default());
+/// tree.insert([1u8; 32], b"one".to_vec(), 1).unwrap();
+/// assert_eq!(tree.get([1u8; 32]).unwrap(), Some((b"one".to_vec(), 1)));
+///
+/// // Another key with the same 8-byte prefix is rejected
+/// let mut other = [1u8; 32];
+/// other[31] = 2;
+/// assert!(matches!(
+///     tree.insert(other, b"two".to_vec(), 2),
+///     Err(MssmtError::KeyCollision { .. })
+/// ));
+/// ```
+pub struct TruncatedTree<S, const P: usize = 8> {
+    tree: FullTree<S, P>,
+}
+
+impl<S: TreeStoreReader<P>, const P: usize> TruncatedTree<S, P> {
+    /// Creates a truncated tree over `store`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `P` is zero or larger than the key size.
+    pub fn new(store: S) -> Self {
+        assert!(
+            P > 0 && P <= HASH_SIZE,
+            "truncated prefix must be between 1 and {} bytes",
+            HASH_SIZE
+        );
+        Self {
+            tree: FullTree::new(store),
+        }
+    }
+
+    /// Returns the underlying tree, whose leaves are keyed by prefix.
+    pub fn tree(&self) -> &FullTree<S, P> {
+        &self.tree
+    }
+
+    /// Consumes the truncated tree, returning the underlying tree.
+    pub fn into_tree(self) -> FullTree<S, P> {
+        self.tree
+    }
+
+    /// Returns the root node of the tree.
+    pub fn root(&self) -> Result<Arc<dyn Node>> {
+        self.tree.root()
+    }
+
+    /// Retrieves the value and sum associated with a key.
+    ///
+    /// Returns `Ok(None)` if the key is absent, including when its prefix holds another key.
+    pub fn get(&self, key: impl Into<Key>) -> Result<Option<(Vec<u8>, Sum)>> {
+        let key = key.into();
+        let Some((committed, sum)) = self.tree.get(key_prefix::<P>(key))? else {
+            return Ok(None);
+        };
+        let (stored_key, value) = split_leaf_value(&committed)?;
+        Ok((stored_key == key).then(|| (value.to_vec(), sum)))
+    }
+
+    /// Generates a proof for `key`, verified against `truncated_leaf` at `key_prefix`.
+    ///
+    /// If the prefix holds another key, the proof shows that leaf instead, which excludes `key` too.
+    pub fn merkle_proof(&self, key: impl Into<Key>) -> Result<Proof<P>> {
+        self.tree.merkle_proof(key_prefix::<P>(key))
+    }
+
+    /// Returns the key stored at the prefix of `key`, if any.
+    fn stored_key(&self, key: Key) -> Result<Option<Key>> {
+        match self.tree.get(key_prefix::<P>(key))? {
+            Some((committed, _)) => Ok(Some(split_leaf_value(&committed)?.0)),
+            None => Ok(None),
+        }
+    }
+}
+
+impl<S: TreeStore<P>, const P: usize> TruncatedTree<S, P> {
+    /// Inserts a key-value-sum entry, updating it if the key is already present.
+    ///
+    /// # Returns
+    ///
+    /// - The previous value and sum of the key, if any.
+    /// - `MssmtError::KeyCollision` if the prefix of `key` holds another key.
+    /// - `MssmtError::SumOverflow` if the sum would overflow the root sum.
+    pub fn insert(
+        &mut self,
+        key: impl Into<Key>,
+        value: Vec<u8>,
+        sum: Sum,
+    ) -> Result<Option<(Vec<u8>, Sum)>> {
+        let key = key.into();
+        if let Some(existing) = self.stored_key(key)? {
+            if existing != key {
+                return Err(MssmtError::KeyCollision { key, existing });
+            }
+        }
+        let leaf = truncated_leaf::<P>(key, value, sum);
+        let previous = self.tree.insert(leaf.key, leaf.value, leaf.sum)?;
+        previous
+            .map(|(committed, sum)| Ok((split_leaf_value(&committed)?.1.to_vec(), sum)))
+            .transpose()
+    }
+
+    /// Deletes a key, returning its value and sum if it was present.
+    ///
+    /// The leaf of another key sharing the prefix is left untouched.
+    pub fn delete(&mut self, key: impl Into<Key>) -> Result<Option<(Vec<u8>, Sum)>> {
+        let key = key.into();
+        if self.stored_key(key)? != Some(key) {
+            return Ok(None);
+        }
+        let removed = self.tree.delete(key_prefix::<P>(key))?;
+        removed
+            .map(|leaf| Ok((split_leaf_value(&leaf.value)?.1.to_vec(), leaf.sum)))
+            .transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::EmptyTreeOf;
+    use crate::store::DefaultStore;
+
+    #[test]
+    fn test_truncated_tree() -> Result<()> {
+        let mut tree = TruncatedTree::new(DefaultStore::<8>::default());
+        let keys: Vec<Key> = (0..8u8).map(|i| Key::hash([i])).collect();
+        for (i, key) in keys.iter().enumerate() {
+            assert_eq!(tree.insert(*key, vec![i as u8], i as Sum)?, None);
+        }
+        assert_eq!(tree.insert(keys[3], vec![9], 9)?, Some((vec![3], 3)));
+        assert_eq!(tree.get(keys[3])?, Some((vec![9], 9)));
+        assert_eq!(tree.root()?.node_sum(), 34);
+
+        let root_hash = tree.root()?.node_hash();
+        let proof = tree.merkle_proof(keys[5])?;
+        assert_eq!(proof.nodes.len(), 64);
+        let leaf = truncated_leaf::<8>(keys[5], vec![5], 5);
+        assert!(proof.verify(key_prefix::<8>(keys[5]), &leaf, root_hash));
+
+        // A key colliding on the prefix is neither inserted, found nor deleted
+        let mut colliding = keys[5];
+        colliding.0[31] ^= 1;
+        assert!(matches!(
+            tree.insert(colliding, vec![0], 1),
+            Err(MssmtError::KeyCollision { key, existing }) if key == colliding && existing == keys[5]
+        ));
+        assert_eq!(tree.get(colliding)?, None);
+        assert_eq!(tree.delete(colliding)?, None);
+        assert_eq!(tree.root()?.node_hash(), root_hash);
+
+        for (i, key) in keys.iter().enumerate() {
+            let expected = if i == 3 {
+                (vec![9], 9)
+            } else {
+                (vec![i as u8], i as Sum)
+            };
+            assert_eq!(tree.delete(*key)?, Some(expected));
+        }
+        assert_eq!(tree.root()?.node_hash(), EmptyTreeOf::<8>::hash_at(0));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,405 @@
+//! Several live versions of a tree over a single store.
+//!
+//! Tree updates are copy-on-write, so the nodes of previous versions stay in the store until they are
+//! deleted. A `VersionedStore` keeps them available: trees opened with `FullTree::open_at` are pinned to
+//! the root they were opened at, never delete nodes themselves, and keep their root pinned while they
+//! are alive. Readers can keep serving proofs against a previous commitment while a writer builds the
+//! next one, and `VersionedStore::compact` only removes the nodes no live version can reach, keeping
+//! those written by versions still being built.
+
+use crate::compact::{mark_reachable, CompactionReport};
+use crate::error::{MssmtError, Result};
+use crate::node::{BranchNode, LeafNode, Node, NodeHash};
+use crate::store::{resolve_root, TreeStore, TreeStoreReader, TreeStoreWriter};
+use crate::tree::FullTree;
+use parking_lot::{Mutex, RwLock};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+/// A store shared by versions of a tree pinned to different roots.
+///
+/// The root of the underlying store is the latest version: it is moved by every update made through the
+/// latest version, and `VersionedStore::latest` opens a tree at it. Updates through older versions fail
+/// with `MssmtError::StaleVersion`.
+///
+/// # Examples
+///
+/// ```rust
+/// use mssmt::versions::VersionedStore;
+/// use mssmt::{DefaultStore, FullTree, Node};
+///
+/// let versions = VersionedStore::new(DefaultStore::new());
+/// let mut writer = versions.latest().unwrap();
+/// writer.insert([1u8; 32], b"one".to_vec(), 1).unwrap();
+///
+/// // A reader keeps serving the first commitment while the writer moves on
+/// let committed = writer.root().unwrap().node_hash();
+/// let reader = FullTree::open_at(&versions, committed).unwrap();
+/// writer.delete([1u8; 32]).unwrap();
+/// writer.insert([2u8; 32], b"two".to_vec(), 2).unwrap();
+///
+/// assert_eq!(reader.get([1u8; 32]).unwrap(), Some((b"one".to_vec(), 1)));
+/// assert_eq!(writer.get([1u8; 32]).unwrap(), None);
+///
+/// // Nodes of the pinned version survive compaction until the reader is dropped
+/// versions.compact().unwrap();
+/// assert_eq!(reader.get([1u8; 32]).unwrap(), Some((b"one".to_vec(), 1)));
+/// drop(reader);
+/// assert_eq!(versions.compact().unwrap().leaves_removed, 1);
+/// ```
+pub struct VersionedStore<S> {
+    store: RwLock<S>,
+    // The number of live versions pinned to each root
+    pins: Mutex<HashMap<NodeHash, usize>>,
+    // The number of versions that wrote each node and have not committed a root since
+    staged: Mutex<HashMap<NodeHash, usize>>,
+}
+
+impl<S> VersionedStore<S> {
+    /// Creates a versioned store over `store`, whose root is the latest version.
+    pub fn new(store: S) -> Self {
+        Self {
+            store: RwLock::new(store),
+            pins: Mutex::new(HashMap::new()),
+            staged: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the root hashes of the live versions, in ascending byte order.
+    pub fn live_versions(&self) -> Vec<NodeHash> {
+        let mut versions: Vec<NodeHash> = self.pins.lock().keys().copied().collect();
+        versions.sort_unstable_by(|a, b| a.as_bytes().cmp(b.as_bytes()));
+        versions
+    }
+
+    /// Consumes the versioned store, returning the underlying store.
+    pub fn into_inner(self) -> S {
+        self.store.into_inner()
+    }
+
+    fn pin(&self, hash: NodeHash) {
+        *self.pins.lock().entry(hash).or_insert(0) += 1;
+    }
+
+    fn unpin(&self, hash: &NodeHash) {
+        release(&mut self.pins.lock(), hash);
+    }
+
+    fn release_staged(&self, hashes: &[NodeHash]) {
+        let mut staged = self.staged.lock();
+        for hash in hashes {
+            release(&mut staged, hash);
+        }
+    }
+}
+
+fn release(counts: &mut HashMap<NodeHash, usize>, hash: &NodeHash) {
+    if let Some(count) = counts.get_mut(hash) {
+        *count -= 1;
+        if *count == 0 {
+            counts.remove(hash);
+        }
+    }
+}
+
+impl<S: TreeStore> VersionedStore<S> {
+    /// Opens a tree at the latest version, the root of the underlying store.
+    pub fn latest(&self) -> Result<FullTree<Version<'_, S>>> {
+        let root = self.store.read().root_node()?;
+        Ok(FullTree::new(Version::pinned(self, root)))
+    }
+
+    /// Deletes every node that is not reachable from the latest version or a live version.
+    ///
+    /// Nodes written by versions that have not committed their root yet are kept, so compaction can
+    /// run while a new version is being built. The store must be able to list its nodes. Updates
+    /// through versions are blocked while the store is compacted.
+    pub fn compact(&self) -> Result<CompactionReport> {
+        let mut store = self.store.write();
+        let mut reachable: HashSet<NodeHash> = self.staged.lock().keys().copied().collect();
+        mark_reachable(&*store, &store.root_node()?, 0, &mut reachable)?;
+        for hash in self.live_versions() {
+            mark_reachable(&*store, &resolve_root(&*store, &hash)?, 0, &mut reachable)?;
+        }
+
+        let mut report = CompactionReport::default();
+        for hash in store.branch_hashes()? {
+            if !reachable.contains(&hash) {
+                store.delete_branch(&hash)?;
+                report.branches_removed += 1;
+            }
+        }
+        for hash in store.leaf_hashes()? {
+            if !reachable.contains(&hash) {
+                store.delete_leaf(&hash)?;
+                report.leaves_removed += 1;
+            }
+        }
+        Ok(report)
+    }
+}
+
+impl<'a, S: TreeStore> FullTree<Version<'a, S>> {
+    /// Opens the version of the tree with root `root_hash` in `versions`.
+    ///
+    /// The tree can be updated like any other while it is the latest version: its updates create a new
+    /// version, which becomes the latest one, and leave the nodes of previous versions in the store.
+    /// Updates to a version that another one has moved past fail with `MssmtError::StaleVersion`
+    /// instead of discarding the updates committed in between, and the tree keeps its root.
+    ///
+    /// # Returns
+    ///
+    /// - The tree, pinned to `root_hash` until it is updated or dropped.
+    /// - `MssmtError::NodeNotFound` if no such root is in the store.
+    pub fn open_at(versions: &'a VersionedStore<S>, root_hash: NodeHash) -> Result<Self> {
+        let root = resolve_root(&*versions.store.read(), &root_hash)?;
+        Ok(FullTree::new(Version::pinned(versions, root)))
+    }
+}
+
+/// The view of a `VersionedStore` backing one version of the tree.
+///
+/// Node reads and writes go to the shared store, node deletions are deferred to
+/// `VersionedStore::compact`, and root updates move the pin of the version to the new root. The nodes
+/// written by the version are kept by compaction until its next root update. A root update only
+/// succeeds if the version is still the latest one, and otherwise fails with
+/// `MssmtError::StaleVersion`; the nodes it wrote are then left for `VersionedStore::compact`. The key
+/// index of the shared store follows the latest version only, so lookups walk the path from the root.
+pub struct Version<'a, S> {
+    versions: &'a VersionedStore<S>,
+    root: Arc<dyn Node>,
+    // The nodes written since the last root update
+    staged: Vec<NodeHash>,
+}
+
+impl<'a, S> Version<'a, S> {
+    fn pinned(versions: &'a VersionedStore<S>, root: Arc<dyn Node>) -> Self {
+        versions.pin(root.node_hash());
+        Self {
+            versions,
+            root,
+            staged: Vec::new(),
+        }
+    }
+
+    /// Records nodes written by the version, while the store lock is held so that compaction sees
+    /// them as soon as they are in the store.
+    fn stage(&mut self, hashes: impl IntoIterator<Item = NodeHash>) {
+        let mut staged = self.versions.staged.lock();
+        for hash in hashes {
+            *staged.entry(hash).or_insert(0) += 1;
+            self.staged.push(hash);
+        }
+    }
+}
+
+impl<S> Drop for Version<'_, S> {
+    fn drop(&mut self) {
+        self.versions.release_staged(&self.staged);
+        self.versions.unpin(&self.root.node_hash());
+    }
+}
+
+impl<S: TreeStoreReader> TreeStoreReader for Version<'_, S> {
+    fn root_node(&self) -> Result<Arc<dyn Node>> {
+        Ok(self.root.clone())
+    }
+
+    fn get_branch(&self, key: &NodeHash) -> Result<Option<Arc<BranchNode>>> {
+        self.versions.store.read().get_branch(key)
+    }
+
+    fn get_leaf(&self, key: &NodeHash) -> Result<Option<Arc<LeafNode>>> {
+        self.versions.store.read().get_leaf(key)
+    }
+}
+
+impl<S: TreeStore> TreeStoreWriter for Version<'_, S> {
+    fn insert_branch(&mut self, branch: Arc<BranchNode>) -> Result<()> {
+        let mut store = self.versions.store.write();
+        let hash = branch.node_hash();
+        store.insert_branch(branch)?;
+        self.stage([hash]);
+        Ok(())
+    }
+
+    fn insert_leaf(&mut self, leaf: Arc<LeafNode>) -> Result<()> {
+        let mut store = self.versions.store.write();
+        let hash = leaf.node_hash();
+        store.insert_leaf(leaf)?;
+        self.stage([hash]);
+        Ok(())
+    }
+
+    fn insert_nodes(
+        &mut self,
+        branches: Vec<Arc<BranchNode>>,
+        leaves: Vec<Arc<LeafNode>>,
+    ) -> Result<()> {
+        let mut store = self.versions.store.write();
+        let hashes: Vec<NodeHash> = branches
+            .iter()
+            .map(|branch| branch.node_hash())
+            .chain(leaves.iter().map(|leaf| leaf.node_hash()))
+            .collect();
+        store.insert_nodes(branches, leaves)?;
+        self.stage(hashes);
+        Ok(())
+    }
+
+    fn delete_branch(&mut self, _key: &NodeHash) -> Result<()> {
+        Ok(())
+    }
+
+    fn delete_leaf(&mut self, _key: &NodeHash) -> Result<()> {
+        Ok(())
+    }
+
+    fn update_root(&mut self, root: Arc<dyn Node>) -> Result<()> {
+        // The comparison and the update happen under the store lock, so versions cannot interleave
+        let mut store = self.versions.store.write();
+        let latest = store.root_node()?.node_hash();
+        let result = if latest != self.root.node_hash() {
+            Err(MssmtError::StaleVersion {
+                expected: self.root.node_hash(),
+                actual: latest,
+            })
+        } else {
+            store.update_root(root.clone())
+        };
+        if result.is_ok() {
+            // The new root is pinned before the staged nodes are released, both under the store lock
+            self.versions.pin(root.node_hash());
+            self.versions.unpin(&self.root.node_hash());
+            self.root = root;
+        }
+        self.versions
+            .release_staged(&std::mem::take(&mut self.staged));
+        drop(store);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::Sum;
+    use crate::store::DefaultStore;
+
+    #[test]
+    fn test_versions_pin_their_roots() -> Result<()> {
+        let versions = VersionedStore::new(DefaultStore::new());
+        let mut writer = versions.latest()?;
+        for i in 1..=4u8 {
+            writer.insert([i; 32], vec![i], i as Sum)?;
+        }
+        let v1 = writer.root()?.node_hash();
+        let reader = FullTree::open_at(&versions, v1)?;
+        let proof = reader.merkle_proof([2u8; 32])?;
+
+        writer.delete([2u8; 32])?;
+        writer.insert([3u8; 32], b"updated".to_vec(), 30)?;
+        let v2 = writer.root()?.node_hash();
+        let live = versions.live_versions();
+        assert!(live.len() == 2 && live.contains(&v1) && live.contains(&v2));
+
+        // The reader serves the first version, before and after compaction
+        versions.compact()?;
+        assert_eq!(reader.root()?.node_hash(), v1);
+        assert_eq!(reader.get([2u8; 32])?, Some((vec![2], 2)));
+        assert_eq!(reader.get([3u8; 32])?, Some((vec![3], 3)));
+        assert_eq!(reader.merkle_proof([2u8; 32])?, proof);
+        assert!(reader.verify_integrity()?.is_ok());
+        assert_eq!(
+            versions.latest()?.get([3u8; 32])?,
+            Some((b"updated".to_vec(), 30))
+        );
+
+        // Once the reader is gone, only the latest version is kept
+        drop(reader);
+        assert_eq!(versions.live_versions(), [v2]);
+        let report = versions.compact()?;
+        assert_eq!(report.leaves_removed, 2);
+        assert!(matches!(
+            FullTree::open_at(&versions, v1),
+            Err(MssmtError::NodeNotFound(_))
+        ));
+
+        drop(writer);
+        let store = versions.into_inner();
+        assert_eq!(store.leaves.len(), 3);
+        assert_eq!(store.root_node()?.node_hash(), v2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_stale_versions_cannot_overwrite_updates() -> Result<()> {
+        let versions = VersionedStore::new(DefaultStore::new());
+        versions.latest()?.insert([1u8; 32], b"one".to_vec(), 1)?;
+
+        // Two writers open the same version, and the first one commits
+        let mut first = versions.latest()?;
+        let mut second = versions.latest()?;
+        let base = second.root()?.node_hash();
+        first.insert([2u8; 32], b"two".to_vec(), 2)?;
+        let committed = first.root()?.node_hash();
+
+        // The second writer fails instead of discarding the first update
+        let result = second.insert([3u8; 32], b"three".to_vec(), 3);
+        assert!(matches!(
+            result,
+            Err(MssmtError::StaleVersion { expected, actual })
+                if expected == base && actual == committed
+        ));
+        assert_eq!(second.root()?.node_hash(), base);
+
+        let latest = versions.latest()?;
+        assert_eq!(latest.root()?.node_hash(), committed);
+        assert_eq!(latest.get([2u8; 32])?, Some((b"two".to_vec(), 2)));
+        assert_eq!(latest.get([3u8; 32])?, None);
+
+        // Reopened at the latest version, the second writer can retry
+        drop(second);
+        let mut second = versions.latest()?;
+        second.insert([3u8; 32], b"three".to_vec(), 3)?;
+        assert_eq!(versions.latest()?.total_sum()?, 6);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_compaction_keeps_nodes_of_versions_being_built() -> Result<()> {
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        let versions = VersionedStore::new(DefaultStore::new());
+        let done = AtomicBool::new(false);
+        std::thread::scope(|scope| -> Result<()> {
+            let writer = scope.spawn(|| -> Result<()> {
+                let mut tree = versions.latest()?;
+                for i in 0..100u32 {
+                    let mut key = [0u8; 32];
+                    key[..4].copy_from_slice(&i.to_be_bytes());
+                    tree.insert(key, vec![1], 1)?;
+                }
+                done.store(true, Ordering::SeqCst);
+                Ok(())
+            });
+            while !done.load(Ordering::SeqCst) && !writer.is_finished() {
+                versions.compact()?;
+                std::thread::yield_now();
+            }
+            writer.join().unwrap()
+        })?;
+
+        // Every committed root only references nodes still in the store
+        let latest = versions.latest()?;
+        assert!(latest.verify_integrity()?.is_ok());
+        assert_eq!(latest.total_sum()?, 100);
+        drop(latest);
+        versions.compact()?;
+        assert_eq!(versions.into_inner().leaves.len(), 100);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,255 @@
+//! Visual representations of a Merkle-Sum Sparse Merkle Tree for debugging.
+//!
+//! Only non-empty subtrees are rendered, since a sparse tree consists almost entirely of empty
+//! branches. Hashes are truncated to their first four bytes to keep the output readable.
+
+use crate::error::Result;
+use crate::node::{EmptyTree, Node, NodeHash, HASH_SIZE, MAX_TREE_LEVELS};
+use crate::store::{resolve_node, TreeStoreReader};
+use crate::tree::FullTree;
+use std::fmt::Write;
+use std::sync::Arc;
+
+impl<S: TreeStoreReader> FullTree<S> {
+    /// Renders the non-empty part of the tree as a Graphviz DOT graph.
+    ///
+    /// Branches are labelled with their height, truncated hash and sum, and leaves with their
+    /// truncated key and sum. Subtrees below `max_depth` are collapsed into a single node.
+    ///
+    /// # Arguments
+    ///
+    /// - `max_depth`: The number of levels below the root to render.
+    ///
+    /// # Returns
+    ///
+    /// - The DOT source of the graph, which can be rendered with `dot -Tsvg`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use mssmt::{DefaultStore, FullTree};
+    ///
+    /// let mut tree = FullTree::new(DefaultStore::new());
+    /// tree.insert([1u8; 32], b"one".to_vec(), 1).unwrap();
+    /// tree.insert([255u8; 32], b"two".to_vec(), 2).unwrap();
+    ///
+    /// let dot = tree.to_dot(4).unwrap();
+    /// assert!(dot.starts_with("digraph mssmt {"));
+    /// assert!(dot.contains("sum=3"));
+    /// ```
+    pub fn to_dot(&self, max_depth: usize) -> Result<String> {
+        let mut dot = String::from("digraph mssmt {\n    node [shape=box, fontname=monospace];\n");
+        let mut next_id = 0;
+        write_dot_node(
+            self.store(),
+            &self.root()?,
+            0,
+            max_depth,
+            &mut next_id,
+            &mut dot,
+        )?;
+        dot.push_str("}\n");
+        Ok(dot)
+    }
+
+    /// Formats the non-empty part of the tree as an indented text dump.
+    ///
+    /// Chains of branches with a single non-empty child are skipped, so only the root, the
+    /// branches where paths split and the leaves are printed. Each branch shows its height,
+    /// truncated hash and sum, and each leaf its full key in hex and its sum. Comparing the dumps
+    /// of two implementations pinpoints the first subtree whose hash or sum differs.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use mssmt::{DefaultStore, FullTree};
+    ///
+    /// let mut tree = FullTree::new(DefaultStore::new());
+    /// tree.insert([0u8; 32], b"one".to_vec(), 1).unwrap();
+    /// tree.insert([255u8; 32], b"two".to_vec(), 2).unwrap();
+    ///
+    /// let dump = tree.format_tree().unwrap();
+    /// assert!(dump.starts_with("h0 "));
+    /// assert_eq!(dump.lines().count(), 3);
+    /// ```
+    pub fn format_tree(&self) -> Result<String> {
+        let root = self.root()?;
+        let mut out = String::new();
+        if root.node_hash() == EmptyTree::hash_at(0) {
+            out.push_str("(empty)\n");
+        } else {
+            write_text_node(self.store(), &root, 0, 0, &mut out)?;
+        }
+        Ok(out)
+    }
+}
+
+/// Writes a node and its non-empty descendants as indented lines.
+fn write_text_node<S: TreeStoreReader>(
+    store: &S,
+    node: &Arc<dyn Node>,
+    height: usize,
+    depth: usize,
+    out: &mut String,
+) -> Result<()> {
+    let indent = "  ".repeat(depth);
+    let node = resolve_node(store, node, height)?;
+
+    if let Some(leaf) = node.as_leaf::<HASH_SIZE, Vec<u8>>() {
+        let _ = writeln!(
+            out,
+            "{}leaf {} key={} sum={}",
+            indent,
+            short_hash(&leaf.node_hash()),
+            hex::encode(leaf.key),
+            leaf.sum
+        );
+        return Ok(());
+    }
+
+    let Some(branch) = node.as_branch() else {
+        let _ = writeln!(
+            out,
+            "{}h{} {} sum={}",
+            indent,
+            height,
+            short_hash(&node.node_hash()),
+            node.node_sum()
+        );
+        return Ok(());
+    };
+
+    let empty_child_hash = EmptyTree::hash_at(height + 1);
+    let children: Vec<&Arc<dyn Node>> = [&branch.left, &branch.right]
+        .into_iter()
+        .filter(|child| child.node_hash() != empty_child_hash)
+        .collect();
+
+    // Descend through single-child chains without printing them, except at the root
+    if children.len() == 1 && height > 0 {
+        return write_text_node(store, children[0], height + 1, depth, out);
+    }
+
+    let _ = writeln!(
+        out,
+        "{}h{} {} sum={}",
+        indent,
+        height,
+        short_hash(&branch.node_hash()),
+        branch.node_sum()
+    );
+    for child in children {
+        write_text_node(store, child, height + 1, depth + 1, out)?;
+    }
+    Ok(())
+}
+
+/// Writes a node and its non-empty descendants, returning the identifier of the node.
+fn write_dot_node<S: TreeStoreReader>(
+    store: &S,
+    node: &Arc<dyn Node>,
+    height: usize,
+    max_depth: usize,
+    next_id: &mut usize,
+    dot: &mut String,
+) -> Result<usize> {
+    let id = *next_id;
+    *next_id += 1;
+    let node = resolve_node(store, node, height)?;
+
+    let hash = short_hash(&node.node_hash());
+    let sum = node.node_sum();
+
+    if let Some(leaf) = node.as_leaf::<HASH_SIZE, Vec<u8>>() {
+        let _ = writeln!(
+            dot,
+            "    n{} [label=\"leaf {}\\nkey={}\\nsum={}\", shape=ellipse];",
+            id,
+            hash,
+            hex::encode(&leaf.key[..4]),
+            sum
+        );
+        return Ok(id);
+    }
+
+    let branch = match node.as_branch() {
+        Some(branch) if height < max_depth && height < MAX_TREE_LEVELS => branch,
+        _ => {
+            let _ = writeln!(
+                dot,
+                "    n{} [label=\"h{} {}\\nsum={}\", style=dashed];",
+                id, height, hash, sum
+            );
+            return Ok(id);
+        }
+    };
+
+    let _ = writeln!(
+        dot,
+        "    n{} [label=\"h{} {}\\nsum={}\"];",
+        id, height, hash, sum
+    );
+
+    let empty_child_hash = EmptyTree::hash_at(height + 1);
+    for (label, child) in [("0", &branch.left), ("1", &branch.right)] {
+        if child.node_hash() == empty_child_hash {
+            continue;
+        }
+        let child_id = write_dot_node(store, child, height + 1, max_depth, next_id, dot)?;
+        let _ = writeln!(dot, "    n{} -> n{} [label=\"{}\"];", id, child_id, label);
+    }
+
+    Ok(id)
+}
+
+/// Returns the first four bytes of a hash as hex.
+fn short_hash(hash: &NodeHash) -> String {
+    hex::encode(&hash.as_bytes()[..4])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::DefaultStore;
+
+    #[test]
+    fn test_to_dot_renders_path_split() -> Result<()> {
+        let mut tree = FullTree::new(DefaultStore::new());
+        assert_eq!(tree.to_dot(8)?.matches(" -> ").count(), 0);
+
+        // The keys differ in the first bit, so the root splits into two collapsed subtrees
+        tree.insert([0u8; 32], b"left".to_vec(), 1)?;
+        tree.insert([255u8; 32], b"right".to_vec(), 2)?;
+
+        let dot = tree.to_dot(1)?;
+        assert_eq!(dot.matches(" -> ").count(), 2);
+        assert_eq!(dot.matches("style=dashed").count(), 2);
+        assert!(dot.contains("h0 "));
+        assert!(dot.contains("sum=3"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_format_tree_skips_chains() -> Result<()> {
+        let mut tree = FullTree::new(DefaultStore::new());
+        assert_eq!(tree.format_tree()?, "(empty)\n");
+
+        // The keys share their first byte, so the first split is at height 8
+        let key1 = [0u8; 32];
+        let mut key2 = [0u8; 32];
+        key2[1] = 0x80;
+        tree.insert(key1, b"one".to_vec(), 1)?;
+        tree.insert(key2, b"two".to_vec(), 2)?;
+
+        let dump = tree.format_tree()?;
+        let lines: Vec<&str> = dump.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].starts_with("h0 ") && lines[0].ends_with("sum=3"));
+        assert!(lines[1].starts_with("  h8 "));
+        assert!(lines[2].contains(&format!("key={}", hex::encode(key1))));
+        assert!(lines[3].starts_with("    leaf ") && lines[3].ends_with("sum=2"));
+
+        Ok(())
+    }
+}
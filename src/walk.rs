@@ -0,0 +1,167 @@
+//! Depth-first traversal of the nodes of a tree.
+//!
+//! `FullTree::walk` visits the non-empty nodes of a tree from the root down, left before right, and lets
+//! the visitor prune the traversal. Exports, audits and statistics can be written as visitors instead of
+//! re-implementing the traversal and the resolution of hash-referenced nodes.
+
+use crate::error::Result;
+use crate::node::{BranchNode, EmptyTreeOf, LeafValue, Node};
+use crate::store::{resolve_node, TreeStoreReader};
+use crate::tree::FullTree;
+use std::sync::Arc;
+
+/// What a walk does after visiting a node.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum WalkControl {
+    /// Visit the children of the node, then carry on.
+    #[default]
+    Continue,
+    /// Skip the children of the node and carry on with its next sibling.
+    SkipSubtree,
+    /// End the walk.
+    Stop,
+}
+
+impl<S: TreeStoreReader<K, V>, const K: usize, V: LeafValue> FullTree<S, K, V> {
+    /// Visits the non-empty nodes of the tree depth first, with the height of each node.
+    ///
+    /// Nodes are visited before their children, and left children before right ones, so leaves are
+    /// visited in key order. Empty subtrees are skipped, and hash-referenced nodes are loaded from the
+    /// store before they are visited. The root is always visited, even when the tree is empty.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use mssmt::node::NodeView;
+    /// use mssmt::walk::WalkControl;
+    /// use mssmt::{DefaultStore, FullTree};
+    ///
+    /// let mut tree = FullTree::new(DefaultStore::new());
+    /// tree.insert([0x00; 32], b"left".to_vec(), 1).unwrap();
+    /// tree.insert([0x80; 32], b"right".to_vec(), 2).unwrap();
+    ///
+    /// // Collect the values of the leaves under the left child of the root
+    /// let mut values = Vec::new();
+    /// tree.walk(|height, node| match node.as_view::<32, Vec<u8>>() {
+    ///     NodeView::Leaf(leaf) => {
+    ///         values.push(leaf.value.clone());
+    ///         WalkControl::Continue
+    ///     }
+    ///     _ if height == 1 && node.node_sum() != 1 => WalkControl::SkipSubtree,
+    ///     _ => WalkControl::Continue,
+    /// })
+    /// .unwrap();
+    /// assert_eq!(values, [b"left".to_vec()]);
+    /// ```
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(())` once every node has been visited or the visitor stopped the walk.
+    /// - `MssmtError::NodeNotFound` if a node is missing from the store.
+    pub fn walk(&self, mut visitor: impl FnMut(usize, &dyn Node) -> WalkControl) -> Result<()> {
+        let root = self.store().root_node()?;
+        let root = resolve_node(self.store(), &root, 0)?;
+        if visitor(0, root.as_ref()) == WalkControl::Continue {
+            if let Some(branch) = root.as_branch() {
+                self.walk_children(branch, 1, &mut visitor)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Walks the children of `branch`, at `height`, returning `false` if the visitor stopped the walk.
+    fn walk_children(
+        &self,
+        branch: &BranchNode,
+        height: usize,
+        visitor: &mut impl FnMut(usize, &dyn Node) -> WalkControl,
+    ) -> Result<bool> {
+        for child in [&branch.left, &branch.right] {
+            if !self.walk_node(child, height, visitor)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    fn walk_node(
+        &self,
+        node: &Arc<dyn Node>,
+        height: usize,
+        visitor: &mut impl FnMut(usize, &dyn Node) -> WalkControl,
+    ) -> Result<bool> {
+        if EmptyTreeOf::<K>::is_empty_at(height, &node.node_hash()) {
+            return Ok(true);
+        }
+
+        let node = resolve_node(self.store(), node, height)?;
+        match visitor(height, node.as_ref()) {
+            WalkControl::Stop => Ok(false),
+            WalkControl::SkipSubtree => Ok(true),
+            WalkControl::Continue => match node.as_branch() {
+                Some(branch) => self.walk_children(branch, height + 1, visitor),
+                None => Ok(true),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::{tree_levels, NodeKind};
+    use crate::store::DefaultStore;
+
+    #[test]
+    fn test_walk_pruning() -> Result<()> {
+        let mut tree = FullTree::new(DefaultStore::new());
+        let mut visited = 0;
+        tree.walk(|height, node| {
+            assert_eq!((height, node.node_sum()), (0, 0));
+            visited += 1;
+            WalkControl::Continue
+        })?;
+        assert_eq!(visited, 1);
+
+        for (key, sum) in [([0x80u8; 32], 4), ([0x40; 32], 2), ([0x00; 32], 1)] {
+            tree.insert(key, vec![sum as u8], sum)?;
+        }
+
+        // Leaves are visited in key order, at the bottom of the tree
+        let mut leaves = Vec::new();
+        tree.walk(|height, node| {
+            if let Some(leaf) = node.as_leaf::<32, Vec<u8>>() {
+                assert_eq!(height, tree_levels(32));
+                leaves.push(leaf.sum);
+            }
+            WalkControl::Continue
+        })?;
+        assert_eq!(leaves, [1, 2, 4]);
+
+        // Skipping the left child of the root leaves only the right leaf
+        let mut leaves = Vec::new();
+        tree.walk(|height, node| {
+            if let Some(leaf) = node.as_leaf::<32, Vec<u8>>() {
+                leaves.push(leaf.sum);
+            }
+            match (height, node.node_sum()) {
+                (1, 3) => WalkControl::SkipSubtree,
+                _ => WalkControl::Continue,
+            }
+        })?;
+        assert_eq!(leaves, [4]);
+
+        // Stopping at the first leaf ends the walk
+        let mut leaves = 0;
+        tree.walk(|_, node| {
+            if node.kind() == NodeKind::Leaf {
+                leaves += 1;
+                return WalkControl::Stop;
+            }
+            WalkControl::Continue
+        })?;
+        assert_eq!(leaves, 1);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,209 @@
+//! Circuit witnesses for Merkle-Sum Sparse Merkle Tree proofs.
+//!
+//! A SNARK circuit checking an MS-SMT proof needs the leaf, the sibling hashes and sums, and the
+//! direction taken at each level. `Proof::to_circuit_witness` lays these out leaf first, the order in
+//! which a circuit folds them, so circuit builders do not have to re-derive the traversal from the key.
+//!
+//! # Layout
+//!
+//! `CircuitWitness::to_field_elements` flattens the witness into `u128` words, which fit in the scalar
+//! field of any curve used for SNARKs. Byte strings are split into 16-byte big-endian limbs, the last one
+//! padded with zeros on the right. The words are, in order:
+//!
+//! 1. The key, as `K / 16` limbs rounded up (2 for 32-byte keys).
+//! 2. The leaf sum.
+//! 3. The value length in bytes, followed by the value limbs.
+//! 4. For each level from the leaf up to the root: the path bit, the sibling hash as 2 limbs and the
+//!    sibling sum.
+//! 5. The root hash as 2 limbs and the root sum.
+//!
+//! A path bit of 1 means the node on the path is the right child of its parent, so the sibling is hashed
+//! on the left.
+
+use crate::error::ProofError;
+use crate::key::Key;
+use crate::node::{bit_index, tree_levels, LeafNode, LeafValue, NodeHash, Sum, HASH_SIZE};
+use crate::proof::Proof;
+
+/// The size in bytes of the limbs byte strings are split into.
+pub const LIMB_SIZE: usize = 16;
+
+/// One level of a circuit witness, from the leaf up.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WitnessLevel {
+    /// Whether the node on the path is the right child of its parent.
+    pub path_bit: bool,
+    /// The hash of the sibling at this level.
+    pub sibling_hash: NodeHash,
+    /// The sum of the sibling at this level.
+    pub sibling_sum: Sum,
+}
+
+/// The inputs of a circuit verifying an MS-SMT proof, see the module documentation for the layout.
+///
+/// # Examples
+///
+/// ```rust
+/// use mssmt::{DefaultStore, FullTree, LeafNode, Node};
+///
+/// let mut tree = FullTree::new(DefaultStore::new());
+/// tree.insert([1u8; 32], b"one".to_vec(), 1).unwrap();
+///
+/// let leaf = LeafNode::new([1u8; 32], b"one".to_vec(), 1);
+/// let witness = tree
+///     .merkle_proof([1u8; 32])
+///     .unwrap()
+///     .to_circuit_witness([1u8; 32], &leaf)
+///     .unwrap();
+///
+/// assert_eq!(witness.levels.len(), 256);
+/// assert_eq!(witness.root_hash, tree.root().unwrap().node_hash());
+/// assert_eq!(witness.to_field_elements().len(), 2 + 2 + 1 + 256 * 4 + 3);
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CircuitWitness<const K: usize = HASH_SIZE> {
+    pub key: [u8; K],
+    pub value: Vec<u8>,
+    pub sum: Sum,
+    /// The levels of the path, from the leaf up to the root.
+    pub levels: Vec<WitnessLevel>,
+    pub root_hash: NodeHash,
+    pub root_sum: Sum,
+}
+
+impl<const K: usize> CircuitWitness<K> {
+    /// Flattens the witness into field-element-sized words.
+    pub fn to_field_elements(&self) -> Vec<u128> {
+        let mut words = limbs(&self.key);
+        words.push(sum_word(self.sum));
+        words.push(self.value.len() as u128);
+        words.extend(limbs(&self.value));
+        for level in &self.levels {
+            words.push(level.path_bit as u128);
+            words.extend(limbs(level.sibling_hash.as_bytes()));
+            words.push(sum_word(level.sibling_sum));
+        }
+        words.extend(limbs(self.root_hash.as_bytes()));
+        words.push(sum_word(self.root_sum));
+        words
+    }
+}
+
+/// Widens a sum to a witness word.
+#[allow(clippy::useless_conversion)] // `Sum` is already `u128` with the `u128` feature
+fn sum_word(sum: Sum) -> u128 {
+    u128::from(sum)
+}
+
+/// Splits bytes into big-endian limbs, padding the last one with zeros.
+fn limbs(bytes: &[u8]) -> Vec<u128> {
+    bytes
+        .chunks(LIMB_SIZE)
+        .map(|chunk| {
+            let mut limb = [0u8; LIMB_SIZE];
+            limb[..chunk.len()].copy_from_slice(chunk);
+            u128::from_be_bytes(limb)
+        })
+        .collect()
+}
+
+impl<const K: usize> Proof<K> {
+    /// Lays out the proof of `leaf` at `key` as the witness of a verification circuit.
+    ///
+    /// The proof is verified while the witness is built, and the reconstructed root is included, so an
+    /// invalid proof never yields a witness.
+    ///
+    /// # Returns
+    ///
+    /// - The `CircuitWitness` of the proof.
+    /// - `ProofError::InvalidLength` if the proof is not canonical.
+    /// - `ProofError::KeyMismatch` if a non-empty leaf is not stored under `key`.
+    /// - `ProofError::SumOverflow` if the sums overflow.
+    pub fn to_circuit_witness(
+        &self,
+        key: impl Into<Key<K>>,
+        leaf: &LeafNode<K, impl LeafValue>,
+    ) -> Result<CircuitWitness<K>, ProofError> {
+        let key = key.into().0;
+        self.validate()?;
+        if !leaf.is_empty() && leaf.key != key {
+            return Err(ProofError::KeyMismatch);
+        }
+        let (root_hash, root_sum) = self.fold_root(key, leaf)?;
+
+        let levels = (0..tree_levels(K))
+            .rev()
+            .map(|height| WitnessLevel {
+                path_bit: bit_index(height, &key) == 1,
+                sibling_hash: self.nodes[height].node_hash(),
+                sibling_sum: self.nodes[height].node_sum(),
+            })
+            .collect();
+
+        Ok(CircuitWitness {
+            key,
+            value: leaf.value.as_ref().to_vec(),
+            sum: leaf.sum,
+            levels,
+            root_hash,
+            root_sum,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Result;
+    use crate::node::{branch_hash, Node, EMPTY_LEAF_NODE};
+    use crate::store::DefaultStore;
+    use crate::tree::FullTree;
+
+    #[test]
+    fn test_circuit_witness() -> Result<()> {
+        let mut tree = FullTree::new(DefaultStore::new());
+        tree.insert([0x40; 32], vec![7; 20], 3)?;
+        tree.insert([0xc0; 32], b"other".to_vec(), 4)?;
+        let root = tree.root()?;
+
+        let leaf = LeafNode::new([0x40; 32], vec![7; 20], 3);
+        let witness = tree
+            .merkle_proof([0x40; 32])?
+            .to_circuit_witness([0x40; 32], &leaf)?;
+        assert_eq!((witness.root_hash, witness.root_sum), (root.node_hash(), 7));
+
+        // Folding the levels as a circuit would rebuilds the root
+        let (mut hash, mut sum) = (leaf.node_hash(), leaf.sum);
+        for level in &witness.levels {
+            sum += level.sibling_sum;
+            hash = if level.path_bit {
+                branch_hash(&level.sibling_hash, &hash, sum)
+            } else {
+                branch_hash(&hash, &level.sibling_hash, sum)
+            };
+        }
+        assert_eq!(hash, witness.root_hash);
+        assert!(!witness.levels[255].path_bit && witness.levels[254].path_bit);
+
+        let words = witness.to_field_elements();
+        assert_eq!(words.len(), 2 + 2 + 2 + 256 * 4 + 3);
+        assert_eq!(words[..2], [u128::from_be_bytes([0x40; 16]); 2]);
+        assert_eq!(words[2..5], [3, 20, u128::from_be_bytes([7; 16])]);
+        assert_eq!(
+            words[5],
+            u128::from_be_bytes([7, 7, 7, 7, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0])
+        );
+
+        // An exclusion proof yields a witness too, but a leaf under another key does not
+        let proof = tree.merkle_proof([1u8; 32])?;
+        assert!(proof
+            .to_circuit_witness([1u8; 32], &EMPTY_LEAF_NODE)
+            .is_ok());
+        assert_eq!(
+            proof.to_circuit_witness([1u8; 32], &leaf),
+            Err(ProofError::KeyMismatch)
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,420 @@
+//! Deterministic synthetic workloads for comparing stores.
+//!
+//! Backend authors compare their stores by running the same load against each. A `Workload` generates
+//! that load from a `WorkloadSpec` and a seed: the leaves of an initial tree, with a chosen number of
+//! leaves, value sizes and key distribution, and sequences of inserts, lookups, proofs and deletes in a
+//! chosen `OperationMix`. The same spec always yields the same leaves and operations, so two runs over
+//! different stores do identical work and reach the same root, and only their timings differ.
+//!
+//! `Workload::run` applies operations to a tree and reports the time spent per kind of operation. Wrap
+//! the store in a `CountingStore` to also count the node traffic it causes, see `FullTree::measure`.
+
+use crate::error::Result;
+use crate::key::Key;
+use crate::node::{LeafNode, NodeHash, Sum};
+use crate::store::TreeStore;
+use crate::tree::FullTree;
+use std::time::{Duration, Instant};
+
+/// How the keys of a workload are spread over the tree.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum KeyDistribution {
+    /// Hashed keys, spread uniformly like the keys of most applications.
+    #[default]
+    Uniform,
+    /// Consecutive big-endian integers, sharing all but their last bits, so the leaves fill a dense
+    /// subtree at the bottom of a single path.
+    Sequential,
+    /// Hashed keys grouped under `clusters` random 8-byte prefixes, like keys namespaced by account.
+    Clustered { clusters: usize },
+}
+
+/// The shape of a synthetic tree and the seed generating it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct WorkloadSpec {
+    /// The number of leaves of the initial tree.
+    pub leaves: usize,
+    /// The smallest value size in bytes.
+    pub min_value_size: usize,
+    /// The largest value size in bytes. Value sizes are uniform between the two bounds.
+    pub max_value_size: usize,
+    /// The largest leaf sum. Sums are uniform between 1 and this bound.
+    pub max_sum: Sum,
+    /// How the keys are spread over the tree.
+    pub keys: KeyDistribution,
+    /// The seed of every random choice of the workload.
+    pub seed: u64,
+}
+
+impl Default for WorkloadSpec {
+    fn default() -> Self {
+        Self {
+            leaves: 1_000,
+            min_value_size: 32,
+            max_value_size: 32,
+            max_sum: 1_000_000,
+            keys: KeyDistribution::Uniform,
+            seed: 0,
+        }
+    }
+}
+
+/// The relative weights of the kinds of operations in a sequence, see `Workload::operations`.
+///
+/// Lookups, proofs and deletes target keys of the initial tree. Inserts add new keys, except for the
+/// `updates` share of them, which replace the leaf of an existing key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct OperationMix {
+    /// The weight of inserts.
+    pub inserts: u32,
+    /// The weight of lookups.
+    pub gets: u32,
+    /// The weight of proof generations.
+    pub proofs: u32,
+    /// The weight of deletes.
+    pub deletes: u32,
+    /// The percentage of inserts replacing an existing key.
+    pub updates: u32,
+}
+
+impl OperationMix {
+    /// A read-heavy mix: 80% lookups, 10% proofs and 10% inserts, half of them updates.
+    pub const READ_HEAVY: Self = Self {
+        inserts: 10,
+        gets: 80,
+        proofs: 10,
+        deletes: 0,
+        updates: 50,
+    };
+
+    /// A write-heavy mix: 60% inserts, 20% deletes, 10% lookups and 10% proofs.
+    pub const WRITE_HEAVY: Self = Self {
+        inserts: 60,
+        gets: 10,
+        proofs: 10,
+        deletes: 20,
+        updates: 25,
+    };
+}
+
+/// An operation of a workload.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum WorkloadOp {
+    /// Inserts or replaces the leaf at `key`.
+    Insert { key: Key, value: Vec<u8>, sum: Sum },
+    /// Looks up `key`.
+    Get { key: Key },
+    /// Generates the proof of `key`.
+    Proof { key: Key },
+    /// Deletes `key`.
+    Delete { key: Key },
+}
+
+/// The number of operations of one kind a run applied and the time they took.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct OperationTimings {
+    /// The number of operations.
+    pub count: u64,
+    /// The time spent in them.
+    pub elapsed: Duration,
+}
+
+impl OperationTimings {
+    /// Returns the number of operations per second, or 0 if none was timed.
+    pub fn per_second(&self) -> f64 {
+        match self.elapsed.as_secs_f64() {
+            secs if secs > 0.0 => self.count as f64 / secs,
+            _ => 0.0,
+        }
+    }
+
+    fn record(&mut self, start: Instant) {
+        self.count += 1;
+        self.elapsed += start.elapsed();
+    }
+}
+
+/// The outcome of `Workload::run`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WorkloadReport {
+    /// The timings of inserts.
+    pub inserts: OperationTimings,
+    /// The timings of lookups.
+    pub gets: OperationTimings,
+    /// The timings of proof generations.
+    pub proofs: OperationTimings,
+    /// The timings of deletes.
+    pub deletes: OperationTimings,
+    /// The number of lookups that found their key.
+    pub hits: u64,
+    /// The root hash after the run, the same for every store.
+    pub root_hash: NodeHash,
+}
+
+impl WorkloadReport {
+    /// Returns the total time spent in operations.
+    pub fn elapsed(&self) -> Duration {
+        self.inserts.elapsed + self.gets.elapsed + self.proofs.elapsed + self.deletes.elapsed
+    }
+}
+
+/// A deterministic generator of synthetic trees and operations.
+///
+/// # Examples
+///
+/// ```rust
+/// use mssmt::workload::{KeyDistribution, OperationMix, Workload, WorkloadSpec};
+/// use mssmt::store::CachedStore;
+/// use mssmt::DefaultStore;
+///
+/// let workload = Workload::new(WorkloadSpec {
+///     leaves: 200,
+///     keys: KeyDistribution::Clustered { clusters: 4 },
+///     ..WorkloadSpec::default()
+/// });
+/// let operations = workload.operations(OperationMix::READ_HEAVY, 100);
+///
+/// let mut plain = workload.build(DefaultStore::new()).unwrap();
+/// let mut cached = workload.build(CachedStore::new(DefaultStore::new(), 1024)).unwrap();
+/// let plain = Workload::run(&mut plain, &operations).unwrap();
+/// let cached = Workload::run(&mut cached, &operations).unwrap();
+/// assert_eq!(plain.root_hash, cached.root_hash);
+/// assert_eq!(plain.gets.count, cached.gets.count);
+/// ```
+#[derive(Clone, Debug)]
+pub struct Workload {
+    spec: WorkloadSpec,
+    cluster_prefixes: Vec<[u8; 8]>,
+}
+
+impl Workload {
+    /// Creates the workload of `spec`.
+    pub fn new(spec: WorkloadSpec) -> Self {
+        let clusters = match spec.keys {
+            KeyDistribution::Clustered { clusters } => clusters.max(1),
+            _ => 0,
+        };
+        let mut rng = SplitMix64(spec.seed ^ CLUSTER_STREAM);
+        let cluster_prefixes = (0..clusters)
+            .map(|_| rng.next_u64().to_be_bytes())
+            .collect();
+        Self {
+            spec,
+            cluster_prefixes,
+        }
+    }
+
+    /// Returns the spec of the workload.
+    pub fn spec(&self) -> &WorkloadSpec {
+        &self.spec
+    }
+
+    /// Returns the key with index `index`. The initial tree holds the keys of indexes below
+    /// `WorkloadSpec::leaves`, and inserts of new keys continue from there.
+    pub fn key(&self, index: u64) -> Key {
+        let hashed = Key::hash([&self.spec.seed.to_be_bytes()[..], &index.to_be_bytes()].concat());
+        match self.spec.keys {
+            KeyDistribution::Uniform => hashed,
+            KeyDistribution::Sequential => {
+                let mut key = [0u8; 32];
+                key[24..].copy_from_slice(&index.to_be_bytes());
+                Key(key)
+            }
+            KeyDistribution::Clustered { .. } => {
+                let cluster = (index % self.cluster_prefixes.len() as u64) as usize;
+                let mut key = hashed.0;
+                key[..8].copy_from_slice(&self.cluster_prefixes[cluster]);
+                Key(key)
+            }
+        }
+    }
+
+    /// Returns the leaves of the initial tree, in index order.
+    pub fn leaves(&self) -> impl Iterator<Item = LeafNode> + '_ {
+        let mut rng = SplitMix64(self.spec.seed ^ LEAF_STREAM);
+        (0..self.spec.leaves as u64).map(move |index| {
+            let (value, sum) = self.random_leaf(&mut rng);
+            LeafNode::new(self.key(index).0, value, sum)
+        })
+    }
+
+    /// Builds the initial tree in `store`, see `FullTree::from_leaves`.
+    pub fn build<S: TreeStore>(&self, store: S) -> Result<FullTree<S>> {
+        FullTree::from_leaves(store, self.leaves())
+    }
+
+    /// Returns `count` operations drawn from `mix`, the same for every call with the same arguments.
+    pub fn operations(&self, mix: OperationMix, count: usize) -> Vec<WorkloadOp> {
+        let mut rng = SplitMix64(self.spec.seed ^ OPERATION_STREAM ^ count as u64);
+        let existing = (self.spec.leaves as u64).max(1);
+        let mut next_key = self.spec.leaves as u64;
+        let weights = [mix.inserts, mix.gets, mix.proofs, mix.deletes].map(u64::from);
+        let total: u64 = weights.iter().sum();
+
+        let mut operations = Vec::with_capacity(count);
+        for _ in 0..count {
+            let mut pick = rng.below(total.max(1));
+            let kind = weights
+                .iter()
+                .position(|weight| {
+                    let hit = pick < *weight;
+                    pick = pick.saturating_sub(*weight);
+                    hit
+                })
+                .unwrap_or(1);
+            let target = self.key(rng.below(existing));
+            operations.push(match kind {
+                0 => {
+                    let key = if rng.below(100) < u64::from(mix.updates) {
+                        target
+                    } else {
+                        next_key += 1;
+                        self.key(next_key - 1)
+                    };
+                    let (value, sum) = self.random_leaf(&mut rng);
+                    WorkloadOp::Insert { key, value, sum }
+                }
+                2 => WorkloadOp::Proof { key: target },
+                3 => WorkloadOp::Delete { key: target },
+                _ => WorkloadOp::Get { key: target },
+            });
+        }
+        operations
+    }
+
+    /// Applies `operations` to `tree` in order, timing each.
+    pub fn run<S: TreeStore>(
+        tree: &mut FullTree<S>,
+        operations: &[WorkloadOp],
+    ) -> Result<WorkloadReport> {
+        let mut inserts = OperationTimings::default();
+        let mut gets = OperationTimings::default();
+        let mut proofs = OperationTimings::default();
+        let mut deletes = OperationTimings::default();
+        let mut hits = 0;
+        for operation in operations {
+            let start = Instant::now();
+            match operation {
+                WorkloadOp::Insert { key, value, sum } => {
+                    tree.insert(*key, value.clone(), *sum)?;
+                    inserts.record(start);
+                }
+                WorkloadOp::Get { key } => {
+                    hits += tree.get(*key)?.is_some() as u64;
+                    gets.record(start);
+                }
+                WorkloadOp::Proof { key } => {
+                    tree.merkle_proof(*key)?;
+                    proofs.record(start);
+                }
+                WorkloadOp::Delete { key } => {
+                    tree.delete(*key)?;
+                    deletes.record(start);
+                }
+            }
+        }
+        Ok(WorkloadReport {
+            inserts,
+            gets,
+            proofs,
+            deletes,
+            hits,
+            root_hash: tree.root()?.node_hash(),
+        })
+    }
+
+    /// Draws the value and sum of a leaf.
+    fn random_leaf(&self, rng: &mut SplitMix64) -> (Vec<u8>, Sum) {
+        let spread = self
+            .spec
+            .max_value_size
+            .saturating_sub(self.spec.min_value_size) as u64;
+        let size = self.spec.min_value_size + rng.below(spread + 1) as usize;
+        let mut value = Vec::with_capacity(size + 8);
+        while value.len() < size {
+            value.extend_from_slice(&rng.next_u64().to_le_bytes());
+        }
+        value.truncate(size);
+        (value, 1 + rng.next_u64() as Sum % self.spec.max_sum.max(1))
+    }
+}
+
+/// Seed offsets separating the random streams of a workload, so that changing the number of leaves
+/// does not change the cluster prefixes and operations do not replay the leaves.
+const CLUSTER_STREAM: u64 = 0x636c_7573_7465_7273;
+const LEAF_STREAM: u64 = 0x6c65_6176_6573_0000;
+const OPERATION_STREAM: u64 = 0x6f70_6572_6174_6500;
+
+/// The SplitMix64 generator, small and fast enough not to weigh on the timings.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Returns a number below `bound`, which must not be 0.
+    fn below(&mut self, bound: u64) -> u64 {
+        self.next_u64() % bound
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::DefaultStore;
+
+    #[test]
+    fn test_workloads_are_deterministic() -> Result<()> {
+        let spec = WorkloadSpec {
+            leaves: 300,
+            min_value_size: 1,
+            max_value_size: 100,
+            max_sum: 50,
+            keys: KeyDistribution::Clustered { clusters: 3 },
+            seed: 7,
+        };
+        let workload = Workload::new(spec);
+        let leaves: Vec<LeafNode> = workload.leaves().collect();
+        assert!(leaves
+            .iter()
+            .all(|leaf| { (1..=100).contains(&leaf.value.len()) && (1..=50).contains(&leaf.sum) }));
+        let mut prefixes: Vec<&[u8]> = leaves.iter().map(|leaf| &leaf.key[..8]).collect();
+        prefixes.sort();
+        prefixes.dedup();
+        assert_eq!(prefixes.len(), 3);
+
+        // The same spec gives the same tree and operations, another seed another tree
+        let mix = OperationMix::WRITE_HEAVY;
+        let operations = workload.operations(mix, 500);
+        assert_eq!(operations, Workload::new(spec).operations(mix, 500));
+        let mut tree = workload.build(DefaultStore::new())?;
+        let other = Workload::new(WorkloadSpec { seed: 8, ..spec }).build(DefaultStore::new())?;
+        assert_ne!(tree.root()?.node_hash(), other.root()?.node_hash());
+
+        let report = Workload::run(&mut tree, &operations)?;
+        let counts = [report.inserts, report.gets, report.proofs, report.deletes]
+            .map(|timings| timings.count);
+        assert_eq!(counts.iter().sum::<u64>(), 500);
+        assert!(counts[0] > counts[3] && counts[3] > 0);
+        let mut replayed = workload.build(DefaultStore::new())?;
+        assert_eq!(
+            Workload::run(&mut replayed, &operations)?.root_hash,
+            report.root_hash
+        );
+
+        // Sequential keys count up in their last bytes
+        let sequential = Workload::new(WorkloadSpec {
+            keys: KeyDistribution::Sequential,
+            ..spec
+        });
+        assert_eq!(sequential.key(258).0[30..], [1, 2]);
+        assert_eq!(sequential.key(258).0[..24], [0u8; 24]);
+
+        Ok(())
+    }
+}